@@ -0,0 +1,76 @@
+#![allow(dead_code)]
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::net::{Ipv4Addr, SocketAddr};
+
+#[path = "../src/router.rs"]
+mod router;
+/* the events of the journal refer to most of the supervisor, the benchmark only needs the
+   handle through which the router records events, which discards them here */
+mod journal {
+    use std::net::{IpAddr, SocketAddr};
+
+    pub enum Event {
+        Broadcast(SocketAddr, crate::router::LuaType),
+        Dropped(IpAddr, String),
+    }
+
+    #[derive(Clone)]
+    pub struct Sender;
+
+    impl Sender {
+        pub fn record(&self, _: Event) -> Result<(), std::convert::Infallible> {
+            Ok(())
+        }
+    }
+}
+
+const PEERS: u16 = 50;
+/* one second of traffic at 100 messages per second */
+const MESSAGES_PER_PEER: usize = 100;
+const MESSAGE_LENGTH: usize = 256;
+
+fn relay(criterion: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let data = Bytes::from(vec![0u8; MESSAGE_LENGTH]);
+    let addrs = (0..PEERS)
+        .map(|port| SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+        .collect::<Vec<_>>();
+    let mut group = criterion.benchmark_group("relay");
+    /* each message is delivered to every peer except its sender */
+    let deliveries = PEERS as u64 * MESSAGES_PER_PEER as u64 * (PEERS as u64 - 1);
+    group.throughput(Throughput::Elements(deliveries));
+    group.bench_function("50 peers x 100 messages", |bencher| bencher.iter(|| runtime.block_on(async {
+        let relay = router::Relay::new();
+        let subscribers = addrs.iter()
+            .map(|addr| {
+                let mut subscription = relay.subscribe(*addr);
+                tokio::spawn(async move {
+                    let mut count = 0usize;
+                    while subscription.next().await.is_ok() {
+                        count += 1;
+                    }
+                    count
+                })
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..MESSAGES_PER_PEER {
+            for addr in addrs.iter() {
+                relay.publish(*addr, data.clone());
+            }
+            tokio::task::yield_now().await;
+        }
+        /* dropping the relay closes the subscriptions once they are drained */
+        drop(relay);
+        for subscriber in subscribers {
+            subscriber.await.unwrap();
+        }
+    })));
+    group.finish();
+}
+
+criterion_group!(benches, relay);
+criterion_main!(benches);
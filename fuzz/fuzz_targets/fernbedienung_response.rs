@@ -0,0 +1,14 @@
+#![no_main]
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mns_protocol::fernbedienung::Response;
+use tokio_util::codec::{Decoder, LengthDelimitedCodec};
+
+/* the responses of the fernbedienung service are JSON in length-delimited frames */
+fuzz_target!(|data: &[u8]| {
+    let mut codec = LengthDelimitedCodec::new();
+    let mut buf = BytesMut::from(data);
+    while let Ok(Some(frame)) = codec.decode(&mut buf) {
+        let _ = serde_json::from_slice::<Response>(&frame);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use mns_protocol::router::{decode, ByteArrayCodec};
+use tokio_util::codec::Decoder;
+
+/* the frames that the message router receives from the robots and the Lua tables within them */
+fuzz_target!(|data: &[u8]| {
+    let mut codec = ByteArrayCodec::default();
+    let mut buf = BytesMut::from(data);
+    while let Ok(Some(frame)) = codec.decode(&mut buf) {
+        let _ = decode(&frame);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mns_protocol::webui::decode_data_url;
+
+/* the files that are uploaded over the websocket of the web interface */
+fuzz_target!(|data: &[u8]| {
+    if let Ok(url) = std::str::from_utf8(data) {
+        let _ = decode_data_url(url);
+    }
+});
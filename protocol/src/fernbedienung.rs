@@ -0,0 +1,245 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use bytes::BytesMut;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/* the buffers of the standard streams are sent as base64 */
+fn bytesmut_serialize<S: Serializer>(bytes: &BytesMut, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(bytes))
+}
+
+fn bytesmut_deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BytesMut, D::Error> {
+    use serde::de::Error;
+    let input: String = Deserialize::deserialize(deserializer)?;
+    base64::decode(input)
+        .map(|vec| BytesMut::from(&vec[..]))
+        .map_err(D::Error::custom)
+}
+
+pub mod process {
+    use std::{collections::HashMap, fmt, path::PathBuf};
+    use bytes::BytesMut;
+    use serde::{Deserialize, Deserializer, Serialize};
+
+    /* the fields after the arguments are omitted when they are not used, so that processes can
+       still be run by older versions of the fernbedienung service, which ignore them */
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    pub struct Process {
+        pub target: PathBuf,
+        pub working_dir: Option<PathBuf>,
+        pub args: Vec<String>,
+        /* variables that are added to the environment of the service */
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub env: HashMap<String, String>,
+        /* the niceness of the process, from -20 (highest priority) to 19 (lowest priority) */
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub nice: Option<i32>,
+        #[serde(default, skip_serializing_if = "Limits::is_unlimited")]
+        pub limits: Limits,
+    }
+
+    /* resource limits that are set with setrlimit before the process is executed */
+    #[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+    #[serde(default)]
+    pub struct Limits {
+        /* the size of the virtual memory in bytes (RLIMIT_AS) */
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub memory: Option<u64>,
+        /* the processor time in seconds (RLIMIT_CPU), after which the process receives SIGXCPU */
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub cpu: Option<u64>,
+    }
+
+    impl Limits {
+        pub fn is_unlimited(&self) -> bool {
+            self.memory.is_none() && self.cpu.is_none()
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Request {
+        Run(Process),
+        #[serde(serialize_with = "super::bytesmut_serialize", deserialize_with = "super::bytesmut_deserialize")]
+        StandardInput(BytesMut),
+        Terminate,
+    }
+
+    /* how a process terminated, older versions of the fernbedienung service only report whether
+       the process succeeded */
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    pub struct ExitStatus {
+        pub success: bool,
+        /* the exit code if the process exited by itself */
+        pub code: Option<i32>,
+        /* the signal that terminated the process, if it was signalled */
+        pub signal: Option<i32>,
+    }
+
+    impl<'de> Deserialize<'de> for ExitStatus {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            #[serde(untagged)]
+            enum Status {
+                Success(bool),
+                Status {
+                    success: bool,
+                    code: Option<i32>,
+                    signal: Option<i32>,
+                },
+            }
+            Ok(match Status::deserialize(deserializer)? {
+                Status::Success(success) => ExitStatus { success, code: None, signal: None },
+                Status::Status { success, code, signal } => ExitStatus { success, code, signal },
+            })
+        }
+    }
+
+    impl ExitStatus {
+        pub fn signalled(&self) -> bool {
+            self.signal.is_some()
+        }
+    }
+
+    impl fmt::Display for ExitStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match (self.code, self.signal) {
+                (_, Some(signal)) => write!(f, "terminated by signal {}", signal),
+                (Some(code), None) => write!(f, "exit code {}", code),
+                (None, None) => write!(f, "{}", if self.success { "success" } else { "failure" }),
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Response {
+        Terminated(ExitStatus),
+        #[serde(serialize_with = "super::bytesmut_serialize", deserialize_with = "super::bytesmut_deserialize")]
+        StandardOutput(BytesMut),
+        #[serde(serialize_with = "super::bytesmut_serialize", deserialize_with = "super::bytesmut_deserialize")]
+        StandardError(BytesMut),
+    }
+}
+
+/* uploads of large files in chunks, all requests of an upload carry the same identifier and each
+   request is acknowledged. The remote writes the chunks to a temporary file that replaces the
+   destination when the upload is closed and that is discarded if the connection is lost first.
+   Closing a chunked upload and uploading a file in a single request are answered with the SHA-256
+   of the written file */
+pub mod upload {
+    use std::path::PathBuf;
+    use bytes::BytesMut;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Request {
+        Open {
+            filename: PathBuf,
+            path: PathBuf,
+        },
+        #[serde(serialize_with = "super::bytesmut_serialize", deserialize_with = "super::bytesmut_deserialize")]
+        Append(BytesMut),
+        Close,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Response {
+        /* the SHA-256 of the file that was written in lower case hex */
+        Written(String),
+    }
+}
+
+/* downloads of files, the remote responds with the contents of the file in chunks that fit in a
+   frame and then with Ok, or with an error if the file can not be read */
+pub mod download {
+    use bytes::BytesMut;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Response {
+        #[serde(serialize_with = "super::bytesmut_serialize", deserialize_with = "super::bytesmut_deserialize")]
+        Chunk(BytesMut),
+    }
+}
+
+/* management of the files on the remote, the requests are answered with Ok, a listing, or an
+   error */
+pub mod files {
+    use std::path::PathBuf;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Request {
+        List(PathBuf),
+        /* a directory is only removed with its contents if the removal is recursive */
+        Remove {
+            path: PathBuf,
+            recursive: bool,
+        },
+        /* creates a directory and its missing parents */
+        MakeDir(PathBuf),
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct Entry {
+        pub name: String,
+        pub directory: bool,
+        /* size in bytes, zero for directories */
+        pub size: u64,
+        /* time of the last modification in seconds since the Unix epoch */
+        pub modified: u64,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub enum Response {
+        List(Vec<Entry>),
+    }
+}
+
+/* the verbosity of the logging of the fernbedienung service */
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Upload {
+    pub filename: PathBuf,
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum RequestKind {
+    Halt,
+    Reboot,
+    /* terminate the processes that were started over connections that have since closed */
+    Reap,
+    /* answered with Ok, for detecting a connection that has stalled */
+    Ping,
+    LogLevel(LogLevel),
+    Upload(Upload),
+    ChunkedUpload(upload::Request),
+    Download(PathBuf),
+    Files(files::Request),
+    Process(process::Request),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Request(pub Uuid, pub RequestKind);
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ResponseKind {
+    Ok,
+    Error(String),
+    Process(process::Response),
+    Upload(upload::Response),
+    Download(download::Response),
+    Files(files::Response),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Response(pub Option<Uuid>, pub ResponseKind);
@@ -0,0 +1,12 @@
+//! Types of the protocols spoken by the MNS supervisor, so that the clients on the robots and
+//! external tools can share the exact definitions used by the supervisor:
+//!
+//! - [`fernbedienung`]: the requests and responses of the fernbedienung service on the robots,
+//!   sent as JSON in frames that are prefixed by their length (`LengthDelimitedCodec`)
+//! - [`router`]: the framing of the messages relayed between robots by the message router and
+//!   the encoding of the Lua tables that they contain
+//! - [`webui`]: the replies sent over the websocket of the web interface
+
+pub mod fernbedienung;
+pub mod router;
+pub mod webui;
@@ -0,0 +1,321 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use std::{io, mem::size_of};
+use tokio_util::codec::{Decoder, Encoder};
+
+const LUA_TNIL: i8 = 0;
+const LUA_TBOOLEAN: i8 = 1;
+//const LUA_TLIGHTUSERDATA: i8 = 2;
+const LUA_TNUMBER: i8 = 3;
+const LUA_TSTRING: i8 = 4;
+const LUA_TTABLE: i8 = 5;
+//const LUA_TFUNCTION: i8 = 6;
+const LUA_TUSERDATA: i8 = 7;
+//const LUA_TTHREAD: i8 = 8;
+const LUA_TUSERDATA_VECTOR2: u8 = 1;
+const LUA_TUSERDATA_VECTOR3: u8 = 2;
+const LUA_TUSERDATA_QUATERNION: u8 = 3;
+const MAX_MANTISSA: f64 = 9223372036854775806.0;
+/// Tables nested deeper than this are rejected so that a malformed message cannot overflow the
+/// stack of the supervisor
+const MAX_TABLE_DEPTH: usize = 32;
+/// Frames longer than this are rejected so that a malformed length cannot exhaust the memory
+/// of the supervisor
+pub const MAX_FRAME_LENGTH: usize = 1 << 20;
+
+/// A value of a message sent between robots, the messages are Lua tables
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum LuaType {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Vector2(f64, f64),
+    Vector3(f64, f64, f64),
+    Quaternion(f64, f64, f64, f64),
+    Table(Vec<(LuaType, LuaType)>),
+}
+
+fn encode_lua_number(value: f64, buf: &mut BytesMut) {
+    /* inverse of Carlo's unusual double encoding, see decode_lua_number */
+    if value == 0.0 || !value.is_finite() {
+        buf.put_i64(0);
+        buf.put_i32(0);
+        return;
+    }
+    /* split the value into a significand in [0.5, 1) and an exponent */
+    let mut exponent = value.abs().log2().floor() as i32 + 1;
+    let mut significand = value.abs() / 2.0f64.powi(exponent);
+    if significand >= 1.0 {
+        significand /= 2.0;
+        exponent += 1;
+    }
+    else if significand < 0.5 {
+        significand *= 2.0;
+        exponent -= 1;
+    }
+    let mantissa = ((significand - 0.5) * 2.0 * MAX_MANTISSA) as i64 + 1;
+    buf.put_i64(if value < 0.0 { -mantissa } else { mantissa });
+    buf.put_i32(exponent);
+}
+
+fn encode_lua_value(value: &LuaType, buf: &mut BytesMut) {
+    match value {
+        LuaType::String(value) => {
+            buf.put_i8(LUA_TSTRING);
+            buf.put_slice(value.as_bytes());
+            buf.put_u8(0);
+        },
+        LuaType::Number(value) => {
+            buf.put_i8(LUA_TNUMBER);
+            encode_lua_number(*value, buf);
+        },
+        LuaType::Boolean(value) => {
+            buf.put_i8(LUA_TBOOLEAN);
+            buf.put_i8(*value as i8);
+        },
+        LuaType::Vector2(x, y) => {
+            buf.put_i8(LUA_TUSERDATA);
+            buf.put_u8(LUA_TUSERDATA_VECTOR2);
+            for value in &[x, y] {
+                encode_lua_number(**value, buf);
+            }
+        },
+        LuaType::Vector3(x, y, z) => {
+            buf.put_i8(LUA_TUSERDATA);
+            buf.put_u8(LUA_TUSERDATA_VECTOR3);
+            for value in &[x, y, z] {
+                encode_lua_number(**value, buf);
+            }
+        },
+        LuaType::Quaternion(w, x, y, z) => {
+            buf.put_i8(LUA_TUSERDATA);
+            buf.put_u8(LUA_TUSERDATA_QUATERNION);
+            for value in &[w, x, y, z] {
+                encode_lua_number(**value, buf);
+            }
+        },
+        LuaType::Table(table) => {
+            buf.put_i8(LUA_TTABLE);
+            encode_lua_table(table, buf);
+        },
+    }
+}
+
+/// Encodes the entries of a table in the format that is decoded by decode_lua_table
+pub fn encode_lua_table(table: &[(LuaType, LuaType)], buf: &mut BytesMut) {
+    for (key, value) in table {
+        encode_lua_value(key, buf);
+        encode_lua_value(value, buf);
+    }
+    buf.put_i8(LUA_TNIL);
+}
+
+/// Decode a message from a robot
+pub fn decode(data: &Bytes) -> Option<LuaType> {
+    decode_lua_table(&mut data.clone()).ok()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not decode message")]
+    DecodeError,
+   
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+}
+
+fn decode_lua_usertype(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    if buf.has_remaining() {
+        match buf.get_u8() {
+            LUA_TUSERDATA_VECTOR2 => decode_lua_vector2(buf),
+            LUA_TUSERDATA_VECTOR3 => decode_lua_vector3(buf),
+            LUA_TUSERDATA_QUATERNION => decode_lua_quaternion(buf),
+            _ => Err(Error::DecodeError)
+        }
+    }
+    else {
+        Err(Error::DecodeError)
+    }
+}
+
+fn decode_lua_vector2(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    let x = decode_lua_number(buf)?;
+    let y = decode_lua_number(buf)?;
+    match (x, y) {
+        (LuaType::Number(x),
+         LuaType::Number(y)) => Ok(LuaType::Vector2(x, y)),
+        _ => Err(Error::DecodeError)
+    }
+}
+
+fn decode_lua_vector3(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    let x = decode_lua_number(buf)?;
+    let y = decode_lua_number(buf)?;
+    let z = decode_lua_number(buf)?;
+    match (x, y, z) {
+        (LuaType::Number(x),
+         LuaType::Number(y),
+         LuaType::Number(z)) => Ok(LuaType::Vector3(x, y, z)),
+        _ => Err(Error::DecodeError)
+    }
+}
+
+fn decode_lua_quaternion(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    let w = decode_lua_number(buf)?;
+    let x = decode_lua_number(buf)?;
+    let y = decode_lua_number(buf)?;
+    let z = decode_lua_number(buf)?;
+    match (w, x, y, z) {
+        (LuaType::Number(w),
+         LuaType::Number(x),
+         LuaType::Number(y),
+         LuaType::Number(z)) => Ok(LuaType::Quaternion(w, x, y, z)),
+        _ => Err(Error::DecodeError)
+    }
+}
+
+fn decode_lua_number(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    /* handle Carlo's unusual double encoding */
+    if buf.remaining() > size_of::<u64>() + size_of::<u32>() {
+        let mantissa = buf.get_i64();
+        let exponent = buf.get_i32();
+        if mantissa == 0 {
+            Ok(LuaType::Number(0.0))
+        }
+        else {
+            /* i64::MIN has no absolute value as an i64 */
+            let magnitude = mantissa.checked_abs().unwrap_or(i64::MAX);
+            let significand = ((magnitude - 1i64) as f64 / MAX_MANTISSA) / 2.0 + 0.5;
+            let value = significand * 2.0f64.powi(exponent);
+            if mantissa < 0 {
+                Ok(LuaType::Number(-value))
+            }
+            else {
+                Ok(LuaType::Number(value))
+            }
+        }
+    }
+    else {
+        Err(Error::DecodeError)
+    }
+}
+
+fn decode_lua_string(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    /* extract C string */
+    let mut data = Vec::new();
+    while buf.has_remaining() {
+        match buf.get_u8() {
+            0 => break,
+            byte => data.push(byte),
+        }
+    }
+    String::from_utf8(data)
+        .map_err(|_| Error::DecodeError)
+        .map(LuaType::String)
+}
+
+fn decode_lua_boolean(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    if buf.has_remaining() {
+        match buf.get_i8() {
+            0 => Ok(LuaType::Boolean(false)),
+            _ => Ok(LuaType::Boolean(true)),
+        }
+    }
+    else {
+        Err(Error::DecodeError)
+    }
+}
+
+/// Decodes the entries of a table up to and including its terminating nil
+pub fn decode_lua_table(buf: &mut impl Buf) -> Result<LuaType, Error> {
+    decode_nested_lua_table(buf, 0)
+}
+
+fn decode_nested_lua_table(buf: &mut impl Buf, depth: usize) -> Result<LuaType, Error> {
+    if depth > MAX_TABLE_DEPTH {
+        return Err(Error::DecodeError);
+    }
+    let mut table = Vec::new();
+    while buf.has_remaining() {
+        /* parse the key */
+        let key = match buf.get_i8() {
+            LUA_TBOOLEAN => decode_lua_boolean(buf),
+            LUA_TNUMBER => decode_lua_number(buf),
+            LUA_TSTRING => decode_lua_string(buf),
+            LUA_TUSERDATA => decode_lua_usertype(buf),
+            LUA_TTABLE => decode_nested_lua_table(buf, depth + 1),
+            LUA_TNIL => break,
+            _ => Err(Error::DecodeError),
+        }?;
+        if buf.has_remaining() {
+            /* parse the value */
+            let value = match buf.get_i8() {
+                LUA_TBOOLEAN => decode_lua_boolean(buf),
+                LUA_TNUMBER => decode_lua_number(buf),
+                LUA_TSTRING => decode_lua_string(buf),
+                LUA_TUSERDATA => decode_lua_usertype(buf),
+                LUA_TTABLE => decode_nested_lua_table(buf, depth + 1),
+                _ => Err(Error::DecodeError),
+            }?;
+            table.push((key, value));
+        }
+        else {
+            return Err(Error::DecodeError);
+        }
+    }
+    Ok(LuaType::Table(table))
+}
+
+/// Frames the messages sent between the robots and the message router, each message is
+/// prefixed by its length as a big endian u32. A frame longer than MAX_FRAME_LENGTH is an error,
+/// after which the connection should be closed
+#[derive(Debug, Default)]
+pub struct ByteArrayCodec {
+    len: Option<usize>
+}
+
+impl Decoder for ByteArrayCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        loop {
+            if let Some(len) = self.len {
+                if buf.len() >= len {
+                    self.len = None;
+                    return Ok(Some(buf.split_to(len).freeze()));
+                }
+                else {
+                    break;
+                }
+            }
+            else {
+                if buf.len() >= 4 {
+                    let len = buf.get_u32() as usize;
+                    if len > MAX_FRAME_LENGTH {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                            format!("Frame of {} bytes exceeds the maximum length", len)));
+                    }
+                    self.len = Some(len);
+                }
+                else {
+                    break;
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Encoder<Bytes> for ByteArrayCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, data: Bytes, buf: &mut BytesMut) -> Result<(), io::Error> {
+        buf.reserve(data.len() + size_of::<u32>());
+        buf.put_u32(data.len() as u32);
+        buf.put(data);
+        Ok(())
+    }
+}
+
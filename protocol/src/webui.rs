@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Decodes the contents of a file that the web interface uploads as a base64 data URL, e.g.,
+/// `data:application/octet-stream;base64,SGVsbG8=`
+pub fn decode_data_url(url: &str) -> Option<Vec<u8>> {
+    let (_, data) = url.split_at(url.find(',')? + 1);
+    base64::decode(data).ok()
+}
+
+/// Content of a card, text may contain HTML
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Content {
+    Text(String),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>
+    },
+    Download  {
+        data: String,
+        filename: String,
+    },
+}
+
+/// A card on a tab of the web interface, the actions are the buttons of the card and are defined
+/// by the supervisor
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Card<A> {
+    pub uuid: Uuid,
+    pub span: u8,
+    pub title: String,
+    pub content: Vec<Content>,
+    pub actions: Vec<A>,
+}
+
+/// The contents of a tab that are sent in reply to an update request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Reply<A> {
+    pub title: String,
+    pub cards: Vec<Card<A>>,
+    /// Warning that is shown above all tabs
+    pub banner: Option<String>,
+    /// Indicator that is shown in the header, e.g., the state of the arming switch
+    pub status: Option<String>,
+    /// Tabs provided by plugins
+    pub tabs: Vec<String>,
+}
+
+/// Token with which the client resumes its session after reconnecting
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionReply {
+    pub session: Uuid,
+}
+
+/// Query of the socket, e.g., /socket?session=<token> to resume a session
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Resume {
+    pub session: Option<Uuid>,
+}
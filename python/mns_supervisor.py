@@ -0,0 +1,213 @@
+"""Client for driving the MNS supervisor from Python scripts.
+
+The client speaks the same websocket API as the web interface, so the
+permissions and the operator lock of the supervisor apply to scripts as
+they do to browsers. Requires the websockets package.
+
+   import asyncio
+   import mns_supervisor
+
+   async def main():
+      async with mns_supervisor.connect('localhost:3030') as supervisor:
+         robots = await supervisor.list_robots(kind='PiPuck', lifecycle='Ready')
+         await supervisor.lock()
+         await supervisor.stage('PiPuck', ['experiment.argos', 'controller.lua'])
+         await supervisor.start()
+         async for event in supervisor.stream_events(level='info'):
+            print(event['message'])
+
+   asyncio.run(main())
+"""
+
+import asyncio
+import base64
+import json
+import os
+import urllib.request
+import uuid
+
+import websockets
+
+# identifiers of the cards that accept software, see webui.rs
+NAMESPACE_ARENA = uuid.uuid3(uuid.NAMESPACE_OID, 'arena')
+SOFTWARE_TARGETS = {
+   'PiPuck': uuid.uuid3(NAMESPACE_ARENA, 'pipucks'),
+   'Drone': uuid.uuid3(NAMESPACE_ARENA, 'drones'),
+}
+# identifiers of the cards of the formations, keyed by the name of the formation
+NAMESPACE_FORMATION = uuid.uuid3(uuid.NAMESPACE_OID, 'formation')
+
+class Error(Exception):
+   pass
+
+class Supervisor:
+   def __init__(self, address, session=None):
+      self.address = address
+      self.session = session
+      self.socket = None
+      self.replies = asyncio.Queue()
+      self.receiver = None
+
+   async def open(self):
+      uri = 'ws://{}/socket'.format(self.address)
+      if self.session is not None:
+         uri += '?session={}'.format(self.session)
+      # the websockets package answers the heartbeat pings of the supervisor
+      self.socket = await websockets.connect(uri, max_size=None)
+      self.receiver = asyncio.ensure_future(self._receive())
+      reply = await self._reply('session')
+      self.session = reply['session']
+      return self
+
+   async def close(self):
+      if self.receiver is not None:
+         self.receiver.cancel()
+      if self.socket is not None:
+         await self.socket.close()
+
+   async def __aenter__(self):
+      return await self.open()
+
+   async def __aexit__(self, *args):
+      await self.close()
+
+   async def _receive(self):
+      # the supervisor sends the replies in batches
+      async for frame in self.socket:
+         for reply in json.loads(frame):
+            await self.replies.put(reply)
+
+   async def _send(self, request):
+      await self.socket.send(json.dumps(request))
+
+   async def _request(self, request, timeout=10.0):
+      # requests that change the testbed carry an identifier, a request that is resent with the
+      # same identifier, e.g., after reopening the session, is only carried out once
+      request = dict(request, id=str(uuid.uuid4()))
+      await self._send(request)
+      while True:
+         reply = (await self._reply('request', timeout))['request']
+         if reply['id'] == request['id']:
+            break
+      if reply['outcome'] == 'refused':
+         raise Error('The supervisor refused the {} request'.format(request['type']))
+
+   async def _reply(self, key, timeout=10.0):
+      # replies without the key, e.g., updates of the tabs, are skipped
+      async def wait():
+         while True:
+            reply = await self.replies.get()
+            if key in reply:
+               return reply
+      try:
+         return await asyncio.wait_for(wait(), timeout)
+      except asyncio.TimeoutError:
+         raise Error('No {} reply from the supervisor'.format(key))
+
+   async def list_robots(self, kind=None, lifecycle=None, battery_above=None):
+      """Summaries of the robots that match all of the given predicates"""
+      predicates = []
+      if kind is not None:
+         predicates.append({'predicate': 'kind', 'value': kind})
+      if lifecycle is not None:
+         predicates.append({'predicate': 'lifecycle', 'value': lifecycle})
+      if battery_above is not None:
+         predicates.append({'predicate': 'battery_above', 'value': battery_above})
+      await self._send({'type': 'query', 'predicates': predicates})
+      return (await self._reply('query'))['query']
+
+   async def lock(self):
+      """Acquire the operator lock, which is required for changing the testbed"""
+      await self._request({'type': 'lock', 'action': 'Lock', 'uuid': str(uuid.UUID(int=0))})
+
+   async def unlock(self):
+      await self._request({'type': 'lock', 'action': 'Unlock', 'uuid': str(uuid.UUID(int=0))})
+
+   async def stage(self, kind, paths, clear=True):
+      """Upload the software (ARGoS configuration, controllers) for a kind of robot"""
+      target = str(SOFTWARE_TARGETS[kind])
+      if clear:
+         await self._request({'type': 'software', 'action': 'Clear', 'file': None, 'uuid': target})
+      for path in paths:
+         with open(path, 'rb') as file:
+            data = base64.b64encode(file.read()).decode()
+         # the file is sent as a data URL as done by the web interface
+         content = 'data:application/octet-stream;base64,' + data
+         await self._request({'type': 'software', 'action': 'Upload',
+                              'file': [os.path.basename(path), content], 'uuid': target})
+
+   async def register_device(self, address, device='fernbedienung'):
+      """Probe a device by its address, e.g., on a routed network, where device is 'xbee' for
+      the Xbee of a drone or 'fernbedienung' for a Pi-Puck, an UP Core, or a compute server"""
+      await self._request({'type': 'register', 'address': address, 'device': device})
+
+   async def set_discovery_filter(self, allow=None, deny=None):
+      """Replace the filter of the devices that are probed with lists of addresses, networks, or
+      MAC prefixes, e.g., deny=['10.0.0.5', '00:11:32'], or return to the configured filter when
+      both lists are None"""
+      if allow is None and deny is None:
+         await self._request({'type': 'filter', 'filter': None})
+      else:
+         await self._request({'type': 'filter', 'filter': {'allow': allow or [], 'deny': deny or []}})
+
+   async def bridge_simulation(self, address):
+      """Exchange the messages of the robots with the simulated robots of the ARGoS instance at
+      address, e.g., '127.0.0.1:4951', or disconnect the simulation if address is None"""
+      await self._request({'type': 'bridge', 'address': address})
+
+   async def formation(self, name, action):
+      """Carry out an action on a formation from the configuration, where action is 'Show',
+      'Hide', 'Measure', 'Drive', or 'Stop'"""
+      await self._request({'type': 'formation', 'action': action,
+                           'uuid': str(uuid.uuid3(NAMESPACE_FORMATION, name))})
+
+   async def _execute(self, action):
+      await self._request({'type': 'arena', 'action': action, 'uuid': str(NAMESPACE_ARENA)})
+
+   async def start(self):
+      """Start the experiment, raises an error if the supervisor refuses the request"""
+      await self._execute('Start Experiment')
+
+   async def stop(self):
+      await self._execute('Stop Experiment')
+
+   async def abort(self, discard=False):
+      """Abort the experiment, preserving its partial results or discarding the run entirely"""
+      await self._execute('Abort and discard' if discard else 'Abort and preserve')
+
+   async def stream_events(self, level=None, module=None):
+      """The log entries of the supervisor as they are written"""
+      query = '&'.join('{}={}'.format(key, value)
+                       for key, value in (('level', level), ('module', module))
+                       if value is not None)
+      url = 'http://{}/logs/stream?{}'.format(self.address, query)
+      loop = asyncio.get_event_loop()
+      stream = await loop.run_in_executor(None, urllib.request.urlopen, url)
+      try:
+         event = None
+         while True:
+            line = await loop.run_in_executor(None, stream.readline)
+            if not line:
+               break
+            line = line.decode().rstrip('\r\n')
+            # server-sent events are separated by blank lines
+            if line.startswith('event:'):
+               event = line[len('event:'):].strip()
+            elif line.startswith('data:') and event == 'log':
+               yield json.loads(line[len('data:'):].strip())
+            elif line == '':
+               event = None
+      finally:
+         stream.close()
+
+def stream_live(address, rate=10, stream='filtered', telemetry=True):
+   """The tracking frames and the telemetry as they arrive, e.g., for a live plot in a notebook"""
+   url = 'http://{}/live?rate={}&stream={}&telemetry={}'.format(
+      address, rate, stream, 'true' if telemetry else 'false')
+   with urllib.request.urlopen(url) as lines:
+      for line in lines:
+         yield json.loads(line)
+
+def connect(address, session=None):
+   """Connect to the supervisor at an address such as localhost:3030"""
+   return Supervisor(address, session)
@@ -1,18 +1,57 @@
 
 use serde::{Deserialize, Serialize};
 use software::Software;
-use std::{collections::HashMap, time::Duration};
-use futures::{StreamExt, TryStreamExt, stream::FuturesUnordered};
+use std::{collections::HashMap, net::{IpAddr, Ipv4Addr, SocketAddr}, time::{Duration, Instant}};
+use futures::{FutureExt, StreamExt, TryStreamExt, stream::FuturesUnordered};
 use log;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use uuid::Uuid;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use rand::Rng;
+use itertools::Itertools;
 
-use crate::robot::{pipuck::{self, PiPuck}, drone::{self, Drone}};
+use crate::robot::{pipuck::{self, PiPuck}, drone::{self, Drone}, simulated::{self, Simulated}, lifecycle::{self, Lifecycle}, queue::{self, Queue}, thermal, action};
 use crate::software;
 use crate::journal;
+use crate::clock;
+use crate::package;
 use crate::network;
+use crate::image::Image;
+use crate::telemetry;
+use crate::config;
+use crate::plugin;
+use crate::dock;
+use crate::environment;
+use crate::optitrack;
+use crate::calibration;
+use crate::region;
+use crate::formation;
+use crate::interlock;
+use crate::arming;
+use crate::push;
+use crate::flags::Flags;
+use crate::router;
+use crate::compute::{self, Server, tunnel};
+use crate::history::{self, History};
+use crate::experiment::{Command, Event, Experiment, Phase};
+use crate::lock::{self, Lock};
+use crate::dedup;
+use crate::digest;
+use crate::quota;
+use crate::campaign;
+use crate::selftest;
+use crate::operation;
+use crate::provision;
+use crate::fleet;
+use crate::availability;
+use crate::link;
+use crate::sensing;
+use crate::command;
+use crate::harvest;
+use crate::merge;
+use crate::replication;
+use crate::hook;
+use crate::chat;
 
 
 #[derive(thiserror::Error, Debug)]
@@ -23,6 +62,27 @@ pub enum Error {
     #[error("Drone {0} error: {1}")]
     DroneError(Uuid, drone::Error),
 
+    #[error("Simulated robot {0} error: {1}")]
+    SimulatedError(Uuid, simulated::Error),
+
+    #[error("Compute server {0} error: {1}")]
+    ComputeError(Uuid, compute::Error),
+
+    #[error("Could not find robot {0}")]
+    RobotNotFound(Uuid),
+
+    #[error("The software has not passed a canary experiment")]
+    CanaryRequired,
+
+    #[error("There are no robots to run a canary experiment")]
+    NoCanaryRobots,
+
+    #[error("The operation was cancelled")]
+    Cancelled,
+
+    #[error("Robot {0}: {1}")]
+    QueueError(Uuid, queue::Error),
+
     #[error(transparent)]
     JournalError(#[from] journal::Error),
     
@@ -31,6 +91,30 @@ pub enum Error {
 
     #[error(transparent)]
     FernbedienungError(#[from] network::fernbedienung::Error),
+
+    #[error(transparent)]
+    HookError(#[from] hook::Error),
+
+    #[error("Robot {0} is charging")]
+    RobotCharging(Uuid),
+
+    #[error("Could not get the status of the charging docks")]
+    DockRequestError,
+
+    #[error(transparent)]
+    InterlockError(#[from] interlock::Error),
+
+    #[error("Drones cannot be started while the arming switch is {0}")]
+    Disarmed(arming::State),
+
+    #[error("Could not get the state of the arming switch")]
+    ArmingRequestError,
+
+    #[error("Required robots are not connected: {}", .0.join(", "))]
+    MissingRobots(Vec<String>),
+
+    #[error("Scripts cannot be run during an experiment")]
+    ExperimentActive,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -39,41 +123,238 @@ pub enum Action {
     StartExperiment,
     #[serde(rename = "Stop Experiment")]
     StopExperiment,
+    #[serde(rename = "Abort and preserve")]
+    AbortPreserve,
+    #[serde(rename = "Abort and discard")]
+    AbortDiscard,
+    #[serde(rename = "Start Canary")]
+    StartCanary,
+    #[serde(rename = "Reload Configuration")]
+    ReloadConfiguration,
+    #[serde(rename = "Pause Scanning")]
+    PauseScanning,
+    #[serde(rename = "Resume Scanning")]
+    ResumeScanning,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-enum State {
-    Standby,
-    Active,
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Kind {
+    Drone,
+    PiPuck,
+}
+
+/* predicates for selecting robots, a robot is selected if it matches all predicates */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "predicate", content = "value")]
+pub enum Predicate {
+    Kind(Kind),
+    Lifecycle(Lifecycle),
+    /* battery remaining as a percentage, robots that do not report their battery never match */
+    BatteryAbove(i8),
+}
+
+impl Predicate {
+    fn matches(&self, summary: &Summary) -> bool {
+        match self {
+            Predicate::Kind(kind) => summary.kind == *kind,
+            Predicate::Lifecycle(lifecycle) => summary.lifecycle == Some(*lifecycle),
+            Predicate::BatteryAbove(threshold) => summary.battery
+                .map_or(false, |battery| battery > *threshold),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    pub uuid: Uuid,
+    pub kind: Kind,
+    pub simulated: bool,
+    pub lifecycle: Option<Lifecycle>,
+    pub battery: Option<i8>,
+    /// The hostname of a physical robot, which remains the same when the robot reconnects
+    pub hostname: Option<String>,
 }
 
 pub enum Request {
     /* Arena requests */
     GetActions(oneshot::Sender<Vec<Action>>),
+    GetGoldenImage(oneshot::Sender<Option<Image>>),
+    GetTelemetry(Uuid, oneshot::Sender<telemetry::History>),
+    SubscribeTelemetry(oneshot::Sender<broadcast::Receiver<telemetry::Record>>),
+    GetJournalStatus(oneshot::Sender<journal::Status>),
+    GetStorage(oneshot::Sender<quota::Usage>),
+    GetLifecycles(oneshot::Sender<HashMap<Uuid, Lifecycle>>),
+    /* the queued operations of each robot and the last operation that was refused */
+    GetQueues(oneshot::Sender<HashMap<Uuid, (Vec<queue::Entry>, Option<String>)>>),
+    GetMissingRobots(oneshot::Sender<Vec<fleet::Missing>>),
+    GetDocks(oneshot::Sender<dock::Statuses>),
+    GetTrackingFrame(optitrack::Stream, oneshot::Sender<Vec<optitrack::Pose>>),
+    GetTrackingMarkers(oneshot::Sender<optitrack::Markers>),
+    GetTrackingDiagnostics(oneshot::Sender<optitrack::Diagnostics>),
+    /* write the tracking data that the journal kept while no experiment was running to a file */
+    SaveTrackingRecording,
+    GetArmingState(oneshot::Sender<arming::State>),
+    /* subscribe a browser to the critical alerts */
+    SubscribePush(push::Subscription),
+    GetRegionOccupancy(oneshot::Sender<region::Occupancy>),
+    /* an action on the formation with the given name */
+    Formation(String, formation::Action),
+    /* the configured formations and the name of the one that is overlaid on the tracked rigid bodies */
+    GetFormations(oneshot::Sender<(Vec<formation::Formation>, Option<String>)>),
+    Calibrate(calibration::Action),
+    GetCalibration(oneshot::Sender<calibration::Status>),
+    /* Environment requests */
+    SetEnvironmentSchedule(Vec<u8>),
+    ClearEnvironmentSchedule,
+    GetEnvironmentSchedule(oneshot::Sender<environment::Schedule>),
+    /* Feature flag requests */
+    SetFlags(Vec<u8>),
+    ClearFlags,
+    GetFlags(oneshot::Sender<Flags>),
+    /* Message injection requests */
+    AddInjectionTemplates(Vec<u8>),
+    ClearInjectionTemplates,
+    GetInjectionTemplates(oneshot::Sender<Vec<router::Template>>),
+    InjectMessage(Option<IpAddr>, serde_json::Value),
+    /* connect the message router to the simulated medium of an ARGoS instance, or disconnect it */
+    BridgeSimulation(Option<SocketAddr>),
+    /* the address of the bridged simulation and whether it is connected */
+    GetSimulationBridge(oneshot::Sender<Option<(SocketAddr, bool)>>),
+    InjectTemplate(String),
+    BroadcastCommand {
+        command: serde_json::Value,
+        deadline: Duration,
+        callback: oneshot::Sender<command::Acknowledgements>,
+    },
+    Query(Vec<Predicate>, oneshot::Sender<Vec<Summary>>),
+    ExecutePluginAction(Uuid, String),
     Execute(Action),
+    /* Network requests */
+    /* probe a device by its address and type, bypassing discovery */
+    RegisterDevice(Ipv4Addr, network::Probe),
+    GetRegisteredDevices(oneshot::Sender<Vec<(Ipv4Addr, network::Probe, bool)>>),
+    /* replace the filter of the devices that are probed, none returns to the configured filter */
+    SetDiscoveryFilter(Option<network::filter::Filter>),
+    GetDiscoveryFilter(oneshot::Sender<(network::filter::Filter, bool)>),
     /* Drone requests */
     AddDrone(network::xbee::Device),
     AddDroneSoftware(String, Vec<u8>),
     ClearDroneSoftware,
     CheckDroneSoftware(oneshot::Sender<(software::Checksums, software::Result<()>)>),
     ForwardDroneAction(Uuid, drone::Action),
-    PairWithDrone(network::fernbedienung::Device),
+    /* the UP Core of a drone and its hostname */
+    PairWithDrone(network::fernbedienung::Device, String),
     //ForwardDroneActionAll(drone::Action),
     GetDrones(oneshot::Sender<HashMap<Uuid, drone::State>>),
     /* Pi-Puck requests */
-    AddPiPuck(network::fernbedienung::Device),
+    /* a Pi-Puck and the hostname of its Raspberry Pi */
+    AddPiPuck(network::fernbedienung::Device, String),
     AddPiPuckSoftware(String, Vec<u8>),
     ClearPiPuckSoftware,
     CheckPiPuckSoftware(oneshot::Sender<(software::Checksums, software::Result<()>)>),
     ForwardPiPuckAction(Uuid, pipuck::Action),
     //ForwardPiPuckActionAll(pipuck::Action),
     GetPiPucks(oneshot::Sender<HashMap<Uuid, pipuck::State>>),
+    /* Simulated robot requests */
+    AddVirtualRobot(Kind),
+    GetVirtualRobots(oneshot::Sender<HashMap<Uuid, simulated::State>>),
+    /* Compute server requests */
+    AddComputeServer(network::fernbedienung::Device),
+    GetComputeServers(oneshot::Sender<HashMap<Uuid, compute::State>>),
+    /* assign (or unassign) the compute server that runs the controller of a robot */
+    AssignRemoteBrain(Uuid, Option<Uuid>),
+    GetRemoteBrains(oneshot::Sender<HashMap<Uuid, Uuid>>),
+    /* History requests */
+    GetHistory(oneshot::Sender<History>),
+    ExecuteHistoryAction(history::Action, Uuid, Option<String>),
+    /* Operator lock requests */
+    Lock(lock::Request),
+    /* Deduplication of requests that carry an identifier */
+    Dedup(dedup::Request),
+    /* Returns the activity since the last digest and starts recording anew */
+    TakeActivity(oneshot::Sender<digest::Activity>),
+    /* Returns activity that was taken but could not be sent, so that it is in the next digest */
+    RestoreActivity(digest::Activity),
+    /* Maintenance requests */
+    /* whether an experiment is running, during which the robots must not be maintained */
+    GetExperimentActive(oneshot::Sender<bool>),
+    RunScript(Uuid, Vec<u8>, oneshot::Sender<Result<()>>),
+    /* follow a file in the working directory of ARGoS on a robot, the number of preceding lines
+       are included, the stream ends if there is no such robot */
+    TailFile(Uuid, std::path::PathBuf, usize, mpsc::UnboundedSender<bytes::BytesMut>),
+    Campaign(campaign::Request),
+    /* the address of the fernbedienung service of the connected robot with the given hostname */
+    FindRobot(String, oneshot::Sender<Option<Ipv4Addr>>),
+    SelfTest(selftest::Request),
+    /* post a message to the chat of the operators, messages sent during a run are journaled */
+    Chat(chat::Message),
+    GetChat(oneshot::Sender<Vec<chat::Message>>),
+    /* staging, result collection, and campaigns that are tracked as operations */
+    Operation(operation::Request),
+    Provision(provision::Request),
+    Availability(availability::Request),
+    /* the recent signal strength of the links to the robots */
+    GetLinks(oneshot::Sender<link::Links>),
+    /* the Wi-Fi signal strength across the arena */
+    GetLinkHeatmap(oneshot::Sender<link::Heatmap>),
+    ClearLinkHeatmap,
+    /* the use of the Wi-Fi channels from the last round of scans */
+    GetWifiSurvey(oneshot::Sender<Option<link::Survey>>),
+    /* the state that is replicated to a standby supervisor */
+    GetSnapshot(oneshot::Sender<replication::Snapshot>),
+    /* resume from the state of a primary supervisor that this supervisor took over from */
+    Restore(replication::Snapshot),
+    /* stop any running experiment and persist the telemetry before the supervisor exits */
+    Shutdown(oneshot::Sender<()>),
+}
+
+/* the senders for the requests that the arena makes to the other services */
+pub struct Services {
+    pub telemetry: telemetry::Sender,
+    pub dock: dock::Sender,
+    pub environment: environment::Sender,
+    pub campaign: campaign::Sender,
+    pub selftest: selftest::Sender,
+    pub tracking: optitrack::Sender,
+    pub arming: arming::Sender,
+    pub push: push::Sender,
+    pub provision: provision::Sender,
+    pub availability: availability::Sender,
+    pub link: link::Sender,
+    pub sensing: sensing::Sender,
+    pub operations: operation::Sender,
+    pub network: network::Sender,
+    pub tunnel: tunnel::Sender,
 }
 
 pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
-                 journal_requests_tx: &mpsc::UnboundedSender<journal::Request>) {
-    let mut state = State::Standby;
+                 journal: &journal::Sender,
+                 services: Services,
+                 golden_image: Option<Image>,
+                 config: config::Receiver,
+                 config_reload_tx: config::ReloadSender,
+                 plugins: plugin::Registry,
+                 relay: router::Relay) {
+    let Services {
+        telemetry: telemetry_requests_tx,
+        dock: dock_requests_tx,
+        environment: environment_requests_tx,
+        campaign: campaign_requests_tx,
+        selftest: selftest_requests_tx,
+        tracking: tracking_requests_tx,
+        arming: arming_requests_tx,
+        push: push_requests_tx,
+        provision: provision_requests_tx,
+        availability: availability_requests_tx,
+        link: link_requests_tx,
+        sensing: sensing_requests_tx,
+        operations: operations_tx,
+        network: network_requests_tx,
+        tunnel: tunnel_requests_tx,
+    } = services;
+    let mut experiment = Experiment::default();
 
     let mut requests = UnboundedReceiverStream::new(arena_request_rx);
     
@@ -81,51 +362,630 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
     let mut drone_tasks : FuturesUnordered<Drone> = Default::default();
     let mut drone_tx_map : HashMap<Uuid, drone::Sender> = Default::default();
     
+    let mut environment_schedule : environment::Schedule = Default::default();
+    let mut flags : Flags = Default::default();
+    let mut injection_templates : Vec<router::Template> = Default::default();
+    let mut shown_formation : Option<String> = None;
+    let mut bridge : Option<router::Bridge> = None;
+    let mut next_command_id : u64 = 0;
+    let mut history : History = Default::default();
+    let mut operator_lock : Lock = Default::default();
+    let mut request_ids : dedup::Requests = Default::default();
+    let mut activity : digest::Activity = Default::default();
+
+    let mut canary_interval = tokio::time::interval(Duration::from_secs(1));
+    /* the robots that stopped running since the last check of the canary */
+    let mut stopped_running : Vec<Uuid> = Default::default();
+    /* the rigid bodies of the drones that must remain tracked during the experiment */
+    let mut interlocked : HashMap<Uuid, i32> = Default::default();
+    let mut interlock_interval = tokio::time::interval(Duration::from_millis(100));
+    let mut chaos_interval = tokio::time::interval(Duration::from_secs(1));
+    /* the checksums of the software that passed the last canary experiment */
+    let mut validated_software : Option<(software::Checksums, software::Checksums)> = None;
+    /* the software of the last experiment that was validated or started, for rolling back */
+    let mut staged_software : Option<(Software, Software)> = None;
+    /* the last upload that was refused because of the software quota */
+    let mut refused_upload : Option<String> = None;
+    /* the run of the current experiment and the reports of the robots on its results */
+    let mut current_run : Option<Uuid> = None;
+    /* the clock of the journal of the current run */
+    let mut run_clock : Option<clock::Run> = None;
+    let mut chat = chat::Log::default();
+    let (harvest_tx, mut harvest_rx) : (harvest::Sender, harvest::Receiver) = mpsc::unbounded_channel();
+
     let mut pipuck_software : crate::software::Software = Default::default();
     let mut pipuck_tasks : FuturesUnordered<PiPuck> = Default::default();
     let mut pipuck_tx_map : HashMap<Uuid, pipuck::Sender> = Default::default();
 
+    let mut simulated_tasks : FuturesUnordered<Simulated> = Default::default();
+    let mut simulated_tx_map : HashMap<Uuid, simulated::Sender> = Default::default();
+
+    let mut compute_tasks : FuturesUnordered<Server> = Default::default();
+    let mut compute_tx_map : HashMap<Uuid, compute::Sender> = Default::default();
+    /* maps the uuid of a robot to the uuid of the compute server that runs its controller */
+    let mut remote_brains : HashMap<Uuid, Uuid> = Default::default();
+
+    /* the robot tasks report changes in their lifecycle, which are validated here */
+    let (lifecycle_tx, mut lifecycle_rx) : (lifecycle::Sender, lifecycle::Receiver) =
+        mpsc::unbounded_channel();
+    let mut lifecycles : HashMap<Uuid, Lifecycle> = Default::default();
+    /* the accepted lifecycles are published back to the robot tasks */
+    let mut published : HashMap<Uuid, watch::Sender<Lifecycle>> = Default::default();
+    /* the robot tasks report when the processors of their robots start or stop being throttled */
+    let (thermal_tx, mut thermal_rx) : (thermal::Sender, thermal::Receiver) = mpsc::unbounded_channel();
+
+    /* the hostnames of the connected robots and when the robots of the fleet were last connected */
+    let mut names : HashMap<Uuid, String> = Default::default();
+    /* the addresses of the devices of each robot, which are probed again when the robot is lost */
+    let mut addresses : HashMap<Uuid, Vec<Ipv4Addr>> = Default::default();
+    /* the operations that change the state of each robot, which are serialized */
+    let mut queues : HashMap<Uuid, Queue> = Default::default();
+    let mut last_seen : HashMap<String, std::time::SystemTime> = Default::default();
+    /* whether the network task has been asked to stop probing for new devices */
+    let mut scanning_paused = false;
+
     loop {
         tokio::select! {
             Some(request) = requests.next() => match request {
                 /* Arena requests */
                 Request::GetActions(callback) => {
-                    let actions = match state {
-                        State::Standby => vec![Action::StartExperiment],
-                        State::Active => vec![Action::StopExperiment],
+                    let mut actions = match experiment.phase() {
+                        Phase::Standby => vec![Action::StartCanary, Action::StartExperiment, Action::ReloadConfiguration],
+                        Phase::Canary{..} => vec![Action::StopExperiment, Action::ReloadConfiguration],
+                        Phase::Active{..} => vec![Action::StopExperiment, Action::AbortPreserve, Action::AbortDiscard, Action::ReloadConfiguration],
                     };
+                    actions.push(match scanning_paused {
+                        true => Action::ResumeScanning,
+                        false => Action::PauseScanning,
+                    });
                     if let Err(_) = callback.send(actions) {
                         log::error!("Could not respond with arena actions");
                     }
                 },
+                Request::GetGoldenImage(callback) => {
+                    if let Err(_) = callback.send(golden_image.clone()) {
+                        log::error!("Could not respond with golden image");
+                    }
+                },
+                Request::GetJournalStatus(callback) => {
+                    if let Err(_) = callback.send(journal.status()) {
+                        log::error!("Could not respond with journal status");
+                    }
+                },
+                Request::GetSnapshot(callback) => {
+                    let snapshot = replication::Snapshot {
+                        pipuck_software: pipuck_software.clone(),
+                        drone_software: drone_software.clone(),
+                        environment_schedule: environment_schedule.clone(),
+                        flags: flags.clone(),
+                        injection_templates: injection_templates.clone(),
+                        history: history.clone(),
+                        experiment_active: !experiment.is_standby(),
+                    };
+                    if let Err(_) = callback.send(snapshot) {
+                        log::error!("Could not respond with snapshot");
+                    }
+                },
+                Request::Restore(snapshot) => {
+                    pipuck_software = snapshot.pipuck_software;
+                    drone_software = snapshot.drone_software;
+                    environment_schedule = snapshot.environment_schedule;
+                    flags = snapshot.flags;
+                    injection_templates = snapshot.injection_templates;
+                    history = snapshot.history;
+                    log::info!("Restored the state of the primary supervisor");
+                    if snapshot.experiment_active {
+                        let message = "Took over from the primary supervisor during an experiment".to_owned();
+                        alert(&push_requests_tx, message.clone());
+                        activity.failures.push((std::time::SystemTime::now(), message));
+                    }
+                },
+                Request::GetStorage(callback) => {
+                    let usage = quota::Usage {
+                        software: pipuck_software.size() + drone_software.size(),
+                        quotas: config.borrow().quotas.clone(),
+                        refused_upload: refused_upload.clone(),
+                    };
+                    if let Err(_) = callback.send(usage) {
+                        log::error!("Could not respond with storage usage");
+                    }
+                },
+                Request::GetLifecycles(callback) => {
+                    if let Err(_) = callback.send(lifecycles.clone()) {
+                        log::error!("Could not respond with robot lifecycles");
+                    }
+                },
+                Request::GetQueues(callback) => {
+                    let queues = queues.iter()
+                        .map(|(uuid, queue)| (*uuid, (queue.entries(), queue.refused())))
+                        .collect();
+                    if let Err(_) = callback.send(queues) {
+                        log::error!("Could not respond with robot queues");
+                    }
+                },
+                Request::GetMissingRobots(callback) => {
+                    let missing = fleet::missing(&config.borrow().fleet, &names, &last_seen);
+                    if let Err(_) = callback.send(missing) {
+                        log::error!("Could not respond with missing robots");
+                    }
+                },
+                Request::Query(predicates, callback) =>
+                    handle_query_request(&pipuck_tx_map, &drone_tx_map, &simulated_tx_map, &lifecycles, &names, predicates, callback),
+                Request::ExecutePluginAction(uuid, action) =>
+                    plugins.execute(uuid, action),
+                Request::GetDocks(callback) => {
+                    let request = dock::Request::Statuses(callback);
+                    if let Err(error) = dock_requests_tx.send(request) {
+                        log::error!("Could not request dock statuses: {}", error);
+                    }
+                },
+                Request::GetTrackingFrame(stream, callback) => {
+                    let request = optitrack::Request::Frame(stream, callback);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not request tracking frame: {}", error);
+                    }
+                },
+                Request::GetTrackingMarkers(callback) => {
+                    let request = optitrack::Request::Markers(callback);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not request tracking markers: {}", error);
+                    }
+                },
+                Request::GetTrackingDiagnostics(callback) => {
+                    let request = optitrack::Request::Diagnostics(callback);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not request tracking diagnostics: {}", error);
+                    }
+                },
+                Request::SaveTrackingRecording => {
+                    let journal = journal.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = journal.save_recording().await {
+                            log::error!("Could not save the tracking recording: {}", error);
+                        }
+                    });
+                },
+                Request::GetArmingState(callback) => {
+                    let request = arming::Request::State(callback);
+                    if let Err(error) = arming_requests_tx.send(request) {
+                        log::error!("Could not request arming state: {}", error);
+                    }
+                },
+                Request::SubscribePush(subscription) => {
+                    if let Err(error) = push_requests_tx.send(push::Request::Subscribe(subscription)) {
+                        log::error!("Could not forward push subscription: {}", error);
+                    }
+                },
+                Request::GetRegionOccupancy(callback) => {
+                    let request = optitrack::Request::Occupancy(callback);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not request region occupancy: {}", error);
+                    }
+                },
+                Request::Formation(name, action) => {
+                    let formation = config.borrow().formations.iter()
+                        .find(|formation| formation.name == name)
+                        .cloned();
+                    match (formation, action) {
+                        (None, _) => log::warn!("Could not find formation {}", name),
+                        (Some(_), formation::Action::Show) => shown_formation = Some(name),
+                        (Some(_), formation::Action::Hide) => if shown_formation.as_ref() == Some(&name) {
+                            shown_formation = None;
+                        },
+                        (Some(formation), formation::Action::Measure) =>
+                            handle_measure_formation_request(formation, &tracking_requests_tx, journal),
+                        /* only the Pi-Pucks are repositioned */
+                        (Some(formation), formation::Action::Drive) => {
+                            let message = formation.message();
+                            for addr in robot_addresses(&pipuck_tx_map, &HashMap::new(), &HashMap::new()).await.keys() {
+                                handle_inject_message_request(&relay, journal, Some(*addr), &message);
+                            }
+                        },
+                        (Some(_), formation::Action::Stop) => {
+                            let message = formation::stop_message();
+                            for addr in robot_addresses(&pipuck_tx_map, &HashMap::new(), &HashMap::new()).await.keys() {
+                                handle_inject_message_request(&relay, journal, Some(*addr), &message);
+                            }
+                        },
+                    }
+                },
+                Request::GetFormations(callback) => {
+                    let formations = config.borrow().formations.clone();
+                    if let Err(_) = callback.send((formations, shown_formation.clone())) {
+                        log::error!("Could not respond with formations");
+                    }
+                },
+                Request::Calibrate(action) => {
+                    let request = optitrack::Request::Calibrate(action);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not forward calibration action: {}", error);
+                    }
+                },
+                Request::GetCalibration(callback) => {
+                    let request = optitrack::Request::Calibration(callback);
+                    if let Err(error) = tracking_requests_tx.send(request) {
+                        log::error!("Could not request calibration: {}", error);
+                    }
+                },
+                Request::SetEnvironmentSchedule(contents) => {
+                    match environment::Schedule::parse(&contents) {
+                        Ok(schedule) => environment_schedule = schedule,
+                        Err(error) => log::error!("Could not parse environment schedule: {}", error),
+                    }
+                },
+                Request::ClearEnvironmentSchedule =>
+                    environment_schedule = Default::default(),
+                Request::GetEnvironmentSchedule(callback) => {
+                    if let Err(_) = callback.send(environment_schedule.clone()) {
+                        log::error!("Could not respond with environment schedule");
+                    }
+                },
+                Request::SetFlags(contents) => {
+                    match Flags::parse(&contents) {
+                        Ok(update) => {
+                            flags = update;
+                            /* deliver the new flags to the controllers of a running experiment */
+                            if flags.live {
+                                if !experiment.is_standby() {
+                                    handle_broadcast_flags(&relay,
+                                                           journal,
+                                                           &flags,
+                                                           &pipuck_tx_map,
+                                                           &drone_tx_map,
+                                                           &simulated_tx_map).await;
+                                }
+                            }
+                        },
+                        Err(error) => log::error!("Could not parse flags: {}", error),
+                    }
+                },
+                Request::ClearFlags =>
+                    flags = Default::default(),
+                Request::GetFlags(callback) => {
+                    if let Err(_) = callback.send(flags.clone()) {
+                        log::error!("Could not respond with flags");
+                    }
+                },
+                Request::AddInjectionTemplates(contents) => {
+                    match serde_json::from_slice::<Vec<router::Template>>(&contents) {
+                        Ok(templates) => for template in templates {
+                            /* replace templates with the same name */
+                            injection_templates.retain(|existing| existing.name != template.name);
+                            injection_templates.push(template);
+                        },
+                        Err(error) => log::error!("Could not parse message templates: {}", error),
+                    }
+                },
+                Request::ClearInjectionTemplates =>
+                    injection_templates.clear(),
+                Request::GetInjectionTemplates(callback) => {
+                    if let Err(_) = callback.send(injection_templates.clone()) {
+                        log::error!("Could not respond with message templates");
+                    }
+                },
+                Request::InjectMessage(recipient, message) =>
+                    handle_inject_message_request(&relay, journal, recipient, &message),
+                Request::BridgeSimulation(addr) => {
+                    /* dropping the previous bridge disconnects it */
+                    bridge = addr.map(|addr| router::Bridge::new(addr, relay.clone(), journal.clone()));
+                },
+                Request::GetSimulationBridge(callback) => {
+                    let status = bridge.as_ref().map(|bridge| (bridge.addr, bridge.is_connected()));
+                    if let Err(_) = callback.send(status) {
+                        log::error!("Could not respond with simulation bridge");
+                    }
+                },
+                Request::InjectTemplate(name) => {
+                    match injection_templates.iter().find(|template| template.name == name) {
+                        Some(template) =>
+                            handle_inject_message_request(&relay, journal, template.recipient, &template.message),
+                        None => log::warn!("Could not find message template {}", name),
+                    }
+                },
+                Request::BroadcastCommand{command, deadline, callback} => {
+                    match router::json_to_lua(&command) {
+                        Some(router::LuaType::Table(command)) => {
+                            next_command_id += 1;
+                            let robots = robot_addresses(&pipuck_tx_map, &drone_tx_map, &simulated_tx_map).await;
+                            let event = journal::Event::Broadcast(router::supervisor_addr(), router::LuaType::Table(command.clone()));
+                            if let Err(error) = journal.record(event) {
+                                log::warn!("Could not record command in journal: {}", error);
+                            }
+                            /* wait for the acknowledgements without blocking the arena */
+                            let acknowledgements = command::broadcast(relay.clone(), robots, next_command_id, command, deadline);
+                            tokio::spawn(async move {
+                                let _ = callback.send(acknowledgements.await);
+                            });
+                        },
+                        _ => log::error!("Could not broadcast command: commands must be JSON objects"),
+                    }
+                },
+                Request::GetTelemetry(uuid, callback) => {
+                    let request = telemetry::Request::History(uuid, callback);
+                    if let Err(error) = telemetry_requests_tx.send(request) {
+                        log::error!("Could not request telemetry history: {}", error);
+                    }
+                },
+                Request::SubscribeTelemetry(callback) => {
+                    let request = telemetry::Request::Subscribe(callback);
+                    if let Err(error) = telemetry_requests_tx.send(request) {
+                        log::error!("Could not subscribe to telemetry: {}", error);
+                    }
+                },
                 Request::Execute(action) => match action {
+                    Action::StartCanary => {
+                        let commands = match experiment.handle(Event::StartRequested { canary: true }).as_slice() {
+                            [Command::StartCanary] => {
+                                let arm_drones_result = match verify_software(&config, &pipuck_software, &drone_software) {
+                                    Ok(_) => arm_drones(&drone_tx_map, &tracking_requests_tx, &arming_requests_tx, journal, &config).await,
+                                    Err(error) => Err(error),
+                                };
+                                let start_canary_result = match arm_drones_result {
+                                    Ok(rigid_bodies) => {
+                                        interlocked = rigid_bodies;
+                                        start_canary(&pipuck_tx_map,
+                                                     &pipuck_software,
+                                                     &drone_tx_map,
+                                                     &drone_software,
+                                                     &flags,
+                                                     journal,
+                                                     &config,
+                                                     &dock_requests_tx,
+                                                     &operations_tx,
+                                                     &queues).await
+                                    },
+                                    Err(error) => Err(error),
+                                };
+                                match start_canary_result {
+                                    Ok(robots) => {
+                                        let duration = Duration::from_secs_f64(config.borrow().canary_duration);
+                                        log::info!("Started canary experiment on {:?}", robots);
+                                        stopped_running.clear();
+                                        let deadline = tokio::time::Instant::now() + duration;
+                                        experiment.handle(Event::CanaryStarted { robots, deadline })
+                                    },
+                                    Err(error) =>
+                                        experiment.handle(Event::StartFailed(format!("Could not start canary experiment: {}", error))),
+                                }
+                            },
+                            refused => refused.to_vec(),
+                        };
+                        execute(commands,
+                                &pipuck_tx_map,
+                                &drone_tx_map,
+                                &simulated_tx_map,
+                                &compute_tx_map,
+                                journal,
+                                &environment_requests_tx,
+                                &push_requests_tx,
+                                &mut interlocked,
+                                &mut activity,
+                                (&mut pipuck_software, &mut drone_software),
+                                &mut validated_software,
+                                &mut staged_software,
+                                current_run,
+                                &mut history,
+                                &harvest_tx,
+                                &operations_tx,
+                                &queues,
+                                &config).await;
+                    },
                     Action::StartExperiment => {
-                        let start_experiment_result = 
-                            start_experiment(&pipuck_tx_map,
-                                             &pipuck_software,
-                                             &drone_tx_map,
-                                             &drone_software,
-                                             &journal_requests_tx).await;
-                        match start_experiment_result {
-                            Ok(_) => state = State::Active,
-                            Err(error) => log::error!("Could not start experiment: {}", error),
+                        let commands = match experiment.handle(Event::StartRequested { canary: false }).as_slice() {
+                            [Command::StartExperiment] => {
+                                let require_canary = config.borrow().require_canary;
+                                let checksums = (pipuck_software.checksums(), drone_software.checksums());
+                                let required = required_robots(&config, &names);
+                                let (signers, verify_result) = match verify_software(&config, &pipuck_software, &drone_software) {
+                                    Ok(signers) => (signers, Ok(())),
+                                    Err(error) => (Vec::new(), Err(error)),
+                                };
+                                let next_run = Uuid::new_v4();
+                                let hook_context = hook::Context {
+                                    run: next_run,
+                                    started: std::time::SystemTime::now(),
+                                    robots: pipuck_tx_map.keys()
+                                        .chain(drone_tx_map.keys())
+                                        .chain(simulated_tx_map.keys())
+                                        .cloned()
+                                        .collect(),
+                                    pipuck_software: pipuck_software.version(),
+                                    drone_software: drone_software.version(),
+                                    aborted: false,
+                                };
+                                let hooks = config.borrow().hooks.clone();
+                                let start_experiment_result = if require_canary && validated_software != Some(checksums) {
+                                    Err(Error::CanaryRequired)
+                                }
+                                else if !required.is_empty() {
+                                    Err(Error::MissingRobots(required))
+                                }
+                                else if let Err(error) = verify_result {
+                                    Err(error)
+                                }
+                                else if let Err(error) = hook::pre_run(&hooks, &hook_context).await {
+                                    Err(Error::HookError(error))
+                                }
+                                else {
+                                    match arm_drones(&drone_tx_map, &tracking_requests_tx, &arming_requests_tx, journal, &config).await {
+                                        Ok(rigid_bodies) => {
+                                            interlocked = rigid_bodies;
+                                            start_experiment(&pipuck_tx_map,
+                                                             &pipuck_software,
+                                                             &drone_tx_map,
+                                                             &drone_software,
+                                                             &simulated_tx_map,
+                                                             &compute_tx_map,
+                                                             &remote_brains,
+                                                             &flags,
+                                                             journal,
+                                                             &config,
+                                                             &dock_requests_tx,
+                                                             &operations_tx,
+                                                             &queues).await
+                                        },
+                                        Err(error) => Err(error),
+                                    }
+                                };
+                                match start_experiment_result {
+                                    Ok((journal_path, clock)) => {
+                                        run_clock = Some(clock);
+                                        staged_software = Some((pipuck_software.clone(), drone_software.clone()));
+                                        let run = history::Run {
+                                            started: std::time::SystemTime::now(),
+                                            pipuck_software: pipuck_software.clone(),
+                                            drone_software: drone_software.clone(),
+                                            environment_schedule: environment_schedule.clone(),
+                                            flags: flags.clone(),
+                                            injection_templates: injection_templates.clone(),
+                                            robots: pipuck_tx_map.keys()
+                                                .chain(drone_tx_map.keys())
+                                                .chain(simulated_tx_map.keys())
+                                                .cloned()
+                                                .collect(),
+                                            names: names.iter()
+                                                .filter(|(uuid, _)| pipuck_tx_map.contains_key(uuid) || drone_tx_map.contains_key(uuid))
+                                                .map(|(uuid, name)| (*uuid, name.clone()))
+                                                .collect(),
+                                            signers,
+                                            results: HashMap::new(),
+                                            aborted: false,
+                                            throttled: Vec::new(),
+                                            annotations: Vec::new(),
+                                            journal: Some(journal_path),
+                                        };
+                                        let run_robots = run.robots.clone();
+                                        activity.runs.push(run.started);
+                                        history.record(next_run, run);
+                                        current_run = Some(next_run);
+                                        let request = environment::Request::Start(environment_schedule.clone());
+                                        if let Err(error) = environment_requests_tx.send(request) {
+                                            log::error!("Could not start environment schedule: {}", error);
+                                        }
+                                        experiment.handle(Event::ExperimentStarted { robots: run_robots })
+                                    },
+                                    Err(error) =>
+                                        experiment.handle(Event::StartFailed(format!("Could not start experiment: {}", error))),
+                                }
+                            },
+                            refused => refused.to_vec(),
                         };
+                        execute(commands,
+                                &pipuck_tx_map,
+                                &drone_tx_map,
+                                &simulated_tx_map,
+                                &compute_tx_map,
+                                journal,
+                                &environment_requests_tx,
+                                &push_requests_tx,
+                                &mut interlocked,
+                                &mut activity,
+                                (&mut pipuck_software, &mut drone_software),
+                                &mut validated_software,
+                                &mut staged_software,
+                                current_run,
+                                &mut history,
+                                &harvest_tx,
+                                &operations_tx,
+                                &queues,
+                                &config).await;
+                    },
+                    Action::StopExperiment | Action::AbortPreserve | Action::AbortDiscard => {
+                        let commands = experiment.handle(match action {
+                            Action::AbortPreserve => Event::AbortRequested { discard: false },
+                            Action::AbortDiscard => Event::AbortRequested { discard: true },
+                            _ => Event::StopRequested,
+                        });
+                        execute(commands,
+                                &pipuck_tx_map,
+                                &drone_tx_map,
+                                &simulated_tx_map,
+                                &compute_tx_map,
+                                journal,
+                                &environment_requests_tx,
+                                &push_requests_tx,
+                                &mut interlocked,
+                                &mut activity,
+                                (&mut pipuck_software, &mut drone_software),
+                                &mut validated_software,
+                                &mut staged_software,
+                                current_run,
+                                &mut history,
+                                &harvest_tx,
+                                &operations_tx,
+                                &queues,
+                                &config).await;
+                    },
+                    Action::ReloadConfiguration => {
+                        if let Err(error) = config_reload_tx.send(()) {
+                            log::error!("Could not request configuration reload: {}", error);
+                        }
+                    },
+                    Action::PauseScanning | Action::ResumeScanning => {
+                        let pause = action == Action::PauseScanning;
+                        let request = match pause {
+                            true => network::Request::Pause,
+                            false => network::Request::Resume,
+                        };
+                        match network_requests_tx.send(request) {
+                            Ok(_) => scanning_paused = pause,
+                            Err(_) => log::error!("Could not reach the network task"),
+                        }
                     },
-                    Action::StopExperiment => {
-                        stop_experiment(&pipuck_tx_map, &drone_tx_map, &journal_requests_tx).await;
-                        state = State::Standby;
-                    }
                 }
+                /* Network requests */
+                Request::RegisterDevice(addr, probe) => {
+                    if let Err(_) = network_requests_tx.send(network::Request::Register(addr, probe)) {
+                        log::error!("Could not register {:?} device at {}", probe, addr);
+                    }
+                },
+                Request::GetRegisteredDevices(callback) => {
+                    if let Err(_) = network_requests_tx.send(network::Request::GetRegistered(callback)) {
+                        log::error!("Could not request the registered devices");
+                    }
+                },
+                Request::SetDiscoveryFilter(filter) => {
+                    if let Err(_) = network_requests_tx.send(network::Request::SetFilter(filter)) {
+                        log::error!("Could not change the discovery filter");
+                    }
+                },
+                Request::GetDiscoveryFilter(callback) => {
+                    if let Err(_) = network_requests_tx.send(network::Request::GetFilter(callback)) {
+                        log::error!("Could not request the discovery filter");
+                    }
+                },
                 /* Drone requests */
                 Request::AddDrone(device) => {
-                    let (uuid, tx, task) = Drone::new(device);
+                    let addr = device.addr;
+                    let link = link::Device::Xbee(device.clone());
+                    let (uuid, tx, task) =
+                        Drone::new(device, telemetry_requests_tx.clone(), lifecycle_tx.clone(), thermal_tx.clone(), config.clone());
+                    let _ = link_requests_tx.send(link::Request::Watch(uuid, link::Interface::Xbee, link, None));
+                    addresses.insert(uuid, vec![addr]);
+                    queues.insert(uuid, Queue::default());
+                    lifecycles.insert(uuid, Lifecycle::Discovered);
                     drone_tx_map.insert(uuid, tx);
-                    drone_tasks.push(task)
+                    drone_tasks.push(task);
+                    for command in experiment.handle(Event::RobotDiscovered(uuid)) {
+                        if let Command::Alert(message) = command {
+                            alert(&push_requests_tx, message);
+                        }
+                    }
                 }
-                Request::AddDroneSoftware(path, contents) =>
-                    drone_software.add(path, contents),
-                Request::ClearDroneSoftware =>
-                    drone_software.clear(),
+                Request::AddDroneSoftware(path, contents) => {
+                    let size = pipuck_software.size() + drone_software.size_with(&path, contents.len());
+                    match check_software_quota(&config, &path, size) {
+                        Ok(_) => drone_software.add(path, contents),
+                        Err(error) => {
+                            log::error!("{}", error);
+                            refused_upload = Some(error);
+                        }
+                    }
+                },
+                Request::ClearDroneSoftware => {
+                    drone_software.clear();
+                    refused_upload = None;
+                },
                 Request::CheckDroneSoftware(callback) => {
                     let checksums = drone_software.checksums();
                     let check = drone_software.check_config();
@@ -134,7 +994,7 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
                     }
                 },
                 Request::ForwardDroneAction(uuid, action) => 
-                    handle_forward_drone_action_request(&drone_tx_map, uuid, action).await,
+                    handle_forward_drone_action_request(&drone_tx_map, &queues, &config, uuid, action).await,
                 /*
                 Request::ForwardDroneActionAll(action) => {
                     for (uuid, tx) in drone_tx_map.iter() {
@@ -147,19 +1007,58 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
                 */
                 Request::GetDrones(callback) => 
                     handle_get_drones_request(&drone_tx_map, callback).await,
-                Request::PairWithDrone(device) => {
-                    handle_pair_with_drone_request(&drone_tx_map, device).await;
+                Request::PairWithDrone(device, hostname) => {
+                    let addr = device.addr;
+                    let link = link::Device::Fernbedienung(device.clone());
+                    match handle_pair_with_drone_request(&drone_tx_map, device).await {
+                        Ok(Some(uuid)) => {
+                            addresses.entry(uuid).or_default().push(addr);
+                            let _ = link_requests_tx.send(link::Request::Watch(uuid, link::Interface::UpCore, link, Some(hostname.clone())));
+                            let _ = sensing_requests_tx.send(sensing::Request::Subscribe(uuid, addr));
+                            let _ = availability_requests_tx.send(availability::Request::Connected(hostname.clone()));
+                            let _ = telemetry_requests_tx.send(telemetry::Request::Identify(uuid, hostname.clone()));
+                            names.insert(uuid, hostname);
+                        },
+                        Ok(None) => {},
+                        Err(error) => log::warn!("Could not pair UP Core {} with a drone: {}", hostname, error),
+                    }
                 },
                 /* Pi-Puck requests */
-                Request::AddPiPuck(device) => {
-                    let (uuid, tx, task) = PiPuck::new(device);
+                Request::AddPiPuck(device, hostname) => {
+                    let addr = device.addr;
+                    let link = link::Device::Fernbedienung(device.clone());
+                    let (uuid, tx, task) =
+                        PiPuck::new(device, telemetry_requests_tx.clone(), lifecycle_tx.clone(), thermal_tx.clone(), config.clone());
+                    let _ = link_requests_tx.send(link::Request::Watch(uuid, link::Interface::RaspberryPi, link, Some(hostname.clone())));
+                    let _ = sensing_requests_tx.send(sensing::Request::Subscribe(uuid, addr));
+                    lifecycles.insert(uuid, Lifecycle::Discovered);
+                    let _ = availability_requests_tx.send(availability::Request::Connected(hostname.clone()));
+                    let _ = telemetry_requests_tx.send(telemetry::Request::Identify(uuid, hostname.clone()));
+                    names.insert(uuid, hostname);
+                    addresses.insert(uuid, vec![addr]);
+                    queues.insert(uuid, Queue::default());
                     pipuck_tx_map.insert(uuid, tx);
-                    pipuck_tasks.push(task)
+                    pipuck_tasks.push(task);
+                    for command in experiment.handle(Event::RobotDiscovered(uuid)) {
+                        if let Command::Alert(message) = command {
+                            alert(&push_requests_tx, message);
+                        }
+                    }
+                },
+                Request::AddPiPuckSoftware(path, contents) => {
+                    let size = pipuck_software.size_with(&path, contents.len()) + drone_software.size();
+                    match check_software_quota(&config, &path, size) {
+                        Ok(_) => pipuck_software.add(path, contents),
+                        Err(error) => {
+                            log::error!("{}", error);
+                            refused_upload = Some(error);
+                        }
+                    }
+                },
+                Request::ClearPiPuckSoftware => {
+                    pipuck_software.clear();
+                    refused_upload = None;
                 },
-                Request::AddPiPuckSoftware(path, contents) =>
-                    pipuck_software.add(path, contents),
-                Request::ClearPiPuckSoftware =>
-                    pipuck_software.clear(),
                 Request::CheckPiPuckSoftware(callback) => {
                     let checksums = pipuck_software.checksums();
                     let check = pipuck_software.check_config();
@@ -168,7 +1067,7 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
                     }
                 },
                 Request::ForwardPiPuckAction(uuid, action) => 
-                    handle_forward_pipuck_action_request(&pipuck_tx_map, uuid, action),
+                    handle_forward_pipuck_action_request(&pipuck_tx_map, &queues, &config, uuid, action),
                 /*
                 Request::ForwardPiPuckActionAll(action) => {
                     for (uuid, tx) in pipuck_tx_map.iter() {
@@ -181,16 +1080,476 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
                 */
                 Request::GetPiPucks(callback) => 
                     handle_get_pipucks_request(&pipuck_tx_map, callback).await,
+                /* Simulated robot requests */
+                Request::AddVirtualRobot(kind) => {
+                    /* simulated robots connect to the message router on this machine */
+                    let router = SocketAddr::from((Ipv4Addr::LOCALHOST, 4950));
+                    let (uuid, tx, task) = Simulated::new(kind, router, lifecycle_tx.clone());
+                    lifecycles.insert(uuid, Lifecycle::Discovered);
+                    simulated_tx_map.insert(uuid, tx);
+                    simulated_tasks.push(task);
+                    for command in experiment.handle(Event::RobotDiscovered(uuid)) {
+                        if let Command::Alert(message) = command {
+                            alert(&push_requests_tx, message);
+                        }
+                    }
+                },
+                Request::GetVirtualRobots(callback) =>
+                    handle_get_virtual_robots_request(&simulated_tx_map, callback).await,
+                /* Operator lock requests */
+                Request::Lock(request) => operator_lock.handle(request, config.borrow().auth.as_ref()),
+                Request::Dedup(request) => request_ids.handle(request),
+                /* Maintenance requests */
+                Request::GetExperimentActive(callback) => {
+                    if let Err(_) = callback.send(!experiment.is_standby()) {
+                        log::error!("Could not respond with whether an experiment is active");
+                    }
+                },
+                Request::RunScript(uuid, script, callback) => match experiment.is_standby() {
+                    true => handle_run_script_request(&pipuck_tx_map, &drone_tx_map, &queues, uuid, script, callback),
+                    false => {
+                        let _ = callback.send(Err(Error::ExperimentActive));
+                    },
+                },
+                Request::Campaign(campaign::Request::Start(_)) if !experiment.is_standby() =>
+                    log::warn!("Campaigns cannot be started during an experiment"),
+                Request::Campaign(request) => {
+                    if let Err(error) = campaign_requests_tx.send(request) {
+                        log::error!("Could not forward request to campaign task: {}", error);
+                    }
+                },
+                Request::FindRobot(hostname, callback) => {
+                    /* the fernbedienung device of a drone is its UP Core, which is added last */
+                    let addr = names.iter()
+                        .find(|(_, name)| **name == hostname)
+                        .and_then(|(uuid, _)| addresses.get(uuid))
+                        .and_then(|addresses| addresses.last().cloned());
+                    if let Err(_) = callback.send(addr) {
+                        log::error!("Could not respond with the address of {}", hostname);
+                    }
+                },
+                Request::SelfTest(request) => {
+                    if let Err(error) = selftest_requests_tx.send(request) {
+                        log::error!("Could not forward request to self test task: {}", error);
+                    }
+                },
+                Request::Chat(mut message) => {
+                    let run = match (experiment.phase(), current_run, run_clock) {
+                        (Phase::Active{..}, Some(run), Some(clock)) => Some((run, clock.at(Instant::now()))),
+                        _ => None,
+                    };
+                    message.run = run;
+                    if let Some((run, _)) = run {
+                        if let Err(error) = journal.record(journal::Event::Chat(message.clone())) {
+                            log::error!("Could not journal chat message: {}", error);
+                        }
+                        if message.pinned {
+                            match history.find_mut(&run) {
+                                Some(record) => record.annotations.push(message.clone()),
+                                None => log::warn!("Could not pin chat message, run {} was deleted", run),
+                            }
+                        }
+                    }
+                    /* a message can only be pinned to a run that is in progress */
+                    else {
+                        message.pinned = false;
+                    }
+                    log::info!("Chat message from {}: {}", message.author, message.text);
+                    chat.push(message);
+                },
+                Request::GetChat(callback) => {
+                    let _ = callback.send(chat.messages());
+                },
+                Request::Operation(request) => {
+                    if let Err(error) = operations_tx.send(request) {
+                        log::error!("Could not forward request to operation task: {}", error);
+                    }
+                },
+                Request::Provision(request) => {
+                    if let Err(error) = provision_requests_tx.send(request) {
+                        log::error!("Could not forward request to provision task: {}", error);
+                    }
+                },
+                Request::Availability(request) => {
+                    if let Err(error) = availability_requests_tx.send(request) {
+                        log::error!("Could not forward request to availability task: {}", error);
+                    }
+                },
+                Request::GetLinks(callback) => {
+                    if let Err(error) = link_requests_tx.send(link::Request::Links(callback)) {
+                        log::error!("Could not request link status: {}", error);
+                    }
+                },
+                Request::GetLinkHeatmap(callback) => {
+                    if let Err(error) = link_requests_tx.send(link::Request::Heatmap(callback)) {
+                        log::error!("Could not request link heatmap: {}", error);
+                    }
+                },
+                Request::ClearLinkHeatmap => {
+                    if let Err(error) = link_requests_tx.send(link::Request::ClearHeatmap) {
+                        log::error!("Could not clear link heatmap: {}", error);
+                    }
+                },
+                Request::GetWifiSurvey(callback) => {
+                    if let Err(error) = link_requests_tx.send(link::Request::Survey(callback)) {
+                        log::error!("Could not request Wi-Fi survey: {}", error);
+                    }
+                },
+                Request::TailFile(uuid, path, lines, output_tx) => {
+                    let result = match (pipuck_tx_map.get(&uuid), drone_tx_map.get(&uuid)) {
+                        (Some(tx), _) => tx.send(pipuck::Request::Tail { path, lines, output_tx }).map_err(|_| ()),
+                        (_, Some(tx)) => tx.send(drone::Request::Tail { path, lines, output_tx }).map_err(|_| ()),
+                        (None, None) => Ok(()),
+                    };
+                    if let Err(_) = result {
+                        log::error!("Could not forward tail request to robot {}", uuid);
+                    }
+                },
+                Request::Shutdown(callback) => {
+                    let commands = experiment.handle(Event::ShutdownRequested);
+                    if !commands.is_empty() {
+                        log::warn!("Stopping the experiment before shutting down");
+                    }
+                    execute(commands,
+                            &pipuck_tx_map,
+                            &drone_tx_map,
+                            &simulated_tx_map,
+                            &compute_tx_map,
+                            journal,
+                            &environment_requests_tx,
+                            &push_requests_tx,
+                            &mut interlocked,
+                            &mut activity,
+                            (&mut pipuck_software, &mut drone_software),
+                            &mut validated_software,
+                            &mut staged_software,
+                            current_run,
+                            &mut history,
+                            &harvest_tx,
+                            &operations_tx,
+                            &queues,
+                            &config).await;
+                    /* the telemetry task responds once the telemetry has been written */
+                    if let Err(error) = telemetry_requests_tx.send(telemetry::Request::Persist(callback)) {
+                        log::error!("Could not persist telemetry: {}", error);
+                    }
+                },
+                Request::TakeActivity(callback) => {
+                    if let Err(_) = callback.send(std::mem::take(&mut activity)) {
+                        log::error!("Could not respond with activity");
+                    }
+                },
+                Request::RestoreActivity(taken) => activity.restore(taken),
+                /* History requests */
+                Request::GetHistory(callback) => {
+                    if let Err(_) = callback.send(history.clone()) {
+                        log::error!("Could not respond with history");
+                    }
+                },
+                Request::ExecuteHistoryAction(action, uuid, name) => match action {
+                    history::Action::Clone => match (experiment.is_standby(), history.find(&uuid)) {
+                        (false, _) =>
+                            log::warn!("Cannot clone a run while an experiment is active"),
+                        (true, None) =>
+                            log::warn!("Could not find run or template {}", uuid),
+                        (true, Some(run)) => {
+                            pipuck_software = run.pipuck_software.clone();
+                            drone_software = run.drone_software.clone();
+                            environment_schedule = run.environment_schedule.clone();
+                            flags = run.flags.clone();
+                            injection_templates = run.injection_templates.clone();
+                            for robot in run.robots.iter() {
+                                if !lifecycles.contains_key(robot) {
+                                    log::warn!("Robot {} from the cloned run is not connected", robot);
+                                }
+                            }
+                        },
+                    },
+                    history::Action::SaveTemplate => match name {
+                        Some(name) => if !history.save_template(&uuid, name) {
+                            log::warn!("Could not find run {}", uuid);
+                        },
+                        None => log::warn!("A template requires a name"),
+                    },
+                    history::Action::Delete => history.delete(&uuid),
+                    history::Action::Export => {
+                        /* the name is the redaction profile, if any */
+                        let profile = name.filter(|name| !name.is_empty()).map(|name| config.borrow()
+                            .redaction_profiles
+                            .iter()
+                            .find(|profile| profile.name == name)
+                            .cloned()
+                            .ok_or(name));
+                        match (history.find(&uuid), profile) {
+                            (_, Some(Err(name))) =>
+                                log::warn!("Could not find redaction profile {}", name),
+                            (None, _) =>
+                                log::warn!("Could not find run {}", uuid),
+                            (Some(run), profile) => {
+                                let run = run.clone();
+                                let profile = profile.and_then(Result::ok);
+                                let metadata = config.borrow().data_package.clone();
+                                /* hashing the results and copying the journal can take a while */
+                                tokio::task::spawn_blocking(move || match package::export(&uuid, &run, &metadata, profile.as_ref()) {
+                                    Ok(path) => log::info!("Exported run {} to {}", uuid, path.display()),
+                                    Err(error) => log::error!("Could not export run {}: {}", uuid, error),
+                                });
+                            },
+                        }
+                    },
+                },
+                /* Compute server requests */
+                Request::AddComputeServer(device) => {
+                    let addr = device.addr;
+                    let (uuid, tx, task) = Server::new(device);
+                    addresses.insert(uuid, vec![addr]);
+                    compute_tx_map.insert(uuid, tx);
+                    compute_tasks.push(task);
+                },
+                Request::GetComputeServers(callback) =>
+                    handle_get_compute_servers_request(&compute_tx_map, callback).await,
+                Request::AssignRemoteBrain(robot, server) => {
+                    match server {
+                        /* ARGoS must support the remote brain mode, see robot::argos::Connection */
+                        Some(_) if !config.borrow().remote_brains =>
+                            log::warn!("Could not assign a remote brain to robot {}: remote brains are disabled", robot),
+                        /* only physical robots can run their sensors and actuators without a controller */
+                        Some(_) if !pipuck_tx_map.contains_key(&robot) && !drone_tx_map.contains_key(&robot) =>
+                            log::warn!("Could not find Pi-Puck or drone {}", robot),
+                        Some(server) => match compute_tx_map.contains_key(&server) {
+                            true => {
+                                log::info!("Controller of robot {} will run on compute server {}", robot, server);
+                                remote_brains.insert(robot, server);
+                            },
+                            false => log::warn!("Could not find compute server {}", server),
+                        },
+                        None => {
+                            remote_brains.remove(&robot);
+                        },
+                    }
+                    let allowed = remote_brains.keys().cloned().collect();
+                    if let Err(error) = tunnel_requests_tx.send(tunnel::Request::Allow(allowed)) {
+                        log::warn!("Could not update the robots of the tunnel: {}", error);
+                    }
+                },
+                Request::GetRemoteBrains(callback) => {
+                    if let Err(_) = callback.send(remote_brains.clone()) {
+                        log::error!("Could not respond with remote brains");
+                    }
+                },
+            },
+            /* a canary fails if any of its robots stop running before the deadline */
+            _ = canary_interval.tick() => {
+                let commands = experiment.handle(Event::Tick { now: tokio::time::Instant::now(), stopped: &stopped_running });
+                stopped_running.clear();
+                execute(commands,
+                        &pipuck_tx_map,
+                        &drone_tx_map,
+                        &simulated_tx_map,
+                        &compute_tx_map,
+                        journal,
+                        &environment_requests_tx,
+                        &push_requests_tx,
+                        &mut interlocked,
+                        &mut activity,
+                        (&mut pipuck_software, &mut drone_software),
+                        &mut validated_software,
+                        &mut staged_software,
+                        current_run,
+                        &mut history,
+                        &harvest_tx,
+                        &operations_tx,
+                        &queues,
+                        &config).await;
+            },
+            /* land the drones and stop the experiment if the drones are no longer tracked */
+            _ = interlock_interval.tick(), if !interlocked.is_empty() => {
+                let interlock = config.borrow().drone_interlock.clone();
+                let result = match (experiment.is_standby(), interlock) {
+                    (true, _) | (_, None) => Ok(()),
+                    (_, Some(interlock)) => match tracking_diagnostics(&tracking_requests_tx).await {
+                        Ok(diagnostics) => interlock.check(&diagnostics, &interlocked),
+                        Err(error) => Err(error),
+                    }
+                };
+                if let Err(error) = result {
+                    let commands = experiment.handle(Event::InterlockTripped(error.to_string()));
+                    execute(commands,
+                            &pipuck_tx_map,
+                            &drone_tx_map,
+                            &simulated_tx_map,
+                            &compute_tx_map,
+                            journal,
+                            &environment_requests_tx,
+                            &push_requests_tx,
+                            &mut interlocked,
+                            &mut activity,
+                            (&mut pipuck_software, &mut drone_software),
+                            &mut validated_software,
+                            &mut staged_software,
+                            current_run,
+                            &mut history,
+                            &harvest_tx,
+                            &operations_tx,
+                            &queues,
+                            &config).await;
+                }
+            },
+            /* failure point for testing how the arena recovers from robot tasks that end unexpectedly */
+            _ = chaos_interval.tick(), if config.borrow().chaos.is_some() => {
+                let kill = config.borrow().chaos.as_ref().map_or(false, |chaos| chaos.kill());
+                let count = pipuck_tasks.len() + drone_tasks.len() + simulated_tasks.len();
+                if kill && count > 0 {
+                    let index = rand::thread_rng().gen_range(0..count);
+                    log::warn!("Chaos: killing the task of a robot");
+                    if index < pipuck_tasks.len() {
+                        if let Some(task) = pipuck_tasks.iter().nth(index) {
+                            task.abort();
+                        }
+                    }
+                    else if index < pipuck_tasks.len() + drone_tasks.len() {
+                        if let Some(task) = drone_tasks.iter().nth(index - pipuck_tasks.len()) {
+                            task.abort();
+                        }
+                    }
+                    else if let Some(task) = simulated_tasks.iter().nth(index - pipuck_tasks.len() - drone_tasks.len()) {
+                        task.abort();
+                    }
+                }
+            },
+            Some((run, uuid, report)) = harvest_rx.recv() => {
+                if !report.missing.is_empty() || !report.failed.is_empty() {
+                    log::warn!("Results of robot {} are incomplete: {} missing, {} failed",
+                        uuid, report.missing.len(), report.failed.len());
+                }
+                if !history.record_results(&run, uuid, report) {
+                    log::warn!("Could not record the results of robot {}, run {} was deleted", uuid, run);
+                }
+                /* the datasets are merged again as the reports of the robots arrive */
+                else if let Some(recorded) = history.find(&run) {
+                    let (started, reports) = (recorded.started, recorded.results.clone());
+                    tokio::task::spawn_blocking(move || match merge::merge(&run, started, &reports) {
+                        Ok(datasets) => log::info!("Merged the results of run {} into {:?}", run, datasets),
+                        Err(error) => log::error!("Could not merge the results of run {}: {}", run, error),
+                    });
+                }
+            },
+            Some((uuid, report)) = lifecycle_rx.recv() => match report {
+                lifecycle::Report::Started(accepted) => {
+                    published.insert(uuid, accepted);
+                },
+                lifecycle::Report::Transition(next) => {
+                    let accepted = handle_lifecycle_transition(&mut lifecycles, &published, &mut stopped_running, journal, uuid, next);
+                    if accepted && next == Lifecycle::Degraded {
+                        activity.degraded.insert(uuid);
+                        let drone = drone_tx_map.contains_key(&uuid);
+                        for command in experiment.handle(Event::RobotDegraded { uuid, drone }) {
+                            if let Command::Alert(message) = command {
+                                alert(&push_requests_tx, message);
+                            }
+                        }
+                    }
+                },
+            },
+            Some((uuid, throttled)) = thermal_rx.recv() => match throttled {
+                true => {
+                    log::warn!("Robot {} is thermally throttling", uuid);
+                    let commands = experiment.handle(Event::RobotThrottled(uuid));
+                    execute(commands,
+                            &pipuck_tx_map,
+                            &drone_tx_map,
+                            &simulated_tx_map,
+                            &compute_tx_map,
+                            journal,
+                            &environment_requests_tx,
+                            &push_requests_tx,
+                            &mut interlocked,
+                            &mut activity,
+                            (&mut pipuck_software, &mut drone_software),
+                            &mut validated_software,
+                            &mut staged_software,
+                            current_run,
+                            &mut history,
+                            &harvest_tx,
+                            &operations_tx,
+                            &queues,
+                            &config).await;
+                },
+                false => log::info!("Robot {} is no longer thermally throttling", uuid),
             },
             Some(result) = drone_tasks.next() => match result {
                 Ok(uuid) => {
+                    for command in experiment.handle(Event::DroneLost(uuid)) {
+                        if let Command::Alert(message) = command {
+                            alert(&push_requests_tx, message);
+                        }
+                    }
                     drone_tx_map.remove(&uuid);
+                    queues.remove(&uuid);
+                    let _ = link_requests_tx.send(link::Request::Unwatch(uuid));
+                    let _ = sensing_requests_tx.send(sensing::Request::Unsubscribe(uuid));
+                    remote_brains.remove(&uuid);
+                    return_addresses(&network_requests_tx, &mut addresses, &uuid);
+                    let _ = telemetry_requests_tx.send(telemetry::Request::Forget(uuid));
+                    if let Some(name) = names.remove(&uuid) {
+                        let _ = availability_requests_tx.send(availability::Request::Disconnected(name.clone()));
+                        last_seen.insert(name, std::time::SystemTime::now());
+                    }
+                    handle_lifecycle_transition(&mut lifecycles, &published, &mut stopped_running, journal, uuid, Lifecycle::Lost);
+                    lifecycles.remove(&uuid);
+                    published.remove(&uuid);
                 },
                 Err(error) => log::error!("Drone task panicked: {}", error),
             },
+            Some(result) = simulated_tasks.next() => match result {
+                Ok(uuid) => {
+                    simulated_tx_map.remove(&uuid);
+                    remote_brains.remove(&uuid);
+                    handle_lifecycle_transition(&mut lifecycles, &published, &mut stopped_running, journal, uuid, Lifecycle::Lost);
+                    lifecycles.remove(&uuid);
+                    published.remove(&uuid);
+                },
+                Err(error) => log::error!("Simulated robot task panicked: {}", error),
+            },
+            Some(result) = compute_tasks.next() => match result {
+                Ok(uuid) => {
+                    compute_tx_map.remove(&uuid);
+                    return_addresses(&network_requests_tx, &mut addresses, &uuid);
+                    /* the robots whose controllers ran on this server are now without a brain */
+                    let orphans = remote_brains.iter()
+                        .filter(|(_, server)| **server == uuid)
+                        .map(|(robot, _)| *robot)
+                        .collect::<Vec<_>>();
+                    for robot in orphans {
+                        log::warn!("Robot {} lost its remote brain on compute server {}", robot, uuid);
+                        remote_brains.remove(&robot);
+                        if let Some(Lifecycle::Running) = lifecycles.get(&robot) {
+                            handle_lifecycle_transition(&mut lifecycles, &published, &mut stopped_running, journal, robot, Lifecycle::Degraded);
+                        }
+                    }
+                    let allowed = remote_brains.keys().cloned().collect();
+                    if let Err(error) = tunnel_requests_tx.send(tunnel::Request::Allow(allowed)) {
+                        log::warn!("Could not update the robots of the tunnel: {}", error);
+                    }
+                },
+                Err(error) => log::error!("Compute server task panicked: {}", error),
+            },
             Some(result) = pipuck_tasks.next() => match result {
                 Ok(uuid) => {
                     pipuck_tx_map.remove(&uuid);
+                    queues.remove(&uuid);
+                    let _ = link_requests_tx.send(link::Request::Unwatch(uuid));
+                    let _ = sensing_requests_tx.send(sensing::Request::Unsubscribe(uuid));
+                    return_addresses(&network_requests_tx, &mut addresses, &uuid);
+                    remote_brains.remove(&uuid);
+                    let _ = telemetry_requests_tx.send(telemetry::Request::Forget(uuid));
+                    if let Some(name) = names.remove(&uuid) {
+                        let _ = availability_requests_tx.send(availability::Request::Disconnected(name.clone()));
+                        last_seen.insert(name, std::time::SystemTime::now());
+                    }
+                    handle_lifecycle_transition(&mut lifecycles, &published, &mut stopped_running, journal, uuid, Lifecycle::Lost);
+                    lifecycles.remove(&uuid);
+                    published.remove(&uuid);
                 },
                 Err(error) => log::error!("Pi-Puck task panicked: {}", error),
             },
@@ -205,9 +1564,292 @@ pub async fn new(arena_request_rx: mpsc::UnboundedReceiver<Request>,
     log::info!("arena task is complete");
 }
 
+/* applies a transition if it is valid and publishes it to the robot task, returns whether the
+   transition was applied */
+fn handle_lifecycle_transition(lifecycles: &mut HashMap<Uuid, Lifecycle>,
+                               published: &HashMap<Uuid, watch::Sender<Lifecycle>>,
+                               stopped_running: &mut Vec<Uuid>,
+                               journal: &journal::Sender,
+                               uuid: Uuid,
+                               next: Lifecycle) -> bool {
+    match lifecycles.get_mut(&uuid) {
+        /* a robot task may report a transition again before it learns that it was accepted */
+        Some(current) if *current == next => false,
+        Some(current) => {
+            if current.can_transition(next) {
+                log::info!("Robot {}: {} -> {}", uuid, current, next);
+                if *current == Lifecycle::Running {
+                    stopped_running.push(uuid);
+                }
+                *current = next;
+                if let Some(accepted) = published.get(&uuid) {
+                    let _ = accepted.send(next);
+                }
+                let event = journal::Event::Lifecycle(uuid, next);
+                if let Err(error) = journal.record(event) {
+                    log::warn!("Could not record lifecycle of {} in journal: {}", uuid, error);
+                }
+                true
+            }
+            else {
+                log::warn!("Robot {}: invalid transition {} -> {}", uuid, current, next);
+                false
+            }
+        },
+        None => {
+            log::warn!("Could not find robot {}", uuid);
+            false
+        },
+    }
+}
+
+/* broadcasts the flags of every robot, keyed by the identifier that the robot uses in the router */
+async fn handle_broadcast_flags(relay: &router::Relay,
+                                journal: &journal::Sender,
+                                flags: &Flags,
+                                pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                                drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                                simulated_tx_map: &HashMap<Uuid, simulated::Sender>) {
+    let simulated_kinds = simulated_tx_map.iter()
+        .filter_map(|(uuid, tx)| {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(simulated::Request::State(response_tx)).ok().map(|_| (*uuid, response_rx))
+        })
+        .collect::<Vec<_>>();
+    let mut assignments = serde_json::Map::new();
+    for uuid in pipuck_tx_map.keys() {
+        assignments.insert(uuid.to_string(), serde_json::json!(flags.resolve(uuid, Kind::PiPuck)));
+    }
+    for uuid in drone_tx_map.keys() {
+        assignments.insert(uuid.to_string(), serde_json::json!(flags.resolve(uuid, Kind::Drone)));
+    }
+    for (uuid, response_rx) in simulated_kinds {
+        if let Ok(state) = response_rx.await {
+            assignments.insert(uuid.to_string(), serde_json::json!(flags.resolve(&uuid, state.kind)));
+        }
+    }
+    let message = serde_json::json!({ "flags": assignments });
+    handle_inject_message_request(relay, journal, None, &message);
+}
+
+fn handle_inject_message_request(relay: &router::Relay,
+                                 journal: &journal::Sender,
+                                 recipient: Option<IpAddr>,
+                                 message: &serde_json::Value) {
+    match router::json_to_lua(message) {
+        Some(router::LuaType::Table(table)) => {
+            let mut data = bytes::BytesMut::new();
+            router::encode_lua_table(&table, &mut data);
+            relay.inject(recipient, data.freeze());
+            match recipient {
+                Some(recipient) => log::info!("Injected message for {}", recipient),
+                None => log::info!("Injected broadcast message"),
+            }
+            let event = journal::Event::Broadcast(router::supervisor_addr(), router::LuaType::Table(table));
+            if let Err(error) = journal.record(event) {
+                log::warn!("Could not record injected message in journal: {}", error);
+            }
+        },
+        _ => log::error!("Could not inject message: messages must be JSON objects or arrays"),
+    }
+}
+
+/* records the deviation of the robots from their targets without blocking the arena */
+fn handle_measure_formation_request(formation: formation::Formation,
+                                    tracking_requests_tx: &optitrack::Sender,
+                                    journal: &journal::Sender) {
+    let (callback_tx, callback_rx) = oneshot::channel();
+    let request = optitrack::Request::Frame(optitrack::Stream::Filtered, callback_tx);
+    if let Err(error) = tracking_requests_tx.send(request) {
+        log::error!("Could not request tracking frame: {}", error);
+        return;
+    }
+    let journal = journal.clone();
+    tokio::spawn(async move {
+        match callback_rx.await {
+            Ok(poses) => {
+                let measurement = formation.measure(&poses);
+                log::info!("{} of {} robots are in formation {}",
+                    measurement.in_place, formation.targets.len(), formation.name);
+                if let Err(error) = journal.record(journal::Event::Formation(measurement)) {
+                    log::warn!("Could not record formation measurement in journal: {}", error);
+                }
+            },
+            Err(_) => log::error!("Could not get tracking frame for measuring formation {}", formation.name),
+        }
+    });
+}
+
+/* addresses from which the robots connect to the message router */
+async fn robot_addresses(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                         drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                         simulated_tx_map: &HashMap<Uuid, simulated::Sender>) -> HashMap<IpAddr, Uuid> {
+    let mut addresses = HashMap::new();
+    for (uuid, tx) in pipuck_tx_map.iter() {
+        let (response_tx, response_rx) = oneshot::channel();
+        if let Ok(_) = tx.send(pipuck::Request::State(response_tx)) {
+            if let Ok(state) = response_rx.await {
+                addresses.insert(IpAddr::V4(state.rpi.0), *uuid);
+            }
+        }
+    }
+    for (uuid, tx) in drone_tx_map.iter() {
+        let (response_tx, response_rx) = oneshot::channel();
+        if let Ok(_) = tx.send(drone::Request::GetState(response_tx)) {
+            if let Ok(state) = response_rx.await {
+                /* ARGoS runs on the UP Core of the drone */
+                if let Some((addr, _)) = state.upcore {
+                    addresses.insert(IpAddr::V4(addr), *uuid);
+                }
+            }
+        }
+    }
+    for (uuid, tx) in simulated_tx_map.iter() {
+        let (response_tx, response_rx) = oneshot::channel();
+        if let Ok(_) = tx.send(simulated::Request::State(response_tx)) {
+            if let Ok(state) = response_rx.await {
+                addresses.insert(IpAddr::V4(state.addr), *uuid);
+            }
+        }
+    }
+    addresses
+}
+
+fn handle_query_request(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                        drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                        simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                        lifecycles: &HashMap<Uuid, Lifecycle>,
+                        names: &HashMap<Uuid, String>,
+                        predicates: Vec<Predicate>,
+                        callback: oneshot::Sender<Vec<Summary>>) {
+    /* the battery levels of the robots are only known by their tasks, which are asked without
+       blocking the arena */
+    let pipuck_battery_requests = pipuck_tx_map
+        .iter()
+        .filter_map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = pipuck::Request::Battery(response_tx);
+            tx.send(request).map(|_| async move {
+                (uuid, response_rx.await)
+            }).ok()
+        })
+        .collect::<FuturesUnordered<_>>();
+    let drone_state_requests = drone_tx_map
+        .iter()
+        .filter_map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = drone::Request::GetState(response_tx);
+            tx.send(request).map(|_| async move {
+                (uuid, response_rx.await)
+            }).ok()
+        })
+        .collect::<FuturesUnordered<_>>();
+    let simulated_state_requests = simulated_tx_map
+        .iter()
+        .filter_map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = simulated::Request::State(response_tx);
+            tx.send(request).map(|_| async move {
+                (uuid, response_rx.await)
+            }).ok()
+        })
+        .collect::<FuturesUnordered<_>>();
+    let pipuck_uuids = pipuck_tx_map.keys().cloned().collect::<Vec<_>>();
+    let drone_uuids = drone_tx_map.keys().cloned().collect::<Vec<_>>();
+    let lifecycles = lifecycles.clone();
+    let names = names.clone();
+    tokio::spawn(async move {
+        let pipuck_batteries = pipuck_battery_requests
+            .filter_map(|(uuid, result)| async move {
+                result.ok().flatten().map(|battery| (uuid, battery.percentage as i8))
+            })
+            .collect::<HashMap<_,_>>().await;
+        let drone_batteries = drone_state_requests
+            .filter_map(|(uuid, result)| async move {
+                result.ok().and_then(|state| state.battery).map(|battery| (uuid, battery.percentage as i8))
+            })
+            .collect::<HashMap<_,_>>().await;
+        let simulated = simulated_state_requests
+            .filter_map(|(uuid, result)| async move { result.ok().map(|state| (uuid, state.kind)) })
+            .collect::<Vec<_>>().await;
+        let drones = drone_uuids.into_iter().map(|uuid| Summary {
+            uuid,
+            kind: Kind::Drone,
+            simulated: false,
+            lifecycle: lifecycles.get(&uuid).cloned(),
+            battery: drone_batteries.get(&uuid).cloned(),
+            hostname: names.get(&uuid).cloned(),
+        });
+        let pipucks = pipuck_uuids.into_iter().map(|uuid| Summary {
+            uuid,
+            kind: Kind::PiPuck,
+            simulated: false,
+            lifecycle: lifecycles.get(&uuid).cloned(),
+            battery: pipuck_batteries.get(&uuid).cloned(),
+            hostname: names.get(&uuid).cloned(),
+        });
+        let simulated = simulated.into_iter().map(|(uuid, kind)| Summary {
+            uuid,
+            kind,
+            simulated: true,
+            lifecycle: lifecycles.get(&uuid).cloned(),
+            battery: None,
+            hostname: None,
+        });
+        let summaries = drones.chain(pipucks).chain(simulated)
+            .filter(|summary| predicates.iter().all(|predicate| predicate.matches(summary)))
+            .collect();
+        if let Err(_) = callback.send(summaries) {
+            log::error!("Could not respond with query results");
+        }
+    });
+}
+
+/* refuses an upload that would take the software over its quota */
+fn check_software_quota(config: &config::Receiver, filename: &str, size: u64) -> std::result::Result<(), String> {
+    match config.borrow().quotas.software {
+        Some(quota) if size > quota => Err(format!("Could not upload {}, the software would exceed its quota of {}",
+            filename, quota::format_bytes(quota))),
+        _ => Ok(()),
+    }
+}
+
+/* the identities of the signers of the software, empty if signing is not required */
+fn verify_software(config: &config::Receiver,
+                   pipuck_software: &Software,
+                   drone_software: &Software) -> Result<Vec<String>> {
+    let signing = match config.borrow().signing.clone() {
+        Some(signing) => signing,
+        None => return Ok(Vec::new()),
+    };
+    let mut signers = Vec::new();
+    for software in &[pipuck_software, drone_software] {
+        /* there is nothing to deploy to a kind of robot without software */
+        if !software.0.is_empty() {
+            let signer = software.verify(&signing)?;
+            if !signers.contains(&signer) {
+                signers.push(signer);
+            }
+        }
+    }
+    Ok(signers)
+}
+
+/* the required robots of the fleet that are not connected */
+fn required_robots(config: &config::Receiver, names: &HashMap<Uuid, String>) -> Vec<String> {
+    config.borrow().required_robots.iter()
+        .filter(|required| !names.values().any(|name| name == *required))
+        .cloned()
+        .collect()
+}
+
 // TODO send the ip address back if pairing unsucessful
+/* pairs the UP Core with a drone and returns the uuid of the drone if successful */
 async fn handle_pair_with_drone_request(drone_tx_map: &HashMap<Uuid, drone::Sender>,
-                                        device: network::fernbedienung::Device) -> Result<()> {
+                                        device: network::fernbedienung::Device) -> Result<Option<Uuid>> {
     /* upload the set id script */
     let write_upcore_id_script = include_bytes!("scripts/drone_set_identifier.sh");
     device.upload("/tmp".into(), "drone_set_identifier.sh".into(), write_upcore_id_script.to_vec()).await
@@ -220,6 +1862,7 @@ async fn handle_pair_with_drone_request(drone_tx_map: &HashMap<Uuid, drone::Send
         target: "sh".into(),
         working_dir: Some("/tmp".into()),
         args: vec!["drone_set_identifier.sh".into(), random_id.to_string()],
+        ..Default::default()
     };
     let (terminate_tx, terminate_rx) = oneshot::channel();
 
@@ -258,8 +1901,10 @@ async fn handle_pair_with_drone_request(drone_tx_map: &HashMap<Uuid, drone::Send
             match drone_tx_map.get(&uuid) {
                 Some(tx) => {
                     let request = drone::Request::Pair(device);
-                    if let Err(error) = tx.send(request) {
-                        log::warn!("Could not pair fernbedienung instance with drone {}: {}", uuid, error);
+                    match tx.send(request) {
+                        Ok(_) => return Ok(Some(*uuid)),
+                        Err(error) =>
+                            log::warn!("Could not pair fernbedienung instance with drone {}: {}", uuid, error),
                     }
                 }
                 None => log::warn!("Could not find drone {}", uuid)
@@ -267,14 +1912,22 @@ async fn handle_pair_with_drone_request(drone_tx_map: &HashMap<Uuid, drone::Send
         }
         _ => log::error!("Multiple candidates for pairing")
     }
-    Ok(())
+    Ok(None)
 }
 
 
 async fn stop_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
                          drone_tx_map: &HashMap<Uuid, drone::Sender>,
-                         journal_requests_tx: &mpsc::UnboundedSender<journal::Request>) {
-    let _ = journal_requests_tx.send(journal::Request::Stop);
+                         simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                         compute_tx_map: &HashMap<Uuid, compute::Sender>,
+                         journal: &journal::Sender) {
+    journal.stop();
+    for (_, tx) in compute_tx_map.into_iter() {
+        let _ = tx.send(compute::Request::ExperimentStop);
+    }
+    for (_, tx) in simulated_tx_map.into_iter() {
+        let _ = tx.send(simulated::Request::ExperimentStop);
+    }
     for (_, tx) in drone_tx_map.into_iter() {
         let _ = tx.send(drone::Request::ExperimentStop);
     }
@@ -283,40 +1936,468 @@ async fn stop_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
     }
 }
 
+/* kills the controllers without letting them write their output, removing the results that they
+   wrote, and discards the journal */
+fn abort_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                    pipuck_patterns: &[String],
+                    drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                    drone_patterns: &[String],
+                    simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                    compute_tx_map: &HashMap<Uuid, compute::Sender>,
+                    journal: &journal::Sender) {
+    journal.discard();
+    for (_, tx) in compute_tx_map.into_iter() {
+        let _ = tx.send(compute::Request::ExperimentStop);
+    }
+    for (_, tx) in simulated_tx_map.into_iter() {
+        let _ = tx.send(simulated::Request::ExperimentStop);
+    }
+    for (_, tx) in drone_tx_map.into_iter() {
+        let _ = tx.send(drone::Request::ExperimentAbort { patterns: drone_patterns.to_vec() });
+    }
+    for (_, tx) in pipuck_tx_map.into_iter() {
+        let _ = tx.send(pipuck::Request::ExperimentAbort { patterns: pipuck_patterns.to_vec() });
+    }
+}
+
+/* carries out the commands of the experiment state machine */
+async fn execute(commands: Vec<Command>,
+                 pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                 drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                 simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                 compute_tx_map: &HashMap<Uuid, compute::Sender>,
+                 journal: &journal::Sender,
+                 environment_requests_tx: &environment::Sender,
+                 push_requests_tx: &push::Sender,
+                 interlocked: &mut HashMap<Uuid, i32>,
+                 activity: &mut digest::Activity,
+                 (pipuck_software, drone_software): (&mut Software, &mut Software),
+                 validated_software: &mut Option<(software::Checksums, software::Checksums)>,
+                 staged_software: &mut Option<(Software, Software)>,
+                 current_run: Option<Uuid>,
+                 history: &mut History,
+                 harvest_tx: &harvest::Sender,
+                 operations_tx: &operation::Sender,
+                 queues: &HashMap<Uuid, Queue>,
+                 config: &config::Receiver) {
+    for command in commands {
+        match command {
+            /* starting an experiment needs the robots and the software of the arena */
+            Command::StartCanary | Command::StartExperiment =>
+                log::error!("Experiments can only be started by the arena"),
+            Command::StopRobots =>
+                stop_experiment(pipuck_tx_map, drone_tx_map, simulated_tx_map, compute_tx_map, journal).await,
+            Command::KillRobots => {
+                let (pipuck_patterns, drone_patterns) = staged_software.as_ref()
+                    .map(|(pipuck, drone)| (pipuck.result_patterns(), drone.result_patterns()))
+                    .unwrap_or_default();
+                abort_experiment(pipuck_tx_map, &pipuck_patterns, drone_tx_map, &drone_patterns,
+                    simulated_tx_map, compute_tx_map, journal);
+            },
+            Command::StopEnvironment => {
+                if let Err(error) = environment_requests_tx.send(environment::Request::Stop) {
+                    log::error!("Could not stop environment schedule: {}", error);
+                }
+            },
+            Command::ClearInterlock => interlocked.clear(),
+            Command::LandDrones => {
+                for (uuid, tx) in drone_tx_map.iter() {
+                    if let Err(_) = tx.send(drone::Request::Land) {
+                        log::error!("Could not land drone {}", uuid);
+                    }
+                }
+            },
+            Command::Alert(message) => alert(push_requests_tx, message),
+            Command::Fail(message) => {
+                log::error!("{}", message);
+                activity.failures.push((std::time::SystemTime::now(), message));
+            },
+            Command::RollBackSoftware => match staged_software.clone() {
+                Some((pipuck, drone)) => {
+                    log::warn!("Rolling back to the previously staged software");
+                    *pipuck_software = pipuck;
+                    *drone_software = drone;
+                },
+                None => log::warn!("There is no previously staged software to roll back to"),
+            },
+            Command::ValidateSoftware => {
+                log::info!("Canary experiment passed");
+                *validated_software = Some((pipuck_software.checksums(), drone_software.checksums()));
+                *staged_software = Some((pipuck_software.clone(), drone_software.clone()));
+            },
+            Command::HarvestResults => match (current_run, staged_software.as_ref()) {
+                (Some(run), Some((pipuck, drone))) => {
+                    let hooks = config.borrow().hooks.clone();
+                    let hook_context = history.find(&run).map(|record| hook::Context::new(run, record));
+                    harvest_results(run, pipuck_tx_map, &pipuck.result_patterns(), drone_tx_map, &drone.result_patterns(),
+                        harvest_tx, operations_tx, queues, hooks, hook_context)
+                },
+                _ => log::warn!("There is no run to collect the results of"),
+            },
+            Command::RecordAbort => match current_run.and_then(|run| history.find_mut(&run)) {
+                Some(run) => run.aborted = true,
+                None => log::warn!("There is no run to mark as aborted"),
+            },
+            Command::DiscardRun => match current_run {
+                Some(run) => {
+                    if let Some(started) = history.find(&run).map(|run| run.started) {
+                        activity.runs.retain(|run| *run != started);
+                    }
+                    history.delete(&run);
+                    log::info!("Discarded run {}", run);
+                },
+                None => log::warn!("There is no run to discard"),
+            },
+            Command::RecordThrottling(robot) => match current_run.and_then(|run| history.find_mut(&run)) {
+                Some(run) => if !run.throttled.contains(&robot) {
+                    run.throttled.push(robot);
+                },
+                None => log::warn!("There is no run to record the throttling of robot {} in", robot),
+            },
+        }
+    }
+}
+
+/* requests that the robots collect the results of a run, the reports of the robots are sent to
+   the arena as they arrive */
+fn harvest_results(run: Uuid,
+                   pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                   pipuck_patterns: &[String],
+                   drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                   drone_patterns: &[String],
+                   harvest_tx: &harvest::Sender,
+                   operations_tx: &operation::Sender,
+                   queues: &HashMap<Uuid, Queue>,
+                   hooks: hook::Hooks,
+                   hook_context: Option<hook::Context>) {
+    /* the post-run hook runs once the results have been collected */
+    let post_run = async move {
+        if let Some(context) = hook_context {
+            hook::post_run(&hooks, &context).await;
+        }
+    };
+    /* the results are requested once the operations queued before the collection, e.g.,
+       maintenance scripts, have completed */
+    let mut callbacks = Vec::new();
+    if !pipuck_patterns.is_empty() {
+        for (uuid, tx) in pipuck_tx_map.iter() {
+            let ticket = match queues.get(uuid).cloned().unwrap_or_default().enter(queue::Kind::Harvest) {
+                Ok(ticket) => ticket,
+                Err(error) => {
+                    log::error!("Could not collect the results of Pi-Puck {}: {}", uuid, error);
+                    continue;
+                }
+            };
+            let (callback_tx, callback_rx) = oneshot::channel();
+            let request = pipuck::Request::Harvest {
+                patterns: pipuck_patterns.to_vec(),
+                destination: harvest::destination(&run, uuid),
+                callback: callback_tx,
+            };
+            let (uuid, tx) = (*uuid, tx.clone());
+            callbacks.push((uuid, async move {
+                ticket.ready().await;
+                if let Err(_) = tx.send(request) {
+                    log::error!("Could not request the results of Pi-Puck {}", uuid);
+                }
+                callback_rx.await
+            }.boxed()));
+        }
+    }
+    if !drone_patterns.is_empty() {
+        for (uuid, tx) in drone_tx_map.iter() {
+            let ticket = match queues.get(uuid).cloned().unwrap_or_default().enter(queue::Kind::Harvest) {
+                Ok(ticket) => ticket,
+                Err(error) => {
+                    log::error!("Could not collect the results of drone {}: {}", uuid, error);
+                    continue;
+                }
+            };
+            let (callback_tx, callback_rx) = oneshot::channel();
+            let request = drone::Request::Harvest {
+                patterns: drone_patterns.to_vec(),
+                destination: harvest::destination(&run, uuid),
+                callback: callback_tx,
+            };
+            let (uuid, tx) = (*uuid, tx.clone());
+            callbacks.push((uuid, async move {
+                ticket.ready().await;
+                if let Err(_) = tx.send(request) {
+                    log::error!("Could not request the results of drone {}", uuid);
+                }
+                callback_rx.await
+            }.boxed()));
+        }
+    }
+    if callbacks.is_empty() {
+        tokio::spawn(post_run);
+        return;
+    }
+    let operation = operation::Handle::begin(operations_tx, operation::Kind::Harvest,
+        format!("Collecting the results of run {}", run), Some(callbacks.len()));
+    let mut reports = callbacks.into_iter()
+        .map(|(uuid, callback_rx)| async move { (uuid, callback_rx.await) })
+        .collect::<FuturesUnordered<_>>();
+    let harvest_tx = harvest_tx.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                report = reports.next() => match report {
+                    Some((uuid, Ok(report))) => {
+                        operation.report(uuid, match report.failed.len() {
+                            0 => Ok(()),
+                            failed => Err(format!("{} files or patterns could not be collected", failed)),
+                        });
+                        let _ = harvest_tx.send((run, uuid, report));
+                    },
+                    Some((uuid, Err(_))) => {
+                        log::error!("Robot {} did not report the results of run {}", uuid, run);
+                        operation.report(uuid, Err("The robot did not report its results".to_owned()));
+                    },
+                    None => break,
+                },
+                /* the robots finish the downloads that have started but their reports are ignored */
+                _ = operation.cancelled() => {
+                    operation.finish(operation::State::Cancelled);
+                    log::warn!("Skipping the post-run hook of run {}, collecting the results was cancelled", run);
+                    return;
+                }
+            }
+        }
+        operation.finish(operation::State::Completed);
+        post_run.await;
+    });
+}
+
+/* returns the addresses of a lost robot to the network task unless a robot that has since
+   connected uses the same address */
+fn return_addresses(network_requests_tx: &network::Sender,
+                    addresses: &mut HashMap<Uuid, Vec<Ipv4Addr>>,
+                    uuid: &Uuid) {
+    for addr in addresses.remove(uuid).unwrap_or_default() {
+        if !addresses.values().any(|others| others.contains(&addr)) {
+            let _ = network_requests_tx.send(network::Request::Lost(addr));
+        }
+    }
+}
+
+fn alert(push_requests_tx: &push::Sender, message: String) {
+    if let Err(error) = push_requests_tx.send(push::Request::Alert(message)) {
+        log::error!("Could not push alert: {}", error);
+    }
+}
+
+async fn tracking_diagnostics(tracking_requests_tx: &optitrack::Sender) -> interlock::Result<optitrack::Diagnostics> {
+    let (diagnostics_tx, diagnostics_rx) = oneshot::channel();
+    tracking_requests_tx.send(optitrack::Request::Diagnostics(diagnostics_tx))
+        .map_err(|_| interlock::Error::TrackingError)?;
+    diagnostics_rx.await
+        .map_err(|_| interlock::Error::TrackingError)
+}
+
+/* checks that the arming switch is engaged, then finds the rigid bodies of the drones and checks
+   that they are tracked, returning no rigid bodies if the interlock is disabled or there are no
+   drones */
+async fn arm_drones(drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                    tracking_requests_tx: &optitrack::Sender,
+                    arming_requests_tx: &arming::Sender,
+                    journal: &journal::Sender,
+                    config: &config::Receiver) -> Result<HashMap<Uuid, i32>> {
+    if !drone_tx_map.is_empty() {
+        let (state_tx, state_rx) = oneshot::channel();
+        arming_requests_tx.send(arming::Request::State(state_tx))
+            .map_err(|_| Error::ArmingRequestError)?;
+        let state = state_rx.await
+            .map_err(|_| Error::ArmingRequestError)?;
+        if !state.is_armed() {
+            let attempt = format!("Start {} drones while the arming switch is {}", drone_tx_map.len(), state);
+            if let Err(error) = journal.record(journal::Event::Refused(attempt)) {
+                log::warn!("Could not record refused start in journal: {}", error);
+            }
+            return Err(Error::Disarmed(state));
+        }
+    }
+    let interlock = config.borrow().drone_interlock.clone();
+    let interlock = match interlock {
+        Some(interlock) if !drone_tx_map.is_empty() => interlock,
+        _ => return Ok(HashMap::new()),
+    };
+    let mut rigid_bodies = HashMap::new();
+    for (uuid, tx) in drone_tx_map.iter() {
+        let (id_tx, id_rx) = oneshot::channel();
+        tx.send(drone::Request::GetId(id_tx))
+            .map_err(|_| Error::DroneError(*uuid, drone::Error::RequestError))?;
+        let id = id_rx.await
+            .map_err(|_| interlock::Error::IdentifierError(*uuid))?;
+        let rigid_body = interlock.rigid_bodies.get(&id)
+            .ok_or(interlock::Error::NoRigidBody(*uuid, id))?;
+        rigid_bodies.insert(*uuid, *rigid_body);
+    }
+    let diagnostics = tracking_diagnostics(tracking_requests_tx).await?;
+    interlock.check(&diagnostics, &rigid_bodies)?;
+    Ok(rigid_bodies)
+}
+
+async fn check_charging(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                        drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                        dock_requests_tx: &dock::Sender) -> Result<()> {
+    let (statuses_tx, statuses_rx) = oneshot::channel();
+    dock_requests_tx.send(dock::Request::Statuses(statuses_tx))
+        .map_err(|_| Error::DockRequestError)?;
+    let statuses = statuses_rx.await
+        .map_err(|_| Error::DockRequestError)?;
+    let charging = dock::charging(&statuses);
+    if charging.is_empty() {
+        return Ok(());
+    }
+    for (uuid, tx) in pipuck_tx_map.iter() {
+        let (response_tx, response_rx) = oneshot::channel();
+        if let Ok(_) = tx.send(pipuck::Request::State(response_tx)) {
+            if let Ok(state) = response_rx.await {
+                if charging.contains(&state.rpi.0) {
+                    return Err(Error::RobotCharging(*uuid));
+                }
+            }
+        }
+    }
+    for (uuid, tx) in drone_tx_map.iter() {
+        let (response_tx, response_rx) = oneshot::channel();
+        if let Ok(_) = tx.send(drone::Request::GetState(response_tx)) {
+            if let Ok(state) = response_rx.await {
+                let upcore_charging = state.upcore
+                    .map_or(false, |(addr, _)| charging.contains(&addr));
+                if charging.contains(&state.xbee.0) || upcore_charging {
+                    return Err(Error::RobotCharging(*uuid));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/* runs the experiment on the first robots, preferring the Pi-Pucks since they are less dangerous */
+async fn start_canary(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                      pipuck_software: &Software,
+                      drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                      drone_software: &Software,
+                      flags: &Flags,
+                      journal: &journal::Sender,
+                      config: &config::Receiver,
+                      dock_requests_tx: &dock::Sender,
+                      operations_tx: &operation::Sender,
+                      queues: &HashMap<Uuid, Queue>) -> Result<Vec<Uuid>> {
+    let count = config.borrow().canary_robots;
+    let canary_pipucks = pipuck_tx_map.iter()
+        .sorted_by_key(|(uuid, _)| *uuid)
+        .take(count)
+        .map(|(uuid, tx)| (*uuid, tx.clone()))
+        .collect::<HashMap<_,_>>();
+    let canary_drones = drone_tx_map.iter()
+        .sorted_by_key(|(uuid, _)| *uuid)
+        .take(count - canary_pipucks.len())
+        .map(|(uuid, tx)| (*uuid, tx.clone()))
+        .collect::<HashMap<_,_>>();
+    if canary_pipucks.is_empty() && canary_drones.is_empty() {
+        return Err(Error::NoCanaryRobots);
+    }
+    start_experiment(&canary_pipucks,
+                     pipuck_software,
+                     &canary_drones,
+                     drone_software,
+                     &HashMap::new(),
+                     &HashMap::new(),
+                     &HashMap::new(),
+                     flags,
+                     journal,
+                     config,
+                     dock_requests_tx,
+                     operations_tx,
+                     queues).await?;
+    Ok(canary_pipucks.keys().chain(canary_drones.keys()).cloned().collect())
+}
+
 async fn start_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
                           pipuck_software: &Software,
                           drone_tx_map: &HashMap<Uuid, drone::Sender>,
                           drone_software: &Software,
-                          journal_requests_tx: &mpsc::UnboundedSender<journal::Request>) -> Result<()> {
+                          simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                          compute_tx_map: &HashMap<Uuid, compute::Sender>,
+                          remote_brains: &HashMap<Uuid, Uuid>,
+                          flags: &Flags,
+                          journal: &journal::Sender,
+                          config: &config::Receiver,
+                          dock_requests_tx: &dock::Sender,
+                          operations_tx: &operation::Sender,
+                          queues: &HashMap<Uuid, Queue>) -> Result<(std::path::PathBuf, clock::Run)> {
     // TODO call luac on each robot and validate the control software
 
+    /* check that the compute servers of the remote brains are still available */
+    for (robot, server) in remote_brains.iter() {
+        if !compute_tx_map.contains_key(server) {
+            return Err(Error::ComputeError(*server, compute::Error::RequestError));
+        }
+        if !pipuck_tx_map.contains_key(robot) && !drone_tx_map.contains_key(robot) {
+            log::warn!("Robot {} has a remote brain but is not connected", robot);
+        }
+    }
+
     /* check software validity before starting */
-    if pipuck_tx_map.len() > 0 {
+    let simulated_kinds = simulated_tx_map.values()
+        .filter_map(|tx| {
+            let (response_tx, response_rx) = oneshot::channel();
+            tx.send(simulated::Request::State(response_tx)).ok().map(|_| response_rx)
+        })
+        .collect::<FuturesUnordered<_>>()
+        .filter_map(|result| async move { result.ok().map(|state| state.kind) })
+        .collect::<Vec<_>>().await;
+    if pipuck_tx_map.len() > 0 || simulated_kinds.contains(&Kind::PiPuck) {
         pipuck_software.check_config()?;
     }
-    if drone_tx_map.len() > 0 {
+    if drone_tx_map.len() > 0 || simulated_kinds.contains(&Kind::Drone) {
         drone_software.check_config()?;
     }   
 
+    /* refuse to start the experiment if any of the robots are charging */
+    let refuse_charging_robots = config.borrow().refuse_charging_robots;
+    if refuse_charging_robots {
+        check_charging(pipuck_tx_map, drone_tx_map, dock_requests_tx).await?;
+    }
+
+    /* refuse to start the experiment if any of the robots are busy, e.g., running a script, the
+       robots are held in their queues until staging has completed */
+    let _tickets = pipuck_tx_map.keys()
+        .chain(drone_tx_map.keys())
+        .map(|uuid| queues.get(uuid).cloned().unwrap_or_default()
+            .enter(queue::Kind::Staging)
+            .map_err(|error| Error::QueueError(*uuid, error)))
+        .collect::<Result<Vec<_>>>()?;
+
     /* start an experiment journal to record events during the experiment */
-    let (callback_tx, callback_rx) = oneshot::channel();
-    journal_requests_tx
-        .send(journal::Request::Start(callback_tx))
-        .map_err(|_| journal::Error::RequestError)?;
-    callback_rx.await
-        .map_err(|_| journal::Error::ResponseError)
-        .and_then(|error| error)?;
+    let schemas = config.borrow().schemas.clone();
+    let quota = config.borrow().quotas.runs;
+    let epoch = config.borrow().journal_epoch;
+    let spool = config.borrow().journal_spool.clone();
+    let run_clock = clock::Run::start();
+    let journal_path = journal.start(schemas, quota, spool, run_clock, epoch).await?;
+
+    /* staging the software can take a while, so its progress is tracked as an operation */
+    let robots = pipuck_tx_map.len() + simulated_tx_map.len() + drone_tx_map.len() + remote_brains.len();
+    let operation = operation::Handle::begin(operations_tx, operation::Kind::Staging,
+        format!("Staging the software on {} robots", robots), Some(robots));
 
     /* start the experiment */
     /* start pi-pucks first since they are less dangerous */
     let pipuck_start = pipuck_tx_map.into_iter()
         .map(|(uuid, tx)| {
             let uuid = uuid.clone();
-            let journal_requests_tx = journal_requests_tx.clone();
+            let journal = journal.clone();
             let (response_tx, response_rx) = oneshot::channel();
             let request = pipuck::Request::ExperimentStart {
                 software: pipuck_software.clone(),
-                journal: journal_requests_tx,
+                flags: flags.resolve(&uuid, Kind::PiPuck),
+                remote_brain: remote_brains.contains_key(&uuid),
+                journal,
+                cancellation: operation.token(),
                 callback: response_tx
             };
             tx.send(request)
@@ -326,12 +2407,13 @@ async fn start_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
                 })
         })
         .collect::<Result<FuturesUnordered<_>>>()?
-        .map(|(uuid, result)| result
+        .map(|(uuid, result)| (uuid, result
             .map_err(|_| Error::PiPuckError(uuid, pipuck::Error::ResponseError))
             .and_then(|response| {
                 response.map_err(|error| Error::PiPuckError(uuid, error))
             })
-        ).try_collect::<Vec<_>>().await;
+        ));
+    let pipuck_start = track(&operation, pipuck_start).await;
     
     // TODO, here it would be useful to watch for the Terminated response from ARGoS to determine
     // if any robot failed (e.g., errors in the Lua script)
@@ -339,7 +2421,47 @@ async fn start_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
     /* abort experiment if there was a problem starting the pipucks */
     if let Err(error) = pipuck_start {
         log::error!("Failed to start Pi-Pucks: {}", error);
-        stop_experiment(pipuck_tx_map, drone_tx_map, journal_requests_tx).await;
+        operation.finish(operation_state(&error));
+        stop_experiment(pipuck_tx_map, drone_tx_map, simulated_tx_map, compute_tx_map, journal).await;
+        return Err(error);
+    }
+
+    /* start the simulated robots with the software for their kind */
+    let simulated_start = simulated_tx_map.into_iter()
+        .map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let journal = journal.clone();
+            let (state_tx, state_rx) = oneshot::channel();
+            tx.send(simulated::Request::State(state_tx))
+                .map_err(|_| Error::SimulatedError(uuid, simulated::Error::RequestError))
+                .map(|_| async move { (uuid, async move {
+                    let (software, kind) = match state_rx.await {
+                        Ok(simulated::State { kind: Kind::PiPuck, .. }) => (pipuck_software.clone(), Kind::PiPuck),
+                        Ok(simulated::State { kind: Kind::Drone, .. }) => (drone_software.clone(), Kind::Drone),
+                        Err(_) => return Err(Error::SimulatedError(uuid, simulated::Error::ResponseError)),
+                    };
+                    let (response_tx, response_rx) = oneshot::channel();
+                    let request = simulated::Request::ExperimentStart {
+                        software,
+                        flags: flags.resolve(&uuid, kind),
+                        journal,
+                        callback: response_tx
+                    };
+                    tx.send(request)
+                        .map_err(|_| Error::SimulatedError(uuid, simulated::Error::RequestError))?;
+                    response_rx.await
+                        .map_err(|_| Error::SimulatedError(uuid, simulated::Error::ResponseError))?
+                        .map_err(|error| Error::SimulatedError(uuid, error))
+                }.await) })
+        })
+        .collect::<Result<FuturesUnordered<_>>>()?;
+    let simulated_start = track(&operation, simulated_start).await;
+
+    /* abort experiment if there was a problem starting the simulated robots */
+    if let Err(error) = simulated_start {
+        log::error!("Failed to start simulated robots: {}", error);
+        operation.finish(operation_state(&error));
+        stop_experiment(pipuck_tx_map, drone_tx_map, simulated_tx_map, compute_tx_map, journal).await;
         return Err(error);
     }
 
@@ -347,11 +2469,14 @@ async fn start_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
     let drone_start = drone_tx_map.into_iter()
         .map(|(uuid, tx)| {
             let uuid = uuid.clone();
-            let journal_requests_tx = journal_requests_tx.clone();
+            let journal = journal.clone();
             let (response_tx, response_rx) = oneshot::channel();
             let request = drone::Request::ExperimentStart {
                 software: drone_software.clone(),
-                journal: journal_requests_tx,
+                flags: flags.resolve(&uuid, Kind::Drone),
+                remote_brain: remote_brains.contains_key(&uuid),
+                journal,
+                cancellation: operation.token(),
                 callback: response_tx
             };
             tx.send(request)
@@ -361,27 +2486,112 @@ async fn start_experiment(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
                 })
         })
         .collect::<Result<FuturesUnordered<_>>>()?
-        .map(|(uuid, result)| result
+        .map(|(uuid, result)| (uuid, result
             .map_err(|_| Error::DroneError(uuid, drone::Error::ResponseError))
             .and_then(|response| {
                 response.map_err(|error| Error::DroneError(uuid, error))
             })
-        ).try_collect::<Vec<_>>().await;
+        ));
+    let drone_start = track(&operation, drone_start).await;
 
     /* abort experiment if there was a problem starting the drones */
     if let Err(error) = drone_start {
         log::error!("Failed to start drones: {}", error);
-        stop_experiment(pipuck_tx_map, drone_tx_map, journal_requests_tx).await;
+        operation.finish(operation_state(&error));
+        stop_experiment(pipuck_tx_map, drone_tx_map, simulated_tx_map, compute_tx_map, journal).await;
         return Err(error);
     }
 
-    Ok(())
+    /* finally start the controllers of the robots with remote brains on their compute servers */
+    let remote_brain_start = remote_brains.into_iter()
+        .filter_map(|(robot, server)| {
+            let (software, kind) = match (pipuck_tx_map.contains_key(robot), drone_tx_map.contains_key(robot)) {
+                (true, _) => (pipuck_software.clone(), Kind::PiPuck),
+                (_, true) => (drone_software.clone(), Kind::Drone),
+                _ => return None,
+            };
+            compute_tx_map.get(server).map(|tx| (*robot, *server, tx, software, kind))
+        })
+        .map(|(robot, server, tx, software, kind)| {
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = compute::Request::ExperimentStart {
+                robot,
+                software,
+                flags: flags.resolve(&robot, kind),
+                journal: journal.clone(),
+                callback: response_tx
+            };
+            tx.send(request)
+                .map_err(|_| Error::ComputeError(server, compute::Error::RequestError))
+                .map(|_| async move {
+                    (robot, server, response_rx.await)
+                })
+        })
+        .collect::<Result<FuturesUnordered<_>>>()?
+        .map(|(robot, server, result)| (robot, result
+            .map_err(|_| Error::ComputeError(server, compute::Error::ResponseError))
+            .and_then(|response| {
+                response.map_err(|error| Error::ComputeError(server, error))
+            })
+        ));
+    let remote_brain_start = track(&operation, remote_brain_start).await;
+
+    /* abort experiment if there was a problem starting the remote brains */
+    if let Err(error) = remote_brain_start {
+        log::error!("Failed to start remote brains: {}", error);
+        operation.finish(operation_state(&error));
+        stop_experiment(pipuck_tx_map, drone_tx_map, simulated_tx_map, compute_tx_map, journal).await;
+        return Err(error);
+    }
+
+    operation.finish(operation::State::Completed);
+    Ok((journal_path, run_clock))
+}
+
+/* waits for the robots to start, reporting each robot to the operation, until a robot fails
+   to start or the operation is cancelled */
+async fn track<S>(operation: &operation::Handle, mut starts: S) -> Result<()>
+    where S: futures::Stream<Item = (Uuid, Result<()>)> + Unpin {
+    loop {
+        tokio::select! {
+            start = starts.next() => match start {
+                Some((uuid, result)) => {
+                    operation.report(uuid, result.as_ref().map(|_| ()).map_err(ToString::to_string));
+                    result?;
+                },
+                None => return Ok(()),
+            },
+            _ = operation.cancelled() => return Err(Error::Cancelled),
+        }
+    }
+}
+
+fn operation_state(error: &Error) -> operation::State {
+    match error {
+        Error::Cancelled => operation::State::Cancelled,
+        error => operation::State::Failed(error.to_string()),
+    }
 }
 
 
 fn handle_forward_pipuck_action_request(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                                        queues: &HashMap<Uuid, Queue>,
+                                        config: &config::Receiver,
                                         uuid: Uuid,
                                         action: pipuck::Action) {
+    if action::find(&config.borrow().pipuck_actions, &action).is_none() {
+        log::warn!("Refused {:?} on Pi-Puck {}, the action is not defined in the configuration", action, uuid);
+        return;
+    }
+    /* actions that change the state of the Pi-Puck are refused while it is busy */
+    let check = match (action.queue_kind(), queues.get(&uuid)) {
+        (Some(kind), Some(queue)) => queue.check(kind),
+        _ => Ok(()),
+    };
+    if let Err(error) = check {
+        log::warn!("Could not execute {:?} on Pi-Puck {}: {}", action, uuid, error);
+        return;
+    }
     match pipuck_tx_map.get(&uuid) {
         Some(tx) => {
             let request = pipuck::Request::Execute(action);
@@ -415,9 +2625,118 @@ async fn handle_get_pipucks_request(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender
     }
 }
 
+async fn handle_get_virtual_robots_request(simulated_tx_map: &HashMap<Uuid, simulated::Sender>,
+                                           callback: oneshot::Sender<HashMap<Uuid, simulated::State>>) {
+    let simulated_states = simulated_tx_map
+        .into_iter()
+        .filter_map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = simulated::Request::State(response_tx);
+            tx.send(request).map(|_| async move {
+                (uuid, response_rx.await)
+            }).ok()
+        })
+        .collect::<FuturesUnordered<_>>()
+        .filter_map(|(uuid, result)| async move {
+            result.ok().map(|state| (uuid, state))
+        })
+        .collect::<HashMap<_,_>>().await;
+    if let Err(_) = callback.send(simulated_states) {
+        log::error!("Could not respond with simulated robot states")
+    }
+}
+
+/* the script runs in the background once the operations queued before it have completed, the
+   robot responds once the script has completed */
+fn handle_run_script_request(pipuck_tx_map: &HashMap<Uuid, pipuck::Sender>,
+                             drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                             queues: &HashMap<Uuid, Queue>,
+                             uuid: Uuid,
+                             script: Vec<u8>,
+                             callback: oneshot::Sender<Result<()>>) {
+    let ticket = match queues.get(&uuid).cloned().unwrap_or_default().enter(queue::Kind::Script) {
+        Ok(ticket) => ticket,
+        Err(error) => {
+            let _ = callback.send(Err(Error::QueueError(uuid, error)));
+            return;
+        }
+    };
+    if let Some(tx) = pipuck_tx_map.get(&uuid) {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            ticket.ready().await;
+            let (response_tx, response_rx) = oneshot::channel();
+            let result = match tx.send(pipuck::Request::RunScript { script, callback: response_tx }) {
+                Ok(_) => response_rx.await
+                    .map_err(|_| Error::PiPuckError(uuid, pipuck::Error::ResponseError))
+                    .and_then(|result| result.map_err(|error| Error::PiPuckError(uuid, error))),
+                Err(_) => Err(Error::PiPuckError(uuid, pipuck::Error::RequestError)),
+            };
+            drop(ticket);
+            let _ = callback.send(result);
+        });
+    }
+    else if let Some(tx) = drone_tx_map.get(&uuid) {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            ticket.ready().await;
+            let (response_tx, response_rx) = oneshot::channel();
+            let result = match tx.send(drone::Request::RunScript { script, callback: response_tx }) {
+                Ok(_) => response_rx.await
+                    .map_err(|_| Error::DroneError(uuid, drone::Error::ResponseError))
+                    .and_then(|result| result.map_err(|error| Error::DroneError(uuid, error))),
+                Err(_) => Err(Error::DroneError(uuid, drone::Error::RequestError)),
+            };
+            drop(ticket);
+            let _ = callback.send(result);
+        });
+    }
+    else {
+        let _ = callback.send(Err(Error::RobotNotFound(uuid)));
+    }
+}
+
+async fn handle_get_compute_servers_request(compute_tx_map: &HashMap<Uuid, compute::Sender>,
+                                            callback: oneshot::Sender<HashMap<Uuid, compute::State>>) {
+    let compute_states = compute_tx_map
+        .into_iter()
+        .filter_map(|(uuid, tx)| {
+            let uuid = uuid.clone();
+            let (response_tx, response_rx) = oneshot::channel();
+            let request = compute::Request::State(response_tx);
+            tx.send(request).map(|_| async move {
+                (uuid, response_rx.await)
+            }).ok()
+        })
+        .collect::<FuturesUnordered<_>>()
+        .filter_map(|(uuid, result)| async move {
+            result.ok().map(|state| (uuid, state))
+        })
+        .collect::<HashMap<_,_>>().await;
+    if let Err(_) = callback.send(compute_states) {
+        log::error!("Could not respond with compute server states")
+    }
+}
+
 async fn handle_forward_drone_action_request(drone_tx_map: &HashMap<Uuid, drone::Sender>,
+                                             queues: &HashMap<Uuid, Queue>,
+                                             config: &config::Receiver,
                                              uuid: Uuid,
                                              action: drone::Action) {
+    if action::find(&config.borrow().drone_actions, &action).is_none() {
+        log::warn!("Refused {:?} on drone {}, the action is not defined in the configuration", action, uuid);
+        return;
+    }
+    /* actions that change the state of the drone are refused while it is busy */
+    let check = match (action.queue_kind(), queues.get(&uuid)) {
+        (Some(kind), Some(queue)) => queue.check(kind),
+        _ => Ok(()),
+    };
+    if let Err(error) = check {
+        log::warn!("Could not execute {:?} on drone {}: {}", action, uuid, error);
+        return;
+    }
     match drone_tx_map.get(&uuid) {
         Some(tx) => {
             let request = drone::Request::Execute(action);
@@ -449,4 +2768,92 @@ async fn handle_get_drones_request(drone_tx_map: &HashMap<Uuid, drone::Sender>,
     if let Err(_) = callback.send(drone_states) {
         log::error!("Could not respond with drone states")
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /* services whose receivers are dropped, any requests to them are discarded */
+    fn unconnected_services() -> Services {
+        Services {
+            telemetry: mpsc::unbounded_channel().0,
+            dock: mpsc::unbounded_channel().0,
+            environment: mpsc::unbounded_channel().0,
+            campaign: mpsc::unbounded_channel().0,
+            selftest: mpsc::unbounded_channel().0,
+            tracking: mpsc::unbounded_channel().0,
+            arming: mpsc::unbounded_channel().0,
+            push: mpsc::unbounded_channel().0,
+            provision: mpsc::unbounded_channel().0,
+            availability: mpsc::unbounded_channel().0,
+            link: mpsc::unbounded_channel().0,
+            sensing: mpsc::unbounded_channel().0,
+            operations: mpsc::unbounded_channel().0,
+            network: mpsc::unbounded_channel().0,
+            tunnel: mpsc::unbounded_channel().0,
+        }
+    }
+
+    /* runs the arena without the other tasks of the supervisor for as long as the test runs */
+    async fn with_arena<F, T>(test: impl FnOnce(mpsc::UnboundedSender<Request>, watch::Sender<Arc<config::Config>>) -> F) -> T
+        where F: std::future::Future<Output = T> {
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        let (config_tx, config_rx) = watch::channel(Arc::new(config::Config::default()));
+        let (journal, _) = journal::new();
+        let arena = new(requests_rx, &journal, unconnected_services(),
+            None, config_rx, mpsc::unbounded_channel().0, Default::default(), router::Relay::new());
+        tokio::pin!(arena);
+        tokio::select! {
+            _ = &mut arena => panic!("The arena stopped during the test"),
+            result = test(requests_tx, config_tx) => result,
+        }
+    }
+
+    async fn query<T>(requests_tx: &mpsc::UnboundedSender<Request>,
+                      request: impl FnOnce(oneshot::Sender<T>) -> Request) -> T {
+        let (callback_tx, callback_rx) = oneshot::channel();
+        requests_tx.send(request(callback_tx)).unwrap();
+        callback_rx.await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn killed_robots_are_lost() {
+        with_arena(|requests_tx, config_tx| async move {
+            requests_tx.send(Request::AddVirtualRobot(Kind::PiPuck)).unwrap();
+            assert_eq!(query(&requests_tx, Request::GetVirtualRobots).await.len(), 1);
+            assert_eq!(query(&requests_tx, Request::GetLifecycles).await.len(), 1);
+            let chaos = crate::chaos::Chaos { kill_period: Some(1.0), ..Default::default() };
+            config_tx.send(Arc::new(config::Config { chaos: Some(chaos), ..Default::default() })).unwrap();
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            assert!(query(&requests_tx, Request::GetVirtualRobots).await.is_empty());
+            assert!(query(&requests_tx, Request::GetLifecycles).await.is_empty());
+        }).await;
+    }
+
+    #[test]
+    fn lifecycle_transitions_are_validated() {
+        let (journal, _) = journal::new();
+        let uuid = Uuid::new_v4();
+        let (accepted_tx, accepted_rx) = watch::channel(Lifecycle::Discovered);
+        let mut lifecycles = HashMap::new();
+        lifecycles.insert(uuid, Lifecycle::Discovered);
+        let mut published = HashMap::new();
+        published.insert(uuid, accepted_tx);
+        let mut stopped_running = Vec::new();
+        let mut transition = |next| handle_lifecycle_transition(&mut lifecycles, &published,
+            &mut stopped_running, &journal, uuid, next);
+        /* a robot that was not identified cannot start running */
+        assert!(!transition(Lifecycle::Running));
+        assert!(transition(Lifecycle::Identified));
+        /* reporting the current lifecycle again is not a transition */
+        assert!(!transition(Lifecycle::Identified));
+        assert!(transition(Lifecycle::Ready));
+        assert!(transition(Lifecycle::Staged));
+        assert!(transition(Lifecycle::Running));
+        assert!(transition(Lifecycle::Ready));
+        assert_eq!(*accepted_rx.borrow(), Lifecycle::Ready);
+        assert_eq!(lifecycles.get(&uuid), Some(&Lifecycle::Ready));
+        assert_eq!(stopped_running, vec![uuid]);
+    }
+}
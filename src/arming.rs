@@ -0,0 +1,167 @@
+use std::{fmt, path::PathBuf, time::Duration};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tokio::{fs::File, io::AsyncReadExt, sync::{mpsc, oneshot}};
+
+use crate::config;
+use crate::journal;
+
+/// Period at which a GPIO switch is polled
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+/// Size of a struct input_event on a 64-bit host
+const INPUT_EVENT_SIZE: usize = 24;
+/* types of input events that report the state of a key or a switch */
+const EV_KEY: u16 = 0x01;
+const EV_SW: u16 = 0x05;
+
+/// A hardware switch that must be engaged before experiments with drones can be started
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "source")]
+pub enum Switch {
+    /// A file that contains 1 while the switch is engaged, e.g., the value of a GPIO in sysfs
+    Gpio {
+        path: PathBuf,
+        #[serde(default)]
+        active_low: bool,
+    },
+    /// A key or a switch of an input device, e.g., a USB HID key switch under /dev/input
+    Input {
+        device: PathBuf,
+        code: u16,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum State {
+    /// There is no arming switch and drones can always be started
+    NotRequired,
+    Armed,
+    Disarmed,
+    /// The arming switch could not be read and is treated as disarmed
+    Unavailable,
+}
+
+impl State {
+    pub fn is_armed(&self) -> bool {
+        match self {
+            State::NotRequired | State::Armed => true,
+            State::Disarmed | State::Unavailable => false,
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::NotRequired => write!(f, "not required"),
+            State::Armed => write!(f, "armed"),
+            State::Disarmed => write!(f, "disarmed"),
+            State::Unavailable => write!(f, "unavailable"),
+        }
+    }
+}
+
+pub enum Request {
+    State(oneshot::Sender<State>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+async fn read_gpio(path: &PathBuf, active_low: bool) -> State {
+    match tokio::fs::read_to_string(path).await {
+        Ok(value) => match (value.trim() == "1") != active_low {
+            true => State::Armed,
+            false => State::Disarmed,
+        },
+        Err(error) => {
+            log::warn!("Could not read the arming switch {}: {}", path.display(), error);
+            State::Unavailable
+        }
+    }
+}
+
+/* the next event (type, code, value) from the input device or pending forever if there is no device */
+async fn read_input(device: &mut Option<File>) -> std::io::Result<(u16, u16, i32)> {
+    match device {
+        Some(device) => {
+            let mut event = [0u8; INPUT_EVENT_SIZE];
+            device.read_exact(&mut event).await?;
+            /* the event follows a struct timeval of two 64-bit integers */
+            Ok((u16::from_le_bytes([event[16], event[17]]),
+                u16::from_le_bytes([event[18], event[19]]),
+                i32::from_le_bytes([event[20], event[21], event[22], event[23]])))
+        },
+        None => future::pending().await,
+    }
+}
+
+/// Monitors the arming switch and journals the changes to its state
+pub async fn new(mut requests_rx: Receiver,
+                 mut config: config::Receiver,
+                 journal: journal::Sender) {
+    let mut state = State::NotRequired;
+    /* start over whenever the switch is reconfigured */
+    'switch: loop {
+        let switch = config.borrow().arming_switch.clone();
+        let mut next = match switch {
+            None => State::NotRequired,
+            /* the state of an input device is unknown until the switch is toggled */
+            Some(_) => State::Disarmed,
+        };
+        let mut device = None;
+        if let Some(Switch::Input { device: path, .. }) = &switch {
+            match File::open(path).await {
+                Ok(file) => device = Some(file),
+                Err(error) => {
+                    log::error!("Could not open the arming switch {}: {}", path.display(), error);
+                    next = State::Unavailable;
+                }
+            }
+        }
+        let mut poll = tokio::time::interval(POLL_PERIOD);
+        loop {
+            if next != state {
+                state = next;
+                log::warn!("Arming switch is {}", state);
+                if let Err(error) = journal.record(journal::Event::Arming(state)) {
+                    log::warn!("Could not record arming switch in journal: {}", error);
+                }
+            }
+            tokio::select! {
+                _ = poll.tick() => if let Some(Switch::Gpio { path, active_low }) = &switch {
+                    next = read_gpio(path, *active_low).await;
+                },
+                event = read_input(&mut device) => match (event, &switch) {
+                    (Ok((kind, code, value)), Some(Switch::Input { code: switch_code, .. })) => {
+                        if (kind == EV_KEY || kind == EV_SW) && code == *switch_code {
+                            /* a value of two is a repeated key press */
+                            next = match value {
+                                0 => State::Disarmed,
+                                _ => State::Armed,
+                            };
+                        }
+                    },
+                    (Ok(_), _) => {},
+                    (Err(error), _) => {
+                        log::error!("Could not read the arming switch: {}", error);
+                        device = None;
+                        next = State::Unavailable;
+                    }
+                },
+                changed = config.changed() => match changed {
+                    Ok(_) => if config.borrow().arming_switch != switch {
+                        continue 'switch;
+                    },
+                    Err(_) => break 'switch,
+                },
+                request = requests_rx.recv() => match request {
+                    Some(Request::State(callback)) => {
+                        let _ = callback.send(state);
+                    },
+                    None => break 'switch,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::{HashMap, HashSet}, net::SocketAddr};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+/// The kinds of requests from the web interface that change the testbed
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Start and stop experiments and canary experiments
+    Experiment,
+    /// Upload and clear the software, environment schedule, flags, and message templates
+    Software,
+    /// Halt, reboot, and power robots on or off
+    Power,
+    /// Camera streams, kernel messages, identification, and software rollbacks of single robots
+    Maintenance,
+    /// Send messages and commands to the robots
+    Inject,
+    /// Capture the corners of the arena and reset its calibration
+    Calibrate,
+    /// Clone, save, and delete past runs and templates
+    History,
+    /// Run scripts on the whole fleet as a maintenance campaign
+    Campaign,
+    /// Provision freshly flashed robots
+    Provision,
+    /// Reload the configuration of the supervisor
+    Configure,
+    /// Actions provided by plugins
+    Plugin,
+    /// Run the controller of a robot on a compute server
+    RemoteBrain,
+    /// Cancel long-running operations such as staging, result collection, and campaigns
+    Operation,
+    /// Follow the log, the live tracking and telemetry, and the operations over HTTP
+    Monitor,
+}
+
+/// The role of the clients connecting from a network
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Client {
+    pub network: IpNet,
+    pub role: String,
+}
+
+/// Permissions of the web interface clients, e.g., students may start experiments and upload
+/// software but may not reboot or halt robots
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Auth {
+    /// Permissions granted to each role
+    pub roles: HashMap<String, HashSet<Permission>>,
+    /// Roles of the clients by their address, the first network that contains the address applies
+    pub clients: Vec<Client>,
+    /// Role of the clients whose address is not in any of the networks, these clients may not
+    /// change the testbed if it is not given
+    pub default_role: Option<String>,
+}
+
+impl Auth {
+    /// The role of a client connecting from the given address
+    pub fn role(&self, addr: Option<SocketAddr>) -> Option<&str> {
+        addr.and_then(|addr| self.clients.iter()
+                .find(|client| client.network.contains(&addr.ip()))
+                .map(|client| client.role.as_str()))
+            .or(self.default_role.as_deref())
+    }
+
+    /// Checks whether a client connecting from the given address has been granted a permission
+    pub fn permits(&self, addr: Option<SocketAddr>, permission: Permission) -> bool {
+        self.role(addr)
+            .and_then(|role| self.roles.get(role))
+            .map_or(false, |permissions| permissions.contains(&permission))
+    }
+}
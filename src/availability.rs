@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf, time::{Duration, SystemTime}};
+use tokio::sync::{mpsc, oneshot};
+
+/// Period over which the availability of the robots is computed
+const WINDOW: Duration = Duration::from_secs(28 * 24 * 60 * 60);
+/// Interval at which the connection history is written to disk
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A period during which a robot was connected
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Interval {
+    connected: SystemTime,
+    disconnected: Option<SystemTime>,
+    /// The interval was ended by stopping the supervisor rather than by the robot disconnecting
+    #[serde(default)]
+    interrupted: bool,
+}
+
+/// The connection history of the robots by their name, i.e., their hostname
+#[derive(Debug, Deserialize, Serialize)]
+struct History {
+    /// When the history was started
+    since: SystemTime,
+    /// When the history was last written, the robots that were connected at this time are
+    /// considered to have disconnected if the supervisor was stopped
+    updated: SystemTime,
+    robots: BTreeMap<String, Vec<Interval>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        let now = SystemTime::now();
+        History { since: now, updated: now, robots: BTreeMap::new() }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Statistics {
+    pub name: String,
+    /// Fraction of the window during which the robot was connected
+    pub availability: f32,
+    /// Number of times the robot disconnected during the window
+    pub disconnections: usize,
+    /// Disconnections per day that the robot was connected, a high score indicates that the
+    /// robot needs hardware attention
+    pub flakiness: f32,
+}
+
+pub enum Request {
+    Connected(String),
+    Disconnected(String),
+    Statistics(oneshot::Sender<Vec<Statistics>>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+impl History {
+    fn load(path: &PathBuf) -> History {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(_) => return History::default(),
+        };
+        match serde_json::from_slice::<History>(&contents) {
+            Ok(mut history) => {
+                let updated = history.updated;
+                for interval in history.robots.values_mut().flatten() {
+                    if interval.disconnected.is_none() {
+                        interval.disconnected = Some(updated);
+                        interval.interrupted = true;
+                    }
+                }
+                history
+            },
+            Err(error) => {
+                log::error!("Could not read connection history from {}: {}", path.display(), error);
+                History::default()
+            }
+        }
+    }
+
+    fn persist(&mut self, path: &PathBuf) -> std::io::Result<()> {
+        self.updated = SystemTime::now();
+        /* write to a temporary file first so that the previous snapshot survives a crash */
+        let temporary = path.with_extension("tmp");
+        let contents = serde_json::to_vec(self)?;
+        std::fs::write(&temporary, contents)?;
+        std::fs::rename(&temporary, path)
+    }
+
+    fn statistics(&mut self) -> Vec<Statistics> {
+        let now = SystemTime::now();
+        let start = now.checked_sub(WINDOW).unwrap_or(self.since).max(self.since);
+        /* forget the intervals that ended before the window */
+        for intervals in self.robots.values_mut() {
+            intervals.retain(|interval| interval.disconnected.map_or(true, |disconnected| disconnected > start));
+        }
+        let window = now.duration_since(start).unwrap_or_default().as_secs_f32();
+        self.robots.iter()
+            .map(|(name, intervals)| {
+                let connected = intervals.iter()
+                    .map(|interval| interval.disconnected.unwrap_or(now)
+                        .duration_since(interval.connected.max(start))
+                        .unwrap_or_default()
+                        .as_secs_f32())
+                    .sum::<f32>();
+                let disconnections = intervals.iter()
+                    .filter(|interval| interval.disconnected.is_some() && !interval.interrupted)
+                    .count();
+                Statistics {
+                    name: name.clone(),
+                    availability: match window > 0.0 {
+                        true => (connected / window).min(1.0),
+                        false => 0.0,
+                    },
+                    disconnections,
+                    flakiness: disconnections as f32 / (connected / 86400.0).max(1.0 / 24.0),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Records when the robots connect and disconnect to compute their availability over weeks
+pub async fn new(mut rx: Receiver, path: Option<PathBuf>) {
+    let mut history = path.as_ref().map(History::load).unwrap_or_default();
+    let mut persist_interval = tokio::time::interval(PERSIST_INTERVAL);
+    loop {
+        tokio::select! {
+            request = rx.recv() => match request {
+                Some(Request::Connected(name)) => {
+                    let intervals = history.robots.entry(name).or_default();
+                    if intervals.last().map_or(true, |interval| interval.disconnected.is_some()) {
+                        intervals.push(Interval { connected: SystemTime::now(), disconnected: None, interrupted: false });
+                    }
+                },
+                Some(Request::Disconnected(name)) => {
+                    if let Some(interval) = history.robots.get_mut(&name).and_then(|intervals| intervals.last_mut()) {
+                        interval.disconnected.get_or_insert_with(SystemTime::now);
+                    }
+                },
+                Some(Request::Statistics(callback)) => {
+                    if let Err(_) = callback.send(history.statistics()) {
+                        log::error!("Could not respond with availability statistics");
+                    }
+                },
+                None => break,
+            },
+            _ = persist_interval.tick() => if let Some(path) = path.as_ref() {
+                if let Err(error) = history.persist(path) {
+                    log::error!("Could not write connection history to {}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::{fs::File, io::{BufReader, BufWriter}, path::PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::optitrack::Pose;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Exactly one rigid body must be tracked while capturing a corner, found {0}")]
+    RigidBodyCount(usize),
+    #[error("The corners of the arena are too close together or in a line")]
+    DegenerateCorners,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Corners of the arena that are captured in order during the calibration
+pub const CORNERS: [&str; 3] = [
+    "the origin of the arena",
+    "a point on the x axis of the arena",
+    "a point on the y axis of the arena",
+];
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Action {
+    #[serde(rename = "Capture corner")]
+    Capture,
+    #[serde(rename = "Reset calibration")]
+    Reset,
+}
+
+/// Rigid transformation from the frame of the tracking system to the frame of the arena
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Transform {
+    /// Rows are the axes of the arena in the frame of the tracking system
+    pub rotation: [[f32; 3]; 3],
+    /// Origin of the arena in the frame of the tracking system
+    pub origin: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            origin: [0.0; 3],
+        }
+    }
+}
+
+fn subtract(left: &[f32; 3], right: &[f32; 3]) -> [f32; 3] {
+    [left[0] - right[0], left[1] - right[1], left[2] - right[2]]
+}
+
+fn dot(left: &[f32; 3], right: &[f32; 3]) -> f32 {
+    left[0] * right[0] + left[1] * right[1] + left[2] * right[2]
+}
+
+fn normalize(vector: &[f32; 3]) -> Option<[f32; 3]> {
+    let norm = dot(vector, vector).sqrt();
+    match norm > 1e-3 {
+        true => Some([vector[0] / norm, vector[1] / norm, vector[2] / norm]),
+        false => None,
+    }
+}
+
+impl Transform {
+    /// Builds the arena frame from its origin, a point on its x axis and a point on its y axis
+    pub fn from_corners(corners: &[[f32; 3]; 3]) -> Result<Transform> {
+        let [origin, on_x, on_y] = corners;
+        let x = normalize(&subtract(on_x, origin))
+            .ok_or(Error::DegenerateCorners)?;
+        /* remove the component along the x axis so that the axes are orthogonal */
+        let towards_y = subtract(on_y, origin);
+        let along_x = dot(&towards_y, &x);
+        let y = normalize(&[towards_y[0] - along_x * x[0], towards_y[1] - along_x * x[1], towards_y[2] - along_x * x[2]])
+            .ok_or(Error::DegenerateCorners)?;
+        let z = [x[1] * y[2] - x[2] * y[1], x[2] * y[0] - x[0] * y[2], x[0] * y[1] - x[1] * y[0]];
+        Ok(Transform { rotation: [x, y, z], origin: *origin })
+    }
+
+    pub fn position(&self, position: &[f32; 3]) -> [f32; 3] {
+        let relative = subtract(position, &self.origin);
+        [dot(&self.rotation[0], &relative), dot(&self.rotation[1], &relative), dot(&self.rotation[2], &relative)]
+    }
+
+    /* quaternion (w, x, y, z) of the rotation matrix */
+    fn quaternion(&self) -> [f32; 4] {
+        let m = &self.rotation;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            [0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s]
+        }
+        else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            [(m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s]
+        }
+        else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            [(m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s]
+        }
+        else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            [(m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s]
+        }
+    }
+
+    pub fn orientation(&self, orientation: &[f32; 4]) -> [f32; 4] {
+        let [aw, ax, ay, az] = self.quaternion();
+        let [bw, bx, by, bz] = *orientation;
+        [
+            aw * bw - ax * bx - ay * by - az * bz,
+            aw * bx + ax * bw + ay * bz - az * by,
+            aw * by - ax * bz + ay * bw + az * bx,
+            aw * bz + ax * by - ay * bx + az * bw,
+        ]
+    }
+
+    /// Expresses a pose from the tracking system in the frame of the arena
+    pub fn pose(&self, pose: &Pose) -> Pose {
+        Pose {
+            position: self.position(&pose.position),
+            orientation: self.orientation(&pose.orientation),
+            ..pose.clone()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub transform: Transform,
+    /// Corners captured so far by an unfinished calibration
+    pub captured: Vec<[f32; 3]>,
+}
+
+/// The transform from the tracking system to the arena and a guided routine for finding it
+pub struct Calibration {
+    transform: Transform,
+    captured: Vec<[f32; 3]>,
+    path: Option<PathBuf>,
+}
+
+impl Calibration {
+    /// Loads the transform from a file, the identity transform is used if there is no file
+    pub fn load(path: Option<PathBuf>) -> Calibration {
+        let transform = path.as_ref()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                let transform = File::open(path)
+                    .map_err(Error::IoError)
+                    .and_then(|file| serde_json::from_reader(BufReader::new(file)).map_err(Error::JsonError));
+                match transform {
+                    Ok(transform) => Some(transform),
+                    Err(error) => {
+                        log::error!("Could not load calibration from {}: {}", path.display(), error);
+                        None
+                    }
+                }
+            })
+            .unwrap_or_default();
+        Calibration { transform, captured: Vec::new(), path }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = self.path.as_ref() {
+            let writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(writer, &self.transform)?;
+        }
+        Ok(())
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn status(&self) -> Status {
+        Status { transform: self.transform.clone(), captured: self.captured.clone() }
+    }
+
+    /// Captures the next corner from the untransformed poses and updates the transform once all
+    /// corners have been captured
+    pub fn execute(&mut self, action: Action, poses: &[Pose]) -> Result<()> {
+        match action {
+            Action::Reset => {
+                self.captured.clear();
+                self.transform = Transform::default();
+                self.save()
+            },
+            Action::Capture => {
+                let position = match poses {
+                    [pose] => pose.position,
+                    _ => return Err(Error::RigidBodyCount(poses.len())),
+                };
+                self.captured.push(position);
+                if self.captured.len() == CORNERS.len() {
+                    let corners = [self.captured[0], self.captured[1], self.captured[2]];
+                    self.captured.clear();
+                    self.transform = Transform::from_corners(&corners)?;
+                    log::info!("Calibrated the arena frame: {:?}", self.transform);
+                    self.save()?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
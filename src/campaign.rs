@@ -0,0 +1,284 @@
+use std::time::Duration;
+use futures::{future::{self, FutureExt}, stream::{FuturesUnordered, StreamExt}};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot, watch};
+use uuid::Uuid;
+
+use crate::arena;
+use crate::operation;
+use crate::robot::Lifecycle;
+
+/// Time given to the robots of a wave to settle before their health is verified
+const HEALTH_CHECK_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not send request to arena")]
+    ArenaRequestError,
+    #[error("Did not receive response from arena")]
+    ArenaResponseError,
+    #[error("Wave size must be at least one")]
+    InvalidWaveSize,
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An update that is rolled out to the robots in waves
+#[derive(Clone, Debug, Deserialize)]
+pub struct Update {
+    pub name: String,
+    /// Packages that are installed or upgraded with opkg
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Shell script that is run after the packages have been installed
+    #[serde(default)]
+    pub script: Option<String>,
+    /// Selects the robots that are updated, all robots are selected if empty
+    #[serde(default)]
+    pub targets: Vec<arena::Predicate>,
+    /// Number of robots that are updated at the same time
+    pub wave_size: usize,
+    /// Fraction of the updated robots that may fail before the campaign is halted
+    pub max_failure_rate: f32,
+}
+
+impl Update {
+    pub fn parse(contents: &[u8]) -> Result<Update> {
+        let update: Update = serde_json::from_slice(contents)?;
+        match update.wave_size {
+            0 => Err(Error::InvalidWaveSize),
+            _ => Ok(update),
+        }
+    }
+
+    fn script(&self) -> Vec<u8> {
+        let mut script = String::from("set -e\n");
+        if !self.packages.is_empty() {
+            script.push_str("opkg update\n");
+            script.push_str(&format!("opkg install {}\n", self.packages.join(" ")));
+        }
+        if let Some(user_script) = &self.script {
+            script.push_str(user_script);
+            script.push('\n');
+        }
+        script.into_bytes()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    Running,
+    Completed,
+    Halted(String),
+    Aborted,
+}
+
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub name: String,
+    pub state: State,
+    pub waves: usize,
+    pub waves_completed: usize,
+    pub pending: Vec<Uuid>,
+    pub succeeded: Vec<Uuid>,
+    pub failed: Vec<(Uuid, String)>,
+}
+
+pub enum Request {
+    Start(Vec<u8>),
+    Abort,
+    Progress(oneshot::Sender<Option<Progress>>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+async fn query(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+               predicates: Vec<arena::Predicate>) -> Result<Vec<arena::Summary>> {
+    let (callback_tx, callback_rx) = oneshot::channel();
+    arena_request_tx.send(arena::Request::Query(predicates, callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    callback_rx.await.map_err(|_| Error::ArenaResponseError)
+}
+
+/* robots must not be updated while an experiment is running */
+async fn experiment_active(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<bool> {
+    let (callback_tx, callback_rx) = oneshot::channel();
+    arena_request_tx.send(arena::Request::GetExperimentActive(callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    callback_rx.await.map_err(|_| Error::ArenaResponseError)
+}
+
+fn halt(update: &Update,
+        mut progress: Progress,
+        progress_tx: &watch::Sender<Option<Progress>>,
+        operation: &operation::Handle,
+        reason: String) {
+    operation.finish(operation::State::Failed(reason.clone()));
+    progress.state = State::Halted(reason);
+    let _ = progress_tx.send(Some(progress));
+    log::error!("Campaign {} halted", update.name);
+}
+
+async fn update_robot(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                      uuid: Uuid,
+                      script: Vec<u8>) -> (Uuid, std::result::Result<(), String>) {
+    let (callback_tx, callback_rx) = oneshot::channel();
+    if let Err(_) = arena_request_tx.send(arena::Request::RunScript(uuid, script, callback_tx)) {
+        return (uuid, Err(Error::ArenaRequestError.to_string()));
+    }
+    let result = match callback_rx.await {
+        Ok(result) => result.map_err(|error| error.to_string()),
+        Err(_) => Err(Error::ArenaResponseError.to_string()),
+    };
+    (uuid, result)
+}
+
+async fn run(update: Update,
+             arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+             progress_tx: &watch::Sender<Option<Progress>>,
+             operation: operation::Handle) -> Result<()> {
+    /* simulated robots have nothing to update */
+    let targets = query(&arena_request_tx, update.targets.clone()).await?
+        .into_iter()
+        .filter(|summary| !summary.simulated)
+        .collect::<Vec<_>>();
+    operation.total(targets.len());
+    let mut progress = Progress {
+        name: update.name.clone(),
+        state: State::Running,
+        waves: (targets.len() + update.wave_size - 1) / update.wave_size,
+        waves_completed: 0,
+        pending: targets.iter().map(|target| target.uuid).collect(),
+        succeeded: Vec::new(),
+        failed: Vec::new(),
+    };
+    let _ = progress_tx.send(Some(progress.clone()));
+    let script = update.script();
+    for wave in targets.chunks(update.wave_size) {
+        if experiment_active(&arena_request_tx).await? {
+            halt(&update, progress, progress_tx, &operation, "an experiment was started".to_owned());
+            return Ok(());
+        }
+        log::info!("Campaign {}: updating {} robots", update.name, wave.len());
+        let results = wave.iter()
+            .map(|target| update_robot(&arena_request_tx, target.uuid, script.clone()))
+            .collect::<FuturesUnordered<_>>()
+            .collect::<Vec<_>>().await;
+        /* give the robots time to restart services before verifying their health */
+        tokio::time::sleep(HEALTH_CHECK_DELAY).await;
+        let robots = query(&arena_request_tx, Vec::new()).await?;
+        for (uuid, result) in results {
+            progress.pending.retain(|pending| *pending != uuid);
+            /* a robot that rebooted during the update reconnects under a new identifier, so it is
+               found by its hostname instead */
+            let hostname = wave.iter()
+                .find(|target| target.uuid == uuid)
+                .and_then(|target| target.hostname.as_ref());
+            let lifecycle = robots.iter()
+                .find(|robot| robot.uuid == uuid)
+                .or_else(|| hostname.and_then(|hostname| robots.iter()
+                    .find(|robot| robot.hostname.as_ref() == Some(hostname))))
+                .and_then(|robot| robot.lifecycle);
+            let result = result.and_then(|_| match lifecycle {
+                Some(Lifecycle::Ready) | Some(Lifecycle::Running) => Ok(()),
+                Some(lifecycle) => Err(format!("robot is {} after the update", lifecycle)),
+                None => Err("robot did not reconnect after the update".to_owned()),
+            });
+            operation.report(uuid, result.clone());
+            match result {
+                Ok(_) => progress.succeeded.push(uuid),
+                Err(error) => {
+                    log::warn!("Campaign {}: update of {} failed: {}", update.name, uuid, error);
+                    progress.failed.push((uuid, error));
+                }
+            }
+        }
+        progress.waves_completed += 1;
+        let updated = progress.succeeded.len() + progress.failed.len();
+        let failure_rate = progress.failed.len() as f32 / updated as f32;
+        if failure_rate > update.max_failure_rate {
+            let reason = format!("failure rate of {:.0}% exceeded the threshold of {:.0}%",
+                failure_rate * 100.0, update.max_failure_rate * 100.0);
+            halt(&update, progress, progress_tx, &operation, reason);
+            return Ok(());
+        }
+        let _ = progress_tx.send(Some(progress.clone()));
+    }
+    progress.state = State::Completed;
+    operation.finish(operation::State::Completed);
+    let _ = progress_tx.send(Some(progress));
+    log::info!("Campaign {} completed", update.name);
+    Ok(())
+}
+
+/* completes once the operation of the running campaign has been cancelled */
+async fn cancelled(operation: Option<&operation::Handle>) {
+    match operation {
+        Some(operation) => operation.cancelled().await,
+        None => future::pending().await,
+    }
+}
+
+pub async fn new(mut requests_rx: Receiver,
+                 arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+                 operations_tx: operation::Sender) {
+    let (progress_tx, progress_rx) = watch::channel(None);
+    let campaign = future::pending().left_future();
+    tokio::pin!(campaign);
+    let mut operation: Option<operation::Handle> = None;
+    loop {
+        let request = tokio::select! {
+            result = &mut campaign => {
+                campaign.set(future::pending().left_future());
+                if let (Err(error), Some(operation)) = (result, operation.take()) {
+                    log::error!("Campaign failed: {}", error);
+                    operation.finish(operation::State::Failed(error.to_string()));
+                }
+                continue;
+            },
+            /* cancelling the operation of the campaign is the same as aborting it */
+            _ = cancelled(operation.as_ref()) => Some(Request::Abort),
+            recv_request = requests_rx.recv() => recv_request,
+        };
+        match request {
+            Some(Request::Start(contents)) => {
+                if let Some(Progress { state: State::Running, .. }) = *progress_rx.borrow() {
+                    log::warn!("Another campaign is already running");
+                    continue;
+                }
+                match Update::parse(&contents) {
+                    Ok(update) => {
+                        log::info!("Starting campaign {}", update.name);
+                        let handle = operation::Handle::begin(&operations_tx,
+                            operation::Kind::Campaign, format!("Campaign {}", update.name), None);
+                        operation = Some(handle.clone());
+                        campaign.set(run(update, arena_request_tx.clone(), &progress_tx, handle).right_future());
+                    },
+                    Err(error) => log::error!("Could not parse campaign: {}", error),
+                }
+            },
+            Some(Request::Abort) => {
+                /* dropping the campaign stops it after the scripts that are already running */
+                campaign.set(future::pending().left_future());
+                if let Some(operation) = operation.take() {
+                    operation.finish(operation::State::Cancelled);
+                }
+                let progress = progress_rx.borrow().clone();
+                if let Some(mut progress) = progress {
+                    if progress.state == State::Running {
+                        progress.state = State::Aborted;
+                        let _ = progress_tx.send(Some(progress));
+                    }
+                }
+            },
+            Some(Request::Progress(callback)) => {
+                let _ = callback.send(progress_rx.borrow().clone());
+            },
+            None => break,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+use rand::Rng;
+use serde::Deserialize;
+
+/// Failures that are injected into the supervisor on purpose, for testing that the arena
+/// recovers from misbehaving robots, lost tracking frames, and robot tasks that end unexpectedly.
+/// This must never be configured on a testbed that is running experiments.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Chaos {
+    /// Probability that a response of the fernbedienung service is dropped
+    pub deny_response: f64,
+    /// Probability that a response of the fernbedienung service is delayed
+    pub delay_response: f64,
+    /// Maximum seconds by which a response is delayed
+    pub max_delay: f64,
+    /// Probability that a frame from the Optitrack system is dropped
+    pub drop_frame: f64,
+    /// Mean seconds between killing the task of a randomly chosen robot
+    pub kill_period: Option<f64>,
+}
+
+/// What happens to a response of the fernbedienung service
+pub enum Fault {
+    None,
+    Deny,
+    Delay(Duration),
+}
+
+fn chance(probability: f64) -> bool {
+    probability > 0.0 && rand::thread_rng().gen_bool(probability.min(1.0))
+}
+
+impl Chaos {
+    pub fn response(&self) -> Fault {
+        if chance(self.deny_response) {
+            Fault::Deny
+        }
+        else if chance(self.delay_response) {
+            let delay = rand::thread_rng().gen_range(0.0..=self.max_delay.max(0.0));
+            Fault::Delay(Duration::from_secs_f64(delay))
+        }
+        else {
+            Fault::None
+        }
+    }
+
+    pub fn drop_frame(&self) -> bool {
+        chance(self.drop_frame)
+    }
+
+    /// Whether a robot task should be killed, this is checked once per second
+    pub fn kill(&self) -> bool {
+        self.kill_period.map_or(false, |period| chance(1.0 / period.max(1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_injects_nothing() {
+        let chaos = Chaos::default();
+        for _ in 0..1000 {
+            assert!(matches!(chaos.response(), Fault::None));
+            assert!(!chaos.drop_frame());
+            assert!(!chaos.kill());
+        }
+    }
+
+    #[test]
+    fn certain_faults_are_always_injected() {
+        let chaos = Chaos {
+            deny_response: 1.0,
+            drop_frame: 1.0,
+            kill_period: Some(1.0),
+            ..Default::default()
+        };
+        for _ in 0..1000 {
+            assert!(matches!(chaos.response(), Fault::Deny));
+            assert!(chaos.drop_frame());
+            assert!(chaos.kill());
+        }
+    }
+
+    #[test]
+    fn delays_are_bounded() {
+        let chaos = Chaos {
+            delay_response: 1.0,
+            max_delay: 0.5,
+            ..Default::default()
+        };
+        for _ in 0..1000 {
+            match chaos.response() {
+                Fault::Delay(delay) => assert!(delay <= Duration::from_secs_f64(0.5)),
+                _ => panic!("expected a delayed response"),
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::{collections::VecDeque, time::{Duration, SystemTime}};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Number of messages that are kept for the chat card, older messages remain in the journals
+const HISTORY: usize = 100;
+
+/// A message between the operators, e.g., between the people in the arena and at the console
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Message {
+    pub sent: SystemTime,
+    pub author: String,
+    pub text: String,
+    /// The run during which the message was sent and the time since the run started, the same
+    /// clock as the events in the journal of the run
+    pub run: Option<(Uuid, Duration)>,
+    /// Whether the message was pinned as an annotation of its run
+    pub pinned: bool,
+}
+
+/// The most recent messages of the chat
+#[derive(Debug, Default)]
+pub struct Log(VecDeque<Message>);
+
+impl Log {
+    pub fn push(&mut self, message: Message) {
+        if self.0.len() == HISTORY {
+            self.0.pop_front();
+        }
+        self.0.push_back(message);
+    }
+
+    /// The messages from the oldest to the most recent
+    pub fn messages(&self) -> Vec<Message> {
+        self.0.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The origin of the timestamps in the journal of a run
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Epoch {
+    /// The start of the run
+    Run,
+    /// The Unix epoch, for aligning the journal with other sources such as the telemetry
+    Unix,
+}
+
+impl Default for Epoch {
+    fn default() -> Self {
+        Epoch::Run
+    }
+}
+
+/// The clock of a run, which is zeroed when the run starts. The subsystems timestamp their events
+/// with an instant, e.g., the exposure of a frame of the tracking system or the receipt of a
+/// message by the router, and the journal converts these instants into the time of the run so
+/// that the events of all sources share the same time base.
+#[derive(Clone, Copy, Debug)]
+pub struct Run {
+    start: Instant,
+    /// The time since the Unix epoch at which the run started
+    started: Duration,
+}
+
+impl Run {
+    pub fn start() -> Run {
+        let started = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Run { start: Instant::now(), started }
+    }
+
+    /// A clock that started at an earlier instant, e.g., for the events that were buffered before
+    /// they were written
+    pub fn since(start: Instant) -> Run {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Run { start, started: now.saturating_sub(start.elapsed()) }
+    }
+
+    /// The time since the Unix epoch at which the run started
+    pub fn started(&self) -> Duration {
+        self.started
+    }
+
+    /// The time of the run at an instant, instants before the start of the run are clamped to
+    /// the start of the run
+    pub fn at(&self, instant: Instant) -> Duration {
+        instant.saturating_duration_since(self.start)
+    }
+
+    /// The timestamp of an instant relative to an epoch
+    pub fn timestamp(&self, instant: Instant, epoch: Epoch) -> Duration {
+        match epoch {
+            Epoch::Run => self.at(instant),
+            Epoch::Unix => self.started + self.at(instant),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::{HashMap, HashSet}, net::IpAddr, time::Duration};
+use bytes::BytesMut;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::router::{self, LuaType};
+
+/// Robots that acknowledged a command before its deadline
+#[derive(Debug, Default, Serialize)]
+pub struct Acknowledgements {
+    pub id: u64,
+    pub confirmed: Vec<Uuid>,
+    pub missing: Vec<Uuid>,
+}
+
+/* acknowledgements are messages of the form { ack = <id> } */
+fn acknowledged_id(message: &LuaType) -> Option<u64> {
+    match message {
+        LuaType::Table(table) => table.iter()
+            .find_map(|entry| match entry {
+                (LuaType::String(key), LuaType::Number(id)) if key == "ack" => Some(*id as u64),
+                _ => None,
+            }),
+        _ => None,
+    }
+}
+
+/// Broadcasts a command to all robots through the message router and waits until either all
+/// robots have acknowledged the command or the deadline has passed. The command is sent with the
+/// additional entry `id`, which the controllers of the robots send back as `{ ack = <id> }`.
+pub async fn broadcast(relay: router::Relay,
+                       robots: HashMap<IpAddr, Uuid>,
+                       id: u64,
+                       mut command: Vec<(LuaType, LuaType)>,
+                       deadline: Duration) -> Acknowledgements {
+    /* subscribe before sending the command so that no acknowledgements are missed */
+    let mut subscription = relay.subscribe(router::supervisor_addr());
+    command.push((LuaType::String("id".to_owned()), LuaType::Number(id as f64)));
+    let mut data = BytesMut::new();
+    router::encode_lua_table(&command, &mut data);
+    relay.inject(None, data.freeze());
+    let mut confirmed = HashSet::new();
+    let collect = async {
+        while confirmed.len() < robots.len() {
+            match subscription.next().await {
+                Ok(message) => {
+                    let acknowledged = router::decode(&message.data)
+                        .and_then(|message| acknowledged_id(&message));
+                    if acknowledged == Some(id) {
+                        if let Some(uuid) = robots.get(&message.sender.ip()) {
+                            confirmed.insert(*uuid);
+                        }
+                    }
+                },
+                Err(error) => {
+                    log::warn!("Stopped collecting acknowledgements of command {}: {}", id, error);
+                    break
+                },
+            }
+        }
+    };
+    if let Err(_) = tokio::time::timeout(deadline, collect).await {
+        log::warn!("Command {} was acknowledged by {} of {} robots", id, confirmed.len(), robots.len());
+    }
+    let (confirmed, missing) = robots.values()
+        .partition::<Vec<Uuid>, _>(|uuid| confirmed.contains(*uuid));
+    Acknowledgements { id, confirmed, missing }
+}
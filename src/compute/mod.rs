@@ -0,0 +1,34 @@
+use futures::FutureExt;
+use uuid::Uuid;
+use std::{future::Future, pin::Pin, task::{Context, Poll}};
+use tokio::{sync::mpsc, task::JoinHandle};
+use crate::network::fernbedienung;
+
+mod task;
+pub mod tunnel;
+
+pub use task::{
+    Error, Receiver, Request, Sender, State
+};
+
+/// A compute server that runs the controllers of robots that are operated in the remote brain
+/// mode. The sensor and actuator traffic of these controllers is exchanged with the physical
+/// robot through the tunnel of the supervisor.
+pub struct Server(JoinHandle<Uuid>);
+
+impl Server {
+    pub fn new(device: fernbedienung::Device) -> (Uuid, Sender, Self) {
+        let uuid = Uuid::new_v4();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(task::new(uuid, rx, device));
+        (uuid, tx, Self(handle))
+    }
+}
+
+impl Future for Server {
+    type Output = Result<Uuid, tokio::task::JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().0.poll_unpin(cx)
+    }
+}
@@ -0,0 +1,168 @@
+use futures::{Future, StreamExt, TryFutureExt, TryStreamExt, stream::FuturesUnordered};
+use uuid::Uuid;
+use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf, time::Duration};
+use tokio::sync::{mpsc, oneshot};
+use crate::network::fernbedienung;
+use crate::journal;
+use crate::software;
+use crate::flags;
+use crate::robot::argos;
+
+#[derive(Debug)]
+pub struct State {
+    pub addr: Ipv4Addr,
+    /* the robots whose controllers are currently running on this server */
+    pub controllers: Vec<Uuid>,
+}
+
+pub enum Request {
+    State(oneshot::Sender<State>),
+    ExperimentStart {
+        robot: Uuid,
+        software: software::Software,
+        flags: flags::Assignment,
+        journal: journal::Sender,
+        callback: oneshot::Sender<Result<()>>
+    },
+    ExperimentStop,
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Operation timed out")]
+    Timeout,
+    #[error("The controller of {0} is already running")]
+    AlreadyRunning(Uuid),
+    #[error("Could not send request")]
+    RequestError,
+    #[error("Did not receive response")]
+    ResponseError,
+
+    #[error(transparent)]
+    FernbedienungError(#[from] fernbedienung::Error),
+    #[error(transparent)]
+    SoftwareError(#[from] software::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ArgosError(#[from] argos::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/* the hostname is polled to check that the compute server is still reachable */
+async fn poll_hostname(device: &fernbedienung::Device) -> Result<String> {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    tokio::time::timeout(Duration::from_secs(2), device.hostname()).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|result| result.map_err(Error::FernbedienungError))
+}
+
+async fn handle_experiment_start<'d>(robot: Uuid,
+                                     device: &'d fernbedienung::Device,
+                                     software: software::Software,
+                                     flags: flags::Assignment,
+                                     journal: journal::Sender)
+    -> Result<(impl Future<Output = (Uuid, fernbedienung::Result<fernbedienung::ExitStatus>)> + 'd, oneshot::Sender<()>)> {
+    /* extract the name of the config file */
+    let (argos_config, _) = software.argos_config()?;
+    let argos_config = argos_config.to_owned();
+    /* upload the control software */
+    let software_upload_path = device.create_temp_dir()
+        .map_err(|error| Error::FernbedienungError(error))
+        .and_then(|path: String| software.controller().into_iter()
+            .map(|(filename, contents)| {
+                let path = PathBuf::from(&path);
+                let filename = PathBuf::from(&filename);
+                device.upload(path, filename, contents)
+            })
+            .collect::<FuturesUnordered<_>>()
+            .map_err(|error| Error::FernbedienungError(error))
+            .try_collect::<Vec<_>>()
+            .map_ok(|_| path)
+        ).await?;
+
+    /* the controller identifies itself to the message router as the robot and reaches the
+       sensors and actuators of the robot through the tunnel */
+    let argos = argos::Argos {
+        uuid: robot,
+        working_dir: software_upload_path.clone().into(),
+        argos_config,
+        args: Vec::new(),
+        connection: argos::Connection::Brain,
+        launch: Default::default(),
+    };
+    let (argos, terminate_tx, _) = match argos.start(device, &software, &flags, journal).await {
+        Ok(argos) => argos,
+        Err(error) => {
+            let _ = device.remove(software_upload_path.into(), true).await;
+            return Err(error.into());
+        }
+    };
+
+    let argos_task_future = async move {
+        let result = argos.await;
+        /* remove the uploaded software so that the temporary directories do not accumulate */
+        if let Err(error) = device.remove(software_upload_path.into(), true).await {
+            log::warn!("Could not remove the software of {} from {}: {}", robot, device.addr, error);
+        }
+        (robot, result)
+    };
+    Ok((argos_task_future, terminate_tx))
+}
+
+pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Device) -> Uuid {
+    let mut controllers = FuturesUnordered::new();
+    let mut terminate_txs : HashMap<Uuid, oneshot::Sender<()>> = HashMap::new();
+    let poll_hostname_task = poll_hostname(&device);
+    tokio::pin!(poll_hostname_task);
+    loop {
+        tokio::select! {
+            Some((robot, result)) = controllers.next() => {
+                terminate_txs.remove(&robot);
+                match result {
+                    Ok(status) => log::info!("Remote controller of {} on {} terminated with {}", robot, device.addr, status),
+                    Err(error) => log::warn!("Remote controller of {} on {} terminated: {}", robot, device.addr, error),
+                }
+            },
+            result = &mut poll_hostname_task => match result {
+                Ok(_) => poll_hostname_task.set(poll_hostname(&device)),
+                Err(error) => {
+                    log::warn!("Lost connection to compute server {}: {}", device.addr, error);
+                    break;
+                }
+            },
+            recv_request = arena_rx.recv() => match recv_request {
+                None => break,
+                Some(request) => match request {
+                    Request::State(callback) => {
+                        let state = State {
+                            addr: device.addr,
+                            controllers: terminate_txs.keys().cloned().collect(),
+                        };
+                        let _ = callback.send(state);
+                    },
+                    Request::ExperimentStart{robot, software, flags, journal, callback} => {
+                        let result = match terminate_txs.contains_key(&robot) {
+                            true => Err(Error::AlreadyRunning(robot)),
+                            false => handle_experiment_start(robot, &device, software, flags, journal).await,
+                        };
+                        let _ = callback.send(result.map(|(controller, terminate_tx)| {
+                            terminate_txs.insert(robot, terminate_tx);
+                            controllers.push(controller);
+                        }));
+                    },
+                    Request::ExperimentStop => {
+                        for (_, terminate_tx) in terminate_txs.drain() {
+                            let _ = terminate_tx.send(());
+                        }
+                    },
+                }
+            }
+        }
+    }
+    uuid
+}
@@ -0,0 +1,121 @@
+use std::{collections::{HashMap, HashSet}, io, net::SocketAddr, time::Duration};
+use tokio::{io::{AsyncBufReadExt, AsyncReadExt, BufReader}, net::{TcpListener, TcpStream}, sync::mpsc};
+use uuid::Uuid;
+
+/// Port of the supervisor on which the robots and the compute servers connect to the tunnel
+pub const PORT: u16 = 4952;
+
+/* the time that a connection has to identify itself and the longest identification accepted */
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_LENGTH: u64 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum End {
+    /// ARGoS on the robot, which only runs the sensors and actuators
+    Body,
+    /// ARGoS on the compute server, which runs the controller
+    Brain,
+}
+
+type Connection = (SocketAddr, BufReader<TcpStream>);
+
+pub enum Request {
+    /// Set the robots whose ends are accepted, the waiting ends of other robots are dropped
+    Allow(HashSet<Uuid>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+async fn handshake(stream: &mut BufReader<TcpStream>) -> io::Result<(End, Uuid)> {
+    let mut line = String::new();
+    (&mut *stream).take(HANDSHAKE_LENGTH).read_line(&mut line).await?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+        format!("Invalid handshake {:?}", line.trim()));
+    let mut words = line.split_whitespace();
+    let end = match words.next() {
+        Some("body") => End::Body,
+        Some("brain") => End::Brain,
+        _ => return Err(invalid()),
+    };
+    let robot = words.next()
+        .and_then(|robot| Uuid::parse_str(robot).ok())
+        .ok_or_else(invalid)?;
+    Ok((end, robot))
+}
+
+/// Tunnels the sensor readings and actuator commands between a robot in the remote brain mode
+/// and its controller on a compute server. Both ends connect to the supervisor and identify
+/// themselves with a line `body <robot>` or `brain <robot>`, after which the traffic between
+/// them is copied until either end disconnects. Only robots that have been assigned a remote
+/// brain are accepted.
+pub async fn new(addr: SocketAddr, mut requests: Receiver) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            log::error!("Could not listen for remote brains on {}: {}", addr, error);
+            return;
+        }
+    };
+    let (identified_tx, mut identified_rx) = mpsc::unbounded_channel();
+    let mut allowed: HashSet<Uuid> = HashSet::new();
+    /* the ends that are waiting for the other end of their tunnel */
+    let mut waiting: HashMap<(Uuid, End), Connection> = HashMap::new();
+    loop {
+        tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, peer)) => {
+                    let identified_tx = identified_tx.clone();
+                    /* identify the connection without holding up the other connections */
+                    tokio::spawn(async move {
+                        let mut stream = BufReader::new(stream);
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream)).await {
+                            Ok(Ok((end, robot))) => {
+                                let _ = identified_tx.send((robot, end, (peer, stream)));
+                            },
+                            Ok(Err(error)) => log::warn!("Could not tunnel {}: {}", peer, error),
+                            Err(_) => log::warn!("Could not tunnel {}: no handshake", peer),
+                        }
+                    });
+                },
+                Err(error) => log::error!("Error accepting incoming connection: {}", error),
+            },
+            Some((robot, end, connection)) = identified_rx.recv() => {
+                if !allowed.contains(&robot) {
+                    log::warn!("Refused to tunnel robot {} from {}: no remote brain is assigned",
+                        robot, connection.0);
+                    continue;
+                }
+                let other = match end {
+                    End::Body => End::Brain,
+                    End::Brain => End::Body,
+                };
+                match waiting.remove(&(robot, other)) {
+                    Some(other) => {
+                        tokio::spawn(relay(robot, connection, other));
+                    },
+                    /* an end that reconnects replaces the end that was waiting */
+                    None => {
+                        waiting.insert((robot, end), connection);
+                    }
+                }
+            },
+            request = requests.recv() => match request {
+                Some(Request::Allow(robots)) => {
+                    allowed = robots;
+                    waiting.retain(|(robot, _), _| allowed.contains(robot));
+                },
+                None => break,
+            },
+        }
+    }
+}
+
+async fn relay(robot: Uuid, (a_peer, mut a): Connection, (b_peer, mut b): Connection) {
+    log::info!("Tunnelling robot {} between {} and {}", robot, a_peer, b_peer);
+    match tokio::io::copy_bidirectional(&mut a, &mut b).await {
+        Ok((a_to_b, b_to_a)) => log::info!("Closed tunnel of robot {} after {} and {} bytes",
+            robot, a_to_b, b_to_a),
+        Err(error) => log::warn!("Tunnel of robot {} failed: {}", robot, error),
+    }
+}
@@ -0,0 +1,287 @@
+use std::{fs::File, io::BufReader, net::{Ipv4Addr, SocketAddr}, path::{Path, PathBuf}, sync::Arc};
+use futures::Future;
+use serde::Deserialize;
+use tokio::{signal::unix::{signal, SignalKind}, sync::{mpsc, watch}};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("{0} must be between zero and a week in seconds, not {1}")]
+    InvalidDuration(&'static str, f64),
+    #[error("pipuck_battery_divider must be positive, not {0}")]
+    InvalidBatteryDivider(f32),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The longest duration in seconds that is accepted in the configuration
+const MAX_DURATION: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+fn check_duration(name: &'static str, seconds: f64) -> Result<()> {
+    match seconds >= 0.0 && seconds <= MAX_DURATION {
+        true => Ok(()),
+        false => Err(Error::InvalidDuration(name, seconds)),
+    }
+}
+
+/// The parts of the configuration that can be reloaded while the supervisor is running
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Address on which the web interface is served, only read when the supervisor starts
+    pub webui_address: SocketAddr,
+    /// Address on which the message router listens, only read when the supervisor starts
+    pub router_address: SocketAddr,
+    /// Ranges of addresses that are probed in addition to the networks given on the command line,
+    /// optionally with the type of the devices on them, e.g., "10.0.1.0/24=xbee"
+    pub networks: Vec<crate::network::Subnet>,
+    /// Whether devices that announce the _fernbedienung._tcp or _xbee._udp services over mDNS
+    /// are discovered, only read when the supervisor starts
+    pub mdns_discovery: bool,
+    /// Addresses, networks, and MAC address prefixes of the devices that are probed or never
+    /// probed, e.g., to keep the supervisor away from printers, which can be replaced at runtime
+    pub discovery_filter: crate::network::filter::Filter,
+    /// Maximum number of addresses that are probed concurrently, overrides --probe-concurrency
+    pub max_probe_concurrency: Option<usize>,
+    /// Actions that are offered on the cards of the Pi-Pucks and the drones, in the order in which
+    /// they are shown, with the permission they require and how they are guarded
+    pub pipuck_actions: Vec<crate::robot::action::Definition<crate::robot::pipuck::Action>>,
+    pub drone_actions: Vec<crate::robot::action::Definition<crate::robot::drone::Action>>,
+    /// Hostnames of the devices that are classified as Pi-Pucks
+    pub pipuck_hostnames: Vec<String>,
+    /// Hostnames of the devices that are classified as the UP Core of a drone
+    pub upcore_hostnames: Vec<String>,
+    /// Hostnames of the devices that are classified as compute servers
+    pub compute_hostnames: Vec<String>,
+    /// Link strength (dBm) below which a Pi-Puck is reported as degraded
+    pub pipuck_degraded_link_strength: i32,
+    /// Ratio of the voltage divider through which the extension board of a Pi-Puck measures its
+    /// battery, the measured voltage is multiplied by it
+    pub pipuck_battery_divider: f32,
+    /// Charge (percent) of the battery below which a robot is shown with a warning
+    pub low_battery: f32,
+    /// Polling of the signal strength of the links to the robots
+    pub link_monitor: crate::link::Settings,
+    /// Charging docks that are polled for their status
+    pub docks: Vec<crate::dock::Dock>,
+    /// Refuse to start an experiment while any of the robots are charging
+    pub refuse_charging_robots: bool,
+    /// Devices in the lab that can be controlled during an experiment
+    pub environment: Vec<crate::environment::Device>,
+    /// Connection to the NatNet server of the Optitrack system
+    pub optitrack: crate::optitrack::Connection,
+    /// Filtering of the tracking data from the Optitrack system
+    pub tracking_filter: crate::optitrack::Filter,
+    /// Distribution of the filtered poses to the robots for virtual sensing, disabled if not given
+    pub virtual_sensing: Option<crate::sensing::Settings>,
+    /// Origin of the timestamps in the journals of the runs, the start of the run by default
+    pub journal_epoch: crate::clock::Epoch,
+    /// Directory in which the journal spools events when it falls behind, this should be on
+    /// another disk than the working directory, which holds the journals
+    pub journal_spool: PathBuf,
+    /// Record the markers of the tracking system in the journal for solving the rigid bodies offline
+    pub record_markers: bool,
+    /// Seconds of tracking data that the journal keeps while no experiment is running, which can
+    /// be saved for review from the Optitrack tab, disabled if not given
+    pub tracking_recording: Option<f64>,
+    /// Hardware switch that must be engaged before starting experiments with drones
+    pub arming_switch: Option<crate::arming::Switch>,
+    /// Tracking requirements for starting and running experiments with drones, disabled if not given
+    pub drone_interlock: Option<crate::interlock::Interlock>,
+    /// Regions of the arena for which the entering and leaving rigid bodies are journaled
+    pub regions: Vec<crate::region::Region>,
+    /// Named target poses of the Pi-Pucks, e.g., the initial positions of an experiment
+    pub formations: Vec<crate::formation::Formation>,
+    /// Schemas for decoding the messages sent between robots
+    pub schemas: crate::schema::Registry,
+    /// Number of robots that run a canary experiment
+    pub canary_robots: usize,
+    /// Seconds that the canary robots must run without crashing
+    pub canary_duration: f64,
+    /// Refuse to start an experiment unless its software has passed a canary experiment
+    pub require_canary: bool,
+    /// Licenses, contributors, and other metadata of the data packages exported from the runs
+    pub data_package: crate::package::Metadata,
+    /// Profiles that can be chosen when exporting a run to strip the addresses, the hostnames,
+    /// the operators, or the camera imagery from its data package
+    pub redaction_profiles: Vec<crate::redact::Profile>,
+    /// Settings for emailing a periodic digest of the testbed activities
+    pub digest: Option<crate::digest::Digest>,
+    /// Settings for pushing critical alerts to the devices of the operators
+    pub push: Option<crate::push::Settings>,
+    /// Permissions of the web interface clients, all clients have all permissions if not given
+    pub auth: Option<crate::auth::Auth>,
+    /// Settings for provisioning freshly flashed robots
+    pub provisioning: Option<crate::provision::Provisioning>,
+    /// Robots that are expected to be part of the fleet
+    pub fleet: Vec<crate::fleet::Group>,
+    /// Names of the robots that must be connected to start an experiment
+    pub required_robots: Vec<String>,
+    /// Failures that are injected for testing the recovery of the supervisor, disabled if not given
+    pub chaos: Option<crate::chaos::Chaos>,
+    /// Encryption of the connections to the fernbedienung service on the robots, plaintext if not given
+    pub fernbedienung_tls: Option<crate::network::fernbedienung::Tls>,
+    /// Timeouts of the requests to the fernbedienung service, these apply to the devices that
+    /// connect after they are changed
+    pub fernbedienung_timeouts: crate::network::fernbedienung::Timeouts,
+    /// Signal, e.g., USR1, that is sent to ARGoS when the verbosity of the logging on a robot is
+    /// changed. Only set this if the controllers handle the signal since it terminates ARGoS
+    /// otherwise.
+    pub argos_log_level_signal: Option<String>,
+    /// Environment variables, niceness, and resource limits of ARGoS on the Pi-Pucks and the
+    /// drones, these require a version of the fernbedienung service that supports them
+    pub argos_launch: crate::robot::staging::Launch,
+    /// Terminate the processes that were orphaned on a robot when it reconnects, this requires a
+    /// version of the fernbedienung service that supports the reap request
+    pub reap_orphans: bool,
+    /// Allow the controllers of the Pi-Pucks and the drones to run on compute servers, this
+    /// requires builds of ARGoS whose hardware plugins support the --body and --brain options
+    pub remote_brains: bool,
+    /// Keys that are authorized to sign software, software does not need to be signed if not given
+    pub signing: Option<crate::software::Signing>,
+    /// Limits on the uploaded software and on the journals of the runs
+    pub quotas: crate::quota::Quotas,
+    /// Replication of the state of this supervisor to a standby supervisor, disabled if not given
+    pub replication: Option<crate::replication::Replication>,
+    /// Shell commands that are run on the supervisor host before and after each run
+    pub hooks: crate::hook::Hooks,
+    /// Throttle, duration, and expected feedback of the motor test of the drones
+    pub drone_motor_test: crate::robot::drone::motors::Settings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            webui_address: (Ipv4Addr::LOCALHOST, 3030).into(),
+            router_address: (Ipv4Addr::UNSPECIFIED, 4950).into(),
+            networks: Vec::new(),
+            mdns_discovery: true,
+            max_probe_concurrency: None,
+            discovery_filter: Default::default(),
+            pipuck_actions: crate::robot::action::default_pipuck_actions(),
+            drone_actions: crate::robot::action::default_drone_actions(),
+            pipuck_hostnames: vec!["raspberrypi0-wifi".to_owned(), "ToshibaLaptop".to_owned()],
+            upcore_hostnames: vec!["up-core".to_owned()],
+            compute_hostnames: Vec::new(),
+            pipuck_degraded_link_strength: -80,
+            pipuck_battery_divider: 2.0,
+            low_battery: 20.0,
+            link_monitor: Default::default(),
+            docks: Vec::new(),
+            refuse_charging_robots: false,
+            environment: Vec::new(),
+            optitrack: Default::default(),
+            tracking_filter: Default::default(),
+            virtual_sensing: None,
+            journal_epoch: Default::default(),
+            journal_spool: std::env::temp_dir().join("mns-supervisor"),
+            record_markers: false,
+            tracking_recording: None,
+            arming_switch: None,
+            drone_interlock: None,
+            regions: Vec::new(),
+            formations: Vec::new(),
+            schemas: Default::default(),
+            canary_robots: 1,
+            canary_duration: 30.0,
+            require_canary: false,
+            data_package: Default::default(),
+            redaction_profiles: Vec::new(),
+            digest: None,
+            push: None,
+            auth: None,
+            provisioning: None,
+            fleet: Vec::new(),
+            required_robots: Vec::new(),
+            chaos: None,
+            fernbedienung_tls: None,
+            fernbedienung_timeouts: Default::default(),
+            argos_log_level_signal: None,
+            argos_launch: Default::default(),
+            reap_orphans: false,
+            remote_brains: false,
+            signing: None,
+            quotas: Default::default(),
+            replication: None,
+            hooks: Default::default(),
+            drone_motor_test: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let reader = BufReader::new(File::open(path)?);
+        let config: Config = serde_json::from_reader(reader)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /* durations that are given in seconds are converted with Duration::from_secs_f64, which
+       panics if they are negative, not a number, or too large, and the battery of a Pi-Puck
+       can only be measured through a divider with a positive ratio */
+    fn validate(&self) -> Result<()> {
+        check_duration("canary_duration", self.canary_duration)?;
+        check_duration("tracking_filter.max_occlusion", self.tracking_filter.max_occlusion)?;
+        if let Some(tracking_recording) = self.tracking_recording {
+            check_duration("tracking_recording", tracking_recording)?;
+        }
+        check_duration("fernbedienung_timeouts.heartbeat", self.fernbedienung_timeouts.heartbeat)?;
+        if let Some(replication) = self.replication.as_ref() {
+            check_duration("replication.interval", replication.interval)?;
+            check_duration("replication.failover_timeout", replication.failover_timeout)?;
+        }
+        match self.pipuck_battery_divider > 0.0 {
+            true => Ok(()),
+            false => Err(Error::InvalidBatteryDivider(self.pipuck_battery_divider)),
+        }
+    }
+}
+
+pub type Receiver = watch::Receiver<Arc<Config>>;
+/// Requests that the configuration is reloaded from disk
+pub type ReloadSender = mpsc::UnboundedSender<()>;
+
+/// Loads the configuration and returns a task that reloads it on SIGHUP or when requested
+pub fn new(path: Option<PathBuf>) -> Result<(Receiver, ReloadSender, impl Future<Output = Result<()>>)> {
+    let config = match path {
+        Some(ref path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let (config_tx, config_rx) = watch::channel(Arc::new(config));
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel();
+    let mut hangup = signal(SignalKind::hangup())?;
+    let task = async move {
+        loop {
+            tokio::select! {
+                Some(_) = hangup.recv() => log::info!("Received SIGHUP"),
+                Some(_) = reload_rx.recv() => {},
+                else => break,
+            }
+            let path = match path {
+                Some(ref path) => path,
+                None => {
+                    log::warn!("Configuration was not loaded from a file and cannot be reloaded");
+                    continue;
+                }
+            };
+            match Config::load(path) {
+                Ok(config) => {
+                    if config != **config_tx.borrow() {
+                        log::info!("Reloaded configuration from {}", path.display());
+                        if let Err(_) = config_tx.send(Arc::new(config)) {
+                            break;
+                        }
+                    }
+                }
+                /* keep the current configuration if the new one is invalid */
+                Err(error) => log::error!("Could not reload configuration from {}: {}", path.display(), error),
+            }
+        }
+        Ok(())
+    };
+    Ok((config_rx, reload_tx, task))
+}
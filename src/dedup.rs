@@ -0,0 +1,52 @@
+use std::{collections::HashMap, time::Duration};
+use serde::Serialize;
+use tokio::{sync::oneshot, time::Instant};
+use uuid::Uuid;
+
+/// Time for which the identifier of a request is remembered, a client that resends a request
+/// within this window receives the outcome of the original request instead of repeating it
+const WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The original request is still being authorized
+    Pending,
+    /// The original request was forwarded to the arena
+    Accepted,
+    /// The original request was refused, e.g., because another client holds the lock
+    Refused,
+}
+
+pub enum Request {
+    /* claim an identifier, the callback receives the outcome of the original request if the
+       identifier has already been claimed and None otherwise */
+    Claim(Uuid, oneshot::Sender<Option<Outcome>>),
+    Complete(Uuid, Outcome),
+}
+
+/// The outcomes of the requests that carried an identifier by identifier
+#[derive(Default)]
+pub struct Requests {
+    outcomes: HashMap<Uuid, (Instant, Outcome)>,
+}
+
+impl Requests {
+    pub fn handle(&mut self, request: Request) {
+        self.outcomes.retain(|_, (claimed_at, _)| claimed_at.elapsed() <= WINDOW);
+        match request {
+            Request::Claim(id, callback) => {
+                let original = self.outcomes.get(&id).map(|(_, outcome)| *outcome);
+                if original.is_none() {
+                    self.outcomes.insert(id, (Instant::now(), Outcome::Pending));
+                }
+                let _ = callback.send(original);
+            },
+            Request::Complete(id, outcome) => {
+                if let Some((_, pending)) = self.outcomes.get_mut(&id) {
+                    *pending = outcome;
+                }
+            },
+        }
+    }
+}
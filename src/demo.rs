@@ -0,0 +1,212 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::arena;
+use crate::optitrack;
+use crate::telemetry;
+
+/// Interval at which the synthetic telemetry of the virtual robots is recorded
+const TELEMETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The scenario has a keyframe at {0}s that is before the previous keyframe")]
+    UnorderedKeyframe(f64),
+    #[error("The frame rate of the scenario must be positive")]
+    InvalidFrameRate,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A rigid body in a keyframe, in the frame of the tracking system
+#[derive(Clone, Debug, Deserialize)]
+pub struct Body {
+    pub id: i32,
+    pub position: [f32; 3],
+    /// Orientation as a quaternion (w, x, y, z)
+    #[serde(default = "identity")]
+    pub orientation: [f32; 4],
+}
+
+fn identity() -> [f32; 4] {
+    [1.0, 0.0, 0.0, 0.0]
+}
+
+/// The rigid bodies at a point in time of the recording, the rigid bodies are interpolated
+/// between the keyframes
+#[derive(Clone, Debug, Deserialize)]
+pub struct Keyframe {
+    /// Seconds since the start of the scenario
+    pub time: f64,
+    pub bodies: Vec<Body>,
+}
+
+/// A metric that is recorded for each virtual robot as a sine wave around a mean, the phase is
+/// derived from the robot so that the robots do not report identical values
+#[derive(Clone, Debug, Deserialize)]
+pub struct Metric {
+    pub name: String,
+    pub mean: f32,
+    #[serde(default)]
+    pub amplitude: f32,
+    /// Seconds for a full cycle
+    #[serde(default = "default_period")]
+    pub period: f64,
+}
+
+fn default_period() -> f64 {
+    60.0
+}
+
+/// A canned scenario that is played back without any hardware, e.g., for presentations, for
+/// developing the web interface, or for teaching the interface to new lab members
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    /// Number of Pi-Pucks and drones that are simulated in addition to the virtual robots given
+    /// on the command line
+    pub pipucks: usize,
+    pub drones: usize,
+    /// Frames of tracking data played back per second
+    pub frame_rate: f64,
+    /// Recording of the rigid bodies that is played back in a loop
+    pub tracking: Vec<Keyframe>,
+    pub telemetry: Vec<Metric>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            pipucks: 0,
+            drones: 0,
+            frame_rate: 30.0,
+            tracking: Vec::new(),
+            telemetry: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Scenario> {
+        let contents = std::fs::read(path)?;
+        let scenario: Scenario = serde_json::from_slice(&contents)?;
+        if scenario.frame_rate.is_nan() || scenario.frame_rate <= 0.0 {
+            return Err(Error::InvalidFrameRate);
+        }
+        for (previous, next) in scenario.tracking.iter().zip(scenario.tracking.iter().skip(1)) {
+            if next.time < previous.time {
+                return Err(Error::UnorderedKeyframe(next.time));
+            }
+        }
+        Ok(scenario)
+    }
+
+    /* the rigid bodies at a time since the start of the scenario, wrapped around its duration */
+    fn frame(&self, time: f64) -> Vec<optitrack::Pose> {
+        let duration = self.tracking.last().map_or(0.0, |keyframe| keyframe.time);
+        let time = match duration > 0.0 {
+            true => time % duration,
+            false => 0.0,
+        };
+        let next = self.tracking.iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(self.tracking.len());
+        let (previous, next) = match next {
+            0 => match self.tracking.first() {
+                Some(first) => (first, first),
+                None => return Vec::new(),
+            },
+            next if next == self.tracking.len() => (&self.tracking[next - 1], &self.tracking[next - 1]),
+            next => (&self.tracking[next - 1], &self.tracking[next]),
+        };
+        let weight = match next.time > previous.time {
+            true => ((time - previous.time) / (next.time - previous.time)) as f32,
+            false => 0.0,
+        };
+        previous.bodies.iter()
+            .map(|body| {
+                /* bodies that are missing from the next keyframe stay where they are */
+                let (position, orientation) = match next.bodies.iter().find(|other| other.id == body.id) {
+                    Some(other) => (lerp(&body.position, &other.position, weight),
+                                    nlerp(&body.orientation, &other.orientation, weight)),
+                    None => (body.position, body.orientation),
+                };
+                optitrack::Pose { id: body.id, position, orientation, estimated: false, motion: None }
+            })
+            .collect()
+    }
+}
+
+fn lerp(from: &[f32; 3], to: &[f32; 3], weight: f32) -> [f32; 3] {
+    let mut position = [0.0; 3];
+    for axis in 0..3 {
+        position[axis] = from[axis] + weight * (to[axis] - from[axis]);
+    }
+    position
+}
+
+/* normalized linear interpolation of two quaternions along the shortest rotation */
+fn nlerp(from: &[f32; 4], to: &[f32; 4], weight: f32) -> [f32; 4] {
+    let dot = (0..4).map(|index| from[index] * to[index]).sum::<f32>();
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+    let mut orientation = [0.0; 4];
+    for index in 0..4 {
+        orientation[index] = from[index] + weight * (sign * to[index] - from[index]);
+    }
+    let norm = orientation.iter().map(|component| component * component).sum::<f32>().sqrt();
+    match norm > f32::EPSILON {
+        true => [orientation[0] / norm, orientation[1] / norm, orientation[2] / norm, orientation[3] / norm],
+        false => *from,
+    }
+}
+
+/// Plays back a scenario through the tracking task and records synthetic telemetry for the
+/// virtual robots so that the web interface can be used without any hardware
+pub async fn new(scenario: Scenario,
+                 arena_requests_tx: mpsc::UnboundedSender<arena::Request>,
+                 tracking_requests_tx: optitrack::Sender,
+                 telemetry_requests_tx: telemetry::Sender) {
+    let start = tokio::time::Instant::now();
+    let mut playback = tokio::time::interval(Duration::from_secs_f64(1.0 / scenario.frame_rate));
+    let mut telemetry = tokio::time::interval(TELEMETRY_INTERVAL);
+    log::info!("Playing back a demo scenario with {} keyframes", scenario.tracking.len());
+    loop {
+        tokio::select! {
+            _ = playback.tick(), if !scenario.tracking.is_empty() => {
+                let frame = scenario.frame(start.elapsed().as_secs_f64());
+                if let Err(_) = tracking_requests_tx.send(optitrack::Request::Playback(frame)) {
+                    break;
+                }
+            },
+            _ = telemetry.tick(), if !scenario.telemetry.is_empty() => {
+                let (callback_tx, callback_rx) = oneshot::channel();
+                if let Err(_) = arena_requests_tx.send(arena::Request::GetVirtualRobots(callback_tx)) {
+                    break;
+                }
+                let robots: HashMap<Uuid, _> = match callback_rx.await {
+                    Ok(robots) => robots,
+                    Err(_) => break,
+                };
+                let elapsed = start.elapsed().as_secs_f64();
+                for robot in robots.keys() {
+                    /* spread the phases of the robots over the cycle */
+                    let phase = robot.as_bytes()[0] as f64 / 256.0;
+                    for metric in &scenario.telemetry {
+                        let angle = std::f64::consts::TAU * (elapsed / metric.period.max(f64::EPSILON) + phase);
+                        let value = metric.mean + metric.amplitude * angle.sin() as f32;
+                        telemetry::record(&telemetry_requests_tx, *robot, &metric.name, value);
+                    }
+                }
+            },
+            /* nothing to play back */
+            else => futures::future::pending().await,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::{collections::BTreeSet, net::SocketAddr, path::Path, time::{Duration, SystemTime}};
+use serde::Deserialize;
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::TcpStream, sync::{mpsc, oneshot}};
+use uuid::Uuid;
+
+use crate::arena;
+use crate::config;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Operation timed out")]
+    Timeout,
+    #[error("SMTP server replied with {0}: {1}")]
+    SmtpError(u16, String),
+    #[error("Invalid reply from SMTP server")]
+    SmtpProtocolError,
+    #[error("Could not get the activity from the arena")]
+    ArenaRequestError,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    fn duration(&self) -> Duration {
+        match self {
+            Period::Daily => Duration::from_secs(24 * 60 * 60),
+            Period::Weekly => Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Settings for emailing a digest of the testbed activities
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Digest {
+    /// SMTP server that accepts mail from this machine without authentication
+    pub smtp: SocketAddr,
+    pub from: String,
+    pub to: Vec<String>,
+    pub period: Period,
+}
+
+/// What happened on the testbed since the last digest
+#[derive(Clone, Debug, Default)]
+pub struct Activity {
+    pub runs: Vec<SystemTime>,
+    /// Experiments that could not be started and the reason why
+    pub failures: Vec<(SystemTime, String)>,
+    /// Robots that were reported as degraded
+    pub degraded: BTreeSet<Uuid>,
+}
+
+impl Activity {
+    /// Puts back activity that was taken earlier, e.g., because its digest could not be sent
+    pub fn restore(&mut self, earlier: Activity) {
+        let runs = std::mem::replace(&mut self.runs, earlier.runs);
+        self.runs.extend(runs);
+        let failures = std::mem::replace(&mut self.failures, earlier.failures);
+        self.failures.extend(failures);
+        self.degraded.extend(earlier.degraded);
+    }
+}
+
+/* size of the journals in the working directory, i.e., where they are written */
+fn journal_usage(directory: &Path) -> std::io::Result<u64> {
+    let mut usage = 0;
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.path().extension().map_or(false, |extension| extension == "pkl") {
+            usage += entry.metadata()?.len();
+        }
+    }
+    Ok(usage)
+}
+
+fn compose(activity: &Activity, usage: Option<u64>, period: Period) -> String {
+    let since = |time: &SystemTime| time.elapsed()
+        .map_or("in the future".to_owned(), |elapsed| format!("{} hours ago", elapsed.as_secs() / 3600));
+    let mut body = format!("Testbed activity during the last {}:\r\n\r\n",
+        match period { Period::Daily => "day", Period::Weekly => "week" });
+    body.push_str(&format!("Runs performed: {}\r\n", activity.runs.len()));
+    for run in activity.runs.iter() {
+        body.push_str(&format!("  - started {}\r\n", since(run)));
+    }
+    body.push_str(&format!("\r\nFailures: {}\r\n", activity.failures.len()));
+    for (time, reason) in activity.failures.iter() {
+        body.push_str(&format!("  - {}: {}\r\n", since(time), reason));
+    }
+    body.push_str(&format!("\r\nRobots with degraded health: {}\r\n", activity.degraded.len()));
+    for robot in activity.degraded.iter() {
+        body.push_str(&format!("  - {}\r\n", robot));
+    }
+    body.push_str(&format!("\r\nDisk usage of the journals: {}\r\n", match usage {
+        Some(usage) => format!("{:.1} MB", usage as f64 / 1_000_000.0),
+        None => "unknown".to_owned(),
+    }));
+    body
+}
+
+/* reads a (possibly multiline) reply and checks that it has the expected code */
+async fn smtp_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R, expected: u16) -> Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(Error::SmtpProtocolError);
+        }
+        let code = line.get(0..3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or(Error::SmtpProtocolError)?;
+        /* the last line of a reply has a space after the code */
+        if line.get(3..4) != Some("-") {
+            return match code == expected {
+                true => Ok(()),
+                false => Err(Error::SmtpError(code, line.get(4..).unwrap_or("").trim().to_owned())),
+            };
+        }
+    }
+}
+
+async fn smtp_send(settings: &Digest, subject: &str, body: &str) -> Result<()> {
+    let stream = TcpStream::connect(settings.smtp).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    smtp_reply(&mut reader, 220).await?;
+    writer.write_all(b"HELO mns-supervisor\r\n").await?;
+    smtp_reply(&mut reader, 250).await?;
+    writer.write_all(format!("MAIL FROM:<{}>\r\n", settings.from).as_bytes()).await?;
+    smtp_reply(&mut reader, 250).await?;
+    for recipient in settings.to.iter() {
+        writer.write_all(format!("RCPT TO:<{}>\r\n", recipient).as_bytes()).await?;
+        smtp_reply(&mut reader, 250).await?;
+    }
+    writer.write_all(b"DATA\r\n").await?;
+    smtp_reply(&mut reader, 354).await?;
+    let mut message = format!("From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n",
+        settings.from, settings.to.join(", "), subject);
+    /* lines starting with a period are escaped by doubling the period */
+    for line in body.split("\r\n") {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message.push_str(".\r\n");
+    writer.write_all(message.as_bytes()).await?;
+    smtp_reply(&mut reader, 250).await?;
+    writer.write_all(b"QUIT\r\n").await?;
+    smtp_reply(&mut reader, 221).await?;
+    Ok(())
+}
+
+async fn send_digest(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                     settings: &Digest) -> Result<()> {
+    let (activity_tx, activity_rx) = oneshot::channel();
+    arena_request_tx.send(arena::Request::TakeActivity(activity_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let activity = activity_rx.await
+        .map_err(|_| Error::ArenaRequestError)?;
+    let usage = std::env::current_dir()
+        .and_then(|directory| journal_usage(&directory))
+        .ok();
+    let body = compose(&activity, usage, settings.period);
+    let subject = format!("MNS supervisor digest: {} runs, {} failures", activity.runs.len(), activity.failures.len());
+    let result = tokio::time::timeout(SMTP_TIMEOUT, smtp_send(settings, &subject, &body)).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|result| result);
+    /* keep the activity for the next digest if this one could not be sent */
+    if result.is_err() {
+        let _ = arena_request_tx.send(arena::Request::RestoreActivity(activity));
+    }
+    result
+}
+
+pub async fn new(arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+                 mut config: config::Receiver) {
+    loop {
+        let settings = config.borrow().digest.clone();
+        match settings {
+            /* wait until the digest is configured */
+            None => if config.changed().await.is_err() {
+                break;
+            },
+            Some(settings) => tokio::select! {
+                /* restart the period if the configuration changes */
+                result = config.changed() => if result.is_err() {
+                    break;
+                },
+                _ = tokio::time::sleep(settings.period.duration()) => {
+                    match send_digest(&arena_request_tx, &settings).await {
+                        Ok(_) => log::info!("Sent digest to {}", settings.to.join(", ")),
+                        Err(error) => log::error!("Could not send digest: {}", error),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{collections::{BTreeMap, HashSet}, net::Ipv4Addr, time::Duration};
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config;
+
+/// Period at which the charging docks are polled
+const POLL_PERIOD: Duration = Duration::from_secs(5);
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A charging dock (or a networked smart plug) that reports its status as JSON over HTTP
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Dock {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Status {
+    pub charging: bool,
+    /// Power drawn by the dock in watts
+    #[serde(default)]
+    pub power: Option<f32>,
+    /// Address of the robot that is docked, if reported by the dock
+    #[serde(default)]
+    pub robot: Option<Ipv4Addr>,
+}
+
+/// The status of each dock, or None if the dock could not be reached
+pub type Statuses = BTreeMap<String, Option<Status>>;
+
+pub enum Request {
+    Statuses(oneshot::Sender<Statuses>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/// Addresses of the robots that are currently charging
+pub fn charging(statuses: &Statuses) -> HashSet<Ipv4Addr> {
+    statuses.values()
+        .filter_map(|status| status.as_ref())
+        .filter(|status| status.charging)
+        .filter_map(|status| status.robot)
+        .collect()
+}
+
+async fn poll(client: &reqwest::Client, dock: &Dock) -> reqwest::Result<Status> {
+    client.get(&dock.url)
+        .timeout(POLL_TIMEOUT)
+        .send().await?
+        .json::<Status>().await
+}
+
+pub async fn new(mut requests_rx: Receiver, config: config::Receiver) {
+    let client = reqwest::Client::new();
+    let mut statuses = Statuses::new();
+    let mut interval = tokio::time::interval(POLL_PERIOD);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let docks = config.borrow().docks.clone();
+                statuses = docks.iter()
+                    .map(|dock| async {
+                        let status = match poll(&client, dock).await {
+                            Ok(status) => Some(status),
+                            Err(error) => {
+                                log::warn!("Could not poll dock {}: {}", dock.name, error);
+                                None
+                            }
+                        };
+                        (dock.name.clone(), status)
+                    })
+                    .collect::<FuturesUnordered<_>>()
+                    .collect::<Statuses>().await;
+            },
+            recv_request = requests_rx.recv() => match recv_request {
+                Some(Request::Statuses(callback)) => {
+                    let _ = callback.send(statuses.clone());
+                },
+                None => break,
+            }
+        }
+    }
+}
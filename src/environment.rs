@@ -0,0 +1,216 @@
+use std::{net::SocketAddr, time::Duration};
+use bytes::{BufMut, BytesMut};
+use futures::{future::{self, FutureExt}, Future};
+use serde::{Deserialize, Serialize};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::mpsc, time::Instant};
+
+use crate::config;
+use crate::journal;
+
+const DRIVER_TIMEOUT: Duration = Duration::from_secs(2);
+/// Latest time in seconds after the start of the experiment at which a step can be scheduled
+const MAX_STEP_AT: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not find device {0}")]
+    UnknownDevice(String),
+    #[error("Step for device {0} at {1} seconds is not within a week of the start of the experiment")]
+    InvalidStep(String, f64),
+    #[error("Operation timed out")]
+    Timeout,
+    #[error("MQTT broker refused the connection ({0})")]
+    MqttConnectionRefused(u8),
+    #[error("Invalid response from MQTT broker")]
+    MqttProtocolError,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A controllable piece of lab infrastructure, e.g., lights or a beacon emitter
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Device {
+    pub name: String,
+    #[serde(flatten)]
+    pub driver: Driver,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "driver")]
+pub enum Driver {
+    /// The value is sent as the JSON body of a POST request
+    Http { url: String },
+    /// The value is published as JSON to a topic
+    Mqtt { broker: SocketAddr, topic: String },
+}
+
+/// Sets a device to a value at a time relative to the start of the experiment
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Step {
+    /// Seconds after the start of the experiment
+    pub at: f64,
+    pub device: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Schedule(pub Vec<Step>);
+
+impl Schedule {
+    pub fn parse(contents: &[u8]) -> Result<Schedule> {
+        let mut steps: Vec<Step> = serde_json::from_slice(contents)?;
+        /* this also rejects steps that are not a number */
+        if let Some(step) = steps.iter().find(|step| !(step.at >= 0.0 && step.at <= MAX_STEP_AT)) {
+            return Err(Error::InvalidStep(step.device.clone(), step.at));
+        }
+        steps.sort_by(|left, right| left.at.partial_cmp(&right.at)
+            .unwrap_or(std::cmp::Ordering::Equal));
+        Ok(Schedule(steps))
+    }
+}
+
+pub enum Request {
+    Start(Schedule),
+    Stop,
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/* encodes the remaining length field of an MQTT packet */
+fn put_mqtt_length(buffer: &mut BytesMut, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        buffer.put_u8(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn put_mqtt_string(buffer: &mut BytesMut, string: &[u8]) {
+    buffer.put_u16(string.len() as u16);
+    buffer.put_slice(string);
+}
+
+/* connects to the broker, publishes a single message with QoS 0, and disconnects */
+async fn mqtt_publish(broker: SocketAddr, topic: &str, payload: &[u8]) -> Result<()> {
+    let mut stream = TcpStream::connect(broker).await?;
+    let mut buffer = BytesMut::new();
+    /* CONNECT with a clean session and a 60 second keep alive */
+    let mut variable = BytesMut::new();
+    put_mqtt_string(&mut variable, b"MQTT");
+    variable.put_u8(4);
+    variable.put_u8(0x02);
+    variable.put_u16(60);
+    put_mqtt_string(&mut variable, b"mns-supervisor");
+    buffer.put_u8(0x10);
+    put_mqtt_length(&mut buffer, variable.len());
+    buffer.put(variable);
+    stream.write_all(&buffer).await?;
+    /* CONNACK */
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack).await?;
+    if connack[0] != 0x20 || connack[1] != 0x02 {
+        return Err(Error::MqttProtocolError);
+    }
+    if connack[3] != 0 {
+        return Err(Error::MqttConnectionRefused(connack[3]));
+    }
+    /* PUBLISH followed by DISCONNECT */
+    let mut variable = BytesMut::new();
+    put_mqtt_string(&mut variable, topic.as_bytes());
+    variable.put_slice(payload);
+    buffer.clear();
+    buffer.put_u8(0x30);
+    put_mqtt_length(&mut buffer, variable.len());
+    buffer.put(variable);
+    buffer.put_slice(&[0xE0, 0x00]);
+    stream.write_all(&buffer).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn apply(client: &reqwest::Client, device: &Device, value: &serde_json::Value) -> Result<()> {
+    let driver = async {
+        match &device.driver {
+            Driver::Http { url } => {
+                client.post(url).json(value).send().await?.error_for_status()?;
+                Ok(())
+            },
+            Driver::Mqtt { broker, topic } => {
+                let payload = serde_json::to_vec(value)?;
+                mqtt_publish(*broker, topic, &payload).await
+            },
+        }
+    };
+    tokio::time::timeout(DRIVER_TIMEOUT, driver).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|result| result)
+}
+
+fn run<'a>(schedule: Schedule,
+           client: &'a reqwest::Client,
+           config: &'a config::Receiver,
+           journal: &'a journal::Sender) -> impl Future<Output = ()> + 'a {
+    async move {
+        let start = Instant::now();
+        for step in schedule.0 {
+            /* schedules are validated when they are parsed, but cloned runs restore them as they
+               were recorded */
+            let at = Duration::from_secs_f64(step.at.max(0.0).min(MAX_STEP_AT));
+            tokio::time::sleep_until(start + at).await;
+            /* look up the device when the step is due so that reloaded configurations apply */
+            let device = config.borrow().environment.iter()
+                .find(|device| device.name == step.device)
+                .cloned();
+            let result = match device {
+                Some(device) => apply(client, &device, &step.value).await,
+                None => Err(Error::UnknownDevice(step.device.clone())),
+            };
+            match result {
+                Ok(_) => {
+                    log::info!("Set {} to {}", step.device, step.value);
+                    let event = journal::Event::Environment(step.device, step.value);
+                    if let Err(error) = journal.record(event) {
+                        log::warn!("Could not record environment change in journal: {}", error);
+                    }
+                },
+                Err(error) => log::error!("Could not set {} to {}: {}", step.device, step.value, error),
+            }
+        }
+    }
+}
+
+pub async fn new(mut requests_rx: Receiver, config: config::Receiver, journal: journal::Sender) {
+    let client = reqwest::Client::new();
+    let schedule_task = future::pending().left_future();
+    tokio::pin!(schedule_task);
+    loop {
+        tokio::select! {
+            _ = &mut schedule_task => {
+                schedule_task.set(future::pending().left_future());
+            },
+            recv_request = requests_rx.recv() => match recv_request {
+                Some(Request::Start(schedule)) => {
+                    schedule_task.set(run(schedule, &client, &config, &journal).right_future());
+                },
+                Some(Request::Stop) => {
+                    schedule_task.set(future::pending().left_future());
+                },
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// The phases of an experiment on the testbed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Phase {
+    Standby,
+    /// A short experiment on a few robots that validates the software before the full experiment,
+    /// the canary fails if any of its robots stop running before the deadline
+    Canary {
+        robots: Vec<Uuid>,
+        deadline: Instant,
+    },
+    Active {
+        robots: Vec<Uuid>,
+    },
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Standby
+    }
+}
+
+/// What happened on the testbed, as observed by the arena
+pub enum Event<'a> {
+    /// The operator requested a canary experiment or a full experiment
+    StartRequested {
+        canary: bool,
+    },
+    /// The robots, the drones, or the hooks refused to start the requested experiment
+    StartFailed(String),
+    CanaryStarted {
+        robots: Vec<Uuid>,
+        deadline: Instant,
+    },
+    ExperimentStarted {
+        robots: Vec<Uuid>,
+    },
+    StopRequested,
+    /// The operator aborted the experiment, either preserving or discarding its partial results
+    AbortRequested {
+        discard: bool,
+    },
+    ShutdownRequested,
+    /// Periodic check of the robots of a canary experiment with the robots that transitioned out
+    /// of running since the last check
+    Tick {
+        now: Instant,
+        stopped: &'a [Uuid],
+    },
+    /// The drones were no longer tracked as required during the experiment
+    InterlockTripped(String),
+    /// A robot connected to the arena, which may happen while an experiment is running
+    RobotDiscovered(Uuid),
+    RobotDegraded {
+        uuid: Uuid,
+        drone: bool,
+    },
+    DroneLost(Uuid),
+    /// The processor of a robot started being throttled to reduce its temperature
+    RobotThrottled(Uuid),
+}
+
+/// What the arena must do in response to an event, in order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Arm the drones and start the controllers on a few of the robots
+    StartCanary,
+    /// Check the software and the fleet, arm the drones, and start the controllers on all robots
+    StartExperiment,
+    /// Stop the controllers on all robots and the journal
+    StopRobots,
+    /// Kill the controllers on all robots, removing their results, and discard the journal
+    KillRobots,
+    StopEnvironment,
+    /// Stop checking that the drones remain tracked
+    ClearInterlock,
+    LandDrones,
+    /// Push an alert to the operators
+    Alert(String),
+    /// Log and record a failure in the activity digest
+    Fail(String),
+    /// Return to the software of the last experiment that was validated or started
+    RollBackSoftware,
+    /// Mark the current software as having passed a canary experiment
+    ValidateSoftware,
+    /// Collect the results of the run from the robots
+    HarvestResults,
+    /// Mark the run as aborted in the history
+    RecordAbort,
+    /// Remove the run from the history and the activity, as if it never started
+    DiscardRun,
+    /// Note in the run that the processor of a robot was throttled during the experiment
+    RecordThrottling(Uuid),
+}
+
+/// The state transitions of an experiment. This has no side effects: the arena feeds it the
+/// events that it observes and carries out the commands that are returned.
+#[derive(Debug, Default)]
+pub struct Experiment {
+    phase: Phase,
+}
+
+impl Experiment {
+    pub fn phase(&self) -> &Phase {
+        &self.phase
+    }
+
+    pub fn is_standby(&self) -> bool {
+        self.phase == Phase::Standby
+    }
+
+    /* whether a robot was started by the running experiment */
+    fn takes_part(&self, uuid: &Uuid) -> bool {
+        match &self.phase {
+            Phase::Standby => false,
+            Phase::Canary { robots, .. } | Phase::Active { robots } => robots.contains(uuid),
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) -> Vec<Command> {
+        match event {
+            Event::StartRequested { canary } => match (self.is_standby(), canary) {
+                (true, true) => vec![Command::StartCanary],
+                (true, false) => vec![Command::StartExperiment],
+                (false, _) => vec![Command::Fail("Could not start experiment: an experiment is already running".to_owned())],
+            },
+            /* the drones may have been armed before the robots refused to start */
+            Event::StartFailed(error) => vec![Command::ClearInterlock, Command::Fail(error)],
+            Event::CanaryStarted { robots, deadline } => {
+                self.phase = Phase::Canary { robots, deadline };
+                Vec::new()
+            },
+            Event::ExperimentStarted { robots } => {
+                self.phase = Phase::Active { robots };
+                Vec::new()
+            },
+            Event::StopRequested => {
+                let active = matches!(self.phase, Phase::Active { .. });
+                self.phase = Phase::Standby;
+                let mut commands = vec![Command::StopRobots, Command::ClearInterlock, Command::StopEnvironment];
+                if active {
+                    commands.push(Command::HarvestResults);
+                }
+                commands
+            },
+            /* a canary experiment has no run to preserve or discard, so it is stopped as usual */
+            Event::AbortRequested { discard } => match self.phase {
+                Phase::Standby => Vec::new(),
+                Phase::Canary { .. } => self.handle(Event::StopRequested),
+                Phase::Active { .. } => {
+                    self.phase = Phase::Standby;
+                    match discard {
+                        true => vec![
+                            Command::KillRobots,
+                            Command::ClearInterlock,
+                            Command::StopEnvironment,
+                            Command::DiscardRun,
+                        ],
+                        false => vec![
+                            Command::StopRobots,
+                            Command::ClearInterlock,
+                            Command::StopEnvironment,
+                            Command::HarvestResults,
+                            Command::RecordAbort,
+                        ],
+                    }
+                },
+            },
+            Event::ShutdownRequested => match self.is_standby() {
+                true => Vec::new(),
+                false => {
+                    self.phase = Phase::Standby;
+                    vec![Command::StopRobots, Command::StopEnvironment, Command::ClearInterlock]
+                }
+            },
+            /* a robot that has not yet reported that it is running has not crashed */
+            Event::Tick { now, stopped } => {
+                let (robots, deadline) = match &self.phase {
+                    Phase::Canary { robots, deadline } => (robots, *deadline),
+                    _ => return Vec::new(),
+                };
+                let crashed = stopped.iter()
+                    .find(|uuid| robots.contains(uuid))
+                    .cloned();
+                if crashed.is_none() && now < deadline {
+                    return Vec::new();
+                }
+                self.phase = Phase::Standby;
+                match crashed {
+                    Some(uuid) => vec![
+                        Command::StopRobots,
+                        Command::Fail(format!("Canary robot {} stopped running", uuid)),
+                        Command::RollBackSoftware,
+                    ],
+                    None => vec![Command::StopRobots, Command::ValidateSoftware],
+                }
+            },
+            Event::InterlockTripped(error) => {
+                /* the interlock only applies while the drones are flying */
+                if self.is_standby() {
+                    return Vec::new();
+                }
+                let active = matches!(self.phase, Phase::Active { .. });
+                self.phase = Phase::Standby;
+                let mut commands = vec![
+                    Command::Alert(format!("Drones landed, tracking interlock tripped: {}", error)),
+                    Command::LandDrones,
+                    Command::StopRobots,
+                    Command::StopEnvironment,
+                    Command::Fail(format!("Tracking interlock tripped: {}", error)),
+                    Command::ClearInterlock,
+                ];
+                if active {
+                    commands.push(Command::HarvestResults);
+                }
+                commands
+            },
+            /* a robot that connects during an experiment was not started and is left alone */
+            Event::RobotDiscovered(uuid) => match self.is_standby() {
+                true => Vec::new(),
+                false => vec![Command::Alert(format!("Robot {} connected during the experiment and does not take part in it", uuid))],
+            },
+            /* a drone that loses its link during an experiment may need intervention */
+            Event::RobotDegraded { uuid, drone } => match drone && self.takes_part(&uuid) {
+                true => vec![Command::Alert(format!("Link to drone {} degraded during the experiment", uuid))],
+                false => Vec::new(),
+            },
+            Event::DroneLost(uuid) => match self.takes_part(&uuid) {
+                false => Vec::new(),
+                true => vec![Command::Alert(format!("Link to drone {} lost during the experiment", uuid))],
+            },
+            /* a throttled robot silently misses the deadlines of its controller */
+            Event::RobotThrottled(uuid) => match (&self.phase, self.takes_part(&uuid)) {
+                (Phase::Standby, _) | (_, false) => Vec::new(),
+                (Phase::Canary { .. }, true) =>
+                    vec![Command::Alert(format!("Robot {} is thermally throttling during the canary experiment", uuid))],
+                (Phase::Active { .. }, true) => vec![
+                    Command::Alert(format!("Robot {} is thermally throttling during the experiment", uuid)),
+                    Command::RecordThrottling(uuid),
+                ],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /* an experiment that started on the given robots */
+    fn active(robots: &[Uuid]) -> Experiment {
+        let mut experiment = Experiment::default();
+        assert_eq!(experiment.handle(Event::StartRequested { canary: false }), vec![Command::StartExperiment]);
+        assert!(experiment.handle(Event::ExperimentStarted { robots: robots.to_vec() }).is_empty());
+        experiment
+    }
+
+    /* a canary experiment that started on the given robots and ends after ten seconds */
+    fn canary(robots: &[Uuid]) -> Experiment {
+        let mut experiment = Experiment::default();
+        assert_eq!(experiment.handle(Event::StartRequested { canary: true }), vec![Command::StartCanary]);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert!(experiment.handle(Event::CanaryStarted { robots: robots.to_vec(), deadline }).is_empty());
+        experiment
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn canary_passes_at_deadline() {
+        let robot = Uuid::new_v4();
+        let mut experiment = canary(&[robot]);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(experiment.handle(Event::Tick { now: Instant::now(), stopped: &[] }).is_empty());
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(experiment.handle(Event::Tick { now: Instant::now(), stopped: &[] }),
+            vec![Command::StopRobots, Command::ValidateSoftware]);
+        assert!(experiment.is_standby());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn canary_fails_when_robot_stops() {
+        let robot = Uuid::new_v4();
+        let mut experiment = canary(&[robot]);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let commands = experiment.handle(Event::Tick { now: Instant::now(), stopped: &[robot] });
+        assert_eq!(commands[0], Command::StopRobots);
+        assert!(matches!(commands[1], Command::Fail(_)));
+        assert_eq!(commands[2], Command::RollBackSoftware);
+        assert!(experiment.is_standby());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn start_is_refused_while_running() {
+        let mut experiment = active(&[]);
+        let commands = experiment.handle(Event::StartRequested { canary: true });
+        assert!(matches!(commands.as_slice(), [Command::Fail(_)]));
+        assert!(matches!(experiment.phase(), Phase::Active { .. }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_start_clears_interlock() {
+        let mut experiment = Experiment::default();
+        experiment.handle(Event::StartRequested { canary: false });
+        let commands = experiment.handle(Event::StartFailed("no robots".to_owned()));
+        assert_eq!(commands, vec![Command::ClearInterlock, Command::Fail("no robots".to_owned())]);
+        assert!(experiment.is_standby());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn robot_discovered_during_canary_does_not_take_part() {
+        let robot = Uuid::new_v4();
+        let late = Uuid::new_v4();
+        let mut experiment = canary(&[robot]);
+        assert!(matches!(experiment.handle(Event::RobotDiscovered(late)).as_slice(), [Command::Alert(_)]));
+        /* the late robot stopping is not a failure of the canary */
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(experiment.handle(Event::Tick { now: Instant::now(), stopped: &[late] }).is_empty());
+        assert!(experiment.handle(Event::DroneLost(late)).is_empty());
+        assert!(experiment.handle(Event::RobotThrottled(late)).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn robot_discovered_in_standby_is_silent() {
+        let mut experiment = Experiment::default();
+        assert!(experiment.handle(Event::RobotDiscovered(Uuid::new_v4())).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drone_lost_mid_run_alerts() {
+        let drone = Uuid::new_v4();
+        let mut experiment = active(&[drone]);
+        assert!(matches!(experiment.handle(Event::RobotDegraded { uuid: drone, drone: true }).as_slice(), [Command::Alert(_)]));
+        assert!(matches!(experiment.handle(Event::DroneLost(drone)).as_slice(), [Command::Alert(_)]));
+        /* the experiment continues without the drone */
+        assert!(matches!(experiment.phase(), Phase::Active { .. }));
+        let commands = experiment.handle(Event::StopRequested);
+        assert!(commands.contains(&Command::HarvestResults));
+        assert!(experiment.handle(Event::DroneLost(drone)).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttling_is_recorded_mid_run() {
+        let robot = Uuid::new_v4();
+        let mut experiment = active(&[robot]);
+        let commands = experiment.handle(Event::RobotThrottled(robot));
+        assert_eq!(commands[1], Command::RecordThrottling(robot));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interlock_lands_drones() {
+        let drone = Uuid::new_v4();
+        let mut experiment = active(&[drone]);
+        let commands = experiment.handle(Event::InterlockTripped("drone not tracked".to_owned()));
+        assert!(matches!(commands[0], Command::Alert(_)));
+        assert_eq!(commands[1], Command::LandDrones);
+        assert!(commands.contains(&Command::HarvestResults));
+        assert!(experiment.is_standby());
+        /* the interlock only trips once */
+        assert!(experiment.handle(Event::InterlockTripped("drone not tracked".to_owned())).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn interlock_during_canary_does_not_harvest() {
+        let mut experiment = canary(&[Uuid::new_v4()]);
+        let commands = experiment.handle(Event::InterlockTripped("drone not tracked".to_owned()));
+        assert_eq!(commands[1], Command::LandDrones);
+        assert!(!commands.contains(&Command::HarvestResults));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_discards_or_preserves_run() {
+        let mut experiment = active(&[]);
+        assert!(experiment.handle(Event::AbortRequested { discard: true }).contains(&Command::DiscardRun));
+        let mut experiment = active(&[]);
+        let commands = experiment.handle(Event::AbortRequested { discard: false });
+        assert!(commands.contains(&Command::RecordAbort));
+        assert!(commands.contains(&Command::HarvestResults));
+        assert!(experiment.handle(Event::AbortRequested { discard: false }).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_stops_running_experiment() {
+        let mut experiment = active(&[]);
+        assert!(experiment.handle(Event::ShutdownRequested).contains(&Command::StopRobots));
+        assert!(experiment.handle(Event::ShutdownRequested).is_empty());
+    }
+}
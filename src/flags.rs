@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::arena::Kind;
+use crate::network::fernbedienung;
+
+/// Prefix of the environment variables that deliver the flags to the controllers
+const VARIABLE_PREFIX: &str = "MNS_FLAG_";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid flag name {0}")]
+    InvalidName(String),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The flags of a single robot
+pub type Assignment = BTreeMap<String, String>;
+
+/// Flags that apply to a group of robots. A robot belongs to a group if it matches the kind
+/// and is one of the listed robots, where an empty list includes all robots.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Group {
+    pub name: String,
+    #[serde(default)]
+    pub kind: Option<Kind>,
+    #[serde(default)]
+    pub robots: Vec<Uuid>,
+    pub flags: Assignment,
+}
+
+impl Group {
+    fn contains(&self, robot: &Uuid, kind: Kind) -> bool {
+        self.kind.map_or(true, |group_kind| group_kind == kind) &&
+            (self.robots.is_empty() || self.robots.contains(robot))
+    }
+}
+
+/// Key/value flags that are delivered to the controllers when the experiment starts
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Flags {
+    /// Flags for all robots in the experiment
+    #[serde(default)]
+    pub experiment: Assignment,
+    /// Flags for groups of robots, later groups override earlier groups and the experiment
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    /// Broadcast changes to the flags to the controllers while an experiment is running
+    #[serde(default)]
+    pub live: bool,
+}
+
+impl Flags {
+    pub fn parse(contents: &[u8]) -> Result<Flags> {
+        let flags: Flags = serde_json::from_slice(contents)?;
+        /* the names of the flags must be usable as the names of environment variables */
+        let names = flags.experiment.keys()
+            .chain(flags.groups.iter().flat_map(|group| group.flags.keys()));
+        for name in names {
+            let valid = !name.is_empty() &&
+                !name.starts_with(|character: char| character.is_ascii_digit()) &&
+                name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_');
+            if !valid {
+                return Err(Error::InvalidName(name.clone()));
+            }
+        }
+        Ok(flags)
+    }
+
+    /// The flags of a robot after applying the flags of the groups that it belongs to
+    pub fn resolve(&self, robot: &Uuid, kind: Kind) -> Assignment {
+        let mut assignment = self.experiment.clone();
+        for group in self.groups.iter().filter(|group| group.contains(robot, kind)) {
+            assignment.extend(group.flags.clone());
+        }
+        assignment
+    }
+}
+
+/// The environment variables for an assignment, e.g., the flag `formation` becomes
+/// `MNS_FLAG_FORMATION`
+pub fn variables(assignment: &Assignment) -> Vec<(String, String)> {
+    assignment.iter()
+        .map(|(name, value)| (format!("{}{}", VARIABLE_PREFIX, name.to_uppercase()), value.clone()))
+        .collect()
+}
+
+/// Wraps a remote process with `env` so that it is started with the flags in its environment
+pub fn wrap(process: fernbedienung::Process, assignment: &Assignment) -> fernbedienung::Process {
+    if assignment.is_empty() {
+        return process;
+    }
+    let mut args = variables(assignment).into_iter()
+        .map(|(variable, value)| format!("{}={}", variable, value))
+        .collect::<Vec<_>>();
+    args.push(process.target.to_string_lossy().into_owned());
+    args.extend(process.args);
+    fernbedienung::Process {
+        target: "env".into(),
+        working_dir: process.working_dir,
+        args,
+        env: process.env,
+        nice: process.nice,
+        limits: process.limits,
+    }
+}
@@ -0,0 +1,71 @@
+use std::{collections::HashMap, time::SystemTime};
+use serde::Deserialize;
+
+use crate::arena::Kind;
+
+/// Robots that are expected to be part of the fleet, identified by the hostname of the
+/// Raspberry Pi of a Pi-Puck or of the UP Core of a drone, e.g., a range of names such as
+/// {"kind": "PiPuck", "names": "pipuck-01..pipuck-16"} or a single name such as "drone-01"
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Group {
+    pub kind: Kind,
+    pub names: String,
+}
+
+/// A robot of the fleet that is not connected
+#[derive(Clone, Debug)]
+pub struct Missing {
+    pub name: String,
+    pub kind: Kind,
+    /// When the robot was last connected since the supervisor started
+    pub last_seen: Option<SystemTime>,
+}
+
+/* splits a name into its prefix and the digits at its end */
+fn split_number(name: &str) -> (&str, &str) {
+    let digits = name.chars().rev().take_while(char::is_ascii_digit).count();
+    name.split_at(name.len() - digits)
+}
+
+/// Expands a range of names, e.g., pipuck-01..pipuck-16, the width of the first number is kept
+pub fn expand(names: &str) -> Vec<String> {
+    if let Some((first, last)) = names.split_once("..") {
+        let ((prefix, first), (last_prefix, last)) = (split_number(first.trim()), split_number(last.trim()));
+        /* the end of the range may also be given without its prefix, e.g., pipuck-01..16 */
+        if last_prefix.is_empty() || last_prefix == prefix {
+            if let (Ok(start), Ok(end)) = (first.parse::<usize>(), last.parse::<usize>()) {
+                return (start..=end)
+                    .map(|number| format!("{}{:0width$}", prefix, number, width = first.len()))
+                    .collect();
+            }
+        }
+        log::warn!("Could not expand the range of names {}", names);
+    }
+    vec![names.trim().to_owned()]
+}
+
+/// The names of the robots in the fleet and their kind
+pub fn members(groups: &[Group]) -> Vec<(String, Kind)> {
+    groups.iter()
+        .flat_map(|group| expand(&group.names).into_iter().map(move |name| (name, group.kind)))
+        .collect()
+}
+
+/// Checks whether a hostname belongs to a robot of the given kind in the fleet
+pub fn contains(groups: &[Group], kind: Kind, hostname: &str) -> bool {
+    members(groups).iter().any(|(name, member_kind)| *member_kind == kind && name == hostname)
+}
+
+/// The robots of the fleet that are not connected
+pub fn missing(groups: &[Group],
+               connected: &HashMap<uuid::Uuid, String>,
+               last_seen: &HashMap<String, SystemTime>) -> Vec<Missing> {
+    members(groups).into_iter()
+        .filter(|(name, _)| !connected.values().any(|connected| connected == name))
+        .map(|(name, kind)| Missing {
+            last_seen: last_seen.get(&name).cloned(),
+            name,
+            kind,
+        })
+        .collect()
+}
@@ -0,0 +1,123 @@
+use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
+
+use crate::optitrack::Pose;
+
+/// Distance (m) within which a robot is considered to be at its target
+const DEFAULT_TOLERANCE: f32 = 0.02;
+
+/// A named arrangement of the ground robots, e.g., the initial positions for an experiment, in
+/// the frame of the arena
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Formation {
+    pub name: String,
+    pub targets: Vec<Target>,
+    /// Distance (m) within which a robot is considered to be at its target
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
+}
+
+fn default_tolerance() -> f32 {
+    DEFAULT_TOLERANCE
+}
+
+/// The pose that the robot with a rigid body should take in the formation
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Target {
+    pub rigid_body: i32,
+    /// Position (m) in the x/y plane
+    pub position: (f32, f32),
+    /// Heading (rad) about the z axis, any heading is accepted if not given
+    #[serde(default)]
+    pub heading: Option<f32>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Action {
+    /// Overlay the formation on the tracked rigid bodies
+    Show,
+    Hide,
+    /// Record how far the robots are from their targets in the journal
+    Measure,
+    /// Send the targets to the repositioning controllers of the Pi-Pucks
+    Drive,
+    /// Stop the repositioning controllers of the Pi-Pucks
+    Stop,
+}
+
+/// How far a robot is from its target
+#[derive(Clone, Debug, Serialize)]
+pub struct Deviation {
+    pub rigid_body: i32,
+    /// Distance (m) in the x/y plane, None if the rigid body is not tracked
+    pub distance: Option<f32>,
+    /// Difference (rad) between the heading and the target heading, None if the rigid body is not
+    /// tracked or if the target does not have a heading
+    pub heading: Option<f32>,
+}
+
+/// The deviations of all robots in a formation at one point in time
+#[derive(Clone, Debug, Serialize)]
+pub struct Measurement {
+    pub formation: String,
+    pub deviations: Vec<Deviation>,
+    /// Number of robots within the tolerance of their targets
+    pub in_place: usize,
+}
+
+/// Heading (rad) of a pose about the z axis
+pub fn heading(pose: &Pose) -> f32 {
+    let [w, x, y, z] = pose.orientation;
+    (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z))
+}
+
+/* wraps an angle into [-pi, pi) */
+fn wrap(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+impl Formation {
+    /// The deviation of each robot from its target given the latest poses
+    pub fn measure(&self, poses: &[Pose]) -> Measurement {
+        let deviations = self.targets.iter()
+            .map(|target| {
+                let pose = poses.iter().find(|pose| pose.id == target.rigid_body);
+                Deviation {
+                    rigid_body: target.rigid_body,
+                    distance: pose.map(|pose| (pose.position[0] - target.position.0)
+                        .hypot(pose.position[1] - target.position.1)),
+                    heading: pose.zip(target.heading)
+                        .map(|(pose, target)| wrap(heading(pose) - target)),
+                }
+            })
+            .collect::<Vec<_>>();
+        let in_place = deviations.iter()
+            .filter(|deviation| deviation.distance.map_or(false, |distance| distance <= self.tolerance))
+            .count();
+        Measurement { formation: self.name.clone(), deviations, in_place }
+    }
+
+    /// The message for the repositioning controllers, each robot drives to the target of its
+    /// rigid body and ignores the formation if it does not have a target
+    pub fn message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "reposition": {
+                "formation": self.name,
+                "tolerance": self.tolerance,
+                "targets": self.targets.iter()
+                    .map(|target| serde_json::json!({
+                        "rigid_body": target.rigid_body,
+                        "x": target.position.0,
+                        "y": target.position.1,
+                        "heading": target.heading,
+                    }))
+                    .collect::<Vec<_>>(),
+            }
+        })
+    }
+}
+
+/// The message that stops the repositioning controllers
+pub fn stop_message() -> serde_json::Value {
+    serde_json::json!({ "reposition": { "stop": true } })
+}
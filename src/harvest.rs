@@ -0,0 +1,117 @@
+use std::{path::{Component, Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::network::fernbedienung;
+use crate::robot::staging;
+
+/// Directory in the working directory of the supervisor where the results of the runs are stored
+const RESULTS_DIR: &str = "results";
+
+/// The results collected from a robot after a run
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Report {
+    /// Files that were downloaded and whose checksums were verified, with the pattern they matched
+    pub files: Vec<(String, String)>,
+    /// Patterns that did not match any files on the robot
+    pub missing: Vec<String>,
+    /// Files or patterns that could not be collected and why
+    pub failed: Vec<(String, String)>,
+    /// Seconds that the clock of the robot was ahead of the clock of the supervisor
+    pub clock_offset: Option<f64>,
+}
+
+/// Reports of the robots by the uuid of the run and the uuid of the robot
+pub type Sender = mpsc::UnboundedSender<(Uuid, Uuid, Report)>;
+pub type Receiver = mpsc::UnboundedReceiver<(Uuid, Uuid, Report)>;
+
+/// Where the results of a run are stored, i.e., `results/<run>`
+pub fn run_dir(run: &Uuid) -> PathBuf {
+    Path::new(RESULTS_DIR).join(run.to_string())
+}
+
+/// Where the results of a robot are stored, i.e., `results/<run>/<robot>`
+pub fn destination(run: &Uuid, robot: &Uuid) -> PathBuf {
+    run_dir(run).join(robot.to_string())
+}
+
+fn unix_time() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64())
+}
+
+/* compares the clock of the robot with the clock of the supervisor at the middle of the round trip */
+async fn measure_clock_offset(device: &fernbedienung::Device) -> fernbedienung::Result<f64> {
+    let sent = unix_time();
+    let clock = device.clock().await?;
+    let received = unix_time();
+    Ok(clock - (sent + received) / 2.0)
+}
+
+/// Whether a path or pattern is relative to the working directory of ARGoS and cannot leave it
+pub fn is_within_working_dir(path: &Path) -> bool {
+    path.components().any(|component| matches!(component, Component::Normal(_))) &&
+        path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Downloads the files that match the patterns from the working directory of ARGoS on a robot,
+/// verifying their checksums, and stores them under the destination
+pub async fn collect(device: &fernbedienung::Device, patterns: Vec<String>, destination: PathBuf) -> Report {
+    let mut report = Report::default();
+    let (patterns, refused) : (Vec<_>, Vec<_>) = patterns.into_iter()
+        .partition(|pattern| is_within_working_dir(Path::new(pattern)));
+    for pattern in refused {
+        report.failed.push((pattern, "Not relative to the working directory of ARGoS".to_owned()));
+    }
+    if patterns.is_empty() {
+        return report;
+    }
+    match measure_clock_offset(device).await {
+        Ok(offset) => report.clock_offset = Some(offset),
+        Err(error) => log::warn!("Could not measure the clock offset of {}: {}", device.addr, error),
+    }
+    let found = match device.find_files(staging::working_dir(), patterns.clone()).await {
+        Ok(found) => found,
+        Err(error) => {
+            report.failed.extend(patterns.into_iter().map(|pattern| (pattern, error.to_string())));
+            return report;
+        }
+    };
+    report.missing = patterns.into_iter()
+        .filter(|pattern| !found.iter().any(|(matched, _, _)| matched == pattern))
+        .collect();
+    for (pattern, file, checksum) in found {
+        /* a file can match more than one pattern */
+        if report.files.iter().any(|(_, collected)| collected == &file) {
+            continue;
+        }
+        match download(device, &file, &checksum, &destination).await {
+            Ok(_) => report.files.push((pattern, file)),
+            Err(error) => report.failed.push((file, error)),
+        }
+    }
+    report
+}
+
+async fn download(device: &fernbedienung::Device, file: &str, checksum: &str, destination: &Path)
+    -> Result<(), String> {
+    if !is_within_working_dir(Path::new(file)) {
+        return Err("Not relative to the working directory of ARGoS".to_owned());
+    }
+    let contents = device.download(staging::working_dir().join(file)).await
+        .map_err(|error| error.to_string())?;
+    let digest = openssl::sha::sha256(&contents).iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    if digest != checksum {
+        return Err(format!("Checksum mismatch, expected {} but downloaded {}", checksum, digest));
+    }
+    let path = destination.join(file);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await
+            .map_err(|error| error.to_string())?;
+    }
+    tokio::fs::write(path, contents).await
+        .map_err(|error| error.to_string())
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::environment;
+use crate::flags::Flags;
+use crate::harvest;
+use crate::router;
+use crate::software::Software;
+
+/// The number of runs that are kept in the history, the oldest runs are forgotten first. The
+/// software of every run is kept in memory, and the history is cloned for the web interface and
+/// the standby supervisor, so it must not grow without a bound.
+pub const MAX_RUNS: usize = 50;
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Action {
+    #[serde(rename = "Clone")]
+    Clone,
+    #[serde(rename = "Save as template")]
+    SaveTemplate,
+    #[serde(rename = "Delete")]
+    Delete,
+    #[serde(rename = "Export")]
+    Export,
+}
+
+/// The configuration of an experiment, as it was when the experiment was started
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub started: SystemTime,
+    pub pipuck_software: Software,
+    pub drone_software: Software,
+    pub environment_schedule: environment::Schedule,
+    pub flags: Flags,
+    pub injection_templates: Vec<router::Template>,
+    /// The robots that took part in the experiment
+    pub robots: Vec<Uuid>,
+    /// The hostnames of the robots that took part in the experiment
+    #[serde(default)]
+    pub names: HashMap<Uuid, String>,
+    /// The identities of the signers of the software, if signing was required
+    #[serde(default)]
+    pub signers: Vec<String>,
+    /// The results collected from each robot after the experiment
+    #[serde(default)]
+    pub results: HashMap<Uuid, harvest::Report>,
+    /// Whether the experiment was aborted, in which case the results are partial
+    #[serde(default)]
+    pub aborted: bool,
+    /// The robots whose processor was thermally throttled during the experiment
+    #[serde(default)]
+    pub throttled: Vec<Uuid>,
+    /// Chat messages of the operators that were pinned to the run
+    #[serde(default)]
+    pub annotations: Vec<crate::chat::Message>,
+    /// The journal of the experiment
+    #[serde(default)]
+    pub journal: Option<PathBuf>,
+}
+
+/// A run that has been saved under a name so that it can be cloned later
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub run: Run,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub runs: Vec<(Uuid, Run)>,
+    pub templates: Vec<(Uuid, Template)>,
+}
+
+impl History {
+    pub fn record(&mut self, uuid: Uuid, run: Run) {
+        self.runs.push((uuid, run));
+        let excess = self.runs.len().saturating_sub(MAX_RUNS);
+        for (uuid, _) in self.runs.drain(..excess) {
+            log::info!("Removed run {} from the history, save a run as a template to keep it", uuid);
+        }
+    }
+
+    /// Records the results collected from a robot, returns false if the run no longer exists
+    pub fn record_results(&mut self, run: &Uuid, robot: Uuid, report: harvest::Report) -> bool {
+        match self.runs.iter_mut().find(|(uuid, _)| uuid == run) {
+            Some((_, run)) => {
+                run.results.insert(robot, report);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /* saving a run under the name of an existing template replaces that template */
+    pub fn save_template(&mut self, run: &Uuid, name: String) -> bool {
+        let run = match self.runs.iter().find(|(uuid, _)| uuid == run) {
+            Some((_, run)) => run.clone(),
+            None => return false,
+        };
+        /* the uuid of a template is derived from its name */
+        let uuid = Uuid::new_v3(&Uuid::NAMESPACE_OID, name.as_bytes());
+        self.templates.retain(|(existing, _)| *existing != uuid);
+        self.templates.push((uuid, Template { name, run }));
+        true
+    }
+
+    pub fn delete(&mut self, uuid: &Uuid) {
+        self.runs.retain(|(run, _)| run != uuid);
+        self.templates.retain(|(template, _)| template != uuid);
+    }
+
+    pub fn find_mut(&mut self, uuid: &Uuid) -> Option<&mut Run> {
+        self.runs.iter_mut()
+            .find(|(run, _)| run == uuid)
+            .map(|(_, run)| run)
+    }
+
+    /// Finds a run, or the run saved in a template, by its unique identifier
+    pub fn find(&self, uuid: &Uuid) -> Option<&Run> {
+        self.runs.iter()
+            .find(|(run, _)| run == uuid)
+            .map(|(_, run)| run)
+            .or_else(|| self.templates.iter()
+                .find(|(template, _)| template == uuid)
+                .map(|(_, template)| &template.run))
+    }
+}
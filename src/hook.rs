@@ -0,0 +1,142 @@
+use std::{fmt, time::{Duration, SystemTime, UNIX_EPOCH}};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::harvest;
+use crate::history;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0} hook did not finish in time")]
+    Timeout(Stage),
+    #[error("{0} hook failed with {1}")]
+    Failed(Stage, std::process::ExitStatus),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Shell commands that are run on the supervisor host around each run, e.g., to start an
+/// analysis job in the pipeline of a lab
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Hooks {
+    /// Runs before the software is staged on the robots, the run does not start if it fails
+    pub pre_run: Option<String>,
+    /// Runs once the results of the robots have been collected
+    pub post_run: Option<String>,
+    /// Seconds after which a hook that has not finished is killed
+    pub timeout: f64,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks {
+            pre_run: None,
+            post_run: None,
+            timeout: 300.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    PreRun,
+    PostRun,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::PreRun => "pre_run",
+            Stage::PostRun => "post_run",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::PreRun => write!(f, "Pre-run"),
+            Stage::PostRun => write!(f, "Post-run"),
+        }
+    }
+}
+
+/// The run as it is described to a hook through its environment
+#[derive(Clone, Debug)]
+pub struct Context {
+    pub run: Uuid,
+    pub started: SystemTime,
+    pub robots: Vec<Uuid>,
+    pub pipuck_software: String,
+    pub drone_software: String,
+    pub aborted: bool,
+}
+
+impl Context {
+    pub fn new(run: Uuid, record: &history::Run) -> Self {
+        Context {
+            run,
+            started: record.started,
+            robots: record.robots.clone(),
+            pipuck_software: record.pipuck_software.version(),
+            drone_software: record.drone_software.version(),
+            aborted: record.aborted,
+        }
+    }
+}
+
+/* runs a hook in the working directory of the supervisor, the hook is killed if it has not
+   finished before the timeout */
+async fn run(stage: Stage, command: &str, timeout: f64, context: &Context) -> Result<()> {
+    let run_dir = std::env::current_dir()?.join(harvest::run_dir(&context.run));
+    let started = context.started.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |started| started.as_secs_f64());
+    let robots = context.robots.iter()
+        .map(Uuid::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("MNS_HOOK", stage.name())
+        .env("MNS_RUN", context.run.to_string())
+        .env("MNS_RUN_DIR", run_dir)
+        .env("MNS_STARTED", started.to_string())
+        .env("MNS_ROBOTS", robots)
+        .env("MNS_PIPUCK_SOFTWARE", &context.pipuck_software)
+        .env("MNS_DRONE_SOFTWARE", &context.drone_software)
+        .env("MNS_ABORTED", context.aborted.to_string())
+        .kill_on_drop(true)
+        .spawn()?;
+    let status = tokio::time::timeout(Duration::from_secs_f64(timeout.max(0.0)), child.wait()).await
+        .map_err(|_| Error::Timeout(stage))??;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::Failed(stage, status)),
+    }
+}
+
+/// Runs the pre-run hook if one is configured, the run must not be started if this fails
+pub async fn pre_run(hooks: &Hooks, context: &Context) -> Result<()> {
+    match hooks.pre_run.as_ref() {
+        Some(command) => {
+            log::info!("Running the pre-run hook for run {}", context.run);
+            run(Stage::PreRun, command, hooks.timeout, context).await
+        },
+        None => Ok(()),
+    }
+}
+
+/// Runs the post-run hook if one is configured, failures are only logged since the run is over
+pub async fn post_run(hooks: &Hooks, context: &Context) {
+    if let Some(command) = hooks.post_run.as_ref() {
+        log::info!("Running the post-run hook for run {}", context.run);
+        if let Err(error) = run(Stage::PostRun, command, hooks.timeout, context).await {
+            log::error!("Run {}: {}", context.run, error);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::network::fernbedienung;
+
+/// Packages whose versions are tracked on each robot
+const KEY_PACKAGES: &[&str] = &["argos3", "argos3-srocs", "fernbedienung", "mjpg-streamer"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("Could not parse image profile: {0}")]
+    ParseError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The software image installed on a robot. When used as a golden image profile, fields that are
+/// left unspecified are not checked for drift
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Image {
+    #[serde(default)]
+    pub os_release: Option<String>,
+    #[serde(default)]
+    pub kernel: Option<String>,
+    #[serde(default)]
+    pub packages: BTreeMap<String, String>,
+}
+
+impl Image {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Image> {
+        let contents = std::fs::read(path)?;
+        serde_json::from_slice(&contents).map_err(Error::ParseError)
+    }
+
+    pub async fn query(device: &fernbedienung::Device) -> fernbedienung::Result<Image> {
+        let os_release = device.os_release().await?;
+        let kernel = device.kernel_version().await?;
+        /* not all images have a package manager, treat this as no packages installed */
+        let packages = device.package_versions().await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(package, _)| KEY_PACKAGES.contains(&package.as_str()))
+            .collect();
+        Ok(Image { os_release: Some(os_release), kernel: Some(kernel), packages })
+    }
+
+    /// Describe how this image differs from the golden image
+    pub fn drift(&self, golden: &Image) -> Vec<String> {
+        let mut drift = Vec::new();
+        if golden.os_release.is_some() && golden.os_release != self.os_release {
+            drift.push(format!("OS release is {} (expected {})",
+                self.os_release.as_deref().unwrap_or("unknown"),
+                golden.os_release.as_deref().unwrap_or_default()));
+        }
+        if golden.kernel.is_some() && golden.kernel != self.kernel {
+            drift.push(format!("Kernel is {} (expected {})",
+                self.kernel.as_deref().unwrap_or("unknown"),
+                golden.kernel.as_deref().unwrap_or_default()));
+        }
+        for (package, version) in golden.packages.iter() {
+            match self.packages.get(package) {
+                Some(installed) if installed == version => {},
+                Some(installed) =>
+                    drift.push(format!("{} is {} (expected {})", package, installed, version)),
+                None =>
+                    drift.push(format!("{} is not installed (expected {})", package, version)),
+            }
+        }
+        drift
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::optitrack::Diagnostics;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The tracking frame rate of {0:.1} Hz is below the minimum of {1:.1} Hz")]
+    FrameRate(f32, f32),
+    #[error("Rigid body {1} of drone {0} has not been tracked")]
+    NotTracked(Uuid, i32),
+    #[error("Rigid body {1} of drone {0} has been lost for {2} frames")]
+    Lost(Uuid, i32, u32),
+    #[error("Drone {0} with identifier {1} does not have a rigid body")]
+    NoRigidBody(Uuid, u8),
+    #[error("Could not get the identifier of drone {0}")]
+    IdentifierError(Uuid),
+    #[error("Could not get the diagnostics of the tracking system")]
+    TrackingError,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Requirements on the tracking system for starting and running experiments with drones
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Interlock {
+    /// Minimum frame rate (Hz) of the tracking system
+    pub min_frame_rate: f32,
+    /// Consecutive frames for which the rigid body of a drone may be missing
+    pub max_lost_frames: u32,
+    /// Rigid body of each drone, keyed by the identifier that is set on the Xbee of the drone
+    pub rigid_bodies: BTreeMap<u8, i32>,
+}
+
+impl Default for Interlock {
+    fn default() -> Self {
+        Interlock {
+            min_frame_rate: 30.0,
+            max_lost_frames: 10,
+            rigid_bodies: BTreeMap::new(),
+        }
+    }
+}
+
+impl Interlock {
+    /// Checks the tracking system against the requirements given the rigid body of each drone
+    pub fn check(&self, diagnostics: &Diagnostics, drones: &HashMap<Uuid, i32>) -> Result<()> {
+        if diagnostics.frame_rate < self.min_frame_rate {
+            return Err(Error::FrameRate(diagnostics.frame_rate, self.min_frame_rate));
+        }
+        for (uuid, rigid_body) in drones {
+            match diagnostics.lost_frames.get(rigid_body) {
+                None => return Err(Error::NotTracked(*uuid, *rigid_body)),
+                Some(&frames) if frames > self.max_lost_frames =>
+                    return Err(Error::Lost(*uuid, *rigid_body, frames)),
+                Some(_) => {},
+            }
+        }
+        Ok(())
+    }
+}
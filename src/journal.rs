@@ -1,97 +1,574 @@
-use std::{net::SocketAddr, time::{Instant, Duration}};
+use std::{collections::VecDeque, net::SocketAddr, path::PathBuf, sync::{Arc, atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}}, time::{Instant, Duration}};
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 use bytes::BytesMut;
-use serde::Serialize;
-use tokio::sync::{mpsc, oneshot};
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc::{self, error::TrySendError}, oneshot};
 use uuid::Uuid;
-use tokio_stream::{StreamExt, wrappers::UnboundedReceiverStream};
 use std::time::{SystemTime, SystemTimeError};
 
+use crate::clock;
+
+/// Number of events that can be waiting to be written before the overflow policy applies
+const RECORD_QUEUE_CAPACITY: usize = 4096;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
     SystemTimeError(#[from] SystemTimeError),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    PickleError(#[from] serde_pickle::Error),
     #[error("Could not send request")]
     RequestError,
     #[error("Did not receive response")]
     ResponseError,
+    #[error("The journals of the runs exceed their quota of {} bytes", .0)]
+    QuotaExceeded(u64),
+    #[error("There is no tracking data to save")]
+    RecordingEmpty,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 pub enum Request {
-    Start(oneshot::Sender<Result<()>>),
-    Stop,
-    Record(Event),
+    /* the schemas of the messages, the quota of the journals of all runs in bytes, the directory
+       of the spool, and the clock of the run with the epoch of the timestamps */
+    Start(crate::schema::Registry, Option<u64>, PathBuf, clock::Run, clock::Epoch, oneshot::Sender<Result<PathBuf>>),
+    /* stop the journal, deleting it and its spool file if the run is discarded */
+    Stop { discard: bool },
+    /* keep the tracking data of the given duration while no experiment is running, or nothing */
+    Retain(Option<Duration>),
+    /* write the kept tracking data to a file and clear it */
+    SaveRecording(oneshot::Sender<Result<PathBuf>>),
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum RecordingAction {
+    #[serde(rename = "Save recording")]
+    Save,
 }
 
 #[derive(Debug, Serialize)]
 pub enum Event {
-    //Optitrack {},
     Robot(Uuid, Robot),
+    /* the filtered poses and the estimated motion of the tracked rigid bodies */
+    Tracking(Vec<crate::optitrack::Pose>),
+    /* the markers of the tracking system, if recording them is enabled */
+    Markers(crate::optitrack::Markers),
+    /* a rigid body entering or leaving a region of the arena */
+    Region(crate::region::Transition),
+    /* the deviation of the robots from the targets of a formation */
+    Formation(crate::formation::Measurement),
     Broadcast(SocketAddr, crate::router::LuaType),
+    Lifecycle(Uuid, crate::robot::Lifecycle),
+    Environment(String, serde_json::Value),
+    /* a change in the state of the arming switch */
+    Arming(crate::arming::State),
+    /* an attempt to start drones that was refused because they were not armed */
+    Refused(String),
+    /* the schemas used to tag the messages in this journal */
+    Schemas(crate::schema::Registry),
+    /* the time since the Unix epoch at which the run started and the epoch of the timestamps */
+    Clock {
+        started: Duration,
+        epoch: clock::Epoch,
+    },
+    /* a connection to a robot that was dropped because the robot sent a malformed frame or
+       because it fell behind and missed messages from the message router */
+    Dropped(std::net::IpAddr, String),
+    /* a message in the chat of the operators */
+    Chat(crate::chat::Message),
+}
+
+enum Priority {
+    /// Events that are sampled at a high rate and can be dropped when the journal is falling behind
+    Low,
+    /// Events that are never dropped and are spooled to disk when the journal is falling behind
+    High,
+}
+
+impl Event {
+    fn priority(&self) -> Priority {
+        match self {
+            Event::Tracking(..) | Event::Markers(..) => Priority::Low,
+            Event::Broadcast(..) | Event::Robot(..) | Event::Lifecycle(..) | Event::Environment(..) | Event::Region(..) |
+            Event::Formation(..) | Event::Arming(..) | Event::Refused(..) | Event::Schemas(..) | Event::Clock{..} | Event::Dropped(..) |
+            Event::Chat(..) => Priority::High,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub enum Robot {
     StandardOutput(BytesMut),
     StandardError(BytesMut),
+    /* how ARGoS terminated, e.g., the exit code with which a controller ended the run */
+    Terminated(crate::network::fernbedienung::ExitStatus),
 }
 
 #[derive(Debug, Serialize)]
-struct Entry {
+pub struct Entry {
     timestamp: Duration,
     event: Event,
+    /* the schema of a broadcast message, if known */
+    schema: Option<crate::schema::Tag>,
+}
+
+impl Entry {
+    pub fn new(timestamp: Duration, event: Event, schemas: &crate::schema::Registry) -> Entry {
+        let schema = match &event {
+            Event::Broadcast(_, message) => schemas.tag(message),
+            _ => None,
+        };
+        Entry { timestamp, event, schema }
+    }
 }
 
-// todo spawn a logging task here and return a channel for logging messages
-pub async fn new(rx: mpsc::UnboundedReceiver<Request>) -> Result<()> {
-    let mut requests = UnboundedReceiverStream::new(rx);
-    let mut start: Option<Instant> = None;
-    let mut writer: Option<BufWriter<_>> = None;
-    while let Some(request) = requests.next().await {
+/* requests to the thread that writes the spool, the spool is written on its own thread so that
+   a stalled disk does not block the tasks that record events */
+enum Spool {
+    Start(Session),
+    Record(Instant, Event),
+    Stop { discard: bool },
+}
+
+struct Session {
+    clock: (clock::Run, clock::Epoch),
+    schemas: crate::schema::Registry,
+    /* the journal into which the spool is merged when the experiment stops */
+    journal_path: PathBuf,
+    spool_path: PathBuf,
+    spool: Option<BufWriter<File>>,
+}
+
+impl Session {
+    fn write(&mut self, recorded: Instant, event: Event) -> Result<()> {
+        if self.spool.is_none() {
+            if let Some(directory) = self.spool_path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            let file = File::create(&self.spool_path)?;
+            log::warn!("Journal is falling behind, spooling events to {}", self.spool_path.display());
+            self.spool = Some(BufWriter::new(file));
+        }
+        if let Some(spool) = self.spool.as_mut() {
+            let (run, epoch) = self.clock;
+            let entry = Entry::new(run.timestamp(recorded, epoch), event, &self.schemas);
+            serde_pickle::ser::to_writer(spool, &entry, true)?;
+        }
+        Ok(())
+    }
+
+    /* appends the spooled entries to the journal, so the journal is no longer ordered by time */
+    fn merge(&self) -> Result<()> {
+        let mut spool = File::open(&self.spool_path)?;
+        let mut journal = std::fs::OpenOptions::new().append(true).open(&self.journal_path)?;
+        std::io::copy(&mut spool, &mut journal)?;
+        std::fs::remove_file(&self.spool_path)?;
+        Ok(())
+    }
+}
+
+fn spool(mut requests: mpsc::UnboundedReceiver<Spool>, shared: Arc<Shared>) {
+    let mut session: Option<Session> = None;
+    while let Some(request) = requests.blocking_recv() {
         match request {
-            // TODO add a callback from here to abort starting the experiment if the log file isn't good
-            Request::Start(callback) => {
-                let response = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-                    Err(error) => Err(Error::SystemTimeError(error)),
-                    Ok(since_unix_epoch) => {
-                        let log_filename = format!("{}.pkl", since_unix_epoch.as_secs());
-                        start = Some(Instant::now());
-                        match File::create(log_filename) {
-                            Err(error) => Err(Error::IoError(error)),
-                            Ok(file) => {
-                                writer = Some(BufWriter::new(file));
-                                Ok(())
-                            }
-                        }
-                    }
-                };
-                if let Err(_) = callback.send(response) {
-                    log::error!("Could not respond to start experiment request");
+            Spool::Start(started) => session = Some(started),
+            /* events are only spooled while an experiment is running */
+            Spool::Record(recorded, event) => if let Some(session) = session.as_mut() {
+                match session.write(recorded, event) {
+                    Ok(_) => {
+                        shared.spooled.fetch_add(1, Ordering::Relaxed);
+                    },
+                    Err(error) => log::error!("Could not spool event: {}", error),
                 }
             },
-            Request::Stop => {
-                /* clear the start time and close the file */
-                start = None;
-                writer = None;
+            Spool::Stop { discard } => if let Some(mut session) = session.take() {
+                if let Some(mut spool) = session.spool.take() {
+                    if let Err(error) = spool.flush() {
+                        log::error!("Could not write the spool {}: {}", session.spool_path.display(), error);
+                    }
+                }
+                if session.spool_path.exists() {
+                    match discard {
+                        true => match std::fs::remove_file(&session.spool_path) {
+                            Ok(_) => log::info!("Discarded {}", session.spool_path.display()),
+                            Err(error) => log::error!("Could not discard {}: {}", session.spool_path.display(), error),
+                        },
+                        false => match session.merge() {
+                            Ok(_) => log::info!("Merged {} into {}", session.spool_path.display(), session.journal_path.display()),
+                            Err(error) => log::error!("Could not merge {} into {}: {}",
+                                session.spool_path.display(), session.journal_path.display(), error),
+                        },
+                    }
+                }
             },
-            Request::Record(event) => if let Some(start) = start.as_ref() {
-                if let Some(writer) = writer.as_mut() {
-                    let entry = Entry { timestamp: start.elapsed(), event };
-                    if let Err(error) = serde_pickle::ser::to_writer(writer, &entry, true) {
-                        log::error!("Error writing entry {:?} to journal: {}", entry, error);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: AtomicUsize,
+    dropped: AtomicU64,
+    spooled: AtomicU64,
+    quota_exceeded: AtomicBool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Status {
+    /// Events waiting to be written to the journal
+    pub pending: usize,
+    /// Low priority events that were dropped during this experiment
+    pub dropped: u64,
+    /// High priority events that were written to the spool during this experiment
+    pub spooled: u64,
+    /// The journal stopped recording during this experiment as it reached its quota
+    pub quota_exceeded: bool,
+}
+
+impl Status {
+    pub fn is_falling_behind(&self) -> bool {
+        self.pending > RECORD_QUEUE_CAPACITY / 2 || self.dropped > 0 || self.spooled > 0
+    }
+}
+
+#[derive(Clone)]
+pub struct Sender {
+    requests: mpsc::UnboundedSender<Request>,
+    records: mpsc::Sender<(Instant, Event)>,
+    spool: mpsc::UnboundedSender<Spool>,
+    shared: Arc<Shared>,
+}
+
+impl Sender {
+    /// Start a new journal, the schemas are written at the start of the journal so that it
+    /// remains decodable if the schemas change. The events are timestamped with the clock of the
+    /// run relative to the given epoch. High priority events are spooled in the given directory
+    /// if the journal falls behind and appended to the journal when it stops. Returns the path
+    /// of the journal.
+    pub async fn start(&self,
+                       schemas: crate::schema::Registry,
+                       quota: Option<u64>,
+                       spool: PathBuf,
+                       run: clock::Run,
+                       epoch: clock::Epoch) -> Result<PathBuf> {
+        let (callback_tx, callback_rx) = oneshot::channel();
+        self.requests
+            .send(Request::Start(schemas, quota, spool, run, epoch, callback_tx))
+            .map_err(|_| Error::RequestError)?;
+        callback_rx.await
+            .map_err(|_| Error::ResponseError)
+            .and_then(|result| result)
+    }
+
+    /// Keep the tracking data of the given duration while no experiment is running, so that what
+    /// happened before, e.g., during manual handling, can be saved for review
+    pub fn retain(&self, retention: Option<Duration>) {
+        let _ = self.requests.send(Request::Retain(retention));
+    }
+
+    /// Write the kept tracking data to a file, timestamped relative to the Unix epoch. Returns
+    /// the path of the file.
+    pub async fn save_recording(&self) -> Result<PathBuf> {
+        let (callback_tx, callback_rx) = oneshot::channel();
+        self.requests
+            .send(Request::SaveRecording(callback_tx))
+            .map_err(|_| Error::RequestError)?;
+        callback_rx.await
+            .map_err(|_| Error::ResponseError)
+            .and_then(|result| result)
+    }
+
+    pub fn stop(&self) {
+        let _ = self.requests.send(Request::Stop { discard: false });
+    }
+
+    /// Stop the journal and delete what was recorded, e.g., when a run is aborted and discarded
+    pub fn discard(&self) {
+        let _ = self.requests.send(Request::Stop { discard: true });
+    }
+
+    /// Record an event in the journal. If the journal is falling behind, low priority events are
+    /// dropped and high priority events are written to a spool file.
+    pub fn record(&self, event: Event) -> Result<()> {
+        self.record_at(Instant::now(), event)
+    }
+
+    /// Record an event that occurred before it was recorded, e.g., a frame of the tracking system
+    /// that is timestamped with the exposure of the cameras rather than its receipt
+    pub fn record_at(&self, recorded: Instant, event: Event) -> Result<()> {
+        self.shared.pending.fetch_add(1, Ordering::Relaxed);
+        match self.records.try_send((recorded, event)) {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                self.shared.pending.fetch_sub(1, Ordering::Relaxed);
+                match error {
+                    TrySendError::Closed(_) => Err(Error::RequestError),
+                    TrySendError::Full((recorded, event)) => match event.priority() {
+                        Priority::Low => {
+                            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                            Ok(())
+                        },
+                        Priority::High => self.spool(recorded, event),
                     }
                 }
             }
         }
     }
+
+    fn spool(&self, recorded: Instant, event: Event) -> Result<()> {
+        /* events are only recorded while the quota is not exceeded */
+        if self.shared.quota_exceeded.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.spool.send(Spool::Record(recorded, event))
+            .map_err(|_| Error::RequestError)
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            pending: self.shared.pending.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            spooled: self.shared.spooled.load(Ordering::Relaxed),
+            quota_exceeded: self.shared.quota_exceeded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn new() -> (Sender, impl Future<Output = Result<()>>) {
+    let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+    let (records_tx, records_rx) = mpsc::channel(RECORD_QUEUE_CAPACITY);
+    let (spool_tx, spool_rx) = mpsc::unbounded_channel();
+    let shared = Arc::new(Shared::default());
+    let sender = Sender {
+        requests: requests_tx,
+        records: records_tx,
+        spool: spool_tx.clone(),
+        shared: shared.clone(),
+    };
+    let spool_shared = shared.clone();
+    std::thread::spawn(move || spool(spool_rx, spool_shared));
+    (sender, run(requests_rx, records_rx, spool_tx, shared))
+}
+
+/* the journal of the running experiment, or the tracking data that is kept in between */
+#[derive(Default)]
+struct Journal {
+    clock: Option<(clock::Run, clock::Epoch)>,
+    writer: Option<BufWriter<File>>,
+    schemas: crate::schema::Registry,
+    /* bytes that the journal can write before the journals of all runs exceed their quota */
+    remaining: Option<u64>,
+    /* the tracking data that is kept while no experiment is running */
+    retention: Option<Duration>,
+    recording: VecDeque<(Instant, Event)>,
+}
+
+impl Journal {
+    fn record(&mut self, shared: &Shared, recorded: Instant, event: Event) {
+        /* outside of the experiments, only the tracking data is kept */
+        if let (None, Some(retention)) = (self.clock, self.retention) {
+            if let Event::Tracking(..) | Event::Markers(..) = event {
+                self.recording.push_back((recorded, event));
+                expire(&mut self.recording, retention);
+            }
+            return;
+        }
+        if let Some((run, epoch)) = self.clock {
+            if let Some(writer) = self.writer.as_mut() {
+                let schemas = &self.schemas;
+                let entry = Entry::new(run.timestamp(recorded, epoch), event, schemas);
+                if let Some(message) = entry.schema.as_ref().and(match &entry.event {
+                    Event::Broadcast(_, message) => schemas.pretty_print(message),
+                    _ => None,
+                }) {
+                    log::debug!("{}", message);
+                }
+                match serde_pickle::ser::to_vec(&entry, true) {
+                    Ok(data) => match self.remaining {
+                        Some(left) if data.len() as u64 > left => {
+                            log::error!("Journal reached the quota for the journals of the runs, \
+                                the remaining events of this run are not recorded");
+                            shared.quota_exceeded.store(true, Ordering::Relaxed);
+                        },
+                        _ => {
+                            self.remaining = self.remaining.map(|left| left - data.len() as u64);
+                            if let Err(error) = writer.write_all(&data) {
+                                log::error!("Error writing entry {:?} to journal: {}", entry, error);
+                            }
+                        }
+                    },
+                    Err(error) => log::error!("Error writing entry {:?} to journal: {}", entry, error),
+                }
+            }
+        }
+        /* stop writing to a journal that reached its quota */
+        if shared.quota_exceeded.load(Ordering::Relaxed) {
+            self.writer = None;
+        }
+    }
+}
+
+async fn run(mut requests: mpsc::UnboundedReceiver<Request>,
+             mut records: mpsc::Receiver<(Instant, Event)>,
+             spool: mpsc::UnboundedSender<Spool>,
+             shared: Arc<Shared>) -> Result<()> {
+    let mut journal = Journal::default();
+    let mut log_path: Option<PathBuf> = None;
+    loop {
+        tokio::select! {
+            request = requests.recv() => match request {
+                // TODO add a callback from here to abort starting the experiment if the log file isn't good
+                Some(Request::Start(registry, quota, spool_dir, run, epoch, callback)) => {
+                    let usage = match quota {
+                        Some(_) => crate::quota::run_usage().map_err(Error::IoError),
+                        None => Ok(0),
+                    };
+                    let response = match (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH), usage) {
+                        (Err(error), _) => Err(Error::SystemTimeError(error)),
+                        (_, Err(error)) => Err(error),
+                        (_, Ok(usage)) if quota.map_or(false, |quota| usage >= quota) =>
+                            Err(Error::QuotaExceeded(quota.unwrap_or_default())),
+                        (Ok(since_unix_epoch), Ok(usage)) => {
+                            let log_filename = format!("{}.pkl", since_unix_epoch.as_secs());
+                            let spool_filename = format!("{}.spool.pkl", since_unix_epoch.as_secs());
+                            match File::create(&log_filename) {
+                                Err(error) => Err(Error::IoError(error)),
+                                Ok(file) => {
+                                    journal.clock = Some((run, epoch));
+                                    let path = PathBuf::from(log_filename);
+                                    log_path = Some(path.clone());
+                                    let mut file = BufWriter::new(file);
+                                    let timestamp = run.timestamp(Instant::now(), epoch);
+                                    let entry = Entry::new(timestamp, Event::Schemas(registry.clone()), &registry);
+                                    if let Err(error) = serde_pickle::ser::to_writer(&mut file, &entry, true) {
+                                        log::error!("Error writing schemas to journal: {}", error);
+                                    }
+                                    let entry = Entry::new(timestamp, Event::Clock { started: run.started(), epoch }, &registry);
+                                    if let Err(error) = serde_pickle::ser::to_writer(&mut file, &entry, true) {
+                                        log::error!("Error writing clock to journal: {}", error);
+                                    }
+                                    journal.writer = Some(file);
+                                    journal.schemas = registry.clone();
+                                    /* reset the overflow state for the new experiment */
+                                    let _ = spool.send(Spool::Start(Session {
+                                        clock: (run, epoch),
+                                        schemas: registry,
+                                        journal_path: path.clone(),
+                                        spool_path: spool_dir.join(spool_filename),
+                                        spool: None,
+                                    }));
+                                    shared.dropped.store(0, Ordering::Relaxed);
+                                    shared.spooled.store(0, Ordering::Relaxed);
+                                    shared.quota_exceeded.store(false, Ordering::Relaxed);
+                                    journal.remaining = quota.map(|quota| quota - usage);
+                                    Ok(path)
+                                }
+                            }
+                        }
+                    };
+                    if let Err(_) = callback.send(response) {
+                        log::error!("Could not respond to start experiment request");
+                    }
+                },
+                Some(Request::Stop { discard }) => {
+                    /* write the events that were queued before the journal was stopped, these are
+                       bounded by the pending events so that a busy journal still stops */
+                    for _ in 0..shared.pending.load(Ordering::Relaxed) {
+                        match records.try_recv() {
+                            Ok((recorded, event)) => {
+                                shared.pending.fetch_sub(1, Ordering::Relaxed);
+                                journal.record(&shared, recorded, event);
+                            },
+                            Err(_) => break,
+                        }
+                    }
+                    /* clear the clock and close the files */
+                    journal.clock = None;
+                    if let Some(mut writer) = journal.writer.take() {
+                        if let Err(error) = writer.flush() {
+                            log::error!("Error writing journal: {}", error);
+                        }
+                    }
+                    let _ = spool.send(Spool::Stop { discard });
+                    if discard {
+                        for path in log_path.iter().filter(|path| path.exists()) {
+                            match std::fs::remove_file(path) {
+                                Ok(_) => log::info!("Discarded {}", path.display()),
+                                Err(error) => log::error!("Could not discard {}: {}", path.display(), error),
+                            }
+                        }
+                    }
+                    log_path = None;
+                    let dropped = shared.dropped.load(Ordering::Relaxed);
+                    let spooled = shared.spooled.load(Ordering::Relaxed);
+                    if dropped > 0 || spooled > 0 {
+                        log::warn!("Journal dropped {} and spooled {} events during the experiment",
+                            dropped, spooled);
+                    }
+                },
+                Some(Request::Retain(duration)) => {
+                    journal.retention = duration;
+                    if journal.retention.is_none() {
+                        journal.recording.clear();
+                    }
+                },
+                Some(Request::SaveRecording(callback)) => {
+                    if let Some(retention) = journal.retention {
+                        expire(&mut journal.recording, retention);
+                    }
+                    let response = save_recording(journal.recording.drain(..).collect());
+                    if let Err(_) = callback.send(response) {
+                        log::error!("Could not respond to save recording request");
+                    }
+                },
+                None => break,
+            },
+            Some((recorded, event)) = records.recv() => {
+                shared.pending.fetch_sub(1, Ordering::Relaxed);
+                journal.record(&shared, recorded, event);
+            }
+        }
+    }
     Ok(())
 }
 
+/* removes the kept events that are older than the retention */
+fn expire(recording: &mut VecDeque<(Instant, Event)>, retention: Duration) {
+    while let Some((recorded, _)) = recording.front() {
+        match recorded.elapsed() > retention {
+            true => recording.pop_front(),
+            false => break,
+        };
+    }
+}
+
+/* writes the kept events to a file in the format of the journal, the timestamps are relative to
+   the Unix epoch since the events do not belong to a run */
+fn save_recording(recording: Vec<(Instant, Event)>) -> Result<PathBuf> {
+    let start = match recording.first() {
+        Some((recorded, _)) => *recorded,
+        None => return Err(Error::RecordingEmpty),
+    };
+    let run = clock::Run::since(start);
+    let path = PathBuf::from(format!("recording-{}.pkl", run.started().as_secs()));
+    let mut file = BufWriter::new(File::create(&path)?);
+    let schemas = crate::schema::Registry::default();
+    let clock = Event::Clock { started: run.started(), epoch: clock::Epoch::Unix };
+    serde_pickle::ser::to_writer(&mut file, &Entry::new(run.timestamp(start, clock::Epoch::Unix), clock, &schemas), true)?;
+    for (recorded, event) in recording {
+        let entry = Entry::new(run.timestamp(recorded, clock::Epoch::Unix), event, &schemas);
+        serde_pickle::ser::to_writer(&mut file, &entry, true)?;
+    }
+    file.flush()?;
+    log::info!("Saved the recent tracking data to {}", path.display());
+    Ok(path)
+}
+
 /* .bashrc
 depickle() {
 python << EOPYTHON
@@ -0,0 +1,449 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, net::Ipv4Addr, time::{Duration, SystemTime}};
+use futures::{stream::FuturesUnordered, StreamExt};
+use rand::seq::IteratorRandom;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::config;
+use crate::network::{fernbedienung, xbee};
+use crate::optitrack;
+use crate::telemetry::Sample;
+
+/// Number of samples kept for each link
+const HISTORY_CAPACITY: usize = 120;
+/// Time after which a poll that has not responded counts as failed
+const POLL_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time after which a scan of the Wi-Fi that has not responded counts as failed
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+/// The channels of the 2.4 GHz band that do not overlap
+const NON_OVERLAPPING_CHANNELS: [u32; 3] = [1, 6, 11];
+
+/// Settings for monitoring the signal strength of the links to the robots
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Seconds between polls of the links
+    pub interval: f64,
+    /// Signal strength (dBm) of a Wi-Fi link below which a warning is raised
+    pub weak_wifi_signal: i32,
+    /// Signal strength (dBm) of an Xbee link below which a warning is raised
+    pub weak_xbee_signal: i32,
+    /// Size (m) of the cells of the heatmap of the Wi-Fi signal strength across the arena
+    pub heatmap_cell: f32,
+    /// Rigid bodies of the robots by the hostname of the Raspberry Pi of a Pi-Puck or of the UP
+    /// Core of a drone, only the links of these robots contribute to the heatmap
+    pub rigid_bodies: BTreeMap<String, i32>,
+    /// Seconds between passive scans of the Wi-Fi channels, zero disables the scans
+    pub scan_interval: f64,
+    /// Number of robots, picked at random, that scan the Wi-Fi channels in each round
+    pub scan_robots: usize,
+    /// Number of access points of other networks from which a channel is reported as congested
+    pub congested_channel: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            interval: 5.0,
+            weak_wifi_signal: -75,
+            weak_xbee_signal: -85,
+            heatmap_cell: 0.25,
+            rigid_bodies: BTreeMap::new(),
+            scan_interval: 900.0,
+            scan_robots: 3,
+            congested_channel: 5,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum Interface {
+    #[serde(rename = "Raspberry Pi")]
+    RaspberryPi,
+    #[serde(rename = "UP Core")]
+    UpCore,
+    Xbee,
+}
+
+impl std::fmt::Display for Interface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Interface::RaspberryPi => "Raspberry Pi",
+            Interface::UpCore => "UP Core",
+            Interface::Xbee => "Xbee",
+        })
+    }
+}
+
+/// The device through which the signal strength of a link is polled
+#[derive(Clone)]
+pub enum Device {
+    Fernbedienung(fernbedienung::Device),
+    Xbee(xbee::Device),
+}
+
+impl Device {
+    fn addr(&self) -> Ipv4Addr {
+        match self {
+            Device::Fernbedienung(device) => device.addr,
+            Device::Xbee(device) => device.addr,
+        }
+    }
+
+    async fn signal_strength(self) -> Option<i32> {
+        let poll = async {
+            match &self {
+                Device::Fernbedienung(device) => device.link_strength().await
+                    .map_err(|error| log::debug!("Could not poll link of {}: {}", device.addr, error))
+                    .ok(),
+                Device::Xbee(device) => device.rssi().await
+                    .map_err(|error| log::debug!("Could not poll link of {}: {}", device.addr, error))
+                    .ok(),
+            }
+        };
+        tokio::time::timeout(POLL_TIMEOUT, poll).await.ok().flatten()
+    }
+}
+
+/// The recent signal strength (dBm) of a link
+#[derive(Clone, Debug, Serialize)]
+pub struct Status {
+    pub addr: Ipv4Addr,
+    pub samples: Vec<Sample>,
+    /// The last sample is below the threshold of the interface
+    pub weak: bool,
+    /// Polls that failed since the last successful poll
+    pub failures: usize,
+}
+
+/// The links of each robot
+pub type Links = BTreeMap<Uuid, BTreeMap<Interface, Status>>;
+
+/// The Wi-Fi signal strength (dBm) sampled in a cell of the heatmap
+#[derive(Clone, Debug)]
+pub struct Cell {
+    pub samples: usize,
+    pub mean: f32,
+    pub min: i32,
+}
+
+/// The Wi-Fi signal strength across the arena, sampled at the positions of the robots since the
+/// supervisor started or since the heatmap was cleared
+#[derive(Clone, Debug)]
+pub struct Heatmap {
+    /// Size (m) of the cells
+    pub cell: f32,
+    /// The cells by their column and row, the cell (0, 0) is centered on the origin of the arena
+    pub cells: BTreeMap<(i32, i32), Cell>,
+}
+
+impl Heatmap {
+    fn new(settings: &Settings) -> Self {
+        Heatmap { cell: settings.heatmap_cell.max(0.01), cells: BTreeMap::new() }
+    }
+
+    fn add(&mut self, x: f32, y: f32, signal_strength: i32) {
+        let index = ((x / self.cell).round() as i32, (y / self.cell).round() as i32);
+        let cell = self.cells.entry(index).or_insert(Cell { samples: 0, mean: 0.0, min: i32::MAX });
+        cell.samples += 1;
+        cell.mean += (signal_strength as f32 - cell.mean) / cell.samples as f32;
+        cell.min = cell.min.min(signal_strength);
+    }
+
+    /// The center (m) of a cell in the frame of the arena
+    pub fn center(&self, (column, row): (i32, i32)) -> (f32, f32) {
+        (column as f32 * self.cell, row as f32 * self.cell)
+    }
+
+    /// The cells as comma-separated values for exporting the heatmap
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("x,y,samples,mean,min\n");
+        for (index, cell) in self.cells.iter() {
+            let (x, y) = self.center(*index);
+            csv.push_str(&format!("{:.3},{:.3},{},{:.1},{}\n", x, y, cell.samples, cell.mean, cell.min));
+        }
+        csv
+    }
+}
+
+/// The access points of other networks on a Wi-Fi channel
+#[derive(Clone, Debug, Default)]
+pub struct Channel {
+    /// The strongest signal (dBm) of each foreign access point by its BSSID
+    pub foreign: BTreeMap<String, f32>,
+    /// Whether the robots are connected to an access point on this channel
+    pub used: bool,
+}
+
+/// The use of the Wi-Fi channels, aggregated from the last round of scans
+#[derive(Clone, Debug)]
+pub struct Survey {
+    pub scanned: SystemTime,
+    /// Number of robots whose scans succeeded
+    pub robots: usize,
+    pub channels: BTreeMap<u32, Channel>,
+}
+
+impl Survey {
+    /* the access points that a robot is associated with define the networks of the arena, every
+       other access point, including hidden ones, is foreign */
+    fn new(scans: Vec<Vec<fernbedienung::Bss>>) -> Self {
+        let networks = scans.iter()
+            .flatten()
+            .filter(|access_point| access_point.associated)
+            .map(|access_point| access_point.ssid.clone())
+            .collect::<BTreeSet<_>>();
+        let mut channels: BTreeMap<u32, Channel> = BTreeMap::new();
+        for access_point in scans.iter().flatten() {
+            let channel = channels.entry(channel(access_point.frequency)).or_default();
+            if access_point.associated {
+                channel.used = true;
+            }
+            if access_point.ssid.is_empty() || !networks.contains(&access_point.ssid) {
+                let signal = channel.foreign.entry(access_point.bssid.clone()).or_insert(access_point.signal);
+                *signal = signal.max(access_point.signal);
+            }
+        }
+        Survey { scanned: SystemTime::now(), robots: scans.len(), channels }
+    }
+
+    /// Advice on the channels used by the robots that are congested
+    pub fn advisories(&self, congested: usize) -> Vec<String> {
+        let foreign = |channel: u32| self.channels.get(&channel).map_or(0, |channel| channel.foreign.len());
+        let mut advisories = Vec::new();
+        for (number, channel) in self.channels.iter().filter(|(_, channel)| channel.used) {
+            if channel.foreign.len() >= congested.max(1) {
+                let mut advisory = format!("Channel {} is heavily used by {} foreign access points",
+                    number, channel.foreign.len());
+                if NON_OVERLAPPING_CHANNELS.contains(number) {
+                    if let Some(quietest) = NON_OVERLAPPING_CHANNELS.iter().cloned()
+                        .min_by_key(|channel| foreign(*channel))
+                        .filter(|quietest| foreign(*quietest) < channel.foreign.len()) {
+                        advisory += &format!(", channel {} has {}", quietest, foreign(quietest));
+                    }
+                }
+                advisories.push(advisory);
+            }
+        }
+        advisories
+    }
+}
+
+/* the channel of a frequency (MHz) in the 2.4 GHz or the 5 GHz band */
+fn channel(frequency: u32) -> u32 {
+    match frequency {
+        2484 => 14,
+        2412..=2472 => (frequency - 2407) / 5,
+        _ => frequency.saturating_sub(5000) / 5,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum HeatmapAction {
+    Clear,
+}
+
+pub enum Request {
+    /* start polling a link of a robot with a hostname, replacing the previous device of the interface */
+    Watch(Uuid, Interface, Device, Option<String>),
+    /* stop polling the links of a robot */
+    Unwatch(Uuid),
+    Links(oneshot::Sender<Links>),
+    Heatmap(oneshot::Sender<Heatmap>),
+    ClearHeatmap,
+    /* the use of the Wi-Fi channels, if the channels have been scanned */
+    Survey(oneshot::Sender<Option<Survey>>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+struct Link {
+    device: Device,
+    hostname: Option<String>,
+    samples: VecDeque<Sample>,
+    weak: bool,
+    failures: usize,
+}
+
+/* a disabled scan is checked again after a minute, in case it is enabled in the configuration */
+fn scan_period(settings: &Settings) -> Duration {
+    match settings.scan_interval > 0.0 {
+        true => Duration::from_secs_f64(settings.scan_interval.max(60.0)),
+        false => Duration::from_secs(60),
+    }
+}
+
+fn threshold(settings: &Settings, interface: Interface) -> i32 {
+    match interface {
+        Interface::RaspberryPi | Interface::UpCore => settings.weak_wifi_signal,
+        Interface::Xbee => settings.weak_xbee_signal,
+    }
+}
+
+/// Polls the signal strength of the links to the robots and warns when a signal becomes weak,
+/// the Wi-Fi samples of the tracked robots are collected into a heatmap of the arena. A few
+/// robots periodically scan the Wi-Fi channels for access points of other networks.
+pub async fn new(mut requests_rx: Receiver, config: config::Receiver, tracking_requests_tx: optitrack::Sender) {
+    let mut links: BTreeMap<(Uuid, Interface), Link> = BTreeMap::new();
+    let mut period = Duration::from_secs_f64(config.borrow().link_monitor.interval.max(1.0));
+    let mut interval = tokio::time::interval(period);
+    let mut polls = FuturesUnordered::new();
+    /* the poses of the rigid bodies when the current round of polls started */
+    let mut frames = FuturesUnordered::new();
+    let mut poses: Vec<optitrack::Pose> = Vec::new();
+    let mut heatmap = Heatmap::new(&config.borrow().link_monitor);
+    /* the scans of the current round and the survey of the last round */
+    let scan_timer = tokio::time::sleep(scan_period(&config.borrow().link_monitor));
+    tokio::pin!(scan_timer);
+    let mut scans = FuturesUnordered::new();
+    let mut round = Vec::new();
+    let mut survey: Option<Survey> = None;
+    loop {
+        tokio::select! {
+            request = requests_rx.recv() => match request {
+                Some(Request::Watch(robot, interface, device, hostname)) => {
+                    links.insert((robot, interface), Link {
+                        device,
+                        hostname,
+                        samples: VecDeque::with_capacity(HISTORY_CAPACITY),
+                        weak: false,
+                        failures: 0,
+                    });
+                },
+                Some(Request::Unwatch(robot)) => links.retain(|(uuid, _), _| *uuid != robot),
+                Some(Request::Links(callback)) => {
+                    let mut response = Links::new();
+                    for ((robot, interface), link) in links.iter() {
+                        response.entry(*robot).or_default().insert(*interface, Status {
+                            addr: link.device.addr(),
+                            samples: link.samples.iter().cloned().collect(),
+                            weak: link.weak,
+                            failures: link.failures,
+                        });
+                    }
+                    if let Err(_) = callback.send(response) {
+                        log::error!("Could not respond with link status");
+                    }
+                },
+                Some(Request::Heatmap(callback)) => {
+                    if let Err(_) = callback.send(heatmap.clone()) {
+                        log::error!("Could not respond with link heatmap");
+                    }
+                },
+                Some(Request::ClearHeatmap) => {
+                    heatmap = Heatmap::new(&config.borrow().link_monitor);
+                },
+                Some(Request::Survey(callback)) => {
+                    if let Err(_) = callback.send(survey.clone()) {
+                        log::error!("Could not respond with Wi-Fi survey");
+                    }
+                },
+                None => break,
+            },
+            _ = interval.tick() => {
+                /* apply a change of the interval from the configuration */
+                let configured = Duration::from_secs_f64(config.borrow().link_monitor.interval.max(1.0));
+                if configured != period {
+                    period = configured;
+                    interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                }
+                /* skip a round while the previous round is still being polled */
+                if polls.is_empty() {
+                    let (callback_tx, callback_rx) = oneshot::channel();
+                    let request = optitrack::Request::Frame(optitrack::Stream::Filtered, callback_tx);
+                    if let Ok(_) = tracking_requests_tx.send(request) {
+                        frames.push(callback_rx);
+                    }
+                    for ((robot, interface), link) in links.iter() {
+                        let (robot, interface) = (*robot, *interface);
+                        let poll = link.device.clone().signal_strength();
+                        polls.push(async move { (robot, interface, poll.await) });
+                    }
+                }
+            },
+            _ = &mut scan_timer => {
+                let settings = config.borrow().link_monitor.clone();
+                scan_timer.as_mut().reset(tokio::time::Instant::now() + scan_period(&settings));
+                /* skip a round while the previous round is still being scanned */
+                if settings.scan_interval > 0.0 && scans.is_empty() {
+                    let devices = links.iter()
+                        .filter(|((_, interface), _)| *interface != Interface::Xbee)
+                        .filter_map(|(_, link)| match &link.device {
+                            Device::Fernbedienung(device) => Some(device.clone()),
+                            Device::Xbee(_) => None,
+                        })
+                        .choose_multiple(&mut rand::thread_rng(), settings.scan_robots);
+                    for device in devices {
+                        scans.push(async move {
+                            match tokio::time::timeout(SCAN_TIMEOUT, device.wifi_scan()).await {
+                                Ok(Ok(access_points)) => Some(access_points),
+                                Ok(Err(error)) => {
+                                    log::warn!("Could not scan the Wi-Fi from {}: {}", device.addr, error);
+                                    None
+                                },
+                                Err(_) => {
+                                    log::warn!("Could not scan the Wi-Fi from {}: timed out", device.addr);
+                                    None
+                                },
+                            }
+                        });
+                    }
+                }
+            },
+            Some(scan) = scans.next() => {
+                round.extend(scan);
+                if scans.is_empty() && !round.is_empty() {
+                    let update = Survey::new(round.drain(..).collect());
+                    for advisory in update.advisories(config.borrow().link_monitor.congested_channel) {
+                        log::warn!("{}", advisory);
+                    }
+                    survey = Some(update);
+                }
+            },
+            Some(frame) = frames.next() => {
+                poses = frame.unwrap_or_default();
+            },
+            Some((robot, interface, signal_strength)) = polls.next() => {
+                /* the robot may have been unwatched while it was polled */
+                if let Some(link) = links.get_mut(&(robot, interface)) {
+                    match signal_strength {
+                        Some(signal_strength) => {
+                            if link.samples.len() == HISTORY_CAPACITY {
+                                link.samples.pop_front();
+                            }
+                            link.samples.push_back(Sample::now(signal_strength as f32));
+                            link.failures = 0;
+                            let weak = signal_strength < threshold(&config.borrow().link_monitor, interface);
+                            if weak && !link.weak {
+                                log::warn!("Weak signal on the {} link of {}: {} dBm", interface, robot, signal_strength);
+                            }
+                            else if !weak && link.weak {
+                                log::info!("Signal on the {} link of {} recovered: {} dBm", interface, robot, signal_strength);
+                            }
+                            link.weak = weak;
+                            /* locate the sample using the rigid body of the robot */
+                            let config = config.borrow();
+                            let settings = &config.link_monitor;
+                            /* a change of the size of the cells starts a new heatmap */
+                            if Heatmap::new(settings).cell != heatmap.cell {
+                                heatmap = Heatmap::new(settings);
+                            }
+                            if interface != Interface::Xbee {
+                                let pose = link.hostname.as_ref()
+                                    .and_then(|hostname| settings.rigid_bodies.get(hostname))
+                                    .and_then(|rigid_body| poses.iter().find(|pose| pose.id == *rigid_body))
+                                    .filter(|pose| !pose.estimated);
+                                if let Some(pose) = pose {
+                                    heatmap.add(pose.position[0], pose.position[1], signal_strength);
+                                }
+                            }
+                        },
+                        None => link.failures += 1,
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{convert::Infallible, time::{Duration, SystemTime}};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{arena, optitrack, telemetry};
+
+/// Tracking frames streamed per second if the rate is not given
+const DEFAULT_RATE: f64 = 10.0;
+/// Upper bound on the tracking frames streamed per second
+const MAX_RATE: f64 = 100.0;
+/// Lower bound on the tracking frames streamed per second unless the tracking is disabled
+const MIN_RATE: f64 = 0.1;
+
+/// Query of the stream, e.g., /live?rate=30&stream=raw&telemetry=false
+#[derive(Debug, Deserialize)]
+pub struct Filter {
+    /// Tracking frames streamed per second, zero disables the tracking
+    rate: Option<f64>,
+    /// Either raw or filtered (the default) tracking
+    stream: Option<String>,
+    /// Stream the telemetry of the robots (the default)
+    telemetry: Option<bool>,
+}
+
+/// A line of the stream
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase", tag = "type")]
+enum Line {
+    Tracking {
+        /// Seconds since the UNIX epoch
+        timestamp: f64,
+        poses: Vec<optitrack::Pose>,
+    },
+    Telemetry(telemetry::Record),
+    /* the client fell behind and missed some of the telemetry */
+    Lagged {
+        skipped: u64,
+    },
+}
+
+fn timestamp() -> f64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
+
+fn line(line: &Line) -> Option<String> {
+    serde_json::to_string(line).ok().map(|mut line| {
+        line.push('\n');
+        line
+    })
+}
+
+/// Streams the tracking and the telemetry as newline-delimited JSON, e.g., for live analysis
+/// in a Jupyter notebook with `for line in requests.get(url, stream=True).iter_lines()`
+pub async fn stream(arena_request_tx: mpsc::UnboundedSender<arena::Request>, filter: Filter)
+    -> Result<impl warp::Reply, Infallible> {
+    let rate = filter.rate.unwrap_or(DEFAULT_RATE).min(MAX_RATE);
+    let tracking_stream = match filter.stream.as_deref() {
+        Some("raw") => optitrack::Stream::Raw,
+        _ => optitrack::Stream::Filtered,
+    };
+    /* subscribe before streaming so that no samples are missed */
+    let telemetry = match filter.telemetry.unwrap_or(true) {
+        true => {
+            let (subscribe_tx, subscribe_rx) = oneshot::channel();
+            match arena_request_tx.send(arena::Request::SubscribeTelemetry(subscribe_tx)) {
+                Ok(_) => subscribe_rx.await.ok(),
+                Err(_) => None,
+            }
+        },
+        false => None,
+    };
+    let telemetry = match telemetry {
+        Some(records) => BroadcastStream::new(records)
+            .filter_map(|record| futures::future::ready(match record {
+                Ok(record) => line(&Line::Telemetry(record)),
+                Err(tokio_stream::wrappers::errors::BroadcastStreamRecvError::Lagged(skipped)) =>
+                    line(&Line::Lagged { skipped }),
+            }))
+            .boxed(),
+        None => stream::empty().boxed(),
+    };
+    let tracking = match rate > 0.0 {
+        true => {
+            let interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate.max(MIN_RATE)));
+            stream::unfold((interval, arena_request_tx), move |(mut interval, arena_request_tx)| async move {
+                interval.tick().await;
+                let (frame_tx, frame_rx) = oneshot::channel();
+                arena_request_tx.send(arena::Request::GetTrackingFrame(tracking_stream, frame_tx)).ok()?;
+                let poses = frame_rx.await.ok()?;
+                Some((Line::Tracking { timestamp: timestamp(), poses }, (interval, arena_request_tx)))
+            })
+            .filter_map(|tracking| futures::future::ready(line(&tracking)))
+            .boxed()
+        },
+        false => stream::empty().boxed(),
+    };
+    let lines = stream::select(tracking, telemetry)
+        .map(Ok::<_, Infallible>);
+    Ok(warp::http::Response::builder()
+        .header("content-type", "application/x-ndjson")
+        .header("cache-control", "no-cache")
+        .body(warp::hyper::Body::wrap_stream(lines)))
+}
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, time::Instant};
+use uuid::Uuid;
+
+use crate::auth::{Auth, Permission};
+
+/// Time after which the lock of an operator that has disconnected is released and after
+/// which the session of a disconnected client can no longer be resumed
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Action {
+    #[serde(rename = "Lock")]
+    Acquire,
+    #[serde(rename = "Unlock")]
+    Release,
+    #[serde(rename = "Hand over")]
+    HandOver,
+}
+
+pub enum Request {
+    /* register a client, resuming the session with the given token if it has not expired */
+    Connect(Option<Uuid>, Option<SocketAddr>, oneshot::Sender<Session>),
+    Disconnect(Uuid),
+    Acquire(Uuid),
+    Release(Uuid),
+    /* hand the lock over from the first client to the second client */
+    HandOver(Uuid, Uuid),
+    /* check whether a client may perform an action that changes the testbed */
+    Authorize(Uuid, Permission, oneshot::Sender<bool>),
+    Status(oneshot::Sender<Status>),
+}
+
+/// The identity of a client that survives reconnecting
+#[derive(Clone, Copy, Debug)]
+pub struct Session {
+    pub client: Uuid,
+    /// Secret with which the client resumes the session, unlike the uuid of the client it is
+    /// not shown to the other clients
+    pub token: Uuid,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub holder: Option<(Uuid, Option<SocketAddr>)>,
+    pub clients: HashMap<Uuid, Option<SocketAddr>>,
+}
+
+/// Restricts the actions that change the testbed to the webui client of a single operator.
+/// While the testbed is not locked, all clients may perform these actions.
+#[derive(Default)]
+pub struct Lock {
+    holder: Option<(Uuid, Option<SocketAddr>)>,
+    disconnected_at: Option<Instant>,
+    clients: HashMap<Uuid, Option<SocketAddr>>,
+    /* sessions by token and the time at which their client disconnected */
+    sessions: HashMap<Uuid, (Uuid, Option<Instant>)>,
+}
+
+impl Lock {
+    fn expire(&mut self) {
+        self.sessions.retain(|_, (_, disconnected_at)| disconnected_at
+            .map_or(true, |disconnected_at| disconnected_at.elapsed() <= RELEASE_TIMEOUT));
+        if let Some(disconnected_at) = self.disconnected_at {
+            if disconnected_at.elapsed() > RELEASE_TIMEOUT {
+                if let Some((client, _)) = self.holder.take() {
+                    log::info!("Released the lock of disconnected operator {}", client);
+                }
+                self.disconnected_at = None;
+            }
+        }
+    }
+
+    fn is_holder(&self, client: &Uuid) -> bool {
+        self.holder.as_ref().map_or(false, |(holder, _)| holder == client)
+    }
+
+    /// Handles a request, the permissions of the clients are only checked if auth is given
+    pub fn handle(&mut self, request: Request, auth: Option<&Auth>) {
+        self.expire();
+        match request {
+            Request::Connect(token, addr, callback) => {
+                let resumed = token.and_then(|token| {
+                    let (client, disconnected_at) = self.sessions.get_mut(&token)?;
+                    /* a session can only be resumed by one client at a time */
+                    disconnected_at.take().map(|_| Session { client: *client, token })
+                });
+                let session = match resumed {
+                    Some(session) => {
+                        log::info!("Client {} resumed its session", session.client);
+                        if self.is_holder(&session.client) {
+                            self.holder = Some((session.client, addr));
+                            self.disconnected_at = None;
+                        }
+                        session
+                    },
+                    None => {
+                        let session = Session { client: Uuid::new_v4(), token: Uuid::new_v4() };
+                        self.sessions.insert(session.token, (session.client, None));
+                        session
+                    }
+                };
+                self.clients.insert(session.client, addr);
+                if let Err(_) = callback.send(session) {
+                    log::error!("Could not respond with session");
+                }
+            },
+            Request::Disconnect(client) => {
+                self.clients.remove(&client);
+                for (session_client, disconnected_at) in self.sessions.values_mut() {
+                    if *session_client == client {
+                        *disconnected_at = Some(Instant::now());
+                    }
+                }
+                if self.is_holder(&client) {
+                    log::warn!("Operator {} disconnected, releasing lock in {:?}", client, RELEASE_TIMEOUT);
+                    self.disconnected_at = Some(Instant::now());
+                }
+            },
+            Request::Acquire(client) => match &self.holder {
+                None => {
+                    log::info!("Testbed locked by operator {}", client);
+                    self.holder = Some((client, self.clients.get(&client).cloned().flatten()));
+                },
+                Some((holder, _)) if *holder != client =>
+                    log::warn!("Client {} cannot lock the testbed, it is locked by {}", client, holder),
+                Some(_) => {},
+            },
+            Request::Release(client) => {
+                if self.is_holder(&client) {
+                    log::info!("Testbed unlocked by operator {}", client);
+                    self.holder = None;
+                }
+            },
+            Request::HandOver(from, to) => {
+                if self.is_holder(&from) {
+                    match self.clients.get(&to) {
+                        Some(addr) => {
+                            log::info!("Operator {} handed the lock over to {}", from, to);
+                            self.holder = Some((to, *addr));
+                        },
+                        None => log::warn!("Could not find client {}", to),
+                    }
+                }
+                else {
+                    log::warn!("Client {} cannot hand over a lock that it does not hold", from);
+                }
+            },
+            Request::Authorize(client, permission, callback) => {
+                let addr = self.clients.get(&client).cloned().flatten();
+                let permitted = auth.map_or(true, |auth| auth.permits(addr, permission));
+                if !permitted {
+                    log::warn!("Client {} at {:?} does not have the {:?} permission", client, addr, permission);
+                }
+                let unlocked = self.holder.is_none() || self.is_holder(&client);
+                if !unlocked {
+                    log::warn!("Client {} cannot change the testbed, it is locked by another operator", client);
+                }
+                let authorized = permitted && unlocked;
+                if let Err(_) = callback.send(authorized) {
+                    log::error!("Could not respond with authorization");
+                }
+            },
+            Request::Status(callback) => {
+                let status = Status {
+                    holder: self.holder.clone(),
+                    clients: self.clients.clone(),
+                };
+                if let Err(_) = callback.send(status) {
+                    log::error!("Could not respond with lock status");
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::{convert::Infallible, time::SystemTime};
+use futures::StreamExt;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize, Serializer};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+/// Number of log entries that a slow client can fall behind before entries are skipped
+const STREAM_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: Level,
+    /// Module that logged the entry
+    pub target: String,
+    pub message: String,
+}
+
+pub type Sender = broadcast::Sender<Entry>;
+
+fn serialize_level<S: Serializer>(level: &Level, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(level)
+}
+
+/* writes to the terminal as before and shares the entries with the streaming clients */
+struct Tee {
+    logger: env_logger::Logger,
+    entries: Sender,
+}
+
+impl Log for Tee {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.logger.matches(record) {
+            self.logger.log(record);
+            /* there are no receivers while no client is streaming */
+            if self.entries.receiver_count() > 0 {
+                let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0.0, |duration| duration.as_secs_f64());
+                let _ = self.entries.send(Entry {
+                    timestamp,
+                    level: record.level(),
+                    target: record.target().to_owned(),
+                    message: record.args().to_string(),
+                });
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+/// Installs the logger and returns the sender from which the streaming clients subscribe
+pub fn init(mut builder: env_logger::Builder) -> Sender {
+    let logger = builder.build();
+    let (entries, _) = broadcast::channel(STREAM_CAPACITY);
+    log::set_max_level(logger.filter());
+    if let Err(error) = log::set_boxed_logger(Box::new(Tee { logger, entries: entries.clone() })) {
+        eprintln!("Could not install logger: {}", error);
+    }
+    entries
+}
+
+/// Query of the stream, e.g., /logs/stream?level=warn&module=mns_supervisor::arena
+#[derive(Debug, Deserialize)]
+pub struct Filter {
+    /// Least severe level that is streamed
+    level: Option<String>,
+    /// Prefix of the modules that are streamed
+    module: Option<String>,
+}
+
+/// Streams the log entries that match the filter as server-sent events
+pub fn stream(entries: &Sender, filter: Filter) -> impl warp::Reply {
+    let level = filter.level
+        .and_then(|level| level.parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::Trace);
+    let module = filter.module.unwrap_or_default();
+    let events = BroadcastStream::new(entries.subscribe())
+        .filter_map(move |entry| {
+            let event = match entry {
+                Ok(entry) => match entry.level <= level && entry.target.starts_with(&module) {
+                    true => serde_json::to_string(&entry).ok()
+                        .map(|data| warp::sse::Event::default().event("log").data(data)),
+                    false => None,
+                },
+                /* let the client know that it fell behind */
+                Err(error) => Some(warp::sse::Event::default().event("lagged").data(error.to_string())),
+            };
+            futures::future::ready(event.map(Ok::<_, Infallible>))
+        });
+    warp::sse::reply(warp::sse::keep_alive().stream(events))
+}
@@ -1,6 +1,5 @@
-use std::net::{Ipv4Addr, SocketAddr};
-use ipnet::Ipv4Net;
-use tokio::sync::mpsc;
+use std::{net::{IpAddr, Ipv4Addr, SocketAddr}, path::PathBuf, time::Duration};
+use tokio::{signal::unix::{signal, SignalKind}, sync::{mpsc, oneshot}};
 use warp::Filter;
 use structopt::StructOpt;
 
@@ -9,17 +8,150 @@ mod robot;
 mod network;
 mod webui;
 mod optitrack;
+mod calibration;
+mod region;
+mod formation;
+mod interlock;
+mod arming;
+mod push;
+mod logs;
+mod secrets;
 mod software;
 mod journal;
+mod clock;
 mod router;
+mod image;
+mod telemetry;
+mod config;
+mod plugin;
+mod dock;
+mod environment;
+mod flags;
+mod schema;
+mod command;
+mod compute;
+mod history;
+mod package;
+mod lock;
+mod auth;
+mod digest;
+mod campaign;
+mod provision;
+mod fleet;
+mod availability;
+mod link;
+mod sensing;
+mod demo;
+mod live;
+mod chaos;
+mod experiment;
+mod quota;
+mod tail;
+mod harvest;
+mod merge;
+mod replication;
+mod dedup;
+mod operation;
+mod selftest;
+mod hook;
+mod chat;
+mod redact;
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = "mns-supervisor", about = "A supervisor for the MNS experiments")]
+#[structopt(name = "mns-supervisor", about = "A supervisor for the MNS experiments",
+            setting = structopt::clap::AppSettings::SubcommandsNegateReqs)]
 struct Options {
+    /// Network that is probed for robots, optionally with the type of the devices on it so that
+    /// the other protocol is not tried, e.g., 10.0.1.0/24=xbee or 10.0.2.0/24=fernbedienung. This
+    /// option can be given more than once.
+    #[structopt(long, required_unless = "demo", number_of_values = 1)]
+    network: Vec<network::Subnet>,
+
+    /// JSON file with the configuration that is reloaded on SIGHUP
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// JSON file describing the expected software image of the robots
+    #[structopt(long, parse(from_os_str))]
+    golden_image: Option<PathBuf>,
+
+    /// File to which the telemetry history is periodically written
+    #[structopt(long, parse(from_os_str))]
+    telemetry_file: Option<PathBuf>,
+
+    /// File in which the connection history of the robots is stored
+    #[structopt(long, parse(from_os_str))]
+    availability_file: Option<PathBuf>,
+
+    /// File in which the calibration of the arena frame is stored
+    #[structopt(long, parse(from_os_str))]
+    calibration_file: Option<PathBuf>,
+
+    /// Niceness of the message router thread (negative values increase its priority)
+    #[structopt(long, allow_hyphen_values = true)]
+    router_niceness: Option<i32>,
+
+    /// Number of Pi-Pucks that are simulated on this machine
+    #[structopt(long, default_value = "0")]
+    virtual_pipucks: usize,
+
+    /// Number of drones that are simulated on this machine
+    #[structopt(long, default_value = "0")]
+    virtual_drones: usize,
+
+    /// Maximum number of addresses that are probed concurrently, unless the configuration sets
+    /// max_probe_concurrency
+    #[structopt(long, default_value = "64")]
+    probe_concurrency: usize,
+
+    /// Run as a service, e.g., under systemd, without opening a browser
+    #[structopt(long)]
+    service: bool,
+
+    /// Do not open the web interface in a browser
+    #[structopt(long)]
+    no_browser: bool,
+
+    /// Seconds to wait before opening the web interface in a browser
+    #[structopt(long, default_value = "0")]
+    browser_delay: f64,
+
+    /// Command for opening the web interface instead of the default browser, e.g., firefox
     #[structopt(long)]
-    network: Ipv4Net,
+    browser: Option<String>,
+
+    /// File of secrets encrypted with openssl enc -aes-256-cbc -pbkdf2
+    #[structopt(long, parse(from_os_str))]
+    secrets: Option<PathBuf>,
+
+    /// JSON file of a scenario that is played back without any hardware, the network is not
+    /// probed for robots unless --network is also given
+    #[structopt(long, parse(from_os_str))]
+    demo: Option<PathBuf>,
+
+    /// Address of the web interface of a primary supervisor, e.g., http://10.0.0.1:3030, whose
+    /// state is replicated until it stops responding and this supervisor takes over
+    #[structopt(long)]
+    standby: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Test the supervisor end to end and exit, the exit status is non-zero if a step failed
+    Selftest {
+        /// Hostname of a robot to test against instead of the mock fernbedienung service, the
+        /// robot must be on a network given with --network
+        #[structopt(long)]
+        robot: Option<String>,
+    },
 }
 
+/// Time given to the arena for stopping the experiment and persisting its state on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 // stream video only while connections tab is open, close when we move to the experiment tab (avoids conflicts with ARGoS)
 // I want to send simple JPEG frames using intra-frame compression only, i.e., raw frames from device
 // fswebcam will send images to stdout
@@ -33,63 +165,458 @@ async fn main() {
     let options = Options::from_args();
     /* initialize the logger */
     let environment = env_logger::Env::default().default_filter_or("mns_supervisor=info");
-    env_logger::Builder::from_env(environment).format_timestamp_millis().init();
+    let mut logger = env_logger::Builder::from_env(environment);
+    logger.format_timestamp_millis();
+    let log_entries = logs::init(logger);
+    /* load the golden image profile */
+    let golden_image = match options.golden_image {
+        Some(path) => match image::Image::load(&path) {
+            Ok(golden_image) => Some(golden_image),
+            Err(error) => {
+                log::error!("Could not load golden image {}: {}", path.display(), error);
+                return;
+            }
+        },
+        None => None,
+    };
+    /* decrypt the secrets, prompting for the passphrase if necessary */
+    let secrets = match options.secrets {
+        Some(path) => match secrets::Store::load(&path) {
+            Ok(secrets) => secrets,
+            Err(error) => {
+                log::error!("Could not load secrets from {}: {}", path.display(), error);
+                return;
+            }
+        },
+        None => secrets::Store::default(),
+    };
+    /* load the demo scenario */
+    let scenario = match options.demo {
+        Some(path) => match demo::Scenario::load(&path) {
+            Ok(scenario) => Some(scenario),
+            Err(error) => {
+                log::error!("Could not load demo scenario {}: {}", path.display(), error);
+                return;
+            }
+        },
+        None => None,
+    };
+    /* load the configuration */
+    let (config, config_reload_tx, config_task) = match config::new(options.config) {
+        Ok(config) => config,
+        Err(error) => {
+            log::error!("Could not load configuration: {}", error);
+            return;
+        }
+    };
+    /* replicate the state of the primary supervisor until it fails, then take over */
+    let restored = match options.standby {
+        Some(primary) => tokio::select! {
+            result = replication::standby(primary, config.clone()) => match result {
+                Ok(snapshot) => Some(snapshot),
+                Err(error) => {
+                    log::error!("Could not stand by for the primary supervisor: {}", error);
+                    return;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => return,
+        },
+        None => None,
+    };
+    /* register plugins, e.g., plugins.register(ChargerDockMonitor::default()) */
+    let plugins = plugin::Registry::default();
+    /* create the relay through which the message router shares messages between robots */
+    let relay = router::Relay::new();
     /* create a task for tracking the robots and state of the experiment */
     let (arena_requests_tx, arena_requests_rx) = mpsc::unbounded_channel();
-    let (journal_requests_tx, journal_requests_rx) = mpsc::unbounded_channel();
-    /* listen for the ctrl-c shutdown signal */
+    let (telemetry_requests_tx, telemetry_requests_rx) = mpsc::unbounded_channel();
+    let (dock_requests_tx, dock_requests_rx) = mpsc::unbounded_channel();
+    let (environment_requests_tx, environment_requests_rx) = mpsc::unbounded_channel();
+    let (campaign_requests_tx, campaign_requests_rx) = mpsc::unbounded_channel();
+    let (tracking_requests_tx, tracking_requests_rx) = mpsc::unbounded_channel();
+    let (arming_requests_tx, arming_requests_rx) = mpsc::unbounded_channel();
+    let (push_requests_tx, push_requests_rx) = mpsc::unbounded_channel();
+    let (provision_requests_tx, provision_requests_rx) = mpsc::unbounded_channel();
+    let (availability_requests_tx, availability_requests_rx) = mpsc::unbounded_channel();
+    let (link_requests_tx, link_requests_rx) = mpsc::unbounded_channel();
+    let (sensing_requests_tx, sensing_requests_rx) = mpsc::unbounded_channel();
+    let (operations_tx, operations_rx) = mpsc::unbounded_channel();
+    let (selftest_requests_tx, selftest_requests_rx) = mpsc::unbounded_channel();
+    let (network_requests_tx, network_requests_rx) = mpsc::unbounded_channel();
+    let (tunnel_requests_tx, tunnel_requests_rx) = mpsc::unbounded_channel();
+    /* listen for the ctrl-c and the terminate (e.g., from systemd) shutdown signals */
     let sigint_task = tokio::signal::ctrl_c();
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(error) => {
+            log::error!("Could not listen for the terminate signal: {}", error);
+            return;
+        }
+    };
+    /* the addresses are only read when the supervisor starts */
+    let (webui_addr, message_router_addr) = {
+        let config = config.borrow();
+        (config.webui_address, config.router_address)
+    };
     /* create journal task */
-    let journal_task = journal::new(journal_requests_rx);
+    let (journal, journal_task) = journal::new();
+    /* create the task for polling the charging docks */
+    let dock_task = dock::new(dock_requests_rx, config.clone());
+    /* create the task for controlling the environment during experiments */
+    let environment_task = environment::new(environment_requests_rx, config.clone(), journal.clone());
+    /* create the task for receiving and filtering the tracking data */
+    let calibration = calibration::Calibration::load(options.calibration_file);
+    let tracking_task = optitrack::new(tracking_requests_rx, config.clone(), journal.clone(), calibration);
+    /* create the task for monitoring the arming switch */
+    let arming_task = arming::new(arming_requests_rx, config.clone(), journal.clone());
+    /* create the task for pushing critical alerts to the operators */
+    let push_task = push::new(push_requests_rx, config.clone(), secrets.clone());
+    /* create the task for provisioning freshly flashed robots */
+    let provision_task = provision::new(provision_requests_rx, config.clone(), secrets);
+    /* create telemetry task */
+    let telemetry_task = telemetry::new(telemetry_requests_rx, options.telemetry_file);
+    /* create the task for tracking the availability of the robots */
+    let availability_task = availability::new(availability_requests_rx, options.availability_file);
+    /* create the task for monitoring the signal strength of the links to the robots */
+    let link_task = link::new(link_requests_rx, config.clone(), tracking_requests_tx.clone());
+    /* create the task for distributing the tracking data to the robots for virtual sensing */
+    let sensing_task = sensing::new(sensing_requests_rx, config.clone(), tracking_requests_tx.clone());
+    /* create the task for tracking long-running operations */
+    let operation_task = operation::new(operations_rx);
+    /* create the task that connects the robots with remote brains to the compute servers */
+    let tunnel_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), compute::tunnel::PORT);
+    let tunnel_task = compute::tunnel::new(tunnel_addr, tunnel_requests_rx);
     /* create arena task */
-    let arena_task = arena::new(arena_requests_rx, &journal_requests_tx);
-    /* create network task */
-    let network_task = network::new(options.network, &arena_requests_tx);
+    let demo_channels = (tracking_requests_tx.clone(), telemetry_requests_tx.clone());
+    let arena_task = arena::new(arena_requests_rx,
+                                &journal,
+                                arena::Services {
+                                    telemetry: telemetry_requests_tx,
+                                    dock: dock_requests_tx,
+                                    environment: environment_requests_tx,
+                                    campaign: campaign_requests_tx,
+                                    selftest: selftest_requests_tx.clone(),
+                                    tracking: tracking_requests_tx,
+                                    arming: arming_requests_tx,
+                                    push: push_requests_tx,
+                                    provision: provision_requests_tx,
+                                    availability: availability_requests_tx,
+                                    link: link_requests_tx,
+                                    sensing: sensing_requests_tx,
+                                    operations: operations_tx.clone(),
+                                    network: network_requests_tx,
+                                    tunnel: tunnel_requests_tx,
+                                },
+                                golden_image,
+                                config.clone(),
+                                config_reload_tx,
+                                plugins.clone(),
+                                relay.clone());
+    /* add the simulated robots */
+    let (demo_pipucks, demo_drones) = scenario.as_ref()
+        .map_or((0, 0), |scenario| (scenario.pipucks, scenario.drones));
+    let virtual_robots = std::iter::repeat(arena::Kind::PiPuck).take(options.virtual_pipucks + demo_pipucks)
+        .chain(std::iter::repeat(arena::Kind::Drone).take(options.virtual_drones + demo_drones));
+    for kind in virtual_robots {
+        if let Err(error) = arena_requests_tx.send(arena::Request::AddVirtualRobot(kind)) {
+            log::error!("Could not add virtual robot: {}", error);
+        }
+    }
+    /* resume from the state of the primary supervisor */
+    if let Some(snapshot) = restored {
+        if let Err(error) = arena_requests_tx.send(arena::Request::Restore(snapshot)) {
+            log::error!("Could not restore the state of the primary supervisor: {}", error);
+        }
+    }
+    /* create task for rolling out updates to the robots */
+    let campaign_task = campaign::new(campaign_requests_rx, arena_requests_tx.clone(), operations_tx.clone());
+    /* create the task for testing the supervisor end to end */
+    let selftest_task = selftest::new(selftest_requests_rx, arena_requests_tx.clone(), config.clone());
+    /* run the self test if it was requested on the command line, the supervisor then exits */
+    let selftest_report_rx = options.command.map(|Command::Selftest { robot }| {
+        let target = robot.map_or(selftest::Target::Mock, selftest::Target::Robot);
+        let (report_tx, report_rx) = oneshot::channel();
+        if let Err(error) = selftest_requests_tx.send(selftest::Request::Run(target, Some(report_tx))) {
+            log::error!("Could not start the self test: {}", error);
+        }
+        report_rx
+    });
+    let headless = selftest_report_rx.is_some();
+    let mut report = None;
+    let selftest_report = async {
+        match selftest_report_rx {
+            Some(report_rx) => report_rx.await.ok(),
+            None => futures::future::pending().await,
+        }
+    };
+    /* create the task for playing back the demo scenario */
+    let demo_task = {
+        let (tracking_requests_tx, telemetry_requests_tx) = demo_channels;
+        let arena_requests_tx = arena_requests_tx.clone();
+        async move {
+            match scenario {
+                Some(scenario) =>
+                    demo::new(scenario, arena_requests_tx, tracking_requests_tx, telemetry_requests_tx).await,
+                None => futures::future::pending().await,
+            }
+        }
+    };
+    /* create task for emailing a digest of the testbed activities */
+    let digest_task = digest::new(arena_requests_tx.clone(), config.clone());
+    /* the public key with which the browsers subscribe to the critical alerts */
+    let push_config = config.clone();
+    let push_key_route = warp::path!("push" / "key")
+        .and(warp::get())
+        .map(move || match push_config.borrow().push.as_ref() {
+            Some(settings) => warp::reply::with_status(settings.public_key.clone(), warp::http::StatusCode::OK),
+            None => warp::reply::with_status(String::new(), warp::http::StatusCode::NOT_FOUND),
+        });
+    /* the state of the arena for a standby supervisor */
+    let replication_config = config.clone();
+    let socket_config = config.clone();
+    /* create network task, which runs even without networks to scan since devices can be
+       registered manually */
+    let (network, probe_concurrency) = (options.network, options.probe_concurrency);
+    let network_task =
+        network::new(network, network_requests_rx, &arena_requests_tx, probe_concurrency, journal.clone(), config);
+    /* create task for the periodic work of the plugins */
+    let plugin_task = plugin::new(plugins.clone(), arena_requests_tx.clone());
     /* create message router task */
-    let message_router_addr : SocketAddr = (Ipv4Addr::UNSPECIFIED, 4950).into();
-    let router_task = router::new(message_router_addr, journal_requests_tx.clone());
+    let router_task = match router::spawn(message_router_addr, journal.clone(), relay, options.router_niceness) {
+        Ok(router_task) => router_task,
+        Err(error) => {
+            log::error!("Could not start the message router: {}", error);
+            return;
+        }
+    };
+    /* for shutting down the arena on the ctrl-c and terminate signals */
+    let shutdown_requests_tx = arena_requests_tx.clone();
     /* create webui task */
+    /* the HTTP routes that expose the testbed are checked against the roles of the clients */
+    let permitted = {
+        let config = socket_config.clone();
+        move |permission: auth::Permission| webui::permitted(config.clone(), permission)
+    };
     /* clone arena requests tx for moving into the closure */
     let arena_requests_tx = arena_requests_tx.clone();
     let arena_channel = warp::any().map(move || arena_requests_tx.clone());
+    let dashboard_channel = arena_channel.clone();
+    let ready_channel = arena_channel.clone();
+    let live_channel = arena_channel.clone();
+    let tail_channel = arena_channel.clone();
+    let replication_channel = arena_channel.clone();
+    let robots_channel = arena_channel.clone();
+    let operations_channel = warp::any().map(move || operations_tx.clone());
+    let plugin_registry = warp::any().map(move || plugins.clone());
     let socket_route = warp::path("socket")
         .and(warp::ws())
         .and(arena_channel)
+        .and(operations_channel.clone())
+        .and(plugin_registry)
+        .and(warp::any().map(move || socket_config.clone()))
+        .and(warp::addr::remote())
+        .and(warp::query::<webui::Resume>())
+        .map(|websocket: warp::ws::Ws, arena_requests_tx, operations_tx, plugins, config, addr, resume| {
+            websocket.on_upgrade(move |socket| webui::run(socket, arena_requests_tx, operations_tx, plugins, config, addr, resume))
+        });
+    /* read-only dashboard, e.g., for a monitor in the hallway */
+    let dashboard_socket_route = warp::path!("dashboard" / "socket")
+        .and(warp::ws())
+        .and(dashboard_channel)
         .map(|websocket: warp::ws::Ws, arena_requests_tx| {
-            websocket.on_upgrade(move |socket| webui::run(socket, arena_requests_tx))
+            websocket.on_upgrade(move |socket| webui::dashboard(socket, arena_requests_tx))
         });
+    let dashboard_route = warp::path!("dashboard")
+        .and(warp::get())
+        .map(|| warp::reply::html(include_str!("../static/dashboard.html")));
+    /* live log entries, e.g., for following the supervisor when it runs as a service */
+    let log_stream_route = warp::path!("logs" / "stream")
+        .and(warp::get())
+        .and(permitted(auth::Permission::Monitor))
+        .and(warp::query::<logs::Filter>())
+        .map(move |filter| logs::stream(&log_entries, filter));
+    /* live tracking and telemetry as newline-delimited JSON, e.g., for a Jupyter notebook */
+    let live_data_route = warp::path!("live")
+        .and(warp::get())
+        .and(permitted(auth::Permission::Monitor))
+        .and(live_channel)
+        .and(warp::query::<live::Filter>())
+        .and_then(live::stream);
+    /* follow a file that a controller writes on a robot, e.g., /tail/<uuid>?path=output.csv */
+    let tail_route = warp::path!("tail" / uuid::Uuid)
+        .and(warp::get())
+        .and(permitted(auth::Permission::Maintenance))
+        .and(warp::query::<tail::Query>())
+        .and(tail_channel)
+        .and_then(|uuid, query, arena_requests_tx| tail::stream(arena_requests_tx, uuid, query));
+    /* the robots that match a selection as JSON, e.g., /robots?kind=PiPuck&lifecycle=Ready */
+    let robots_route = warp::path!("robots")
+        .and(warp::get())
+        .and(permitted(auth::Permission::Monitor))
+        .and(robots_channel)
+        .and(warp::query::<webui::Selection>())
+        .and_then(webui::robots);
+    /* the state of the arena for a standby supervisor, see replication.rs */
+    let replication_route = warp::path!("replication")
+        .and(warp::get())
+        .and(replication_channel)
+        .and(warp::any().map(move || replication_config.clone()))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(replication::serve);
+    /* the running and recent operations as JSON, the operation task answers even while the arena
+       is busy starting an experiment */
+    let operations_route = warp::path!("operations")
+        .and(warp::get())
+        .and(permitted(auth::Permission::Monitor))
+        .and(operations_channel)
+        .and_then(operation::serve);
+    /* liveness and readiness checks for process supervisors */
+    let live_route = warp::path!("health" / "live")
+        .and(warp::get())
+        .map(|| "live");
+    let ready_route = warp::path!("health" / "ready")
+        .and(warp::get())
+        .and(ready_channel)
+        .and_then(webui::ready);
     let static_route = warp::get()
         .and(static_dir::static_dir!("static"));
     //    .and(warp::fs::dir("/home/mallwright/Workspace/mns-supervisor/static"));
-    let server_addr : SocketAddr = (Ipv4Addr::LOCALHOST, 3030).into();
-    let webui_task = warp::serve(socket_route.or(static_route)).run(server_addr);
+    let routes = socket_route
+        .or(dashboard_socket_route)
+        .or(dashboard_route)
+        .or(push_key_route)
+        .or(log_stream_route)
+        .or(live_data_route)
+        .or(tail_route)
+        .or(robots_route)
+        .or(replication_route)
+        .or(operations_route)
+        .or(live_route)
+        .or(ready_route)
+        .or(static_route)
+        .recover(webui::forbidden);
+    let webui_task = warp::serve(routes).run(webui_addr);
     /* pin the futures so that they can be polled via &mut */
     tokio::pin!(arena_task);
     tokio::pin!(journal_task);
+    tokio::pin!(telemetry_task);
+    tokio::pin!(config_task);
+    tokio::pin!(plugin_task);
+    tokio::pin!(dock_task);
+    tokio::pin!(digest_task);
+    tokio::pin!(campaign_task);
+    tokio::pin!(environment_task);
+    tokio::pin!(tracking_task);
+    tokio::pin!(arming_task);
+    tokio::pin!(push_task);
+    tokio::pin!(provision_task);
+    tokio::pin!(availability_task);
+    tokio::pin!(link_task);
+    tokio::pin!(sensing_task);
+    tokio::pin!(operation_task);
+    tokio::pin!(tunnel_task);
+    tokio::pin!(selftest_task);
+    tokio::pin!(selftest_report);
     tokio::pin!(network_task);
+    tokio::pin!(demo_task);
     tokio::pin!(webui_task);
     tokio::pin!(sigint_task);
-
-    let mut router_task = tokio::spawn(router_task);
+    tokio::pin!(router_task);
     /* no point in implementing automatic browser opening */
     /* https://bugzilla.mozilla.org/show_bug.cgi?id=1512438 */
-    let server_addr = format!("http://{}/", server_addr);
-    if let Err(_) = webbrowser::open(&server_addr) {
-        log::warn!("Could not start browser");
-        log::info!("Please open this URL manually: {}", server_addr);
-    };
+    let server_addr = format!("http://{}/", webui_addr);
+    log::info!("Serving the web interface at {}", server_addr);
+    if !(options.service || options.no_browser || headless) {
+        let delay = Duration::from_secs_f64(options.browser_delay.max(0.0));
+        let browser = options.browser;
+        /* the web interface reconnects by itself, so the browser can be opened before the server */
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let opened = match browser {
+                Some(browser) => tokio::process::Command::new(&browser)
+                    .arg(&server_addr)
+                    .spawn()
+                    .map(|_| ())
+                    .map_err(|error| log::warn!("Could not start {}: {}", browser, error)),
+                None => webbrowser::open(&server_addr)
+                    .map(|_| ())
+                    .map_err(|error| log::warn!("Could not start browser: {}", error)),
+            };
+            if let Err(_) = opened {
+                log::info!("Please open this URL manually: {}", server_addr);
+            }
+        });
+    }
     
-    tokio::select! {
-        _ = &mut arena_task => {},
-        _ = &mut journal_task => {},
-        _ = &mut network_task => {},
-        _ = &mut router_task => {},
-        _ = &mut webui_task => {},
-        _ = &mut sigint_task => {
-            /* TODO: is it safe to do this? should messages be broadcast to robots */
-            /* what happens if ARGoS is running on the robots, does breaking the
-            connection to fernbedienung kill ARGoS? How does the Pixhawk respond */
-            log::info!("Shutting down");
+    let shutdown = tokio::select! {
+        _ = &mut arena_task => false,
+        _ = &mut journal_task => false,
+        _ = &mut telemetry_task => false,
+        _ = &mut config_task => false,
+        _ = &mut plugin_task => false,
+        _ = &mut dock_task => false,
+        _ = &mut digest_task => false,
+        _ = &mut campaign_task => false,
+        _ = &mut environment_task => false,
+        _ = &mut tracking_task => false,
+        _ = &mut arming_task => false,
+        _ = &mut push_task => false,
+        _ = &mut provision_task => false,
+        _ = &mut availability_task => false,
+        _ = &mut link_task => false,
+        _ = &mut sensing_task => false,
+        _ = &mut operation_task => false,
+        _ = &mut tunnel_task => false,
+        _ = &mut selftest_task => false,
+        result = &mut selftest_report => {
+            report = result;
+            true
+        },
+        _ = &mut network_task => false,
+        _ = &mut demo_task => false,
+        _ = &mut router_task => false,
+        _ = &mut webui_task => false,
+        _ = &mut sigint_task => true,
+        _ = sigterm.recv() => true,
+    };
+    /* TODO: is it safe to do this? should messages be broadcast to robots */
+    /* what happens if ARGoS is running on the robots, does breaking the
+    connection to fernbedienung kill ARGoS? How does the Pixhawk respond */
+    log::info!("Shutting down");
+    if shutdown {
+        /* let the arena stop the experiment and persist the telemetry, the tasks that it depends
+           on must be polled until it is done */
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        if let Err(error) = shutdown_requests_tx.send(arena::Request::Shutdown(shutdown_tx)) {
+            log::error!("Could not shut down the arena: {}", error);
+            return;
+        }
+        let shutdown = tokio::time::timeout(SHUTDOWN_TIMEOUT, shutdown_rx);
+        tokio::pin!(shutdown);
+        tokio::select! {
+            result = &mut shutdown => if let Err(_) = result {
+                log::warn!("Arena did not shut down within {:?}", SHUTDOWN_TIMEOUT);
+            },
+            _ = &mut arena_task => {},
+            _ = &mut journal_task => {},
+            _ = &mut telemetry_task => {},
+            _ = &mut environment_task => {},
         }
     }
-}
\ No newline at end of file
+    /* the outcome of a self test that was run from the command line */
+    if headless {
+        match report {
+            Some(report) => {
+                println!("{}", report);
+                if !report.passed() {
+                    std::process::exit(1);
+                }
+            },
+            None => {
+                log::error!("The self test did not complete");
+                std::process::exit(1);
+            }
+        }
+    }
+}
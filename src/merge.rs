@@ -0,0 +1,110 @@
+use std::{collections::HashMap, fs, io, time::{SystemTime, UNIX_EPOCH}};
+use uuid::Uuid;
+
+use crate::harvest;
+
+/// Column of a result that holds the time on the clock of the robot in seconds since the Unix epoch
+const TIME_COLUMN: &str = "time";
+/// Columns that are prepended to each row of a combined dataset
+const ROBOT_COLUMN: &str = "robot";
+const EXPERIMENT_TIME_COLUMN: &str = "experiment_time";
+
+/* splits a line of a CSV file into its fields, fields can be quoted but cannot contain line breaks */
+fn split_record(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut characters = line.chars().peekable();
+    while let Some(character) = characters.next() {
+        match (character, quoted) {
+            ('"', true) if characters.peek() == Some(&'"') => {
+                characters.next();
+                field.push('"');
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(character),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn join_record<'a, I: IntoIterator<Item = &'a str>>(fields: I) -> String {
+    fields.into_iter()
+        .map(|field| match field.contains(&[',', '"'][..]) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The name of the combined dataset of a pattern, e.g., `output/*.csv` becomes `output_all.csv`
+pub fn dataset_name(pattern: &str) -> String {
+    pattern.chars()
+        .map(|character| match character {
+            '/' | '?' | '[' | ']' => "_".to_owned(),
+            '*' => "all".to_owned(),
+            _ => character.to_string(),
+        })
+        .collect()
+}
+
+/// Merges the CSV files that the robots collected for each pattern into one dataset per pattern
+/// in the directory of the run. Each row is prefixed with the robot and, if the file has a time
+/// column, the seconds since the start of the run after correcting for the clock of the robot.
+pub fn merge(run: &Uuid, started: SystemTime, reports: &HashMap<Uuid, harvest::Report>) -> io::Result<Vec<String>> {
+    let started = started.duration_since(UNIX_EPOCH)
+        .map_or(0.0, |started| started.as_secs_f64());
+    /* the files of each pattern by robot */
+    let mut datasets : HashMap<&str, Vec<(&Uuid, &str, f64)>> = HashMap::new();
+    for (robot, report) in reports {
+        for (pattern, file) in report.files.iter().filter(|(pattern, _)| pattern.ends_with(".csv")) {
+            datasets.entry(pattern.as_str())
+                .or_default()
+                .push((robot, file.as_str(), report.clock_offset.unwrap_or(0.0)));
+        }
+    }
+    let mut merged = Vec::new();
+    for (pattern, mut files) in datasets {
+        files.sort_by(|left, right| (left.0, left.1).cmp(&(right.0, right.1)));
+        let mut header : Vec<String> = Vec::new();
+        let mut rows : Vec<(String, String, HashMap<String, String>)> = Vec::new();
+        for (robot, file, clock_offset) in files {
+            let contents = fs::read_to_string(harvest::destination(run, robot).join(file))?;
+            let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+            let columns = match lines.next() {
+                Some(line) => split_record(line),
+                None => continue,
+            };
+            for column in columns.iter() {
+                if !header.contains(column) {
+                    header.push(column.clone());
+                }
+            }
+            for line in lines {
+                let row = columns.iter().cloned()
+                    .zip(split_record(line))
+                    .collect::<HashMap<_, _>>();
+                let experiment_time = row.get(TIME_COLUMN)
+                    .and_then(|time| time.trim().parse::<f64>().ok())
+                    .map_or_else(String::new, |time| format!("{:.6}", time - clock_offset - started));
+                rows.push((robot.to_string(), experiment_time, row));
+            }
+        }
+        let mut dataset = join_record([ROBOT_COLUMN, EXPERIMENT_TIME_COLUMN].iter().cloned()
+            .chain(header.iter().map(String::as_str)));
+        dataset.push('\n');
+        for (robot, experiment_time, row) in rows.iter() {
+            dataset += &join_record([robot.as_str(), experiment_time.as_str()].iter().cloned()
+                .chain(header.iter().map(|column| row.get(column).map_or("", String::as_str))));
+            dataset.push('\n');
+        }
+        let name = dataset_name(pattern);
+        fs::write(harvest::run_dir(run).join(&name), dataset)?;
+        merged.push(name);
+    }
+    merged.sort();
+    Ok(merged)
+}
@@ -1,57 +1,290 @@
 use std::net::Ipv4Addr;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use mpsc::UnboundedSender;
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use tokio::sync::{mpsc::{self, UnboundedReceiver}, oneshot};
+use tokio::sync::{broadcast, mpsc::{self, UnboundedReceiver}, oneshot};
 use uuid::Uuid;
-use futures::{self, FutureExt, StreamExt, stream::FuturesUnordered};
+use futures::{self, Future, FutureExt, Stream, StreamExt, stream::{BoxStream, FuturesUnordered}};
 
-use tokio::net::TcpStream;
-use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use tokio::{io::{AsyncRead, AsyncWrite}, net::TcpStream};
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec, LengthDelimitedCodecError};
 use tokio_serde::{SymmetricallyFramed, formats::SymmetricalJson};
 use regex::Regex;
+use serde::Deserialize;
 
-mod protocol;
+use crate::chaos;
+use crate::journal;
 
-pub use protocol::{Upload, process::Process};
+mod tls;
+
+pub use tls::Tls;
+
+use mns_protocol::fernbedienung as protocol;
+
+pub use protocol::{LogLevel, Upload, process::{ExitStatus, Limits, Process}};
+
+const ARGOS_DEFAULT_PLUGIN_PATH: &str = "/usr/lib/argos3";
+/// Maximum length of a response from a robot, a longer frame drops the connection to the robot
+const MAX_RESPONSE_LENGTH: usize = 1 << 20;
+/// Maximum length of a request to a robot, uploads are sent as arrays of JSON numbers and need
+/// roughly four bytes per byte of the file
+const MAX_REQUEST_LENGTH: usize = 64 << 20;
+/// Maximum size of a chunk of a chunked upload, which is sent as base64
+const UPLOAD_CHUNK_SIZE: usize = 256 << 10;
+/// Number of chunks of a chunked upload that can be sent before they are acknowledged
+const UPLOAD_WINDOW: usize = 4;
+/// Time given to the requests for terminating the running processes to be sent before the
+/// connection to a dropped device is closed
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Time to wait before the first attempt to re-establish a lost connection, the time doubles
+/// after each failed attempt
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_millis(500);
+/// Maximum time between the attempts to re-establish a lost connection
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(10);
 
 lazy_static::lazy_static! {
     static ref REGEX_LINK_STRENGTH: Regex = 
         Regex::new(r"signal:\s+(-\d+)\s+dBm+").unwrap();
+    static ref REGEX_OS_RELEASE: Regex =
+        Regex::new(r#"PRETTY_NAME="?([^"\n]*)"?"#).unwrap();
+    static ref REGEX_PACKAGE_VERSION: Regex =
+        Regex::new(r"^(\S+) - (\S+)").unwrap();
+    static ref REGEX_ARGOS_PLUGIN_PATH: Regex =
+        Regex::new(r"(/\S*/lib/argos3)\b").unwrap();
+    static ref REGEX_BSS: Regex =
+        Regex::new(r"^BSS ([[:xdigit:]:]{17})").unwrap();
+    static ref REGEX_RPI_TEMPERATURE: Regex =
+        Regex::new(r"temp=(\d+(?:\.\d+)?)'C").unwrap();
+    static ref REGEX_RPI_THROTTLED: Regex =
+        Regex::new(r"throttled=0x([[:xdigit:]]+)").unwrap();
 }
 
+/// Bits of `vcgencmd get_throttled` that are set while the Raspberry Pi is thermally throttled:
+/// the frequency is capped, the processor is throttled, or the soft temperature limit is active
+const RPI_THROTTLED_MASK: u32 = 0x2 | 0x4 | 0x8;
+
+/* prints the raw reading and the scale (mV) of the first channel of the ADS1015 that measures the
+   voltage of the battery of a Pi-Puck */
+const BATTERY_SCRIPT: &str = r#"for device in /sys/bus/iio/devices/iio:device*; do
+  if [ "$(cat $device/name)" = ads1015 ]; then
+    echo "$(cat $device/in_voltage0_raw) $(cat $device/in_voltage0_scale)"
+    exit 0
+  fi
+done
+exit 1"#;
+
+/// Voltage (mV) of a single cell battery when it is empty and when it is full
+const BATTERY_EMPTY_MV: f32 = 3500.0;
+const BATTERY_FULL_MV: f32 = 4050.0;
+
+/* prints the metrics of the system as keys and values, the amounts of memory and disk space are
+   in kilobytes and the temperature is in millidegrees Celsius */
+const SYSTEM_INFO_SCRIPT: &str = r#"read load rest < /proc/loadavg
+echo "load $load"
+echo "cpus $(nproc)"
+awk '/^MemTotal:/ { print "memory_total", $2 } /^MemAvailable:/ { print "memory_available", $2 }' /proc/meminfo
+[ -r /sys/class/thermal/thermal_zone0/temp ] && echo "temperature $(cat /sys/class/thermal/thermal_zone0/temp)"
+df -Pk / | awk 'NR == 2 { print "disk_available", $4 }'"#;
+
+/* prints the temperature of each thermal zone in millidegrees Celsius followed by the
+   temperature of its passive trip point, if it has one */
+const THERMAL_ZONES_SCRIPT: &str = r#"for zone in /sys/class/thermal/thermal_zone*; do
+    passive=""
+    for type in "$zone"/trip_point_*_type; do
+        [ "$(cat "$type" 2>/dev/null)" = passive ] && passive=$(cat "${type%_type}_temp")
+    done
+    echo "$(cat "$zone/temp") $passive"
+done"#;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("Could not send request")]
     RequestError,
-    #[error("Process terminated abnormally")]
-    AbnormalTerminationError,
+    #[error("Process terminated abnormally ({0})")]
+    AbnormalTerminationError(ExitStatus),
     #[error("Remote error: {0}")]
     RemoteError(String),
     #[error("Did not receive response")]
     ResponseError,
+    #[error("Timed out waiting for response")]
+    TimeoutError,
+    #[error("Lost the connection to the remote")]
+    ConnectionLost,
     #[error("Could not decode data")]
     DecodeError,
+    #[error("The checksum of {path} is {found} instead of {expected}")]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    #[error("The certificate of {0} is not trusted")]
+    UntrustedCertificate(String),
+    #[error(transparent)]
+    TlsError(#[from] openssl::ssl::Error),
+    #[error(transparent)]
+    OpensslError(#[from] openssl::error::ErrorStack),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Seconds to wait for the responses from a device before a request fails with
+/// `Error::TimeoutError`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Requests that the remote answers at once, e.g., halting or managing files
+    pub request: f64,
+    /// Uploads, each chunk of a chunked upload, and each chunk of a download
+    pub transfer: f64,
+    /// Processes that can not be terminated by their caller, e.g., querying the hostname or
+    /// running a script, processes such as ARGoS run until they are terminated
+    pub process: f64,
+    /// Re-establishing a lost connection before the device is given up
+    pub reconnect: f64,
+    /// Interval between the pings sent to the device, zero disables the pings. This requires a
+    /// version of the fernbedienung service that answers the ping request.
+    pub heartbeat: f64,
+    /// Consecutive pings that the device does not respond to before the connection is lost
+    pub heartbeat_misses: u32,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            request: 10.0,
+            transfer: 60.0,
+            process: 600.0,
+            reconnect: 60.0,
+            heartbeat: 0.0,
+            heartbeat_misses: 3,
+        }
+    }
+}
+
+impl Timeouts {
+    fn request(&self) -> Duration {
+        Duration::from_secs_f64(self.request.max(0.0))
+    }
+
+    fn transfer(&self) -> Duration {
+        Duration::from_secs_f64(self.transfer.max(0.0))
+    }
+
+    fn process(&self) -> Duration {
+        Duration::from_secs_f64(self.process.max(0.0))
+    }
+
+    fn reconnect(&self) -> Duration {
+        Duration::from_secs_f64(self.reconnect.max(0.0))
+    }
+
+    fn heartbeat(&self) -> Option<Duration> {
+        match self.heartbeat > 0.0 {
+            true => Some(Duration::from_secs_f64(self.heartbeat)),
+            false => None,
+        }
+    }
+}
+
+/// The thermal state of the processor of a device
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Thermal {
+    /// The temperature of the processor in degrees Celsius
+    pub temperature: f32,
+    /// Whether the processor is currently throttled to reduce its temperature
+    pub throttled: bool,
+}
+
+/// The state of the battery of a device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Battery {
+    /// Voltage (V) of the battery
+    pub voltage: f32,
+    /// Remaining charge (percent), estimated linearly from the voltage
+    pub percentage: f32,
+}
+
+impl Battery {
+    /// Estimates the remaining charge of a battery with the given number of cells
+    pub fn from_voltage(voltage: f32, cells: f32) -> Battery {
+        let cell_mv = voltage * 1000.0 / cells;
+        let remaining = (cell_mv - BATTERY_EMPTY_MV) / (BATTERY_FULL_MV - BATTERY_EMPTY_MV);
+        Battery { voltage, percentage: remaining.max(0.0).min(1.0) * 100.0 }
+    }
+}
+
+/// The metrics of the system of a device
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SystemInfo {
+    /// The load average over the last minute
+    pub load: f32,
+    /// The number of processors
+    pub cpus: u32,
+    /// Memory (bytes) that is available to new processes and in total
+    pub memory_available: u64,
+    pub memory_total: u64,
+    /// The temperature (degrees Celsius) of the first thermal zone, usually the SoC
+    pub temperature: Option<f32>,
+    /// Free space (bytes) on the root file system
+    pub disk_available: u64,
+}
+
+/// An access point that was found by a scan of the Wi-Fi
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bss {
+    pub bssid: String,
+    /// The name of the network, empty if the network is hidden
+    pub ssid: String,
+    /// Frequency (MHz) of the primary channel
+    pub frequency: u32,
+    /// Signal strength (dBm)
+    pub signal: f32,
+    /// Whether the device is associated with the access point
+    pub associated: bool,
+}
+
+async fn within<T>(timeout: Duration, response: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(timeout, response).await
+        .unwrap_or(Err(Error::TimeoutError))
+}
+
+/// The exit status of a process that terminated, whether it succeeded or not
+pub fn exit_status(result: &Result<ExitStatus>) -> Option<ExitStatus> {
+    match result {
+        Ok(status) | Err(Error::AbnormalTerminationError(status)) => Some(*status),
+        _ => None,
+    }
+}
+
+pub fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/* a connection to the fernbedienung service, which may be encrypted */
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
 type RemoteResponses = SymmetricallyFramed<
-    FramedRead<tokio::io::ReadHalf<TcpStream>, LengthDelimitedCodec>,
+    FramedRead<tokio::io::ReadHalf<Box<dyn Connection>>, LengthDelimitedCodec>,
     protocol::Response,
     SymmetricalJson<protocol::Response>>;
 
-pub type RemoteRequests = SymmetricallyFramed<
-    FramedWrite<tokio::io::WriteHalf<TcpStream>, LengthDelimitedCodec>,
+type RemoteRequests = SymmetricallyFramed<
+    FramedWrite<tokio::io::WriteHalf<Box<dyn Connection>>, LengthDelimitedCodec>,
     protocol::Request,
     SymmetricalJson<protocol::Request>>;
 
+/// A handle to the connection with a device, clones share the same connection
+#[derive(Clone)]
 pub struct Device {
     request_tx: mpsc::UnboundedSender<Request>,
     pub addr: Ipv4Addr
@@ -64,158 +297,468 @@ enum Request {
     Reboot {
         result_tx: oneshot::Sender<Result<()>>,
     },
+    Reap {
+        result_tx: oneshot::Sender<Result<()>>,
+    },
+    LogLevel {
+        level: protocol::LogLevel,
+        result_tx: oneshot::Sender<Result<()>>,
+    },
     Run {
         process: protocol::process::Process,
         terminate_rx: Option<oneshot::Receiver<()>>,
         stdin_rx: Option<UnboundedReceiver<BytesMut>>,
         stdout_tx: Option<UnboundedSender<BytesMut>>,
         stderr_tx: Option<UnboundedSender<BytesMut>>,
-        result_tx: oneshot::Sender<Result<()>>,
+        result_tx: oneshot::Sender<Result<ExitStatus>>,
     },
+    /* the uploads respond with the checksum reported by the remote, if any */
     Upload {
         upload: protocol::Upload,
-        result_tx: oneshot::Sender<Result<()>>
+        result_tx: oneshot::Sender<Result<Option<String>>>
+    },
+    /* the chunked uploads also respond with the checksum of the chunks that were sent */
+    UploadStream {
+        path: PathBuf,
+        filename: PathBuf,
+        chunks: BoxStream<'static, Bytes>,
+        result_tx: oneshot::Sender<Result<(String, Option<String>)>>
+    },
+    Download {
+        path: PathBuf,
+        result_tx: oneshot::Sender<Result<Vec<u8>>>
+    },
+    Files {
+        request: protocol::files::Request,
+        result_tx: oneshot::Sender<Result<Option<protocol::files::Response>>>
     },
 }
 
+impl Request {
+    /* responds to a request that can not be sent to the remote */
+    fn fail(self, error: Error) {
+        match self {
+            Request::Halt { result_tx } |
+            Request::Reboot { result_tx } |
+            Request::Reap { result_tx } |
+            Request::LogLevel { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+            Request::Run { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+            Request::Upload { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+            Request::UploadStream { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+            Request::Download { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+            Request::Files { result_tx, .. } => {
+                let _ = result_tx.send(Err(error));
+            },
+        }
+    }
+}
+
 impl Device {
-    pub async fn new(addr: Ipv4Addr, return_addr_tx: mpsc::UnboundedSender<Ipv4Addr>) -> Result<Self> {
-        let stream = TcpStream::connect((addr, 17653)).await
-            .map_err(|error| Error::IoError(error))?;
+    pub async fn new(addr: Ipv4Addr,
+                     return_addr_tx: mpsc::UnboundedSender<Ipv4Addr>,
+                     journal: journal::Sender,
+                     tls: Option<Tls>,
+                     chaos: Option<chaos::Chaos>,
+                     timeouts: Timeouts) -> Result<Self> {
+        let stream = Device::connect(addr, tls.as_ref()).await?;
         let (local_request_tx, mut local_request_rx) = mpsc::unbounded_channel();
         tokio::spawn(async move {
-            /* requests and responses from remote */
-            let (read, write) = tokio::io::split(stream);
-            let remote_requests: RemoteRequests = SymmetricallyFramed::new(
-                FramedWrite::new(write, LengthDelimitedCodec::new()),
-                SymmetricalJson::<protocol::Request>::default(),
-            );
-            let mut remote_responses: RemoteResponses = SymmetricallyFramed::new(
-                FramedRead::new(read, LengthDelimitedCodec::new()),
-                SymmetricalJson::<protocol::Response>::default(),
-            );
-            /* create an mpsc channel to share for remote_requests */
-            let (remote_requests_tx, remote_requests_rx) = mpsc::unbounded_channel();           
-            let mut forward_remote_requests = UnboundedReceiverStream::new(remote_requests_rx)
-                .map(|request| Ok(request))
-                .forward(remote_requests);
-            /* collections for tracking state */
-            let mut status_txs: HashMap<Uuid, UnboundedSender<protocol::ResponseKind>> = Default::default();
-            let mut tasks: FuturesUnordered<_> = Default::default();
-            /* event loop */
-            loop {
+            /* notifies the requests in flight that the connection was lost */
+            let (lost_tx, _) = broadcast::channel(1);
+            let mut connection = Some(stream);
+            while let Some(stream) = connection.take() {
+                let lost = Device::serve(addr, stream, &mut local_request_rx, &journal, &chaos, &timeouts, &lost_tx).await;
+                if lost {
+                    log::warn!("Lost the connection to {}, reconnecting", addr);
+                    connection = Device::reconnect(addr, tls.as_ref(), &timeouts, &mut local_request_rx).await;
+                    if connection.is_some() {
+                        log::info!("Reconnected to {}", addr);
+                    }
+                }
+            }
+            /* requests fail until the struct is dropped */
+            while let Some(_) = local_request_rx.recv().await {}
+            /* terminate this task when the struct is dropped */
+            let _ = return_addr_tx.send(addr);
+        });
+        Ok(Device { request_tx: local_request_tx, addr })
+    }
+
+    /* connects to the fernbedienung service on a device */
+    async fn connect(addr: Ipv4Addr, tls: Option<&Tls>) -> Result<Box<dyn Connection>> {
+        let stream: Box<dyn Connection> = match tls {
+            Some(tls) => match tls.connect(addr).await {
+                Ok(stream) => Box::new(stream),
+                /* fall back to plaintext if the robot does not accept encrypted connections */
+                Err(Error::IoError(_)) if !tls.required => Box::new(TcpStream::connect((addr, 17653)).await
+                    .map_err(|error| Error::IoError(error))?),
+                Err(error) => return Err(error),
+            },
+            None => Box::new(TcpStream::connect((addr, 17653)).await
+                .map_err(|error| Error::IoError(error))?),
+        };
+        Ok(stream)
+    }
+
+    /* re-establishes a lost connection, backing off between the attempts. The requests that
+       arrive in the meantime fail with Error::ConnectionLost. Gives up once the reconnection
+       timeout has passed or when the struct is dropped */
+    async fn reconnect(addr: Ipv4Addr,
+                       tls: Option<&Tls>,
+                       timeouts: &Timeouts,
+                       local_request_rx: &mut UnboundedReceiver<Request>) -> Option<Box<dyn Connection>> {
+        let deadline = tokio::time::Instant::now() + timeouts.reconnect();
+        let mut backoff = RECONNECT_BACKOFF_MIN;
+        loop {
+            let attempt = async move {
+                tokio::time::sleep(backoff).await;
+                within(timeouts.request(), Device::connect(addr, tls)).await
+            };
+            tokio::pin!(attempt);
+            let result = loop {
                 tokio::select! {
-                    Some(response) = remote_responses.next() => match response {
-                        Ok(protocol::Response(uuid, response)) => {
-                            if let Some(uuid) = uuid {
-                                if let Some(status_tx) = status_txs.get(&uuid) {
-                                    let _ = status_tx.send(response);
+                    result = &mut attempt => break result,
+                    request = local_request_rx.recv() => match request {
+                        Some(request) => request.fail(Error::ConnectionLost),
+                        None => return None,
+                    },
+                    _ = tokio::time::sleep_until(deadline) => {
+                        log::warn!("Could not reconnect to {} within {:?}", addr, timeouts.reconnect());
+                        return None;
+                    }
+                }
+            };
+            match result {
+                Ok(stream) => return Some(stream),
+                Err(error) => log::debug!("Could not reconnect to {}: {}", addr, error),
+            }
+            backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+        }
+    }
+
+    /* serves the requests over a connection until the connection is lost or the struct is
+       dropped, returns whether the connection was lost */
+    async fn serve(addr: Ipv4Addr,
+                   stream: Box<dyn Connection>,
+                   local_request_rx: &mut UnboundedReceiver<Request>,
+                   journal: &journal::Sender,
+                   chaos: &Option<chaos::Chaos>,
+                   timeouts: &Timeouts,
+                   lost_tx: &broadcast::Sender<()>) -> bool {
+        /* requests and responses from remote */
+        let (read, write) = tokio::io::split(stream);
+        let remote_requests: RemoteRequests = SymmetricallyFramed::new(
+            FramedWrite::new(write, LengthDelimitedCodec::builder()
+                .max_frame_length(MAX_REQUEST_LENGTH)
+                .new_codec()),
+            SymmetricalJson::<protocol::Request>::default(),
+        );
+        let mut remote_responses: RemoteResponses = SymmetricallyFramed::new(
+            FramedRead::new(read, LengthDelimitedCodec::builder()
+                .max_frame_length(MAX_RESPONSE_LENGTH)
+                .new_codec()),
+            SymmetricalJson::<protocol::Response>::default(),
+        );
+        /* create an mpsc channel to share for remote_requests */
+        let (remote_requests_tx, remote_requests_rx) = mpsc::unbounded_channel();           
+        let mut forward_remote_requests = UnboundedReceiverStream::new(remote_requests_rx)
+            .map(|request| Ok(request))
+            .forward(remote_requests);
+        /* collections for tracking state */
+        let mut status_txs: HashMap<Uuid, UnboundedSender<protocol::ResponseKind>> = Default::default();
+        /* the processes that are running on the remote */
+        let mut processes: HashSet<Uuid> = Default::default();
+        let mut tasks: FuturesUnordered<_> = Default::default();
+        /* the pings sent since the remote last responded, any response counts as an answer */
+        let mut heartbeat = timeouts.heartbeat()
+            .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+        let mut unanswered = 0;
+        /* event loop, which ends when the connection is lost or the struct is dropped */
+        let lost = loop {
+            tokio::select! {
+                response = remote_responses.next() => match response {
+                    Some(Ok(protocol::Response(uuid, response))) => {
+                        unanswered = 0;
+                        if let Some(uuid) = uuid {
+                            if let Some(status_tx) = status_txs.get(&uuid) {
+                                /* failure point for testing how the supervisor copes with a misbehaving robot */
+                                match chaos.as_ref().map_or(chaos::Fault::None, chaos::Chaos::response) {
+                                    chaos::Fault::None => {
+                                        let _ = status_tx.send(response);
+                                    },
+                                    chaos::Fault::Deny => log::warn!("Chaos: denied response from {}", addr),
+                                    chaos::Fault::Delay(delay) => {
+                                        let status_tx = status_tx.clone();
+                                        tokio::spawn(async move {
+                                            tokio::time::sleep(delay).await;
+                                            let _ = status_tx.send(response);
+                                        });
+                                    }
                                 }
                             }
-                            else {
-                                log::warn!("Received message without identifier: {:?}", response);
-                            }
-                        },
-                        Err(error) => {
-                            log::warn!("Could not deserialize response from remote: {}", error);
+                        }
+                        else {
+                            log::warn!("Received message without identifier: {:?}", response);
                         }
                     },
-                    request = local_request_rx.recv() => match request {
-                        Some(request) => {
-                            let task = match request {
-                                Request::Halt { result_tx } => {
-                                    let uuid = Uuid::new_v4();
-                                    let request = protocol::RequestKind::Halt;
-                                    let (halt_status_tx, mut halt_status_rx) = mpsc::unbounded_channel();
-                                    status_txs.insert(uuid, halt_status_tx);
-                                    let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
-                                    async move {
-                                        let result = match request_result {
-                                            Ok(_) => match halt_status_rx.recv().await {
-                                                Some(protocol::ResponseKind::Ok) => Ok(()),
-                                                _ => Err(Error::ResponseError),
-                                            }
-                                            _ => Err(Error::RequestError),
-                                        };
-                                        let _ = result_tx.send(result);
-                                        uuid
-                                    }.boxed()
-                                }
-                                Request::Reboot { result_tx } => {
-                                    let uuid = Uuid::new_v4();
-                                    let request = protocol::RequestKind::Reboot;
-                                    let (reboot_status_tx, mut reboot_status_rx) = mpsc::unbounded_channel();
-                                    status_txs.insert(uuid, reboot_status_tx);
-                                    let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
-                                    async move {
-                                        let result = match request_result {
-                                            Ok(_) => match reboot_status_rx.recv().await {
-                                                Some(protocol::ResponseKind::Ok) => Ok(()),
-                                                _ => Err(Error::ResponseError),
-                                            }
-                                            _ => Err(Error::RequestError),
-                                        };
-                                        let _ = result_tx.send(result);
-                                        uuid
-                                    }.boxed()
-                                }
-                                Request::Upload { upload, result_tx } => {
-                                    let uuid = Uuid::new_v4();
-                                    let request = protocol::RequestKind::Upload(upload);
-                                    /* subscribe to updates */
-                                    let (upload_status_tx, mut upload_status_rx) = mpsc::unbounded_channel();
-                                    status_txs.insert(uuid, upload_status_tx);
-                                    /* send the request */
-                                    let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
-                                    /* process responses */
-                                    async move {
-                                        let result = match request_result {
-                                            Ok(_) => match upload_status_rx.recv().await {
-                                                Some(protocol::ResponseKind::Ok) => Ok(()),
-                                                _ => Err(Error::ResponseError),
-                                            }
-                                            _ => Err(Error::RequestError),
-                                        };
-                                        let _ = result_tx.send(result);
-                                        uuid
-                                    }.boxed()
-                                },
-                                Request::Run { process, terminate_rx, stdin_rx, stdout_tx, stderr_tx, result_tx } => {
-                                    let uuid = Uuid::new_v4();
-                                    let request = protocol::RequestKind::Process(protocol::process::Request::Run(process));
-                                    /* subscribe to updates */
-                                    let (run_status_tx, run_status_rx) = mpsc::unbounded_channel();
-                                    status_txs.insert(uuid, run_status_tx);
-                                    /* send the request */
-                                    match remote_requests_tx.send(protocol::Request(uuid, request)) {
+                    /* a corrupted length prefix leaves the stream unusable, drop the connection */
+                    Some(Err(error)) if error.get_ref().map_or(false, |inner| inner.is::<LengthDelimitedCodecError>()) => {
+                        log::error!("Dropping connection to {}: {}", addr, error);
+                        let event = journal::Event::Dropped(addr.into(), error.to_string());
+                        if let Err(error) = journal.record(event) {
+                            log::error!("Could not record event in journal: {}", error);
+                        }
+                        break true;
+                    },
+                    Some(Err(error)) => {
+                        log::warn!("Could not deserialize response from remote: {}", error);
+                    },
+                    None => break true,
+                },
+                request = local_request_rx.recv() => match request {
+                    Some(request) => {
+                        let task = match request {
+                            Request::Halt { result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Halt;
+                                let (halt_status_tx, mut halt_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, halt_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => match halt_status_rx.recv().await {
+                                            Some(protocol::ResponseKind::Ok) => Ok(()),
+                                            _ => Err(Error::ResponseError),
+                                        }
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.request()), result_tx, response).boxed()
+                            }
+                            Request::Reboot { result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Reboot;
+                                let (reboot_status_tx, mut reboot_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, reboot_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => match reboot_status_rx.recv().await {
+                                            Some(protocol::ResponseKind::Ok) => Ok(()),
+                                            _ => Err(Error::ResponseError),
+                                        }
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.request()), result_tx, response).boxed()
+                            }
+                            Request::Reap { result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Reap;
+                                let (reap_status_tx, mut reap_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, reap_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => match reap_status_rx.recv().await {
+                                            Some(protocol::ResponseKind::Ok) => Ok(()),
+                                            _ => Err(Error::ResponseError),
+                                        }
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.request()), result_tx, response).boxed()
+                            }
+                            Request::LogLevel { level, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::LogLevel(level);
+                                let (log_level_status_tx, mut log_level_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, log_level_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => Device::acknowledged(&mut log_level_status_rx).await,
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.request()), result_tx, response).boxed()
+                            }
+                            Request::Upload { upload, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Upload(upload);
+                                /* subscribe to updates */
+                                let (upload_status_tx, mut upload_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, upload_status_tx);
+                                /* send the request */
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                /* process responses */
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => Device::written(&mut upload_status_rx).await,
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.transfer()), result_tx, response).boxed()
+                            },
+                            Request::UploadStream { path, filename, chunks, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                /* subscribe to the acknowledgements */
+                                let (upload_status_tx, upload_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, upload_status_tx);
+                                let remote_requests_tx = remote_requests_tx.clone();
+                                /* the time to upload a large file is unknown, so each acknowledgement is timed instead */
+                                let response = Device::handle_upload_stream_request(uuid, upload_status_rx,
+                                    remote_requests_tx, path, filename, chunks, timeouts.transfer());
+                                Device::complete(uuid, lost_tx.subscribe(), None, result_tx, response).boxed()
+                            },
+                            Request::Download { path, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Download(path);
+                                /* subscribe to the chunks */
+                                let (download_status_tx, mut download_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, download_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let timeout = timeouts.transfer();
+                                /* each chunk is timed rather than the whole download */
+                                let response = async move {
+                                    match request_result {
                                         Ok(_) => {
-                                            let remote_requests_tx = remote_requests_tx.clone();
-                                            Device::handle_run_request(uuid, run_status_rx, remote_requests_tx,
-                                                terminate_rx, stdin_rx, stdout_tx, stderr_tx, result_tx).left_future()
+                                            let mut contents = Vec::new();
+                                            loop {
+                                                let response = tokio::time::timeout(timeout, download_status_rx.recv()).await
+                                                    .map_err(|_| Error::TimeoutError)?;
+                                                match response {
+                                                    Some(protocol::ResponseKind::Download(
+                                                        protocol::download::Response::Chunk(chunk))) =>
+                                                        contents.extend_from_slice(&chunk),
+                                                    Some(protocol::ResponseKind::Ok) => break Ok(contents),
+                                                    Some(protocol::ResponseKind::Error(error)) =>
+                                                        break Err(Error::RemoteError(error)),
+                                                    _ => break Err(Error::ResponseError),
+                                                }
+                                            }
+                                        },
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), None, result_tx, response).boxed()
+                            },
+                            Request::Files { request, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                let request = protocol::RequestKind::Files(request);
+                                let (files_status_tx, mut files_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, files_status_tx);
+                                let request_result = remote_requests_tx.send(protocol::Request(uuid, request));
+                                let response = async move {
+                                    match request_result {
+                                        Ok(_) => match files_status_rx.recv().await {
+                                            Some(protocol::ResponseKind::Ok) => Ok(None),
+                                            Some(protocol::ResponseKind::Files(response)) => Ok(Some(response)),
+                                            Some(protocol::ResponseKind::Error(error)) => Err(Error::RemoteError(error)),
+                                            _ => Err(Error::ResponseError),
                                         }
-                                        _ => async move {
-                                            let _ = result_tx.send(Err(Error::RequestError));
-                                            uuid
-                                        }.right_future()
-                                    }.boxed()
-                                },
-                            };
-                            tasks.push(task);
-                        },
-                        None => {
-                            /* terminate this task when the struct is dropped */
-                            let _ = return_addr_tx.send(addr);
-                            break
-                        },
+                                        _ => Err(Error::RequestError),
+                                    }
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), Some(timeouts.request()), result_tx, response).boxed()
+                            },
+                            Request::Run { process, terminate_rx, stdin_rx, stdout_tx, stderr_tx, result_tx } => {
+                                let uuid = Uuid::new_v4();
+                                /* processes that the caller can terminate, e.g., ARGoS, may run for as long as
+                                   the caller wants, other processes are expected to terminate by themselves */
+                                let timeout = match terminate_rx {
+                                    Some(_) => None,
+                                    None => Some(timeouts.process()),
+                                };
+                                let request = protocol::RequestKind::Process(protocol::process::Request::Run(process));
+                                /* subscribe to updates */
+                                let (run_status_tx, run_status_rx) = mpsc::unbounded_channel();
+                                status_txs.insert(uuid, run_status_tx);
+                                processes.insert(uuid);
+                                /* send the request */
+                                let response = match remote_requests_tx.send(protocol::Request(uuid, request)) {
+                                    Ok(_) => {
+                                        let remote_requests_tx = remote_requests_tx.clone();
+                                        Device::handle_run_request(uuid, run_status_rx, remote_requests_tx,
+                                            terminate_rx, stdin_rx, stdout_tx, stderr_tx).left_future()
+                                    }
+                                    _ => futures::future::ready(Err(Error::RequestError)).right_future()
+                                };
+                                Device::complete(uuid, lost_tx.subscribe(), timeout, result_tx, response).boxed()
+                            },
+                        };
+                        tasks.push(task);
                     },
-                    Some(uuid) = tasks.next() => {
-                        status_txs.remove(&uuid);
-                    },
-                    _ = &mut forward_remote_requests => {}
+                    None => break false,
+                },
+                Some((uuid, completed)) = tasks.next() => {
+                    status_txs.remove(&uuid);
+                    /* terminate a process that timed out or whose caller stopped waiting for it */
+                    if processes.remove(&uuid) && !completed {
+                        log::warn!("Terminating process {} on {} that timed out or was cancelled", uuid, addr);
+                        let request = protocol::RequestKind::Process(protocol::process::Request::Terminate);
+                        let _ = remote_requests_tx.send(protocol::Request(uuid, request));
+                    }
+                },
+                _ = Device::tick(&mut heartbeat) => {
+                    if unanswered >= timeouts.heartbeat_misses.max(1) {
+                        log::warn!("Dropping connection to {}: {} pings were not answered", addr, unanswered);
+                        let event = journal::Event::Dropped(addr.into(), format!("{} pings were not answered", unanswered));
+                        if let Err(error) = journal.record(event) {
+                            log::error!("Could not record event in journal: {}", error);
+                        }
+                        break true;
+                    }
+                    /* the response to a ping has no receiver and is discarded */
+                    let _ = remote_requests_tx.send(protocol::Request(Uuid::new_v4(), protocol::RequestKind::Ping));
+                    unanswered += 1;
+                },
+                /* the requests can no longer be written to the remote */
+                _ = &mut forward_remote_requests => break true,
+            }
+        };
+        match lost {
+            /* the callers of the requests in flight are notified that the connection was lost,
+               the processes that they started are orphaned on the remote */
+            true => {
+                let _ = lost_tx.send(());
+                while let Some(_) = tasks.next().await {}
+            },
+            /* terminate the processes that are still running so that they are not orphaned on the
+               remote, this is best effort since the connection may already be broken */
+            false => {
+                drop(tasks);
+                for uuid in processes.drain() {
+                    let request = protocol::RequestKind::Process(protocol::process::Request::Terminate);
+                    let _ = remote_requests_tx.send(protocol::Request(uuid, request));
+                }
+                drop(remote_requests_tx);
+                if let Err(_) = tokio::time::timeout(CLOSE_TIMEOUT, &mut forward_remote_requests).await {
+                    log::warn!("Could not terminate the processes on {} before closing the connection", addr);
                 }
             }
-        });
-        Ok(Device { request_tx: local_request_tx, addr })
+        }
+        lost
+    }
+
+    /* completes at the next tick of the heartbeat, never if the heartbeat is disabled */
+    async fn tick(heartbeat: &mut Option<tokio::time::Interval>) {
+        match heartbeat {
+            Some(interval) => {
+                interval.tick().await;
+            },
+            None => futures::future::pending().await,
+        }
     }
 
     async fn handle_run_request(uuid: Uuid,
@@ -224,8 +767,7 @@ impl Device {
                                 terminate_rx: Option<oneshot::Receiver<()>>,
                                 stdin_rx: Option<mpsc::UnboundedReceiver<BytesMut>>,
                                 stdout_tx: Option<mpsc::UnboundedSender<BytesMut>>,
-                                stderr_tx: Option<mpsc::UnboundedSender<BytesMut>>,
-                                exit_status_tx: oneshot::Sender<Result<()>>) -> Uuid {
+                                stderr_tx: Option<mpsc::UnboundedSender<BytesMut>>) -> Result<ExitStatus> {
         let mut terminate_rx = match terminate_rx {
             Some(terminate_rx) => terminate_rx.into_stream().left_stream(),
             None => futures::stream::pending().right_stream(),
@@ -251,20 +793,15 @@ impl Device {
 
                 },
                 Some(response) = run_status_rx.recv() => match response {
-                    protocol::ResponseKind::Ok => {},
-                    protocol::ResponseKind::Error(error) => {
-                        let status = Err(Error::RemoteError(error));
-                        let _ = exit_status_tx.send(status);
-                        break;
-                    }
+                    protocol::ResponseKind::Ok |
+                    protocol::ResponseKind::Upload(_) |
+                    protocol::ResponseKind::Download(_) |
+                    protocol::ResponseKind::Files(_) => {},
+                    protocol::ResponseKind::Error(error) => break Err(Error::RemoteError(error)),
                     protocol::ResponseKind::Process(response) => match response {
-                        protocol::process::Response::Terminated(result) => {
-                            let status = match result {
-                                true => Ok(()),
-                                false => Err(Error::AbnormalTerminationError),
-                            };
-                            let _ = exit_status_tx.send(status);
-                            break;
+                        protocol::process::Response::Terminated(status) => break match status.success {
+                            true => Ok(status),
+                            false => Err(Error::AbnormalTerminationError(status)),
                         },
                         protocol::process::Response::StandardOutput(data) => {
                             if let Some(stdout_tx) = &stdout_tx {
@@ -278,14 +815,129 @@ impl Device {
                         },
                     },
                 },
-                else => break
+                else => break Err(Error::ResponseError)
+            }
+        }
+    }
+
+    async fn handle_upload_stream_request(uuid: Uuid,
+                                          mut upload_status_rx: mpsc::UnboundedReceiver<protocol::ResponseKind>,
+                                          remote_requests_tx: mpsc::UnboundedSender<protocol::Request>,
+                                          path: PathBuf,
+                                          filename: PathBuf,
+                                          mut chunks: BoxStream<'static, Bytes>,
+                                          timeout: Duration) -> Result<(String, Option<String>)> {
+        let send = |request| remote_requests_tx
+            .send(protocol::Request(uuid, protocol::RequestKind::ChunkedUpload(request)))
+            .map_err(|_| Error::RequestError);
+        let mut hasher = openssl::sha::Sha256::new();
+        /* the number of requests that have not been acknowledged */
+        let mut unacknowledged = 0;
+        send(protocol::upload::Request::Open { filename, path })?;
+        unacknowledged += 1;
+        while let Some(mut chunk) = chunks.next().await {
+            while !chunk.is_empty() {
+                let part = chunk.split_to(chunk.len().min(UPLOAD_CHUNK_SIZE));
+                hasher.update(&part);
+                if unacknowledged == UPLOAD_WINDOW {
+                    within(timeout, Device::acknowledged(&mut upload_status_rx)).await?;
+                    unacknowledged -= 1;
+                }
+                send(protocol::upload::Request::Append(BytesMut::from(&part[..])))?;
+                unacknowledged += 1;
+            }
+        }
+        send(protocol::upload::Request::Close)?;
+        /* the responses arrive in order, the last one answers the close request */
+        for _ in 0..unacknowledged {
+            within(timeout, Device::acknowledged(&mut upload_status_rx)).await?;
+        }
+        let written = within(timeout, Device::written(&mut upload_status_rx)).await?;
+        Ok((hex(&hasher.finish()), written))
+    }
+
+    /* sends the result of a request to its caller unless the request times out or the caller
+       stops waiting for it, i.e., the request is cancelled. The request fails if the connection
+       is lost. Returns the identifier of the request so that the event loop can clean up after it
+       and whether the request completed */
+    async fn complete<T>(uuid: Uuid,
+                         mut lost_rx: broadcast::Receiver<()>,
+                         timeout: Option<Duration>,
+                         mut result_tx: oneshot::Sender<Result<T>>,
+                         response: impl Future<Output = Result<T>>) -> (Uuid, bool) {
+        let response = async {
+            match timeout {
+                Some(timeout) => within(timeout, response).await,
+                None => response.await,
             }
+        };
+        let result = tokio::select! {
+            result = response => Some(result),
+            _ = lost_rx.recv() => Some(Err(Error::ConnectionLost)),
+            _ = result_tx.closed() => None,
+        };
+        match result {
+            Some(result) => {
+                let completed = !matches!(result, Err(Error::TimeoutError));
+                let _ = result_tx.send(result);
+                (uuid, completed)
+            },
+            None => (uuid, false),
+        }
+    }
+
+    async fn acknowledged(status_rx: &mut mpsc::UnboundedReceiver<protocol::ResponseKind>) -> Result<()> {
+        match status_rx.recv().await {
+            Some(protocol::ResponseKind::Ok) => Ok(()),
+            Some(protocol::ResponseKind::Error(error)) => Err(Error::RemoteError(error)),
+            _ => Err(Error::ResponseError),
         }
-        /* return the uuid so it can be removed from the hashmap */
-        uuid
     }
 
+    /* the response to an upload, older versions of the fernbedienung service respond with Ok
+       instead of the checksum of the written file */
+    async fn written(status_rx: &mut mpsc::UnboundedReceiver<protocol::ResponseKind>) -> Result<Option<String>> {
+        match status_rx.recv().await {
+            Some(protocol::ResponseKind::Upload(protocol::upload::Response::Written(checksum))) => Ok(Some(checksum)),
+            Some(protocol::ResponseKind::Ok) => Ok(None),
+            Some(protocol::ResponseKind::Error(error)) => Err(Error::RemoteError(error)),
+            _ => Err(Error::ResponseError),
+        }
+    }
+
+    /* compares the checksum of an uploaded file with the checksum of the contents that were sent,
+       the checksum is computed with sha256sum if the remote did not report it */
+    async fn verify(&self, path: PathBuf, expected: String, reported: Option<String>) -> Result<()> {
+        let found = match reported {
+            Some(checksum) => checksum,
+            None => self.checksum(path.clone()).await?,
+        };
+        match found.eq_ignore_ascii_case(&expected) {
+            true => Ok(()),
+            false => Err(Error::ChecksumMismatch { path, expected, found }),
+        }
+    }
+
+    async fn checksum(&self, path: PathBuf) -> Result<String> {
+        let process = protocol::process::Process {
+            target: "sha256sum".into(),
+            working_dir: None,
+            args: vec!["--".to_owned(), path.to_string_lossy().into_owned()],
+            ..Default::default()
+        };
+        /* sha256sum prints the checksum, two spaces, and the path */
+        self.standard_output(process).await?
+            .split_whitespace()
+            .next()
+            .map(str::to_owned)
+            .ok_or(Error::DecodeError)
+    }
+
+    /// Uploads a file and verifies the checksum of the written file, a corrupted upload results in
+    /// `Error::ChecksumMismatch`
     pub async fn upload(&self, path: PathBuf, filename: PathBuf, contents: Vec<u8>) -> Result<()> {
+        let destination = path.join(&filename);
+        let expected = hex(&openssl::sha::sha256(&contents));
         let upload = protocol::Upload {
             path, filename, contents,
         };
@@ -293,6 +945,42 @@ impl Device {
         self.request_tx
             .send(Request::Upload { upload, result_tx })
             .map_err(|_| Error::RequestError)?;
+        let reported = result_rx.await.map_err(|_| Error::ResponseError).and_then(|result| result)?;
+        self.verify(destination, expected, reported).await
+    }
+
+    /// Uploads a file in chunks as they are produced by a stream, e.g., while reading a large
+    /// library from disk, without holding the whole file in memory or in a single request. The
+    /// remote only replaces the destination once all chunks have been written. The checksum of the
+    /// written file is verified as for `upload`.
+    pub async fn upload_stream<S>(&self, path: PathBuf, filename: PathBuf, chunks: S) -> Result<()>
+        where S: Stream<Item = Bytes> + Send + 'static {
+        let destination = path.join(&filename);
+        let (result_tx, result_rx) = oneshot::channel();
+        self.request_tx
+            .send(Request::UploadStream { path, filename, chunks: chunks.boxed(), result_tx })
+            .map_err(|_| Error::RequestError)?;
+        let (expected, reported) = result_rx.await.map_err(|_| Error::ResponseError).and_then(|result| result)?;
+        self.verify(destination, expected, reported).await
+    }
+
+    /// Requests that the remote terminates the processes that were orphaned when their connection
+    /// closed, e.g., when the supervisor restarted during an experiment
+    pub async fn reap_orphans(&self) -> Result<()> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.request_tx
+            .send(Request::Reap { result_tx })
+            .map_err(|_| Error::RequestError)?;
+        result_rx.await.map_err(|_| Error::ResponseError).and_then(|result| result)
+    }
+
+    /// Changes the verbosity of the logging of the fernbedienung service, this requires a version
+    /// of the fernbedienung service that supports the log level request
+    pub async fn set_log_level(&self, level: LogLevel) -> Result<()> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.request_tx
+            .send(Request::LogLevel { level, result_tx })
+            .map_err(|_| Error::RequestError)?;
         result_rx.await.map_err(|_| Error::ResponseError).and_then(|result| result)
     }
 
@@ -317,7 +1005,7 @@ impl Device {
                      terminate_rx: Option<oneshot::Receiver<()>>,
                      stdin_rx: Option<mpsc::UnboundedReceiver<BytesMut>>,
                      stdout_tx: Option<mpsc::UnboundedSender<BytesMut>>,
-                     stderr_tx: Option<mpsc::UnboundedSender<BytesMut>>) -> Result<()> {
+                     stderr_tx: Option<mpsc::UnboundedSender<BytesMut>>) -> Result<ExitStatus> {
         let (result_tx, result_rx) = oneshot::channel();
         let request = Request::Run{ process, terminate_rx, stdin_rx, stdout_tx, stderr_tx, result_tx };
         self.request_tx.send(request).map_err(|_ | Error::RequestError)?;
@@ -329,6 +1017,7 @@ impl Device {
             target: "mktemp".into(),
             working_dir: None,
             args: vec!["-d".to_owned()],
+            ..Default::default()
         };
         let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
         let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
@@ -341,11 +1030,85 @@ impl Device {
         Ok(temp_dir.trim().to_owned())
     }
 
+    /// Uploads a shell script to a temporary directory and runs it, the temporary directory is
+    /// removed afterwards
+    pub async fn run_script(&self, script: Vec<u8>) -> Result<()> {
+        let path = self.create_temp_dir().await?;
+        let result = async {
+            self.upload(path.clone().into(), "script.sh".into(), script).await?;
+            let process = protocol::process::Process {
+                target: "sh".into(),
+                working_dir: Some(path.clone().into()),
+                args: vec!["script.sh".to_owned()],
+                ..Default::default()
+            };
+            self.run(process, None, None, None, None).await.map(|_| ())
+        }.await;
+        if let Err(error) = self.remove(path.clone().into(), true).await {
+            log::warn!("Could not remove {} from {}: {}", path, self.addr, error);
+        }
+        result
+    }
+
+    async fn files(&self, request: protocol::files::Request) -> Result<Option<protocol::files::Response>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.request_tx
+            .send(Request::Files { request, result_tx })
+            .map_err(|_| Error::RequestError)?;
+        result_rx.await.map_err(|_| Error::ResponseError).and_then(|result| result)
+    }
+
+    /// The entries of a directory, this requires a version of the fernbedienung service that
+    /// supports managing files
+    pub async fn list_dir(&self, path: PathBuf) -> Result<Vec<protocol::files::Entry>> {
+        match self.files(protocol::files::Request::List(path)).await? {
+            Some(protocol::files::Response::List(entries)) => Ok(entries),
+            None => Err(Error::ResponseError),
+        }
+    }
+
+    /// Removes a file or a directory, a directory that is not empty is only removed if the removal
+    /// is recursive
+    pub async fn remove(&self, path: PathBuf, recursive: bool) -> Result<()> {
+        let request = protocol::files::Request::Remove { path: path.clone(), recursive };
+        match self.files(request).await {
+            /* older versions of the fernbedienung service reject the request */
+            Err(Error::RemoteError(_)) => {
+                let flags = match recursive {
+                    true => "-rf",
+                    false => "-f",
+                };
+                self.run(protocol::process::Process {
+                    target: "rm".into(),
+                    working_dir: None,
+                    args: vec![flags.to_owned(), "--".to_owned(), path.to_string_lossy().into_owned()],
+                    ..Default::default()
+                }, None, None, None, None).await.map(|_| ())
+            },
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// Creates a directory and its missing parents
+    pub async fn mkdir(&self, path: PathBuf) -> Result<()> {
+        match self.files(protocol::files::Request::MakeDir(path.clone())).await {
+            /* older versions of the fernbedienung service reject the request */
+            Err(Error::RemoteError(_)) => self.run(protocol::process::Process {
+                target: "mkdir".into(),
+                working_dir: None,
+                args: vec!["-p".to_owned(), "--".to_owned(), path.to_string_lossy().into_owned()],
+                ..Default::default()
+            }, None, None, None, None).await.map(|_| ()),
+            result => result.map(|_| ()),
+        }
+    }
+
     pub async fn hostname(&self) -> Result<String> {
         let process = protocol::process::Process {
             target: "hostname".into(),
             working_dir: None,
             args: vec![],
+            ..Default::default()
         };
         let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
         let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
@@ -358,11 +1121,171 @@ impl Device {
         Ok(hostname.trim().to_owned())
     }
 
+    /// Follows a file like `tail -F`, sending its output until the receiver is dropped
+    pub async fn tail(&self,
+                      working_dir: PathBuf,
+                      path: PathBuf,
+                      lines: usize,
+                      output_tx: mpsc::UnboundedSender<BytesMut>) -> Result<()> {
+        let process = protocol::process::Process {
+            target: "tail".into(),
+            working_dir: Some(working_dir),
+            args: vec!["-F".to_owned(), "-n".to_owned(), lines.to_string(), "--".to_owned(), path.to_string_lossy().into_owned()],
+            ..Default::default()
+        };
+        let (terminate_tx, terminate_rx) = oneshot::channel();
+        let tail = self.run(process, Some(terminate_rx), None, Some(output_tx.clone()), None);
+        tokio::pin!(tail);
+        tokio::select! {
+            result = &mut tail => result.map(|_| ()),
+            _ = output_tx.closed() => {
+                let _ = terminate_tx.send(());
+                /* tail is expected to terminate abnormally when it is terminated */
+                match tail.await {
+                    Ok(_) | Err(Error::AbnormalTerminationError(_)) => Ok(()),
+                    Err(error) => Err(error),
+                }
+            }
+        }
+    }
+
+    /// Finds the regular files that match each of the patterns (expanded by the shell) and
+    /// returns the pattern, the path, and the SHA-256 of each file in hex
+    pub async fn find_files(&self, working_dir: PathBuf, patterns: Vec<String>)
+        -> Result<Vec<(String, String, String)>> {
+        let mut args = vec![
+            "-c".to_owned(),
+            "for pattern in \"$@\"; do for file in $pattern; do \
+                 if [ -f \"$file\" ]; then printf '%s\\t' \"$pattern\"; sha256sum -- \"$file\"; fi; \
+             done; done".to_owned(),
+            "sh".to_owned(),
+        ];
+        args.extend(patterns);
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: Some(working_dir),
+            args,
+            ..Default::default()
+        };
+        let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
+        let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
+        let (_, stdout) = tokio::try_join!(
+            self.run(process, None, None, Some(stdout_tx), None),
+            stdout_stream.concat().map(Result::Ok)
+        )?;
+        let stdout = std::str::from_utf8(stdout.as_ref())
+            .map_err(|_| Error::DecodeError)?;
+        /* each line is the pattern, a tab, and the output of sha256sum, i.e., the checksum, two
+           spaces, and the path */
+        stdout.lines()
+            .map(|line| {
+                let (pattern, line) = line.split_once('\t')?;
+                let (checksum, file) = line.split_once("  ")?;
+                Some((pattern.to_owned(), file.to_owned(), checksum.to_owned()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::DecodeError)
+    }
+
+    /// Kills the processes with the given name immediately, i.e., without giving them a chance to
+    /// write their output
+    pub async fn kill(&self, name: &str) -> Result<()> {
+        self.signal(name, "KILL").await
+    }
+
+    /// Sends a signal, e.g., USR1, to the processes with the given name
+    pub async fn signal(&self, name: &str, signal: &str) -> Result<()> {
+        let process = protocol::process::Process {
+            target: "pkill".into(),
+            working_dir: None,
+            args: vec![format!("-{}", signal), "-x".to_owned(), name.to_owned()],
+            ..Default::default()
+        };
+        match self.run(process, None, None, None, None).await {
+            /* pkill exits with an error if there were no processes to signal */
+            Ok(_) | Err(Error::AbnormalTerminationError(_)) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Removes the regular files that match each of the patterns (expanded by the shell)
+    pub async fn remove_files(&self, working_dir: PathBuf, patterns: Vec<String>) -> Result<()> {
+        let mut args = vec![
+            "-c".to_owned(),
+            "for pattern in \"$@\"; do for file in $pattern; do \
+                 if [ -f \"$file\" ]; then rm -f -- \"$file\"; fi; \
+             done; done".to_owned(),
+            "sh".to_owned(),
+        ];
+        args.extend(patterns);
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: Some(working_dir),
+            args,
+            ..Default::default()
+        };
+        self.run(process, None, None, None, None).await.map(|_| ())
+    }
+
+    /// The time on the clock of the device in seconds since the Unix epoch
+    pub async fn clock(&self) -> Result<f64> {
+        let process = protocol::process::Process {
+            target: "date".into(),
+            working_dir: None,
+            args: vec!["+%s.%N".to_owned()],
+            ..Default::default()
+        };
+        let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
+        let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
+        let (_, stdout) = tokio::try_join!(
+            self.run(process, None, None, Some(stdout_tx), None),
+            stdout_stream.concat().map(Result::Ok)
+        )?;
+        std::str::from_utf8(stdout.as_ref())
+            .map_err(|_| Error::DecodeError)?
+            .trim()
+            .parse()
+            .map_err(|_| Error::DecodeError)
+    }
+
+    /// Reads the contents of a file, e.g., the logs of ARGoS or a core dump after an experiment
+    pub async fn download(&self, path: PathBuf) -> Result<Vec<u8>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.request_tx
+            .send(Request::Download { path: path.clone(), result_tx })
+            .map_err(|_| Error::RequestError)?;
+        match result_rx.await.map_err(|_| Error::ResponseError)? {
+            /* older versions of the fernbedienung service reject the download request */
+            Err(Error::RemoteError(error)) => {
+                log::debug!("Downloading {} from {} with cat: {}", path.display(), self.addr, error);
+                self.cat(path).await
+            },
+            result => result,
+        }
+    }
+
+    async fn cat(&self, path: PathBuf) -> Result<Vec<u8>> {
+        let process = protocol::process::Process {
+            target: "cat".into(),
+            working_dir: None,
+            args: vec!["--".to_owned(), path.to_string_lossy().into_owned()],
+            ..Default::default()
+        };
+        let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
+        let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
+        let (_, stdout) = tokio::try_join!(
+            self.run(process, None, None, Some(stdout_tx), None),
+            stdout_stream.concat().map(Result::Ok)
+        )?;
+        Ok(stdout.to_vec())
+    }
+
     pub async fn kernel_messages(&self) -> Result<String> {
         let process = protocol::process::Process {
             target: "dmesg".into(),
             working_dir: None,
             args: vec![],
+            ..Default::default()
         };
         let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
         let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
@@ -380,6 +1303,7 @@ impl Device {
             target: "iw".into(),
             working_dir: None,
             args: vec!["dev".to_owned(), "wlan0".to_owned(), "link".to_owned()],
+            ..Default::default()
         };
         let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
         let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
@@ -395,5 +1319,295 @@ impl Device {
             .ok_or(Error::DecodeError)
             .and_then(|strength| strength.parse().map_err(|_| Error::DecodeError))
     }
+
+    async fn standard_output(&self, process: protocol::process::Process) -> Result<String> {
+        let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
+        let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
+        let (_, stdout) = tokio::try_join!(
+            self.run(process, None, None, Some(stdout_tx), None),
+            stdout_stream.concat().map(Result::Ok)
+        )?;
+        std::str::from_utf8(stdout.as_ref())
+            .map(|stdout| stdout.trim().to_owned())
+            .map_err(|_| Error::DecodeError)
+    }
+
+    /// The load, the memory, the temperature, and the free disk space of the device
+    pub async fn system_info(&self) -> Result<SystemInfo> {
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: None,
+            args: vec!["-c".to_owned(), SYSTEM_INFO_SCRIPT.to_owned()],
+            ..Default::default()
+        };
+        let output = self.standard_output(process).await?;
+        let mut info = SystemInfo::default();
+        for line in output.lines() {
+            let mut fields = line.split_whitespace();
+            let (key, value) = match (fields.next(), fields.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            match key {
+                "load" => info.load = value.parse().map_err(|_| Error::DecodeError)?,
+                "cpus" => info.cpus = value.parse().map_err(|_| Error::DecodeError)?,
+                "memory_total" => info.memory_total = value.parse::<u64>().map_err(|_| Error::DecodeError)? * 1024,
+                "memory_available" => info.memory_available = value.parse::<u64>().map_err(|_| Error::DecodeError)? * 1024,
+                "temperature" => info.temperature = value.parse::<f32>().ok().map(|millidegrees| millidegrees / 1000.0),
+                "disk_available" => info.disk_available = value.parse::<u64>().map_err(|_| Error::DecodeError)? * 1024,
+                _ => {},
+            }
+        }
+        Ok(info)
+    }
+
+    /// The battery of a Pi-Puck as measured by the ADS1015 on its extension board through a
+    /// voltage divider with the given ratio, this fails on devices without this ADC
+    pub async fn battery(&self, divider: f32) -> Result<Battery> {
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: None,
+            args: vec!["-c".to_owned(), BATTERY_SCRIPT.to_owned()],
+            ..Default::default()
+        };
+        let output = self.standard_output(process).await?;
+        let mut fields = output.split_whitespace();
+        let raw: f32 = fields.next()
+            .and_then(|raw| raw.parse().ok())
+            .ok_or(Error::DecodeError)?;
+        let scale: f32 = fields.next()
+            .and_then(|scale| scale.parse().ok())
+            .ok_or(Error::DecodeError)?;
+        Ok(Battery::from_voltage(raw * scale * divider / 1000.0, 1.0))
+    }
+
+    /// The thermal state of a Raspberry Pi, as reported by its firmware
+    pub async fn rpi_thermal(&self) -> Result<Thermal> {
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: None,
+            args: vec!["-c".to_owned(), "vcgencmd measure_temp; vcgencmd get_throttled".to_owned()],
+            ..Default::default()
+        };
+        let output = self.standard_output(process).await?;
+        let temperature = REGEX_RPI_TEMPERATURE.captures(&output)
+            .and_then(|captures| captures.get(1))
+            .and_then(|capture| capture.as_str().parse().ok())
+            .ok_or(Error::DecodeError)?;
+        let throttled = REGEX_RPI_THROTTLED.captures(&output)
+            .and_then(|captures| captures.get(1))
+            .and_then(|capture| u32::from_str_radix(capture.as_str(), 16).ok())
+            .ok_or(Error::DecodeError)?;
+        Ok(Thermal { temperature, throttled: throttled & RPI_THROTTLED_MASK != 0 })
+    }
+
+    /// The thermal state of a device from the thermal zones of the kernel, the processor is
+    /// considered throttled once a zone reaches its passive trip point
+    pub async fn thermal_zones(&self) -> Result<Thermal> {
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: None,
+            args: vec!["-c".to_owned(), THERMAL_ZONES_SCRIPT.to_owned()],
+            ..Default::default()
+        };
+        let output = self.standard_output(process).await?;
+        let mut thermal: Option<Thermal> = None;
+        for line in output.lines() {
+            let mut fields = line.split_whitespace()
+                .map(|field| field.parse::<f32>().map(|millidegrees| millidegrees / 1000.0));
+            let temperature = match fields.next() {
+                Some(Ok(temperature)) => temperature,
+                _ => continue,
+            };
+            let throttled = match fields.next() {
+                Some(Ok(passive)) => temperature >= passive,
+                _ => false,
+            };
+            let current = thermal.get_or_insert(Thermal { temperature, throttled });
+            current.temperature = current.temperature.max(temperature);
+            current.throttled |= throttled;
+        }
+        thermal.ok_or(Error::DecodeError)
+    }
+
+    /// Passively scans for the access points around the device, the scan briefly takes the
+    /// Wi-Fi interface off its channel
+    pub async fn wifi_scan(&self) -> Result<Vec<Bss>> {
+        let process = protocol::process::Process {
+            target: "iw".into(),
+            working_dir: None,
+            args: vec!["dev".to_owned(), "wlan0".to_owned(), "scan".to_owned(), "passive".to_owned()],
+            ..Default::default()
+        };
+        let output = self.standard_output(process).await?;
+        let mut access_points: Vec<Bss> = Vec::new();
+        for line in output.lines() {
+            if let Some(captures) = REGEX_BSS.captures(line) {
+                access_points.push(Bss {
+                    bssid: captures[1].to_owned(),
+                    ssid: String::new(),
+                    frequency: 0,
+                    signal: f32::NAN,
+                    associated: line.ends_with("-- associated"),
+                });
+                continue;
+            }
+            /* the attributes of an access point follow its header, newer versions of iw report
+               the frequency with a decimal part */
+            if let Some(access_point) = access_points.last_mut() {
+                let line = line.trim();
+                if let Some(frequency) = line.strip_prefix("freq:") {
+                    access_point.frequency = frequency.trim().parse::<f32>()
+                        .map(|frequency| frequency.round() as u32)
+                        .unwrap_or_default();
+                }
+                else if let Some(signal) = line.strip_prefix("signal:") {
+                    access_point.signal = signal.trim().trim_end_matches("dBm").trim().parse()
+                        .unwrap_or(f32::NAN);
+                }
+                else if let Some(ssid) = line.strip_prefix("SSID:") {
+                    access_point.ssid = ssid.trim().to_owned();
+                }
+            }
+        }
+        Ok(access_points)
+    }
+
+    pub async fn architecture(&self) -> Result<String> {
+        let process = protocol::process::Process {
+            target: "uname".into(),
+            working_dir: None,
+            args: vec!["-m".to_owned()],
+            ..Default::default()
+        };
+        self.standard_output(process).await
+    }
+
+    pub async fn argos_plugin_path(&self) -> Result<PathBuf> {
+        let process = protocol::process::Process {
+            target: "argos3".into(),
+            working_dir: None,
+            args: vec!["--query".to_owned(), "all".to_owned()],
+            ..Default::default()
+        };
+        let query = self.standard_output(process).await?;
+        /* fall back to the default installation path if the query does not report one */
+        let path = REGEX_ARGOS_PLUGIN_PATH.captures(&query)
+            .and_then(|captures| captures.get(1))
+            .map(|capture| capture.as_str())
+            .unwrap_or(ARGOS_DEFAULT_PLUGIN_PATH);
+        Ok(PathBuf::from(path))
+    }
+
+    pub async fn set_permissions(&self, path: PathBuf, mode: u32) -> Result<()> {
+        let process = protocol::process::Process {
+            target: "chmod".into(),
+            working_dir: None,
+            args: vec![format!("{:o}", mode), path.to_string_lossy().into_owned()],
+            ..Default::default()
+        };
+        self.run(process, None, None, None, None).await.map(|_| ())
+    }
+
+    /// Install ARGoS plugins into the plugin directory of the device
+    pub async fn install_plugins(&self, plugins: Vec<(String, Vec<u8>)>) -> Result<()> {
+        if plugins.is_empty() {
+            return Ok(());
+        }
+        let plugin_path = self.argos_plugin_path().await?;
+        for (filename, contents) in plugins {
+            self.upload(plugin_path.clone(), PathBuf::from(&filename), contents).await?;
+            self.set_permissions(plugin_path.join(&filename), 0o755).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn os_release(&self) -> Result<String> {
+        let process = protocol::process::Process {
+            target: "cat".into(),
+            working_dir: None,
+            args: vec!["/etc/os-release".to_owned()],
+            ..Default::default()
+        };
+        let os_release = self.standard_output(process).await?;
+        REGEX_OS_RELEASE.captures(&os_release)
+            .and_then(|captures| captures.get(1))
+            .map(|capture| capture.as_str().to_owned())
+            .ok_or(Error::DecodeError)
+    }
+
+    pub async fn kernel_version(&self) -> Result<String> {
+        let process = protocol::process::Process {
+            target: "uname".into(),
+            working_dir: None,
+            args: vec!["-r".to_owned()],
+            ..Default::default()
+        };
+        self.standard_output(process).await
+    }
+
+    pub async fn package_versions(&self) -> Result<Vec<(String, String)>> {
+        /* opkg is used on the Yocto images, dpkg-query is used as a fallback */
+        let process = protocol::process::Process {
+            target: "sh".into(),
+            working_dir: None,
+            args: vec![
+                "-c".to_owned(),
+                "opkg list-installed 2>/dev/null || dpkg-query -W -f='${Package} - ${Version}\\n'".to_owned()
+            ],
+            ..Default::default()
+        };
+        let packages = self.standard_output(process).await?;
+        let packages = packages.lines()
+            .filter_map(|line| REGEX_PACKAGE_VERSION.captures(line))
+            .filter_map(|captures| match (captures.get(1), captures.get(2)) {
+                (Some(package), Some(version)) =>
+                    Some((package.as_str().to_owned(), version.as_str().to_owned())),
+                _ => None,
+            })
+            .collect();
+        Ok(packages)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selftest;
+
+    /* starts the mock fernbedienung service on a loopback address that no other test uses and
+       connects to it with the given faults */
+    async fn connect(addr: Ipv4Addr, chaos: Option<chaos::Chaos>) -> Device {
+        let listener = tokio::net::TcpListener::bind((addr, 17653)).await.unwrap();
+        tokio::spawn(selftest::serve_mock(listener));
+        let (return_addr_tx, _) = mpsc::unbounded_channel();
+        let (journal, _) = journal::new();
+        let timeouts = Timeouts {
+            request: 1.0,
+            process: 1.0,
+            ..Default::default()
+        };
+        Device::new(addr, return_addr_tx, journal, None, chaos, timeouts).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn responses_without_faults() {
+        let device = connect(Ipv4Addr::new(127, 0, 0, 60), None).await;
+        assert_eq!(device.hostname().await.unwrap(), selftest::MOCK_HOSTNAME);
+    }
+
+    #[tokio::test]
+    async fn denied_responses_time_out() {
+        let chaos = chaos::Chaos { deny_response: 1.0, ..Default::default() };
+        let device = connect(Ipv4Addr::new(127, 0, 0, 61), Some(chaos)).await;
+        assert!(device.hostname().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delayed_responses_arrive() {
+        let chaos = chaos::Chaos { delay_response: 1.0, max_delay: 0.2, ..Default::default() };
+        let device = connect(Ipv4Addr::new(127, 0, 0, 62), Some(chaos)).await;
+        /* the delayed responses can overtake each other, so only the request is checked */
+        assert!(device.hostname().await.is_ok());
+    }
+}
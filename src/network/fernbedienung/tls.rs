@@ -0,0 +1,78 @@
+use std::{collections::HashMap, net::Ipv4Addr, pin::Pin};
+use openssl::{hash::MessageDigest, nid::Nid, ssl::{SslConnector, SslMethod, SslVerifyMode}};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_openssl::SslStream;
+
+use super::{Error, Result};
+
+/// Port on which the fernbedienung service accepts encrypted connections
+const DEFAULT_PORT: u16 = 17654;
+
+/// Encryption of the connections to the fernbedienung service. The robots use self-signed
+/// certificates whose common name is the hostname of the robot, a certificate is only trusted
+/// if its SHA-256 fingerprint is pinned for that hostname.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Tls {
+    /// Port on which the robots accept encrypted connections
+    pub port: u16,
+    /// Fingerprints of the certificates by hostname, e.g., "pipuck1": "AB:CD:..."
+    pub certificates: HashMap<String, String>,
+    /// Refuse robots that do not accept encrypted connections instead of using plaintext
+    pub required: bool,
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Tls {
+            port: DEFAULT_PORT,
+            certificates: HashMap::new(),
+            required: false,
+        }
+    }
+}
+
+/* fingerprints are compared without separators and regardless of case */
+fn normalize(fingerprint: &str) -> String {
+    fingerprint.chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|digit| digit.to_ascii_lowercase())
+        .collect()
+}
+
+impl Tls {
+    /// Connects to a robot and checks its certificate against the pinned fingerprints
+    pub async fn connect(&self, addr: Ipv4Addr) -> Result<SslStream<TcpStream>> {
+        let stream = TcpStream::connect((addr, self.port)).await?;
+        let mut connector = SslConnector::builder(SslMethod::tls())?;
+        /* the certificates are self-signed, they are verified against the pinned fingerprints */
+        connector.set_verify(SslVerifyMode::NONE);
+        let ssl = connector.build()
+            .configure()?
+            .verify_hostname(false)
+            .use_server_name_indication(false)
+            .into_ssl("")?;
+        let mut stream = SslStream::new(ssl, stream)?;
+        Pin::new(&mut stream).connect().await?;
+        let certificate = stream.ssl().peer_certificate()
+            .ok_or_else(|| Error::UntrustedCertificate(addr.to_string()))?;
+        let hostname = certificate.subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .and_then(|entry| entry.data().as_utf8().ok())
+            .map(|hostname| hostname.to_string())
+            .ok_or_else(|| Error::UntrustedCertificate(addr.to_string()))?;
+        let fingerprint = certificate.digest(MessageDigest::sha256())?
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        match self.certificates.get(&hostname) {
+            Some(pinned) if normalize(pinned) == fingerprint => Ok(stream),
+            _ => {
+                log::warn!("Refusing {} at {}, its certificate is not pinned", hostname, addr);
+                Err(Error::UntrustedCertificate(hostname))
+            },
+        }
+    }
+}
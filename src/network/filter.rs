@@ -0,0 +1,127 @@
+use std::{convert::TryFrom, net::Ipv4Addr, str::FromStr, time::Duration};
+use ipnet::Ipv4Net;
+use serde::{Deserialize, Serialize};
+
+/// Time for the address resolution to complete after a datagram was sent to a device
+const ARP_DELAY: Duration = Duration::from_millis(200);
+/// Port to which the datagram that triggers the address resolution is sent, i.e., discard
+const DISCARD_PORT: u16 = 9;
+
+/// An address or a network, e.g., `10.0.0.5` or `10.0.3.0/24`, or the prefix of a MAC address,
+/// e.g., `b8:27:eb` for the Raspberry Pis
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Rule {
+    Network(Ipv4Net),
+    Mac(Vec<u8>),
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> std::result::Result<Self, Self::Err> {
+        let rule = rule.trim();
+        if rule.contains(':') {
+            let prefix = rule.split(':')
+                .map(|octet| u8::from_str_radix(octet, 16))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| format!("Invalid MAC address prefix {}", rule))?;
+            match prefix.len() {
+                1..=6 => Ok(Rule::Mac(prefix)),
+                _ => Err(format!("Invalid MAC address prefix {}", rule)),
+            }
+        }
+        else if rule.contains('/') {
+            rule.parse().map(Rule::Network)
+                .map_err(|error| format!("Invalid network {}: {}", rule, error))
+        }
+        else {
+            rule.parse::<Ipv4Addr>().map(|addr| Rule::Network(addr.into()))
+                .map_err(|error| format!("Invalid address {}: {}", rule, error))
+        }
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(rule: String) -> std::result::Result<Self, Self::Error> {
+        rule.parse()
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.to_string()
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Network(network) if network.prefix_len() == 32 => write!(f, "{}", network.addr()),
+            Rule::Network(network) => write!(f, "{}", network),
+            Rule::Mac(prefix) => f.write_str(&prefix.iter()
+                .map(|octet| format!("{:02x}", octet))
+                .collect::<Vec<_>>()
+                .join(":")),
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, addr: Ipv4Addr, mac: Option<&[u8]>) -> bool {
+        match self {
+            Rule::Network(network) => network.contains(&addr),
+            Rule::Mac(prefix) => mac.map_or(false, |mac| mac.starts_with(prefix)),
+        }
+    }
+}
+
+/// Which devices may be probed, a device is probed if it matches none of the denied rules and,
+/// unless the allowed rules are empty, one of the allowed rules
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Filter {
+    pub allow: Vec<Rule>,
+    pub deny: Vec<Rule>,
+}
+
+impl Filter {
+    /// Whether the MAC address of a device is needed to decide if it may be probed
+    pub fn uses_mac(&self) -> bool {
+        self.allow.iter().chain(self.deny.iter()).any(|rule| matches!(rule, Rule::Mac(_)))
+    }
+
+    /// Whether a device may be probed, a device whose MAC address is unknown matches no MAC rule
+    pub fn permits(&self, addr: Ipv4Addr, mac: Option<&[u8]>) -> bool {
+        !self.deny.iter().any(|rule| rule.matches(addr, mac)) &&
+            (self.allow.is_empty() || self.allow.iter().any(|rule| rule.matches(addr, mac)))
+    }
+}
+
+/* the MAC address of an address in the ARP table of the supervisor */
+async fn arp_lookup(addr: Ipv4Addr) -> Option<Vec<u8>> {
+    let table = tokio::fs::read_to_string("/proc/net/arp").await.ok()?;
+    table.lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|columns| columns.len() >= 4)
+        /* the flags are zero while the resolution is incomplete */
+        .find(|columns| columns[0].parse::<Ipv4Addr>().ok() == Some(addr) && columns[2] != "0x0")
+        .and_then(|columns| columns[3].split(':')
+            .map(|octet| u8::from_str_radix(octet, 16).ok())
+            .collect())
+}
+
+/// The MAC address of a device on a network that the supervisor is connected to, a datagram is
+/// sent to the device if it is not in the ARP table so that its address is resolved
+pub async fn mac_address(addr: Ipv4Addr) -> Option<Vec<u8>> {
+    if let Some(mac) = arp_lookup(addr).await {
+        return Some(mac);
+    }
+    let socket = tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+    socket.send_to(&[], (addr, DISCARD_PORT)).await.ok()?;
+    tokio::time::sleep(ARP_DELAY).await;
+    arp_lookup(addr).await
+}
@@ -0,0 +1,194 @@
+use std::{collections::HashMap, net::{Ipv4Addr, SocketAddr}, time::Duration};
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use super::Probe;
+
+/// The multicast group and port of mDNS
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+/// The services that the devices announce and the protocol with which they are probed
+const SERVICES: [(&str, Probe); 2] = [
+    ("_fernbedienung._tcp.local", Probe::Fernbedienung),
+    ("_xbee._udp.local", Probe::Xbee),
+];
+/// Time between the queries for the services, devices also announce themselves when they join
+const QUERY_INTERVAL: Duration = Duration::from_secs(30);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Malformed mDNS message")]
+    Malformed,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+struct Record<'a> {
+    name: String,
+    kind: u16,
+    /* offset of the data in the message, names in the data can point anywhere in the message */
+    offset: usize,
+    data: &'a [u8],
+}
+
+/* reads a possibly compressed name at the offset, returning the name and the offset after it */
+fn read_name(message: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    /* bound the number of pointers that are followed to avoid loops */
+    for _ in 0..128 {
+        let length = *message.get(offset).ok_or(Error::Malformed)? as usize;
+        match length {
+            0 => {
+                return Ok((labels.join("."), end.unwrap_or(offset + 1)));
+            },
+            length if length & 0xC0 == 0xC0 => {
+                let low = *message.get(offset + 1).ok_or(Error::Malformed)? as usize;
+                end.get_or_insert(offset + 2);
+                offset = ((length & 0x3F) << 8) | low;
+            },
+            length => {
+                let label = message.get(offset + 1 .. offset + 1 + length).ok_or(Error::Malformed)?;
+                labels.push(String::from_utf8_lossy(label).to_lowercase());
+                offset += 1 + length;
+            }
+        }
+    }
+    Err(Error::Malformed)
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Result<u16> {
+    message.get(offset .. offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or(Error::Malformed)
+}
+
+/* the answers and the additional records of a response */
+fn parse(message: &[u8]) -> Result<Vec<Record>> {
+    let questions = read_u16(message, 4)?;
+    let records = read_u16(message, 6)? as usize +
+        read_u16(message, 8)? as usize +
+        read_u16(message, 10)? as usize;
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(message, offset)?.1 + 4;
+    }
+    let mut parsed = Vec::with_capacity(records);
+    for _ in 0..records {
+        let (name, after_name) = read_name(message, offset)?;
+        let kind = read_u16(message, after_name)?;
+        let length = read_u16(message, after_name + 8)? as usize;
+        let data_offset = after_name + 10;
+        let data = message.get(data_offset .. data_offset + length).ok_or(Error::Malformed)?;
+        parsed.push(Record { name, kind, offset: data_offset, data });
+        offset = data_offset + length;
+    }
+    Ok(parsed)
+}
+
+/// The addresses of the devices that a response announces, the instances of the services are
+/// followed to the hosts that provide them and to the addresses of these hosts
+fn announced(message: &[u8]) -> Result<Vec<(Ipv4Addr, Probe)>> {
+    let records = parse(message)?;
+    let mut addresses: HashMap<&str, Vec<Ipv4Addr>> = HashMap::new();
+    let mut names = Vec::new();
+    for record in records.iter() {
+        match record.kind {
+            TYPE_A if record.data.len() == 4 => addresses.entry(record.name.as_str())
+                .or_default()
+                .push(Ipv4Addr::new(record.data[0], record.data[1], record.data[2], record.data[3])),
+            TYPE_PTR | TYPE_SRV => {
+                /* the target of a service record follows its priority, weight, and port */
+                let offset = match record.kind {
+                    TYPE_SRV => record.offset + 6,
+                    _ => record.offset,
+                };
+                names.push((record, read_name(message, offset)?.0));
+            },
+            _ => {},
+        }
+    }
+    let mut targets: HashMap<&str, &str> = HashMap::new();
+    for (record, name) in names.iter() {
+        if record.kind == TYPE_SRV {
+            targets.insert(record.name.as_str(), name.as_str());
+        }
+    }
+    let mut devices = Vec::new();
+    for (record, instance) in names.iter().filter(|(record, _)| record.kind == TYPE_PTR) {
+        let probe = SERVICES.iter()
+            .find(|(service, _)| service.eq_ignore_ascii_case(&record.name))
+            .map(|(_, probe)| *probe);
+        if let Some(probe) = probe {
+            let host = targets.get(instance.as_str()).cloned().unwrap_or(instance.as_str());
+            for addr in addresses.get(host).into_iter().flatten() {
+                devices.push((*addr, probe));
+            }
+        }
+    }
+    Ok(devices)
+}
+
+/* a query for the pointer records of the services */
+fn query() -> Vec<u8> {
+    let mut message = vec![0, 0, 0, 0];
+    message.extend_from_slice(&(SERVICES.len() as u16).to_be_bytes());
+    message.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    for (service, _) in SERVICES.iter() {
+        for label in service.split('.') {
+            message.push(label.len() as u8);
+            message.extend_from_slice(label.as_bytes());
+        }
+        message.push(0);
+        message.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    message
+}
+
+/* the port of mDNS is shared with other responders on the host, e.g., avahi */
+fn bind() -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(socket2::Domain::IPV4,
+                                      socket2::Type::DGRAM,
+                                      Some(socket2::Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Queries for and listens to the announcements of the services of the devices, sending the
+/// address of each device that is found with the protocol that it is to be probed with
+pub async fn discover(discovered_tx: mpsc::UnboundedSender<(Ipv4Addr, Probe)>) -> Result<()> {
+    let socket = bind()?;
+    let mut queries = tokio::time::interval(QUERY_INTERVAL);
+    let mut buffer = [0u8; 9000];
+    loop {
+        tokio::select! {
+            _ = queries.tick() => {
+                socket.send_to(&query(), (MDNS_ADDR, MDNS_PORT)).await?;
+            },
+            received = socket.recv_from(&mut buffer) => {
+                let (length, source) = received?;
+                match announced(&buffer[..length]) {
+                    Ok(devices) => for device in devices {
+                        if discovered_tx.send(device).is_err() {
+                            return Ok(());
+                        }
+                    },
+                    Err(error) => log::debug!("Could not parse mDNS message from {}: {}", source, error),
+                }
+            }
+        }
+    }
+}
@@ -1,60 +1,422 @@
 use futures::stream::FuturesUnordered;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio_stream::StreamExt;
-use std::{collections::HashMap, net::Ipv4Addr, time::Duration};
+use std::{collections::{HashMap, HashSet, VecDeque}, convert::TryFrom, net::Ipv4Addr, str::FromStr, sync::Arc, time::Duration};
 use ipnet::Ipv4Net;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 pub mod xbee;
 pub mod fernbedienung;
+pub mod filter;
+mod mdns;
 
 use crate::arena;
+use crate::fleet;
+use crate::journal;
+use crate::config::{self, Config};
+
+pub enum Request {
+    /* the arena lost a robot, the addresses of its devices are probed again */
+    Lost(Ipv4Addr),
+    /* stop and resume starting new probes, e.g., to keep the network quiet during experiments */
+    Pause,
+    Resume,
+    /* probe a device that was registered manually, e.g., because it is on a routed network */
+    Register(Ipv4Addr, Probe),
+    /* the manually registered devices and whether they are connected */
+    GetRegistered(oneshot::Sender<Vec<(Ipv4Addr, Probe, bool)>>),
+    /* replace the filter of the devices that are probed, or return to the configured filter */
+    SetFilter(Option<filter::Filter>),
+    /* the filter and whether it replaces the configured filter */
+    GetFilter(oneshot::Sender<(filter::Filter, bool)>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/// Number of concurrent probes when the network task starts, this number ramps up to the
+/// maximum concurrency as probes succeed and is halved, but not below this number, when they fail
+const INITIAL_PROBE_CONCURRENCY: usize = 8;
+/// Delay before an address that could not be associated is probed again, the delay doubles with
+/// each failure up to the maximum delay and is randomized to spread the probes out
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
 
 #[derive(thiserror::Error, Debug)]
 enum Error {
     #[error("Could not associate address")]
     AssociateError,
+    #[error("The filter does not permit probing the address")]
+    Filtered,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-pub async fn new(network: Ipv4Net, arena_request_tx: &mpsc::UnboundedSender<arena::Request>) {
+/// The protocol with which the devices at an address are probed
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Probe {
+    /// The Xbee of a drone
+    Xbee,
+    /// A Pi-Puck, the UP Core of a drone, or a compute server
+    Fernbedienung,
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(probe: &str) -> std::result::Result<Self, Self::Err> {
+        match probe.trim() {
+            "xbee" => Ok(Probe::Xbee),
+            "fernbedienung" => Ok(Probe::Fernbedienung),
+            other => Err(format!("Unknown device type {}, expected xbee or fernbedienung", other)),
+        }
+    }
+}
+
+/// A network that is probed for robots, optionally with the type of the devices that are on
+/// it so that the other protocol is not tried, e.g., `10.0.1.0/24=xbee`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Subnet {
+    pub network: Ipv4Net,
+    pub probe: Option<Probe>,
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(subnet: &str) -> std::result::Result<Self, Self::Err> {
+        let (network, probe) = match subnet.split_once('=') {
+            Some((network, probe)) => (network, Some(probe.parse()?)),
+            None => (subnet, None),
+        };
+        let network = network.trim().parse()
+            .map_err(|error: ipnet::AddrParseError| format!("Invalid network {}: {}", network, error))?;
+        Ok(Subnet { network, probe })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(subnet: String) -> std::result::Result<Self, Self::Error> {
+        subnet.parse()
+    }
+}
+
+impl Probe {
+    /// The probe to try next if this probe fails
+    fn next(self) -> Probe {
+        match self {
+            Probe::Xbee => Probe::Fernbedienung,
+            Probe::Fernbedienung => Probe::Xbee,
+        }
+    }
+}
+
+async fn probe(arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+               return_addr_tx: mpsc::UnboundedSender<Ipv4Addr>,
+               journal: journal::Sender,
+               config: Arc<Config>,
+               filter: Option<Arc<filter::Filter>>,
+               addr: Ipv4Addr,
+               probe: Probe) -> (Ipv4Addr, Probe, Result<()>) {
+    if let Some(filter) = filter {
+        let mac = match filter.uses_mac() {
+            true => filter::mac_address(addr).await,
+            false => None,
+        };
+        if !filter.permits(addr, mac.as_deref()) {
+            return (addr, probe, Err(Error::Filtered));
+        }
+    }
+    let (addr, result) = match probe {
+        Probe::Xbee => associate_xbee(&arena_request_tx, &return_addr_tx, addr).await,
+        Probe::Fernbedienung => associate_fernbedienung(&arena_request_tx, &return_addr_tx, &journal, &config, addr).await,
+    };
+    (addr, probe, result)
+}
+
+/// The addresses in the networks given on the command line and in the configured networks, with
+/// the protocol that the devices at each address are expected to use. An address that is in
+/// networks with different expectations is probed with both protocols.
+fn hosts(networks: &[Subnet], config: &Config) -> HashMap<Ipv4Addr, Option<Probe>> {
+    let mut hosts = HashMap::new();
+    for subnet in networks.iter().chain(config.networks.iter()) {
+        for addr in subnet.network.hosts() {
+            hosts.entry(addr)
+                .and_modify(|probe: &mut Option<Probe>| if *probe != subnet.probe {
+                    *probe = None;
+                })
+                .or_insert(subnet.probe);
+        }
+    }
+    hosts
+}
+
+/* the delay before probing an address again after the given number of failures */
+fn retry_delay(failures: u32) -> Duration {
+    let delay = INITIAL_RETRY_DELAY.checked_mul(1 << failures.saturating_sub(1).min(16))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY));
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+}
+
+/* probes a device that was discovered or registered next, unless it is connected or already
+   being probed */
+fn prioritize(addr: Ipv4Addr,
+              probe: Probe,
+              addr_in_use_map: &mut HashMap<Ipv4Addr, bool>,
+              waiting: &mut HashMap<Ipv4Addr, u32>,
+              queue: &mut VecDeque<(Ipv4Addr, Probe)>) {
+    match addr_in_use_map.get(&addr) {
+        Some(true) => {},
+        Some(false) => if waiting.remove(&addr).is_some() {
+            queue.push_front((addr, probe));
+        }
+        else if let Some(index) = queue.iter().position(|(queued, _)| *queued == addr) {
+            queue.remove(index);
+            queue.push_front((addr, probe));
+        },
+        None => {
+            addr_in_use_map.insert(addr, false);
+            queue.push_front((addr, probe));
+        }
+    }
+}
+
+/* queues the addresses that the previous filter refused to be probed with the new filter */
+fn refilter(filtered: &mut HashSet<Ipv4Addr>,
+            probes_map: &HashMap<Ipv4Addr, Option<Probe>>,
+            discovered: &HashMap<Ipv4Addr, Probe>,
+            queue: &mut VecDeque<(Ipv4Addr, Probe)>) {
+    for addr in filtered.drain() {
+        let probe = discovered.get(&addr)
+            .cloned()
+            .or_else(|| probes_map.get(&addr).cloned().flatten())
+            .unwrap_or(Probe::Xbee);
+        queue.push_back((addr, probe));
+    }
+}
+
+/* queues an address whose device was dropped or lost to be probed again, returns whether the
+   address was in use */
+fn release(addr: Ipv4Addr,
+           addr_in_use_map: &mut HashMap<Ipv4Addr, bool>,
+           probes_map: &HashMap<Ipv4Addr, Option<Probe>>,
+           discovered: &HashMap<Ipv4Addr, Probe>,
+           queue: &mut VecDeque<(Ipv4Addr, Probe)>) -> bool {
+    if let Some(true) = addr_in_use_map.get(&addr) {
+        /* stop probing addresses that were removed from the configuration */
+        let probe = discovered.get(&addr).cloned().map(Some)
+            .or_else(|| probes_map.get(&addr).cloned());
+        match probe {
+            Some(probe) => {
+                addr_in_use_map.insert(addr, false);
+                queue.push_back((addr, probe.unwrap_or(Probe::Xbee)));
+            },
+            None => {
+                addr_in_use_map.remove(&addr);
+            }
+        }
+        return true;
+    }
+    false
+}
+
+pub async fn new(networks: Vec<Subnet>,
+                 mut requests_rx: Receiver,
+                 arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                 max_concurrency: usize,
+                 journal: journal::Sender,
+                 mut config: config::Receiver) {
     let (return_addr_tx, mut return_addr_rx) = mpsc::unbounded_channel::<Ipv4Addr>();
-    let mut addr_in_use_map = network.clone().hosts()
-        .map(|addr| (addr, false))
+    /* devices that announce their services are probed first and with the announced protocol,
+       scanning the networks remains the fallback for devices that do not announce themselves */
+    let (discovered_tx, mut discovered_rx) = mpsc::unbounded_channel::<(Ipv4Addr, Probe)>();
+    if config.borrow().mdns_discovery {
+        tokio::spawn(async move {
+            if let Err(error) = mdns::discover(discovered_tx).await {
+                log::error!("mDNS discovery failed: {}", error);
+            }
+        });
+    }
+    let mut discovered : HashMap<Ipv4Addr, Probe> = HashMap::new();
+    /* the manually registered devices are kept with the discovered devices */
+    let mut registered : HashSet<Ipv4Addr> = HashSet::new();
+    /* the filter of the devices that are probed, registered devices are probed regardless, and
+       the addresses that the filter refused, which are probed again when the filter changes */
+    let mut filter = Arc::new(config.borrow().discovery_filter.clone());
+    let mut filter_replaced = false;
+    let mut filtered : HashSet<Ipv4Addr> = HashSet::new();
+    let mut released : HashSet<Ipv4Addr> = HashSet::new();
+    let mut probes_map = hosts(&networks, &config.borrow());
+    let mut addr_in_use_map = probes_map.keys()
+        .map(|addr| (*addr, false))
         .collect::<HashMap<_,_>>();
-    let mut associate_xbee_queue = network.hosts()
-        .map(|addr| associate_xbee(&arena_request_tx, &return_addr_tx, addr))
-        .collect::<FuturesUnordered<_>>();
-    let mut associate_fernbedienung_queue: FuturesUnordered<_> = Default::default();
+    let mut queue = probes_map.iter()
+        .map(|(addr, probe)| (*addr, probe.unwrap_or(Probe::Xbee)))
+        .collect::<VecDeque<_>>();
+    /* the maximum concurrency in the configuration overrides the command line */
+    let default_concurrency = max_concurrency;
+    let mut max_concurrency = config.borrow().max_probe_concurrency
+        .unwrap_or(default_concurrency).max(1);
+    let mut concurrency = INITIAL_PROBE_CONCURRENCY.min(max_concurrency);
+    let mut probes: FuturesUnordered<_> = Default::default();
+    /* the number of consecutive failures of each address and the addresses waiting to be probed
+       again with the number of failures at the time the retry was scheduled */
+    let mut failures : HashMap<Ipv4Addr, u32> = HashMap::new();
+    let mut waiting : HashMap<Ipv4Addr, u32> = HashMap::new();
+    let mut retries: FuturesUnordered<_> = Default::default();
+    let mut paused = false;
     loop {
+        /* start probes up to the current concurrency limit */
+        while !paused && probes.len() < concurrency {
+            match queue.pop_front() {
+                Some((addr, kind)) => {
+                    let task = probe(arena_request_tx.clone(),
+                                     return_addr_tx.clone(),
+                                     journal.clone(),
+                                     config.borrow().clone(),
+                                     match registered.contains(&addr) {
+                                         true => None,
+                                         false => Some(filter.clone()),
+                                     },
+                                     addr,
+                                     kind);
+                    let task = tokio::spawn(task);
+                    /* the address is kept with the task so that it is retried if the task panics */
+                    probes.push(async move { (addr, kind, task.await) });
+                },
+                None => break,
+            }
+        }
         tokio::select!{
+            /* apply changes to the discovery ranges without dropping connected robots */
+            Ok(_) = config.changed() => {
+                max_concurrency = config.borrow().max_probe_concurrency
+                    .unwrap_or(default_concurrency).max(1);
+                concurrency = concurrency.min(max_concurrency);
+                probes_map = hosts(&networks, &config.borrow());
+                addr_in_use_map.retain(|addr, in_use| *in_use ||
+                    probes_map.contains_key(addr) || discovered.contains_key(addr));
+                queue.retain(|(addr, _)| probes_map.contains_key(addr) || discovered.contains_key(addr));
+                waiting.retain(|addr, _| addr_in_use_map.contains_key(addr));
+                failures.retain(|addr, _| addr_in_use_map.contains_key(addr));
+                filtered.retain(|addr| addr_in_use_map.contains_key(addr));
+                if !filter_replaced && *filter != config.borrow().discovery_filter {
+                    filter = Arc::new(config.borrow().discovery_filter.clone());
+                    refilter(&mut filtered, &probes_map, &discovered, &mut queue);
+                }
+                for (addr, probe) in probes_map.iter() {
+                    if !addr_in_use_map.contains_key(addr) {
+                        addr_in_use_map.insert(*addr, false);
+                        queue.push_back((*addr, probe.unwrap_or(Probe::Xbee)));
+                    }
+                }
+            },
+            Some((addr, probe)) = discovered_rx.recv() => {
+                /* a registered device keeps the type that it was registered with */
+                if !registered.contains(&addr) {
+                    if discovered.insert(addr, probe).is_none() {
+                        log::info!("Discovered {:?} device at {}", probe, addr);
+                    }
+                    prioritize(addr, probe, &mut addr_in_use_map, &mut waiting, &mut queue);
+                }
+            },
             Some(recv_addr) = return_addr_rx.recv() => {
-                /* check if received address was in-use */
-                if let Some(true) = addr_in_use_map.get(&recv_addr) {
-                    addr_in_use_map.insert(recv_addr, false);
-                    let association =
-                        associate_xbee(&arena_request_tx, &return_addr_tx, recv_addr);
-                    associate_xbee_queue.push(association);
+                /* the arena may have already returned the address when it lost the robot */
+                if !released.remove(&recv_addr) {
+                    release(recv_addr, &mut addr_in_use_map, &probes_map, &discovered, &mut queue);
                 }
             },
-            Some((addr, result)) = associate_xbee_queue.next() => match result {
-                Ok(_) => { 
-                    addr_in_use_map.insert(addr, true);
+            Some(request) = requests_rx.recv() => match request {
+                /* the arena lost a robot whose devices may not have been dropped yet, e.g., because
+                   a task still holds a reference to them, so their addresses are probed again now */
+                Request::Lost(lost_addr) => {
+                    if release(lost_addr, &mut addr_in_use_map, &probes_map, &discovered, &mut queue) {
+                        released.insert(lost_addr);
+                    }
+                },
+                Request::Pause => {
+                    log::info!("Paused probing the network");
+                    paused = true;
+                },
+                Request::Resume => {
+                    log::info!("Resumed probing the network");
+                    paused = false;
+                },
+                Request::Register(addr, probe) => {
+                    log::info!("Registered {:?} device at {}", probe, addr);
+                    registered.insert(addr);
+                    discovered.insert(addr, probe);
+                    failures.remove(&addr);
+                    prioritize(addr, probe, &mut addr_in_use_map, &mut waiting, &mut queue);
+                },
+                Request::SetFilter(replacement) => {
+                    filter_replaced = replacement.is_some();
+                    filter = Arc::new(replacement.unwrap_or_else(|| config.borrow().discovery_filter.clone()));
+                    log::info!("Probing devices that match {:?}", filter);
+                    refilter(&mut filtered, &probes_map, &discovered, &mut queue);
+                },
+                Request::GetFilter(callback) => {
+                    let _ = callback.send(((*filter).clone(), filter_replaced));
                 },
-                Err(_) => {
-                    let association =
-                        associate_fernbedienung(&arena_request_tx, &return_addr_tx, addr);
-                    associate_fernbedienung_queue.push(association);
+                Request::GetRegistered(callback) => {
+                    let devices = registered.iter()
+                        .filter_map(|addr| discovered.get(addr).map(|probe| (*addr, *probe,
+                            addr_in_use_map.get(addr) == Some(&true))))
+                        .collect();
+                    let _ = callback.send(devices);
+                },
+            },
+            Some((addr, probe, count)) = retries.next() => {
+                /* the retry is stale if the address was probed again in the meantime */
+                if waiting.get(&addr) == Some(&count) {
+                    waiting.remove(&addr);
+                    if addr_in_use_map.get(&addr) == Some(&false) {
+                        queue.push_back((addr, probe));
+                    }
                 }
             },
-            Some((addr, result)) = associate_fernbedienung_queue.next() => match result {
-                Ok(_) => { 
-                    addr_in_use_map.insert(addr, true);
-                },
-                Err(_) => {
-                    let association =
-                        associate_xbee(&arena_request_tx, &return_addr_tx, addr);
-                    associate_xbee_queue.push(association);
+            Some((addr, kind, result)) = probes.next() => {
+                if let Err(error) = &result {
+                    log::error!("Probe task for {} panicked: {}", addr, error);
+                }
+                match result.map(|(_, _, result)| result) {
+                    Ok(Ok(_)) => {
+                        /* ramp up the concurrency as probes succeed */
+                        concurrency = (concurrency + 1).min(max_concurrency);
+                        addr_in_use_map.insert(addr, true);
+                        failures.remove(&addr);
+                    },
+                    /* the address is not probed again until the filter changes */
+                    Ok(Err(Error::Filtered)) => {
+                        if addr_in_use_map.contains_key(&addr) {
+                            filtered.insert(addr);
+                        }
+                    },
+                    /* only the expected protocol is retried on the networks with a device type, a
+                       probe that panicked is retried like a probe that failed */
+                    _ => {
+                        /* back off in case the probes fail because there are too many of them */
+                        concurrency = (concurrency / 2).max(INITIAL_PROBE_CONCURRENCY).min(max_concurrency);
+                        if addr_in_use_map.contains_key(&addr) {
+                            let next = discovered.get(&addr)
+                                .cloned()
+                                .or_else(|| probes_map.get(&addr).cloned().flatten())
+                                .unwrap_or_else(|| kind.next());
+                            let count = failures.entry(addr).or_insert(0);
+                            *count += 1;
+                            let (count, delay) = (*count, retry_delay(*count));
+                            waiting.insert(addr, count);
+                            retries.push(async move {
+                                tokio::time::sleep(delay).await;
+                                (addr, next, count)
+                            });
+                        }
+                    },
                 }
             },
             else => break
@@ -97,25 +459,49 @@ async fn associate_xbee(arena_request_tx: &mpsc::UnboundedSender<arena::Request>
 
 async fn associate_fernbedienung(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
                                  return_addr_tx: &mpsc::UnboundedSender<Ipv4Addr>,
+                                 journal: &journal::Sender,
+                                 config: &Config,
                                  addr: Ipv4Addr) -> (Ipv4Addr, Result<()>) {
     /* assume address is a device running the fernbedienung service and 
        attempt to connect for 500 ms */
     let fernbedienung_attempt = tokio::time::timeout(Duration::from_millis(500), async {
-        let device = fernbedienung::Device::new(addr, return_addr_tx.clone()).await?;
+        let device = fernbedienung::Device::new(addr,
+                                                return_addr_tx.clone(),
+                                                journal.clone(),
+                                                config.fernbedienung_tls.clone(),
+                                                config.chaos.clone(),
+                                                config.fernbedienung_timeouts.clone()).await?;
         let hostname = device.hostname().await?;
         std::result::Result::<_, fernbedienung::Error>::Ok((hostname, device))
     }).await;
+    /* terminate the processes that were started over a previous connection to the device */
+    if let (true, Ok(Ok((hostname, device)))) = (config.reap_orphans, fernbedienung_attempt.as_ref()) {
+        match tokio::time::timeout(Duration::from_millis(500), device.reap_orphans()).await {
+            Ok(Ok(_)) => {},
+            Ok(Err(error)) => log::warn!("Could not reap orphaned processes on {}: {}", hostname, error),
+            Err(_) => log::warn!("Could not reap orphaned processes on {}: timed out", hostname),
+        }
+    }
     /* inspect result */
     if let Ok(fernbedienung_result) = fernbedienung_attempt {
         if let Ok((hostname, device)) = fernbedienung_result {
-            let result = match &hostname[..] {
-                "raspberrypi0-wifi" | "ToshibaLaptop" =>
-                    arena_request_tx.send(arena::Request::AddPiPuck(device))
-                        .map_err(|_| Error::AssociateError),
-                "up-core" =>
-                    arena_request_tx.send(arena::Request::PairWithDrone(device))
-                        .map_err(|_| Error::AssociateError),
-                _ => Err(Error::AssociateError),
+            /* the robots of the expected fleet are classified by their names */
+            let result = if config.pipuck_hostnames.contains(&hostname) ||
+                fleet::contains(&config.fleet, arena::Kind::PiPuck, &hostname) {
+                arena_request_tx.send(arena::Request::AddPiPuck(device, hostname))
+                    .map_err(|_| Error::AssociateError)
+            }
+            else if config.upcore_hostnames.contains(&hostname) ||
+                fleet::contains(&config.fleet, arena::Kind::Drone, &hostname) {
+                arena_request_tx.send(arena::Request::PairWithDrone(device, hostname))
+                    .map_err(|_| Error::AssociateError)
+            }
+            else if config.compute_hostnames.contains(&hostname) {
+                arena_request_tx.send(arena::Request::AddComputeServer(device))
+                    .map_err(|_| Error::AssociateError)
+            }
+            else {
+                Err(Error::AssociateError)
             };
             return (addr, result);
         }
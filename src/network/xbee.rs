@@ -122,6 +122,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 struct Codec;
 
+/// A handle to the connection with an Xbee, clones share the same connection
+#[derive(Clone)]
 pub struct Device {
     request_tx: mpsc::UnboundedSender<Request>,
     pub addr: Ipv4Addr
@@ -334,6 +336,16 @@ impl Device {
         value.first().cloned().map(|state| state as i32).ok_or(Error::DecodeError)
     }
 
+    /// Signal strength (dBm) of the last packet that the Xbee received
+    pub async fn rssi(&self) -> Result<i32> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let request = Request::GetParameter([b'D',b'B'], response_tx);
+        self.request_tx.send(request).map_err(|_| Error::RequestFailed)?;
+        let value = response_rx.await.map_err(|_| Error::NoResponse)??;
+        /* the Xbee reports the magnitude of the negative signal strength */
+        value.first().cloned().map(|rssi| -(rssi as i32)).ok_or(Error::DecodeError)
+    }
+
     pub async fn pin_states(&self) -> Result<Vec<(Pin, bool)>> {
         let (response_tx, response_rx) = oneshot::channel();
         let request = Request::GetParameter([b'I',b'S'], response_tx);
@@ -0,0 +1,184 @@
+use std::{collections::{HashMap, VecDeque}, time::SystemTime};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot, watch};
+use uuid::Uuid;
+
+/// Number of finished operations that are kept so that their results can be inspected
+const FINISHED_OPERATIONS: usize = 20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum Kind {
+    /// Staging the software on the robots and starting their controllers
+    Staging,
+    /// Collecting the results of a run from the robots
+    Harvest,
+    /// Rolling out an update campaign
+    Campaign,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum State {
+    Running,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Action {
+    Cancel,
+}
+
+/// A long-running operation on the robots, with the results of the robots that have finished
+#[derive(Clone, Debug, Serialize)]
+pub struct Operation {
+    pub id: Uuid,
+    pub kind: Kind,
+    pub description: String,
+    pub started: SystemTime,
+    pub state: State,
+    /// Number of robots that the operation covers, if known
+    pub total: Option<usize>,
+    pub succeeded: Vec<Uuid>,
+    pub failed: Vec<(Uuid, String)>,
+}
+
+pub enum Request {
+    Begin(Operation, watch::Sender<bool>),
+    Total(Uuid, usize),
+    Report(Uuid, Uuid, Result<(), String>),
+    Finish(Uuid, State),
+    Cancel(Uuid),
+    List(oneshot::Sender<Vec<Operation>>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/// Observes the cancellation of an operation from the tasks that carry it out, e.g., the robot
+/// tasks check it between the uploads of the software
+#[derive(Clone, Debug)]
+pub struct Token(watch::Receiver<bool>);
+
+impl Token {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// The side of an operation that reports its progress and observes its cancellation
+#[derive(Clone)]
+pub struct Handle {
+    id: Uuid,
+    requests_tx: Sender,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl Handle {
+    pub fn begin(requests_tx: &Sender, kind: Kind, description: String, total: Option<usize>) -> Handle {
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let operation = Operation {
+            id: Uuid::new_v4(),
+            kind,
+            description,
+            started: SystemTime::now(),
+            state: State::Running,
+            total,
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        };
+        let id = operation.id;
+        if let Err(_) = requests_tx.send(Request::Begin(operation, cancel_tx)) {
+            log::error!("Could not register operation {}", id);
+        }
+        Handle { id, requests_tx: requests_tx.clone(), cancelled: cancel_rx }
+    }
+
+    pub fn total(&self, total: usize) {
+        let _ = self.requests_tx.send(Request::Total(self.id, total));
+    }
+
+    /// Records the result of a robot
+    pub fn report(&self, robot: Uuid, result: Result<(), String>) {
+        let _ = self.requests_tx.send(Request::Report(self.id, robot, result));
+    }
+
+    pub fn finish(&self, state: State) {
+        let _ = self.requests_tx.send(Request::Finish(self.id, state));
+    }
+
+    pub fn token(&self) -> Token {
+        Token(self.cancelled.clone())
+    }
+
+    /// Completes once the operation has been cancelled
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.clone();
+        while !*cancelled.borrow() {
+            /* an operation that can no longer be cancelled waits forever */
+            if cancelled.changed().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+pub async fn new(mut requests_rx: Receiver) {
+    let mut running: HashMap<Uuid, (Operation, watch::Sender<bool>)> = HashMap::new();
+    let mut finished: VecDeque<Operation> = VecDeque::new();
+    while let Some(request) = requests_rx.recv().await {
+        match request {
+            Request::Begin(operation, cancel_tx) => {
+                log::info!("{} started", operation.description);
+                running.insert(operation.id, (operation, cancel_tx));
+            },
+            Request::Total(id, total) => {
+                if let Some((operation, _)) = running.get_mut(&id) {
+                    operation.total = Some(total);
+                }
+            },
+            Request::Report(id, robot, result) => {
+                if let Some((operation, _)) = running.get_mut(&id) {
+                    match result {
+                        Ok(_) => operation.succeeded.push(robot),
+                        Err(error) => operation.failed.push((robot, error)),
+                    }
+                }
+            },
+            Request::Finish(id, state) => {
+                if let Some((mut operation, _)) = running.remove(&id) {
+                    log::info!("{} finished: {:?}", operation.description, state);
+                    operation.state = state;
+                    finished.push_front(operation);
+                    finished.truncate(FINISHED_OPERATIONS);
+                }
+            },
+            Request::Cancel(id) => match running.get(&id) {
+                Some((operation, cancel_tx)) => {
+                    log::warn!("Cancelling {}", operation.description);
+                    let _ = cancel_tx.send(true);
+                },
+                None => log::warn!("Operation {} is not running", id),
+            },
+            Request::List(callback) => {
+                let mut operations = running.values()
+                    .map(|(operation, _)| operation.clone())
+                    .collect::<Vec<_>>();
+                operations.sort_by_key(|operation| operation.started);
+                operations.extend(finished.iter().cloned());
+                let _ = callback.send(operations);
+            },
+        }
+    }
+}
+
+/// Responds with the running operations and the operations that finished recently
+pub async fn serve(operations_tx: Sender) -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    let (callback_tx, callback_rx) = oneshot::channel();
+    operations_tx.send(Request::List(callback_tx))
+        .map_err(|_| warp::reject())?;
+    let operations = callback_rx.await
+        .map_err(|_| warp::reject())?;
+    Ok(warp::reply::json(&operations))
+}
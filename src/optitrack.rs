@@ -1,34 +1,66 @@
 use bytes::{Buf, BytesMut};
-use futures::StreamExt;
+use futures::{future, StreamExt};
+use serde::{Deserialize, Serialize};
 use natnet_decode::{
     NatNet,
     NatNetResponse,
     ParseError,
-    FrameOfData
+    FrameOfData,
+    RigidBody,
 };
 use semver::Version;
 use std::{
-    io::{
-        self, BufReader
-    },
-    net::Ipv4Addr,
+    collections::{HashMap, VecDeque},
+    io::BufReader,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
 };
-use tokio::net::UdpSocket;
+use tokio::{net::UdpSocket, sync::{mpsc, oneshot}, time::timeout};
 use tokio_util::{
     udp::UdpFramed,
     codec::Decoder,
 };
 
+use crate::calibration::{self, Calibration};
+use crate::config;
+use crate::journal;
+use crate::region;
+
+/// Version of the NatNet protocol that is assumed if it cannot be detected
+const DEFAULT_VERSION: &str = "2.9.0";
+/// Time to wait for the NatNet server to respond when detecting the protocol version
+const DETECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* message identifiers of the NatNet command protocol */
+const NAT_CONNECT: u16 = 0;
+const NAT_SERVERINFO: u16 = 1;
+const NAT_KEEPALIVE: u16 = 10;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The address of the NatNet server is required for unicast")]
+    MissingServer,
+    #[error("Invalid NatNet version {0}")]
+    InvalidVersion(String),
+    #[error("No response from the NatNet server")]
+    Timeout,
+    #[error("Invalid response from the NatNet server")]
+    InvalidResponse,
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
 #[derive(Debug)]
 struct NatNetCodec {
     version: Version,
 }
 
 impl NatNetCodec {
-    fn new(version: &str) -> Self {
-        NatNetCodec {
-            version: Version::parse(version).unwrap(),
-        }
+    fn new(version: Version) -> Self {
+        NatNetCodec { version }
     }
 }
 
@@ -43,26 +75,652 @@ impl Decoder for NatNetCodec {
     }
 }
 
-pub async fn once() -> io::Result<FrameOfData> {
-    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 1511)).await?;
-    socket.join_multicast_v4(Ipv4Addr::new(239,255,42,99), Ipv4Addr::UNSPECIFIED)?;
-    let mut responses = UdpFramed::new(socket, NatNetCodec::new("2.9.0"));
-    while let Some(Ok(response)) = responses.next().await {
-        if let NatNetResponse::FrameOfData(frame_of_data) = response.0 {
-            return Ok(frame_of_data);
+/// Settings for filtering the tracking data before it is used by the supervisor
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Filter {
+    /// Number of samples over which the position is median filtered, one disables the filter
+    pub median_window: usize,
+    /// Speed (m/s) above which a sample is rejected as an outlier
+    pub max_speed: f32,
+    /// Seconds for which the position of an occluded rigid body is estimated
+    pub max_occlusion: f64,
+    /// Weight of the newest estimate of the velocities and the acceleration, one disables smoothing
+    pub motion_smoothing: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            median_window: 5,
+            max_speed: 5.0,
+            max_occlusion: 0.25,
+            motion_smoothing: 0.3,
+        }
+    }
+}
+
+/// Settings for connecting to the NatNet server of the Optitrack system
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Connection {
+    /// Address of the NatNet server, required for unicast and for detecting the protocol version
+    pub server: Option<Ipv4Addr>,
+    /// Port on which the NatNet server receives commands
+    pub command_port: u16,
+    /// Port to which the NatNet server multicasts the frames
+    pub data_port: u16,
+    /// Multicast group of the frames, the frames are requested by unicast if not given
+    pub multicast_group: Option<Ipv4Addr>,
+    /// Version of the NatNet protocol, detected from the server if not given
+    pub version: Option<String>,
+    /// Seconds between the server sending a frame and the supervisor receiving it, which is added
+    /// to the latency reported by the server when estimating the time of the exposure
+    pub transport_latency: f32,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            server: None,
+            command_port: 1510,
+            data_port: 1511,
+            multicast_group: Some(Ipv4Addr::new(239,255,42,99)),
+            version: None,
+            transport_latency: 0.0,
+        }
+    }
+}
+
+/// Health of the connection to the Optitrack system
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    /// How the frames are received, e.g., the multicast group and port
+    pub mode: String,
+    /// Version of the NatNet protocol used for decoding the frames
+    pub version: Option<Version>,
+    /// The version was reported by the NatNet server
+    pub detected: bool,
+    /// Reason why the connection could not be established
+    pub error: Option<String>,
+    /// Packets received per second
+    pub packet_rate: f32,
+    /// Frames of data received per second
+    pub frame_rate: f32,
+    pub frames: u64,
+    /// Frames missing from the sequence of frame numbers
+    pub dropped_frames: u64,
+    /// Packets that could not be decoded, e.g., due to the wrong protocol version
+    pub decode_errors: u64,
+    /// Time at which the last frame was received
+    pub last_frame: Option<Instant>,
+    /// Mean seconds from the exposure of the cameras to the receipt of a frame
+    pub latency: f32,
+    /// Maximum seconds from the exposure of the cameras to the receipt of a frame
+    pub max_latency: f32,
+    /// Consecutive frames for which each rigid body that has been tracked is missing
+    pub lost_frames: HashMap<i32, u32>,
+    /* counters since the rates were last updated */
+    packets: u32,
+    window_frames: u32,
+    window_latency: f32,
+    window_max_latency: f32,
+    window_start: Option<Instant>,
+    frame_number: Option<i32>,
+}
+
+impl Diagnostics {
+    fn packet(&mut self) {
+        self.packets += 1;
+    }
+
+    /// Records the receipt of a frame and returns the estimated time of the exposure
+    fn frame(&mut self, frame_number: i32, latency: f32, transport_latency: f32) -> Instant {
+        let received = Instant::now();
+        if let Some(previous) = self.frame_number {
+            /* the frame numbers restart if the server is restarted */
+            if frame_number > previous {
+                self.dropped_frames += (frame_number - previous - 1) as u64;
+            }
+        }
+        self.frame_number = Some(frame_number);
+        self.frames += 1;
+        self.window_frames += 1;
+        self.last_frame = Some(received);
+        /* the latency reported by the server covers the exposure to the transmission */
+        let latency = latency + transport_latency;
+        if !latency.is_finite() || latency < 0.0 {
+            return received;
+        }
+        self.window_latency += latency;
+        self.window_max_latency = self.window_max_latency.max(latency);
+        received.checked_sub(Duration::from_secs_f32(latency)).unwrap_or(received)
+    }
+
+    fn rigid_bodies(&mut self, poses: &[Pose]) {
+        for lost_frames in self.lost_frames.values_mut() {
+            *lost_frames += 1;
+        }
+        for pose in poses {
+            self.lost_frames.insert(pose.id, 0);
+        }
+    }
+
+    fn update_rates(&mut self) {
+        let now = Instant::now();
+        if let Some(start) = self.window_start {
+            let elapsed = now.duration_since(start).as_secs_f32();
+            if elapsed > 0.0 {
+                self.packet_rate = self.packets as f32 / elapsed;
+                self.frame_rate = self.window_frames as f32 / elapsed;
+            }
         }
+        if self.window_frames > 0 {
+            self.latency = self.window_latency / self.window_frames as f32;
+            self.max_latency = self.window_max_latency;
+        }
+        self.window_latency = 0.0;
+        self.window_max_latency = 0.0;
+        self.packets = 0;
+        self.window_frames = 0;
+        self.window_start = Some(now);
     }
-    Err(io::Error::new(io::ErrorKind::ConnectionReset, "No more data"))
 }
 
-/*
-pub async fn stream() -> io::Result<()> {
-    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 1511)).await?;
-    socket.join_multicast_v4(Ipv4Addr::new(239,255,42,99), Ipv4Addr::UNSPECIFIED)?;
-    let mut responses = UdpFramed::new(socket, NatNetCodec::new("2.9.0"));
-    while let Some(response) = responses.next().await {
-        eprintln!("response = {:?}", response)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Raw,
+    Filtered,
+}
+
+/// Motion of a rigid body estimated from the filtered poses
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Motion {
+    /// Linear velocity (m/s)
+    pub velocity: [f32; 3],
+    /// Angular velocity (rad/s) about the axes of the tracking system
+    pub angular_velocity: [f32; 3],
+    /// Linear acceleration (m/s²)
+    pub acceleration: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Pose {
+    pub id: i32,
+    pub position: [f32; 3],
+    /// Orientation as a quaternion (w, x, y, z)
+    pub orientation: [f32; 4],
+    /// The rigid body was occluded or rejected as an outlier and its position was estimated
+    pub estimated: bool,
+    /// Only estimated for the filtered stream
+    pub motion: Option<Motion>,
+}
+
+/// The markers of a frame, in the frame of the arena
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Markers {
+    /// Named marker sets and the positions of their markers
+    pub sets: Vec<(String, Vec<[f32; 3]>)>,
+    /// Markers that have been identified by the tracking system
+    pub labeled: Vec<(i32, [f32; 3])>,
+    /// Markers that do not belong to a rigid body or a marker set
+    pub unlabeled: Vec<[f32; 3]>,
+    /// Skeletons and the poses of their bones
+    pub skeletons: Vec<(i32, Vec<Pose>)>,
+}
+
+pub enum Request {
+    Frame(Stream, oneshot::Sender<Vec<Pose>>),
+    Markers(oneshot::Sender<Markers>),
+    Occupancy(oneshot::Sender<region::Occupancy>),
+    Calibrate(calibration::Action),
+    Calibration(oneshot::Sender<calibration::Status>),
+    Diagnostics(oneshot::Sender<Diagnostics>),
+    /// A frame of rigid bodies in the frame of the tracking system that is processed as if it had
+    /// been received from the Optitrack system, e.g., when playing back a demo scenario
+    Playback(Vec<Pose>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/* the samples of a rigid body that were accepted by the filter */
+struct Track {
+    samples: VecDeque<[f32; 3]>,
+    seen: Instant,
+    motion: Motion,
+    pose: Pose,
+}
+
+/// Magnitude of a vector, e.g., the speed for a velocity
+pub fn norm(vector: &[f32; 3]) -> f32 {
+    vector.iter().map(|component| component * component).sum::<f32>().sqrt()
+}
+
+/* finite difference between two vectors, blended with the previous estimate */
+fn derivative(previous: &[f32; 3], current: &[f32; 3], dt: f32, estimate: &[f32; 3], weight: f32) -> [f32; 3] {
+    let mut derivative = [0.0; 3];
+    for axis in 0..3 {
+        let difference = (current[axis] - previous[axis]) / dt;
+        derivative[axis] = estimate[axis] + weight * (difference - estimate[axis]);
+    }
+    derivative
+}
+
+/* angular velocity that rotates the previous orientation into the current orientation */
+fn angular_velocity(previous: &[f32; 4], current: &[f32; 4], dt: f32) -> [f32; 3] {
+    let [aw, ax, ay, az] = *current;
+    /* the conjugate of the previous orientation */
+    let [bw, bx, by, bz] = [previous[0], -previous[1], -previous[2], -previous[3]];
+    let mut delta = [
+        aw * bw - ax * bx - ay * by - az * bz,
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+    ];
+    /* take the shortest rotation */
+    if delta[0] < 0.0 {
+        delta.iter_mut().for_each(|component| *component = -*component);
+    }
+    let axis = [delta[1], delta[2], delta[3]];
+    let sine = norm(&axis);
+    if sine < f32::EPSILON {
+        return [0.0; 3];
+    }
+    let rate = 2.0 * sine.atan2(delta[0]) / dt;
+    [axis[0] / sine * rate, axis[1] / sine * rate, axis[2] / sine * rate]
+}
+
+impl Track {
+    fn median(&self) -> [f32; 3] {
+        let mut median = [0.0; 3];
+        for axis in 0..3 {
+            let mut values = self.samples.iter()
+                .map(|sample| sample[axis])
+                .collect::<Vec<_>>();
+            values.sort_by(|left, right| left.partial_cmp(right)
+                .unwrap_or(std::cmp::Ordering::Equal));
+            median[axis] = values[values.len() / 2];
+        }
+        median
+    }
+}
+
+#[derive(Default)]
+struct Filtering {
+    tracks: HashMap<i32, Track>,
+}
+
+impl Filtering {
+    fn apply(&mut self, settings: &Filter, at: Instant, raw: &[Pose]) -> Vec<Pose> {
+        for pose in raw {
+            match self.tracks.get_mut(&pose.id) {
+                None => {
+                    self.tracks.insert(pose.id, Track {
+                        samples: std::iter::once(pose.position).collect(),
+                        seen: at,
+                        motion: Motion::default(),
+                        pose: Pose { motion: Some(Motion::default()), ..pose.clone() },
+                    });
+                },
+                Some(track) => {
+                    let dt = at.duration_since(track.seen).as_secs_f32().max(f32::EPSILON);
+                    let last = *track.samples.back().unwrap_or(&pose.position);
+                    let speed = norm(&derivative(&last, &pose.position, dt, &[0.0; 3], 1.0));
+                    /* reject samples that would require the rigid body to move implausibly fast */
+                    if speed > settings.max_speed {
+                        log::debug!("Rejected sample of rigid body {} moving at {:.1} m/s", pose.id, speed);
+                        continue;
+                    }
+                    track.samples.push_back(pose.position);
+                    while track.samples.len() > settings.median_window.max(1) {
+                        track.samples.pop_front();
+                    }
+                    /* estimate the motion by differencing the filtered poses */
+                    let position = track.median();
+                    let weight = settings.motion_smoothing.max(0.0).min(1.0);
+                    let velocity = derivative(&track.pose.position, &position, dt, &track.motion.velocity, weight);
+                    let angular = angular_velocity(&track.pose.orientation, &pose.orientation, dt);
+                    track.motion = Motion {
+                        velocity,
+                        angular_velocity: derivative(&[0.0; 3], &angular, 1.0, &track.motion.angular_velocity, weight),
+                        acceleration: derivative(&track.motion.velocity, &velocity, dt, &track.motion.acceleration, weight),
+                    };
+                    track.seen = at;
+                    track.pose = Pose {
+                        position,
+                        motion: Some(track.motion),
+                        ..pose.clone()
+                    };
+                },
+            }
+        }
+        /* estimate the positions of the rigid bodies that were missing from this frame */
+        let max_occlusion = Duration::from_secs_f64(settings.max_occlusion);
+        self.tracks.retain(|_, track| at.duration_since(track.seen) <= max_occlusion);
+        self.tracks.values()
+            .map(|track| match track.seen == at {
+                true => track.pose.clone(),
+                false => {
+                    let dt = at.duration_since(track.seen).as_secs_f32();
+                    let mut position = track.pose.position;
+                    for axis in 0..3 {
+                        position[axis] += track.motion.velocity[axis] * dt;
+                    }
+                    Pose { position, estimated: true, ..track.pose.clone() }
+                }
+            })
+            .collect()
+    }
+}
+
+fn poses(rigid_bodies: &[RigidBody]) -> Vec<Pose> {
+    rigid_bodies.iter()
+        .map(|rigid_body| Pose {
+            id: rigid_body.id,
+            position: [rigid_body.position.x, rigid_body.position.y, rigid_body.position.z],
+            orientation: [
+                rigid_body.orientation.w,
+                rigid_body.orientation.vector().x,
+                rigid_body.orientation.vector().y,
+                rigid_body.orientation.vector().z,
+            ],
+            estimated: false,
+            motion: None,
+        })
+        .collect()
+}
+
+fn markers(frame_of_data: &FrameOfData, transform: &calibration::Transform) -> Markers {
+    Markers {
+        sets: frame_of_data.marker_sets.iter()
+            .map(|set| (set.name.clone(), set.markers.iter()
+                .map(|marker| transform.position(&[marker.x, marker.y, marker.z]))
+                .collect()))
+            .collect(),
+        labeled: frame_of_data.labeled_markers.iter()
+            .map(|marker| (marker.id, transform.position(&[marker.position.x, marker.position.y, marker.position.z])))
+            .collect(),
+        unlabeled: frame_of_data.other_markers.iter()
+            .map(|marker| transform.position(&[marker.x, marker.y, marker.z]))
+            .collect(),
+        skeletons: frame_of_data.skeletons.iter()
+            .map(|skeleton| (skeleton.id, poses(&skeleton.rigid_bodies).iter()
+                .map(|bone| transform.pose(bone))
+                .collect()))
+            .collect(),
+    }
+}
+
+/* a NatNet command packet without a payload */
+fn command(message: u16) -> [u8; 4] {
+    let [id_low, id_high] = message.to_le_bytes();
+    [id_low, id_high, 0, 0]
+}
+
+/// Asks the NatNet server for the version of the protocol that it uses
+async fn detect_version(server: SocketAddr) -> Result<Version> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.send_to(&command(NAT_CONNECT), server).await?;
+    let mut buffer = [0u8; 1024];
+    let (length, _) = timeout(DETECT_TIMEOUT, socket.recv_from(&mut buffer)).await
+        .map_err(|_| Error::Timeout)??;
+    /* message id and size, the application name (256 bytes), the application version and the
+       NatNet version (4 bytes each) */
+    if length < 268 || u16::from_le_bytes([buffer[0], buffer[1]]) != NAT_SERVERINFO {
+        return Err(Error::InvalidResponse);
+    }
+    Ok(Version {
+        major: buffer[264] as u64,
+        minor: buffer[265] as u64,
+        patch: buffer[266] as u64,
+        pre: Vec::new(),
+        build: Vec::new(),
+    })
+}
+
+type Responses = UdpFramed<NatNetCodec>;
+
+/// Binds the socket for the frames and decides on the version of the protocol
+async fn connect(connection: Connection, diagnostics: &mut Diagnostics) -> Result<Responses> {
+    let server = connection.server
+        .map(|server| SocketAddr::from((server, connection.command_port)));
+    let socket = match connection.multicast_group {
+        Some(group) => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, connection.data_port)).await?;
+            socket.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+            diagnostics.mode = format!("Multicast {}:{}", group, connection.data_port);
+            socket
+        },
+        None => {
+            let server = server.ok_or(Error::MissingServer)?;
+            /* the server sends the frames to the address from which the keep alives are sent */
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+            socket.send_to(&command(NAT_CONNECT), server).await?;
+            diagnostics.mode = format!("Unicast from {}", server);
+            socket
+        }
+    };
+    let version = match (connection.version, server) {
+        (Some(version), _) => Version::parse(&version)
+            .map_err(|_| Error::InvalidVersion(version))?,
+        (None, Some(server)) => match detect_version(server).await {
+            Ok(version) => {
+                log::info!("NatNet server {} uses version {}", server, version);
+                diagnostics.detected = true;
+                version
+            },
+            Err(error) => {
+                log::warn!("Could not detect the NatNet version, assuming {}: {}", DEFAULT_VERSION, error);
+                Version::parse(DEFAULT_VERSION).unwrap()
+            }
+        },
+        (None, None) => Version::parse(DEFAULT_VERSION).unwrap(),
+    };
+    diagnostics.version = Some(version.clone());
+    Ok(UdpFramed::new(socket, NatNetCodec::new(version)))
+}
+
+/* the next response or pending forever if there is no connection */
+async fn next(responses: &mut Option<Responses>)
+    -> Option<std::result::Result<(NatNetResponse, SocketAddr), ParseError>> {
+    match responses {
+        Some(responses) => responses.next().await,
+        None => future::pending().await,
+    }
+}
+
+/// Receives the frames from the Optitrack system and keeps the most recent raw and filtered poses
+/// in the frame of the arena
+pub async fn new(mut requests_rx: Receiver,
+                 mut config: config::Receiver,
+                 journal: journal::Sender,
+                 mut calibration: Calibration) {
+    let mut filtering = Filtering::default();
+    let mut occupancy = region::Occupancy::default();
+    /* the poses in the frame of the tracking system, for calibrating the arena frame */
+    let mut untransformed = Vec::new();
+    let mut raw = Vec::new();
+    let mut markers = Markers::default();
+    let mut filtered = Vec::new();
+    /* the seconds of tracking data that the journal keeps while no experiment is running */
+    let mut retention = None;
+    /* reconnect whenever the connection settings change */
+    'connection: loop {
+        let connection = config.borrow().optitrack.clone();
+        let mut diagnostics = Diagnostics::default();
+        let mut responses = match connect(connection.clone(), &mut diagnostics).await {
+            Ok(responses) => Some(responses),
+            Err(error) => {
+                log::error!("Could not connect to the Optitrack system: {}", error);
+                diagnostics.error = Some(error.to_string());
+                None
+            }
+        };
+        /* unicast subscriptions must be kept alive by the client */
+        let keep_alive = match connection.multicast_group {
+            Some(_) => None,
+            None => connection.server
+                .map(|server| SocketAddr::from((server, connection.command_port))),
+        };
+        let mut update = tokio::time::interval(Duration::from_secs(1));
+        let mut playback_frame_number = 0;
+        loop {
+            /* the time of the exposure, the rigid bodies, and the markers of a new frame */
+            let mut frame = None;
+            tokio::select! {
+                Some(response) = next(&mut responses) => {
+                    diagnostics.packet();
+                    match response {
+                        Ok((NatNetResponse::FrameOfData(frame_of_data), _)) => {
+                            /* failure point for testing how the supervisor copes with lost frames */
+                            if config.borrow().chaos.as_ref().map_or(false, |chaos| chaos.drop_frame()) {
+                                continue;
+                            }
+                            let exposure = diagnostics.frame(frame_of_data.frame_number,
+                                frame_of_data.latency, connection.transport_latency);
+                            frame = Some((exposure,
+                                          poses(&frame_of_data.rigid_bodies),
+                                          self::markers(&frame_of_data, calibration.transform())));
+                        },
+                        Ok(_) => {},
+                        Err(error) => {
+                            diagnostics.decode_errors += 1;
+                            log::warn!("Could not decode Optitrack response: {:?}", error);
+                        }
+                    }
+                },
+                _ = update.tick() => {
+                    diagnostics.update_rates();
+                    if let (Some(responses), Some(server)) = (responses.as_ref(), keep_alive) {
+                        if let Err(error) = responses.get_ref().send_to(&command(NAT_KEEPALIVE), server).await {
+                            log::warn!("Could not keep the Optitrack connection alive: {}", error);
+                        }
+                    }
+                },
+                changed = config.changed() => match changed {
+                    Ok(_) => if config.borrow().optitrack != connection {
+                        log::info!("Reconnecting to the Optitrack system");
+                        continue 'connection;
+                    },
+                    Err(_) => break 'connection,
+                },
+                request = requests_rx.recv() => match request {
+                    Some(Request::Frame(stream, callback)) => {
+                        let poses = match stream {
+                            Stream::Raw => raw.clone(),
+                            Stream::Filtered => filtered.clone(),
+                        };
+                        let _ = callback.send(poses);
+                    },
+                    Some(Request::Markers(callback)) => {
+                        let _ = callback.send(markers.clone());
+                    },
+                    Some(Request::Occupancy(callback)) => {
+                        let _ = callback.send(occupancy.clone());
+                    },
+                    Some(Request::Calibrate(action)) => {
+                        if let Err(error) = calibration.execute(action, &untransformed) {
+                            log::error!("Could not calibrate the arena frame: {}", error);
+                        }
+                    },
+                    Some(Request::Calibration(callback)) => {
+                        let _ = callback.send(calibration.status());
+                    },
+                    Some(Request::Diagnostics(callback)) => {
+                        let _ = callback.send(diagnostics.clone());
+                    },
+                    Some(Request::Playback(poses)) => {
+                        diagnostics.packet();
+                        /* played back frames are lost like the frames of the Optitrack system */
+                        if config.borrow().chaos.as_ref().map_or(false, |chaos| chaos.drop_frame()) {
+                            continue;
+                        }
+                        playback_frame_number += 1;
+                        let exposure = diagnostics.frame(playback_frame_number, 0.0, 0.0);
+                        frame = Some((exposure, poses, Markers::default()));
+                    },
+                    None => break 'connection,
+                }
+            }
+            if let Some((exposure, poses, frame_markers)) = frame {
+                untransformed = poses;
+                markers = frame_markers;
+                raw = untransformed.iter()
+                    .map(|pose| calibration.transform().pose(pose))
+                    .collect();
+                diagnostics.rigid_bodies(&raw);
+                let (transitions, record_markers, recording) = {
+                    let config = config.borrow();
+                    filtered = filtering.apply(&config.tracking_filter, exposure, &raw);
+                    (occupancy.update(&config.regions, &filtered), config.record_markers, config.tracking_recording)
+                };
+                if recording != retention {
+                    retention = recording;
+                    journal.retain(retention.map(Duration::from_secs_f64));
+                }
+                /* the marker clouds allow the rigid bodies to be solved again offline */
+                if record_markers {
+                    if let Err(error) = journal.record_at(exposure, journal::Event::Markers(markers.clone())) {
+                        log::warn!("Could not record markers in journal: {}", error);
+                    }
+                }
+                if let Err(error) = journal.record_at(exposure, journal::Event::Tracking(filtered.clone())) {
+                    log::warn!("Could not record tracking data in journal: {}", error);
+                }
+                for transition in transitions {
+                    log::info!("Rigid body {} {} region {}", transition.rigid_body, match transition.crossing {
+                        region::Crossing::Entered => "entered",
+                        region::Crossing::Left => "left",
+                    }, transition.region);
+                    if let Err(error) = journal.record_at(exposure, journal::Event::Region(transition)) {
+                        log::warn!("Could not record region transition in journal: {}", error);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::watch;
+
+    /* plays back a frame with a single rigid body and returns the raw frame afterwards */
+    async fn play_back(chaos: Option<crate::chaos::Chaos>) -> Vec<Pose> {
+        let config = config::Config {
+            /* without a server or a multicast group, only played back frames are tracked */
+            optitrack: Connection { multicast_group: None, ..Default::default() },
+            chaos,
+            ..Default::default()
+        };
+        let (_config_tx, config_rx) = watch::channel(Arc::new(config));
+        let (journal, _) = journal::new();
+        let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+        tokio::spawn(new(requests_rx, config_rx, journal, Calibration::load(None)));
+        let pose = Pose {
+            id: 1,
+            position: [0.0; 3],
+            orientation: [1.0, 0.0, 0.0, 0.0],
+            estimated: false,
+            motion: None,
+        };
+        requests_tx.send(Request::Playback(vec![pose])).unwrap();
+        let (callback_tx, callback_rx) = oneshot::channel();
+        requests_tx.send(Request::Frame(Stream::Raw, callback_tx)).unwrap();
+        callback_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn frames_are_played_back() {
+        assert_eq!(play_back(None).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn dropped_frames_are_not_tracked() {
+        let chaos = crate::chaos::Chaos { drop_frame: 1.0, ..Default::default() };
+        assert!(play_back(Some(chaos)).await.is_empty());
     }
-    Ok(())
 }
-*/
@@ -0,0 +1,309 @@
+use std::{fs, io, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::harvest;
+use crate::history::Run;
+use crate::redact::{self, Redactor};
+use crate::software::Software;
+
+/// Name of the descriptor of the data package in the directory of a run
+const DESCRIPTOR_FILENAME: &str = "datapackage.json";
+/// Name of the copy of the journal in a data package
+const JOURNAL_FILENAME: &str = "journal.pkl";
+/// Directory under which the redacted exports are written, i.e., `exports/<profile>/<run>`
+const EXPORTS_DIR: &str = "exports";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    RedactError(#[from] redact::Error),
+    #[error("{0} is not a valid name for a redaction profile")]
+    InvalidProfile(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A license under which the data of the runs is published, e.g.,
+/// {"name": "CC-BY-4.0", "path": "https://creativecommons.org/licenses/by/4.0/"}
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct License {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A person or an organization that contributed to the runs
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Contributor {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+/// Metadata that is added to the data packages of the runs
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Metadata {
+    /// Prefix of the title of each package, followed by the time at which the run started
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub licenses: Vec<License>,
+    pub contributors: Vec<Contributor>,
+    pub keywords: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    name: String,
+    path: String,
+    bytes: u64,
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct Robot {
+    uuid: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<harvest::Report>,
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: String,
+    files: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Experiment {
+    run: Uuid,
+    aborted: bool,
+    signers: Vec<String>,
+    pipuck_software: Version,
+    drone_software: Version,
+    robots: Vec<Robot>,
+}
+
+#[derive(Serialize)]
+struct Descriptor<'m> {
+    profile: &'static str,
+    name: String,
+    id: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'m String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    homepage: Option<&'m String>,
+    created: String,
+    licenses: &'m [License],
+    contributors: &'m [Contributor],
+    keywords: &'m [String],
+    resources: Vec<Resource>,
+    /* the description of the run in the terms of the supervisor */
+    experiment: Experiment,
+}
+
+/* formats a time as an RFC 3339 timestamp in UTC, converting the days since the Unix epoch into
+   a civil date as described in http://howardhinnant.github.io/date_algorithms.html */
+fn rfc3339(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()) as i64;
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+fn version(software: &Software) -> Version {
+    Version {
+        version: software.version(),
+        files: software.0.iter().map(|(filename, _)| filename.clone()).collect(),
+    }
+}
+
+/* collects the files under a directory of the root, relative to the root */
+fn files(root: &Path, directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(directory))? {
+        let entry = entry?;
+        let path = directory.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            self::files(root, &path, files)?;
+        }
+        else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn resource(root: &Path, path: &Path) -> io::Result<Resource> {
+    let contents = fs::read(root.join(path))?;
+    let digest = openssl::sha::sha256(&contents).iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    /* the paths in the descriptor are always separated by slashes */
+    let path = path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    /* the names of the resources must be lower case and may only contain . _ - and / */
+    let name = path.to_lowercase().chars()
+        .map(|character| match character {
+            'a'..='z' | '0'..='9' | '.' | '_' | '-' | '/' => character,
+            _ => '_',
+        })
+        .collect();
+    Ok(Resource { name, path, bytes: contents.len() as u64, hash: format!("sha256:{}", digest) })
+}
+
+/* copies the results of a run into the directory of a redacted export, redacting the files that
+   are text and leaving out the files that the profile excludes */
+fn copy_results(uuid: &Uuid, root: &Path, redactor: &Redactor) -> Result<()> {
+    let results = harvest::run_dir(uuid);
+    if !results.exists() {
+        return Ok(());
+    }
+    let mut paths = Vec::new();
+    files(&results, Path::new(""), &mut paths)?;
+    for path in paths {
+        if path.as_path() == Path::new(DESCRIPTOR_FILENAME) ||
+           path.as_path() == Path::new(JOURNAL_FILENAME) ||
+           redactor.excludes(&path) {
+            continue;
+        }
+        let destination = root.join(&path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(destination, redactor.contents(fs::read(results.join(&path))?))?;
+    }
+    Ok(())
+}
+
+/// Writes a data package for a run. Without a redaction profile, the package is written into the
+/// directory of results of the run (`results/<run>`): the software of the run and its journal are
+/// copied into the directory, and a descriptor lists every file in the directory with its
+/// checksum together with the robots, the versions of the software, and the given metadata. With
+/// a profile, the package is written into `exports/<profile>/<run>` instead, and the results, the
+/// journal, and the descriptor are redacted. Returns the path of the descriptor.
+pub fn export(uuid: &Uuid, run: &Run, metadata: &Metadata, profile: Option<&redact::Profile>) -> Result<PathBuf> {
+    let redactor = profile.map(|profile| Redactor::new(profile, &run.names));
+    let root = match redactor.as_ref() {
+        Some(redactor) => {
+            let name = &redactor.profile().name;
+            /* the name of the profile is used as a directory */
+            if !harvest::is_within_working_dir(Path::new(name)) || Path::new(name).components().count() != 1 {
+                return Err(Error::InvalidProfile(name.clone()));
+            }
+            let root = Path::new(EXPORTS_DIR).join(name).join(uuid.to_string());
+            /* files that the profile redacts may remain from an earlier export */
+            if root.exists() {
+                fs::remove_dir_all(&root)?;
+            }
+            fs::create_dir_all(&root)?;
+            copy_results(uuid, &root, redactor)?;
+            root
+        },
+        None => {
+            let root = harvest::run_dir(uuid);
+            fs::create_dir_all(&root)?;
+            root
+        }
+    };
+    /* copy the software and the journal of the run */
+    for (kind, software) in [("pipuck", &run.pipuck_software), ("drone", &run.drone_software)].iter() {
+        let directory = root.join("software").join(kind);
+        for (filename, contents) in software.0.iter() {
+            /* the filenames of the software may not leave its directory */
+            if !harvest::is_within_working_dir(Path::new(filename)) {
+                log::warn!("Skipping file {} of the {} software", filename, kind);
+                continue;
+            }
+            let path = directory.join(filename);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+    }
+    if let Some(journal) = run.journal.as_ref() {
+        match journal.exists() {
+            true => match redactor.as_ref() {
+                Some(redactor) => redactor.journal(journal, &root.join(JOURNAL_FILENAME))?,
+                None => {
+                    fs::copy(journal, root.join(JOURNAL_FILENAME))?;
+                },
+            },
+            false => log::warn!("Journal {} of run {} no longer exists", journal.display(), uuid),
+        }
+    }
+    /* list the files of the package */
+    let mut paths = Vec::new();
+    files(&root, Path::new(""), &mut paths)?;
+    paths.sort();
+    let resources = paths.iter()
+        .filter(|path| path.as_path() != Path::new(DESCRIPTOR_FILENAME))
+        .map(|path| resource(&root, path))
+        .collect::<io::Result<Vec<_>>>()?;
+    let created = rfc3339(run.started);
+    let descriptor = Descriptor {
+        profile: "data-package",
+        name: format!("run-{}", uuid),
+        id: uuid.to_urn().to_string(),
+        title: match metadata.title.as_ref() {
+            Some(title) => format!("{} {}", title, created),
+            None => format!("Run {}", created),
+        },
+        description: metadata.description.as_ref(),
+        homepage: metadata.homepage.as_ref(),
+        created,
+        licenses: &metadata.licenses,
+        contributors: &metadata.contributors,
+        keywords: &metadata.keywords,
+        resources,
+        experiment: Experiment {
+            run: *uuid,
+            aborted: run.aborted,
+            signers: match redactor.as_ref() {
+                Some(redactor) if redactor.profile().operators => Vec::new(),
+                _ => run.signers.clone(),
+            },
+            pipuck_software: version(&run.pipuck_software),
+            drone_software: version(&run.drone_software),
+            robots: run.robots.iter()
+                .map(|robot| Robot {
+                    uuid: *robot,
+                    hostname: match redactor.as_ref() {
+                        Some(redactor) if redactor.profile().hostnames => None,
+                        _ => run.names.get(robot).cloned(),
+                    },
+                    results: run.results.get(robot).map(|report| match redactor.as_ref() {
+                        Some(redactor) => redactor.report(report),
+                        None => report.clone(),
+                    }),
+                })
+                .collect(),
+        },
+    };
+    let path = root.join(DESCRIPTOR_FILENAME);
+    fs::write(&path, serde_json::to_vec_pretty(&descriptor)?)?;
+    Ok(path)
+}
@@ -0,0 +1,100 @@
+use std::{sync::Arc, time::Duration};
+use futures::{future::{self, BoxFuture}, stream::FuturesUnordered, StreamExt};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{arena, webui};
+
+/// Time that a plugin is given to handle an action before it is abandoned
+const ACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Extends the supervisor with webui tabs, handlers for actions and periodic tasks. Plugins are
+/// registered in main before the supervisor starts, e.g., a monitor for the charging docks of a lab
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Title of the webui tab provided by this plugin
+    fn tab(&self) -> Option<&str> {
+        None
+    }
+
+    /// Generates the cards for the webui tab of this plugin
+    fn cards<'a>(&'a self, _arena: &'a mpsc::UnboundedSender<arena::Request>)
+        -> BoxFuture<'a, webui::Cards> {
+        Box::pin(future::ready(Vec::new()))
+    }
+
+    /// Handles an action from a card, plugins should ignore cards that they did not create
+    fn execute<'a>(&'a self, _uuid: Uuid, _action: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(future::ready(()))
+    }
+
+    /// Period at which `tick` is called
+    fn period(&self) -> Option<Duration> {
+        None
+    }
+
+    fn tick<'a>(&'a self, _arena: &'a mpsc::UnboundedSender<arena::Request>) -> BoxFuture<'a, ()> {
+        Box::pin(future::ready(()))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Registry {
+    plugins: Vec<Arc<dyn Plugin>>,
+}
+
+impl Registry {
+    pub fn register<P: Plugin + 'static>(&mut self, plugin: P) {
+        log::info!("Registered plugin {}", plugin.name());
+        self.plugins.push(Arc::new(plugin));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Plugin>> {
+        self.plugins.iter()
+    }
+
+    /// Titles of the webui tabs provided by the plugins
+    pub fn tabs(&self) -> Vec<String> {
+        self.plugins.iter()
+            .filter_map(|plugin| plugin.tab().map(str::to_owned))
+            .collect()
+    }
+
+    pub fn find_tab(&self, title: &str) -> Option<&Arc<dyn Plugin>> {
+        self.plugins.iter().find(|plugin| plugin.tab() == Some(title))
+    }
+
+    /// Passes an action to every plugin, each plugin handles the action on its own task so that a
+    /// slow plugin does not hold up the caller
+    pub fn execute(&self, uuid: Uuid, action: String) {
+        for plugin in self.plugins.iter().cloned() {
+            let action = action.clone();
+            tokio::spawn(async move {
+                if let Err(_) = tokio::time::timeout(ACTION_TIMEOUT, plugin.execute(uuid, &action)).await {
+                    log::warn!("Plugin {} did not handle action {} in time", plugin.name(), action);
+                }
+            });
+        }
+    }
+}
+
+/// Runs the periodic tasks of the registered plugins
+pub async fn new(registry: Registry, arena_request_tx: mpsc::UnboundedSender<arena::Request>) {
+    let mut tasks = registry.iter()
+        .filter_map(|plugin| plugin.period().map(|period| (plugin.clone(), period)))
+        .map(|(plugin, period)| {
+            let arena_request_tx = arena_request_tx.clone();
+            async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    plugin.tick(&arena_request_tx).await;
+                }
+            }
+        })
+        .collect::<FuturesUnordered<_>>();
+    /* the periodic tasks never complete, do not complete if there are none */
+    while tasks.next().await.is_some() {}
+    future::pending::<()>().await
+}
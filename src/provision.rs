@@ -0,0 +1,304 @@
+use std::{collections::BTreeMap, fmt, net::Ipv4Addr, path::PathBuf, process::Stdio, time::Duration};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use tokio::{io::AsyncWriteExt, net::TcpStream, process::Command, sync::{mpsc, oneshot}};
+
+use crate::arena::Kind;
+use crate::config;
+use crate::secrets;
+
+/// Time within which a robot must come back with the fernbedienung service after rebooting
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(180);
+/// Interval at which the fernbedienung service of a rebooting robot is probed
+const VERIFY_PERIOD: Duration = Duration::from_secs(5);
+/// Port of the fernbedienung service
+const FERNBEDIENUNG_PORT: u16 = 17653;
+/// File on the robot that records the kind of robot it was provisioned as
+const MARKER_PATH: &str = "/etc/mns-robot";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Provisioning has not been configured")]
+    NotConfigured,
+    #[error("{0} is not a valid hostname")]
+    InvalidHostname(String),
+    #[error("Hostname {0} is not classified as a {1:?} in the configuration")]
+    UnclassifiedHostname(String, Kind),
+    #[error("The Wi-Fi {0} contains a quote or a control character")]
+    InvalidWifi(&'static str),
+    #[error("{0} failed with {1}: {2}")]
+    StepFailed(Step, std::process::ExitStatus, String),
+    #[error("The fernbedienung service did not come up within {0:?}")]
+    VerifyTimeout(Duration),
+
+    #[error(transparent)]
+    SecretError(#[from] secrets::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Wi-Fi network that a provisioned robot joins
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Wifi {
+    pub ssid: String,
+    pub psk: secrets::Secret,
+    /// Configuration file of wpa_supplicant on the robot
+    #[serde(default = "default_wpa_supplicant")]
+    pub path: PathBuf,
+}
+
+/* wpa_supplicant does not unescape quoted strings, so these characters would end the string or
+   the line and inject further directives into the configuration */
+fn check_wifi_string(name: &'static str, value: &str) -> Result<()> {
+    match value.chars().any(|character| character == '"' || character.is_control()) {
+        true => Err(Error::InvalidWifi(name)),
+        false => Ok(()),
+    }
+}
+
+fn default_wpa_supplicant() -> PathBuf {
+    PathBuf::from("/etc/wpa_supplicant.conf")
+}
+
+/// How a freshly flashed robot is reached and what is installed on it
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Provisioning {
+    /// User with which the robot is reached over SSH
+    #[serde(default = "default_user")]
+    pub user: String,
+    /// Default password of the user, if not given the SSH keys of the supervisor are used
+    pub password: Option<secrets::Secret>,
+    /// Public keys that are authorized for the user
+    pub authorized_keys: Option<secrets::Secret>,
+    pub wifi: Option<Wifi>,
+    /// Package (ipk) of the fernbedienung service
+    pub fernbedienung: PathBuf,
+}
+
+fn default_user() -> String {
+    "root".to_owned()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    Connect,
+    Hostname,
+    Wifi,
+    Fernbedienung,
+    Keys,
+    Marker,
+    Reboot,
+    Verify,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Connect => write!(f, "Connecting"),
+            Step::Hostname => write!(f, "Setting hostname"),
+            Step::Wifi => write!(f, "Configuring Wi-Fi"),
+            Step::Fernbedienung => write!(f, "Installing fernbedienung"),
+            Step::Keys => write!(f, "Installing SSH keys"),
+            Step::Marker => write!(f, "Marking robot type"),
+            Step::Reboot => write!(f, "Rebooting"),
+            Step::Verify => write!(f, "Verifying"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    Running(Step),
+    Completed,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub hostname: String,
+    pub kind: Kind,
+    pub state: State,
+}
+
+pub enum Request {
+    Start(Ipv4Addr, Kind, String),
+    Progress(oneshot::Sender<BTreeMap<Ipv4Addr, Job>>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+/* a shell command that writes the contents to a file on the robot */
+fn write_file(path: &str, contents: &[u8]) -> String {
+    format!("echo {} | base64 -d > {}\n", base64::encode(contents), path)
+}
+
+/* a robot that is reached using the system ssh client and sshpass for passwords */
+struct Remote {
+    addr: Ipv4Addr,
+    user: String,
+    password: Option<Vec<u8>>,
+    steps_tx: mpsc::UnboundedSender<(Ipv4Addr, Step)>,
+}
+
+impl Remote {
+    async fn run(&self, step: Step, script: String) -> Result<()> {
+        let _ = self.steps_tx.send((self.addr, step));
+        log::info!("Provisioning {}: {}", self.addr, step);
+        ssh(self.addr, &self.user, &self.password, step, script).await
+    }
+}
+
+async fn ssh(addr: Ipv4Addr, user: &str, password: &Option<Vec<u8>>, step: Step, script: String) -> Result<()> {
+    let destination = format!("{}@{}", user, addr);
+    let mut command = match password {
+        Some(password) => {
+            let mut command = Command::new("sshpass");
+            command.arg("-e").arg("ssh").env("SSHPASS", String::from_utf8_lossy(password).as_ref());
+            command
+        },
+        None => {
+            let mut command = Command::new("ssh");
+            command.arg("-o").arg("BatchMode=yes");
+            command
+        }
+    };
+    /* a freshly flashed robot has a new host key */
+    let mut child = command
+        .args(&["-o", "StrictHostKeyChecking=no", "-o", "UserKnownHostsFile=/dev/null", "-o", "ConnectTimeout=10"])
+        .arg(destination)
+        .arg("sh -s")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(format!("set -e\n{}", script).as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(Error::StepFailed(step, output.status, String::from_utf8_lossy(&output.stderr).trim().to_owned())),
+    }
+}
+
+async fn provision(addr: Ipv4Addr,
+                   kind: Kind,
+                   hostname: String,
+                   settings: Provisioning,
+                   secrets: secrets::Store,
+                   steps_tx: mpsc::UnboundedSender<(Ipv4Addr, Step)>) -> Result<()> {
+    let password = match &settings.password {
+        Some(password) => Some(password.resolve(&secrets).await?),
+        None => None,
+    };
+    let robot = Remote { addr, user: settings.user.clone(), password, steps_tx };
+    robot.run(Step::Connect, "true\n".to_owned()).await?;
+    robot.run(Step::Hostname, format!("echo {0} > /etc/hostname\nhostname {0}\n", hostname)).await?;
+    if let Some(wifi) = &settings.wifi {
+        let psk = String::from_utf8_lossy(&wifi.psk.resolve(&secrets).await?).into_owned();
+        check_wifi_string("SSID", &wifi.ssid)?;
+        check_wifi_string("pre-shared key", &psk)?;
+        let config = format!("ctrl_interface=/var/run/wpa_supplicant\nupdate_config=1\n\nnetwork={{\n\tssid=\"{}\"\n\tpsk=\"{}\"\n}}\n",
+            wifi.ssid, psk);
+        robot.run(Step::Wifi, write_file(&wifi.path.to_string_lossy(), config.as_bytes())).await?;
+    }
+    let package = tokio::fs::read(&settings.fernbedienung).await?;
+    robot.run(Step::Fernbedienung, format!("{}opkg install /tmp/fernbedienung.ipk\nrm /tmp/fernbedienung.ipk\nsystemctl enable fernbedienung\n",
+        write_file("/tmp/fernbedienung.ipk", &package))).await?;
+    if let Some(authorized_keys) = &settings.authorized_keys {
+        let authorized_keys = authorized_keys.resolve(&secrets).await?;
+        robot.run(Step::Keys, format!("mkdir -p -m 700 ~/.ssh\n{}chmod 600 ~/.ssh/authorized_keys\n",
+            write_file("~/.ssh/authorized_keys", &authorized_keys))).await?;
+    }
+    let marker = match kind {
+        Kind::PiPuck => "pipuck\n",
+        Kind::Drone => "drone\n",
+    };
+    robot.run(Step::Marker, write_file(MARKER_PATH, marker.as_bytes())).await?;
+    /* the connection is usually dropped before ssh can report the exit status of reboot */
+    let _ = robot.run(Step::Reboot, "sync\n(sleep 1; reboot) > /dev/null 2>&1 &\n".to_owned()).await;
+    let _ = robot.steps_tx.send((addr, Step::Verify));
+    tokio::time::sleep(VERIFY_PERIOD).await;
+    tokio::time::timeout(VERIFY_TIMEOUT, async {
+        loop {
+            if TcpStream::connect((addr, FERNBEDIENUNG_PORT)).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(VERIFY_PERIOD).await;
+        }
+    }).await.map_err(|_| Error::VerifyTimeout(VERIFY_TIMEOUT))
+}
+
+/* checks that the robot will be recognised by the network task once it is back up */
+fn check(config: &config::Config, kind: Kind, hostname: &str) -> Result<Provisioning> {
+    let settings = config.provisioning.clone().ok_or(Error::NotConfigured)?;
+    if hostname.is_empty() || !hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(Error::InvalidHostname(hostname.to_owned()));
+    }
+    let hostnames = match kind {
+        Kind::PiPuck => &config.pipuck_hostnames,
+        Kind::Drone => &config.upcore_hostnames,
+    };
+    match hostnames.iter().any(|classified| classified == hostname) ||
+        crate::fleet::contains(&config.fleet, kind, hostname) {
+        true => Ok(settings),
+        false => Err(Error::UnclassifiedHostname(hostname.to_owned(), kind)),
+    }
+}
+
+/// Provisions freshly flashed robots that appear on the network with their default credentials
+pub async fn new(mut requests_rx: Receiver, config: config::Receiver, secrets: secrets::Store) {
+    let mut jobs: BTreeMap<Ipv4Addr, Job> = BTreeMap::new();
+    let mut provisions = FuturesUnordered::new();
+    let (steps_tx, mut steps_rx) = mpsc::unbounded_channel();
+    loop {
+        tokio::select! {
+            Some((addr, step)) = steps_rx.recv() => {
+                if let Some(job) = jobs.get_mut(&addr) {
+                    job.state = State::Running(step);
+                }
+            },
+            Some((addr, result)) = provisions.next() => {
+                if let Some(job) = jobs.get_mut(&addr) {
+                    job.state = match result {
+                        Ok(_) => {
+                            log::info!("Provisioned {} as {}", addr, job.hostname);
+                            State::Completed
+                        },
+                        Err(error) => {
+                            log::error!("Could not provision {}: {}", addr, error);
+                            State::Failed(error.to_string())
+                        }
+                    };
+                }
+            },
+            request = requests_rx.recv() => match request {
+                Some(Request::Start(addr, kind, hostname)) => {
+                    if let Some(Job { state: State::Running(_), .. }) = jobs.get(&addr) {
+                        log::warn!("{} is already being provisioned", addr);
+                        continue;
+                    }
+                    let state = match check(&config.borrow(), kind, &hostname) {
+                        Ok(settings) => {
+                            let provision = provision(addr, kind, hostname.clone(), settings, secrets.clone(), steps_tx.clone());
+                            provisions.push(async move { (addr, provision.await) });
+                            State::Running(Step::Connect)
+                        },
+                        Err(error) => {
+                            log::error!("Could not provision {}: {}", addr, error);
+                            State::Failed(error.to_string())
+                        }
+                    };
+                    jobs.insert(addr, Job { hostname, kind, state });
+                },
+                Some(Request::Progress(callback)) => {
+                    let _ = callback.send(jobs.clone());
+                },
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{fs::File, io::{BufReader, BufWriter}, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use web_push::{
+    ContentEncoding,
+    SubscriptionInfo,
+    VapidSignatureBuilder,
+    WebPushClient,
+    WebPushError,
+    WebPushMessageBuilder,
+};
+
+use crate::config;
+use crate::secrets;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    WebPushError(#[from] WebPushError),
+    #[error(transparent)]
+    SecretError(#[from] secrets::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The private VAPID key, either a secret or the path of a file as in the configurations from
+/// before secrets were supported
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum PrivateKey {
+    Secret(secrets::Secret),
+    Path(PathBuf),
+}
+
+impl PrivateKey {
+    async fn resolve(&self, store: &secrets::Store) -> secrets::Result<Vec<u8>> {
+        match self {
+            PrivateKey::Secret(secret) => secret.resolve(store).await,
+            PrivateKey::Path(path) => Ok(tokio::fs::read(path).await?),
+        }
+    }
+}
+
+/// Settings for delivering critical alerts as web push notifications
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Settings {
+    /// Public VAPID key (base64url) with which the browsers subscribe
+    pub public_key: String,
+    /// Private VAPID key in PEM format
+    pub private_key: PrivateKey,
+    /// Contact for the push services, e.g., mailto:operator@example.com
+    pub subject: String,
+    /// File in which the subscriptions of the operator devices are stored
+    pub subscriptions: PathBuf,
+}
+
+/// A browser that receives the alerts, as returned by PushManager.subscribe()
+pub type Subscription = SubscriptionInfo;
+
+pub enum Request {
+    Subscribe(Subscription),
+    Alert(String),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+fn load(path: &PathBuf) -> Result<Vec<Subscription>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+fn save(path: &PathBuf, subscriptions: &[Subscription]) -> Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, subscriptions)?;
+    Ok(())
+}
+
+async fn send(client: &WebPushClient,
+              settings: &Settings,
+              private_key: &[u8],
+              subscription: &Subscription,
+              payload: &[u8]) -> Result<()> {
+    let mut signature = VapidSignatureBuilder::from_pem(private_key, subscription)?;
+    signature.add_claim("sub", settings.subject.as_str());
+    let mut message = WebPushMessageBuilder::new(subscription)?;
+    message.set_payload(ContentEncoding::Aes128Gcm, payload);
+    message.set_vapid_signature(signature.build()?);
+    client.send(message.build()?).await?;
+    Ok(())
+}
+
+/// Stores the subscriptions of the operator devices and pushes the alerts to them
+pub async fn new(mut requests_rx: Receiver, config: config::Receiver, secrets: secrets::Store) {
+    let client = match WebPushClient::new() {
+        Ok(client) => client,
+        Err(error) => {
+            log::error!("Could not create web push client: {}", error);
+            return;
+        }
+    };
+    /* the subscriptions are reloaded if the file in which they are stored changes */
+    let mut store: Option<(PathBuf, Vec<Subscription>)> = None;
+    while let Some(request) = requests_rx.recv().await {
+        let settings = match config.borrow().push.clone() {
+            Some(settings) => settings,
+            None => {
+                log::warn!("Web push notifications have not been configured");
+                continue;
+            }
+        };
+        if store.as_ref().map_or(true, |(path, _)| path != &settings.subscriptions) {
+            let subscriptions = load(&settings.subscriptions).unwrap_or_else(|error| {
+                log::error!("Could not load push subscriptions from {}: {}", settings.subscriptions.display(), error);
+                Vec::new()
+            });
+            store = Some((settings.subscriptions.clone(), subscriptions));
+        }
+        let subscriptions = match store.as_mut() {
+            Some((_, subscriptions)) => subscriptions,
+            None => continue,
+        };
+        let changed = match request {
+            Request::Subscribe(subscription) => {
+                match subscriptions.iter().any(|existing| existing.endpoint == subscription.endpoint) {
+                    true => false,
+                    false => {
+                        log::info!("Subscribed {} to critical alerts", subscription.endpoint);
+                        subscriptions.push(subscription);
+                        true
+                    }
+                }
+            },
+            Request::Alert(body) => {
+                let payload = match serde_json::to_vec(&Payload { title: "MNS Supervisor", body: &body }) {
+                    Ok(payload) => payload,
+                    Err(error) => {
+                        log::error!("Could not serialize alert: {}", error);
+                        continue;
+                    }
+                };
+                let private_key = match settings.private_key.resolve(&secrets).await {
+                    Ok(private_key) => private_key,
+                    Err(error) => {
+                        log::error!("Could not read the private VAPID key: {}", error);
+                        continue;
+                    }
+                };
+                let count = subscriptions.len();
+                let mut retained = Vec::with_capacity(count);
+                for subscription in subscriptions.drain(..) {
+                    match send(&client, &settings, &private_key, &subscription, &payload).await {
+                        /* the browser has unsubscribed or the subscription has expired */
+                        Err(Error::WebPushError(WebPushError::EndpointNotValid)) |
+                        Err(Error::WebPushError(WebPushError::EndpointNotFound)) =>
+                            log::info!("Removed expired push subscription {}", subscription.endpoint),
+                        result => {
+                            if let Err(error) = result {
+                                log::warn!("Could not push alert to {}: {}", subscription.endpoint, error);
+                            }
+                            retained.push(subscription);
+                        }
+                    }
+                }
+                *subscriptions = retained;
+                subscriptions.len() != count
+            }
+        };
+        if changed {
+            if let Err(error) = save(&settings.subscriptions, subscriptions) {
+                log::error!("Could not save push subscriptions to {}: {}", settings.subscriptions.display(), error);
+            }
+        }
+    }
+}
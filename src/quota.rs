@@ -0,0 +1,53 @@
+use std::io;
+use serde::Deserialize;
+
+/// Extension of the journals and their spool files, see journal.rs
+const JOURNAL_EXTENSION: &str = "pkl";
+
+/// Limits on the storage used by the supervisor, unlimited if not given
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Quotas {
+    /// Bytes of software that can be uploaded for the Pi-Pucks and the drones together
+    pub software: Option<u64>,
+    /// Bytes of the journals of the runs in the working directory, a journal stops recording
+    /// when it would exceed this quota
+    pub runs: Option<u64>,
+}
+
+/// The storage used by the supervisor and the quotas that apply to it
+#[derive(Clone, Debug, Default)]
+pub struct Usage {
+    /// Bytes of the uploaded software
+    pub software: u64,
+    pub quotas: Quotas,
+    /// The last upload that was refused because it would have exceeded the software quota
+    pub refused_upload: Option<String>,
+}
+
+/// Bytes used by the journals of the runs (including the spool files) in the working directory
+pub fn run_usage() -> io::Result<u64> {
+    let mut usage = 0;
+    for entry in std::fs::read_dir(".")? {
+        let entry = entry?;
+        if entry.path().extension().map_or(false, |extension| extension == JOURNAL_EXTENSION) {
+            usage += entry.metadata()?.len();
+        }
+    }
+    Ok(usage)
+}
+
+/// Formats a number of bytes for humans, e.g., 1.5 MiB
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
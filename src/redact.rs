@@ -0,0 +1,288 @@
+use std::{cell::Cell, collections::HashMap, fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}};
+use regex::Regex;
+use serde::Deserialize;
+use serde_pickle::{HashableValue, Value};
+use uuid::Uuid;
+
+use crate::harvest;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Journal {} ends within the entry at byte {}", .0.display(), .1)]
+    TruncatedJournal(PathBuf, u64),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    PickleError(#[from] serde_pickle::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Replacement of the addresses and of the identities of the operators
+const REDACTED: &str = "[redacted]";
+
+/// Extensions of the files that are treated as camera imagery
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "bmp", "gif", "tif", "tiff", "mjpg", "mjpeg", "avi", "mp4"];
+
+lazy_static::lazy_static! {
+    static ref REGEX_IPV4: Regex =
+        Regex::new(r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d{1,5})?\b").unwrap();
+    /* full and compressed addresses with an optional zone, in brackets if followed by a port. Paths
+       such as Request::Add are left alone, and so is the loopback address ::1 */
+    static ref REGEX_IPV6: Regex = {
+        let address = concat!(
+            r"(?:\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){7}\b",
+            r"|\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){1,6}::(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b)?",
+            r"|\b[0-9A-Fa-f]{1,4}::[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b)(?:%[0-9A-Za-z]+)?");
+        Regex::new(&format!(r"\[{0}\](?::\d{{1,5}})?|{0}", address)).unwrap()
+    };
+    static ref REGEX_MAC: Regex =
+        Regex::new(r"\b[0-9A-Fa-f]{2}(?::[0-9A-Fa-f]{2}){5}\b").unwrap();
+}
+
+/// What is removed from a run when it is exported for publication or sharing, e.g.,
+/// {"name": "public", "addresses": true, "hostnames": true, "operators": true, "imagery": true}
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    /// Name under which the profile is chosen when exporting a run
+    pub name: String,
+    /// Replace IPv4 and IPv6 addresses, with their ports, and MAC addresses
+    pub addresses: bool,
+    /// Replace the hostnames of the robots with their unique identifiers
+    pub hostnames: bool,
+    /// Remove the authors of chat messages and the signers of the software
+    pub operators: bool,
+    /// Leave out images and videos, e.g., frames of the cameras of the robots
+    pub imagery: bool,
+}
+
+/// Redacts the runs according to a profile, the hostnames of the robots are replaced by their
+/// unique identifiers so that the data of a robot can still be told apart
+pub struct Redactor<'p> {
+    profile: &'p Profile,
+    hostnames: Vec<(String, String)>,
+}
+
+impl<'p> Redactor<'p> {
+    pub fn new(profile: &'p Profile, names: &HashMap<Uuid, String>) -> Self {
+        let mut hostnames = names.iter()
+            .filter(|(_, name)| !name.is_empty())
+            .map(|(uuid, name)| (name.clone(), uuid.to_string()))
+            .collect::<Vec<_>>();
+        /* longer names first so that a name is not replaced within a longer name */
+        hostnames.sort_by(|(left, _), (right, _)| right.len().cmp(&left.len()));
+        Redactor { profile, hostnames }
+    }
+
+    pub fn profile(&self) -> &Profile {
+        self.profile
+    }
+
+    /// Whether a file is left out of the export
+    pub fn excludes(&self, path: &Path) -> bool {
+        self.profile.imagery && path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+    }
+
+    pub fn text(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+        if self.profile.addresses {
+            /* before IPv6, whose pattern would otherwise match the first octet of an IPv4 mapped address */
+            text = REGEX_IPV4.replace_all(&text, REDACTED).into_owned();
+            text = REGEX_IPV6.replace_all(&text, REDACTED).into_owned();
+            text = REGEX_MAC.replace_all(&text, REDACTED).into_owned();
+        }
+        if self.profile.hostnames {
+            for (hostname, uuid) in self.hostnames.iter() {
+                text = text.replace(hostname.as_str(), uuid);
+            }
+        }
+        text
+    }
+
+    /// Redacts the contents of a file, files that are not text are returned unchanged
+    pub fn contents(&self, contents: Vec<u8>) -> Vec<u8> {
+        match String::from_utf8(contents) {
+            Ok(text) => self.text(&text).into_bytes(),
+            Err(error) => error.into_bytes(),
+        }
+    }
+
+    /// Redacts the files that could not be collected from a robot and why, the collected files
+    /// keep their paths as they are exported under them
+    pub fn report(&self, report: &harvest::Report) -> harvest::Report {
+        harvest::Report {
+            failed: report.failed.iter()
+                .map(|(path, reason)| (self.text(path), self.text(reason)))
+                .collect(),
+            ..report.clone()
+        }
+    }
+
+    fn value(&self, value: Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.text(&text)),
+            Value::Bytes(bytes) => Value::Bytes(self.contents(bytes)),
+            Value::List(values) => Value::List(values.into_iter().map(|value| self.value(value)).collect()),
+            Value::Tuple(values) => Value::Tuple(values.into_iter().map(|value| self.value(value)).collect()),
+            Value::Dict(entries) => Value::Dict(entries.into_iter()
+                .map(|(key, value)| {
+                    let value = match (&key, self.profile.operators) {
+                        /* the author of a chat message */
+                        (HashableValue::String(key), true) if key == "author" => Value::String(REDACTED.to_owned()),
+                        _ => self.value(value),
+                    };
+                    (key, value)
+                })
+                .collect()),
+            value => value,
+        }
+    }
+
+    /// Writes a redacted copy of a journal, the journal is a sequence of pickled entries. A journal
+    /// that ends within an entry, e.g., after the supervisor crashed, is reported as truncated
+    pub fn journal(&self, source: &Path, destination: &Path) -> Result<()> {
+        let consumed = Cell::new(0);
+        let reader = Counted { inner: BufReader::new(File::open(source)?), consumed: &consumed };
+        let mut deserializer = serde_pickle::Deserializer::new(reader, false);
+        let mut writer = BufWriter::new(File::create(destination)?);
+        loop {
+            let start = consumed.get();
+            match deserializer.deserialize_value() {
+                Ok(entry) => serde_pickle::value_to_writer(&mut writer, &self.value(entry), true)?,
+                Err(serde_pickle::Error::Eval(serde_pickle::ErrorCode::EOFWhileParsing, _)) => match consumed.get() == start {
+                    true => break,
+                    false => return Err(Error::TruncatedJournal(source.to_owned(), start)),
+                },
+                Err(error) => return Err(error.into()),
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/* hands the deserializer one byte at a time so that it can not read ahead of the entry that it
+   is parsing, which makes the bytes that it consumed equal to the bytes that were read */
+struct Counted<'c, R> {
+    inner: R,
+    consumed: &'c Cell<u64>,
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = buffer.len().min(1);
+        let read = self.inner.read(&mut buffer[..length])?;
+        self.consumed.set(self.consumed.get() + read as u64);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, net::{IpAddr, Ipv6Addr}, time::{Duration, SystemTime}};
+    use bytes::BytesMut;
+    use crate::journal::{Entry, Event, Robot};
+
+    const HOSTNAME: &str = "pipuck-01";
+
+    fn profile() -> Profile {
+        Profile {
+            name: "public".to_owned(),
+            addresses: true,
+            hostnames: true,
+            operators: true,
+            imagery: true,
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle.as_bytes())
+    }
+
+    /* writes a journal the way the journal task does, with entries that contain an address, a
+       hostname, and the author of a chat message */
+    fn write_journal(path: &Path) {
+        let schemas = crate::schema::Registry::default();
+        let output = format!("{} connected from 192.168.1.5:4950", HOSTNAME);
+        let events = vec![
+            Event::Robot(Uuid::new_v4(), Robot::StandardOutput(BytesMut::from(output.as_str()))),
+            Event::Dropped(IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x42)), "Malformed frame".to_owned()),
+            Event::Chat(crate::chat::Message {
+                sent: SystemTime::now(),
+                author: "operator".to_owned(),
+                text: "Starting the run".to_owned(),
+                run: None,
+                pinned: false,
+            }),
+        ];
+        let mut file = File::create(path).unwrap();
+        for (index, event) in events.into_iter().enumerate() {
+            let entry = Entry::new(Duration::from_secs(index as u64), event, &schemas);
+            serde_pickle::ser::to_writer(&mut file, &entry, true).unwrap();
+        }
+    }
+
+    fn temporary(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mns-redact-{}-{}.pkl", name, Uuid::new_v4()))
+    }
+
+    #[test]
+    fn addresses_are_redacted() {
+        let profile = profile();
+        let redactor = Redactor::new(&profile, &HashMap::new());
+        assert_eq!(redactor.text("from 192.168.1.5:4950"), "from [redacted]");
+        assert_eq!(redactor.text("from [fe80::1%eth0]:4950"), "from [redacted]");
+        assert_eq!(redactor.text("2001:db8:0:0:0:0:2:1 and 2001:db8::2:1"), "[redacted] and [redacted]");
+        assert_eq!(redactor.text("b8:27:eb:01:02:03"), "[redacted]");
+        assert_eq!(redactor.text("at 12:34:56 in Request::Add"), "at 12:34:56 in Request::Add");
+    }
+
+    #[test]
+    fn failed_results_are_redacted() {
+        let profile = profile();
+        let uuid = Uuid::new_v4();
+        let names = vec![(uuid, HOSTNAME.to_owned())].into_iter().collect();
+        let redactor = Redactor::new(&profile, &names);
+        let report = harvest::Report {
+            failed: vec![(format!("{}.log", HOSTNAME), "Could not reach 192.168.1.5".to_owned())],
+            ..Default::default()
+        };
+        let redacted = redactor.report(&report);
+        assert_eq!(redacted.failed, vec![(format!("{}.log", uuid), "Could not reach [redacted]".to_owned())]);
+    }
+
+    #[test]
+    fn journal_round_trip() {
+        let (source, destination) = (temporary("source"), temporary("destination"));
+        write_journal(&source);
+        let profile = profile();
+        let names = vec![(Uuid::new_v4(), HOSTNAME.to_owned())].into_iter().collect();
+        Redactor::new(&profile, &names).journal(&source, &destination).unwrap();
+        let (original, redacted) = (fs::read(&source).unwrap(), fs::read(&destination).unwrap());
+        fs::remove_file(source).unwrap();
+        fs::remove_file(destination).unwrap();
+        for sensitive in &["192.168.1.5", "fe80::42", HOSTNAME, "operator"] {
+            assert!(contains(&original, sensitive));
+            assert!(!contains(&redacted, sensitive));
+        }
+        assert!(contains(&redacted, "Starting the run"));
+        assert!(contains(&redacted, REDACTED));
+    }
+
+    #[test]
+    fn truncated_journal_is_reported() {
+        let (source, destination) = (temporary("source"), temporary("destination"));
+        write_journal(&source);
+        let length = fs::metadata(&source).unwrap().len();
+        fs::OpenOptions::new().write(true).open(&source).unwrap().set_len(length - 4).unwrap();
+        let profile = profile();
+        let result = Redactor::new(&profile, &HashMap::new()).journal(&source, &destination);
+        fs::remove_file(source).unwrap();
+        let _ = fs::remove_file(destination);
+        assert!(matches!(result, Err(Error::TruncatedJournal(_, start)) if start > 0));
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+
+use crate::optitrack::Pose;
+
+/// A named region of the arena in the coordinates of the tracking system
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Region {
+    pub name: String,
+    #[serde(flatten)]
+    pub shape: Shape,
+    /// Range of heights (z) covered by the region, unbounded if not given
+    #[serde(default)]
+    pub heights: Option<(f32, f32)>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "shape")]
+pub enum Shape {
+    /// Polygon in the x/y plane
+    Polygon { vertices: Vec<(f32, f32)> },
+    /// Circle in the x/y plane
+    Cylinder { center: (f32, f32), radius: f32 },
+}
+
+impl Region {
+    pub fn contains(&self, position: &[f32; 3]) -> bool {
+        let [x, y, z] = *position;
+        if let Some((bottom, top)) = self.heights {
+            if z < bottom || z > top {
+                return false;
+            }
+        }
+        match &self.shape {
+            Shape::Cylinder { center: (cx, cy), radius } =>
+                (x - cx).powi(2) + (y - cy).powi(2) <= radius.powi(2),
+            /* even-odd rule: count the edges that a ray in the positive x direction crosses */
+            Shape::Polygon { vertices } => {
+                let mut inside = false;
+                let edges = vertices.iter().zip(vertices.iter().cycle().skip(1));
+                for (&(x1, y1), &(x2, y2)) in edges {
+                    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Crossing {
+    Entered,
+    Left,
+}
+
+/// A rigid body entering or leaving a region
+#[derive(Clone, Debug, Serialize)]
+pub struct Transition {
+    pub region: String,
+    pub rigid_body: i32,
+    pub crossing: Crossing,
+}
+
+/// The rigid bodies inside each region
+#[derive(Clone, Debug, Default)]
+pub struct Occupancy(pub BTreeMap<String, BTreeSet<i32>>);
+
+impl Occupancy {
+    /// Updates the occupancy from the latest poses, rigid bodies that are no longer tracked
+    /// leave the regions that they were in
+    pub fn update(&mut self, regions: &[Region], poses: &[Pose]) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        /* forget the regions that are no longer configured */
+        self.0.retain(|name, _| regions.iter().any(|region| &region.name == name));
+        for region in regions {
+            let occupants = poses.iter()
+                .filter(|pose| region.contains(&pose.position))
+                .map(|pose| pose.id)
+                .collect::<BTreeSet<_>>();
+            let previous = self.0.entry(region.name.clone()).or_default();
+            transitions.extend(occupants.difference(previous).map(|&rigid_body| Transition {
+                region: region.name.clone(),
+                rigid_body,
+                crossing: Crossing::Entered,
+            }));
+            transitions.extend(previous.difference(&occupants).map(|&rigid_body| Transition {
+                region: region.name.clone(),
+                rigid_body,
+                crossing: Crossing::Left,
+            }));
+            *previous = occupants;
+        }
+        transitions
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::arena;
+use crate::config;
+use crate::environment;
+use crate::flags::Flags;
+use crate::history::History;
+use crate::router;
+use crate::software::Software;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Replication is not configured")]
+    NotConfigured,
+    #[error("The primary refused the replication token")]
+    Unauthorized,
+    #[error("Could not take over from the primary: {0}")]
+    TakeoverFailed(String),
+
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Replication of the state of a primary supervisor to a standby supervisor, which takes over if
+/// the primary stops responding
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Replication {
+    /// Token that the standby presents to the primary
+    pub token: String,
+    /// Seconds between the requests of the standby for the state of the primary
+    pub interval: f64,
+    /// Seconds without a response from the primary after which the standby takes over
+    pub failover_timeout: f64,
+    /// Shell command that the standby runs when taking over, e.g., to claim the virtual IP
+    /// address to which the robots connect
+    pub takeover_command: Option<String>,
+    /// Take over even if the state of the primary was never replicated, otherwise a standby that
+    /// cannot reach the primary, e.g., because of a mistyped address, keeps waiting for it
+    pub takeover_unreplicated: bool,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication {
+            token: String::new(),
+            interval: 5.0,
+            failover_timeout: 30.0,
+            takeover_command: None,
+            takeover_unreplicated: false,
+        }
+    }
+}
+
+/// The state of the arena that a standby supervisor needs to resume from
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub pipuck_software: Software,
+    pub drone_software: Software,
+    pub environment_schedule: environment::Schedule,
+    pub flags: Flags,
+    pub injection_templates: Vec<router::Template>,
+    pub history: History,
+    /// Whether an experiment was running on the primary
+    pub experiment_active: bool,
+}
+
+/// Responds with the state of the arena to a standby supervisor that presents the token, the
+/// state is only sent if it differs from the state that the standby already has
+pub async fn serve(arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+                   config: config::Receiver,
+                   authorization: Option<String>,
+                   if_none_match: Option<String>) -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    let token = config.borrow().replication.as_ref().map(|replication| replication.token.clone());
+    let status = match token {
+        None => Some(warp::http::StatusCode::NOT_FOUND),
+        Some(token) if token.is_empty() || authorization != Some(format!("Bearer {}", token)) =>
+            Some(warp::http::StatusCode::UNAUTHORIZED),
+        Some(_) => None,
+    };
+    if let Some(status) = status {
+        return Ok(warp::http::Response::builder()
+            .status(status)
+            .body(warp::hyper::Body::empty()));
+    }
+    let (callback_tx, callback_rx) = oneshot::channel();
+    arena_request_tx.send(arena::Request::GetSnapshot(callback_tx))
+        .map_err(|_| warp::reject())?;
+    let snapshot = callback_rx.await
+        .map_err(|_| warp::reject())?;
+    let body = serde_json::to_vec(&snapshot)
+        .map_err(|_| warp::reject())?;
+    let etag = format!("\"{:x}\"", md5::compute(&body));
+    let response = warp::http::Response::builder()
+        .header("etag", etag.clone());
+    Ok(match if_none_match == Some(etag) {
+        true => response.status(warp::http::StatusCode::NOT_MODIFIED)
+            .body(warp::hyper::Body::empty()),
+        false => response.header("content-type", "application/json")
+            .body(warp::hyper::Body::from(body)),
+    })
+}
+
+/* runs the takeover command, e.g., to claim the virtual IP address of the router */
+async fn take_over(command: &str) -> Result<()> {
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .status()
+        .await?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::TakeoverFailed(status.to_string())),
+    }
+}
+
+/// Replicates the state of the primary until it has not responded for the failover timeout,
+/// then takes over and returns the last state that was replicated
+pub async fn standby(primary: String, config: config::Receiver) -> Result<Snapshot> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/replication", primary.trim_end_matches('/'));
+    let mut snapshot: Option<Snapshot> = None;
+    let mut etag: Option<String> = None;
+    let mut last_response = Instant::now();
+    log::info!("Standing by for {}", primary);
+    loop {
+        let replication = config.borrow().replication.clone()
+            .ok_or(Error::NotConfigured)?;
+        let mut request = client.get(&url)
+            .bearer_auth(&replication.token)
+            .timeout(Duration::from_secs_f64(replication.interval.max(1.0)));
+        if let Some(etag) = etag.as_ref() {
+            request = request.header("if-none-match", etag);
+        }
+        match request.send().await {
+            Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED =>
+                return Err(Error::Unauthorized),
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_MODIFIED =>
+                last_response = Instant::now(),
+            Ok(response) if response.status().is_success() => {
+                let update = response.headers()
+                    .get("etag")
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(ToOwned::to_owned);
+                match response.json::<Snapshot>().await {
+                    Ok(update_snapshot) => {
+                        if snapshot.is_none() {
+                            log::info!("Replicated the state of {}", primary);
+                        }
+                        snapshot = Some(update_snapshot);
+                        etag = update;
+                        last_response = Instant::now();
+                    },
+                    Err(error) => log::warn!("Could not decode the state of {}: {}", primary, error),
+                }
+            },
+            Ok(response) => log::warn!("Primary {} responded with {}", primary, response.status()),
+            Err(error) => log::warn!("Could not reach primary {}: {}", primary, error),
+        }
+        if last_response.elapsed() >= Duration::from_secs_f64(replication.failover_timeout) {
+            /* the primary may still be running and driving the robots if it was never reached */
+            if snapshot.is_none() && !replication.takeover_unreplicated {
+                log::error!("Primary {} has never responded, not taking over", primary);
+                last_response = Instant::now();
+                tokio::time::sleep(Duration::from_secs_f64(replication.interval.max(0.1))).await;
+                continue;
+            }
+            log::error!("Primary {} has not responded for {} seconds, taking over", primary,
+                last_response.elapsed().as_secs());
+            if let Some(command) = replication.takeover_command.as_ref() {
+                take_over(command).await?;
+            }
+            if snapshot.is_none() {
+                log::warn!("The state of {} was never replicated", primary);
+            }
+            return Ok(snapshot.unwrap_or_default());
+        }
+        tokio::time::sleep(Duration::from_secs_f64(replication.interval.max(0.1))).await;
+    }
+}
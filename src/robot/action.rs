@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Permission;
+use crate::robot::{drone, pipuck};
+
+/// How much harm an action can do, the web interface styles the buttons of the actions accordingly
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Danger {
+    Safe,
+    /// Interrupts the robot, e.g., rebooting it
+    Caution,
+    /// Requires someone to go to the robot to recover it, e.g., halting it
+    Dangerous,
+}
+
+impl Default for Danger {
+    fn default() -> Self {
+        Danger::Safe
+    }
+}
+
+/// An action that is offered on the cards of a type of robot
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Definition<A> {
+    pub action: A,
+    /// Label of the button, the name of the action if not given
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub danger: Danger,
+    /// Permission that a client needs to carry out the action
+    pub permission: Permission,
+    /// Whether the operator must confirm the action before it is sent
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// An action as it is offered on the card of a robot
+#[derive(Debug, Serialize)]
+pub struct Control<A> {
+    pub action: A,
+    pub label: String,
+    pub danger: Danger,
+    pub confirm: bool,
+}
+
+impl<A: Serialize> Definition<A> {
+    fn new(action: A, danger: Danger, permission: Permission, confirm: bool) -> Self {
+        Definition { action, label: None, danger, permission, confirm }
+    }
+
+    pub fn label(&self) -> String {
+        self.label.clone()
+            .or_else(|| serde_json::to_value(&self.action).ok()
+                .and_then(|name| name.as_str().map(ToOwned::to_owned)))
+            .unwrap_or_default()
+    }
+}
+
+/// The actions that the robot can currently carry out and that are defined, in the order in which
+/// they are defined
+pub fn offered<A: Copy + PartialEq + Serialize>(definitions: &[Definition<A>], available: &[A]) -> Vec<Control<A>> {
+    definitions.iter()
+        .filter(|definition| available.contains(&definition.action))
+        .map(|definition| Control {
+            action: definition.action,
+            label: definition.label(),
+            danger: definition.danger,
+            confirm: definition.confirm,
+        })
+        .collect()
+}
+
+/// The definition of an action, actions without a definition are not offered and are refused
+pub fn find<'a, A: PartialEq>(definitions: &'a [Definition<A>], action: &A) -> Option<&'a Definition<A>> {
+    definitions.iter().find(|definition| definition.action == *action)
+}
+
+pub fn default_pipuck_actions() -> Vec<Definition<pipuck::Action>> {
+    use pipuck::Action::*;
+    vec![
+        Definition::new(RpiHalt, Danger::Dangerous, Permission::Power, true),
+        Definition::new(RpiReboot, Danger::Caution, Permission::Power, true),
+        Definition::new(GetKernelMessages, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(StartCameraStream, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(StopCameraStream, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(RollBackSoftware, Danger::Caution, Permission::Maintenance, true),
+        Definition::new(UseLatestSoftware, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(EnableVerboseLogging, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(DisableVerboseLogging, Danger::Safe, Permission::Maintenance, false),
+    ]
+}
+
+pub fn default_drone_actions() -> Vec<Definition<drone::Action>> {
+    use drone::Action::*;
+    vec![
+        Definition::new(UpCorePowerOn, Danger::Safe, Permission::Power, false),
+        Definition::new(UpCorePowerOff, Danger::Dangerous, Permission::Power, true),
+        Definition::new(PixhawkPowerOn, Danger::Safe, Permission::Power, false),
+        Definition::new(PixhawkPowerOff, Danger::Dangerous, Permission::Power, true),
+        Definition::new(UpCoreReboot, Danger::Caution, Permission::Power, true),
+        Definition::new(UpCoreHalt, Danger::Dangerous, Permission::Power, true),
+        Definition::new(StartCameraStream, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(StopCameraStream, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(GetKernelMessages, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(Identify, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(TestMotors, Danger::Caution, Permission::Maintenance, true),
+        Definition::new(RollBackSoftware, Danger::Caution, Permission::Maintenance, true),
+        Definition::new(UseLatestSoftware, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(EnableVerboseLogging, Danger::Safe, Permission::Maintenance, false),
+        Definition::new(DisableVerboseLogging, Danger::Safe, Permission::Maintenance, false),
+    ]
+}
@@ -0,0 +1,133 @@
+use std::{io, net::SocketAddr, ops::Deref, path::PathBuf};
+use futures::Future;
+use tokio::{net::UdpSocket, sync::{mpsc, oneshot}};
+use uuid::Uuid;
+use crate::{compute::tunnel, flags, journal, network::fernbedienung, software};
+use super::staging;
+
+/// Port of the supervisor on which ARGoS connects to the message router
+const MESSAGE_ROUTER_PORT: u16 = 4950;
+
+/// How ARGoS reaches the message router and, in the remote brain mode, the other half of the
+/// robot. The `--body` and `--brain` options are only understood by builds of ARGoS whose
+/// hardware plugins support the remote brain mode, which is why the mode has to be enabled with
+/// the remote_brains option of the configuration.
+#[derive(Clone, Copy, Debug)]
+pub enum Connection {
+    /// ARGoS runs the controller and connects to the message router
+    Router,
+    /// ARGoS on the robot only connects the sensors and actuators to the tunnel
+    Body,
+    /// ARGoS on a compute server runs the controller, connects to the message router on behalf
+    /// of the robot, and reaches the sensors and actuators of the robot through the tunnel
+    Brain,
+}
+
+impl Connection {
+    /// The arguments of ARGoS, where the supervisor is reached at the given address
+    fn args(&self, supervisor: SocketAddr) -> Vec<String> {
+        let message_router = SocketAddr::new(supervisor.ip(), MESSAGE_ROUTER_PORT).to_string();
+        let tunnel = SocketAddr::new(supervisor.ip(), tunnel::PORT).to_string();
+        match self {
+            Connection::Router => vec!["--router".to_owned(), message_router],
+            Connection::Body => vec!["--body".to_owned(), tunnel],
+            Connection::Brain => vec!["--router".to_owned(), message_router, "--brain".to_owned(), tunnel],
+        }
+    }
+}
+
+/// The address of this machine as seen by the device
+async fn supervisor_addr(device: &fernbedienung::Device) -> io::Result<SocketAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect((device.addr, 80)).await?;
+    socket.local_addr()
+}
+
+/// ARGoS on a device, which still has to be started
+pub struct Argos {
+    pub uuid: Uuid,
+    pub working_dir: PathBuf,
+    pub argos_config: String,
+    /// Arguments that are specific to the device, e.g., the serial port of the Pixhawk
+    pub args: Vec<String>,
+    pub connection: Connection,
+    pub launch: staging::Launch,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    FernbedienungError(#[from] fernbedienung::Error),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+}
+
+impl Argos {
+    /// Installs the ARGoS plugins for the architecture of the device and starts ARGoS with the
+    /// flags of the robot in its environment. The returned future runs ARGoS and forwards its
+    /// output to the journal, ARGoS is terminated by sending on the returned sender and the
+    /// returned receiver resolves once ARGoS produces its first output.
+    pub async fn start<D>(self,
+                          device: D,
+                          software: &software::Software,
+                          flags: &flags::Assignment,
+                          journal: journal::Sender)
+        -> Result<(impl Future<Output = fernbedienung::Result<fernbedienung::ExitStatus>>, oneshot::Sender<()>, oneshot::Receiver<()>), Error>
+        where D: Deref<Target = fernbedienung::Device> {
+        let Argos { uuid, working_dir, argos_config, args, connection, launch } = self;
+        let supervisor = supervisor_addr(&device).await?;
+        let architecture = device.architecture().await?;
+        device.install_plugins(software.plugins(&architecture)).await?;
+
+        let process = flags::wrap(fernbedienung::Process {
+            target: "argos3".into(),
+            working_dir: Some(working_dir),
+            args: vec!["--config".to_owned(), argos_config].into_iter()
+                .chain(args)
+                .chain(connection.args(supervisor))
+                .chain(vec!["--id".to_owned(), uuid.to_string()])
+                .collect(),
+            env: launch.env,
+            nice: launch.nice,
+            limits: launch.limits,
+        }, flags);
+
+        /* channel for terminating ARGoS */
+        let (stop_tx, stop_rx) = oneshot::channel();
+        /* channel for reporting that ARGoS has started */
+        let (started_tx, started_rx) = oneshot::channel();
+
+        let argos = async move {
+            let mut started_tx = Some(started_tx);
+            /* channels for routing stdout and stderr to the journal */
+            let (stdout_tx, mut stdout_rx) = mpsc::unbounded_channel();
+            let (stderr_tx, mut stderr_rx) = mpsc::unbounded_channel();
+            let argos = device.run(process, Some(stop_rx), None, Some(stdout_tx), Some(stderr_tx));
+            tokio::pin!(argos);
+            loop {
+                let message = tokio::select! {
+                    Some(data) = stdout_rx.recv() => journal::Robot::StandardOutput(data),
+                    Some(data) = stderr_rx.recv() => journal::Robot::StandardError(data),
+                    exit_status = &mut argos => {
+                        if let Some(status) = fernbedienung::exit_status(&exit_status) {
+                            let event = journal::Event::Robot(uuid, journal::Robot::Terminated(status));
+                            if let Err(error) = journal.record(event) {
+                                log::warn!("Could not record the exit status of {} in journal: {}", uuid, error);
+                            }
+                        }
+                        break exit_status;
+                    },
+                };
+                /* the fernbedienung service does not report when a process starts, its first
+                   output is taken as the sign that ARGoS is running */
+                if let Some(started_tx) = started_tx.take() {
+                    let _ = started_tx.send(());
+                }
+                if let Err(error) = journal.record(journal::Event::Robot(uuid, message)) {
+                    log::warn!("Could not forward the output of {} to journal: {}", uuid, error);
+                }
+            }
+        };
+        Ok((argos, stop_tx, started_rx))
+    }
+}
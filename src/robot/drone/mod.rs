@@ -3,22 +3,34 @@ use uuid::Uuid;
 use std::{future::Future, pin::Pin, task::{Context, Poll}};
 use tokio::{sync::mpsc, task::JoinHandle};
 use crate::network::xbee;
+use crate::telemetry;
+use crate::robot::{lifecycle, thermal};
+use crate::config;
 
 mod task;
 mod codec;
+pub mod motors;
 
 pub use task::{
     Action, Error, Receiver, Request, Sender, State
 };
 
-pub struct Drone(JoinHandle<Uuid>);
+pub struct Drone(Uuid, JoinHandle<Uuid>);
 
 impl Drone {
-    pub fn new(device: xbee::Device) -> (Uuid, Sender, Self) {
+    pub fn new(device: xbee::Device,
+               telemetry: telemetry::Sender,
+               lifecycle: lifecycle::Sender,
+               thermal: thermal::Sender,
+               config: config::Receiver) -> (Uuid, Sender, Self) {
         let uuid = Uuid::new_v4();
         let (tx, rx) = mpsc::unbounded_channel();
-        let handle = tokio::spawn(task::new(uuid, rx, device));
-        (uuid, tx, Self(handle))
+        let handle = tokio::spawn(task::new(uuid, rx, device, telemetry, lifecycle::Reporter::new(uuid, lifecycle), thermal::Reporter::new(uuid, thermal), config));
+        (uuid, tx, Self(uuid, handle))
+    }
+
+    pub fn abort(&self) {
+        self.1.abort();
     }
 }
 
@@ -26,6 +38,11 @@ impl Future for Drone {
     type Output = Result<Uuid, tokio::task::JoinError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.get_mut().0.poll_unpin(cx)
+        let Self(uuid, handle) = self.get_mut();
+        /* an aborted task ends like a task whose robot disconnected */
+        handle.poll_unpin(cx).map(|result| match result {
+            Err(error) if error.is_cancelled() => Ok(*uuid),
+            result => result,
+        })
     }
 }
@@ -0,0 +1,178 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, net::tcp::OwnedWriteHalf, sync::mpsc};
+use mavlink::common::{MavCmd, MavMessage, MavResult};
+
+use super::task::{Error, Result};
+
+/// The expected response of a motor that spins at the test throttle with its propeller removed
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Number of motors, these are tested one after the other starting from the first
+    pub motors: u8,
+    /// Throttle (percent) at which each motor is spun
+    pub throttle: f32,
+    /// Seconds for which each motor is spun
+    pub duration: f64,
+    /// Range of the speed (RPM) reported by the ESC of a motor
+    pub rpm: (i32, i32),
+    /// Range of the current (A) that a motor adds to the current drawn from the battery
+    pub current: (f32, f32),
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            motors: 4,
+            throttle: 10.0,
+            duration: 2.0,
+            rpm: (1000, 6000),
+            current: (0.2, 3.0),
+        }
+    }
+}
+
+/// The feedback of a motor during the test, the speed and the current are only known if the
+/// Pixhawk reports them
+#[derive(Clone, Debug, Serialize)]
+pub struct Motor {
+    pub index: u8,
+    pub rpm: Option<i32>,
+    pub current: Option<f32>,
+    pub passed: bool,
+    /// Why the motor did not pass
+    pub fault: Option<String>,
+}
+
+pub type Report = Vec<Motor>;
+
+/* the feedback collected while a motor spins */
+#[derive(Default)]
+struct Feedback {
+    accepted: Option<bool>,
+    rpm: Option<i32>,
+    current: Option<f32>,
+}
+
+fn motor_test(motor: u8, settings: &Settings) -> MavMessage {
+    MavMessage::COMMAND_LONG(mavlink::common::COMMAND_LONG_DATA {
+        /* the motors are numbered from one */
+        param1: motor as f32,
+        /* MOTOR_TEST_THROTTLE_PERCENT */
+        param2: 0.0,
+        param3: settings.throttle,
+        param4: settings.duration as f32,
+        /* spin only this motor */
+        param5: 1.0,
+        /* MOTOR_TEST_ORDER_DEFAULT */
+        param6: 0.0,
+        param7: 0.0,
+        command: MavCmd::MAV_CMD_DO_MOTOR_TEST,
+        target_system: 1,
+        target_component: 1,
+        confirmation: 0,
+    })
+}
+
+async fn send(mavlink: &mut OwnedWriteHalf, message: &MavMessage) -> Result<()> {
+    let header = mavlink::MavHeader { system_id: 255, component_id: 0, sequence: 0 };
+    let mut buffer = Vec::new();
+    mavlink::write_v2_msg(&mut buffer, header, message)
+        .map_err(|_| Error::MavlinkEncodeError)?;
+    mavlink.write_all(&buffer).await?;
+    Ok(())
+}
+
+/* the current drawn from the battery in amperes, if known */
+fn battery_current(message: &MavMessage) -> Option<f32> {
+    match message {
+        MavMessage::BATTERY_STATUS(data) if data.current_battery >= 0 =>
+            Some(data.current_battery as f32 / 100.0),
+        _ => None,
+    }
+}
+
+/* collects the feedback of a motor while it spins, the current of the motor is the increase of
+   the current drawn from the battery over the current drawn before it started */
+async fn collect(messages: &mut mpsc::UnboundedReceiver<MavMessage>,
+                 motor: u8,
+                 baseline: Option<f32>,
+                 duration: Duration) -> Feedback {
+    let mut feedback = Feedback::default();
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            message = messages.recv() => match message {
+                Some(MavMessage::COMMAND_ACK(data)) if data.command == MavCmd::MAV_CMD_DO_MOTOR_TEST =>
+                    feedback.accepted = Some(data.result == MavResult::MAV_RESULT_ACCEPTED),
+                /* an ESC_STATUS message covers four ESCs starting from its index */
+                Some(MavMessage::ESC_STATUS(data)) => {
+                    let offset = (motor as usize - 1).checked_sub(data.index as usize);
+                    if let Some(rpm) = offset.and_then(|offset| data.rpm.get(offset)) {
+                        feedback.rpm = Some(feedback.rpm.map_or(*rpm, |max| max.max(*rpm)));
+                    }
+                },
+                Some(message) => if let (Some(current), Some(baseline)) = (battery_current(&message), baseline) {
+                    let added = current - baseline;
+                    feedback.current = Some(feedback.current.map_or(added, |max| max.max(added)));
+                },
+                None => break,
+            }
+        }
+    }
+    feedback
+}
+
+fn judge(index: u8, feedback: Feedback, settings: &Settings) -> Motor {
+    let fault = if feedback.accepted == Some(false) {
+        Some("The Pixhawk refused to spin the motor".to_owned())
+    }
+    else if feedback.rpm.is_none() && feedback.current.is_none() {
+        Some("No feedback from the ESC or the battery".to_owned())
+    }
+    else if let Some(rpm) = feedback.rpm.filter(|rpm| *rpm < settings.rpm.0 || *rpm > settings.rpm.1) {
+        Some(format!("{} RPM is outside of {} to {} RPM", rpm, settings.rpm.0, settings.rpm.1))
+    }
+    else if let Some(current) = feedback.current.filter(|current| *current < settings.current.0 || *current > settings.current.1) {
+        Some(format!("{:.2} A is outside of {:.2} to {:.2} A", current, settings.current.0, settings.current.1))
+    }
+    else {
+        None
+    };
+    Motor {
+        index,
+        rpm: feedback.rpm,
+        current: feedback.current,
+        passed: fault.is_none(),
+        fault,
+    }
+}
+
+/// Spins each motor of a drone on its own through the MAVLink connection to its Pixhawk and
+/// compares the feedback of the ESCs and the battery with the expected ranges. The propellers
+/// must be removed. The connection is given back once the test is over so that the drone task
+/// can use it again.
+pub async fn test(mut mavlink: OwnedWriteHalf,
+                  mut messages: mpsc::UnboundedReceiver<MavMessage>,
+                  settings: Settings) -> (OwnedWriteHalf, Result<Report>) {
+    let mut report = Report::new();
+    let duration = Duration::from_secs_f64(settings.duration.max(0.5));
+    for index in 1..=settings.motors {
+        /* let the previous motor spin down and take the current drawn from the battery before
+           this motor spins from the messages that arrived in the meantime */
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let mut baseline = None;
+        while let Ok(message) = messages.try_recv() {
+            baseline = battery_current(&message).or(baseline);
+        }
+        if let Err(error) = send(&mut mavlink, &motor_test(index, &settings)).await {
+            return (mavlink, Err(error));
+        }
+        let feedback = collect(&mut messages, index, baseline, duration).await;
+        report.push(judge(index, feedback, &settings));
+    }
+    (mavlink, Ok(report))
+}
@@ -1,17 +1,23 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{Future, FutureExt, StreamExt, TryFutureExt, TryStreamExt, future::{self, Either}, stream::{FuturesOrdered, FuturesUnordered}};
 use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
 use tokio_util::codec::FramedRead;
 use uuid::Uuid;
-use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf, sync::Arc, time::Duration};
-use tokio::{net::{TcpStream, UdpSocket}, sync::{mpsc, oneshot}};
+use std::{collections::HashMap, net::Ipv4Addr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use tokio::{io::AsyncWriteExt, net::{TcpStream, tcp::OwnedWriteHalf}, sync::{mpsc, oneshot}};
 use crate::network::{fernbedienung, xbee};
 use crate::journal;
+use crate::harvest;
+use crate::operation;
 use crate::software;
+use crate::flags;
+use crate::telemetry;
+use crate::image::Image;
+use crate::robot::lifecycle::{self, Lifecycle};
+use crate::robot::{argos, queue, staging, thermal, verbosity};
+use crate::config;
 
-const DRONE_BATT_FULL_MV: f32 = 4050.0;
-const DRONE_BATT_EMPTY_MV: f32 = 3500.0;
 const DRONE_BATT_NUM_CELLS: f32 = 3.0;
 const DRONE_CAMERAS_CONFIG: &[(&str, u16, u16, u16)] = &[
     ("/dev/camera0", 1024, 768, 8000),
@@ -19,17 +25,23 @@ const DRONE_CAMERAS_CONFIG: &[(&str, u16, u16, u16)] = &[
     ("/dev/camera2", 1024, 768, 8002),
     ("/dev/camera3", 1024, 768, 8003),
 ];
+/// Interval at which the load, memory, temperature, and disk space of the UP Core are polled
+const DRONE_SYSTEM_INFO_INTERVAL: Duration = Duration::from_secs(10);
 
-use crate::robot::drone::codec;
+use crate::robot::drone::{codec, motors};
 
 pub struct State {
     pub xbee: (Ipv4Addr, i32),
     pub upcore: Option<(Ipv4Addr, i32)>,
-    pub battery_remaining: i8,
+    pub battery: Option<fernbedienung::Battery>,
     pub actions: Vec<Action>,
     pub cameras: Vec<Bytes>,
     pub devices: Vec<(String, String)>,
     pub kernel_messages: Option<String>,
+    pub image: Option<Image>,
+    pub system: Option<fernbedienung::SystemInfo>,
+    /// The result of the last motor test
+    pub motor_test: Option<motors::Report>,
 }
 
 pub enum Request {
@@ -39,10 +51,38 @@ pub enum Request {
     Execute(Action),    
     ExperimentStart {
         software: software::Software,
-        journal: mpsc::UnboundedSender<journal::Request>,
+        flags: flags::Assignment,
+        /* only run the sensors and actuators, the controller runs on a compute server */
+        remote_brain: bool,
+        journal: journal::Sender,
+        /* staging stops between the uploads once the operation is cancelled */
+        cancellation: operation::Token,
         callback: oneshot::Sender<Result<()>>
     },
     ExperimentStop,
+    /* kill ARGoS without letting it write its output and remove the results that it wrote */
+    ExperimentAbort {
+        patterns: Vec<String>,
+    },
+    /* command the Pixhawk to land, e.g., when the drone is no longer tracked */
+    Land,
+    /* run a maintenance script on the UP Core, e.g., to update the installed packages */
+    RunScript {
+        script: Vec<u8>,
+        callback: oneshot::Sender<Result<()>>
+    },
+    /* follow a file in the working directory of ARGoS until the receiver is dropped */
+    Tail {
+        path: PathBuf,
+        lines: usize,
+        output_tx: mpsc::UnboundedSender<BytesMut>,
+    },
+    /* collect the results of a run into the destination on the supervisor */
+    Harvest {
+        patterns: Vec<String>,
+        destination: PathBuf,
+        callback: oneshot::Sender<harvest::Report>
+    },
 }
 
 pub type Sender = mpsc::UnboundedSender<Request>;
@@ -70,6 +110,29 @@ pub enum Action {
     GetKernelMessages,
     #[serde(rename = "Identify")]
     Identify,
+    #[serde(rename = "Roll back software")]
+    RollBackSoftware,
+    #[serde(rename = "Use latest software")]
+    UseLatestSoftware,
+    #[serde(rename = "Enable verbose logging")]
+    EnableVerboseLogging,
+    #[serde(rename = "Disable verbose logging")]
+    DisableVerboseLogging,
+    #[serde(rename = "Test motors (propellers removed)")]
+    TestMotors,
+}
+
+impl Action {
+    /// The kind of operation in the queue of the drone that the action conflicts with, if any
+    pub fn queue_kind(&self) -> Option<queue::Kind> {
+        match self {
+            Action::UpCorePowerOn | Action::UpCoreHalt | Action::UpCorePowerOff |
+            Action::UpCoreReboot | Action::PixhawkPowerOn | Action::PixhawkPowerOff =>
+                Some(queue::Kind::Power),
+            Action::RollBackSoftware | Action::UseLatestSoftware => Some(queue::Kind::Software),
+            _ => None,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -78,6 +141,14 @@ pub enum Error {
     Timeout,
     #[error("{0:?} is not currently valid")]
     InvalidAction(Action),
+    #[error("UP Core is not connected")]
+    UpCoreNotConnected,
+    #[error("Pixhawk is not connected")]
+    PixhawkNotConnected,
+    #[error("Could not encode MAVLink message")]
+    MavlinkEncodeError,
+    #[error("The motors are being tested")]
+    MotorTestRunning,
 
     #[error("Could not request action")]
     RequestError,
@@ -93,7 +164,11 @@ pub enum Error {
     #[error(transparent)]
     SoftwareError(#[from] software::Error),
     #[error(transparent)]
+    StagingError(#[from] staging::Error),
+    #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ArgosError(#[from] argos::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -106,6 +181,7 @@ pub async fn identify(device: Arc<fernbedienung::Device>) -> Result<()> {
         target: "sh".into(),
         working_dir: Some("/tmp".into()),
         args: vec!["drone_identify.sh".to_owned()],
+        ..Default::default()
     };
     let (terminate_tx, terminate_rx) = oneshot::channel();
     tokio::try_join!(
@@ -127,6 +203,7 @@ pub async fn poll_upcore_devices(device: Arc<fernbedienung::Device>) -> Result<V
         target: "sh".into(),
         working_dir: Some("/tmp".into()),
         args: vec!["drone_query_devices.sh".to_owned()],
+        ..Default::default()
     };
     let (stdout_tx, stdout_rx) = mpsc::unbounded_channel();
     let stdout_stream = UnboundedReceiverStream::new(stdout_rx);
@@ -152,6 +229,17 @@ pub async fn poll_upcore_devices(device: Arc<fernbedienung::Device>) -> Result<V
     Ok(result)
 }
 
+/* terminates the processes that were orphaned on the UP Core when its connection was lost */
+async fn reap_orphans(device: &fernbedienung::Device, config: &config::Receiver, uuid: Uuid) {
+    if config.borrow().reap_orphans {
+        match tokio::time::timeout(Duration::from_millis(500), device.reap_orphans()).await {
+            Ok(Ok(_)) => {},
+            Ok(Err(error)) => log::warn!("Could not reap orphaned processes on drone {}: {}", uuid, error),
+            Err(_) => log::warn!("Could not reap orphaned processes on drone {}: timed out", uuid),
+        }
+    }
+}
+
 pub async fn poll_upcore_link_strength(fernbedienung: Arc<fernbedienung::Device>) -> Result<i32> {
     tokio::time::sleep(Duration::from_secs(1)).await;
     tokio::time::timeout(Duration::from_secs(1), fernbedienung.link_strength()).await
@@ -159,6 +247,47 @@ pub async fn poll_upcore_link_strength(fernbedienung: Arc<fernbedienung::Device>
         .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
 }
 
+async fn poll_upcore_thermal(fernbedienung: Arc<fernbedienung::Device>) -> Result<fernbedienung::Thermal> {
+    tokio::time::sleep(thermal::POLL_INTERVAL).await;
+    tokio::time::timeout(Duration::from_secs(2), fernbedienung.thermal_zones()).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
+}
+
+async fn poll_upcore_system_info(fernbedienung: Arc<fernbedienung::Device>) -> Result<fernbedienung::SystemInfo> {
+    tokio::time::sleep(DRONE_SYSTEM_INFO_INTERVAL).await;
+    tokio::time::timeout(Duration::from_secs(2), fernbedienung.system_info()).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
+}
+
+async fn query_upcore_image(device: Arc<fernbedienung::Device>) -> Result<Image> {
+    Image::query(&device).await.map_err(|error| Error::FernbedienungError(error))
+}
+
+async fn land(mavlink: &mut OwnedWriteHalf) -> Result<()> {
+    let message = mavlink::common::MavMessage::COMMAND_LONG(mavlink::common::COMMAND_LONG_DATA {
+        param1: 0.0,
+        param2: 0.0,
+        param3: 0.0,
+        /* keep the current heading */
+        param4: f32::NAN,
+        param5: 0.0,
+        param6: 0.0,
+        param7: 0.0,
+        command: mavlink::common::MavCmd::MAV_CMD_NAV_LAND,
+        target_system: 1,
+        target_component: 1,
+        confirmation: 0,
+    });
+    let header = mavlink::MavHeader { system_id: 255, component_id: 0, sequence: 0 };
+    let mut buffer = Vec::new();
+    mavlink::write_v2_msg(&mut buffer, header, &message)
+        .map_err(|_| Error::MavlinkEncodeError)?;
+    mavlink.write_all(&buffer).await?;
+    Ok(())
+}
+
 async fn poll_xbee_link_margin(xbee: &xbee::Device) -> Result<i32> {
     tokio::time::sleep(Duration::from_secs(1)).await;
     xbee.link_margin().await.map_err(|error| Error::XbeeError(error))
@@ -171,12 +300,19 @@ async fn poll_xbee_link_margin(xbee: &xbee::Device) -> Result<i32> {
 // futures generated by different async expressions are always different futures
 // also, putting everything inside an futuresunordered makes it different 
 
-pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
+pub async fn new(uuid: Uuid,
+                 mut rx: Receiver,
+                 xbee: xbee::Device,
+                 telemetry: telemetry::Sender,
+                 mut lifecycle: lifecycle::Reporter,
+                 mut thermal: thermal::Reporter,
+                 config: config::Receiver) -> Uuid {
     /* initialize the xbee pins and mux */
     if let Err(error) = init(&xbee).await {
         log::error!("Drone {}: failed to initialize Xbee: {}", uuid, error);
         return uuid;
     }
+    lifecycle.report(Lifecycle::Identified);
     
     /* try to connect to the xbee scs for one second, using a pending stream on failure */
     let mavlink_connect = TcpStream::connect((xbee.addr, 9750));
@@ -184,23 +320,33 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
     let mavlink_connect_result = tokio::time::timeout(mavlink_connect_timeout, mavlink_connect).await
         .map_err(|inner| std::io::Error::new(std::io::ErrorKind::TimedOut, inner))
         .and_then(|inner| inner);
-    let mut mavlink = match mavlink_connect_result {
+    let (mut mavlink, mut mavlink_tx) = match mavlink_connect_result {
         Ok(stream) => {
-            FramedRead::new(stream, codec::MavMessageDecoder::<mavlink::common::MavMessage>::new())
-                .left_stream()
+            let (read_half, write_half) = stream.into_split();
+            let stream = FramedRead::new(read_half, codec::MavMessageDecoder::<mavlink::common::MavMessage>::new())
+                .left_stream();
+            (stream, Some(write_half))
         },
         Err(error) => {
             log::warn!("Drone {}: failed to connect to the Xbee serial communication service: {}", uuid, error);
-            futures::stream::pending()
-                .right_stream()
+            (futures::stream::pending().right_stream(), None)
         }
     };
 
     let mut fernbedienung: Option<Arc<fernbedienung::Device>> = None;
+    /* whether the connection to the UP Core is being re-established */
+    let mut upcore_reconnecting = false;
     let poll_upcore_link_strength_task = future::pending().left_future();
     tokio::pin!(poll_upcore_link_strength_task);
     let mut upcore_link_strength = -100;
 
+    let poll_upcore_thermal_task = future::pending().left_future();
+    tokio::pin!(poll_upcore_thermal_task);
+
+    let poll_upcore_system_info_task = future::pending().left_future();
+    tokio::pin!(poll_upcore_system_info_task);
+    let mut upcore_system_info = None;
+
     let poll_upcore_devices_task = future::pending().left_future();
     tokio::pin!(poll_upcore_devices_task);
     let mut upcore_devices = Vec::new();
@@ -208,9 +354,18 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
     let mut argos_stop_tx = None;
     let argos_task = future::pending().left_future();
     tokio::pin!(argos_task);
+    /* resolves once ARGoS produces its first output, i.e., once it has actually started */
+    let argos_started = future::pending().left_future();
+    tokio::pin!(argos_started);
+    /* whether the robot was reported as staged and waits for ARGoS to start */
+    let mut awaiting_argos = false;
 
     let mut kernel_messages = None;
 
+    let query_upcore_image_task = future::pending().left_future();
+    tokio::pin!(query_upcore_image_task);
+    let mut upcore_image = None;
+
     let identify_task = future::pending().left_future();
     tokio::pin!(identify_task);
 
@@ -224,7 +379,17 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
     tokio::pin!(upcore_camera_task);
     let mut upcore_camera_frames = Vec::new();
 
-    let mut battery_remaining = -1i8;
+    let mut battery = None;
+
+    /* the MAVLink connection is lent to the motor test while it runs */
+    let motor_test_task = future::pending().left_future();
+    tokio::pin!(motor_test_task);
+    let mut motor_test_tx: Option<mpsc::UnboundedSender<mavlink::common::MavMessage>> = None;
+    let mut motor_test_report = None;
+
+    let mut versions = staging::Versions::default();
+    /* whether the fernbedienung service and ARGoS on the UP Core log verbosely */
+    let mut verbose = false;
 
     loop {
         tokio::select! {
@@ -232,15 +397,18 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                 upcore_camera_frames = frames;
             },
             Some(message) = mavlink.next() => {
+                if let (Some(tx), Ok((_, message))) = (motor_test_tx.as_ref(), message.as_ref()) {
+                    let _ = tx.send(message.clone());
+                }
                 if let Ok((_, mavlink::common::MavMessage::BATTERY_STATUS(data))) = message {
                     /* voltages: [u16; 10] Battery voltage of cells 1 to 10 in mV. If individual
                        cell voltages are unknown or not measured for this battery, then the overall
-                       battery voltage should be filled in cell 0. */
-                    let mut battery_reading = data.voltages[0] as f32;
-                    battery_reading /= DRONE_BATT_NUM_CELLS;
-                    battery_reading -= DRONE_BATT_EMPTY_MV;
-                    battery_reading /= DRONE_BATT_FULL_MV - DRONE_BATT_EMPTY_MV;
-                    battery_remaining = (battery_reading.max(0.0).min(1.0) * 100.0) as i8;
+                       battery voltage should be filled in cell 0, UINT16_MAX means unknown. */
+                    if data.voltages[0] != u16::MAX {
+                        let reading = fernbedienung::Battery::from_voltage(data.voltages[0] as f32 / 1000.0, DRONE_BATT_NUM_CELLS);
+                        telemetry::record(&telemetry, uuid, "Battery", reading.percentage);
+                        battery = Some(reading);
+                    }
                 }
             },
             result = &mut identify_task => {
@@ -262,10 +430,19 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
             result = &mut poll_xbee_link_margin_task => match result {
                 Ok(link_margin) => {
                     xbee_link_margin = link_margin;
+                    telemetry::record(&telemetry, uuid, "Xbee link margin", link_margin as f32);
                     poll_xbee_link_margin_task.set(poll_xbee_link_margin(&xbee));
+                    /* the drone is only fully available once the UP Core has been paired */
+                    if fernbedienung.is_some() && !upcore_reconnecting && lifecycle.current() == Lifecycle::Degraded {
+                        lifecycle.report(match *argos_task {
+                            Either::Left(_) => Lifecycle::Ready,
+                            Either::Right(_) => Lifecycle::Running,
+                        });
+                    }
                 }
                 Err(error) => {
                     log::warn!("Xbee on drone {}: {}", uuid, error);
+                    lifecycle.report(Lifecycle::Degraded);
                     /* disconnect here if the upcore is offline, otherwise try to reestablish
                        the connection with xbee */
                     match fernbedienung {
@@ -276,7 +453,28 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
             },
             result = &mut poll_upcore_link_strength_task => match result {
                 Ok(link_strength) => {
+                    if upcore_reconnecting {
+                        log::info!("Reconnected to the UP Core on drone {}", uuid);
+                        upcore_reconnecting = false;
+                        if let Some(device) = fernbedienung.as_ref() {
+                            reap_orphans(device, &config, uuid).await;
+                        }
+                    }
                     upcore_link_strength = link_strength;
+                    telemetry::record(&telemetry, uuid, "UP Core signal strength", link_strength as f32);
+                    poll_upcore_link_strength_task.set(match fernbedienung {
+                        Some(ref device) => poll_upcore_link_strength(device.clone()).right_future(),
+                        None => future::pending().left_future(),
+                    });
+                }
+                /* keep polling while the connection is re-established, the requests fail with a
+                   different error if the device gives up */
+                Err(Error::FernbedienungError(fernbedienung::Error::ConnectionLost)) => {
+                    if !upcore_reconnecting {
+                        log::warn!("Lost the connection to the UP Core on drone {}, reconnecting", uuid);
+                        upcore_reconnecting = true;
+                        lifecycle.report(Lifecycle::Degraded);
+                    }
                     poll_upcore_link_strength_task.set(match fernbedienung {
                         Some(ref device) => poll_upcore_link_strength(device.clone()).right_future(),
                         None => future::pending().left_future(),
@@ -284,19 +482,91 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                 }
                 Err(error) => {
                     log::warn!("UP Core on drone {}: {}", uuid, error);
+                    upcore_reconnecting = false;
                     /* TODO consider this as a disconnection scenario */
                     fernbedienung = None;
                     poll_upcore_link_strength_task.set(future::pending().left_future());
                     poll_upcore_devices_task.set(future::pending().left_future());
+                    poll_upcore_thermal_task.set(future::pending().left_future());
+                    poll_upcore_system_info_task.set(future::pending().left_future());
+                    upcore_system_info = None;
+                    thermal.report(false);
                     upcore_devices.clear();
                     upcore_camera_frames.clear();
+                    upcore_image = None;
+                    lifecycle.report(Lifecycle::Degraded);
+                }
+            },
+            /* the thermal state is not available on every image, failures are not fatal */
+            result = &mut poll_upcore_thermal_task => {
+                match result {
+                    Ok(state) => {
+                        telemetry::record(&telemetry, uuid, "UP Core temperature", state.temperature);
+                        thermal.report(state.throttled);
+                    },
+                    Err(error) => log::debug!("Could not poll the thermal state of drone {}: {}", uuid, error),
+                }
+                poll_upcore_thermal_task.set(match fernbedienung {
+                    Some(ref device) => poll_upcore_thermal(device.clone()).right_future(),
+                    None => future::pending().left_future(),
+                });
+            },
+            result = &mut poll_upcore_system_info_task => {
+                match result {
+                    Ok(system_info) => upcore_system_info = Some(system_info),
+                    Err(error) => {
+                        log::debug!("Could not poll the system metrics of drone {}: {}", uuid, error);
+                        upcore_system_info = None;
+                    }
+                }
+                poll_upcore_system_info_task.set(match fernbedienung {
+                    Some(ref device) => poll_upcore_system_info(device.clone()).right_future(),
+                    None => future::pending().left_future(),
+                });
+            },
+            (write_half, result) = &mut motor_test_task => {
+                motor_test_task.set(future::pending().left_future());
+                motor_test_tx = None;
+                mavlink_tx = Some(write_half);
+                match result {
+                    Ok(report) => {
+                        for motor in report.iter() {
+                            match motor.fault {
+                                None => log::info!("Motor {} of drone {} passed: {:?} RPM, {:?} A",
+                                    motor.index, uuid, motor.rpm, motor.current),
+                                Some(ref fault) => log::warn!("Motor {} of drone {} failed: {}",
+                                    motor.index, uuid, fault),
+                            }
+                        }
+                        motor_test_report = Some(report);
+                    },
+                    Err(error) => log::warn!("Could not test the motors of drone {}: {}", uuid, error),
+                }
+            },
+            result = &mut query_upcore_image_task => {
+                query_upcore_image_task.set(future::pending().left_future());
+                match result {
+                    Ok(image) => upcore_image = Some(image),
+                    Err(error) => log::warn!("Could not query the image of drone {}: {}", uuid, error),
                 }
             },
             /* if ARGoS is running, keep forwarding stdout/stderr  */
             argos_result = &mut argos_task => {
                 argos_stop_tx = None;
                 argos_task.set(futures::future::pending().left_future());
+                argos_started.set(futures::future::pending().left_future());
+                awaiting_argos = false;
                 log::info!("ARGoS terminated with {:?}", argos_result);
+                if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                    lifecycle.report(Lifecycle::Ready);
+                }
+            },
+            started = &mut argos_started => {
+                argos_started.set(futures::future::pending().left_future());
+                if started.is_ok() && awaiting_argos {
+                    awaiting_argos = false;
+                    lifecycle.report(Lifecycle::Running);
+                }
             },
             /* clean up for when the streaming process terminates */
             upcore_camera_result = &mut upcore_camera_task => {
@@ -306,7 +576,7 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                 match upcore_camera_result {
                     /* since we use the terminate signal to shutdown mjpg_streamer, report
                        AbnormalTerminationError as not an error */
-                    Ok(_) | Err(Error::FernbedienungError(fernbedienung::Error::AbnormalTerminationError)) =>
+                    Ok(_) | Err(Error::FernbedienungError(fernbedienung::Error::AbnormalTerminationError(_))) =>
                         log::info!("Camera stream stopped"),
                     Err(error) =>
                         log::warn!("Camera stream stopped: {}", error),
@@ -327,15 +597,31 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                             });
                             actions.push(Action::GetKernelMessages);
                             actions.push(Action::Identify);
+                            actions.push(match verbose {
+                                false => Action::EnableVerboseLogging,
+                                true => Action::DisableVerboseLogging,
+                            });
+                        }
+                        if mavlink_tx.is_some() && matches!(*argos_task, Either::Left(_)) {
+                            actions.push(Action::TestMotors);
+                        }
+                        if versions.can_roll_back() {
+                            actions.push(Action::RollBackSoftware);
+                        }
+                        if versions.is_rolled_back() {
+                            actions.push(Action::UseLatestSoftware);
                         }
                         /* send back the state */
                         let state = State {
                             xbee: (xbee.addr, xbee_link_margin),
                             upcore: fernbedienung.as_ref().map(|dev| (dev.addr, upcore_link_strength)),
-                            battery_remaining: battery_remaining,
+                            battery,
                             cameras: upcore_camera_frames.clone(),
                             devices: upcore_devices.clone(),
                             kernel_messages: kernel_messages.take(),
+                            image: upcore_image.clone(),
+                            system: upcore_system_info,
+                            motor_test: motor_test_report.clone(),
                             actions,
                         };
                         let _ = callback.send(state);
@@ -344,7 +630,21 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                         let device = Arc::new(device);
                         poll_upcore_link_strength_task.set(poll_upcore_link_strength(device.clone()).right_future());
                         poll_upcore_devices_task.set(poll_upcore_devices(device.clone()).right_future());
+                        poll_upcore_thermal_task.set(poll_upcore_thermal(device.clone()).right_future());
+                        poll_upcore_system_info_task.set(poll_upcore_system_info(device.clone()).right_future());
+                        query_upcore_image_task.set(query_upcore_image(device.clone()).right_future());
+                        /* keep the versions that were staged before the UP Core connected */
+                        if let Err(error) = versions.restore(&device).await {
+                            log::warn!("Could not find the software staged on drone {}: {}", uuid, error);
+                        }
                         fernbedienung = Some(device);
+                        upcore_reconnecting = false;
+                        /* the UP Core may have rebooted and logs normally again */
+                        verbose = false;
+                        lifecycle.report(match *argos_task {
+                            Either::Left(_) => Lifecycle::Ready,
+                            Either::Right(_) => Lifecycle::Running,
+                        });
                     },
                     Request::Execute(action) => {
                         let result = match action {
@@ -374,6 +674,16 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                                 }
                                 None => Err(Error::InvalidAction(action)),
                             }
+                            Action::EnableVerboseLogging | Action::DisableVerboseLogging => match fernbedienung {
+                                Some(ref device) => {
+                                    let enable = action == Action::EnableVerboseLogging;
+                                    let signal = config.borrow().argos_log_level_signal.clone();
+                                    verbosity::set(device, enable, signal.as_deref()).await
+                                        .map(|_| verbose = enable)
+                                        .map_err(|error| Error::FernbedienungError(error))
+                                },
+                                None => Err(Error::InvalidAction(action)),
+                            },
                             Action::Identify => match fernbedienung {
                                 Some(ref device) => {
                                     identify_task.set(identify(device.clone()).right_future());
@@ -407,7 +717,35 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                                     }
                                 }
                                 Ok(())
-                            }
+                            },
+                            Action::RollBackSoftware => match versions.can_roll_back() {
+                                true => {
+                                    versions.roll_back();
+                                    log::info!("Drone {} will use the previous version of the software", uuid);
+                                    Ok(())
+                                },
+                                false => Err(Error::InvalidAction(action)),
+                            },
+                            Action::UseLatestSoftware => {
+                                versions.use_latest();
+                                log::info!("Drone {} will use the latest version of the software", uuid);
+                                Ok(())
+                            },
+                            /* the motors must not spin while ARGoS may command them */
+                            Action::TestMotors => match (&*argos_task, mavlink_tx.take()) {
+                                (Either::Left(_), Some(write_half)) => {
+                                    let (messages_tx, messages_rx) = mpsc::unbounded_channel();
+                                    motor_test_tx = Some(messages_tx);
+                                    let settings = config.borrow().drone_motor_test.clone();
+                                    motor_test_task.set(motors::test(write_half, messages_rx, settings).right_future());
+                                    log::info!("Testing the motors of drone {}", uuid);
+                                    Ok(())
+                                },
+                                (_, write_half) => {
+                                    mavlink_tx = write_half;
+                                    Err(Error::InvalidAction(action))
+                                },
+                            },
                         };
                         if let Err(error) = result {
                             log::warn!("Could not execute {:?}: {}", action, error);
@@ -419,25 +757,95 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                             let _ = callback.send(id);
                         }
                     },
-                    Request::ExperimentStart{software, journal, callback} => {
+                    Request::ExperimentStart{software, flags, remote_brain, journal, cancellation, callback} => {
                         match fernbedienung.as_ref() {
                             None => {
                                 let _ = callback.send(Err(Error::RequestError));
                             },
+                            Some(_) if motor_test_tx.is_some() => {
+                                let _ = callback.send(Err(Error::MotorTestRunning));
+                            },
                             Some(device) => {
-                                match handle_experiment_start(uuid, device.clone(), software, journal).await {
-                                    Ok((argos, stop_tx)) => {
+                                let result = match versions.stage(&device, &software, &cancellation).await {
+                                    Ok(staged) => {
+                                        /* the software was uploaded, the robot is running once ARGoS has started */
+                                        if lifecycle.current() == Lifecycle::Ready {
+                                            lifecycle.report(Lifecycle::Staged);
+                                            awaiting_argos = true;
+                                        }
+                                        let launch = config.borrow().argos_launch.clone();
+                                        handle_experiment_start(uuid, device.clone(), software, staged, flags, remote_brain, launch, journal).await
+                                    },
+                                    Err(error) => Err(Error::StagingError(error)),
+                                };
+                                match result {
+                                    Ok((argos, stop_tx, started_rx)) => {
                                         argos_task.set(argos.right_future());
                                         argos_stop_tx = Some(stop_tx);
+                                        argos_started.set(started_rx.right_future());
                                         let _ = callback.send(Ok(()));
                                     },
                                     Err(error) => {
+                                        if awaiting_argos {
+                                            awaiting_argos = false;
+                                            lifecycle.report(Lifecycle::Ready);
+                                        }
                                         let _ = callback.send(Err(error));
                                     }
                                 }
                             }
                         }
                     },
+                    Request::Land => {
+                        let result = match mavlink_tx.as_mut() {
+                            Some(mavlink_tx) => land(mavlink_tx).await,
+                            None => Err(Error::PixhawkNotConnected),
+                        };
+                        match result {
+                            Ok(_) => log::warn!("Drone {}: commanded to land", uuid),
+                            Err(error) => log::error!("Drone {}: could not command to land: {}", uuid, error),
+                        }
+                    },
+                    Request::RunScript{script, callback} => match fernbedienung.as_ref() {
+                        Some(device) => {
+                            /* the script runs in the background so that the drone remains responsive */
+                            let device = device.clone();
+                            tokio::spawn(async move {
+                                let result = device.run_script(script).await
+                                    .map_err(Error::FernbedienungError);
+                                let _ = callback.send(result);
+                            });
+                        },
+                        None => {
+                            let _ = callback.send(Err(Error::UpCoreNotConnected));
+                        }
+                    },
+                    /* dropping the output channel ends the stream if the UP Core is not connected */
+                    Request::Tail{path, lines, output_tx} => if let Some(device) = fernbedienung.as_ref() {
+                        let device = device.clone();
+                        tokio::spawn(async move {
+                            if let Err(error) = device.tail(staging::working_dir(), path, lines, output_tx).await {
+                                log::warn!("Could not follow a file on drone {}: {}", uuid, error);
+                            }
+                        });
+                    },
+                    Request::Harvest{patterns, destination, callback} => match fernbedienung.as_ref() {
+                        Some(device) => {
+                            let device = device.clone();
+                            tokio::spawn(async move {
+                                let _ = callback.send(harvest::collect(&device, patterns, destination).await);
+                            });
+                        },
+                        None => {
+                            let report = harvest::Report {
+                                failed: patterns.into_iter()
+                                    .map(|pattern| (pattern, Error::UpCoreNotConnected.to_string()))
+                                    .collect(),
+                                ..Default::default()
+                            };
+                            let _ = callback.send(report);
+                        }
+                    },
                     Request::ExperimentStop => {
                         if let Some(stop_tx) = argos_stop_tx.take() {
                             let _ = stop_tx.send(());
@@ -446,6 +854,37 @@ pub async fn new(uuid: Uuid, mut rx: Receiver, xbee: xbee::Device) -> Uuid {
                         let result = (&mut argos_task).await;
                         log::info!("ARGoS terminated with {:?}", result);
                         argos_task.set(futures::future::pending().left_future());
+                        argos_started.set(futures::future::pending().left_future());
+                        awaiting_argos = false;
+                        if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                            lifecycle.report(Lifecycle::Ready);
+                        }
+                    },
+                    Request::ExperimentAbort{patterns} => {
+                        argos_stop_tx = None;
+                        if let Some(device) = fernbedienung.as_ref() {
+                            if let Err(error) = device.kill("argos3").await {
+                                log::error!("Could not kill ARGoS on drone {}: {}", uuid, error);
+                            }
+                            if let Either::Right(_) = *argos_task {
+                                let result = (&mut argos_task).await;
+                                log::info!("ARGoS was killed with {:?}", result);
+                            }
+                            let patterns = patterns.into_iter()
+                                .filter(|pattern| harvest::is_within_working_dir(Path::new(pattern)))
+                                .collect::<Vec<_>>();
+                            if !patterns.is_empty() {
+                                if let Err(error) = device.remove_files(staging::working_dir(), patterns).await {
+                                    log::error!("Could not remove the results on drone {}: {}", uuid, error);
+                                }
+                            }
+                        }
+                        argos_task.set(futures::future::pending().left_future());
+                        argos_started.set(futures::future::pending().left_future());
+                        awaiting_argos = false;
+                        if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                            lifecycle.report(Lifecycle::Ready);
+                        }
                     },
                 }
             }
@@ -540,6 +979,7 @@ fn handle_stream_start(device: Arc<fernbedienung::Device>, configs: &'static [(&
                     "-o".to_owned(),
                     format!("output_http.so -p {} -l {}", port, device.addr)
                 ],
+                ..Default::default()
             };
             let (stop_tx, stop_rx) = oneshot::channel::<()>();
             let process = async move {
@@ -603,80 +1043,22 @@ fn handle_stream_start(device: Arc<fernbedienung::Device>, configs: &'static [(&
 async fn handle_experiment_start(uuid: Uuid,
                                  device: Arc<fernbedienung::Device>,
                                  software: software::Software,
-                                 journal: mpsc::UnboundedSender<journal::Request>) 
-    -> Result<(impl Future<Output = fernbedienung::Result<()>>, oneshot::Sender<()>)> {
-    /* extract the name of the config file */
-    let (argos_config, _) = software.argos_config()?;
-    let argos_config = argos_config.to_owned();
-    /* get the relevant ip address of this machine */
-    let message_router_addr = async {
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
-        socket.connect((device.addr, 80)).await?;
-        socket.local_addr().map(|mut socket| {
-            socket.set_port(4950);
-            socket
-        })
-    }.await?;
-
-    /* upload the control software */
-    let software_upload_path = device.create_temp_dir()
-        .map_err(|error| Error::FernbedienungError(error))
-        .and_then(|path: String| software.0.into_iter()
-            .map(|(filename, contents)| {
-                let path = PathBuf::from(&path);
-                let filename = PathBuf::from(&filename);
-                device.upload(path, filename, contents)
-            })
-            .collect::<FuturesUnordered<_>>()
-            .map_err(|error| Error::FernbedienungError(error))
-            .try_collect::<Vec<_>>()
-            .map_ok(|_| path)
-        ).await?;
-
-    /* create a remote instance of ARGoS3 */
-    let process = fernbedienung::Process {
-        target: "argos3".into(),
-        working_dir: Some(software_upload_path.into()),
-        args: vec![
-            "--config".to_owned(), argos_config.to_owned(),
-            "--pixhawk".to_owned(), "/dev/ttyS1:921600".to_owned(),
-            "--router".to_owned(), message_router_addr.to_string(),
-            "--id".to_owned(), uuid.to_string(),
-        ],
-    };
-
-    /* channel for terminating ARGoS */
-    let (stop_tx, stop_rx) = oneshot::channel();
-
-    /* create future for running ARGoS */
-    let argos_task_future = async move {
-        /* channels for routing stdout and stderr to the journal */
-        let (stdout_tx, mut stdout_rx) = mpsc::unbounded_channel();
-        let (stderr_tx, mut stderr_rx) = mpsc::unbounded_channel();
-        /* run argos remotely */
-        let argos = device.run(process, Some(stop_rx), None, Some(stdout_tx), Some(stderr_tx));
-        tokio::pin!(argos);
-        loop {
-            tokio::select! {
-                Some(data) = stdout_rx.recv() => {
-                    let message = journal::Robot::StandardOutput(data);
-                    let event = journal::Event::Robot(uuid, message);
-                    let request = journal::Request::Record(event);
-                    if let Err(error) = journal.send(request) {
-                        log::warn!("Could not forward standard output of {} to journal: {}", uuid, error);
-                    }
-                },
-                Some(data) = stderr_rx.recv() => {
-                    let message = journal::Robot::StandardError(data);
-                    let event = journal::Event::Robot(uuid, message);
-                    let request = journal::Request::Record(event);
-                    if let Err(error) = journal.send(request) {
-                        log::warn!("Could not forward standard error of {} to journal: {}", uuid, error);
-                    }
-                },
-                exit_status = &mut argos => break exit_status,
-            }
-        }
+                                 (software_path, argos_config): (PathBuf, String),
+                                 flags: flags::Assignment,
+                                 remote_brain: bool,
+                                 launch: staging::Launch,
+                                 journal: journal::Sender) 
+    -> Result<(impl Future<Output = fernbedienung::Result<fernbedienung::ExitStatus>>, oneshot::Sender<()>, oneshot::Receiver<()>)> {
+    let argos = argos::Argos {
+        uuid,
+        working_dir: software_path,
+        argos_config,
+        args: vec!["--pixhawk".to_owned(), "/dev/ttyS1:921600".to_owned()],
+        connection: match remote_brain {
+            true => argos::Connection::Body,
+            false => argos::Connection::Router,
+        },
+        launch,
     };
-    Ok((argos_task_future, stop_tx))
+    Ok(argos.start(device, &software, &flags, journal).await?)
 }
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lifecycle {
+    /// A device was found on the network and a task was created for it
+    Discovered,
+    /// The device was identified as a robot
+    Identified,
+    /// The robot is connected and can start an experiment
+    Ready,
+    /// The control software has been uploaded to the robot
+    Staged,
+    /// ARGoS is running on the robot
+    Running,
+    /// The robot is connected but part of it is unavailable or its link is weak
+    Degraded,
+    /// The connection to the robot was lost
+    Lost,
+}
+
+impl Lifecycle {
+    pub fn can_transition(self, next: Lifecycle) -> bool {
+        use Lifecycle::*;
+        match (self, next) {
+            (_, Lost) => self != Lost,
+            (Discovered, Identified) => true,
+            (Identified, Ready) | (Identified, Degraded) => true,
+            (Ready, Staged) | (Ready, Degraded) => true,
+            (Staged, Running) | (Staged, Ready) | (Staged, Degraded) => true,
+            (Running, Ready) | (Running, Degraded) => true,
+            (Degraded, Identified) | (Degraded, Ready) | (Degraded, Running) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+pub enum Report {
+    /// A robot task started, the arena publishes the lifecycle of the robot through the sender
+    /// whenever it accepts a transition
+    Started(watch::Sender<Lifecycle>),
+    /// A robot task requests a transition
+    Transition(Lifecycle),
+}
+
+pub type Sender = mpsc::UnboundedSender<(Uuid, Report)>;
+pub type Receiver = mpsc::UnboundedReceiver<(Uuid, Report)>;
+
+/// Used by the robot tasks to report changes in their lifecycle to the arena, which owns the
+/// state machine and validates the transitions
+pub struct Reporter {
+    uuid: Uuid,
+    tx: Sender,
+    accepted: watch::Receiver<Lifecycle>,
+}
+
+impl Reporter {
+    pub fn new(uuid: Uuid, tx: Sender) -> Self {
+        let (accepted_tx, accepted) = watch::channel(Lifecycle::Discovered);
+        if let Err(error) = tx.send((uuid, Report::Started(accepted_tx))) {
+            log::warn!("Could not report lifecycle of {}: {}", uuid, error);
+        }
+        Reporter { uuid, tx, accepted }
+    }
+
+    /// The lifecycle of the robot as it was last accepted by the arena, transitions that were
+    /// reported but not yet accepted are not included
+    pub fn current(&self) -> Lifecycle {
+        *self.accepted.borrow()
+    }
+
+    pub fn report(&mut self, next: Lifecycle) {
+        if self.current() != next {
+            if let Err(error) = self.tx.send((self.uuid, Report::Transition(next))) {
+                log::warn!("Could not report lifecycle of {}: {}", self.uuid, error);
+            }
+        }
+    }
+}
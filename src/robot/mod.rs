@@ -1,5 +1,15 @@
 pub mod drone;
 pub mod pipuck;
+pub mod simulated;
+pub mod action;
+pub mod argos;
+pub mod lifecycle;
+pub mod queue;
+pub mod staging;
+pub mod thermal;
+pub mod verbosity;
+
+pub use lifecycle::Lifecycle;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -1,17 +1,27 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{Future, FutureExt, StreamExt, TryFutureExt, TryStreamExt, future::Either, stream::{FuturesOrdered, FuturesUnordered}};
 use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
-use std::{net::Ipv4Addr, path::PathBuf, time::Duration};
-use tokio::{net::UdpSocket, sync::{mpsc, oneshot}};
+use std::{net::Ipv4Addr, path::{Path, PathBuf}, time::Duration};
+use tokio::sync::{mpsc, oneshot};
 use crate::network::fernbedienung;
 use crate::journal;
+use crate::harvest;
+use crate::operation;
 use crate::software;
+use crate::flags;
+use crate::telemetry;
+use crate::image::Image;
+use crate::robot::lifecycle::{self, Lifecycle};
+use crate::robot::{argos, queue, staging, thermal, verbosity};
+use crate::config;
 
-//const PIPUCK_BATT_FULL_MV: f32 = 4050.0;
-//const PIPUCK_BATT_EMPTY_MV: f32 = 3500.0;
 const PIPUCK_CAMERAS_CONFIG: &[(&str, u16, u16, u16)] = &[];
+/// Interval at which the load, memory, temperature, and disk space of the Raspberry Pi are polled
+const PIPUCK_SYSTEM_INFO_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval at which the battery of the Pi-Puck is polled
+const PIPUCK_BATTERY_INTERVAL: Duration = Duration::from_secs(10);
 
 // Info about reading the Pi-Puck battery level here:
 // https://github.com/yorkrobotlab/pi-puck-packages/blob/master/pi-puck-utils/pi-puck-battery
@@ -28,17 +38,48 @@ pub struct State {
     pub cameras: Vec<Bytes>,
     pub actions: Vec<Action>,
     pub kernel_messages: Option<String>,
+    pub image: Option<Image>,
+    pub system: Option<fernbedienung::SystemInfo>,
+    pub battery: Option<fernbedienung::Battery>,
 }
 
 pub enum Request {
     State(oneshot::Sender<State>),
+    /* the last reading of the battery, this does not take the kernel messages like State */
+    Battery(oneshot::Sender<Option<fernbedienung::Battery>>),
     Execute(Action),
     ExperimentStart {
         software: software::Software,
-        journal: mpsc::UnboundedSender<journal::Request>,
+        flags: flags::Assignment,
+        /* only run the sensors and actuators, the controller runs on a compute server */
+        remote_brain: bool,
+        journal: journal::Sender,
+        /* staging stops between the uploads once the operation is cancelled */
+        cancellation: operation::Token,
         callback: oneshot::Sender<Result<()>>
     },
     ExperimentStop,
+    /* kill ARGoS without letting it write its output and remove the results that it wrote */
+    ExperimentAbort {
+        patterns: Vec<String>,
+    },
+    /* run a maintenance script, e.g., to update the installed packages */
+    RunScript {
+        script: Vec<u8>,
+        callback: oneshot::Sender<Result<()>>
+    },
+    /* follow a file in the working directory of ARGoS until the receiver is dropped */
+    Tail {
+        path: PathBuf,
+        lines: usize,
+        output_tx: mpsc::UnboundedSender<BytesMut>,
+    },
+    /* collect the results of a run into the destination on the supervisor */
+    Harvest {
+        patterns: Vec<String>,
+        destination: PathBuf,
+        callback: oneshot::Sender<harvest::Report>
+    },
 }
 
 pub type Sender = mpsc::UnboundedSender<Request>;
@@ -56,12 +97,33 @@ pub enum Action {
     StopCameraStream,
     #[serde(rename = "Get kernel messages")]
     GetKernelMessages,
+    #[serde(rename = "Roll back software")]
+    RollBackSoftware,
+    #[serde(rename = "Use latest software")]
+    UseLatestSoftware,
+    #[serde(rename = "Enable verbose logging")]
+    EnableVerboseLogging,
+    #[serde(rename = "Disable verbose logging")]
+    DisableVerboseLogging,
+}
+
+impl Action {
+    /// The kind of operation in the queue of the Pi-Puck that the action conflicts with, if any
+    pub fn queue_kind(&self) -> Option<queue::Kind> {
+        match self {
+            Action::RpiHalt | Action::RpiReboot => Some(queue::Kind::Power),
+            Action::RollBackSoftware | Action::UseLatestSoftware => Some(queue::Kind::Software),
+            _ => None,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Operation timed out")]
     Timeout,
+    #[error("A script is already running")]
+    ScriptAlreadyRunning,
     #[error("Could not send request")]
     RequestError,
     #[error("Did not receive response")]
@@ -74,7 +136,11 @@ pub enum Error {
     #[error(transparent)]
     SoftwareError(#[from] software::Error),
     #[error(transparent)]
+    StagingError(#[from] staging::Error),
+    #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ArgosError(#[from] argos::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -86,23 +152,101 @@ pub async fn poll_rpi_link_strength(device: &fernbedienung::Device) -> Result<i3
         .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
 }
 
-pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Device) -> Uuid {
+async fn poll_rpi_thermal(device: &fernbedienung::Device) -> Result<fernbedienung::Thermal> {
+    tokio::time::sleep(thermal::POLL_INTERVAL).await;
+    tokio::time::timeout(Duration::from_secs(2), device.rpi_thermal()).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
+}
+
+async fn poll_rpi_system_info(device: &fernbedienung::Device) -> Result<fernbedienung::SystemInfo> {
+    tokio::time::sleep(PIPUCK_SYSTEM_INFO_INTERVAL).await;
+    tokio::time::timeout(Duration::from_secs(2), device.system_info()).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
+}
+
+async fn poll_rpi_battery(device: &fernbedienung::Device, config: &config::Receiver) -> Result<fernbedienung::Battery> {
+    tokio::time::sleep(PIPUCK_BATTERY_INTERVAL).await;
+    let divider = config.borrow().pipuck_battery_divider;
+    tokio::time::timeout(Duration::from_secs(2), device.battery(divider)).await
+        .map_err(|_| Error::Timeout)
+        .and_then(|inner| inner.map_err(|error| Error::FernbedienungError(error)))
+}
+
+/* terminates the processes that were orphaned on the Pi-Puck when its connection was lost */
+async fn reap_orphans(device: &fernbedienung::Device, config: &config::Receiver, uuid: Uuid) {
+    if config.borrow().reap_orphans {
+        match tokio::time::timeout(Duration::from_millis(500), device.reap_orphans()).await {
+            Ok(Ok(_)) => {},
+            Ok(Err(error)) => log::warn!("Could not reap orphaned processes on Pi-Puck {}: {}", uuid, error),
+            Err(_) => log::warn!("Could not reap orphaned processes on Pi-Puck {}: timed out", uuid),
+        }
+    }
+}
+
+pub async fn new(uuid: Uuid,
+                 mut arena_rx: Receiver,
+                 device: fernbedienung::Device,
+                 telemetry: telemetry::Sender,
+                 mut lifecycle: lifecycle::Reporter,
+                 mut thermal: thermal::Reporter,
+                 config: config::Receiver) -> Uuid {
+    /* the Pi-Puck was identified by its hostname during discovery */
+    lifecycle.report(Lifecycle::Identified);
     let mut argos_stop_tx = None;
     let argos_task = futures::future::pending().left_future();
     tokio::pin!(argos_task);
+    /* resolves once ARGoS produces its first output, i.e., once it has actually started */
+    let argos_started = futures::future::pending().left_future();
+    tokio::pin!(argos_started);
+    /* whether the robot was reported as staged and waits for ARGoS to start */
+    let mut awaiting_argos = false;
 
     let poll_rpi_link_strength_task = poll_rpi_link_strength(&device);
     tokio::pin!(poll_rpi_link_strength_task);
     let mut rpi_link_strength = -100;
 
+    let poll_rpi_thermal_task = poll_rpi_thermal(&device);
+    tokio::pin!(poll_rpi_thermal_task);
+
+    let poll_rpi_system_info_task = poll_rpi_system_info(&device);
+    tokio::pin!(poll_rpi_system_info_task);
+    let mut rpi_system_info = None;
+
+    let poll_rpi_battery_task = poll_rpi_battery(&device, &config);
+    tokio::pin!(poll_rpi_battery_task);
+    let mut rpi_battery = None;
+
     let mut kernel_messages = None;
 
+    let query_image_task = Image::query(&device).right_future();
+    tokio::pin!(query_image_task);
+    let mut image = None;
+
     let mut rpi_camera_stream = futures::stream::pending().left_stream();
     let mut rpi_camera_stream_stop_tx = None;
     let rpi_camera_task = futures::future::pending().left_future();
     tokio::pin!(rpi_camera_task);
     let mut rpi_camera_frames = Vec::new();
 
+    let script_task = futures::future::pending().left_future();
+    tokio::pin!(script_task);
+    let mut script_callback = None;
+
+    let mut tail_tasks = FuturesUnordered::new();
+    let mut harvest_tasks = FuturesUnordered::new();
+
+    /* keep the versions that were staged before the Pi-Puck connected */
+    let mut versions = staging::Versions::default();
+    if let Err(error) = versions.restore(&device).await {
+        log::warn!("Could not find the software staged on Pi-Puck {}: {}", uuid, error);
+    }
+    /* whether the fernbedienung service and ARGoS log verbosely */
+    let mut verbose = false;
+    /* whether the connection to the fernbedienung service is being re-established */
+    let mut reconnecting = false;
+
     loop {
         tokio::select! {
             Some(frames) = rpi_camera_stream.next() => {
@@ -111,7 +255,32 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
             /* poll the fernbedienung, exiting the main loop if we don't get a response */
             result = &mut poll_rpi_link_strength_task => match result {
                 Ok(link_strength) => {
+                    if reconnecting {
+                        log::info!("Reconnected to Pi-Puck {}", uuid);
+                        reconnecting = false;
+                        reap_orphans(&device, &config, uuid).await;
+                    }
                     rpi_link_strength = link_strength;
+                    telemetry::record(&telemetry, uuid, "Raspberry Pi signal strength", link_strength as f32);
+                    if link_strength < config.borrow().pipuck_degraded_link_strength {
+                        lifecycle.report(Lifecycle::Degraded);
+                    }
+                    else if let Lifecycle::Identified | Lifecycle::Degraded = lifecycle.current() {
+                        lifecycle.report(match *argos_task {
+                            Either::Left(_) => Lifecycle::Ready,
+                            Either::Right(_) => Lifecycle::Running,
+                        });
+                    }
+                    poll_rpi_link_strength_task.set(poll_rpi_link_strength(&device));
+                }
+                /* keep polling while the connection is re-established, the requests fail with a
+                   different error if the device gives up */
+                Err(Error::FernbedienungError(fernbedienung::Error::ConnectionLost)) => {
+                    if !reconnecting {
+                        log::warn!("Lost the connection to Pi-Puck {}, reconnecting", uuid);
+                        reconnecting = true;
+                        lifecycle.report(Lifecycle::Degraded);
+                    }
                     poll_rpi_link_strength_task.set(poll_rpi_link_strength(&device));
                 }
                 Err(error) => {
@@ -119,12 +288,78 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                     break;
                 }
             },
+            /* the thermal state is not available on every image, failures are not fatal */
+            result = &mut poll_rpi_thermal_task => {
+                match result {
+                    Ok(state) => {
+                        telemetry::record(&telemetry, uuid, "Raspberry Pi temperature", state.temperature);
+                        thermal.report(state.throttled);
+                    },
+                    Err(error) => log::debug!("Could not poll the thermal state of Pi-Puck {}: {}", uuid, error),
+                }
+                poll_rpi_thermal_task.set(poll_rpi_thermal(&device));
+            },
+            result = &mut poll_rpi_system_info_task => {
+                match result {
+                    Ok(system_info) => rpi_system_info = Some(system_info),
+                    Err(error) => {
+                        log::debug!("Could not poll the system metrics of Pi-Puck {}: {}", uuid, error);
+                        rpi_system_info = None;
+                    }
+                }
+                poll_rpi_system_info_task.set(poll_rpi_system_info(&device));
+            },
+            result = &mut poll_rpi_battery_task => {
+                match result {
+                    Ok(battery) => {
+                        telemetry::record(&telemetry, uuid, "Battery", battery.percentage);
+                        rpi_battery = Some(battery);
+                    },
+                    Err(error) => {
+                        log::debug!("Could not poll the battery of Pi-Puck {}: {}", uuid, error);
+                        rpi_battery = None;
+                    }
+                }
+                poll_rpi_battery_task.set(poll_rpi_battery(&device, &config));
+            },
+            result = &mut query_image_task => {
+                query_image_task.set(futures::future::pending().left_future());
+                match result {
+                    Ok(queried_image) => image = Some(queried_image),
+                    Err(error) => log::warn!("Could not query the image of Pi-Puck {}: {}", uuid, error),
+                }
+            },
             /* if ARGoS is running, keep forwarding stdout/stderr  */
             argos_result = &mut argos_task => {
                 argos_stop_tx = None;
                 argos_task.set(futures::future::pending().left_future());
+                argos_started.set(futures::future::pending().left_future());
+                awaiting_argos = false;
                 log::info!("ARGoS terminated with {:?}", argos_result);
+                if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                    lifecycle.report(Lifecycle::Ready);
+                }
+            },
+            started = &mut argos_started => {
+                argos_started.set(futures::future::pending().left_future());
+                if started.is_ok() && awaiting_argos {
+                    awaiting_argos = false;
+                    lifecycle.report(Lifecycle::Running);
+                }
+            },
+            /* report the result of a maintenance script */
+            script_result = &mut script_task => {
+                script_task.set(futures::future::pending().left_future());
+                if let Some(callback) = script_callback.take() {
+                    let _ = callback.send(script_result.map_err(Error::FernbedienungError));
+                }
+            },
+            Some(tail_result) = tail_tasks.next() => {
+                if let Err(error) = tail_result {
+                    log::warn!("Could not follow a file on Pi-Puck {}: {}", uuid, error);
+                }
             },
+            Some(_) = harvest_tasks.next() => {},
             /* clean up for when the streaming process terminates */
             rpi_camera_result = &mut rpi_camera_task => {
                 rpi_camera_task.set(futures::future::pending().left_future());
@@ -132,7 +367,7 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                 match rpi_camera_result {
                     /* since we use the terminate signal to shutdown mjpg_streamer, report
                        AbnormalTerminationError as not an error */
-                    Ok(_) | Err(Error::FernbedienungError(fernbedienung::Error::AbnormalTerminationError)) => 
+                    Ok(_) | Err(Error::FernbedienungError(fernbedienung::Error::AbnormalTerminationError(_))) => 
                         log::info!("Camera stream stopped"),
                     Err(error) =>
                         log::warn!("Camera stream stopped: {}", error),
@@ -150,13 +385,26 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                                 match *rpi_camera_task {
                                     Either::Left(_) => Action::StartCameraStream,
                                     Either::Right(_) => Action::StopCameraStream
+                                },
+                                match verbose {
+                                    false => Action::EnableVerboseLogging,
+                                    true => Action::DisableVerboseLogging,
                                 }
-                            ],
+                            ].into_iter()
+                                .chain(versions.can_roll_back().then(|| Action::RollBackSoftware))
+                                .chain(versions.is_rolled_back().then(|| Action::UseLatestSoftware))
+                                .collect(),
                             cameras: rpi_camera_frames.clone(),
                             kernel_messages: kernel_messages.take(),
+                            image: image.clone(),
+                            system: rpi_system_info,
+                            battery: rpi_battery,
                         };
                         let _ = callback.send(state);
                     }
+                    Request::Battery(callback) => {
+                        let _ = callback.send(rpi_battery);
+                    }
                     Request::Execute(action) => match action {
                         Action::RpiReboot => {
                             if let Err(error) = device.reboot().await {
@@ -170,6 +418,22 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                             }
                             break;
                         }
+                        Action::RollBackSoftware => {
+                            versions.roll_back();
+                            log::info!("Pi-Puck {} will use the previous version of the software", uuid);
+                        },
+                        Action::UseLatestSoftware => {
+                            versions.use_latest();
+                            log::info!("Pi-Puck {} will use the latest version of the software", uuid);
+                        },
+                        Action::EnableVerboseLogging | Action::DisableVerboseLogging => {
+                            let enable = action == Action::EnableVerboseLogging;
+                            let signal = config.borrow().argos_log_level_signal.clone();
+                            match verbosity::set(&device, enable, signal.as_deref()).await {
+                                Ok(_) => verbose = enable,
+                                Err(error) => log::error!("Could not change the log level on Pi-Puck {}: {}", uuid, error),
+                            }
+                        },
                         Action::GetKernelMessages => {
                             match device.kernel_messages().await {
                                 Ok(messages) => kernel_messages = Some(messages),
@@ -202,18 +466,52 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                     },
                     // modify experiment start to use a mpsc channel to send ARGoS started/stopped
                     // events back to the arena. The stop event should be sent when ARGoS terminates
-                    Request::ExperimentStart{software, journal, callback} => {
-                        match handle_experiment_start(uuid, &device, software, journal).await {
-                            Ok((argos, stop_tx)) => {
+                    Request::ExperimentStart{software, flags, remote_brain, journal, cancellation, callback} => {
+                        let result = match versions.stage(&device, &software, &cancellation).await {
+                            Ok(staged) => {
+                                /* the software was uploaded, the robot is running once ARGoS has started */
+                                if lifecycle.current() == Lifecycle::Ready {
+                                    lifecycle.report(Lifecycle::Staged);
+                                    awaiting_argos = true;
+                                }
+                                let launch = config.borrow().argos_launch.clone();
+                                handle_experiment_start(uuid, &device, software, staged, flags, remote_brain, launch, journal).await
+                            },
+                            Err(error) => Err(Error::StagingError(error)),
+                        };
+                        match result {
+                            Ok((argos, stop_tx, started_rx)) => {
                                 argos_task.set(argos.right_future());
                                 argos_stop_tx = Some(stop_tx);
+                                argos_started.set(started_rx.right_future());
                                 let _ = callback.send(Ok(()));
                             },
                             Err(error) => {
+                                if awaiting_argos {
+                                    awaiting_argos = false;
+                                    lifecycle.report(Lifecycle::Ready);
+                                }
                                 let _ = callback.send(Err(error));
                             }
                         }
                     },
+                    Request::Tail{path, lines, output_tx} =>
+                        tail_tasks.push(device.tail(staging::working_dir(), path, lines, output_tx)),
+                    Request::Harvest{patterns, destination, callback} => {
+                        let device = &device;
+                        harvest_tasks.push(async move {
+                            let _ = callback.send(harvest::collect(device, patterns, destination).await);
+                        });
+                    },
+                    Request::RunScript{script, callback} => {
+                        if let Either::Left(_) = *script_task {
+                            script_task.set(device.run_script(script).right_future());
+                            script_callback = Some(callback);
+                        }
+                        else {
+                            let _ = callback.send(Err(Error::ScriptAlreadyRunning));
+                        }
+                    },
                     Request::ExperimentStop => {
                         if let Some(stop_tx) = argos_stop_tx.take() {
                             let _ = stop_tx.send(());
@@ -222,7 +520,36 @@ pub async fn new(uuid: Uuid, mut arena_rx: Receiver, device: fernbedienung::Devi
                         let result = (&mut argos_task).await;
                         log::info!("ARGoS terminated with {:?}", result);
                         argos_task.set(futures::future::pending().left_future());
-                    }
+                        argos_started.set(futures::future::pending().left_future());
+                        awaiting_argos = false;
+                        if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                            lifecycle.report(Lifecycle::Ready);
+                        }
+                    },
+                    Request::ExperimentAbort{patterns} => {
+                        argos_stop_tx = None;
+                        if let Err(error) = device.kill("argos3").await {
+                            log::error!("Could not kill ARGoS on Pi-Puck {}: {}", uuid, error);
+                        }
+                        if let Either::Right(_) = *argos_task {
+                            let result = (&mut argos_task).await;
+                            log::info!("ARGoS was killed with {:?}", result);
+                        }
+                        let patterns = patterns.into_iter()
+                            .filter(|pattern| harvest::is_within_working_dir(Path::new(pattern)))
+                            .collect::<Vec<_>>();
+                        if !patterns.is_empty() {
+                            if let Err(error) = device.remove_files(staging::working_dir(), patterns).await {
+                                log::error!("Could not remove the results on Pi-Puck {}: {}", uuid, error);
+                            }
+                        }
+                        argos_task.set(futures::future::pending().left_future());
+                        argos_started.set(futures::future::pending().left_future());
+                        awaiting_argos = false;
+                        if let Lifecycle::Staged | Lifecycle::Running = lifecycle.current() {
+                            lifecycle.report(Lifecycle::Ready);
+                        }
+                    },
                 }
             }
         }
@@ -244,6 +571,7 @@ fn handle_stream_start<'d>(device: &'d fernbedienung::Device, configs: &'static
                     "-o".to_owned(),
                     format!("output_http.so -p {} -l {}", port, device.addr)
                 ],
+                ..Default::default()
             };
             let (stop_tx, stop_rx) = oneshot::channel::<()>();
             let process = device.run(process_request, Some(stop_rx), None, None, None)
@@ -289,79 +617,22 @@ fn handle_stream_start<'d>(device: &'d fernbedienung::Device, configs: &'static
 async fn handle_experiment_start<'d>(uuid: Uuid,
                                      device: &'d fernbedienung::Device,
                                      software: software::Software,
-                                     journal: mpsc::UnboundedSender<journal::Request>) 
-    -> Result<(impl Future<Output = fernbedienung::Result<()>> + 'd, oneshot::Sender<()>)> {
-    /* extract the name of the config file */
-    let (argos_config, _) = software.argos_config()?;
-    let argos_config = argos_config.to_owned();
-    /* get the relevant ip address of this machine */
-    let message_router_addr = async {
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
-        socket.connect((device.addr, 80)).await?;
-        socket.local_addr().map(|mut socket| {
-            socket.set_port(4950);
-            socket
-        })
-    }.await?;
-
-    /* upload the control software */
-    let software_upload_path = device.create_temp_dir()
-        .map_err(|error| Error::FernbedienungError(error))
-        .and_then(|path: String| software.0.into_iter()
-            .map(|(filename, contents)| {
-                let path = PathBuf::from(&path);
-                let filename = PathBuf::from(&filename);
-                device.upload(path, filename, contents)
-            })
-            .collect::<FuturesUnordered<_>>()
-            .map_err(|error| Error::FernbedienungError(error))
-            .try_collect::<Vec<_>>()
-            .map_ok(|_| path)
-        ).await?;
-
-    /* create a remote instance of ARGoS3 */
-    let process = fernbedienung::Process {
-        target: "argos3".into(),
-        working_dir: Some(software_upload_path.into()),
-        args: vec![
-            "--config".to_owned(), argos_config.to_owned(),
-            "--router".to_owned(), message_router_addr.to_string(),
-            "--id".to_owned(), uuid.to_string(),
-        ],
-    };
-
-    /* channel for terminating ARGoS */
-    let (terminate_tx, terminate_rx) = oneshot::channel();
-
-    /* create future for running ARGoS */
-    let argos_task_future = async move {
-        /* channels for routing stdout and stderr to the journal */
-        let (stdout_tx, mut stdout_rx) = mpsc::unbounded_channel();
-        let (stderr_tx, mut stderr_rx) = mpsc::unbounded_channel();
-        /* run argos remotely */
-        let argos = device.run(process, Some(terminate_rx), None, Some(stdout_tx), Some(stderr_tx));
-        tokio::pin!(argos);
-        loop {
-            tokio::select! {
-                Some(data) = stdout_rx.recv() => {
-                    let message = journal::Robot::StandardOutput(data);
-                    let event = journal::Event::Robot(uuid, message);
-                    let request = journal::Request::Record(event);
-                    if let Err(error) = journal.send(request) {
-                        log::warn!("Could not forward standard output of {} to journal: {}", uuid, error);
-                    }
-                },
-                Some(data) = stderr_rx.recv() => {
-                    let message = journal::Robot::StandardError(data);
-                    let event = journal::Event::Robot(uuid, message);
-                    let request = journal::Request::Record(event);
-                    if let Err(error) = journal.send(request) {
-                        log::warn!("Could not forward standard error of {} to journal: {}", uuid, error);
-                    }
-                },
-                exit_status = &mut argos => break exit_status,
-            }
-        }
+                                     (software_path, argos_config): (PathBuf, String),
+                                     flags: flags::Assignment,
+                                     remote_brain: bool,
+                                     launch: staging::Launch,
+                                     journal: journal::Sender) 
+    -> Result<(impl Future<Output = fernbedienung::Result<fernbedienung::ExitStatus>> + 'd, oneshot::Sender<()>, oneshot::Receiver<()>)> {
+    let argos = argos::Argos {
+        uuid,
+        working_dir: software_path,
+        argos_config,
+        args: Vec::new(),
+        connection: match remote_brain {
+            true => argos::Connection::Body,
+            false => argos::Connection::Router,
+        },
+        launch,
     };
-    Ok((argos_task_future, terminate_tx)) 
+    Ok(argos.start(device, &software, &flags, journal).await?)
 }
\ No newline at end of file
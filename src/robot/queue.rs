@@ -0,0 +1,167 @@
+use std::{collections::VecDeque, sync::{Arc, Mutex}, time::{Duration, Instant, SystemTime}};
+use futures::future;
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// Time for which the last operation that was refused is reported
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Operations that change the state of a robot
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum Kind {
+    /// Staging the software and starting ARGoS
+    Staging,
+    /// Running a maintenance script
+    Script,
+    /// Collecting the results of a run
+    Harvest,
+    /// Powering on, halting, rebooting, or powering off the robot or one of its parts
+    Power,
+    /// Rolling back the software or returning to the latest software
+    Software,
+}
+
+impl Kind {
+    /* scripts and the collection of results wait for each other, any other combination of
+       operations is refused */
+    fn waits_for(self, other: Kind) -> bool {
+        matches!(self, Kind::Script | Kind::Harvest) && matches!(other, Kind::Script | Kind::Harvest)
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Kind::Staging => "staging",
+            Kind::Script => "a maintenance script",
+            Kind::Harvest => "the collection of results",
+            Kind::Power => "a power action",
+            Kind::Software => "a change of software version",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    pub kind: Kind,
+    /// Whether the operation is being carried out or is waiting for the operations before it
+    pub running: bool,
+    pub queued: SystemTime,
+    #[serde(skip)]
+    id: u64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Refused {requested}, {existing} is {state}")]
+    Conflict {
+        requested: Kind,
+        existing: Kind,
+        /* whether the existing operation is running or queued */
+        state: &'static str,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Default)]
+struct Entries {
+    next_id: u64,
+    entries: VecDeque<Entry>,
+    /// The last operation that was refused, why, and when
+    refused: Option<(Instant, String)>,
+}
+
+/// The operations of a robot that are running or waiting, which is shared between the arena and
+/// the tasks that carry out the operations
+#[derive(Clone)]
+pub struct Queue {
+    entries: Arc<Mutex<Entries>>,
+    changed_tx: Arc<watch::Sender<()>>,
+    changed_rx: watch::Receiver<()>,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        let (changed_tx, changed_rx) = watch::channel(());
+        Queue {
+            entries: Default::default(),
+            changed_tx: Arc::new(changed_tx),
+            changed_rx,
+        }
+    }
+}
+
+/// A place in the queue, the operation is removed from the queue when its ticket is dropped
+pub struct Ticket {
+    id: u64,
+    queue: Queue,
+}
+
+impl Queue {
+    /// Refuses an operation that conflicts with the operations in the queue, otherwise queues it
+    pub fn enter(&self, kind: Kind) -> Result<Ticket> {
+        let mut entries = self.entries.lock().unwrap();
+        let conflict = entries.entries.iter()
+            .find(|entry| !kind.waits_for(entry.kind))
+            .map(|entry| Error::Conflict {
+                requested: kind,
+                existing: entry.kind,
+                state: if entry.running { "running" } else { "queued" },
+            });
+        if let Some(conflict) = conflict {
+            entries.refused = Some((Instant::now(), conflict.to_string()));
+            return Err(conflict);
+        }
+        let id = entries.next_id;
+        entries.next_id += 1;
+        let running = entries.entries.is_empty();
+        entries.entries.push_back(Entry { kind, running, queued: SystemTime::now(), id });
+        Ok(Ticket { id, queue: self.clone() })
+    }
+
+    /// Refuses an operation that conflicts with the operations in the queue, for operations that
+    /// complete as soon as they are requested
+    pub fn check(&self, kind: Kind) -> Result<()> {
+        self.enter(kind).map(drop)
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries.lock().unwrap().entries.iter().cloned().collect()
+    }
+
+    /// Why the last operation was refused, if this happened recently
+    pub fn refused(&self) -> Option<String> {
+        self.entries.lock().unwrap().refused.as_ref()
+            .filter(|(refused_at, _)| refused_at.elapsed() < REFUSAL_TIMEOUT)
+            .map(|(_, reason)| reason.clone())
+    }
+}
+
+impl Ticket {
+    /// Completes once the operations that were queued before this one have completed
+    pub async fn ready(&self) {
+        let mut changed_rx = self.queue.changed_rx.clone();
+        loop {
+            let front = self.queue.entries.lock().unwrap().entries.front().map(|entry| entry.id);
+            if front == Some(self.id) {
+                return;
+            }
+            if changed_rx.changed().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut entries = self.queue.entries.lock().unwrap();
+        entries.entries.retain(|entry| entry.id != self.id);
+        if let Some(next) = entries.entries.front_mut() {
+            next.running = true;
+        }
+        drop(entries);
+        let _ = self.queue.changed_tx.send(());
+    }
+}
@@ -0,0 +1,54 @@
+use futures::FutureExt;
+use uuid::Uuid;
+use std::{future::Future, net::{Ipv4Addr, SocketAddr}, pin::Pin, sync::atomic::{AtomicU16, Ordering}, task::{Context, Poll}};
+use tokio::{sync::mpsc, task::JoinHandle};
+use crate::arena;
+use crate::robot::lifecycle;
+
+mod task;
+
+pub use task::{
+    Error, Receiver, Request, Sender, State
+};
+
+/* the last loopback address that was given to a simulated robot */
+static LAST_ADDRESS: AtomicU16 = AtomicU16::new(0);
+
+/* the message router tells the robots apart by their addresses, so each simulated robot is given
+   its own loopback address in 127.1.0.0/16, which its instance of ARGoS connects from */
+fn next_address() -> Ipv4Addr {
+    let [high, low] = LAST_ADDRESS.fetch_add(1, Ordering::Relaxed).wrapping_add(1).max(1).to_be_bytes();
+    Ipv4Addr::new(127, 1, high, low)
+}
+
+/// A robot that is simulated by an instance of ARGoS running on the supervisor. Simulated robots
+/// connect to the message router in the same way as real robots.
+pub struct Simulated(Uuid, JoinHandle<Uuid>);
+
+impl Simulated {
+    pub fn new(kind: arena::Kind,
+               router: SocketAddr,
+               lifecycle: lifecycle::Sender) -> (Uuid, Sender, Self) {
+        let uuid = Uuid::new_v4();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(task::new(uuid, rx, kind, router, next_address(), lifecycle::Reporter::new(uuid, lifecycle)));
+        (uuid, tx, Self(uuid, handle))
+    }
+
+    pub fn abort(&self) {
+        self.1.abort();
+    }
+}
+
+impl Future for Simulated {
+    type Output = Result<Uuid, tokio::task::JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self(uuid, handle) = self.get_mut();
+        /* an aborted task ends like a task that stopped by itself */
+        handle.poll_unpin(cx).map(|result| match result {
+            Err(error) if error.is_cancelled() => Ok(*uuid),
+            result => result,
+        })
+    }
+}
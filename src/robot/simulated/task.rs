@@ -0,0 +1,160 @@
+use bytes::BytesMut;
+use futures::future::{self, Either};
+use uuid::Uuid;
+use std::{net::{Ipv4Addr, SocketAddr}, path::PathBuf, process::Stdio};
+use tokio::{io::{AsyncRead, AsyncReadExt}, process::{Child, Command}, sync::{mpsc, oneshot}};
+use crate::arena;
+use crate::journal;
+use crate::software;
+use crate::flags;
+use crate::robot::lifecycle::{self, Lifecycle};
+
+#[derive(Debug)]
+pub struct State {
+    pub kind: arena::Kind,
+    /// Loopback address from which the robot connects to the message router
+    pub addr: Ipv4Addr,
+    pub running: bool,
+}
+
+pub enum Request {
+    State(oneshot::Sender<State>),
+    ExperimentStart {
+        software: software::Software,
+        flags: flags::Assignment,
+        journal: journal::Sender,
+        callback: oneshot::Sender<Result<()>>
+    },
+    ExperimentStop,
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("ARGoS is already running")]
+    AlreadyRunning,
+    #[error("Could not send request")]
+    RequestError,
+    #[error("Did not receive response")]
+    ResponseError,
+
+    #[error(transparent)]
+    SoftwareError(#[from] software::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/* forwards the output of the local ARGoS instance to the journal */
+async fn forward<R: AsyncRead + Unpin>(uuid: Uuid,
+                                       mut output: R,
+                                       journal: journal::Sender,
+                                       message: fn(BytesMut) -> journal::Robot) {
+    let mut buffer = BytesMut::with_capacity(4096);
+    loop {
+        buffer.clear();
+        match output.read_buf(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let event = journal::Event::Robot(uuid, message(buffer.split()));
+                if let Err(error) = journal.record(event) {
+                    log::warn!("Could not forward output of simulated robot {} to journal: {}", uuid, error);
+                }
+            }
+        }
+    }
+}
+
+fn handle_experiment_start(uuid: Uuid,
+                           router: SocketAddr,
+                           addr: Ipv4Addr,
+                           software: software::Software,
+                           flags: &flags::Assignment,
+                           journal: journal::Sender) -> Result<Child> {
+    let (argos_config, _) = software.argos_config()?;
+    let argos_config = argos_config.to_owned();
+    /* write the control software and the plugins for this machine into a working directory */
+    let working_dir = std::env::temp_dir().join(format!("mns-supervisor-{}", uuid));
+    std::fs::create_dir_all(&working_dir)?;
+    for (filename, contents) in software.controller().into_iter()
+        .chain(software.plugins(std::env::consts::ARCH)) {
+        let path = working_dir.join(PathBuf::from(filename));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+    }
+    let mut child = Command::new("argos3")
+        .current_dir(&working_dir)
+        .env("ARGOS_PLUGIN_PATH", &working_dir)
+        .envs(flags::variables(flags))
+        .args(&["--config", &argos_config])
+        .args(&["--router", &router.to_string()])
+        .args(&["--id", &uuid.to_string()])
+        /* the address that the connection to the router is bound to */
+        .args(&["--address", &addr.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward(uuid, stdout, journal.clone(), journal::Robot::StandardOutput));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward(uuid, stderr, journal, journal::Robot::StandardError));
+    }
+    Ok(child)
+}
+
+pub async fn new(uuid: Uuid,
+                 mut arena_rx: Receiver,
+                 kind: arena::Kind,
+                 router: SocketAddr,
+                 addr: Ipv4Addr,
+                 mut lifecycle: lifecycle::Reporter) -> Uuid {
+    /* simulated robots are available immediately */
+    lifecycle.report(Lifecycle::Identified);
+    lifecycle.report(Lifecycle::Ready);
+    let mut argos = None;
+    loop {
+        let argos_task = match argos.as_mut() {
+            Some(child) => Either::Right(Child::wait(child)),
+            None => Either::Left(future::pending()),
+        };
+        tokio::select! {
+            result = argos_task => {
+                argos = None;
+                log::info!("ARGoS terminated with {:?}", result);
+                lifecycle.report(Lifecycle::Ready);
+            },
+            recv_request = arena_rx.recv() => match recv_request {
+                None => break,
+                Some(Request::State(callback)) => {
+                    let _ = callback.send(State { kind, addr, running: argos.is_some() });
+                },
+                Some(Request::ExperimentStart{software, flags, journal, callback}) => {
+                    let result = match argos {
+                        Some(_) => Err(Error::AlreadyRunning),
+                        None => handle_experiment_start(uuid, router, addr, software, &flags, journal),
+                    };
+                    let _ = callback.send(result.map(|child| {
+                        argos = Some(child);
+                        lifecycle.report(Lifecycle::Staged);
+                        lifecycle.report(Lifecycle::Running);
+                    }));
+                },
+                Some(Request::ExperimentStop) => {
+                    if let Some(mut child) = argos.take() {
+                        let _ = child.kill().await;
+                        lifecycle.report(Lifecycle::Ready);
+                    }
+                },
+            }
+        }
+    }
+    uuid
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, path::PathBuf};
+use serde::Deserialize;
+use crate::network::fernbedienung;
+use crate::operation;
+use crate::software;
+
+/// Directory on the robot that contains a subdirectory for each staged version of the software
+const SOFTWARE_DIR: &str = "/opt/mns-supervisor/software";
+/// Number of versions that are kept on the robot
+const STAGED_VERSIONS: usize = 3;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    FernbedienungError(#[from] fernbedienung::Error),
+    #[error(transparent)]
+    SoftwareError(#[from] software::Error),
+    #[error("Rolled back to version {0} of the software, use the latest software to run the uploaded version {1}")]
+    RolledBack(String, String),
+    #[error("Staging was cancelled")]
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The environment and the resources of ARGoS on the robots
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Launch {
+    /// Variables added to the environment of ARGoS, e.g., ARGOS_PLUGIN_PATH or LD_LIBRARY_PATH
+    pub env: HashMap<String, String>,
+    /// Niceness of ARGoS, from -20 (highest priority) to 19 (lowest priority)
+    pub nice: Option<i32>,
+    /// Limits on the virtual memory (bytes) and the processor time (seconds) of ARGoS
+    pub limits: fernbedienung::Limits,
+}
+
+#[derive(Clone, Debug)]
+struct Version {
+    id: String,
+    argos_config: String,
+}
+
+/// The versions of the software that have been staged on a robot. The most recent version is
+/// used unless the robot has been rolled back to a previous version.
+#[derive(Debug, Default)]
+pub struct Versions {
+    staged: Vec<Version>,
+    selected: Option<usize>,
+}
+
+async fn shell(device: &fernbedienung::Device, command: String) -> fernbedienung::Result<()> {
+    let process = fernbedienung::Process {
+        target: "sh".into(),
+        working_dir: None,
+        args: vec!["-c".to_owned(), command],
+        ..Default::default()
+    };
+    device.run(process, None, None, None, None).await.map(|_| ())
+}
+
+/// The working directory of ARGoS, which links to the selected version
+pub fn working_dir() -> PathBuf {
+    PathBuf::from(SOFTWARE_DIR).join("current")
+}
+
+impl Versions {
+    /// Rebuilds the versions from the directories on the robot, so that the versions staged before
+    /// the robot reconnected or the supervisor restarted are kept and can be rolled back to. The
+    /// versions that are already tracked are left as they are.
+    pub async fn restore(&mut self, device: &fernbedienung::Device) -> Result<()> {
+        if !self.staged.is_empty() {
+            return Ok(());
+        }
+        let mut entries = match device.list_dir(PathBuf::from(SOFTWARE_DIR)).await {
+            Ok(entries) => entries,
+            /* older versions of the fernbedienung service can not list directories */
+            Err(fernbedienung::Error::RemoteError(_)) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        entries.retain(|entry| entry.directory && entry.name != "current");
+        entries.sort_by_key(|entry| entry.modified);
+        for entry in entries.into_iter().rev().take(STAGED_VERSIONS) {
+            let files = device.list_dir(PathBuf::from(SOFTWARE_DIR).join(&entry.name)).await?;
+            let mut argos_configs = files.into_iter()
+                .filter(|file| file.name.ends_with(".argos"));
+            /* versions without a unique configuration can not be launched and are removed later */
+            if let (Some(argos_config), None) = (argos_configs.next(), argos_configs.next()) {
+                self.staged.insert(0, Version { id: entry.name, argos_config: argos_config.name });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn can_roll_back(&self) -> bool {
+        self.selected.unwrap_or(self.staged.len().saturating_sub(1)) > 0
+    }
+
+    pub fn is_rolled_back(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    /// Selects the version before the currently selected version
+    pub fn roll_back(&mut self) {
+        if self.can_roll_back() {
+            let current = self.selected.unwrap_or(self.staged.len() - 1);
+            self.selected = Some(current - 1);
+        }
+    }
+
+    /// Returns to using the most recent version
+    pub fn use_latest(&mut self) {
+        self.selected = None;
+    }
+
+    /// Makes the selected version current on the robot, uploading the given software first unless
+    /// the robot has been rolled back. The files are uploaded one at a time so that staging stops
+    /// soon after the operation has been cancelled. Returns the working directory and the ARGoS
+    /// configuration.
+    pub async fn stage(&mut self,
+                       device: &fernbedienung::Device,
+                       software: &software::Software,
+                       cancellation: &operation::Token) -> Result<(PathBuf, String)> {
+        /* the uploaded software is not staged while the robot is rolled back */
+        if let Some(index) = self.selected {
+            let uploaded = software.version();
+            if self.staged[index].id != uploaded {
+                return Err(Error::RolledBack(self.staged[index].id.clone(), uploaded));
+            }
+        }
+        else {
+            let (argos_config, _) = software.argos_config()?;
+            let version = Version {
+                id: software.version(),
+                argos_config: argos_config.to_owned(),
+            };
+            let path = PathBuf::from(SOFTWARE_DIR).join(&version.id);
+            device.mkdir(path.clone()).await?;
+            for (filename, contents) in software.controller() {
+                if cancellation.is_cancelled() {
+                    /* the partially uploaded version is removed when the next version is staged */
+                    return Err(Error::Cancelled);
+                }
+                device.upload(path.clone(), filename.into(), contents).await?;
+            }
+            self.staged.retain(|staged| staged.id != version.id);
+            self.staged.push(version);
+            if self.staged.len() > STAGED_VERSIONS {
+                self.staged.remove(0);
+            }
+            self.remove_untracked(device).await?;
+        }
+        let version = match self.selected {
+            Some(index) => &self.staged[index],
+            None => &self.staged[self.staged.len() - 1],
+        };
+        /* point the launch configuration at the selected version */
+        shell(device, format!("cd {} && ln -sfn {} current", SOFTWARE_DIR, version.id)).await?;
+        Ok((working_dir(), version.argos_config.clone()))
+    }
+
+    /* removes the versions on the robot that are no longer tracked, including those staged before
+       the supervisor restarted */
+    async fn remove_untracked(&self, device: &fernbedienung::Device) -> Result<()> {
+        match device.list_dir(PathBuf::from(SOFTWARE_DIR)).await {
+            Ok(entries) => {
+                let untracked = entries.into_iter()
+                    .filter(|entry| entry.name != "current" &&
+                        !self.staged.iter().any(|staged| staged.id == entry.name));
+                for entry in untracked {
+                    device.remove(PathBuf::from(SOFTWARE_DIR).join(&entry.name), true).await?;
+                }
+            },
+            /* older versions of the fernbedienung service can not list directories */
+            Err(fernbedienung::Error::RemoteError(_)) => {
+                let keep = self.staged.iter()
+                    .map(|staged| format!("-e '^{}$'", staged.id))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                shell(device, format!("cd {} && ls -1 | grep -v -e '^current$' {} | xargs -r rm -rf",
+                    SOFTWARE_DIR, keep)).await?;
+            },
+            Err(error) => return Err(error.into()),
+        }
+        Ok(())
+    }
+}
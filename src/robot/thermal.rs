@@ -0,0 +1,32 @@
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Interval at which the robots are polled for the thermal state of their processors
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type Sender = mpsc::UnboundedSender<(Uuid, bool)>;
+pub type Receiver = mpsc::UnboundedReceiver<(Uuid, bool)>;
+
+/// Used by the robot tasks to report to the arena when their processor starts or stops being
+/// throttled, since a throttled robot silently misses the deadlines of its controller
+pub struct Reporter {
+    uuid: Uuid,
+    tx: Sender,
+    throttled: bool,
+}
+
+impl Reporter {
+    pub fn new(uuid: Uuid, tx: Sender) -> Self {
+        Reporter { uuid, tx, throttled: false }
+    }
+
+    pub fn report(&mut self, throttled: bool) {
+        if self.throttled != throttled {
+            self.throttled = throttled;
+            if let Err(error) = self.tx.send((self.uuid, throttled)) {
+                log::warn!("Could not report thermal state of {}: {}", self.uuid, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+use crate::network::fernbedienung::{self, LogLevel};
+
+/// Directory of the file from which the controllers read the level of their logging, the file is
+/// in /tmp so that a robot logs normally again after it reboots
+const LOG_LEVEL_DIR: &str = "/tmp";
+/// Name of the file from which the controllers read the level of their logging
+const LOG_LEVEL_FILENAME: &str = "mns_log_level";
+
+/// Switches the logging of the fernbedienung service and of ARGoS on a robot between the verbose
+/// (debug) and the normal (info) level. The level of ARGoS is written to a file that the
+/// controllers read when they start, and if a signal is given, it is sent to ARGoS so that running
+/// controllers read the file again.
+pub async fn set(device: &fernbedienung::Device, verbose: bool, signal: Option<&str>) -> fernbedienung::Result<()> {
+    let (level, name) = match verbose {
+        true => (LogLevel::Debug, "debug"),
+        false => (LogLevel::Info, "info"),
+    };
+    match device.set_log_level(level).await {
+        /* older versions of the fernbedienung service reject the request, the level of ARGoS can
+           still be changed */
+        Err(fernbedienung::Error::RemoteError(error)) =>
+            log::warn!("Could not change the log level of the fernbedienung service on {}: {}", device.addr, error),
+        result => result?,
+    }
+    let contents = format!("{}\n", name).into_bytes();
+    device.upload(PathBuf::from(LOG_LEVEL_DIR), PathBuf::from(LOG_LEVEL_FILENAME), contents).await?;
+    match signal {
+        Some(signal) => device.signal("argos3", signal).await,
+        None => Ok(()),
+    }
+}
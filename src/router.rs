@@ -1,295 +1,344 @@
-use tokio_stream::wrappers::UnboundedReceiverStream;
-use tokio_util::codec::{Decoder, Encoder, Framed};
-use bytes::{BytesMut, Bytes, BufMut, Buf};
-use std::{io, collections::HashMap, sync::Arc, net::SocketAddr};
-use tokio::{net::{TcpListener, TcpStream}, sync::{Mutex, mpsc}};
+use tokio_util::codec::Framed;
+use bytes::Bytes;
+use std::{io, sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}}, net::{IpAddr, Ipv4Addr, SocketAddr}, time::{Duration, Instant}};
+use tokio::{net::{TcpListener, TcpStream}, sync::{broadcast, mpsc, oneshot}};
 use futures::StreamExt;
 use log;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use std::mem::size_of;
+use mns_protocol::router::ByteArrayCodec;
+pub use mns_protocol::router::{decode, encode_lua_table, LuaType};
 
 use crate::journal;
 
-const LUA_TNIL: i8 = 0;
-const LUA_TBOOLEAN: i8 = 1;
-//const LUA_TLIGHTUSERDATA: i8 = 2;
-const LUA_TNUMBER: i8 = 3;
-const LUA_TSTRING: i8 = 4;
-const LUA_TTABLE: i8 = 5;
-//const LUA_TFUNCTION: i8 = 6;
-const LUA_TUSERDATA: i8 = 7;
-//const LUA_TTHREAD: i8 = 8;
-const LUA_TUSERDATA_VECTOR2: u8 = 1;
-const LUA_TUSERDATA_VECTOR3: u8 = 2;
-const LUA_TUSERDATA_QUATERNION: u8 = 3;
-const MAX_MANTISSA: f64 = 9223372036854775806.0;
-
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum LuaType {
-    String(String),
-    Number(f64),
-    Boolean(bool),
-    Vector2(f64, f64),
-    Vector3(f64, f64, f64),
-    Quaternion(f64, f64, f64, f64),
-    Table(Vec<(LuaType, LuaType)>),
-}
+/// Initial capacity of the read and write buffers for each robot
+const BUFFER_CAPACITY: usize = 64 * 1024;
+/// Number of messages that a robot can fall behind before it is disconnected
+const RELAY_CAPACITY: usize = 1024;
+/// Upper bounds of the relay latency histogram buckets in microseconds
+const LATENCY_BUCKETS_US: [u64; 7] = [100, 500, 1000, 2000, 5000, 10000, 50000];
+/// Interval at which the relay latency histogram is reported
+const LATENCY_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+/// Delay before reconnecting to a simulation after the connection to it was lost
+const BRIDGE_RETRY_DELAY: Duration = Duration::from_secs(2);
 
-#[derive(thiserror::Error, Debug)]
-enum Error {
-    #[error("Could not decode message")]
-    DecodeError,
-   
-    #[error(transparent)]
-    IoError(#[from] io::Error),
+/// Histogram of the time between a message being received from one robot and being
+/// handed to the socket of another robot
+#[derive(Default)]
+struct LatencyHistogram {
+    /* the last bucket counts the messages that exceeded all bounds */
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
 }
 
-fn decode_lua_usertype(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    if buf.has_remaining() {
-        match buf.get_u8() {
-            LUA_TUSERDATA_VECTOR2 => decode_lua_vector2(buf),
-            LUA_TUSERDATA_VECTOR3 => decode_lua_vector3(buf),
-            LUA_TUSERDATA_QUATERNION => decode_lua_quaternion(buf),
-            _ => Err(Error::DecodeError)
-        }
+impl LatencyHistogram {
+    fn record(&self, latency: Duration) {
+        let latency = latency.as_micros() as u64;
+        let index = LATENCY_BUCKETS_US.iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
     }
-    else {
-        Err(Error::DecodeError)
+
+    /// Take the counts in each bucket, resetting the histogram
+    fn take(&self) -> Vec<u64> {
+        self.buckets.iter()
+            .map(|bucket| bucket.swap(0, Ordering::Relaxed))
+            .collect()
     }
-}
 
-fn decode_lua_vector2(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    let x = decode_lua_number(buf)?;
-    let y = decode_lua_number(buf)?;
-    match (x, y) {
-        (LuaType::Number(x),
-         LuaType::Number(y)) => Ok(LuaType::Vector2(x, y)),
-        _ => Err(Error::DecodeError)
+    fn report(&self) {
+        let counts = self.take();
+        if counts.iter().any(|count| *count != 0) {
+            let summary = LATENCY_BUCKETS_US.iter()
+                .map(|bound| format!("<={}us", bound))
+                .chain(std::iter::once(String::from("more")))
+                .zip(counts.iter())
+                .map(|(bucket, count)| format!("{}: {}", bucket, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            log::info!("Message router relay latency: {}", summary);
+        }
     }
 }
 
-fn decode_lua_vector3(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    let x = decode_lua_number(buf)?;
-    let y = decode_lua_number(buf)?;
-    let z = decode_lua_number(buf)?;
-    match (x, y, z) {
-        (LuaType::Number(x),
-         LuaType::Number(y),
-         LuaType::Number(z)) => Ok(LuaType::Vector3(x, y, z)),
-        _ => Err(Error::DecodeError)
-    }
+/// A message that can be injected into the router from the webui
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Template {
+    pub name: String,
+    /// Address of the robot that receives the message, the message is broadcast if None
+    #[serde(default)]
+    pub recipient: Option<IpAddr>,
+    pub message: serde_json::Value,
 }
 
-fn decode_lua_quaternion(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    let w = decode_lua_number(buf)?;
-    let x = decode_lua_number(buf)?;
-    let y = decode_lua_number(buf)?;
-    let z = decode_lua_number(buf)?;
-    match (w, x, y, z) {
-        (LuaType::Number(w),
-         LuaType::Number(x),
-         LuaType::Number(y),
-         LuaType::Number(z)) => Ok(LuaType::Quaternion(w, x, y, z)),
-        _ => Err(Error::DecodeError)
-    }
+/// Address used as the sender of the messages that are injected by the supervisor
+pub fn supervisor_addr() -> SocketAddr {
+    (Ipv4Addr::UNSPECIFIED, 0).into()
 }
 
-fn decode_lua_number(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    /* handle Carlo's unusual double encoding */
-    if buf.remaining() > size_of::<u64>() + size_of::<u32>() {
-        let mantissa = buf.get_i64();
-        let exponent = buf.get_i32();
-        if mantissa == 0 {
-            Ok(LuaType::Number(0.0))
-        }
-        else {
-            let significand = ((mantissa.abs() - 1i64) as f64 / MAX_MANTISSA) / 2.0 + 0.5;
-            let value = significand * 2.0f64.powi(exponent);
-            if mantissa < 0 {
-                Ok(LuaType::Number(-value))
-            }
-            else {
-                Ok(LuaType::Number(value))
-            }
-        }
-    }
-    else {
-        Err(Error::DecodeError)
+/// Converts JSON into a Lua value, objects and arrays become tables and nulls are omitted
+pub fn json_to_lua(value: &serde_json::Value) -> Option<LuaType> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(value) => Some(LuaType::Boolean(*value)),
+        serde_json::Value::Number(value) => value.as_f64().map(LuaType::Number),
+        serde_json::Value::String(value) => Some(LuaType::String(value.clone())),
+        serde_json::Value::Array(values) => Some(LuaType::Table(values.iter()
+            .enumerate()
+            .filter_map(|(index, value)| json_to_lua(value)
+                .map(|value| (LuaType::Number((index + 1) as f64), value)))
+            .collect())),
+        serde_json::Value::Object(values) => Some(LuaType::Table(values.iter()
+            .filter_map(|(key, value)| json_to_lua(value)
+                .map(|value| (LuaType::String(key.clone()), value)))
+            .collect())),
     }
 }
 
-fn decode_lua_string(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    /* extract C string */
-    let mut data = Vec::new();
-    while buf.has_remaining() {
-        match buf.get_u8() {
-            0 => break,
-            byte => data.push(byte),
-        }
-    }
-    String::from_utf8(data)
-        .map_err(|_| Error::DecodeError)
-        .map(|content| LuaType::String(content))
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub sender: SocketAddr,
+    /// Address of the only robot that receives this message, if any
+    pub recipient: Option<IpAddr>,
+    pub received: Instant,
+    pub data: Bytes,
 }
 
-fn decode_lua_boolean(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    if buf.has_remaining() {
-        match buf.get_i8() {
-            0 => Ok(LuaType::Boolean(false)),
-            _ => Ok(LuaType::Boolean(true)),
-        }
+/// Shares each message among all robots connected to the router. The data of a message is a
+/// single frozen buffer, cloning a message only increments the reference count of this buffer.
+#[derive(Clone)]
+pub struct Relay(broadcast::Sender<Message>);
+
+impl Relay {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(RELAY_CAPACITY);
+        Relay(tx)
     }
-    else {
-        Err(Error::DecodeError)
+
+    pub fn publish(&self, sender: SocketAddr, data: Bytes) {
+        let message = Message { sender, recipient: None, received: Instant::now(), data };
+        /* this only fails if there are no subscribers */
+        let _ = self.0.send(message);
     }
-}
 
-fn decode_lua_table(buf: &mut impl Buf) -> Result<LuaType, Error> {
-    let mut table = Vec::new();
-    while buf.has_remaining() {
-        /* parse the key */
-        let key = match buf.get_i8() {
-            LUA_TBOOLEAN => decode_lua_boolean(buf),
-            LUA_TNUMBER => decode_lua_number(buf),
-            LUA_TSTRING => decode_lua_string(buf),
-            LUA_TUSERDATA => decode_lua_usertype(buf),
-            LUA_TTABLE => decode_lua_table(buf),
-            LUA_TNIL => break,
-            _ => Err(Error::DecodeError),
-        }?;
-        if buf.has_remaining() {
-            /* parse the value */
-            let value = match buf.get_i8() {
-                LUA_TBOOLEAN => decode_lua_boolean(buf),
-                LUA_TNUMBER => decode_lua_number(buf),
-                LUA_TSTRING => decode_lua_string(buf),
-                LUA_TUSERDATA => decode_lua_usertype(buf),
-                LUA_TTABLE => decode_lua_table(buf),
-                _ => Err(Error::DecodeError),
-            }?;
-            table.push((key, value));
-        }
-        else {
-            return Err(Error::DecodeError);
-        }
+    /// Send a message from the supervisor to a single robot or, if recipient is None, to all robots
+    pub fn inject(&self, recipient: Option<IpAddr>, data: Bytes) {
+        let message = Message { sender: supervisor_addr(), recipient, received: Instant::now(), data };
+        let _ = self.0.send(message);
+    }
+
+    pub fn subscribe(&self, addr: SocketAddr) -> Subscription {
+        Subscription { addr, rx: self.0.subscribe() }
     }
-    Ok(LuaType::Table(table))
 }
 
-#[derive(Debug, Default)]
-struct ByteArrayCodec {
-    len: Option<usize>
+impl Default for Relay {
+    fn default() -> Self {
+        Relay::new()
+    }
 }
 
-impl Decoder for ByteArrayCodec {
-    type Item = Bytes;
-    type Error = io::Error;
+pub struct Subscription {
+    addr: SocketAddr,
+    rx: broadcast::Receiver<Message>,
+}
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+impl Subscription {
+    /// Get the next message from another robot, failing when the relay is closed or when the
+    /// subscriber fell so far behind that it missed messages
+    pub async fn next(&mut self) -> Result<Message, broadcast::error::RecvError> {
         loop {
-            if let Some(len) = self.len {
-                if buf.len() >= len {
-                    self.len = None;
-                    return Ok(Some(buf.split_to(len).freeze()));
-                }
-                else {
-                    break;
-                }
-            }
-            else {
-                if buf.len() >= 4 {
-                    self.len = Some(buf.get_u32() as usize);
-                }
-                else {
-                    break;
-                }
+            match self.rx.recv().await? {
+                /* do not send messages to the sending robot */
+                message if message.sender == self.addr => continue,
+                message if message.recipient.map_or(false, |recipient| recipient != self.addr.ip()) => continue,
+                message => break Ok(message),
             }
         }
-        Ok(None)
     }
 }
 
-impl Encoder<Bytes> for ByteArrayCodec {
-    type Error = io::Error;
-
-    fn encode(&mut self, data: Bytes, buf: &mut BytesMut) -> Result<(), io::Error> {
-        buf.reserve(data.len() + size_of::<u32>());
-        buf.put_u32(data.len() as u32);
-        buf.put(data);
-        Ok(())
-    }
-}
-
-type Peers = Arc<Mutex<HashMap<SocketAddr, mpsc::UnboundedSender<Bytes>>>>;
-
 
 async fn client_handler(stream: TcpStream,
                         addr: SocketAddr,
-                        peers: Peers,
-                        journal: mpsc::UnboundedSender<journal::Request>) {
+                        relay: Relay,
+                        journal: journal::Sender,
+                        latency: Arc<LatencyHistogram>) {
     log::info!("Robot {} connected to message router", addr);
-    /* set up a channel for communicating with other robot sockets */
-    let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
-    let rx_stream = UnboundedReceiverStream::new(rx);
-    /* wrap up socket in our ByteArrayCodec */
-    let (sink, mut stream) = Framed::new(stream, ByteArrayCodec::default()).split();
-    
-    {
-        peers.lock().await.insert(addr, tx);
+    /* reduce latency for the small messages sent between robots */
+    if let Err(error) = stream.set_nodelay(true) {
+        log::warn!("Could not disable Nagle's algorithm for robot {}: {}", addr, error);
     }
+    /* subscribe to the messages from other robots, a robot that missed messages is disconnected
+       since its controller would otherwise continue without them */
+    let subscription = relay.subscribe(addr);
+    let subscription = futures::stream::unfold((subscription, journal.clone()), |(mut subscription, journal)| async move {
+        match subscription.next().await {
+            Ok(message) => Some((message, (subscription, journal))),
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                log::error!("Dropping connection to {}: missed {} messages from the message router", addr, count);
+                let event = journal::Event::Dropped(addr.ip(), format!("missed {} messages", count));
+                if let Err(error) = journal.record(event) {
+                    log::error!("Could not record event in journal: {}", error);
+                }
+                None
+            },
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    });
+    /* wrap up socket in our ByteArrayCodec */
+    let (sink, mut stream) =
+        Framed::with_capacity(stream, ByteArrayCodec::default(), BUFFER_CAPACITY).split();
 
     /* send and receive messages concurrently */
-    let mut forward = rx_stream.map(|msg| Ok(msg)).forward(sink);
+    let mut forward = subscription.map(|message| {
+        latency.record(message.received.elapsed());
+        Ok(message.data)
+    }).forward(sink);
 
     loop {
         tokio::select! {
             biased;
             Some(message) = stream.next() => match message {
-                Ok(mut message) => {
-                    for (peer_addr, tx) in peers.lock().await.iter() {
-                        /* do not send messages to the sending robot */   
-                        if peer_addr != &addr {
-                            let _ = tx.send(message.clone());
-                        }
-                    }
-                    if let Ok(decoded) = decode_lua_table(&mut message) {
+                Ok(message) => {
+                    relay.publish(addr, message.clone());
+                    if let Some(decoded) = decode(&message) {
                         let event = journal::Event::Broadcast(addr, decoded);
-                        if let Err(error) = journal.send(journal::Request::Record(event)) {
+                        if let Err(error) = journal.record(event) {
                             log::error!("Could not record event in journal: {}", error);
                         }
                     }
                 },
-                Err(_) => break
+                /* a frame that exceeds the maximum length drops only the connection to this robot */
+                Err(error) => {
+                    if error.kind() == io::ErrorKind::InvalidData {
+                        log::error!("Dropping connection to {}: {}", addr, error);
+                        let event = journal::Event::Dropped(addr.ip(), error.to_string());
+                        if let Err(error) = journal.record(event) {
+                            log::error!("Could not record event in journal: {}", error);
+                        }
+                    }
+                    break
+                }
             },
             _ = &mut forward => break
         }
     }
-    {
-        peers.lock().await.remove(&addr);
-    }
     log::info!("Robot {} disconnected from message router", addr);
 }
 
-pub async fn new(addr: SocketAddr, journal: mpsc::UnboundedSender<journal::Request>) -> io::Result<()> {
+pub async fn new(addr: SocketAddr, journal: journal::Sender, relay: Relay) -> io::Result<()> {
     let listener = TcpListener::bind(addr).await?;
     log::info!("Message router running on: {:?}", listener.local_addr());
-    /* create an atomic map of all peers */
-    let peers = Peers::default();
+    let latency = Arc::new(LatencyHistogram::default());
+    let mut latency_report = tokio::time::interval(LATENCY_REPORT_INTERVAL);
     /* start the main loop */
     loop {
-        match listener.accept().await {
-            Ok((stream, addr)) => {
-                let journal = journal.clone();
-                let peers = Arc::clone(&peers);
-                /* spawn a handler for the newly connected client */
-                tokio::spawn(client_handler(stream, addr, peers, journal));
-            }
-            Err(err) => {
-                log::error!("Error accepting incoming connection: {}", err);
+        tokio::select! {
+            _ = latency_report.tick() => latency.report(),
+            result = listener.accept() => match result {
+                Ok((stream, addr)) => {
+                    let journal = journal.clone();
+                    let relay = relay.clone();
+                    let latency = Arc::clone(&latency);
+                    /* spawn a handler for the newly connected client */
+                    tokio::spawn(client_handler(stream, addr, relay, journal, latency));
+                }
+                Err(err) => {
+                    log::error!("Error accepting incoming connection: {}", err);
+                }
             }
-        }   
+        }
     }
     // Ok(())
 }
+
+/// A connection from the message router to the simulated medium of an ARGoS instance, which
+/// speaks the same protocol as the robots. The messages of the robots are forwarded to the
+/// simulation and the messages of the simulated robots are shared with the robots, so that the
+/// controllers on real robots can interact with simulated neighbors. The connection is
+/// reestablished whenever it is lost until the bridge is dropped.
+pub struct Bridge {
+    pub addr: SocketAddr,
+    connected: Arc<AtomicBool>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Bridge {
+    pub fn new(addr: SocketAddr, relay: Relay, journal: journal::Sender) -> Self {
+        let connected = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(bridge(addr, relay, journal, Arc::clone(&connected)));
+        Bridge { addr, connected, task }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.task.abort();
+        log::info!("Stopped bridging message router to simulation at {}", self.addr);
+    }
+}
+
+async fn bridge(addr: SocketAddr, relay: Relay, journal: journal::Sender, connected: Arc<AtomicBool>) {
+    let latency = Arc::new(LatencyHistogram::default());
+    loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                log::info!("Bridging message router to simulation at {}", addr);
+                connected.store(true, Ordering::Relaxed);
+                /* the simulation is handled like a robot that relays the messages of all simulated robots */
+                client_handler(stream, addr, relay.clone(), journal.clone(), Arc::clone(&latency)).await;
+                connected.store(false, Ordering::Relaxed);
+            },
+            Err(error) => log::warn!("Could not connect to simulation at {}: {}", addr, error),
+        }
+        tokio::time::sleep(BRIDGE_RETRY_DELAY).await;
+    }
+}
+
+/// Run the message router on a dedicated thread with its own runtime so that relaying messages
+/// between robots is not delayed by the rest of the supervisor. The niceness of the thread can
+/// optionally be set, where negative values (higher priority) usually require privileges.
+pub fn spawn(addr: SocketAddr,
+             journal: journal::Sender,
+             relay: Relay,
+             niceness: Option<i32>) -> io::Result<oneshot::Receiver<io::Result<()>>> {
+    let (result_tx, result_rx) = oneshot::channel();
+    std::thread::Builder::new()
+        .name("router".to_owned())
+        .spawn(move || {
+            if let Some(niceness) = niceness {
+                /* on Linux, this only applies to the calling thread */
+                if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } != 0 {
+                    log::warn!("Could not set the niceness of the message router: {}",
+                        io::Error::last_os_error());
+                }
+            }
+            let result = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .and_then(|runtime| runtime.block_on(new(addr, journal, relay)));
+            let _ = result_tx.send(result);
+        })?;
+    Ok(result_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slow_subscribers_lag() {
+        let relay = Relay::new();
+        let sender = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+        let mut subscription = relay.subscribe(SocketAddr::from((Ipv4Addr::LOCALHOST, 2)));
+        for _ in 0..=RELAY_CAPACITY {
+            relay.publish(sender, Bytes::from_static(b"message"));
+        }
+        assert!(matches!(subscription.next().await, Err(broadcast::error::RecvError::Lagged(1))));
+    }
+}
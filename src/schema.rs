@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::router::LuaType;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum FieldType {
+    Number,
+    String,
+    Boolean,
+    Vector2,
+    Vector3,
+    Quaternion,
+    Table,
+}
+
+impl FieldType {
+    fn matches(self, value: &LuaType) -> bool {
+        matches!((self, value),
+            (FieldType::Number, LuaType::Number(_)) |
+            (FieldType::String, LuaType::String(_)) |
+            (FieldType::Boolean, LuaType::Boolean(_)) |
+            (FieldType::Vector2, LuaType::Vector2(..)) |
+            (FieldType::Vector3, LuaType::Vector3(..)) |
+            (FieldType::Quaternion, LuaType::Quaternion(..)) |
+            (FieldType::Table, LuaType::Table(_)))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+}
+
+/// Describes the payload of a message. A message refers to its schema with the string entry
+/// `schema` and optionally the number entry `version`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Schema {
+    pub name: String,
+    #[serde(default)]
+    pub version: u32,
+    pub fields: Vec<Field>,
+}
+
+/// Identifies the schema of a message in the journal
+#[derive(Clone, Debug, Serialize)]
+pub struct Tag {
+    pub schema: String,
+    pub version: u32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(transparent)]
+pub struct Registry(pub Vec<Schema>);
+
+fn entry<'a>(table: &'a [(LuaType, LuaType)], key: &str) -> Option<&'a LuaType> {
+    table.iter()
+        .find(|(entry_key, _)| match entry_key {
+            LuaType::String(entry_key) => entry_key == key,
+            _ => false,
+        })
+        .map(|(_, value)| value)
+}
+
+pub fn format_lua(value: &LuaType) -> String {
+    match value {
+        LuaType::String(value) => format!("{:?}", value),
+        LuaType::Number(value) => format!("{}", value),
+        LuaType::Boolean(value) => format!("{}", value),
+        LuaType::Vector2(x, y) => format!("({}, {})", x, y),
+        LuaType::Vector3(x, y, z) => format!("({}, {}, {})", x, y, z),
+        LuaType::Quaternion(w, x, y, z) => format!("({}, {}, {}, {})", w, x, y, z),
+        LuaType::Table(table) => format!("{{{}}}", table.iter()
+            .map(|(key, value)| format!("{}: {}", format_lua(key), format_lua(value)))
+            .collect::<Vec<_>>()
+            .join(", ")),
+    }
+}
+
+impl Registry {
+    /// Find the schema of a message, using the latest version if the message has no version
+    pub fn identify(&self, message: &LuaType) -> Option<&Schema> {
+        let table = match message {
+            LuaType::Table(table) => table,
+            _ => return None,
+        };
+        let name = match entry(table, "schema") {
+            Some(LuaType::String(name)) => name,
+            _ => return None,
+        };
+        let candidates = self.0.iter().filter(|schema| &schema.name == name);
+        match entry(table, "version") {
+            Some(LuaType::Number(version)) => candidates
+                .find(|schema| schema.version as f64 == *version),
+            _ => candidates.max_by_key(|schema| schema.version),
+        }
+    }
+
+    pub fn tag(&self, message: &LuaType) -> Option<Tag> {
+        self.identify(message).map(|schema| Tag {
+            schema: schema.name.clone(),
+            version: schema.version,
+        })
+    }
+
+    /// Formats the fields of a message in the order given by its schema, marking fields that are
+    /// missing or that have the wrong type
+    pub fn pretty_print(&self, message: &LuaType) -> Option<String> {
+        let schema = self.identify(message)?;
+        let table = match message {
+            LuaType::Table(table) => table,
+            _ => return None,
+        };
+        let fields = schema.fields.iter()
+            .map(|field| match entry(table, &field.name) {
+                Some(value) if field.field_type.matches(value) =>
+                    format!("{}: {}", field.name, format_lua(value)),
+                Some(value) =>
+                    format!("{}: {} (expected {:?})", field.name, format_lua(value), field.field_type),
+                None => format!("{}: missing", field.name),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(format!("{} v{} {{{}}}", schema.name, schema.version, fields))
+    }
+}
@@ -0,0 +1,119 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Arc};
+use openssl::{hash::MessageDigest, pkcs5::pbkdf2_hmac, symm::{self, Cipher}};
+use serde::Deserialize;
+
+/// Environment variable from which the passphrase of the encrypted store is read, e.g., when
+/// running as a service, otherwise the passphrase is prompted for
+const PASSPHRASE_VARIABLE: &str = "MNS_SUPERVISOR_PASSPHRASE";
+/// Environment variable from which the token for Vault is read
+const VAULT_TOKEN_VARIABLE: &str = "VAULT_TOKEN";
+/// Header and iterations of `openssl enc -aes-256-cbc -pbkdf2`
+const ENCRYPTED_HEADER: &[u8] = b"Salted__";
+const PBKDF2_ITERATIONS: usize = 10000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Environment variable {0} is not set")]
+    MissingVariable(String),
+    #[error("Secret {0} is not in the encrypted store")]
+    MissingSecret(String),
+    #[error("Vault has no secret {0} at {1}")]
+    MissingVaultSecret(String, String),
+    #[error("The encrypted store is not in the format of openssl enc")]
+    InvalidStore,
+    #[error("Could not decrypt the store, the passphrase may be wrong")]
+    DecryptError(#[source] openssl::error::ErrorStack),
+
+    #[error(transparent)]
+    OpensslError(#[from] openssl::error::ErrorStack),
+    #[error(transparent)]
+    HttpError(#[from] reqwest::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Where a secret, e.g., a private key or a token, is kept instead of the configuration file
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase", tag = "source")]
+pub enum Secret {
+    /// An environment variable, e.g., injected by systemd or a container runtime
+    Environment {
+        variable: String,
+    },
+    /// A file that only the supervisor can read
+    File {
+        path: PathBuf,
+    },
+    /// An entry of the encrypted store given on the command line
+    Encrypted {
+        name: String,
+    },
+    /// A key of a secret in the KV (version 2) engine of HashiCorp Vault
+    Vault {
+        /// Address of the Vault server, e.g., https://vault.example.com:8200
+        address: String,
+        /// Path of the secret, e.g., secret/data/mns-supervisor
+        path: String,
+        key: String,
+    },
+}
+
+/// The decrypted entries of the encrypted store
+#[derive(Clone, Debug, Default)]
+pub struct Store(Arc<HashMap<String, String>>);
+
+impl Store {
+    /// Decrypts a JSON object of named secrets that was encrypted with
+    /// `openssl enc -aes-256-cbc -pbkdf2 -in secrets.json -out secrets.json.enc`
+    pub fn load(path: &Path) -> Result<Store> {
+        let contents = std::fs::read(path)?;
+        if contents.len() < 16 || !contents.starts_with(ENCRYPTED_HEADER) {
+            return Err(Error::InvalidStore);
+        }
+        let (salt, data) = contents[ENCRYPTED_HEADER.len()..].split_at(8);
+        let passphrase = match std::env::var(PASSPHRASE_VARIABLE) {
+            Ok(passphrase) => passphrase,
+            Err(_) => rpassword::prompt_password_stderr(
+                &format!("Passphrase for {}: ", path.display()))?,
+        };
+        let cipher = Cipher::aes_256_cbc();
+        let mut key_iv = [0u8; 48];
+        pbkdf2_hmac(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS, MessageDigest::sha256(), &mut key_iv)?;
+        let (key, iv) = key_iv.split_at(cipher.key_len());
+        let decrypted = symm::decrypt(cipher, key, Some(iv), data)
+            .map_err(Error::DecryptError)?;
+        Ok(Store(Arc::new(serde_json::from_slice(&decrypted)?)))
+    }
+}
+
+impl Secret {
+    /// Reads the secret, the secret is read each time so that it can be rotated
+    pub async fn resolve(&self, store: &Store) -> Result<Vec<u8>> {
+        match self {
+            Secret::Environment { variable } => std::env::var(variable)
+                .map(String::into_bytes)
+                .map_err(|_| Error::MissingVariable(variable.clone())),
+            Secret::File { path } => Ok(tokio::fs::read(path).await?),
+            Secret::Encrypted { name } => store.0.get(name)
+                .map(|secret| secret.clone().into_bytes())
+                .ok_or_else(|| Error::MissingSecret(name.clone())),
+            Secret::Vault { address, path, key } => {
+                let token = std::env::var(VAULT_TOKEN_VARIABLE)
+                    .map_err(|_| Error::MissingVariable(VAULT_TOKEN_VARIABLE.to_owned()))?;
+                let response: serde_json::Value = reqwest::Client::new()
+                    .get(format!("{}/v1/{}", address.trim_end_matches('/'), path))
+                    .header("X-Vault-Token", token)
+                    .send().await?
+                    .error_for_status()?
+                    .json().await?;
+                response["data"]["data"][key.as_str()].as_str()
+                    .map(|secret| secret.as_bytes().to_vec())
+                    .ok_or_else(|| Error::MissingVaultSecret(key.clone(), path.clone()))
+            }
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use std::{collections::{HashMap, HashSet}, fmt, future::Future, net::{Ipv4Addr, SocketAddr}, path::PathBuf, time::{Duration, Instant, SystemTime}};
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::{net::{TcpListener, TcpStream}, sync::{mpsc, oneshot}};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use uuid::Uuid;
+
+use mns_protocol::fernbedienung as protocol;
+use mns_protocol::router::ByteArrayCodec;
+
+use crate::{arena, clock, config, journal, router};
+use crate::network::fernbedienung;
+
+/// Address on which the mock fernbedienung service listens, the port of the service is fixed so
+/// the mock takes a loopback address of its own
+const MOCK_ADDR: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 42);
+/// Port of the fernbedienung service
+const FERNBEDIENUNG_PORT: u16 = 17653;
+/// Hostname reported by the mock fernbedienung service
+pub const MOCK_HOSTNAME: &str = "mns-selftest";
+/// Time given to each step of the self test
+const STEP_TIMEOUT: Duration = Duration::from_secs(15);
+/// Time given to discovering the robot, which may require the network to be probed first
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Size of the file that is uploaded to the robot and read back
+const PAYLOAD_SIZE: usize = 64 << 10;
+/// Number of lines that are echoed by the process on the robot
+const STREAM_LINES: usize = 8;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Timed out")]
+    Timeout,
+    #[error("Expected hostname {expected} but found {found}")]
+    UnexpectedHostname {
+        expected: String,
+        found: String,
+    },
+    #[error("{0}")]
+    Mismatch(&'static str),
+    #[error("Could not send request")]
+    RequestError,
+    #[error("Did not receive response")]
+    ResponseError,
+    #[error(transparent)]
+    FernbedienungError(#[from] fernbedienung::Error),
+    #[error(transparent)]
+    JournalError(#[from] journal::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The stack is either tested against a mock fernbedienung service or a robot with the given
+/// hostname
+#[derive(Clone, Debug)]
+pub enum Target {
+    Mock,
+    Robot(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Mock => write!(f, "mock fernbedienung service"),
+            Target::Robot(hostname) => write!(f, "{}", hostname),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum Outcome {
+    Passed(String),
+    Failed(String),
+    /* a step is skipped if a step that it depends on failed */
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub target: String,
+    pub started: SystemTime,
+    pub steps: Vec<Step>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| matches!(step.outcome, Outcome::Passed(_)))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Self test against {}", self.target)?;
+        for step in &self.steps {
+            let (result, detail) = match &step.outcome {
+                Outcome::Passed(detail) => ("PASSED", detail.as_str()),
+                Outcome::Failed(error) => ("FAILED", error.as_str()),
+                Outcome::Skipped => ("SKIPPED", ""),
+            };
+            writeln!(f, "{:<12} {:<8} {:>8.3}s  {}", step.name, result, step.duration.as_secs_f64(), detail)?;
+        }
+        write!(f, "{}", match self.passed() {
+            true => "All steps passed",
+            false => "Self test failed",
+        })
+    }
+}
+
+pub enum Request {
+    /* run a self test, the report is sent to the callback once the test has finished */
+    Run(Target, Option<oneshot::Sender<Report>>),
+    /* the report of the last self test and whether a self test is running */
+    Report(oneshot::Sender<(Option<Report>, bool)>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+pub async fn new(mut requests_rx: Receiver,
+                 arena_requests_tx: mpsc::UnboundedSender<arena::Request>,
+                 config: config::Receiver) {
+    let mut last: Option<Report> = None;
+    let mut callbacks: Vec<oneshot::Sender<Report>> = Vec::new();
+    let mut running: futures::future::BoxFuture<'static, Report> = futures::future::pending().boxed();
+    let mut is_running = false;
+    loop {
+        tokio::select! {
+            request = requests_rx.recv() => match request {
+                Some(Request::Run(target, callback)) => {
+                    callbacks.extend(callback);
+                    /* the self tests share the address of the mock and are run one at a time */
+                    match is_running {
+                        true => log::warn!("A self test is already running"),
+                        false => {
+                            log::info!("Starting self test against {}", target);
+                            running = run(target, arena_requests_tx.clone(), config.clone()).boxed();
+                            is_running = true;
+                        }
+                    }
+                },
+                Some(Request::Report(callback)) => {
+                    if let Err(_) = callback.send((last.clone(), is_running)) {
+                        log::error!("Could not respond with the self test report");
+                    }
+                },
+                None => break,
+            },
+            report = &mut running => {
+                match report.passed() {
+                    true => log::info!("Self test against {} passed", report.target),
+                    false => log::warn!("Self test against {} failed", report.target),
+                }
+                for callback in callbacks.drain(..) {
+                    let _ = callback.send(report.clone());
+                }
+                last = Some(report);
+                running = futures::future::pending().boxed();
+                is_running = false;
+            }
+        }
+    }
+}
+
+/* runs a step within a timeout and records its outcome, returning its value if it passed */
+async fn step<T, F>(steps: &mut Vec<Step>, name: &'static str, timeout: Duration, future: F) -> Option<T>
+    where F: Future<Output = Result<(T, String)>> {
+    let start = Instant::now();
+    let (value, outcome) = match tokio::time::timeout(timeout, future).await {
+        Ok(Ok((value, detail))) => (Some(value), Outcome::Passed(detail)),
+        Ok(Err(error)) => (None, Outcome::Failed(error.to_string())),
+        Err(_) => (None, Outcome::Failed(Error::Timeout.to_string())),
+    };
+    steps.push(Step { name, outcome, duration: start.elapsed() });
+    value
+}
+
+fn skip(steps: &mut Vec<Step>, names: &[&'static str]) {
+    steps.extend(names.iter().map(|&name| Step { name, outcome: Outcome::Skipped, duration: Duration::default() }));
+}
+
+async fn run(target: Target,
+             arena_requests_tx: mpsc::UnboundedSender<arena::Request>,
+             config: config::Receiver) -> Report {
+    let started = SystemTime::now();
+    let mut steps = Vec::new();
+    let (tls, timeouts, router_addr) = {
+        let config = config.borrow();
+        (config.fernbedienung_tls.clone(), config.fernbedienung_timeouts.clone(), config.router_address)
+    };
+    /* find the robot or start the mock fernbedienung service */
+    let mut mock = None;
+    let discovery = step(&mut steps, "Discovery", DISCOVERY_TIMEOUT, async {
+        match &target {
+            Target::Mock => {
+                let listener = TcpListener::bind((MOCK_ADDR, FERNBEDIENUNG_PORT)).await?;
+                mock = Some(tokio::spawn(serve_mock(listener)));
+                Ok::<_, Error>((MOCK_ADDR, format!("Mock service listening on {}", MOCK_ADDR)))
+            },
+            Target::Robot(hostname) => loop {
+                let (callback_tx, callback_rx) = oneshot::channel();
+                arena_requests_tx.send(arena::Request::FindRobot(hostname.clone(), callback_tx))
+                    .map_err(|_| Error::RequestError)?;
+                match callback_rx.await.map_err(|_| Error::ResponseError)? {
+                    Some(addr) => break Ok((addr, format!("Found {} at {}", hostname, addr))),
+                    /* the robot may not have been discovered yet */
+                    None => tokio::time::sleep(Duration::from_secs(1)).await,
+                }
+            },
+        }
+    }).await;
+    /* connect to the fernbedienung service */
+    let device = match discovery {
+        Some(addr) => step(&mut steps, "Association", STEP_TIMEOUT, async {
+            /* the connection is separate from the one of the arena and is not recorded */
+            let (return_addr_tx, _) = mpsc::unbounded_channel();
+            let (journal, _) = journal::new();
+            let (tls, expected) = match &target {
+                Target::Mock => (None, MOCK_HOSTNAME),
+                Target::Robot(hostname) => (tls, hostname.as_str()),
+            };
+            let device = fernbedienung::Device::new(addr, return_addr_tx, journal, tls, None, timeouts).await?;
+            let found = device.hostname().await?;
+            match found == expected {
+                true => Ok((device, format!("Connected to {}", found))),
+                false => Err(Error::UnexpectedHostname { expected: expected.to_owned(), found }),
+            }
+        }).await,
+        None => {
+            skip(&mut steps, &["Association"]);
+            None
+        }
+    };
+    match device {
+        Some(device) => {
+            let filename = PathBuf::from(format!("mns-selftest-{}", Uuid::new_v4()));
+            let path = PathBuf::from("/tmp").join(&filename);
+            let payload: Vec<u8> = (0..PAYLOAD_SIZE).map(|_| rand::random()).collect();
+            let uploaded = step(&mut steps, "Upload", STEP_TIMEOUT, async {
+                device.upload("/tmp".into(), filename.clone(), payload.clone()).await?;
+                Ok::<_, Error>(((), format!("Uploaded and verified {} bytes", payload.len())))
+            }).await;
+            match uploaded {
+                Some(_) => {
+                    step(&mut steps, "Run", STEP_TIMEOUT, read_back(&device, &path, &payload)).await;
+                },
+                None => skip(&mut steps, &["Run"]),
+            }
+            step(&mut steps, "Stream", STEP_TIMEOUT, echo(&device)).await;
+            if uploaded.is_some() {
+                if let Err(error) = device.remove(path.clone(), false).await {
+                    log::warn!("Could not remove {} after the self test: {}", path.display(), error);
+                }
+            }
+        },
+        None => skip(&mut steps, &["Upload", "Run", "Stream"]),
+    }
+    if let Some(mock) = mock {
+        mock.abort();
+    }
+    step(&mut steps, "Router", STEP_TIMEOUT, router_echo(router_addr)).await;
+    step(&mut steps, "Journal", STEP_TIMEOUT, journal_write()).await;
+    Report { target: target.to_string(), started, steps }
+}
+
+/* reads the uploaded file back with cat */
+async fn read_back(device: &fernbedienung::Device, path: &PathBuf, payload: &[u8]) -> Result<((), String)> {
+    let process = protocol::process::Process {
+        target: "cat".into(),
+        working_dir: None,
+        args: vec!["--".to_owned(), path.to_string_lossy().into_owned()],
+        ..Default::default()
+    };
+    let (stdout_tx, mut stdout_rx) = mpsc::unbounded_channel();
+    device.run(process, None, None, Some(stdout_tx), None).await?;
+    let mut output = Vec::new();
+    while let Some(chunk) = stdout_rx.recv().await {
+        output.extend_from_slice(&chunk);
+    }
+    match output == payload {
+        true => Ok(((), format!("Read back {} bytes", output.len()))),
+        false => Err(Error::Mismatch("The output of the process does not match the uploaded file")),
+    }
+}
+
+/* streams lines through cat and terminates it */
+async fn echo(device: &fernbedienung::Device) -> Result<((), String)> {
+    let process = protocol::process::Process {
+        target: "cat".into(),
+        working_dir: None,
+        args: vec![],
+        ..Default::default()
+    };
+    let (stdin_tx, stdin_rx) = mpsc::unbounded_channel();
+    let (stdout_tx, mut stdout_rx) = mpsc::unbounded_channel();
+    let (terminate_tx, terminate_rx) = oneshot::channel();
+    let input = (0..STREAM_LINES)
+        .map(|line| format!("mns-selftest {}\n", line))
+        .collect::<String>();
+    let exchange = async {
+        let mut output = Vec::new();
+        for line in input.split_inclusive('\n') {
+            stdin_tx.send(BytesMut::from(line)).map_err(|_| Error::RequestError)?;
+        }
+        while output.len() < input.len() {
+            match stdout_rx.recv().await {
+                Some(chunk) => output.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        let _ = terminate_tx.send(());
+        Ok::<_, Error>(output)
+    };
+    let (result, output) = tokio::join!(
+        device.run(process, Some(terminate_rx), Some(stdin_rx), Some(stdout_tx), None),
+        exchange
+    );
+    match result {
+        /* the process terminates abnormally since it is terminated */
+        Ok(_) | Err(fernbedienung::Error::AbnormalTerminationError(_)) => {},
+        Err(error) => return Err(error.into()),
+    }
+    match output? == input.as_bytes() {
+        true => Ok(((), format!("Echoed {} lines and terminated the process", STREAM_LINES))),
+        false => Err(Error::Mismatch("The process did not echo its standard input")),
+    }
+}
+
+/* relays a message between two connections to the message router */
+async fn router_echo(addr: SocketAddr) -> Result<((), String)> {
+    let addr = match addr.ip().is_unspecified() {
+        true => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port()),
+        false => addr,
+    };
+    let mut sender = Framed::new(TcpStream::connect(addr).await?, ByteArrayCodec::default());
+    let mut receiver = Framed::new(TcpStream::connect(addr).await?, ByteArrayCodec::default());
+    /* the message is a table that the controllers on the robots can decode and ignore */
+    let mut message = BytesMut::new();
+    let table = [(router::LuaType::String("selftest".to_owned()), router::LuaType::String(Uuid::new_v4().to_string()))];
+    router::encode_lua_table(&table, &mut message);
+    let message = message.freeze();
+    /* the router may not have subscribed the receiver yet, the message is resent until it arrives */
+    let start = Instant::now();
+    let mut resend = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        tokio::select! {
+            _ = resend.tick() => sender.send(message.clone()).await?,
+            received = receiver.next() => match received {
+                Some(Ok(received)) if received == message =>
+                    break Ok(((), format!("Relayed by {} in {} ms", addr, start.elapsed().as_millis()))),
+                /* messages from the robots */
+                Some(Ok(_)) => {},
+                Some(Err(error)) => break Err(error.into()),
+                None => break Err(Error::ResponseError),
+            }
+        }
+    }
+}
+
+/* starts a separate journal, records an event, and removes the journal */
+async fn journal_write() -> Result<((), String)> {
+    let (journal, journal_task) = journal::new();
+    let journal_task = tokio::spawn(journal_task);
+    let path = journal.start(Default::default(), None, std::env::temp_dir(), clock::Run::start(), clock::Epoch::Run).await?;
+    let result = async {
+        let event = journal::Event::Environment("selftest".to_owned(), serde_json::Value::Bool(true));
+        journal.record(event)?;
+        /* wait until the event has been written before stopping the journal */
+        while journal.status().pending > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        journal.stop();
+        drop(journal);
+        journal_task.await.map_err(|_| Error::ResponseError)??;
+        let length = tokio::fs::metadata(&path).await?.len();
+        match length > 0 {
+            true => Ok(((), format!("Wrote {} bytes to {}", length, path.display()))),
+            false => Err(Error::Mismatch("The journal is empty")),
+        }
+    }.await;
+    if let Err(error) = tokio::fs::remove_file(&path).await {
+        log::warn!("Could not remove {} after the self test: {}", path.display(), error);
+    }
+    result
+}
+
+/// Exit status reported by the mock fernbedienung service for a process that succeeded
+const EXITED: protocol::process::ExitStatus = protocol::process::ExitStatus { success: true, code: Some(0), signal: None };
+/// Exit status reported by the mock for a process that failed
+const FAILED: protocol::process::ExitStatus = protocol::process::ExitStatus { success: false, code: Some(1), signal: None };
+/// Exit status reported by the mock for a process that was terminated
+const TERMINATED: protocol::process::ExitStatus = protocol::process::ExitStatus { success: false, code: None, signal: Some(15) };
+
+/* a fernbedienung service that supports the requests used by the self test, the files are kept
+   in memory. The tests of the supervisor also connect to it */
+pub async fn serve_mock(listener: TcpListener) {
+    let mut files: HashMap<PathBuf, Vec<u8>> = Default::default();
+    while let Ok((stream, _)) = listener.accept().await {
+        if let Err(error) = mock_connection(stream, &mut files).await {
+            log::warn!("Mock fernbedienung service: {}", error);
+        }
+    }
+}
+
+async fn mock_connection(stream: TcpStream, files: &mut HashMap<PathBuf, Vec<u8>>) -> std::io::Result<()> {
+    let mut framed = Framed::new(stream, LengthDelimitedCodec::new());
+    /* the processes that echo their standard input */
+    let mut echoing: HashSet<Uuid> = Default::default();
+    while let Some(frame) = framed.next().await {
+        let protocol::Request(uuid, request) = serde_json::from_slice(&frame?)?;
+        let responses = match request {
+            protocol::RequestKind::Ping => vec![protocol::ResponseKind::Ok],
+            protocol::RequestKind::Upload(upload) => {
+                let checksum = fernbedienung::hex(&openssl::sha::sha256(&upload.contents));
+                files.insert(upload.path.join(upload.filename), upload.contents);
+                vec![protocol::ResponseKind::Upload(protocol::upload::Response::Written(checksum))]
+            },
+            protocol::RequestKind::Files(protocol::files::Request::Remove { path, .. }) => {
+                files.remove(&path);
+                vec![protocol::ResponseKind::Ok]
+            },
+            protocol::RequestKind::Process(protocol::process::Request::Run(process)) =>
+                match (process.target.to_str(), process.args.last()) {
+                    (Some("hostname"), _) => vec![
+                        protocol::ResponseKind::Process(protocol::process::Response::StandardOutput(
+                            BytesMut::from(format!("{}\n", MOCK_HOSTNAME).as_str()))),
+                        protocol::ResponseKind::Process(protocol::process::Response::Terminated(EXITED)),
+                    ],
+                    (Some("cat"), Some(path)) => match files.get(&PathBuf::from(path)) {
+                        Some(contents) => vec![
+                            protocol::ResponseKind::Process(protocol::process::Response::StandardOutput(
+                                BytesMut::from(&contents[..]))),
+                            protocol::ResponseKind::Process(protocol::process::Response::Terminated(EXITED)),
+                        ],
+                        None => vec![
+                            protocol::ResponseKind::Process(protocol::process::Response::Terminated(FAILED)),
+                        ],
+                    },
+                    (Some("cat"), None) => {
+                        echoing.insert(uuid);
+                        vec![]
+                    },
+                    _ => vec![protocol::ResponseKind::Error("Not supported by the mock".to_owned())],
+                },
+            protocol::RequestKind::Process(protocol::process::Request::StandardInput(data)) if echoing.contains(&uuid) =>
+                vec![protocol::ResponseKind::Process(protocol::process::Response::StandardOutput(data))],
+            protocol::RequestKind::Process(protocol::process::Request::Terminate) if echoing.remove(&uuid) =>
+                vec![protocol::ResponseKind::Process(protocol::process::Response::Terminated(TERMINATED))],
+            _ => vec![protocol::ResponseKind::Error("Not supported by the mock".to_owned())],
+        };
+        for response in responses {
+            let response = serde_json::to_vec(&protocol::Response(Some(uuid), response))?;
+            framed.send(Bytes::from(response)).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::{Ipv4Addr, SocketAddr}, time::{Duration, Instant}};
+use tokio::{net::UdpSocket, sync::{mpsc, oneshot}};
+use uuid::Uuid;
+
+use crate::config;
+use crate::optitrack;
+
+/// Size of the buffer for the acknowledgements from the robots
+const ACKNOWLEDGEMENT_CAPACITY: usize = 256;
+
+/// How the frames of the virtual sensing feed are distributed to the robots
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Mode {
+    /// A datagram is sent to each robot
+    Unicast,
+    /// A single datagram is sent to the multicast group
+    Multicast,
+    /// A single datagram is sent to the broadcast address
+    Broadcast,
+}
+
+/// Settings for distributing the filtered poses of the rigid bodies to the robots
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub mode: Mode,
+    /// Group to which the frames are sent in the multicast mode
+    pub group: Ipv4Addr,
+    /// Address to which the frames are sent in the broadcast mode
+    pub broadcast: Ipv4Addr,
+    /// Port on the robots to which the frames are sent
+    pub port: u16,
+    /// Time to live of the multicast datagrams, i.e., the number of routers they may cross
+    pub ttl: u32,
+    /// Frames per second that are sent to the robots
+    pub rate: f64,
+    /// Seconds without an acknowledgement after which a robot is sent the frames by unicast
+    pub fallback_after: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            mode: Mode::Multicast,
+            group: Ipv4Addr::new(239,255,42,100),
+            broadcast: Ipv4Addr::BROADCAST,
+            port: 4960,
+            ttl: 1,
+            rate: 10.0,
+            fallback_after: 3.0,
+        }
+    }
+}
+
+/// A frame of the feed, each robot picks out the poses that it needs
+#[derive(Serialize)]
+struct Frame<'p> {
+    sequence: u64,
+    poses: &'p [optitrack::Pose],
+}
+
+/// The acknowledgement that a robot periodically sends back with the last sequence number that
+/// it received
+#[derive(Deserialize)]
+struct Acknowledgement {
+    sequence: u64,
+}
+
+/// How the frames are delivered to a robot
+#[derive(Clone, Debug, Serialize)]
+pub struct Delivery {
+    pub addr: Ipv4Addr,
+    /// The robot is sent the frames by unicast since it did not acknowledge the shared frames
+    pub fallback: bool,
+    /// The last sequence number acknowledged by the robot
+    pub acknowledged: Option<u64>,
+}
+
+/// The state of the feed
+#[derive(Clone, Debug, Serialize)]
+pub struct Status {
+    /// The mode or None if the feed is disabled
+    pub mode: Option<Mode>,
+    pub sequence: u64,
+    pub robots: HashMap<Uuid, Delivery>,
+}
+
+pub enum Request {
+    /* send the frames to a robot at an address */
+    Subscribe(Uuid, Ipv4Addr),
+    Unsubscribe(Uuid),
+    Status(oneshot::Sender<Status>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+pub type Receiver = mpsc::UnboundedReceiver<Request>;
+
+struct Subscriber {
+    addr: Ipv4Addr,
+    subscribed: Instant,
+    fallback: bool,
+    acknowledged: Option<(u64, Instant)>,
+}
+
+impl Subscriber {
+    /* a robot that has not acknowledged a frame within the timeout can not receive the shared
+       frames, e.g., since the multicast group is not routed to its network */
+    fn missing(&self, now: Instant, timeout: Duration) -> bool {
+        let last = self.acknowledged.map_or(self.subscribed, |(_, at)| at);
+        now.saturating_duration_since(last) > timeout
+    }
+}
+
+async fn bind(settings: &Settings) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    match settings.mode {
+        Mode::Multicast => {
+            socket.set_multicast_ttl_v4(settings.ttl)?;
+            socket.set_multicast_loop_v4(false)?;
+        },
+        Mode::Broadcast => socket.set_broadcast(true)?,
+        Mode::Unicast => {},
+    }
+    Ok(socket)
+}
+
+/// Distributes the filtered poses of the rigid bodies to the robots for virtual sensing
+pub async fn new(mut requests_rx: Receiver,
+                 mut config: config::Receiver,
+                 tracking_requests_tx: optitrack::Sender) {
+    let mut subscribers: HashMap<Uuid, Subscriber> = HashMap::new();
+    let mut sequence = 0u64;
+    let mut buffer = [0u8; ACKNOWLEDGEMENT_CAPACITY];
+    /* rebind the socket whenever the settings change */
+    'settings: loop {
+        let settings = config.borrow().virtual_sensing.clone();
+        let socket = match settings.as_ref() {
+            Some(settings) => match bind(settings).await {
+                Ok(socket) => Some(socket),
+                Err(error) => {
+                    log::error!("Could not create the socket for virtual sensing: {}", error);
+                    None
+                }
+            },
+            None => None,
+        };
+        /* robots are given another chance to receive the shared frames */
+        let now = Instant::now();
+        for subscriber in subscribers.values_mut() {
+            subscriber.subscribed = now;
+            subscriber.fallback = false;
+            subscriber.acknowledged = None;
+        }
+        let period = settings.as_ref()
+            .map_or(1.0, |settings| 1.0 / settings.rate.max(0.1));
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(period));
+        loop {
+            tokio::select! {
+                changed = config.changed() => match changed {
+                    Ok(_) => if config.borrow().virtual_sensing != settings {
+                        continue 'settings;
+                    },
+                    Err(_) => break 'settings,
+                },
+                request = requests_rx.recv() => match request {
+                    Some(Request::Subscribe(robot, addr)) => {
+                        subscribers.insert(robot, Subscriber {
+                            addr,
+                            subscribed: Instant::now(),
+                            fallback: false,
+                            acknowledged: None,
+                        });
+                    },
+                    Some(Request::Unsubscribe(robot)) => {
+                        subscribers.remove(&robot);
+                    },
+                    Some(Request::Status(callback)) => {
+                        let status = Status {
+                            mode: settings.as_ref().map(|settings| settings.mode),
+                            sequence,
+                            robots: subscribers.iter()
+                                .map(|(robot, subscriber)| (*robot, Delivery {
+                                    addr: subscriber.addr,
+                                    fallback: subscriber.fallback,
+                                    acknowledged: subscriber.acknowledged.map(|(sequence, _)| sequence),
+                                }))
+                                .collect(),
+                        };
+                        if let Err(_) = callback.send(status) {
+                            log::error!("Could not respond with the status of virtual sensing");
+                        }
+                    },
+                    None => break 'settings,
+                },
+                Some(result) = async {
+                    match socket.as_ref() {
+                        Some(socket) => Some(socket.recv_from(&mut buffer).await),
+                        None => futures::future::pending().await,
+                    }
+                } => match result {
+                    Ok((length, SocketAddr::V4(source))) => {
+                        let acknowledgement = serde_json::from_slice::<Acknowledgement>(&buffer[..length]);
+                        match acknowledgement {
+                            Ok(Acknowledgement { sequence }) => {
+                                let subscriber = subscribers.values_mut()
+                                    .find(|subscriber| subscriber.addr == *source.ip());
+                                if let Some(subscriber) = subscriber {
+                                    subscriber.acknowledged = Some((sequence, Instant::now()));
+                                }
+                            },
+                            Err(error) => log::debug!("Invalid acknowledgement from {}: {}", source, error),
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(error) => log::warn!("Could not receive acknowledgement for virtual sensing: {}", error),
+                },
+                _ = interval.tick() => {
+                    let (socket, settings) = match (socket.as_ref(), settings.as_ref()) {
+                        (Some(socket), Some(settings)) => (socket, settings),
+                        _ => continue,
+                    };
+                    if subscribers.is_empty() {
+                        continue;
+                    }
+                    let (callback_tx, callback_rx) = oneshot::channel();
+                    let request = optitrack::Request::Frame(optitrack::Stream::Filtered, callback_tx);
+                    if let Err(_) = tracking_requests_tx.send(request) {
+                        break 'settings;
+                    }
+                    let poses = match callback_rx.await {
+                        Ok(poses) => poses,
+                        Err(_) => continue,
+                    };
+                    sequence += 1;
+                    let frame = match serde_json::to_vec(&Frame { sequence, poses: &poses }) {
+                        Ok(frame) => frame,
+                        Err(error) => {
+                            log::error!("Could not encode frame for virtual sensing: {}", error);
+                            continue;
+                        }
+                    };
+                    /* robots that stop acknowledging the shared frames fall back to unicast */
+                    let now = Instant::now();
+                    let timeout = Duration::from_secs_f64(settings.fallback_after.max(period));
+                    for (robot, subscriber) in subscribers.iter_mut() {
+                        if settings.mode != Mode::Unicast && !subscriber.fallback && subscriber.missing(now, timeout) {
+                            log::warn!("Robot {} did not acknowledge the {:?} frames for virtual sensing, falling back to unicast",
+                                robot, settings.mode);
+                            subscriber.fallback = true;
+                        }
+                    }
+                    let shared = match settings.mode {
+                        Mode::Unicast => None,
+                        Mode::Multicast => Some(settings.group),
+                        Mode::Broadcast => Some(settings.broadcast),
+                    };
+                    let unicast = subscribers.values()
+                        .filter(|subscriber| shared.is_none() || subscriber.fallback)
+                        .map(|subscriber| subscriber.addr);
+                    for addr in shared.into_iter().chain(unicast) {
+                        if let Err(error) = socket.send_to(&frame, (addr, settings.port)).await {
+                            log::warn!("Could not send frame for virtual sensing to {}: {}", addr, error);
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
 
+use std::collections::HashMap;
+use openssl::{pkey::{Id, PKey}, sign::Verifier};
 use serde::{Deserialize, Serialize};
+use itertools::Itertools;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Action {
@@ -23,14 +26,75 @@ pub enum Error {
 
     #[error("Configuration file was not valid XML")]
     ParseError(#[from] roxmltree::Error),
+
+    #[error("The software is not signed, {} is missing", SIGNATURE_FILENAME)]
+    MissingSignature,
+
+    #[error("The software is not signed by an authorized key")]
+    UntrustedSignature,
+
+    #[error("The key of signer {0} is not a base64 encoded Ed25519 public key")]
+    InvalidKey(String),
+
+    #[error(transparent)]
+    OpensslError(#[from] openssl::error::ErrorStack),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub type Checksums = Vec<(String, md5::Digest)>;
 
-#[derive(Clone, Default, Debug)]
-pub struct Software(pub Vec<(String, Vec<u8>)>);
+/// Architectures with which ARGoS plugins are tagged, e.g., `libmy_sensor.armv7l.so`, other
+/// shared libraries are uploaded with the controller
+const PLUGIN_ARCHITECTURES: &[&str] = &["armv6l", "armv7l", "aarch64", "x86_64"];
+
+/// File of a software bundle that holds the base64 encoded Ed25519 signature of its manifest
+pub const SIGNATURE_FILENAME: &str = "bundle.sig";
+
+/// File of a software bundle that lists the results of a run, one pattern per line relative to
+/// the working directory of ARGoS, e.g., `output/*.csv`. Lines starting with `#` are ignored.
+pub const RESULTS_FILENAME: &str = "results.txt";
+
+/* splits a plugin into its name and the architecture with which it is tagged */
+fn plugin(filename: &str) -> Option<(&str, &str)> {
+    filename.strip_suffix(".so")
+        .and_then(|stem| stem.rsplit_once('.'))
+        .filter(|(_, tag)| PLUGIN_ARCHITECTURES.contains(tag))
+}
+
+/// Keys that are authorized to sign software, when given, only software signed by one of these
+/// keys can be deployed to the robots
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Signing {
+    /// Base64 encoded Ed25519 public keys (the raw 32 bytes) by the identity of the signer
+    pub keys: HashMap<String, String>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Software(#[serde(with = "base64_contents")] pub Vec<(String, Vec<u8>)>);
+
+/* the contents of the files are encoded as base64 when the software is serialized, e.g., when it
+   is replicated to a standby supervisor */
+mod base64_contents {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(files: &[(String, Vec<u8>)], serializer: S) -> Result<S::Ok, S::Error> {
+        files.iter()
+            .map(|(filename, contents)| (filename, base64::encode(contents)))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, Vec<u8>)>, D::Error> {
+        Vec::<(String, String)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(filename, contents)| base64::decode(contents)
+                .map(|contents| (filename, contents))
+                .map_err(D::Error::custom))
+            .collect()
+    }
+}
 
 impl Software {
     pub fn add<F: Into<String>, C: Into<Vec<u8>>>(&mut self, new_filename: F, new_contents: C) {
@@ -49,12 +113,111 @@ impl Software {
         self.0.clear();
     }
 
+    /// Bytes of all files
+    pub fn size(&self) -> u64 {
+        self.0.iter().map(|(_, contents)| contents.len() as u64).sum()
+    }
+
+    /// Bytes of all files after adding or replacing a file
+    pub fn size_with(&self, filename: &str, size: usize) -> u64 {
+        let replaced = self.0.iter()
+            .find(|(existing, _)| existing == filename)
+            .map_or(0, |(_, contents)| contents.len() as u64);
+        self.size() - replaced + size as u64
+    }
+
     pub fn checksums(&self) -> Checksums {
         self.0.iter()
             .map(|(filename, data)| (filename.clone(), md5::compute(data)))
             .collect()
     }
 
+    /// Identifies the version of the controller, i.e., changes if any file of the controller changes
+    pub fn version(&self) -> String {
+        let mut context = md5::Context::new();
+        for (filename, contents) in self.controller().iter().sorted_by(|left, right| left.0.cmp(&right.0)) {
+            context.consume(filename.as_bytes());
+            context.consume(contents);
+        }
+        format!("{:x}", context.compute())
+    }
+
+    /// The signed description of the software: for each file except the signature, sorted by
+    /// filename, a line with the filename followed by a line with the SHA-256 of its contents in
+    /// hex, i.e., what `export LC_ALL=C; for file in *; do echo $file; sha256sum < $file | cut -d' ' -f1; done` prints
+    pub fn manifest(&self) -> String {
+        self.0.iter()
+            .filter(|(filename, _)| filename != SIGNATURE_FILENAME)
+            .sorted_by(|left, right| left.0.cmp(&right.0))
+            .map(|(filename, contents)| {
+                let digest = openssl::sha::sha256(contents).iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>();
+                format!("{}\n{}\n", filename, digest)
+            })
+            .collect()
+    }
+
+    /// Verifies the signature of the manifest and returns the identity of the signer
+    pub fn verify(&self, signing: &Signing) -> Result<String> {
+        let signature = self.0.iter()
+            .find(|(filename, _)| filename == SIGNATURE_FILENAME)
+            .ok_or(Error::MissingSignature)?;
+        let signature = std::str::from_utf8(&signature.1)
+            .ok()
+            .and_then(|signature| base64::decode(signature.trim()).ok())
+            .ok_or(Error::UntrustedSignature)?;
+        let manifest = self.manifest();
+        for (identity, key) in signing.keys.iter().sorted() {
+            /* a malformed key does not prevent the other signers from being trusted */
+            let key = match base64::decode(key.trim()).ok()
+                .and_then(|key| PKey::public_key_from_raw_bytes(&key, Id::ED25519).ok()) {
+                Some(key) => key,
+                None => {
+                    log::warn!("{}", Error::InvalidKey(identity.clone()));
+                    continue;
+                }
+            };
+            let mut verifier = Verifier::new_without_digest(&key)?;
+            if verifier.verify_oneshot(&signature, manifest.as_bytes()).unwrap_or(false) {
+                return Ok(identity.clone());
+            }
+        }
+        Err(Error::UntrustedSignature)
+    }
+
+    /// Patterns of the files that are collected from the robots after a run
+    pub fn result_patterns(&self) -> Vec<String> {
+        self.0.iter()
+            .find(|(filename, _)| filename == RESULTS_FILENAME)
+            .map(|(_, contents)| String::from_utf8_lossy(contents)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(ToOwned::to_owned)
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Files that are uploaded to the working directory of ARGoS (i.e., everything except plugins)
+    pub fn controller(&self) -> Vec<(String, Vec<u8>)> {
+        self.0.iter()
+            .filter(|(filename, _)| plugin(filename).is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// ARGoS plugins that should be installed on a robot with the given architecture. The
+    /// plugins are renamed so that the tag of the architecture is removed.
+    pub fn plugins(&self, architecture: &str) -> Vec<(String, Vec<u8>)> {
+        self.0.iter()
+            .filter_map(|(filename, contents)| match plugin(filename) {
+                Some((name, tag)) if tag == architecture => Some((format!("{}.so", name), contents.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn argos_config(&self) -> Result<&(String, Vec<u8>)> {
         let config = self.0.iter()
             .filter(|entry| {
@@ -0,0 +1,103 @@
+use std::{convert::Infallible, path::{Component, Path, PathBuf}, time::Duration};
+use bytes::BytesMut;
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::arena;
+
+/// Lines streamed per second if the rate is not given
+const DEFAULT_RATE: usize = 20;
+/// Upper bound on the lines streamed per second
+const MAX_RATE: usize = 200;
+/// Lines preceding the end of the file that are included when the stream starts
+const DEFAULT_LINES: usize = 10;
+/// Longer lines are split so that a file without line breaks cannot exhaust the memory
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+/// Lines waiting to be sent to a client that is reading slowly
+const BODY_CAPACITY: usize = 64;
+
+/// Query of the stream, e.g., /tail/<uuid>?path=output.csv&rate=10
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    /// Path of the file relative to the working directory of ARGoS on the robot
+    path: PathBuf,
+    /// Lines streamed per second, the lines beyond this rate are skipped
+    rate: Option<usize>,
+    /// Lines preceding the end of the file that are streamed first
+    lines: Option<usize>,
+}
+
+/* only files written by the controller can be followed */
+fn is_within_working_dir(path: &Path) -> bool {
+    path.components().any(|component| matches!(component, Component::Normal(_))) &&
+        path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Follows a file on a robot like `tail -F` as plain text, e.g., for checking that a controller is
+/// writing its output during a run. Lines that exceed the rate are skipped and counted.
+pub async fn stream(arena_request_tx: mpsc::UnboundedSender<arena::Request>, uuid: Uuid, query: Query)
+    -> Result<impl warp::Reply, Infallible> {
+    if !is_within_working_dir(&query.path) {
+        return Ok(warp::http::Response::builder()
+            .status(warp::http::StatusCode::BAD_REQUEST)
+            .body(warp::hyper::Body::from("The path must be relative to the working directory of ARGoS\n")));
+    }
+    let rate = query.rate.unwrap_or(DEFAULT_RATE).min(MAX_RATE);
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel::<BytesMut>();
+    let request = arena::Request::TailFile(uuid, query.path, query.lines.unwrap_or(DEFAULT_LINES), output_tx);
+    if let Err(error) = arena_request_tx.send(request) {
+        log::error!("Could not request tail from arena: {}", error);
+    }
+    let (body_tx, body_rx) = mpsc::channel::<String>(BODY_CAPACITY);
+    tokio::spawn(async move {
+        let mut window = tokio::time::interval(Duration::from_secs(1));
+        let mut budget = rate;
+        let mut skipped = 0usize;
+        let mut partial = Vec::new();
+        loop {
+            let mut lines = Vec::new();
+            tokio::select! {
+                _ = window.tick() => {
+                    if skipped > 0 {
+                        lines.push(format!("[{} lines skipped]\n", skipped));
+                        skipped = 0;
+                    }
+                    budget = rate;
+                },
+                output = output_rx.recv() => match output {
+                    Some(output) => {
+                        partial.extend_from_slice(&output);
+                        while let Some(end) = partial.iter().position(|byte| *byte == b'\n')
+                            .or_else(|| (partial.len() >= MAX_LINE_LENGTH).then(|| MAX_LINE_LENGTH - 1)) {
+                            let line = partial.drain(..=end).collect::<Vec<_>>();
+                            match budget {
+                                0 => skipped += 1,
+                                _ => {
+                                    budget -= 1;
+                                    lines.push(String::from_utf8_lossy(&line).into_owned());
+                                }
+                            }
+                        }
+                    },
+                    /* the file is no longer followed, e.g., the robot disconnected */
+                    None => break,
+                },
+                /* the client disconnected, dropping the output stops following the file */
+                _ = body_tx.closed() => break,
+            }
+            for line in lines {
+                if let Err(_) = body_tx.send(line).await {
+                    return;
+                }
+            }
+        }
+    });
+    let body = ReceiverStream::new(body_rx).map(Ok::<_, Infallible>);
+    Ok(warp::http::Response::builder()
+        .header("content-type", "text/plain; charset=utf-8")
+        .header("cache-control", "no-cache")
+        .body(warp::hyper::Body::wrap_stream(body)))
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::{BTreeMap, HashMap, VecDeque}, path::PathBuf, time::{Duration, SystemTime}};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+/// Number of samples kept at each level of a series
+const LEVEL_CAPACITY: usize = 120;
+/// Number of levels in a series, the last level discards its oldest samples
+const LEVEL_COUNT: usize = 4;
+/// Number of samples on one level that are averaged into a single sample on the next level
+const DOWNSAMPLE_FACTOR: usize = 10;
+/// Interval at which the telemetry is written to disk
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+/// Number of samples that a slow subscriber can fall behind before samples are skipped
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Sample {
+    /// Seconds since the UNIX epoch
+    pub timestamp: f64,
+    pub value: f32,
+}
+
+impl Sample {
+    pub fn now(value: f32) -> Sample {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or_default();
+        Sample { timestamp, value }
+    }
+}
+
+/// A time series where older samples are progressively downsampled to bound memory usage
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Series {
+    levels: Vec<VecDeque<Sample>>,
+}
+
+impl Series {
+    pub fn push(&mut self, sample: Sample) {
+        let mut next = Some(sample);
+        let mut level = 0;
+        while let Some(sample) = next.take() {
+            if self.levels.len() <= level {
+                self.levels.push(VecDeque::with_capacity(LEVEL_CAPACITY + 1));
+            }
+            let samples = &mut self.levels[level];
+            samples.push_back(sample);
+            if samples.len() > LEVEL_CAPACITY {
+                /* average the oldest samples into a single sample on the next level */
+                let oldest = samples.drain(..DOWNSAMPLE_FACTOR).collect::<Vec<_>>();
+                if level + 1 < LEVEL_COUNT {
+                    let count = oldest.len() as f64;
+                    let timestamp = oldest.iter().map(|sample| sample.timestamp).sum::<f64>() / count;
+                    let value = oldest.iter().map(|sample| sample.value as f64).sum::<f64>() / count;
+                    next = Some(Sample { timestamp, value: value as f32 });
+                    level += 1;
+                }
+            }
+        }
+    }
+
+    /// All samples in chronological order, with the coarsest samples first
+    pub fn history(&self) -> Vec<Sample> {
+        self.levels.iter().rev()
+            .flat_map(|samples| samples.iter().cloned())
+            .collect()
+    }
+}
+
+pub type History = BTreeMap<String, Vec<Sample>>;
+
+/// A sample as it is recorded, for streaming the telemetry live
+#[derive(Clone, Debug, Serialize)]
+pub struct Record {
+    pub robot: Uuid,
+    pub metric: String,
+    #[serde(flatten)]
+    pub sample: Sample,
+}
+
+pub enum Request {
+    Record {
+        robot: Uuid,
+        metric: String,
+        value: f32,
+    },
+    History(Uuid, oneshot::Sender<History>),
+    /* file the telemetry of a robot under its hostname so that it is kept across reconnections */
+    Identify(Uuid, String),
+    /* the robot disconnected, its telemetry is kept under its hostname */
+    Forget(Uuid),
+    /* receive the samples as they are recorded */
+    Subscribe(oneshot::Sender<broadcast::Receiver<Record>>),
+    /* write the telemetry to disk now, e.g., before shutting down */
+    Persist(oneshot::Sender<()>),
+}
+
+pub type Sender = mpsc::UnboundedSender<Request>;
+
+/// Helper for recording a sample from a robot task
+pub fn record(telemetry: &Sender, robot: Uuid, metric: &str, value: f32) {
+    let request = Request::Record { robot, metric: metric.to_owned(), value };
+    if let Err(error) = telemetry.send(request) {
+        log::warn!("Could not record {} of {}: {}", metric, robot, error);
+    }
+}
+
+/// The series of each metric by robot, robots that have not been identified are filed under
+/// their uuid
+type Robots = BTreeMap<String, BTreeMap<String, Series>>;
+
+fn load(path: &PathBuf) -> Robots {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(_) => return Robots::default(),
+    };
+    match serde_json::from_slice::<Robots>(&contents) {
+        Ok(series) => series,
+        Err(error) => {
+            log::error!("Could not read telemetry from {}: {}", path.display(), error);
+            Robots::default()
+        }
+    }
+}
+
+fn persist(path: &PathBuf, series: &Robots) -> std::io::Result<()> {
+    /* write to a temporary file first so that the previous snapshot survives a crash */
+    let temporary = path.with_extension("tmp");
+    let contents = serde_json::to_vec(series)?;
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
+
+pub async fn new(mut rx: mpsc::UnboundedReceiver<Request>, path: Option<PathBuf>) {
+    let mut series = path.as_ref().map(load).unwrap_or_default();
+    let mut names: HashMap<Uuid, String> = Default::default();
+    let mut persist_interval = tokio::time::interval(PERSIST_INTERVAL);
+    let (records_tx, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+    loop {
+        tokio::select! {
+            request = rx.recv() => match request {
+                Some(Request::Record { robot, metric, value }) => {
+                    let sample = Sample::now(value);
+                    /* this only fails if there are no subscribers */
+                    let _ = records_tx.send(Record { robot, metric: metric.clone(), sample });
+                    let name = names.get(&robot).cloned().unwrap_or_else(|| robot.to_string());
+                    series.entry(name)
+                        .or_default()
+                        .entry(metric)
+                        .or_default()
+                        .push(sample);
+                },
+                Some(Request::Subscribe(callback)) => {
+                    let _ = callback.send(records_tx.subscribe());
+                },
+                Some(Request::Identify(robot, name)) => {
+                    /* move the samples that were recorded before the robot was identified */
+                    if let Some(unidentified) = series.remove(&robot.to_string()) {
+                        let metrics = series.entry(name.clone()).or_default();
+                        for (metric, samples) in unidentified {
+                            let identified = metrics.entry(metric).or_default();
+                            for sample in samples.history() {
+                                identified.push(sample);
+                            }
+                        }
+                    }
+                    names.insert(robot, name);
+                },
+                Some(Request::Forget(robot)) => {
+                    names.remove(&robot);
+                },
+                Some(Request::History(robot, callback)) => {
+                    let name = names.get(&robot).cloned().unwrap_or_else(|| robot.to_string());
+                    let history = series.get(&name)
+                        .map(|metrics| metrics.iter()
+                            .map(|(metric, series)| (metric.clone(), series.history()))
+                            .collect())
+                        .unwrap_or_default();
+                    if let Err(_) = callback.send(history) {
+                        log::error!("Could not respond with telemetry history");
+                    }
+                },
+                Some(Request::Persist(callback)) => {
+                    if let Some(path) = path.as_ref() {
+                        if let Err(error) = persist(path, &series) {
+                            log::error!("Could not write telemetry to {}: {}", path.display(), error);
+                        }
+                    }
+                    let _ = callback.send(());
+                },
+                None => break,
+            },
+            _ = persist_interval.tick() => if let Some(path) = path.as_ref() {
+                if let Err(error) = persist(path, &series) {
+                    log::error!("Could not write telemetry to {}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+}
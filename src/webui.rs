@@ -1,33 +1,115 @@
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::ws;
 
 use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
     time::Duration
 };
 
-use futures::{FutureExt, StreamExt};
+use futures::{SinkExt, StreamExt, stream::SplitSink};
 
-use tokio::{sync::{mpsc, oneshot}, time::timeout};
+use tokio::sync::{mpsc, oneshot};
 
 use regex::Regex;
 
 use crate::{
     arena,
+    arming,
+    auth::Permission,
+    availability,
+    campaign,
+    chat,
+    dedup,
+    operation,
+    history,
+    image::Image,
+    calibration,
+    journal,
+    formation,
+    link,
+    lock,
     optitrack,
+    plugin,
+    provision,
+    push,
+    quota,
+    selftest,
     software,
+    telemetry,
     robot::drone,
     robot::pipuck,
+    robot::queue,
+    robot::action,
+    config::{self, Config},
+    network::{self, fernbedienung},
 };
 
 use serde::{Deserialize, Serialize};
 
+use mns_protocol::webui::{self as api, SessionReply};
+pub use mns_protocol::webui::{Content, Resume};
+
 use log;
 
 use itertools::Itertools;
 
+/// Window in which replies are coalesced into a single websocket frame
+const BATCH_WINDOW: Duration = Duration::from_millis(50);
+/// Interval at which the read-only dashboard is refreshed
+const DASHBOARD_PERIOD: Duration = Duration::from_secs(1);
+/// Time within which the arena must respond for the supervisor to be ready
+const READY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Interval at which the clients are pinged
+const HEARTBEAT_PERIOD: Duration = Duration::from_secs(5);
+/// Time after which a client that has not sent anything, not even a pong, is disconnected
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Form for sending a message to one or all robots via the message router
+const INJECTION_FORM: &str = "<input id=\"inject-recipient\" class=\"mdl-textfield__input\" placeholder=\"Recipient address (empty for all robots)\" />\
+<textarea id=\"inject-message\" class=\"mdl-textfield__input\" rows=\"3\" placeholder=\"{&quot;formation&quot;: &quot;B&quot;}\"></textarea>\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"injectForm()\">Send</button>";
+
+/// Form for sending a message to the other operators
+const CHAT_FORM: &str = "<input id=\"chat-author\" class=\"mdl-textfield__input\" placeholder=\"Name (optional)\" />\
+<input id=\"chat-text\" class=\"mdl-textfield__input\" placeholder=\"Message\" />\
+<label><input id=\"chat-pin\" type=\"checkbox\" /> Pin as an annotation of the run</label>\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"chatForm()\">Send</button>";
+
+/// Maximum length of a chat message in characters
+const CHAT_MAX_LENGTH: usize = 1000;
+
+/// Form for bridging the message router to the simulated medium of an ARGoS instance
+const BRIDGE_FORM: &str = "<input id=\"bridge-address\" class=\"mdl-textfield__input\" placeholder=\"Address of the simulation, e.g., 127.0.0.1:4951\" />\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"bridgeForm()\">Connect</button>\
+<button class=\"mdl-button mdl-js-button\" onclick=\"bridgeForm(true)\">Disconnect</button>";
+
+/// Form for provisioning a freshly flashed robot
+const PROVISION_FORM: &str = "<input id=\"provision-address\" class=\"mdl-textfield__input\" placeholder=\"Address of the robot, e.g., 192.168.1.10\" />\
+<input id=\"provision-hostname\" class=\"mdl-textfield__input\" placeholder=\"Hostname\" />\
+<select id=\"provision-kind\" class=\"mdl-textfield__input\"><option value=\"PiPuck\">Pi-Puck</option><option value=\"Drone\">Drone</option></select>\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"provisionForm()\">Provision</button>";
+
+/// HTML form for registering a device that discovery cannot find, e.g., on a routed network
+const REGISTER_FORM: &str = "<input id=\"register-address\" class=\"mdl-textfield__input\" placeholder=\"Address of the device, e.g., 10.0.5.20\" />\
+<select id=\"register-device\" class=\"mdl-textfield__input\"><option value=\"fernbedienung\">Pi-Puck, UP Core, or compute server</option><option value=\"xbee\">Xbee of a drone</option></select>\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"registerForm()\">Register</button>";
+
+/// HTML form for replacing the filter of the devices that are probed
+const FILTER_FORM: &str = "<input id=\"filter-allow\" class=\"mdl-textfield__input\" placeholder=\"Allowed addresses, networks, or MAC prefixes, e.g., 10.0.3.0/24, b8:27:eb\" />\
+<input id=\"filter-deny\" class=\"mdl-textfield__input\" placeholder=\"Denied addresses, networks, or MAC prefixes, e.g., 10.0.0.5\" />\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"filterForm()\">Apply</button>\
+<button class=\"mdl-button mdl-js-button\" onclick=\"filterForm(true)\">Use configuration</button>";
+
+/// HTML form for running the self test against a robot or the mock fernbedienung service
+const SELFTEST_FORM: &str = "<input id=\"selftest-robot\" class=\"mdl-textfield__input\" placeholder=\"Hostname of the robot, leave empty for the mock service\" />\
+<button class=\"mdl-button mdl-button--colored mdl-js-button\" onclick=\"selfTestForm()\">Run</button>";
+
 /// MDL HTML for icons
 const OK_ICON: &str = "<i class=\"material-icons mdl-list__item-icon\" style=\"color:green; vertical-align: middle;\">check_circle</i>";
 const ERROR_ICON: &str = "<i class=\"material-icons mdl-list__item-icon\" style=\"color:red; vertical-align: middle;\">error</i>";
+/// Temperature (degrees Celsius) at which the health of a robot is flagged, the Raspberry Pi
+/// firmware starts throttling at 80 degrees
+const HEALTH_TEMPERATURE_WARNING: f32 = 70.0;
 
 const WIFI1_IMG: &str = "<img src=\"images/wifi1.svg\" style=\"height:2em;padding-right:10px\" />";
 const WIFI2_IMG: &str = "<img src=\"images/wifi2.svg\" style=\"height:2em;padding-right:10px\" />";
@@ -46,18 +128,220 @@ fn generate_image_node(mime: &str, data: &[u8], style: &str) -> String {
     format!("<img src=\"data:{};base64,{}\" style=\"{}\" onclick=\"{}\" />", mime, data, style, download)
 }
 
-#[derive(Serialize, Debug)]
-#[serde(rename_all = "lowercase")]
-enum Content {
-    Text(String),
-    Table {
-        header: Vec<String>,
-        rows: Vec<Vec<String>>
-    },
-    Download  {
-        data: String,
-        filename: String,
-    },
+fn generate_battery_text(battery: Option<fernbedienung::Battery>, low_battery: f32) -> String {
+    match battery {
+        Some(battery) => {
+            let image = match battery.percentage as i32 {
+                25..=49  => BATT2_IMG,
+                50..=74  => BATT3_IMG,
+                75..=100 => BATT4_IMG,
+                _ => BATT1_IMG,
+            };
+            match battery.percentage < low_battery {
+                true => format!("{}<br/>{} {:.0}% ({:.2} V)", image, ERROR_ICON, battery.percentage, battery.voltage),
+                false => format!("{}<br/>{:.0}% ({:.2} V)", image, battery.percentage, battery.voltage),
+            }
+        },
+        None => "Unknown".to_owned(),
+    }
+}
+
+fn generate_health_text(system: Option<fernbedienung::SystemInfo>) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    match system {
+        Some(system) => {
+            let temperature = match system.temperature {
+                Some(temperature) if temperature >= HEALTH_TEMPERATURE_WARNING =>
+                    format!("{} {:.1} °C", ERROR_ICON, temperature),
+                Some(temperature) => format!("{:.1} °C", temperature),
+                None => "Unknown temperature".to_owned(),
+            };
+            format!("{}<br/>Load {:.2} ({} CPUs)<br/>{:.0} of {:.0} MiB memory free<br/>{:.0} MiB disk free",
+                temperature,
+                system.load,
+                system.cpus,
+                system.memory_available as f64 / MIB,
+                system.memory_total as f64 / MIB,
+                system.disk_available as f64 / MIB)
+        },
+        None => "Unknown".to_owned(),
+    }
+}
+
+fn generate_software_image_content(image: Option<Image>, golden_image: &Option<Image>) -> Vec<Content> {
+    let (os_release, kernel, status) = match image {
+        Some(image) => {
+            let status = match golden_image {
+                Some(golden_image) => {
+                    let drift = image.drift(golden_image);
+                    match drift.is_empty() {
+                        true => format!("{} Matches golden image", OK_ICON),
+                        false => format!("{} {}", ERROR_ICON, drift.iter().map(|drift| escape_html(drift)).join("<br/>")),
+                    }
+                },
+                None => "No golden image".to_owned(),
+            };
+            (escape_html(&image.os_release.unwrap_or_default()), escape_html(&image.kernel.unwrap_or_default()), status)
+        },
+        None => ("Unknown".to_owned(), "Unknown".to_owned(), "Unknown".to_owned()),
+    };
+    vec![
+        Content::Text("Software image".to_owned()),
+        Content::Table {
+            header: vec!["OS Release".to_owned(), "Kernel".to_owned(), "Status".to_owned()],
+            rows: vec![vec![os_release, kernel, status]]
+        },
+    ]
+}
+
+/* the operations that are running or waiting on a robot and the last operation that was refused */
+fn generate_queue_content(queue: Option<(Vec<queue::Entry>, Option<String>)>) -> Vec<Content> {
+    let (entries, refused) = queue.unwrap_or_default();
+    if entries.is_empty() && refused.is_none() {
+        return Vec::new();
+    }
+    let mut rows = entries.into_iter()
+        .map(|entry| vec![
+            entry.kind.to_string(),
+            match entry.running {
+                true => "Running".to_owned(),
+                false => "Queued".to_owned(),
+            },
+            entry.queued.elapsed()
+                .map_or("Unknown".to_owned(), |elapsed| format!("{} s ago", elapsed.as_secs())),
+        ])
+        .collect::<Vec<_>>();
+    if let Some(refused) = refused {
+        rows.push(vec![format!("{} {}", ERROR_ICON, refused), "Refused".to_owned(), String::new()]);
+    }
+    vec![
+        Content::Text("Action queue".to_owned()),
+        Content::Table {
+            header: vec!["Operation".to_owned(), "State".to_owned(), "Queued".to_owned()],
+            rows,
+        },
+    ]
+}
+
+fn generate_chart_node(samples: &[telemetry::Sample]) -> String {
+    const WIDTH: f64 = 300.0;
+    const HEIGHT: f64 = 60.0;
+    let (first, last) = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return String::new(),
+    };
+    let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), sample| {
+        (min.min(sample.value), max.max(sample.value))
+    });
+    let duration = (last - first).max(f64::EPSILON);
+    let range = ((max - min) as f64).max(f64::EPSILON);
+    let points = samples.iter()
+        .map(|sample| format!("{:.1},{:.1}",
+            (sample.timestamp - first) / duration * WIDTH,
+            HEIGHT - (sample.value - min) as f64 / range * HEIGHT))
+        .join(" ");
+    format!("<svg viewBox=\"0 0 {} {}\" style=\"width:100%;height:4em\" preserveAspectRatio=\"none\">\
+             <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\" \
+             vector-effect=\"non-scaling-stroke\" /></svg>", WIDTH, HEIGHT, points)
+}
+
+/* the targets of a formation as rings with the tolerance as their radius and a tick for their
+   heading, and the tracked rigid bodies of the formation as dots joined to their targets */
+fn generate_formation_node(formation: &formation::Formation, poses: &[optitrack::Pose]) -> String {
+    const MARGIN: f32 = 0.1;
+    let tracked = formation.targets.iter()
+        .filter_map(|target| poses.iter()
+            .find(|pose| pose.id == target.rigid_body)
+            .map(|pose| (target, pose)))
+        .collect::<Vec<_>>();
+    let points = formation.targets.iter()
+        .map(|target| target.position)
+        .chain(tracked.iter().map(|(_, pose)| (pose.position[0], pose.position[1])))
+        .collect::<Vec<_>>();
+    if points.is_empty() {
+        return String::new();
+    }
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+        |(min_x, max_x, min_y, max_y), (x, y)| (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y)));
+    let (left, top) = (min_x - MARGIN, max_y + MARGIN);
+    let (width, height) = (max_x - min_x + 2.0 * MARGIN, max_y - min_y + 2.0 * MARGIN);
+    let size = width.max(height) * 0.02;
+    /* the y axis of the arena points up and the y axis of the drawing points down */
+    let point = |x: f32, y: f32| (x - left, top - y);
+    let mut elements = String::new();
+    for target in formation.targets.iter() {
+        let (x, y) = point(target.position.0, target.position.1);
+        elements.push_str(&format!("<circle cx=\"{:.3}\" cy=\"{:.3}\" r=\"{:.3}\" fill=\"none\" stroke=\"grey\" \
+            vector-effect=\"non-scaling-stroke\" />", x, y, formation.tolerance.max(size)));
+        if let Some(heading) = target.heading {
+            let (x2, y2) = point(target.position.0 + 2.0 * size * heading.cos(),
+                                 target.position.1 + 2.0 * size * heading.sin());
+            elements.push_str(&format!("<line x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\" stroke=\"grey\" \
+                vector-effect=\"non-scaling-stroke\" />", x, y, x2, y2));
+        }
+        elements.push_str(&format!("<text x=\"{:.3}\" y=\"{:.3}\" font-size=\"{:.3}\" fill=\"grey\">{}</text>",
+            x + size, y - size, 2.0 * size, target.rigid_body));
+    }
+    for (target, pose) in tracked {
+        let (tx, ty) = point(target.position.0, target.position.1);
+        let (x, y) = point(pose.position[0], pose.position[1]);
+        elements.push_str(&format!("<line x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\" stroke=\"steelblue\" \
+            stroke-dasharray=\"2\" vector-effect=\"non-scaling-stroke\" />", x, y, tx, ty));
+        elements.push_str(&format!("<circle cx=\"{:.3}\" cy=\"{:.3}\" r=\"{:.3}\" fill=\"steelblue\" />", x, y, size));
+    }
+    format!("<svg viewBox=\"0 0 {:.3} {:.3}\" style=\"width:100%;max-height:20em\">{}</svg>", width, height, elements)
+}
+
+/* the cells of the heatmap colored from red to green by their mean signal strength */
+fn generate_heatmap_node(heatmap: &link::Heatmap) -> String {
+    const WEAKEST: f32 = -90.0;
+    const STRONGEST: f32 = -40.0;
+    if heatmap.cells.is_empty() {
+        return String::new();
+    }
+    let (min_x, max_x, min_y, max_y) = heatmap.cells.keys().fold(
+        (i32::MAX, i32::MIN, i32::MAX, i32::MIN),
+        |(min_x, max_x, min_y, max_y), (x, y)| (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y)));
+    let (columns, rows) = (max_x - min_x + 1, max_y - min_y + 1);
+    let mut elements = String::new();
+    for ((x, y), cell) in heatmap.cells.iter() {
+        let hue = ((cell.mean - WEAKEST) / (STRONGEST - WEAKEST)).max(0.0).min(1.0) * 120.0;
+        /* the y axis of the arena points up and the y axis of the drawing points down */
+        elements.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"hsl({:.0},70%,50%)\">\
+            <title>{:.0} dBm (min. {} dBm, {} samples)</title></rect>",
+            x - min_x, max_y - y, hue, cell.mean, cell.min, cell.samples));
+    }
+    format!("<svg viewBox=\"0 0 {} {}\" style=\"width:100%;max-height:20em\">{}</svg>", columns, rows, elements)
+}
+
+fn generate_history_content(history: telemetry::History) -> Vec<Content> {
+    match history.is_empty() {
+        true => vec![],
+        false => vec![
+            Content::Text("History".to_owned()),
+            Content::Table {
+                header: vec!["Metric".to_owned(), "Latest".to_owned(), "Chart".to_owned()],
+                rows: history.into_iter()
+                    .map(|(metric, samples)| vec![
+                        metric,
+                        samples.last().map_or(String::new(), |sample| format!("{:.0}", sample.value)),
+                        generate_chart_node(&samples),
+                    ])
+                    .collect()
+            },
+        ]
+    }
+}
+
+async fn get_telemetry(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                       uuid: uuid::Uuid) -> Result<telemetry::History> {
+    let (get_telemetry_callback_tx, get_telemetry_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetTelemetry(uuid, get_telemetry_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    get_telemetry_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -72,9 +356,6 @@ pub enum Error {
     ArenaRequestError,
     #[error("Could not get a response from arena")]
     ArenaResponseError,
-
-    #[error("Timed out while waiting for response from Optitrack system")]
-    OptitrackTimeoutError,
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -102,34 +383,199 @@ enum Request {
         action: software::Action,
         file: Option<(String, String)>,
         uuid: uuid::Uuid
+    },
+    Query {
+        predicates: Vec<arena::Predicate>,
+    },
+    Plugin {
+        action: String,
+        uuid: uuid::Uuid
+    },
+    /* send a message template */
+    Inject {
+        action: String,
+        uuid: uuid::Uuid
+    },
+    /* broadcast a command and report which robots acknowledged it */
+    Command {
+        command: serde_json::Value,
+        deadline_ms: u64,
+    },
+    /* send a message from the injection form */
+    Message {
+        recipient: Option<IpAddr>,
+        message: serde_json::Value,
+    },
+    /* bridge the message router to a simulation, or disconnect the bridge if address is null */
+    Bridge {
+        address: Option<SocketAddr>,
+    },
+    /* lock or unlock the testbed, or hand the lock over to the client with the given uuid */
+    Lock {
+        action: lock::Action,
+        uuid: uuid::Uuid,
+    },
+    /* clone a past run or template, or save a past run as a template */
+    History {
+        action: history::Action,
+        uuid: uuid::Uuid,
+        /* the name of the template or of the redaction profile of an export */
+        name: Option<String>,
+    },
+    /* capture a corner of the arena or reset the calibration of the arena frame */
+    Calibration {
+        action: calibration::Action,
+        uuid: uuid::Uuid,
+    },
+    /* show, measure, or drive the robots into the formation of the card */
+    Formation {
+        action: formation::Action,
+        uuid: uuid::Uuid,
+    },
+    /* clear the heatmap of the Wi-Fi signal strength across the arena */
+    Heatmap {
+        action: link::HeatmapAction,
+        uuid: uuid::Uuid,
+    },
+    /* save the tracking data that was kept while no experiment was running */
+    Recording {
+        action: journal::RecordingAction,
+        uuid: uuid::Uuid,
+    },
+    /* run the controller of a robot on a compute server (or on the robot if server is null) */
+    RemoteBrain {
+        robot: uuid::Uuid,
+        server: Option<uuid::Uuid>,
+    },
+    /* receive the critical alerts as push notifications */
+    Subscribe {
+        subscription: push::Subscription,
+    },
+    /* cancel a long-running operation */
+    Operation {
+        action: operation::Action,
+        uuid: uuid::Uuid,
+    },
+    /* probe a device by its address and type, bypassing discovery */
+    Register {
+        address: std::net::Ipv4Addr,
+        device: network::Probe,
+    },
+    /* replace the filter of the devices that are probed, null returns to the configured filter */
+    Filter {
+        filter: Option<network::filter::Filter>,
+    },
+    /* provision a freshly flashed robot */
+    Provision {
+        address: std::net::Ipv4Addr,
+        kind: arena::Kind,
+        hostname: String,
+    },
+    /* run the self test against a robot, or against the mock fernbedienung service if null */
+    SelfTest {
+        robot: Option<String>,
+    },
+    /* send a message to the other operators, optionally pinning it to the current run */
+    Chat {
+        author: Option<String>,
+        text: String,
+        pin: bool,
+    },
+}
+
+/// A request with an optional identifier generated by the client, a request that is resent with
+/// the same identifier, e.g., after reconnecting, is not carried out a second time
+#[derive(Deserialize, Debug)]
+struct Envelope {
+    id: Option<uuid::Uuid>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+impl Request {
+    /* requests that change the state of the testbed require the operator lock and a permission */
+    fn permission(&self, config: &Config) -> Option<Permission> {
+        match self {
+            Request::Update{..} | Request::Query{..} | Request::Subscribe{..} => None,
+            /* the holder may always release the lock, but only an operator may take or pass it on */
+            Request::Lock{action: lock::Action::Release, ..} => None,
+            Request::Lock{..} => Some(Permission::Experiment),
+            /* anyone may chat, but pinning a message changes the history */
+            Request::Chat{pin: false, ..} => None,
+            Request::Chat{pin: true, ..} => Some(Permission::History),
+            Request::Arena{action: arena::Action::ReloadConfiguration, ..} |
+            Request::Arena{action: arena::Action::PauseScanning, ..} |
+            Request::Arena{action: arena::Action::ResumeScanning, ..} => Some(Permission::Configure),
+            Request::Arena{..} | Request::Formation{..} | Request::Bridge{..} => Some(Permission::Experiment),
+            /* the arena refuses the actions that are not defined */
+            Request::Drone{action, ..} => Some(action::find(&config.drone_actions, action)
+                .map_or(Permission::Maintenance, |definition| definition.permission)),
+            Request::PiPuck{action, ..} => Some(action::find(&config.pipuck_actions, action)
+                .map_or(Permission::Maintenance, |definition| definition.permission)),
+            Request::Software{uuid, ..} if *uuid == *UUID_MAINTENANCE_CAMPAIGN => Some(Permission::Campaign),
+            Request::Software{..} => Some(Permission::Software),
+            Request::Plugin{..} => Some(Permission::Plugin),
+            Request::Inject{..} | Request::Command{..} | Request::Message{..} => Some(Permission::Inject),
+            Request::History{..} => Some(Permission::History),
+            Request::Calibration{..} => Some(Permission::Calibrate),
+            Request::Heatmap{..} | Request::Recording{..} | Request::SelfTest{..} => Some(Permission::Maintenance),
+            Request::RemoteBrain{..} => Some(Permission::RemoteBrain),
+            Request::Provision{..} => Some(Permission::Provision),
+            Request::Register{..} | Request::Filter{..} => Some(Permission::Configure),
+            Request::Operation{..} => Some(Permission::Operation),
+        }
     }
 }
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "lowercase", tag = "type", content = "action")]
-enum Action {
-    Drone(drone::Action),
-    PiPuck(pipuck::Action),
+pub enum Action {
+    Drone(action::Control<drone::Action>),
+    PiPuck(action::Control<pipuck::Action>),
     Arena(arena::Action),
     Software(software::Action),
+    Plugin(String),
+    /* the label is the escaped name of the template, the action is the name itself */
+    Inject(action::Control<String>),
+    History(history::Action),
+    Lock(lock::Action),
+    Calibration(calibration::Action),
+    Formation(formation::Action),
+    Heatmap(link::HeatmapAction),
+    Recording(journal::RecordingAction),
+    Operation(operation::Action),
 }
 
-#[derive(Serialize, Debug)]
-struct Card {
-    uuid: uuid::Uuid,
-    span: u8,
-    title: String,
-    content: Vec<Content>,
-    actions: Vec<Action>,
+pub type Card = api::Card<Action>;
+pub type Cards = Vec<Card>;
+
+// TODO, Reply will probably need to be wrapped in a enum soon Reply::Update, Reply::XXX
+type Reply = api::Reply<Action>;
+
+/// Robots that acknowledged a command
+#[derive(Serialize)]
+struct CommandReply {
+    command: crate::command::Acknowledgements,
+}
+
+/// The outcome of a request that carried an identifier
+#[derive(Serialize)]
+struct RequestReply {
+    request: RequestOutcome,
 }
 
-type Cards = Vec<Card>;
+#[derive(Serialize)]
+struct RequestOutcome {
+    id: uuid::Uuid,
+    outcome: dedup::Outcome,
+    /// Whether the request was a resend whose outcome is that of the original request
+    replayed: bool,
+}
 
-// TODO, Reply will probably need to be wrapped in a enum soon Reply::Update, Reply::XXX
+/// Robots matching the predicates of a query request
 #[derive(Serialize)]
-struct Reply {
-    title: String,
-    cards: Cards,
+struct QueryReply {
+    query: Vec<arena::Summary>,
 }
 
 lazy_static::lazy_static! {
@@ -140,15 +586,54 @@ lazy_static::lazy_static! {
         uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_OID, "arena".as_bytes());
     static ref NAMESPACE_OPTITRACK: uuid::Uuid =
         uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_OID, "optitrack".as_bytes());
+    static ref NAMESPACE_FORMATION: uuid::Uuid =
+        uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_OID, "formation".as_bytes());
     static ref NAMESPACE_ERROR: uuid::Uuid =
         uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_OID, "error".as_bytes());
 
+    static ref UUID_CONNECTIONS_DOCKS: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_CONNECTIONS, "docks".as_bytes());
+    static ref UUID_CONNECTIONS_LINKS: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_CONNECTIONS, "links".as_bytes());
+    static ref UUID_CONNECTIONS_CHANNELS: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_CONNECTIONS, "channels".as_bytes());
+
     static ref UUID_ARENA_DRONES: uuid::Uuid =
         uuid::Uuid::new_v3(&NAMESPACE_ARENA, "drones".as_bytes());
     static ref UUID_ARENA_PIPUCKS: uuid::Uuid =
         uuid::Uuid::new_v3(&NAMESPACE_ARENA, "pipucks".as_bytes());
+    static ref UUID_ARENA_ENVIRONMENT: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "environment".as_bytes());
+    static ref UUID_ARENA_FLAGS: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "flags".as_bytes());
+    static ref UUID_ARENA_INJECTION: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "injection".as_bytes());
+    static ref UUID_ARENA_BRIDGE: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "bridge".as_bytes());
+    static ref UUID_DASHBOARD_SUMMARY: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "dashboard".as_bytes());
+    static ref UUID_MAINTENANCE_CAMPAIGN: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "campaign".as_bytes());
+    static ref UUID_MAINTENANCE_PROVISIONING: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "provisioning".as_bytes());
+    static ref UUID_MAINTENANCE_REGISTRATION: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "registration".as_bytes());
+    static ref UUID_MAINTENANCE_FILTER: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "filter".as_bytes());
+    static ref UUID_MAINTENANCE_AVAILABILITY: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "availability".as_bytes());
+    static ref UUID_MAINTENANCE_STORAGE: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "storage".as_bytes());
+    static ref UUID_MAINTENANCE_SELFTEST: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "selftest".as_bytes());
+    static ref UUID_MAINTENANCE_OPERATIONS: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "operations".as_bytes());
+    static ref UUID_ARENA_LOCK: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "lock".as_bytes());
     static ref UUID_ARENA_DASHBOARD: uuid::Uuid =
         uuid::Uuid::new_v3(&NAMESPACE_ARENA, "dashboard".as_bytes());
+    static ref UUID_ARENA_CHAT: uuid::Uuid =
+        uuid::Uuid::new_v3(&NAMESPACE_ARENA, "chat".as_bytes());
     
     /* other */
     static ref IIO_CHECKS: Vec<(String, String)> =
@@ -159,24 +644,56 @@ lazy_static::lazy_static! {
 }
 
 pub async fn run(ws: ws::WebSocket,
-                 arena_request_tx: mpsc::UnboundedSender<arena::Request>) {
-    log::info!("Client connected");
+                 arena_request_tx: mpsc::UnboundedSender<arena::Request>,
+                 operations_tx: operation::Sender,
+                 plugins: plugin::Registry,
+                 config: config::Receiver,
+                 addr: Option<SocketAddr>,
+                 resume: Resume) {
+    /* each client is identified so that the actions of observers can be refused, a client
+       that reconnects with the token of its session keeps its identity and hence its lock */
+    let (connect_callback_tx, connect_callback_rx) = oneshot::channel();
+    let request = arena::Request::Lock(lock::Request::Connect(resume.session, addr, connect_callback_tx));
+    if let Err(error) = arena_request_tx.send(request) {
+        log::error!("Could not register client with arena: {}", error);
+        return;
+    }
+    let session = match connect_callback_rx.await {
+        Ok(session) => session,
+        Err(_) => {
+            log::error!("Could not get session from arena");
+            return;
+        }
+    };
+    let client = session.client;
+    log::info!("Client {} connected from {:?}", client, addr);
     /* split the socket into a sender and receive of messages */
     let (websocket_tx, mut websocket_rx) = ws.split();
 
     // TODO is this multiplexing necessary?
     let (tx, rx) = mpsc::unbounded_channel();
-    let rx_stream = UnboundedReceiverStream::new(rx);
 
     // TODO is it desirable to spawn here?
-    tokio::task::spawn(rx_stream.forward(websocket_tx).map(|result| {
-        if let Err(error) = result {
-            log::error!("Sending data over WebSocket failed: {}", error);
-        }
-    }));
+    tokio::task::spawn(send_batches(rx, websocket_tx));
+
+    match serde_json::to_string(&SessionReply { session: session.token }) {
+        Ok(content) => {
+            let _ = tx.send(content);
+        },
+        Err(_) => log::error!("Could not serialize reply"),
+    }
 
     /* this loop is update task for a webui client */
-    while let Some(data) = websocket_rx.next().await {
+    loop {
+        /* the client answers the pings, a socket that stays silent is half-open */
+        let data = match tokio::time::timeout(HEARTBEAT_TIMEOUT, websocket_rx.next()).await {
+            Ok(Some(data)) => data,
+            Ok(None) => break,
+            Err(_) => {
+                log::warn!("Client {} stopped responding", client);
+                break;
+            }
+        };
         let request : ws::Message = match data {
             Ok(request) => request,
             Err(error) => {
@@ -193,8 +710,63 @@ pub async fn run(ws: ws::WebSocket,
             eprintln!("t2 = {}", serde_json::to_string(&t2).unwrap());
             eprintln!("t3 = {}", serde_json::to_string(&t3).unwrap());
             */
-            if let Ok(action) = serde_json::from_str::<Request>(request) {
+            if let Ok(Envelope { id, request: action }) = serde_json::from_str::<Envelope>(request) {
+                /* the arena is busy while it stages the software, so cancellations go straight to
+                   the operation task and only the role of the client is checked, cancelling twice
+                   is harmless so these requests are not deduplicated */
+                if let Request::Operation{action: operation::Action::Cancel, uuid} = action {
+                    let permitted = config.borrow().auth.as_ref()
+                        .map_or(true, |auth| auth.permits(addr, Permission::Operation));
+                    let outcome = match permitted {
+                        true => dedup::Outcome::Accepted,
+                        false => {
+                            log::warn!("Refused {:?} request from client {}", Permission::Operation, client);
+                            dedup::Outcome::Refused
+                        }
+                    };
+                    if let Some(id) = id {
+                        reply_outcome(&tx, id, outcome, false);
+                    }
+                    if outcome == dedup::Outcome::Accepted {
+                        if let Err(error) = operations_tx.send(operation::Request::Cancel(uuid)) {
+                            log::error!("Could not forward cancellation to operation task: {}", error);
+                        }
+                    }
+                    continue;
+                }
+                if let Some(id) = id {
+                    if let Some(outcome) = claim(&arena_request_tx, id).await {
+                        log::info!("Suppressed duplicate request {} from client {}", id, client);
+                        reply_outcome(&tx, id, outcome, true);
+                        continue;
+                    }
+                }
+                let permission = action.permission(&config.borrow());
+                let outcome = match permission {
+                    Some(permission) if !authorize(&arena_request_tx, client, permission).await => {
+                        log::warn!("Refused {:?} request from client {}", permission, client);
+                        dedup::Outcome::Refused
+                    },
+                    _ => dedup::Outcome::Accepted,
+                };
+                if let Some(id) = id {
+                    let _ = arena_request_tx.send(arena::Request::Dedup(dedup::Request::Complete(id, outcome)));
+                    reply_outcome(&tx, id, outcome, false);
+                }
+                if outcome == dedup::Outcome::Refused {
+                    continue;
+                }
                 match action {
+                    Request::Lock{action, uuid} => {
+                        let request = match action {
+                            lock::Action::Acquire => lock::Request::Acquire(client),
+                            lock::Action::Release => lock::Request::Release(client),
+                            lock::Action::HandOver => lock::Request::HandOver(client, uuid),
+                        };
+                        if let Err(error) = arena_request_tx.send(arena::Request::Lock(request)) {
+                            log::error!("Could not forward lock action to arena: {}", error);
+                        }
+                    },
                     Request::Arena{action, ..} => {
                         let request = arena::Request::Execute(action);
                         if let Err(error) = arena_request_tx.send(request) {
@@ -215,13 +787,21 @@ pub async fn run(ws: ws::WebSocket,
                     },
                     Request::Update{tab} => {
                         let result = match &tab[..] {
-                            "Connections" => connections_tab(&arena_request_tx).await,
-                            "Experiment" => experiment_tab(&arena_request_tx).await,
-                            "Optitrack" => optitrack_tab().await,
-                            _ => Err(Error::BadRequest),
+                            "Connections" => connections_tab(&arena_request_tx, &config).await,
+                            "Experiment" => experiment_tab(&arena_request_tx, client).await,
+                            "Optitrack" => optitrack_tab(&arena_request_tx, &config, true).await,
+                            "History" => history_tab(&arena_request_tx).await,
+                            "Maintenance" => maintenance_tab(&arena_request_tx).await,
+                            _ => match plugins.find_tab(&tab) {
+                                Some(plugin) => Ok(plugin.cards(&arena_request_tx).await),
+                                None => Err(Error::BadRequest),
+                            }
                         };
+                        let tabs = plugins.tabs();
+                        let banner = banner(&arena_request_tx, client).await;
+                        let status = status(&arena_request_tx).await;
                         let reply = match result {
-                            Ok(cards) => Reply { title: tab, cards },
+                            Ok(cards) => Reply { title: tab, cards, banner, status, tabs },
                             Err(error) => {
                                 let error_message = format!("{}", error);
                                 let card = Card {
@@ -231,13 +811,179 @@ pub async fn run(ws: ws::WebSocket,
                                     content: vec![Content::Text(error_message)],
                                     actions: vec![],
                                 };
-                                Reply { title: tab, cards: vec![ card ] }
+                                Reply { title: tab, cards: vec![ card ], banner, status, tabs }
+                            }
+                        };
+                        match serde_json::to_string(&reply) {
+                            Ok(content) => {
+                                if let Err(_) = tx.send(content) {
+                                    log::error!("Could not reply to client");
+                                }
+                            },
+                            Err(_) => log::error!("Could not serialize reply"),
+                        }
+                    },
+                    Request::Plugin{uuid, action} => {
+                        let request = arena::Request::ExecutePluginAction(uuid, action);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward plugin action to arena: {}", error);
+                        }
+                    },
+                    Request::History{action, uuid, name} => {
+                        let request = arena::Request::ExecuteHistoryAction(action, uuid, name);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward history action to arena: {}", error);
+                        }
+                    },
+                    Request::Calibration{action, ..} => {
+                        let request = arena::Request::Calibrate(action);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward calibration action to arena: {}", error);
+                        }
+                    },
+                    Request::Heatmap{action: link::HeatmapAction::Clear, ..} => {
+                        if let Err(error) = arena_request_tx.send(arena::Request::ClearLinkHeatmap) {
+                            log::error!("Could not forward heatmap action to arena: {}", error);
+                        }
+                    },
+                    Request::Recording{action: journal::RecordingAction::Save, ..} => {
+                        if let Err(error) = arena_request_tx.send(arena::Request::SaveTrackingRecording) {
+                            log::error!("Could not forward recording action to arena: {}", error);
+                        }
+                    },
+                    Request::Bridge{address} => {
+                        let request = arena::Request::BridgeSimulation(address);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward simulation bridge to arena: {}", error);
+                        }
+                    },
+                    Request::Formation{action, uuid} => {
+                        let name = config.borrow().formations.iter()
+                            .map(|formation| &formation.name)
+                            .find(|name| uuid::Uuid::new_v3(&NAMESPACE_FORMATION, name.as_bytes()) == uuid)
+                            .cloned();
+                        match name {
+                            Some(name) => {
+                                let request = arena::Request::Formation(name, action);
+                                if let Err(error) = arena_request_tx.send(request) {
+                                    log::error!("Could not forward formation action to arena: {}", error);
+                                }
+                            },
+                            None => log::warn!("Could not find formation {}", uuid),
+                        }
+                    },
+                    Request::Inject{action, ..} => {
+                        let request = arena::Request::InjectTemplate(action);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward message template to arena: {}", error);
+                        }
+                    },
+                    Request::Command{command, deadline_ms} => {
+                        let (command_callback_tx, command_callback_rx) = oneshot::channel();
+                        let request = arena::Request::BroadcastCommand {
+                            command,
+                            deadline: Duration::from_millis(deadline_ms),
+                            callback: command_callback_tx,
+                        };
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward command to arena: {}", error);
+                            continue;
+                        }
+                        /* wait for the acknowledgements in the background */
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                            if let Ok(command) = command_callback_rx.await {
+                                match serde_json::to_string(&CommandReply { command }) {
+                                    Ok(content) => {
+                                        let _ = tx.send(content);
+                                    },
+                                    Err(_) => log::error!("Could not serialize reply"),
+                                }
+                            }
+                        });
+                    },
+                    Request::RemoteBrain{robot, server} => {
+                        let request = arena::Request::AssignRemoteBrain(robot, server);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward remote brain assignment to arena: {}", error);
+                        }
+                    },
+                    Request::Register{address, device} => {
+                        let request = arena::Request::RegisterDevice(address, device);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward device registration to arena: {}", error);
+                        }
+                    },
+                    Request::Filter{filter} => {
+                        let request = arena::Request::SetDiscoveryFilter(filter);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward discovery filter to arena: {}", error);
+                        }
+                    },
+                    Request::Provision{address, kind, hostname} => {
+                        let request = arena::Request::Provision(provision::Request::Start(address, kind, hostname));
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward provisioning request to arena: {}", error);
+                        }
+                    },
+                    Request::Chat{author, text, pin} => {
+                        let text = text.trim().chars().take(CHAT_MAX_LENGTH).collect::<String>();
+                        if text.is_empty() {
+                            continue;
+                        }
+                        let author = author.map(|author| author.trim().to_owned())
+                            .filter(|author| !author.is_empty())
+                            .or_else(|| addr.map(|addr| addr.ip().to_string()))
+                            .unwrap_or_else(|| client.to_string());
+                        let message = chat::Message {
+                            sent: std::time::SystemTime::now(),
+                            author,
+                            text,
+                            run: None,
+                            pinned: pin,
+                        };
+                        if let Err(error) = arena_request_tx.send(arena::Request::Chat(message)) {
+                            log::error!("Could not forward chat message to arena: {}", error);
+                        }
+                    },
+                    Request::SelfTest{robot} => {
+                        let target = robot.map_or(selftest::Target::Mock, selftest::Target::Robot);
+                        let request = arena::Request::SelfTest(selftest::Request::Run(target, None));
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward self test request to arena: {}", error);
+                        }
+                    },
+                    /* cancellations are handled before the permission check above */
+                    Request::Operation{..} => {},
+                    Request::Subscribe{subscription} => {
+                        let request = arena::Request::SubscribePush(subscription);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward push subscription to arena: {}", error);
+                        }
+                    },
+                    Request::Message{recipient, message} => {
+                        let request = arena::Request::InjectMessage(recipient, message);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not forward message to arena: {}", error);
+                        }
+                    },
+                    Request::Query{predicates} => {
+                        let (query_callback_tx, query_callback_rx) = oneshot::channel();
+                        let request = arena::Request::Query(predicates, query_callback_tx);
+                        if let Err(error) = arena_request_tx.send(request) {
+                            log::error!("Could not query arena: {}", error);
+                            continue;
+                        }
+                        let reply = match query_callback_rx.await {
+                            Ok(query) => QueryReply { query },
+                            Err(_) => {
+                                log::error!("Could not get query results from arena");
+                                continue;
                             }
                         };
                         match serde_json::to_string(&reply) {
                             Ok(content) => {
-                                let message = Ok(ws::Message::text(content));
-                                if let Err(_) = tx.send(message) {
+                                if let Err(_) = tx.send(content) {
                                     log::error!("Could not reply to client");
                                 }
                             },
@@ -248,17 +994,12 @@ pub async fn run(ws: ws::WebSocket,
                         match action {
                             software::Action::Upload => {
                                 let file = file.and_then(|(name, content)| {
-                                    match content.split(',').tuples::<(_,_)>().next() {
-                                        Some((_, data)) => {
-                                            match base64::decode(data) {
-                                                Ok(data) => Some((name, data)),
-                                                Err(error) => {
-                                                    log::error!("Could not decode {}: {}", name, error);
-                                                    None
-                                                }
-                                            }
-                                        },
-                                        None => None
+                                    match api::decode_data_url(&content) {
+                                        Some(data) => Some((name, data)),
+                                        None => {
+                                            log::error!("Could not decode {}", name);
+                                            None
+                                        }
                                     }
                                 });
                                 if let Some((filename, contents)) = file {
@@ -274,6 +1015,30 @@ pub async fn run(ws: ws::WebSocket,
                                             log::error!("Could not add Pi-Puck software: {}", error);
                                         }
                                     }
+                                    else if uuid == *UUID_ARENA_ENVIRONMENT {
+                                        let request = arena::Request::SetEnvironmentSchedule(contents);
+                                        if let Err(error) = arena_request_tx.send(request) {
+                                            log::error!("Could not set environment schedule: {}", error);
+                                        }
+                                    }
+                                    else if uuid == *UUID_ARENA_FLAGS {
+                                        let request = arena::Request::SetFlags(contents);
+                                        if let Err(error) = arena_request_tx.send(request) {
+                                            log::error!("Could not set flags: {}", error);
+                                        }
+                                    }
+                                    else if uuid == *UUID_ARENA_INJECTION {
+                                        let request = arena::Request::AddInjectionTemplates(contents);
+                                        if let Err(error) = arena_request_tx.send(request) {
+                                            log::error!("Could not add message templates: {}", error);
+                                        }
+                                    }
+                                    else if uuid == *UUID_MAINTENANCE_CAMPAIGN {
+                                        let request = arena::Request::Campaign(campaign::Request::Start(contents));
+                                        if let Err(error) = arena_request_tx.send(request) {
+                                            log::error!("Could not start campaign: {}", error);
+                                        }
+                                    }
                                     else {
                                         log::error!("Target {} does not support adding software", uuid);
                                     }
@@ -292,6 +1057,30 @@ pub async fn run(ws: ws::WebSocket,
                                         log::error!("Could not clear Pi-Puck software: {}", error);
                                     }
                                 }
+                                else if uuid == *UUID_ARENA_ENVIRONMENT {
+                                    let request = arena::Request::ClearEnvironmentSchedule;
+                                    if let Err(error) = arena_request_tx.send(request) {
+                                        log::error!("Could not clear environment schedule: {}", error);
+                                    }
+                                }
+                                else if uuid == *UUID_ARENA_FLAGS {
+                                    let request = arena::Request::ClearFlags;
+                                    if let Err(error) = arena_request_tx.send(request) {
+                                        log::error!("Could not clear flags: {}", error);
+                                    }
+                                }
+                                else if uuid == *UUID_ARENA_INJECTION {
+                                    let request = arena::Request::ClearInjectionTemplates;
+                                    if let Err(error) = arena_request_tx.send(request) {
+                                        log::error!("Could not clear message templates: {}", error);
+                                    }
+                                }
+                                else if uuid == *UUID_MAINTENANCE_CAMPAIGN {
+                                    let request = arena::Request::Campaign(campaign::Request::Abort);
+                                    if let Err(error) = arena_request_tx.send(request) {
+                                        log::error!("Could not abort campaign: {}", error);
+                                    }
+                                }
                                 else {
                                     log::error!("Target {} does not support clearing software", uuid);
                                 }
@@ -305,28 +1094,352 @@ pub async fn run(ws: ws::WebSocket,
             }
         }
     }
-    log::info!("Client disconnected");
+    if let Err(error) = arena_request_tx.send(arena::Request::Lock(lock::Request::Disconnect(client))) {
+        log::error!("Could not unregister client with arena: {}", error);
+    }
+    log::info!("Client {} disconnected", client);
 }
 
-async fn experiment_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Cards> {
-    let mut cards = Cards::default();
-    /* check pipuck software */
-    let (check_pipuck_software_callback_tx, check_pipuck_software_callback_rx) =
-        oneshot::channel();
-    let check_pipuck_software_request = 
-        arena::Request::CheckPiPuckSoftware(check_pipuck_software_callback_tx);
+/// Readiness check for process supervisors, the supervisor is ready if the arena responds
+pub async fn ready(arena_request_tx: mpsc::UnboundedSender<arena::Request>)
+    -> std::result::Result<impl warp::Reply, std::convert::Infallible> {
+    let (get_actions_callback_tx, get_actions_callback_rx) = oneshot::channel();
+    let ready = arena_request_tx.send(arena::Request::GetActions(get_actions_callback_tx)).is_ok() &&
+        matches!(tokio::time::timeout(READY_TIMEOUT, get_actions_callback_rx).await, Ok(Ok(_)));
+    Ok(match ready {
+        true => warp::reply::with_status("ready", warp::http::StatusCode::OK),
+        false => warp::reply::with_status("not ready", warp::http::StatusCode::SERVICE_UNAVAILABLE),
+    })
+}
+
+/// Query of the robots endpoint, e.g., /robots?kind=Drone&lifecycle=Running&battery_above=50,
+/// a robot is selected if it matches all of the given fields
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Selection {
+    kind: Option<arena::Kind>,
+    lifecycle: Option<crate::robot::Lifecycle>,
+    /// Battery remaining as a percentage
+    battery_above: Option<i8>,
+}
+
+/// Responds with the robots that match a selection as JSON, like the query of the web interface
+pub async fn robots(arena_request_tx: mpsc::UnboundedSender<arena::Request>, selection: Selection)
+    -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    let predicates = selection.kind.map(arena::Predicate::Kind).into_iter()
+        .chain(selection.lifecycle.map(arena::Predicate::Lifecycle))
+        .chain(selection.battery_above.map(arena::Predicate::BatteryAbove))
+        .collect();
+    let (query_callback_tx, query_callback_rx) = oneshot::channel();
+    arena_request_tx.send(arena::Request::Query(predicates, query_callback_tx))
+        .map_err(|_| warp::reject())?;
+    let summaries = query_callback_rx.await
+        .map_err(|_| warp::reject())?;
+    Ok(warp::reply::json(&summaries))
+}
+
+/// A read-only view of the testbed. Requests from the client are ignored and the cards are
+/// pushed to the client without any actions.
+pub async fn dashboard(ws: ws::WebSocket,
+                       arena_request_tx: mpsc::UnboundedSender<arena::Request>) {
+    let (websocket_tx, mut websocket_rx) = ws.split();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::task::spawn(send_batches(rx, websocket_tx));
+    let mut interval = tokio::time::interval(DASHBOARD_PERIOD);
+    let mut last_seen = tokio::time::Instant::now();
+    loop {
+        tokio::select! {
+            message = websocket_rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {
+                    last_seen = tokio::time::Instant::now();
+                    continue
+                },
+                _ => break,
+            },
+            _ = interval.tick() => {
+                if last_seen.elapsed() > HEARTBEAT_TIMEOUT {
+                    log::warn!("Dashboard stopped responding");
+                    break;
+                }
+                let mut cards = match dashboard_cards(&arena_request_tx).await {
+                    Ok(cards) => cards,
+                    Err(error) => {
+                        log::error!("Could not update dashboard: {}", error);
+                        continue;
+                    }
+                };
+                /* a snapshot of the tracked rigid bodies, if available */
+                if let Ok(optitrack_cards) = optitrack_tab(&arena_request_tx, &config, false).await {
+                    cards.extend(optitrack_cards);
+                }
+                for card in cards.iter_mut() {
+                    card.actions.clear();
+                }
+                let reply = Reply {
+                    title: "Dashboard".to_owned(),
+                    cards,
+                    banner: None,
+                    status: status(&arena_request_tx).await,
+                    tabs: Vec::new(),
+                };
+                match serde_json::to_string(&reply) {
+                    Ok(content) => if let Err(_) = tx.send(content) {
+                        break;
+                    },
+                    Err(_) => log::error!("Could not serialize reply"),
+                }
+            }
+        }
+    }
+}
+
+async fn dashboard_cards(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Cards> {
+    /* the experiment is running if it can be stopped */
+    let (get_actions_callback_tx, get_actions_callback_rx) = oneshot::channel();
     arena_request_tx
-        .send(check_pipuck_software_request)
+        .send(arena::Request::GetActions(get_actions_callback_tx))
         .map_err(|_| Error::ArenaRequestError)?;
-    let (pipuck_software_checksums, pipuck_software_check) = check_pipuck_software_callback_rx.await
-        .map_err(|_| Error::ArenaResponseError)?;
-    /* check drone software */
-    let (check_drone_software_callback_tx, check_drone_software_callback_rx) =
-        oneshot::channel();
-    let check_drone_software_request = 
-        arena::Request::CheckDroneSoftware(check_drone_software_callback_tx);
+    let running = get_actions_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?
+        .contains(&arena::Action::StopExperiment);
+    /* summarize all robots */
+    let (query_callback_tx, query_callback_rx) = oneshot::channel();
     arena_request_tx
-        .send(check_drone_software_request)
+        .send(arena::Request::Query(Vec::new(), query_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let robots = query_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let count = |kind: arena::Kind| robots.iter()
+        .filter(|robot| robot.kind == kind)
+        .count();
+    let mut rows = vec![
+        vec!["Experiment".to_owned(), match running {
+            true => "Running".to_owned(),
+            false => "Stopped".to_owned(),
+        }],
+        vec!["Pi-Pucks".to_owned(), count(arena::Kind::PiPuck).to_string()],
+        vec!["Drones".to_owned(), count(arena::Kind::Drone).to_string()],
+    ];
+    let mut lifecycles : BTreeMap<String, usize> = BTreeMap::new();
+    for lifecycle in robots.iter().filter_map(|robot| robot.lifecycle) {
+        *lifecycles.entry(lifecycle.to_string()).or_insert(0) += 1;
+    }
+    rows.extend(lifecycles.into_iter()
+        .map(|(lifecycle, count)| vec![lifecycle, count.to_string()]));
+    let mut cards = Cards::default();
+    cards.push(Card {
+        uuid: UUID_DASHBOARD_SUMMARY.clone(),
+        span: 4,
+        title: "Testbed".to_owned(),
+        content: vec![Content::Table {
+            header: vec!["".to_owned(), "".to_owned()],
+            rows,
+        }],
+        actions: vec![],
+    });
+    Ok(cards)
+}
+
+async fn authorize(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                   client: uuid::Uuid,
+                   permission: Permission) -> bool {
+    let (authorize_callback_tx, authorize_callback_rx) = oneshot::channel();
+    let request = arena::Request::Lock(lock::Request::Authorize(client, permission, authorize_callback_tx));
+    if let Err(_) = arena_request_tx.send(request) {
+        return false;
+    }
+    authorize_callback_rx.await.unwrap_or(false)
+}
+
+/* rejection of the HTTP requests of clients without the required permission */
+#[derive(Debug)]
+struct Forbidden;
+
+impl warp::reject::Reject for Forbidden {}
+
+/// Rejects the HTTP requests of clients whose role does not grant a permission, using the same
+/// roles as the actions on the web interface. The roles are read from the configuration so that
+/// the routes answer while the arena is busy, e.g., starting an experiment.
+pub fn permitted(config: config::Receiver,
+                 permission: Permission) -> impl warp::Filter<Extract = (), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    warp::addr::remote()
+        .and_then(move |addr: Option<SocketAddr>| {
+            let permitted = config.borrow().auth.as_ref()
+                .map_or(true, |auth| auth.permits(addr, permission));
+            if !permitted {
+                log::warn!("HTTP client at {:?} does not have the {:?} permission", addr, permission);
+            }
+            async move {
+                match permitted {
+                    true => Ok(()),
+                    false => Err(warp::reject::custom(Forbidden)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Responds to the HTTP requests that were rejected by [permitted]
+pub async fn forbidden(rejection: warp::Rejection) -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    match rejection.find::<Forbidden>() {
+        Some(_) => Ok(warp::reply::with_status("Forbidden\n", warp::http::StatusCode::FORBIDDEN)),
+        None => Err(rejection),
+    }
+}
+
+/* claims the identifier of a request, returning the outcome of the original request if the
+   identifier has already been claimed */
+async fn claim(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+               id: uuid::Uuid) -> Option<dedup::Outcome> {
+    let (claim_callback_tx, claim_callback_rx) = oneshot::channel();
+    let request = arena::Request::Dedup(dedup::Request::Claim(id, claim_callback_tx));
+    if let Err(_) = arena_request_tx.send(request) {
+        return None;
+    }
+    claim_callback_rx.await.unwrap_or(None)
+}
+
+fn reply_outcome(tx: &mpsc::UnboundedSender<String>, id: uuid::Uuid, outcome: dedup::Outcome, replayed: bool) {
+    match serde_json::to_string(&RequestReply { request: RequestOutcome { id, outcome, replayed } }) {
+        Ok(content) => {
+            let _ = tx.send(content);
+        },
+        Err(_) => log::error!("Could not serialize reply"),
+    }
+}
+
+async fn lock_status(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<lock::Status> {
+    let (lock_status_callback_tx, lock_status_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::Lock(lock::Request::Status(lock_status_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    lock_status_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)
+}
+
+/// Generate a warning for conditions that affect the whole arena
+async fn banner(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                client: uuid::Uuid) -> Option<String> {
+    /* observers are reminded that the testbed is locked by another operator */
+    if let Ok(lock::Status { holder: Some((holder, addr)), .. }) = lock_status(arena_request_tx).await {
+        if holder != client {
+            return Some(format!("Testbed is locked by the operator at {}, you are observing",
+                addr.map_or("an unknown address".to_owned(), |addr| addr.to_string())));
+        }
+    }
+    let (get_journal_status_callback_tx, get_journal_status_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetJournalStatus(get_journal_status_callback_tx))
+        .ok()?;
+    let status = get_journal_status_callback_rx.await.ok()?;
+    if status.quota_exceeded {
+        return Some("Journal stopped recording, the journals of the runs reached their quota".to_owned());
+    }
+    if status.is_falling_behind() {
+        return Some(format!("Journal is falling behind: {} events pending, {} dropped, {} spooled to disk",
+            status.pending, status.dropped, status.spooled));
+    }
+    /* links whose signal strength is below the threshold */
+    let (get_links_callback_tx, get_links_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetLinks(get_links_callback_tx))
+        .ok()?;
+    let weak = get_links_callback_rx.await.ok()?
+        .into_iter()
+        .flat_map(|(_, interfaces)| interfaces)
+        .filter(|(_, status)| status.weak)
+        .map(|(interface, status)| format!("{} of {}", interface, status.addr))
+        .collect::<Vec<_>>();
+    if !weak.is_empty() {
+        return Some(format!("Weak signal on the {}", weak.join(", ")));
+    }
+    /* uploads that were refused because of the software quota */
+    let (get_storage_callback_tx, get_storage_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetStorage(get_storage_callback_tx))
+        .ok()?;
+    get_storage_callback_rx.await.ok()?.refused_upload
+}
+
+/// Show the state of the arming switch, if there is one
+async fn status(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Option<String> {
+    let (get_arming_state_callback_tx, get_arming_state_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetArmingState(get_arming_state_callback_tx))
+        .ok()?;
+    match get_arming_state_callback_rx.await.ok()? {
+        arming::State::NotRequired => None,
+        arming::State::Armed =>
+            Some("<span class=\"mdl-color--green-700 mdl-color-text--white\" style=\"padding:4px 12px\">ARMED</span>".to_owned()),
+        state =>
+            Some(format!("<span class=\"mdl-color--red-700 mdl-color-text--white\" style=\"padding:4px 12px\">{}</span>",
+                state.to_string().to_uppercase())),
+    }
+}
+
+/// Forward serialized replies to the client, coalescing the replies that arrive within
+/// BATCH_WINDOW of each other into a single frame containing a JSON array, and ping the
+/// client every HEARTBEAT_PERIOD
+async fn send_batches(mut rx: mpsc::UnboundedReceiver<String>,
+                      mut websocket_tx: SplitSink<ws::WebSocket, ws::Message>) {
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_PERIOD);
+    loop {
+        let reply = tokio::select! {
+            reply = rx.recv() => match reply {
+                Some(reply) => reply,
+                None => break,
+            },
+            _ = heartbeat.tick() => {
+                if let Err(error) = websocket_tx.send(ws::Message::ping(Vec::new())).await {
+                    log::error!("Sending ping over WebSocket failed: {}", error);
+                    break;
+                }
+                continue;
+            }
+        };
+        let mut batch = vec![reply];
+        let window = tokio::time::sleep(BATCH_WINDOW);
+        tokio::pin!(window);
+        loop {
+            tokio::select! {
+                _ = &mut window => break,
+                reply = rx.recv() => match reply {
+                    Some(reply) => batch.push(reply),
+                    None => break,
+                }
+            }
+        }
+        let frame = format!("[{}]", batch.join(","));
+        if let Err(error) = websocket_tx.send(ws::Message::text(frame)).await {
+            log::error!("Sending data over WebSocket failed: {}", error);
+            break;
+        }
+    }
+}
+
+async fn experiment_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                        client: uuid::Uuid) -> Result<Cards> {
+    let mut cards = Cards::default();
+    /* get the operator lock */
+    let lock_status = lock_status(arena_request_tx).await?;
+    /* check pipuck software */
+    let (check_pipuck_software_callback_tx, check_pipuck_software_callback_rx) =
+        oneshot::channel();
+    let check_pipuck_software_request = 
+        arena::Request::CheckPiPuckSoftware(check_pipuck_software_callback_tx);
+    arena_request_tx
+        .send(check_pipuck_software_request)
+        .map_err(|_| Error::ArenaRequestError)?;
+    let (pipuck_software_checksums, pipuck_software_check) = check_pipuck_software_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    /* check drone software */
+    let (check_drone_software_callback_tx, check_drone_software_callback_rx) =
+        oneshot::channel();
+    let check_drone_software_request = 
+        arena::Request::CheckDroneSoftware(check_drone_software_callback_tx);
+    arena_request_tx
+        .send(check_drone_software_request)
         .map_err(|_| Error::ArenaRequestError)?;
     let (drone_software_checksums, drone_software_check) = check_drone_software_callback_rx.await
         .map_err(|_| Error::ArenaResponseError)?;
@@ -338,6 +1451,29 @@ async fn experiment_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>
         .map_err(|_| Error::ArenaRequestError)?;
     let actions = get_actions_callback_rx.await
         .map_err(|_| Error::ArenaResponseError)?;
+    /* get environment schedule */
+    let (get_environment_schedule_callback_tx, get_environment_schedule_callback_rx) =
+        oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetEnvironmentSchedule(get_environment_schedule_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let environment_schedule = get_environment_schedule_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    /* get flags */
+    let (get_flags_callback_tx, get_flags_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetFlags(get_flags_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let flags = get_flags_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    /* get message templates */
+    let (get_injection_templates_callback_tx, get_injection_templates_callback_rx) =
+        oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetInjectionTemplates(get_injection_templates_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let injection_templates = get_injection_templates_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
 
     let card = Card {
         uuid: UUID_ARENA_DRONES.clone(),
@@ -385,6 +1521,137 @@ async fn experiment_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>
             .into_iter().map(Action::Software).collect(),
     };
     cards.push(card);
+    let card = Card {
+        uuid: UUID_ARENA_ENVIRONMENT.clone(),
+        span: 4,
+        title: "Environment".to_owned(),
+        content: vec![
+            Content::Text("Schedule".to_owned()),
+            Content::Table {
+                header: vec!["Time (s)".to_owned(), "Device".to_owned(), "Value".to_owned()],
+                rows: environment_schedule.0
+                    .into_iter()
+                    .map(|step| vec![format!("{:.1}", step.at), escape_html(&step.device), escape_html(&step.value.to_string())])
+                    .collect::<Vec<_>>()
+            },
+        ],
+        actions: vec![software::Action::Upload, software::Action::Clear]
+            .into_iter().map(Action::Software).collect(),
+    };
+    cards.push(card);
+    let card = Card {
+        uuid: UUID_ARENA_FLAGS.clone(),
+        span: 4,
+        title: "Feature Flags".to_owned(),
+        content: vec![
+            Content::Text(match flags.live {
+                true => "Changes are sent to running controllers".to_owned(),
+                false => "Changes take effect in the next experiment".to_owned(),
+            }),
+            Content::Table {
+                header: vec!["Scope".to_owned(), "Flag".to_owned(), "Value".to_owned()],
+                rows: flags.experiment.iter()
+                    .map(|(name, value)| ("Experiment", name, value))
+                    .chain(flags.groups.iter().flat_map(|group| group.flags.iter()
+                        .map(move |(name, value)| (group.name.as_str(), name, value))))
+                    .map(|(scope, name, value)| vec![scope.to_owned(), name.clone(), value.clone()])
+                    .collect::<Vec<_>>()
+            },
+        ],
+        actions: vec![software::Action::Upload, software::Action::Clear]
+            .into_iter().map(Action::Software).collect(),
+    };
+    cards.push(card);
+    let mut injection_actions = vec![Action::Software(software::Action::Upload), Action::Software(software::Action::Clear)];
+    injection_actions.extend(injection_templates.iter().map(|template| Action::Inject(action::Control {
+        action: template.name.clone(),
+        label: escape_html(&template.name),
+        danger: action::Danger::Safe,
+        confirm: false,
+    })));
+    let card = Card {
+        uuid: UUID_ARENA_INJECTION.clone(),
+        span: 4,
+        title: "Message Injection".to_owned(),
+        content: vec![
+            Content::Text("Templates".to_owned()),
+            Content::Table {
+                header: vec!["Name".to_owned(), "Recipient".to_owned(), "Message".to_owned()],
+                rows: injection_templates
+                    .into_iter()
+                    .map(|template| vec![
+                        escape_html(&template.name),
+                        template.recipient.map_or("All".to_owned(), |recipient| recipient.to_string()),
+                        escape_html(&template.message.to_string())
+                    ])
+                    .collect::<Vec<_>>()
+            },
+            Content::Text(INJECTION_FORM.to_owned()),
+        ],
+        actions: injection_actions,
+    };
+    cards.push(card);
+    /* the bridge between the message router and a simulation */
+    let (get_bridge_callback_tx, get_bridge_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetSimulationBridge(get_bridge_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let bridge = get_bridge_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    cards.push(Card {
+        uuid: UUID_ARENA_BRIDGE.clone(),
+        span: 4,
+        title: "Simulation Bridge".to_owned(),
+        content: vec![
+            Content::Text(BRIDGE_FORM.to_owned()),
+            Content::Text(match bridge {
+                Some((addr, true)) => format!("{} Bridged to the simulation at {}", OK_ICON, addr),
+                Some((addr, false)) => format!("Connecting to the simulation at {}...", addr),
+                None => "Not bridged".to_owned(),
+            }),
+        ],
+        actions: vec![],
+    });
+    let holder = lock_status.holder.map(|(holder, _)| holder);
+    let card = Card {
+        uuid: UUID_ARENA_LOCK.clone(),
+        span: 4,
+        title: "Operator".to_owned(),
+        content: vec![
+            Content::Text(match holder {
+                Some(holder) if holder == client => "You hold the lock".to_owned(),
+                Some(_) => "Testbed is locked by another operator".to_owned(),
+                None => "Testbed is unlocked".to_owned(),
+            }),
+            Content::Table {
+                header: vec!["Client".to_owned(), "Address".to_owned(), "Role".to_owned()],
+                rows: lock_status.clients
+                    .into_iter()
+                    .sorted_by_key(|(uuid, _)| *uuid)
+                    .map(|(uuid, addr)| vec![
+                        match uuid == client {
+                            true => format!("{} (this client)", uuid),
+                            false => uuid.to_string(),
+                        },
+                        addr.map_or("Unknown".to_owned(), |addr| addr.to_string()),
+                        match (holder, uuid == client) {
+                            (Some(holder), _) if holder == uuid => "Operator".to_owned(),
+                            (Some(holder), false) if holder == client =>
+                                format!("<a href=\"javascript:handOver('{}')\">Hand over</a>", uuid),
+                            (Some(_), _) => "Observer".to_owned(),
+                            (None, _) => "Unlocked".to_owned(),
+                        }
+                    ])
+                    .collect(),
+            },
+        ],
+        actions: match holder {
+            Some(holder) if holder == client => vec![lock::Action::Release],
+            Some(_) => vec![],
+            None => vec![lock::Action::Acquire],
+        }.into_iter().map(Action::Lock).collect(),
+    };
+    cards.push(card);
     let card = Card {
         uuid: UUID_ARENA_DASHBOARD.clone(),
         span: 4,
@@ -396,43 +1663,712 @@ async fn experiment_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>
         actions: actions.into_iter().map(Action::Arena).collect(), // start/stop experiment
     };
     cards.push(card);
+    cards.push(generate_chat_card(arena_request_tx).await?);
     Ok(cards)
 }
 
-async fn optitrack_tab() -> Result<Cards> {
-    let mut cards = Cards::default();
-    let update = timeout(Duration::from_millis(100), optitrack::once());
-    if let Ok(frame_of_data) = update.await.map_err(|_| Error::OptitrackTimeoutError)? {
-        for rigid_body in frame_of_data.rigid_bodies {
-            let position = format!("x = {:.3}, y = {:.3}, z = {:.3}",
-                rigid_body.position.x,
-                rigid_body.position.y,
-                rigid_body.position.z);
-            let orientation = format!("w = {:.3}, x = {:.3}, y = {:.3}, z = {:.3}",
-                rigid_body.orientation.w,
-                rigid_body.orientation.vector().x,
-                rigid_body.orientation.vector().y,
-                rigid_body.orientation.vector().z);
-            let card = Card {
-                uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, &rigid_body.id.to_be_bytes()),
-                span: 3,
-                title: format!("Rigid body {}", rigid_body.id),
-                content: vec![Content::Table {
-                    header: vec!["Position".to_owned(), "Orientation".to_owned()],
-                    rows: vec![vec![position, orientation]]
+/* escapes the text of the operators so that it is not interpreted as HTML */
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn generate_chat_rows(messages: &[chat::Message]) -> Vec<Vec<String>> {
+    messages.iter()
+        .rev()
+        .map(|message| vec![
+            message.sent.elapsed()
+                .map_or("Unknown".to_owned(), |elapsed| format!("{} minutes ago", elapsed.as_secs() / 60)),
+            message.run.map_or(String::new(), |(_, time)| format!("{:.1} s", time.as_secs_f64())),
+            escape_html(&message.author),
+            match message.pinned {
+                true => format!("<i class=\"material-icons\" style=\"vertical-align: middle;\">push_pin</i> {}", escape_html(&message.text)),
+                false => escape_html(&message.text),
+            },
+        ])
+        .collect()
+}
+
+async fn generate_chat_card(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Card> {
+    let (get_chat_callback_tx, get_chat_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetChat(get_chat_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let messages = get_chat_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    Ok(Card {
+        uuid: UUID_ARENA_CHAT.clone(),
+        span: 4,
+        title: "Operator Chat".to_owned(),
+        content: vec![
+            Content::Text(CHAT_FORM.to_owned()),
+            Content::Table {
+                header: vec!["Sent".to_owned(), "Run time".to_owned(), "Author".to_owned(), "Message".to_owned()],
+                rows: generate_chat_rows(&messages),
+            },
+        ],
+        actions: Vec::new(),
+    })
+}
+
+fn generate_run_content(run: history::Run) -> Vec<Content> {
+    let started = run.started.elapsed()
+        .map_or("Unknown".to_owned(), |elapsed| format!("{} minutes ago", elapsed.as_secs() / 60));
+    let checksums = |software: &software::Software| software.checksums()
+        .into_iter()
+        .map(|(filename, checksum)| format!("{} ({:x})", filename, checksum))
+        .join("<br>");
+    vec![
+        Content::Table {
+            header: vec!["Setting".to_owned(), "Value".to_owned()],
+            rows: vec![
+                vec!["Started".to_owned(), started],
+                vec!["Pi-Puck software".to_owned(), checksums(&run.pipuck_software)],
+                vec!["Drone software".to_owned(), checksums(&run.drone_software)],
+                vec!["Environment steps".to_owned(), run.environment_schedule.0.len().to_string()],
+                vec!["Feature flags".to_owned(), run.flags.experiment.iter()
+                    .chain(run.flags.groups.iter().flat_map(|group| group.flags.iter()))
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .join("<br>")],
+                vec!["Message templates".to_owned(), run.injection_templates.len().to_string()],
+                vec!["Robots".to_owned(), run.robots.iter().map(ToString::to_string).join("<br>")],
+                vec!["Signed by".to_owned(), run.signers.iter().join("<br>")],
+                vec!["Outcome".to_owned(), match run.aborted {
+                    true => "Aborted, the results are partial".to_owned(),
+                    false => "Completed".to_owned(),
                 }],
-                // the actions depend on the state of the drone
-                // the action part of the message must contain
-                // the uuid, action name, and optionally arguments
-                actions: vec![], // start/stop experiment
+                vec!["Thermally throttled".to_owned(), run.throttled.iter().map(ToString::to_string).join("<br>")],
+                vec!["Annotations".to_owned(), run.annotations.iter()
+                    .map(|message| format!("{:.1} s {}: {}",
+                        message.run.map_or(0.0, |(_, time)| time.as_secs_f64()),
+                        escape_html(&message.author),
+                        escape_html(&message.text)))
+                    .join("<br>")],
+                vec!["Results".to_owned(), run.results.iter()
+                    .sorted_by_key(|(robot, _)| *robot)
+                    .map(|(robot, report)| {
+                        let mut summary = format!("{}: {} files", robot, report.files.len());
+                        if !report.missing.is_empty() {
+                            summary += &format!(", missing {}", report.missing.iter().join(", "));
+                        }
+                        for (file, error) in report.failed.iter() {
+                            summary += &format!(", {} failed ({})", file, error);
+                        }
+                        summary
+                    })
+                    .join("<br>")],
+            ]
+        },
+    ]
+}
+
+async fn history_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Cards> {
+    let (get_history_callback_tx, get_history_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetHistory(get_history_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let history = get_history_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let mut cards = Cards::default();
+    for (uuid, template) in history.templates.into_iter() {
+        cards.push(Card {
+            uuid: uuid,
+            span: 4,
+            title: format!("Template: {}", escape_html(&template.name)),
+            content: generate_run_content(template.run),
+            actions: vec![history::Action::Clone, history::Action::Delete]
+                .into_iter().map(Action::History).collect(),
+        });
+    }
+    /* show the most recent runs first */
+    for (index, (uuid, run)) in history.runs.into_iter().enumerate().rev() {
+        cards.push(Card {
+            uuid: uuid,
+            span: 4,
+            title: format!("Run {}", index + 1),
+            content: generate_run_content(run),
+            actions: vec![history::Action::Clone, history::Action::SaveTemplate, history::Action::Export, history::Action::Delete]
+                .into_iter().map(Action::History).collect(),
+        });
+    }
+    Ok(cards)
+}
+
+async fn maintenance_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Cards> {
+    let (progress_callback_tx, progress_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::Campaign(campaign::Request::Progress(progress_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let progress = progress_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let content = match progress {
+        None => vec![Content::Text("Upload a campaign to start updating the robots".to_owned())],
+        Some(progress) => {
+            let state = match progress.state {
+                campaign::State::Running => "Running".to_owned(),
+                campaign::State::Completed => format!("{} Completed", OK_ICON),
+                campaign::State::Halted(reason) => format!("{} Halted: {}", ERROR_ICON, reason),
+                campaign::State::Aborted => "Aborted".to_owned(),
             };
-            cards.push(card);
+            let rows = progress.succeeded.into_iter()
+                .map(|uuid| vec![uuid.to_string(), "Updated".to_owned()])
+                .chain(progress.failed.into_iter()
+                    .map(|(uuid, error)| vec![uuid.to_string(), format!("Failed: {}", error)]))
+                .chain(progress.pending.into_iter()
+                    .map(|uuid| vec![uuid.to_string(), "Pending".to_owned()]))
+                .collect();
+            vec![
+                Content::Table {
+                    header: vec!["Campaign".to_owned(), "State".to_owned(), "Waves".to_owned()],
+                    rows: vec![vec![
+                        progress.name,
+                        state,
+                        format!("{}/{}", progress.waves_completed, progress.waves)
+                    ]]
+                },
+                Content::Table {
+                    header: vec!["Robot".to_owned(), "Result".to_owned()],
+                    rows
+                },
+            ]
         }
+    };
+    let mut cards = Cards::default();
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_CAMPAIGN.clone(),
+        span: 6,
+        title: "Update Campaign".to_owned(),
+        content,
+        actions: vec![software::Action::Upload, software::Action::Clear]
+            .into_iter().map(Action::Software).collect(),
+    });
+    /* provisioning of freshly flashed robots */
+    let (progress_callback_tx, progress_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::Provision(provision::Request::Progress(progress_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let jobs = progress_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let rows = jobs.into_iter()
+        .map(|(addr, job)| vec![
+            addr.to_string(),
+            job.hostname,
+            format!("{:?}", job.kind),
+            match job.state {
+                provision::State::Running(step) => format!("{}...", step),
+                provision::State::Completed => format!("{} Completed", OK_ICON),
+                provision::State::Failed(error) => format!("{} {}", ERROR_ICON, error),
+            }
+        ])
+        .collect();
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_PROVISIONING.clone(),
+        span: 6,
+        title: "Provisioning".to_owned(),
+        content: vec![
+            Content::Text(PROVISION_FORM.to_owned()),
+            Content::Table {
+                header: vec!["Address".to_owned(), "Hostname".to_owned(), "Type".to_owned(), "State".to_owned()],
+                rows
+            },
+        ],
+        actions: vec![],
+    });
+    /* devices that were registered manually */
+    let (registered_callback_tx, registered_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetRegisteredDevices(registered_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let rows = registered_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?
+        .into_iter()
+        .sorted_by_key(|(addr, _, _)| *addr)
+        .map(|(addr, device, connected)| vec![
+            addr.to_string(),
+            format!("{:?}", device),
+            match connected {
+                true => format!("{} Connected", OK_ICON),
+                false => "Probing".to_owned(),
+            }
+        ])
+        .collect();
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_REGISTRATION.clone(),
+        span: 6,
+        title: "Registered Devices".to_owned(),
+        content: vec![
+            Content::Text(REGISTER_FORM.to_owned()),
+            Content::Table {
+                header: vec!["Address".to_owned(), "Type".to_owned(), "State".to_owned()],
+                rows
+            },
+        ],
+        actions: vec![],
+    });
+    /* the filter of the devices that are probed */
+    let (filter_callback_tx, filter_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetDiscoveryFilter(filter_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let (filter, replaced) = filter_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let rules = |list: &[network::filter::Rule]| match list.is_empty() {
+        true => "-".to_owned(),
+        false => list.iter().map(ToString::to_string).join(", "),
+    };
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_FILTER.clone(),
+        span: 6,
+        title: "Discovery Filter".to_owned(),
+        content: vec![
+            Content::Text(FILTER_FORM.to_owned()),
+            Content::Table {
+                header: vec!["Allowed".to_owned(), "Denied".to_owned(), "Source".to_owned()],
+                rows: vec![vec![
+                    match filter.allow.is_empty() {
+                        true => "Any device".to_owned(),
+                        false => rules(&filter.allow),
+                    },
+                    rules(&filter.deny),
+                    match replaced {
+                        true => "Replaced at runtime".to_owned(),
+                        false => "Configuration".to_owned(),
+                    },
+                ]],
+            },
+        ],
+        actions: vec![],
+    });
+    /* the report of the last self test */
+    let (report_callback_tx, report_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::SelfTest(selftest::Request::Report(report_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let (report, running) = report_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let mut content = vec![Content::Text(SELFTEST_FORM.to_owned())];
+    if running {
+        content.push(Content::Text("Running...".to_owned()));
+    }
+    if let Some(report) = report {
+        let rows = report.steps.into_iter()
+            .map(|step| vec![
+                step.name.to_owned(),
+                match step.outcome {
+                    selftest::Outcome::Passed(detail) => format!("{} {}", OK_ICON, detail),
+                    selftest::Outcome::Failed(error) => format!("{} {}", ERROR_ICON, error),
+                    selftest::Outcome::Skipped => "Skipped".to_owned(),
+                },
+                format!("{:.3}s", step.duration.as_secs_f64()),
+            ])
+            .collect();
+        content.push(Content::Table {
+            header: vec![format!("Step ({})", report.target), "Result".to_owned(), "Duration".to_owned()],
+            rows
+        });
+    }
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_SELFTEST.clone(),
+        span: 6,
+        title: "Self Test".to_owned(),
+        content,
+        actions: vec![],
+    });
+    /* availability of the robots, the flakiest robots first */
+    let (statistics_callback_tx, statistics_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::Availability(availability::Request::Statistics(statistics_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let statistics = statistics_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let rows = statistics.into_iter()
+        .sorted_by(|left, right| right.flakiness.partial_cmp(&left.flakiness).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|robot| vec![
+            robot.name,
+            format!("{:.1}%", robot.availability * 100.0),
+            robot.disconnections.to_string(),
+            format!("{:.2}", robot.flakiness),
+        ])
+        .collect();
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_AVAILABILITY.clone(),
+        span: 6,
+        title: "Availability (last 4 weeks)".to_owned(),
+        content: vec![
+            Content::Table {
+                header: vec!["Robot".to_owned(), "Availability".to_owned(), "Disconnections".to_owned(),
+                    "Disconnections per day".to_owned()],
+                rows
+            },
+        ],
+        actions: vec![],
+    });
+    /* storage used by the uploaded software and the journals of the runs */
+    let (get_storage_callback_tx, get_storage_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetStorage(get_storage_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let usage = get_storage_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let runs = tokio::task::spawn_blocking(quota::run_usage).await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let row = |name: &str, used: Option<u64>, quota: Option<u64>| {
+        let used = used.map_or("Unknown".to_owned(), quota::format_bytes);
+        match quota {
+            Some(quota) => vec![name.to_owned(), used, quota::format_bytes(quota)],
+            None => vec![name.to_owned(), used, "Unlimited".to_owned()],
+        }
+    };
+    let mut content = vec![
+        Content::Table {
+            header: vec!["Storage".to_owned(), "Used".to_owned(), "Quota".to_owned()],
+            rows: vec![
+                row("Uploaded software", Some(usage.software), usage.quotas.software),
+                row("Journals of the runs", runs.ok(), usage.quotas.runs),
+            ]
+        },
+    ];
+    if let Some(refused_upload) = usage.refused_upload {
+        content.push(Content::Text(format!("{} {}", ERROR_ICON, refused_upload)));
+    }
+    cards.push(Card {
+        uuid: UUID_MAINTENANCE_STORAGE.clone(),
+        span: 6,
+        title: "Storage".to_owned(),
+        content,
+        actions: vec![],
+    });
+    /* staging, result collection, and campaigns, the running operations can be cancelled */
+    let (list_callback_tx, list_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::Operation(operation::Request::List(list_callback_tx)))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let operations = list_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let (running, finished) : (Vec<_>, Vec<_>) = operations.into_iter()
+        .partition(|operation| operation.state == operation::State::Running);
+    for operation in running {
+        let rows = operation.succeeded.iter()
+            .map(|uuid| vec![uuid.to_string(), format!("{} Done", OK_ICON)])
+            .chain(operation.failed.iter()
+                .map(|(uuid, error)| vec![uuid.to_string(), format!("{} {}", ERROR_ICON, error)]))
+            .collect();
+        let progress = operation.succeeded.len() + operation.failed.len();
+        cards.push(Card {
+            uuid: operation.id,
+            span: 6,
+            title: operation.description,
+            content: vec![
+                Content::Text(match operation.total {
+                    Some(total) => format!("{} of {} robots finished", progress, total),
+                    None => format!("{} robots finished", progress),
+                }),
+                Content::Table {
+                    header: vec!["Robot".to_owned(), "Result".to_owned()],
+                    rows
+                },
+            ],
+            actions: vec![Action::Operation(operation::Action::Cancel)],
+        });
+    }
+    if !finished.is_empty() {
+        let rows = finished.into_iter()
+            .map(|operation| vec![
+                operation.description,
+                match operation.state {
+                    operation::State::Completed => format!("{} Completed", OK_ICON),
+                    operation::State::Failed(error) => format!("{} {}", ERROR_ICON, error),
+                    state => format!("{:?}", state),
+                },
+                format!("{}/{}", operation.succeeded.len(),
+                    operation.succeeded.len() + operation.failed.len()),
+            ])
+            .collect();
+        cards.push(Card {
+            uuid: UUID_MAINTENANCE_OPERATIONS.clone(),
+            span: 6,
+            title: "Recent Operations".to_owned(),
+            content: vec![
+                Content::Table {
+                    header: vec!["Operation".to_owned(), "State".to_owned(), "Succeeded".to_owned()],
+                    rows
+                },
+            ],
+            actions: vec![],
+        });
     }
     Ok(cards)
 }
 
-async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>) -> Result<Cards> {
+async fn optitrack_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                       config: &config::Receiver,
+                       calibrate: bool) -> Result<Cards> {
+    let mut cards = Cards::default();
+    /* show the health of the connection to the tracking system */
+    let (get_diagnostics_callback_tx, get_diagnostics_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetTrackingDiagnostics(get_diagnostics_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let diagnostics = get_diagnostics_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let state = match (&diagnostics.error, diagnostics.last_frame) {
+        (Some(error), _) => format!("{} {}", ERROR_ICON, error),
+        (None, Some(last_frame)) if last_frame.elapsed() < std::time::Duration::from_secs(1) => "Receiving".to_owned(),
+        (None, Some(last_frame)) => format!("No frames for {:.0} s", last_frame.elapsed().as_secs_f32()),
+        (None, None) => "Waiting for frames".to_owned(),
+    };
+    let version = diagnostics.version.as_ref().map_or_else(|| "Unknown".to_owned(), |version| {
+        match diagnostics.detected {
+            true => format!("{} (detected)", version),
+            false => version.to_string(),
+        }
+    });
+    cards.push(Card {
+        uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, "connection".as_bytes()),
+        span: 3,
+        title: "Connection".to_owned(),
+        content: vec![Content::Table {
+            header: vec!["Diagnostic".to_owned(), "Value".to_owned()],
+            rows: vec![
+                vec!["State".to_owned(), state],
+                vec!["Mode".to_owned(), diagnostics.mode.clone()],
+                vec!["NatNet version".to_owned(), version],
+                vec!["Packet rate".to_owned(), format!("{:.1} Hz", diagnostics.packet_rate)],
+                vec!["Frame rate".to_owned(), format!("{:.1} Hz", diagnostics.frame_rate)],
+                vec!["Frames".to_owned(), diagnostics.frames.to_string()],
+                vec!["Dropped frames".to_owned(), diagnostics.dropped_frames.to_string()],
+                vec!["Decode errors".to_owned(), diagnostics.decode_errors.to_string()],
+                vec!["Latency".to_owned(), format!("{:.1} ms (max. {:.1} ms)",
+                    diagnostics.latency * 1e3, diagnostics.max_latency * 1e3)],
+            ],
+        }],
+        /* the recent tracking data is only kept if the recording is enabled */
+        actions: match config.borrow().tracking_recording {
+            Some(_) => vec![Action::Recording(journal::RecordingAction::Save)],
+            None => vec![],
+        },
+    });
+    /* guide the operator through capturing the corners of the arena */
+    if calibrate {
+        let (get_calibration_callback_tx, get_calibration_callback_rx) = oneshot::channel();
+        arena_request_tx
+            .send(arena::Request::GetCalibration(get_calibration_callback_tx))
+            .map_err(|_| Error::ArenaRequestError)?;
+        let status = get_calibration_callback_rx.await
+            .map_err(|_| Error::ArenaResponseError)?;
+        let step = status.captured.len();
+        let format_row = |row: &[f32; 3]| format!("{:.3}, {:.3}, {:.3}", row[0], row[1], row[2]);
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, "calibration".as_bytes()),
+            span: 3,
+            title: "Arena Calibration".to_owned(),
+            content: vec![
+                Content::Text(format!("Step {} of {}: place a single rigid body at {} and capture it",
+                    step + 1, calibration::CORNERS.len(), calibration::CORNERS[step])),
+                Content::Table {
+                    header: vec!["Transform".to_owned(), "Value".to_owned()],
+                    rows: vec![
+                        vec!["Origin".to_owned(), format_row(&status.transform.origin)],
+                        vec!["X axis".to_owned(), format_row(&status.transform.rotation[0])],
+                        vec!["Y axis".to_owned(), format_row(&status.transform.rotation[1])],
+                        vec!["Z axis".to_owned(), format_row(&status.transform.rotation[2])],
+                    ],
+                },
+            ],
+            actions: vec![calibration::Action::Capture, calibration::Action::Reset]
+                .into_iter().map(Action::Calibration).collect(),
+        });
+    }
+    /* get the raw and the filtered poses of the rigid bodies */
+    let mut frames = Vec::new();
+    for stream in [optitrack::Stream::Raw, optitrack::Stream::Filtered].iter() {
+        let (get_tracking_frame_callback_tx, get_tracking_frame_callback_rx) = oneshot::channel();
+        arena_request_tx
+            .send(arena::Request::GetTrackingFrame(*stream, get_tracking_frame_callback_tx))
+            .map_err(|_| Error::ArenaRequestError)?;
+        frames.push(get_tracking_frame_callback_rx.await
+            .map_err(|_| Error::ArenaResponseError)?);
+    }
+    let filtered = frames.pop().unwrap_or_default();
+    let raw = frames.pop().unwrap_or_default();
+    /* get the markers that are not solved as rigid bodies */
+    let (get_markers_callback_tx, get_markers_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetTrackingMarkers(get_markers_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let markers = get_markers_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let format_position = |position: &[f32; 3]|
+        format!("x = {:.3}, y = {:.3}, z = {:.3}", position[0], position[1], position[2]);
+    if !(markers.sets.is_empty() && markers.labeled.is_empty() && markers.unlabeled.is_empty()) {
+        let rows = markers.sets.iter()
+            .flat_map(|(name, positions)| positions.iter()
+                .map(move |position| vec![format!("Marker set {}", name), format_position(position)]))
+            .chain(markers.labeled.iter()
+                .map(|(id, position)| vec![format!("Labeled {}", id), format_position(position)]))
+            .chain(markers.unlabeled.iter()
+                .map(|position| vec!["Unlabeled".to_owned(), format_position(position)]))
+            .collect();
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, "markers".as_bytes()),
+            span: 3,
+            title: "Markers".to_owned(),
+            content: vec![Content::Table {
+                header: vec!["Marker".to_owned(), "Position".to_owned()],
+                rows,
+            }],
+            actions: vec![],
+        });
+    }
+    for (id, bones) in markers.skeletons.iter() {
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, format!("skeleton{}", id).as_bytes()),
+            span: 3,
+            title: format!("Skeleton {}", id),
+            content: vec![Content::Table {
+                header: vec!["Bone".to_owned(), "Position".to_owned()],
+                rows: bones.iter()
+                    .map(|bone| vec![bone.id.to_string(), format_position(&bone.position)])
+                    .collect(),
+            }],
+            actions: vec![],
+        });
+    }
+    /* get the rigid bodies inside each region */
+    let (get_occupancy_callback_tx, get_occupancy_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetRegionOccupancy(get_occupancy_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let occupancy = get_occupancy_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    if !occupancy.0.is_empty() {
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, "regions".as_bytes()),
+            span: 3,
+            title: "Regions".to_owned(),
+            content: vec![Content::Table {
+                header: vec!["Region".to_owned(), "Rigid bodies".to_owned()],
+                rows: occupancy.0.into_iter()
+                    .map(|(region, rigid_bodies)| vec![region, rigid_bodies.iter().join(", ")])
+                    .collect(),
+            }],
+            actions: vec![],
+        });
+    }
+    /* the formations, with the shown formation overlaid on the tracked rigid bodies */
+    let (get_formations_callback_tx, get_formations_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetFormations(get_formations_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let (formations, shown) = get_formations_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    for formation in formations {
+        let visible = shown.as_ref() == Some(&formation.name);
+        let content = match visible {
+            true => {
+                let measurement = formation.measure(&filtered);
+                vec![
+                    Content::Text(generate_formation_node(&formation, &filtered)),
+                    Content::Text(format!("{} of {} robots within {:.0} mm of their targets",
+                        measurement.in_place, formation.targets.len(), formation.tolerance * 1e3)),
+                    Content::Table {
+                        header: vec!["Rigid body".to_owned(), "Distance".to_owned(), "Heading".to_owned()],
+                        rows: measurement.deviations.iter()
+                            .map(|deviation| vec![
+                                deviation.rigid_body.to_string(),
+                                deviation.distance.map_or_else(|| "Not tracked".to_owned(),
+                                    |distance| format!("{:.0} mm", distance * 1e3)),
+                                deviation.heading.map_or_else(|| "-".to_owned(),
+                                    |heading| format!("{:.1}°", heading.to_degrees())),
+                            ])
+                            .collect(),
+                    },
+                ]
+            },
+            false => vec![Content::Text(format!("{} targets", formation.targets.len()))],
+        };
+        let show = match visible {
+            true => formation::Action::Hide,
+            false => formation::Action::Show,
+        };
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_FORMATION, formation.name.as_bytes()),
+            span: 3,
+            title: format!("Formation {}", formation.name),
+            content,
+            actions: vec![show, formation::Action::Measure, formation::Action::Drive, formation::Action::Stop]
+                .into_iter().map(Action::Formation).collect(),
+        });
+    }
+    /* the Wi-Fi signal strength across the arena */
+    let (get_heatmap_callback_tx, get_heatmap_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetLinkHeatmap(get_heatmap_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let heatmap = get_heatmap_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    if !heatmap.cells.is_empty() {
+        let samples = heatmap.cells.values().map(|cell| cell.samples).sum::<usize>();
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, "heatmap".as_bytes()),
+            span: 3,
+            title: "Link Quality Heatmap".to_owned(),
+            content: vec![
+                Content::Text(generate_heatmap_node(&heatmap)),
+                Content::Text(format!("{} samples in {} cells of {:.2} m", samples, heatmap.cells.len(), heatmap.cell)),
+                Content::Download {
+                    data: base64::encode(heatmap.csv().as_bytes()),
+                    filename: "link_heatmap.csv".to_owned(),
+                },
+            ],
+            actions: vec![Action::Heatmap(link::HeatmapAction::Clear)],
+        });
+    }
+    let ids = raw.iter()
+        .chain(filtered.iter())
+        .map(|pose| pose.id)
+        .sorted()
+        .dedup();
+    for id in ids {
+        let rows = [("Raw", &raw), ("Filtered", &filtered)].iter()
+            .filter_map(|(stream, poses)| poses.iter()
+                .find(|pose| pose.id == id)
+                .map(|pose| vec![
+                    match pose.estimated {
+                        true => format!("{} (estimated)", stream),
+                        false => stream.to_string(),
+                    },
+                    format!("x = {:.3}, y = {:.3}, z = {:.3}",
+                        pose.position[0], pose.position[1], pose.position[2]),
+                    format!("w = {:.3}, x = {:.3}, y = {:.3}, z = {:.3}",
+                        pose.orientation[0], pose.orientation[1], pose.orientation[2], pose.orientation[3]),
+                    pose.motion.map_or_else(String::new, |motion| format!(
+                        "speed = {:.2} m/s, rotation = {:.2} rad/s, acceleration = {:.2} m/s²",
+                        optitrack::norm(&motion.velocity),
+                        optitrack::norm(&motion.angular_velocity),
+                        optitrack::norm(&motion.acceleration))),
+                ]))
+            .collect();
+        let card = Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_OPTITRACK, &id.to_be_bytes()),
+            span: 3,
+            title: format!("Rigid body {}", id),
+            content: vec![Content::Table {
+                header: vec!["Stream".to_owned(), "Position".to_owned(), "Orientation".to_owned(), "Motion".to_owned()],
+                rows,
+            }],
+            actions: vec![],
+        };
+        cards.push(card);
+    }
+    Ok(cards)
+}
+
+async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request>,
+                         config: &config::Receiver) -> Result<Cards> {
     /* get connected Pi-Pucks */
     let (get_pipucks_callback_tx, get_pipucks_callback_rx) = oneshot::channel();
     let get_pipucks_request = 
@@ -451,6 +2387,34 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
         .map_err(|_| Error::ArenaRequestError)?;
     let drones = get_drones_callback_rx.await
         .map_err(|_| Error::ArenaResponseError)?;
+    /* get the golden image */
+    let (get_golden_image_callback_tx, get_golden_image_callback_rx) = oneshot::channel();
+    let get_golden_image_request =
+        arena::Request::GetGoldenImage(get_golden_image_callback_tx);
+    arena_request_tx
+        .send(get_golden_image_request)
+        .map_err(|_| Error::ArenaRequestError)?;
+    let golden_image = get_golden_image_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    /* get the lifecycle of each robot */
+    let (get_lifecycles_callback_tx, get_lifecycles_callback_rx) = oneshot::channel();
+    let get_lifecycles_request =
+        arena::Request::GetLifecycles(get_lifecycles_callback_tx);
+    arena_request_tx
+        .send(get_lifecycles_request)
+        .map_err(|_| Error::ArenaRequestError)?;
+    let lifecycles = get_lifecycles_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let lifecycle = |uuid: uuid::Uuid| lifecycles.get(&uuid)
+        .map_or_else(|| "Unknown".to_owned(), ToString::to_string);
+    /* get the action queue of each robot */
+    let (get_queues_callback_tx, get_queues_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetQueues(get_queues_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let mut queues = get_queues_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let low_battery = config.borrow().low_battery;
     /* generate cards */
     let mut cards = Cards::default();
     /* generate Pi-Puck cards */
@@ -462,8 +2426,8 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
             content: vec![
                 Content::Text("Overview".to_owned()),
                 Content::Table {
-                    header: vec!["Unique Identifier".to_owned(), "Battery".to_owned()],
-                    rows: vec![vec![uuid.to_string(), "TODO".to_owned()]]
+                    header: vec!["Unique Identifier".to_owned(), "State".to_owned(), "Battery".to_owned(), "Health".to_owned()],
+                    rows: vec![vec![uuid.to_string(), lifecycle(uuid), generate_battery_text(state.battery, low_battery), generate_health_text(state.system)]]
                 },
                 Content::Text("Connectivity".to_owned()),
                 Content::Table {
@@ -482,8 +2446,13 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
                     ]
                 }
             ],
-            actions: state.actions.into_iter().map(Action::PiPuck).collect(),
+            actions: action::offered(&config.borrow().pipuck_actions, &state.actions).into_iter()
+                .map(Action::PiPuck)
+                .collect(),
         };
+        card.content.extend(generate_software_image_content(state.image, &golden_image));
+        card.content.extend(generate_queue_content(queues.remove(&uuid)));
+        card.content.extend(generate_history_content(get_telemetry(arena_request_tx, uuid).await?));
         if state.cameras.len() > 0 {
             let camera_frames = state.cameras.into_iter()
                 .map(|data| {
@@ -503,16 +2472,13 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
         let mut content = vec![
             Content::Text("Overview".to_owned()),
             Content::Table {
-                header: vec!["Unique Identifier".to_owned(), "Battery".to_owned()],
+                header: vec!["Unique Identifier".to_owned(), "State".to_owned(), "Battery".to_owned(), "Health".to_owned()],
                 rows: vec![
                     vec![
                         uuid.to_string(),
-                        match state.battery_remaining {
-                            25..=49  => BATT2_IMG,
-                            50..=74  => BATT3_IMG,
-                            75..=100 => BATT4_IMG,
-                            _ => BATT1_IMG,
-                        }.to_owned()
+                        lifecycle(uuid),
+                        generate_battery_text(state.battery, low_battery),
+                        generate_health_text(state.system)
                     ]
                 ]
             },
@@ -554,6 +2520,27 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
                 rows.push(upcore);
             }
         }
+        if state.upcore.is_some() {
+            content.extend(generate_software_image_content(state.image, &golden_image));
+        }
+        if let Some(report) = state.motor_test {
+            let unknown = || "Unknown".to_owned();
+            content.push(Content::Text("Motor test".to_owned()));
+            content.push(Content::Table {
+                header: vec!["Motor".to_owned(), "Speed".to_owned(), "Current".to_owned(), "Result".to_owned()],
+                rows: report.into_iter().map(|motor| vec![
+                    motor.index.to_string(),
+                    motor.rpm.map_or_else(unknown, |rpm| format!("{} RPM", rpm)),
+                    motor.current.map_or_else(unknown, |current| format!("{:.2} A", current)),
+                    match motor.fault {
+                        None => format!("{} Passed", OK_ICON),
+                        Some(fault) => format!("{} {}", ERROR_ICON, fault),
+                    },
+                ]).collect()
+            });
+        }
+        content.extend(generate_queue_content(queues.remove(&uuid)));
+        content.extend(generate_history_content(get_telemetry(arena_request_tx, uuid).await?));
         if let Some(kernel_messages) = state.kernel_messages {
             let data = base64::encode(kernel_messages.as_bytes());
             content.push(Content::Download { data, filename: "kernel_messages.txt".to_owned() } );
@@ -563,7 +2550,9 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
             span: 4,
             title: String::from("Drone"),
             content: content,
-            actions: state.actions.into_iter().map(Action::Drone).collect(),
+            actions: action::offered(&config.borrow().drone_actions, &state.actions).into_iter()
+                .map(Action::Drone)
+                .collect(),
         };
         if state.cameras.len() > 0 {
             let camera_frames = state.cameras.into_iter()
@@ -575,5 +2564,227 @@ async fn connections_tab(arena_request_tx: &mpsc::UnboundedSender<arena::Request
         }
         cards.push(card);
     }
+    /* generate simulated robot cards */
+    let (get_virtual_robots_callback_tx, get_virtual_robots_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetVirtualRobots(get_virtual_robots_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let virtual_robots = get_virtual_robots_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    for (uuid, state) in virtual_robots.into_iter() {
+        cards.push(Card {
+            uuid: uuid,
+            span: 4,
+            title: match state.kind {
+                arena::Kind::PiPuck => String::from("Virtual Pi-Puck"),
+                arena::Kind::Drone => String::from("Virtual Drone"),
+            },
+            content: vec![
+                Content::Text("Overview".to_owned()),
+                Content::Table {
+                    header: vec!["Unique Identifier".to_owned(), "Address".to_owned(), "State".to_owned(), "ARGoS".to_owned()],
+                    rows: vec![vec![
+                        uuid.to_string(),
+                        state.addr.to_string(),
+                        lifecycle(uuid),
+                        match state.running {
+                            true => "Running".to_owned(),
+                            false => "Stopped".to_owned(),
+                        }
+                    ]]
+                },
+            ],
+            actions: vec![],
+        });
+    }
+    /* generate compute server cards */
+    let (get_compute_servers_callback_tx, get_compute_servers_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetComputeServers(get_compute_servers_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let compute_servers = get_compute_servers_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    let (get_remote_brains_callback_tx, get_remote_brains_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetRemoteBrains(get_remote_brains_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let remote_brains = get_remote_brains_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    for (uuid, state) in compute_servers.into_iter() {
+        let rows = remote_brains.iter()
+            .filter(|(_, server)| **server == uuid)
+            .map(|(robot, _)| vec![
+                robot.to_string(),
+                lifecycle(*robot),
+                match state.controllers.contains(robot) {
+                    true => "Running".to_owned(),
+                    false => "Stopped".to_owned(),
+                }
+            ])
+            .collect();
+        cards.push(Card {
+            uuid: uuid,
+            span: 4,
+            title: String::from("Compute Server"),
+            content: vec![
+                Content::Text("Connectivity".to_owned()),
+                Content::Table {
+                    header: vec!["Unique Identifier".to_owned(), "IP Address".to_owned()],
+                    rows: vec![vec![uuid.to_string(), state.addr.to_string()]]
+                },
+                Content::Text("Remote brains".to_owned()),
+                Content::Table {
+                    header: vec!["Robot".to_owned(), "State".to_owned(), "Controller".to_owned()],
+                    rows: rows
+                },
+            ],
+            actions: vec![],
+        });
+    }
+    /* generate the charging docks card */
+    let (get_docks_callback_tx, get_docks_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetDocks(get_docks_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let docks = get_docks_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    if !docks.is_empty() {
+        let rows = docks.into_iter()
+            .map(|(name, status)| match status {
+                Some(status) => vec![
+                    name,
+                    match status.charging {
+                        true => "Charging".to_owned(),
+                        false => "Idle".to_owned(),
+                    },
+                    status.power.map_or(String::new(), |power| format!("{:.1} W", power)),
+                    status.robot.map_or(String::new(), |robot| robot.to_string()),
+                ],
+                None => vec![name, format!("{} Unreachable", ERROR_ICON), String::new(), String::new()],
+            })
+            .collect();
+        cards.push(Card {
+            uuid: *UUID_CONNECTIONS_DOCKS,
+            span: 4,
+            title: String::from("Charging docks"),
+            content: vec![
+                Content::Table {
+                    header: vec!["Dock".to_owned(), "Status".to_owned(), "Power".to_owned(), "Robot".to_owned()],
+                    rows
+                }
+            ],
+            actions: vec![],
+        });
+    }
+    /* generate the card with the signal strength of the links to the robots */
+    let (get_links_callback_tx, get_links_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetLinks(get_links_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let links = get_links_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    if !links.is_empty() {
+        let rows = links.into_iter()
+            .flat_map(|(robot, interfaces)| interfaces.into_iter()
+                .map(move |(interface, status)| vec![
+                    robot.to_string(),
+                    format!("{} ({})", interface, status.addr),
+                    match (status.samples.last(), status.weak) {
+                        (None, _) => "No samples".to_owned(),
+                        (Some(sample), true) => format!("{} {:.0} dBm", ERROR_ICON, sample.value),
+                        (Some(sample), false) => format!("{:.0} dBm", sample.value),
+                    },
+                    match status.failures {
+                        0 => String::new(),
+                        failures => format!("{} failed polls", failures),
+                    },
+                    generate_chart_node(&status.samples),
+                ]))
+            .collect();
+        cards.push(Card {
+            uuid: *UUID_CONNECTIONS_LINKS,
+            span: 4,
+            title: String::from("Links"),
+            content: vec![
+                Content::Table {
+                    header: vec!["Robot".to_owned(), "Interface".to_owned(), "Signal".to_owned(),
+                                 "Polls".to_owned(), "History".to_owned()],
+                    rows
+                }
+            ],
+            actions: vec![],
+        });
+    }
+    /* generate the card with the use of the Wi-Fi channels by other networks */
+    let (get_survey_callback_tx, get_survey_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetWifiSurvey(get_survey_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let survey = get_survey_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    if let Some(survey) = survey {
+        let scanned = survey.scanned.elapsed()
+            .map_or("Unknown".to_owned(), |elapsed| format!("{} minutes ago", elapsed.as_secs() / 60));
+        let mut content = vec![Content::Text(format!("Scanned from {} robots {}", survey.robots, scanned))];
+        let congested = config.borrow().link_monitor.congested_channel;
+        content.extend(survey.advisories(congested).into_iter()
+            .map(|advisory| Content::Text(format!("{} {}", ERROR_ICON, advisory))));
+        content.push(Content::Table {
+            header: vec!["Channel".to_owned(), "Foreign access points".to_owned(),
+                         "Strongest".to_owned(), "Robots connected".to_owned()],
+            rows: survey.channels.iter()
+                .map(|(number, channel)| vec![
+                    number.to_string(),
+                    channel.foreign.len().to_string(),
+                    /* the maximum ignores the access points whose signal was not reported */
+                    match channel.foreign.values().cloned().fold(f32::NAN, f32::max) {
+                        signal if signal.is_nan() => "-".to_owned(),
+                        signal => format!("{:.0} dBm", signal),
+                    },
+                    match channel.used {
+                        true => "Yes".to_owned(),
+                        false => String::new(),
+                    },
+                ])
+                .collect(),
+        });
+        cards.push(Card {
+            uuid: *UUID_CONNECTIONS_CHANNELS,
+            span: 4,
+            title: String::from("Wi-Fi Channels"),
+            content,
+            actions: vec![],
+        });
+    }
+    /* generate greyed out cards for the robots of the fleet that are not connected */
+    let (get_missing_robots_callback_tx, get_missing_robots_callback_rx) = oneshot::channel();
+    arena_request_tx
+        .send(arena::Request::GetMissingRobots(get_missing_robots_callback_tx))
+        .map_err(|_| Error::ArenaRequestError)?;
+    let missing = get_missing_robots_callback_rx.await
+        .map_err(|_| Error::ArenaResponseError)?;
+    for robot in missing {
+        let last_seen = match robot.last_seen.map(|time| time.elapsed().unwrap_or_default().as_secs()) {
+            None => "Not since the supervisor started".to_owned(),
+            Some(seconds) if seconds < 60 => "Less than a minute ago".to_owned(),
+            Some(seconds) if seconds < 3600 => format!("{} minutes ago", seconds / 60),
+            Some(seconds) => format!("{} hours ago", seconds / 3600),
+        };
+        cards.push(Card {
+            uuid: uuid::Uuid::new_v3(&NAMESPACE_CONNECTIONS, robot.name.as_bytes()),
+            span: 4,
+            title: format!("{} (missing)", robot.name),
+            content: vec![
+                Content::Text(format!("<div style=\"opacity:0.5\">{} {} is not connected<br/>Last seen: {}</div>",
+                    ERROR_ICON,
+                    match robot.kind {
+                        arena::Kind::PiPuck => "Pi-Puck",
+                        arena::Kind::Drone => "Drone",
+                    },
+                    last_seen)),
+            ],
+            actions: vec![],
+        });
+    }
     Ok(cards)
 }
\ No newline at end of file
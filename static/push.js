@@ -0,0 +1,21 @@
+/* service worker that shows the critical alerts even if the supervisor tab is in the background */
+self.addEventListener('push', function(event) {
+   let alert = event.data ? event.data.json() : { title: 'MNS Supervisor', body: '' };
+   event.waitUntil(self.registration.showNotification(alert.title, {
+      body: alert.body,
+      requireInteraction: true
+   }));
+});
+
+/* bring the supervisor to the front when an alert is clicked */
+self.addEventListener('notificationclick', function(event) {
+   event.notification.close();
+   event.waitUntil(clients.matchAll({ type: 'window' }).then(function(windows) {
+      for(let window of windows) {
+         if('focus' in window) {
+            return window.focus();
+         }
+      }
+      return clients.openWindow('/');
+   }));
+});
@@ -1,6 +1,8 @@
 // https://getmdl.io/started/index.html#dynamic (call upgrade on dynamic components)
 
-const uri = 'ws://' + location.host + '/socket';
+/* the read-only dashboard receives updates without requesting them */
+const dashboard = (location.pathname == '/dashboard');
+const uri = 'ws://' + location.host + (dashboard ? '/dashboard/socket' : '/socket');
 
 var uiCurrentView = 'Connections';
 var uiTimer = null;
@@ -9,10 +11,69 @@ function setView(uiView) {
    uiCurrentView = uiView;
 }
 
-let ws = new WebSocket(uri);
+/* the socket is reopened with an increasing delay if the connection to the supervisor is lost */
+const reconnectDelayMax = 10000;
+var reconnectDelay = 500;
+let ws = null;
 
-ws.onopen = function() {
+/* a socket over which nothing has been received for this long is considered half-open */
+const heartbeatTimeout = 15000;
+var heartbeatTimer = null;
+var lastMessage = 0;
+
+/* the supervisor issues a session token that is used to resume the session after reconnecting */
+function socketUri() {
+   let session = sessionStorage.getItem('session');
+   return (session && !dashboard) ? uri + '?session=' + session : uri;
+}
+
+function checkHeartbeat() {
+   if(Date.now() - lastMessage > heartbeatTimeout) {
+      /* give up on the socket without waiting for the closing handshake */
+      ws.onclose = null;
+      ws.close();
+      onClose();
+   }
+}
+
+/* show whether the supervisor can be reached while the socket is closed */
+function showOffline(reason) {
+   let uiOffline = document.getElementById('offline');
+   let uiStatus = document.getElementById('offline-status');
+   uiOffline.style.display = '';
+   uiStatus.innerHTML = reason + '<br/>Reconnecting in ' + (reconnectDelay / 1000).toFixed(1) + ' s';
+   fetch('/health/ready').then(function(response) {
+      uiStatus.innerHTML = (response.ok ? 'The supervisor is reachable' : 'The supervisor is starting') +
+         '<br/>Reconnecting in ' + (reconnectDelay / 1000).toFixed(1) + ' s';
+   }).catch(function() {
+      uiStatus.innerHTML = 'The supervisor is not reachable' +
+         '<br/>Reconnecting in ' + (reconnectDelay / 1000).toFixed(1) + ' s';
+   });
+}
+
+function connect() {
+   ws = new WebSocket(socketUri());
+   ws.onopen = onOpen;
+   ws.onclose = onClose;
+   ws.onmessage = onMessage;
+}
+
+function onOpen() {
    document.getElementById('offline').style.display = 'None'
+   reconnectDelay = 500;
+   lastMessage = Date.now();
+   heartbeatTimer = setInterval(checkHeartbeat, 1000);
+   if(dashboard) {
+      return;
+   }
+   /* resend the requests whose outcome was not received, the supervisor ignores duplicates */
+   for(let message of Object.values(unacknowledged)) {
+      ws.send(message);
+   }
+   /* renew the subscription to the critical alerts if the operator has already allowed them */
+   if('Notification' in window && Notification.permission == 'granted') {
+      subscribeAlerts();
+   }
    uiTimer = setInterval(function() {
       var message = JSON.stringify({
          type: 'update',
@@ -20,20 +81,265 @@ ws.onopen = function() {
       });
       ws.send(message);
    }, 250);
-};
+}
 
-ws.onclose = function() {
-   document.getElementById('offline').style.display = ''
+function onClose() {
    clearInterval(uiTimer);
-};
+   clearInterval(heartbeatTimer);
+   showOffline('Connection to the supervisor lost');
+   setTimeout(connect, reconnectDelay);
+   reconnectDelay = Math.min(reconnectDelay * 2, reconnectDelayMax);
+}
+
+function onMessage(message) {
+   lastMessage = Date.now();
+   /* the server coalesces updates into batches */
+   let updates = JSON.parse(message.data);
+   for(let update of updates) {
+      if('session' in update) {
+         sessionStorage.setItem('session', update.session);
+      }
+      else if('query' in update) {
+         /* results of a query are forwarded to any listening scripts */
+         window.dispatchEvent(new CustomEvent('query', { detail: update.query }));
+      }
+      else if('request' in update) {
+         /* the request has reached the supervisor and does not need to be resent */
+         delete unacknowledged[update.request.id];
+         if(update.request.outcome == 'refused') {
+            console.log('Request ' + update.request.id + ' was refused');
+         }
+      }
+      else if('command' in update) {
+         /* as are the acknowledgements of a command */
+         window.dispatchEvent(new CustomEvent('command', { detail: update.command }));
+      }
+      else {
+         applyUpdate(update);
+      }
+   }
+}
+
+connect();
+
+/* requests that change the testbed carry an identifier so that they are carried out only once
+   even if they are resent after reconnecting */
+var unacknowledged = {};
+
+function requestId() {
+   return 'xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx'.replace(/[xy]/g, function(character) {
+      let random = Math.random() * 16 | 0;
+      return (character == 'x' ? random : (random & 0x3 | 0x8)).toString(16);
+   });
+}
+
+function sendRequest(request) {
+   request.id = requestId();
+   let message = JSON.stringify(request);
+   unacknowledged[request.id] = message;
+   if(ws.readyState == WebSocket.OPEN) {
+      ws.send(message);
+   }
+}
+
+/* send a message to one robot or, if recipient is null, to all robots */
+function inject(recipient, message) {
+   sendRequest({
+      type: 'message',
+      recipient: recipient,
+      message: message
+   });
+}
+
+function injectForm() {
+   let recipient = document.getElementById('inject-recipient').value.trim();
+   try {
+      let message = JSON.parse(document.getElementById('inject-message').value);
+      inject(recipient == '' ? null : recipient, message);
+   }
+   catch(error) {
+      alert('Message is not valid JSON: ' + error.message);
+   }
+}
+
+/* bridge the message router to the simulation in the form on the experiment tab, or disconnect it */
+function bridgeForm(disconnect) {
+   sendRequest({
+      type: 'bridge',
+      address: disconnect ? null : document.getElementById('bridge-address').value.trim()
+   });
+}
+
+/* provision a freshly flashed robot using the form on the maintenance tab */
+function provisionForm() {
+   sendRequest({
+      type: 'provision',
+      address: document.getElementById('provision-address').value.trim(),
+      kind: document.getElementById('provision-kind').value,
+      hostname: document.getElementById('provision-hostname').value.trim()
+   });
+}
 
-ws.onmessage = function(message) {
-   let update = JSON.parse(message.data);
+/* probe a device that discovery cannot find using the form on the maintenance tab */
+function registerForm() {
+   sendRequest({
+      type: 'register',
+      address: document.getElementById('register-address').value.trim(),
+      device: document.getElementById('register-device').value
+   });
+}
+
+/* send the message in the chat form on the experiment tab to the other operators */
+function chatForm() {
+   let text = document.getElementById('chat-text');
+   if(text.value.trim() == '') {
+      return;
+   }
+   let author = document.getElementById('chat-author').value.trim();
+   sendRequest({
+      type: 'chat',
+      author: author == '' ? null : author,
+      text: text.value,
+      pin: document.getElementById('chat-pin').checked
+   });
+   text.value = '';
+   document.getElementById('chat-pin').checked = false;
+}
+
+/* run the self test against the robot in the form, or against the mock service if it is empty */
+function selfTestForm() {
+   var robot = document.getElementById('selftest-robot').value.trim();
+   sendRequest({
+      type: 'selftest',
+      robot: robot.length > 0 ? robot : null
+   });
+}
+
+/* replace the filter of the devices that are probed with the comma separated rules in the form,
+   or return to the filter in the configuration */
+function filterForm(configured) {
+   var rules = function(id) {
+      return document.getElementById(id).value.split(',')
+         .map(function(rule) { return rule.trim(); })
+         .filter(function(rule) { return rule.length > 0; });
+   };
+   sendRequest({
+      type: 'filter',
+      filter: configured ? null : { allow: rules('filter-allow'), deny: rules('filter-deny') }
+   });
+}
+
+/* hand the operator lock over to another client */
+function handOver(client) {
+   sendRequest({
+      type: 'lock',
+      action: 'Hand over',
+      uuid: client
+   });
+}
+
+/* run the controller of a robot on a compute server, e.g., remoteBrain(robot, server),
+   or on the robot itself, e.g., remoteBrain(robot, null) */
+function remoteBrain(robot, server) {
+   sendRequest({
+      type: 'remotebrain',
+      robot: robot,
+      server: server
+   });
+}
+
+/* broadcast a command and wait up to deadline milliseconds for acknowledgements, e.g.,
+   command({parameter: 'speed', value: 5}, 1000) */
+function command(command, deadline) {
+   sendRequest({
+      type: 'command',
+      command: command,
+      deadline_ms: deadline
+   });
+}
+
+/* convert the base64url encoded public key of the supervisor */
+function decodeKey(key) {
+   let padding = '='.repeat((4 - key.length % 4) % 4);
+   let data = atob((key + padding).replace(/-/g, '+').replace(/_/g, '/'));
+   return Uint8Array.from(data, character => character.charCodeAt(0));
+}
+
+/* subscribe this browser to the critical alerts, which are shown even when the tab is in the background */
+async function subscribeAlerts() {
+   if(!('serviceWorker' in navigator) || !('PushManager' in window)) {
+      alert('This browser does not support push notifications');
+      return;
+   }
+   let response = await fetch('/push/key');
+   if(!response.ok) {
+      console.log('Push notifications have not been configured on the supervisor');
+      return;
+   }
+   let key = await response.text();
+   let registration = await navigator.serviceWorker.register('/push.js');
+   let subscription = await registration.pushManager.subscribe({
+      userVisibleOnly: true,
+      applicationServerKey: decodeKey(key)
+   });
+   ws.send(JSON.stringify({
+      type: 'subscribe',
+      subscription: subscription.toJSON()
+   }));
+}
+
+/* select robots, e.g., query([{predicate: 'kind', value: 'PiPuck'},
+                               {predicate: 'lifecycle', value: 'Ready'}]) */
+function query(predicates) {
+   ws.send(JSON.stringify({
+      type: 'query',
+      predicates: predicates
+   }));
+}
+
+function applyUpdate(update) {
    /* Update the title of the current interface */
    let uiTitle = document.getElementById('ui-title');
    if('title' in update) {
       uiTitle.innerHTML = update.title;
    }
+   /* Show the state of the arming switch */
+   let uiStatus = document.getElementById('ui-status');
+   uiStatus.innerHTML = (update.status != null) ? update.status : '';
+   /* Add navigation links for the tabs provided by plugins */
+   if('tabs' in update) {
+      let uiNavigation = document.getElementById('navigation');
+      for(let tab of update.tabs) {
+         if(document.getElementById('tab-' + tab) == null) {
+            let uiLink = document.createElement('a');
+            uiLink.setAttribute('id', 'tab-' + tab);
+            uiLink.setAttribute('class', 'mdl-navigation__link');
+            uiLink.setAttribute('href', '#');
+            uiLink.dataset.tab = tab;
+            uiLink.addEventListener('click', function(event) {
+               event.preventDefault();
+               setView(this.dataset.tab);
+            });
+            /* the name of the tab comes from a plugin and is only ever set as text */
+            let uiIcon = document.createElement('i');
+            uiIcon.setAttribute('class', 'mdl-color-text--blue-grey-400 material-icons');
+            uiIcon.setAttribute('role', 'presentation');
+            uiIcon.textContent = 'extension';
+            uiLink.appendChild(uiIcon);
+            uiLink.appendChild(document.createTextNode(tab));
+            uiNavigation.appendChild(uiLink);
+         }
+      }
+   }
+   /* Show or hide the warning banner */
+   let uiBanner = document.getElementById('banner');
+   if(update.banner != null) {
+      uiBanner.innerHTML = update.banner;
+      uiBanner.style.display = '';
+   }
+   else {
+      uiBanner.style.display = 'None';
+   }
    if('cards' in update) {
       let uiContainer = document.getElementById('ui-container');
       /* iterate over the existing uiCards (i.e., HTMLDivElement's) */
@@ -65,7 +371,7 @@ ws.onmessage = function(message) {
          }
       }
    }
-};
+}
 
 // TODO
 //emergency = document.getElementById('emergency-stop');
@@ -125,15 +431,20 @@ function contentToHTML(content) {
    }
 }
 
+/* what happens to the run when an experiment is aborted */
+var abortConfirmations = {
+   'Abort and preserve': 'Stop the controllers, collect the partial results, and mark the run as aborted?',
+   'Abort and discard': 'Kill the controllers, delete the partial results and the journal, and remove the run from the history?',
+};
+
 /* factory for sending commands to the backend */
 function sendActionFactory(type, uuid, action) {
    return function() {
-      var message = JSON.stringify({
+      sendRequest({
          type: type,
          action: action,
          uuid: uuid,
       });
-      ws.send(message);
    }
 }
 
@@ -172,13 +483,12 @@ function newCard(uuid, title, span, content, controls) {
                const file = uploadInput.files[i];
                const reader = new FileReader();
                reader.onload = function(ev) {
-                  var message = JSON.stringify({
+                  sendRequest({
                      type: 'software',
                      action: 'Upload',
                      file: [file.name, ev.target.result],
                      uuid: uuid,
                   });
-                  ws.send(message);
                };
                reader.readAsDataURL(file);
             }
@@ -189,6 +499,63 @@ function newCard(uuid, title, span, content, controls) {
          cardControl.innerHTML = control.action;
          cardControl.appendChild(cardControlInput);
       }
+      else if(control.type == 'history' && control.action == 'Save as template') {
+         cardControl = document.createElement('a');
+         cardControl.setAttribute('class', 'mdl-button mdl-button--colored mdl-js-button mdl-js-ripple-effect');
+         cardControl.innerHTML = control.action;
+         cardControl.onclick = function() {
+            let name = prompt('Template name');
+            if(name != null && name.trim() != '') {
+               sendRequest({
+                  type: 'history',
+                  action: 'Save as template',
+                  uuid: uuid,
+                  name: name.trim()
+               });
+            }
+         };
+      }
+      else if(control.type == 'history' && control.action == 'Export') {
+         cardControl = document.createElement('a');
+         cardControl.setAttribute('class', 'mdl-button mdl-button--colored mdl-js-button mdl-js-ripple-effect');
+         cardControl.innerHTML = control.action;
+         cardControl.onclick = function() {
+            let profile = prompt('Redaction profile (leave empty to export without redaction)', '');
+            if(profile != null) {
+               sendRequest({
+                  type: 'history',
+                  action: 'Export',
+                  uuid: uuid,
+                  name: profile.trim() == '' ? null : profile.trim()
+               });
+            }
+         };
+      }
+      else if(control.type == 'arena' && control.action in abortConfirmations) {
+         cardControl = document.createElement('a');
+         cardControl.setAttribute('class', 'mdl-button mdl-button--colored mdl-js-button mdl-js-ripple-effect');
+         cardControl.innerHTML = control.action;
+         let sendAction = sendActionFactory(control.type, uuid, control.action);
+         let confirmation = abortConfirmations[control.action];
+         cardControl.onclick = function() {
+            if(confirm(confirmation)) {
+               sendAction();
+            }
+         };
+      }
+      /* actions with a label, a danger level, and whether they must be confirmed */
+      else if(typeof control.action == 'object') {
+         let guarded = control.action;
+         cardControl = document.createElement('a');
+         cardControl.setAttribute('class', 'mdl-button mdl-button--colored mdl-js-button mdl-js-ripple-effect action-' + guarded.danger);
+         cardControl.innerHTML = guarded.label;
+         let sendAction = sendActionFactory(control.type, uuid, guarded.action);
+         cardControl.onclick = function() {
+            if(!guarded.confirm || confirm(guarded.label + ' (' + title + ' ' + uuid + ')?')) {
+               sendAction();
+            }
+         };
+      }
       else {
          cardControl = document.createElement('a');
          cardControl.setAttribute('class', 'mdl-button mdl-button--colored mdl-js-button mdl-js-ripple-effect');